@@ -0,0 +1,55 @@
+// Memory vs speed of condensed pattern tables: the same segment is opened
+// with every condense threshold and decoded in full. The benchmark id
+// carries the number of table slots, the memory side of the trade-off.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use erigon_dumper::{Compressor, Decompressor};
+use std::path::Path;
+use tempfile::TempDir;
+
+const WORDS: usize = 20_000;
+
+fn build_segment(path: &Path) {
+    let mut compressor = Compressor::builder(path)
+        .min_pattern_score(1)
+        .fsync(false)
+        .build()
+        .unwrap();
+    for i in 0..WORDS {
+        let word = format!("word-{}-{:x}-{}", i, i * 7919, i % 97);
+        compressor.add_word(word.as_bytes()).unwrap();
+    }
+    compressor.compress().unwrap();
+}
+
+fn bench_condense_threshold(c: &mut Criterion) {
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("bench.seg");
+    build_segment(&path);
+
+    let mut group = c.benchmark_group("decode_all_words");
+    group.throughput(Throughput::Elements(WORDS as u64));
+    for threshold in 3..=9 {
+        let decompressor = Decompressor::with_condense_threshold(&path, threshold).unwrap();
+        let id = format!(
+            "threshold={}/entries={}",
+            threshold,
+            decompressor.pattern_table_entries()
+        );
+        group.bench_with_input(BenchmarkId::from_parameter(id), &decompressor, |b, d| {
+            b.iter(|| {
+                let mut getter = d.make_getter();
+                let mut buf = Vec::new();
+                while getter.has_next() {
+                    buf.clear();
+                    buf = getter.next(buf).0;
+                }
+                buf.len()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_condense_threshold);
+criterion_main!(benches);
@@ -0,0 +1,190 @@
+// Throughput of the main code paths, to catch regressions in any of them:
+// compression on two corpora, full decoding, skipping, ordinal lookups in an
+// enum index (one by one and sequentially) and pattern matching. Compression
+// is slow enough that its groups take few samples.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use erigon_dumper::parallel_compress::MatchFinder;
+use erigon_dumper::snapshots::recsplit::{RecSplit, RecSplitIndex};
+use erigon_dumper::testutil::SyntheticChain;
+use erigon_dumper::{Compressor, Decompressor, Pattern};
+use std::path::Path;
+use tempfile::TempDir;
+
+const WORDS: usize = 10_000;
+
+const LOREM: &str = "lorem ipsum dolor sit amet consectetur adipiscing elit sed do eiusmod \
+tempor incididunt ut labore et dolore magna aliqua ut enim ad minim veniam quis nostrud \
+exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat duis aute irure dolor in \
+reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur excepteur sint \
+occaecat cupidatat non proident sunt in culpa qui officia deserunt mollit anim id est laborum";
+
+// Short phrases of lorem words, picked by a fixed LCG
+fn lorem_corpus() -> Vec<Vec<u8>> {
+    let words: Vec<&str> = LOREM.split(' ').collect();
+    let mut state = 42u64;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+    (0..WORDS)
+        .map(|_| {
+            let len = 1 + next() % 8;
+            (0..len)
+                .map(|_| words[next() % words.len()])
+                .collect::<Vec<_>>()
+                .join(" ")
+                .into_bytes()
+        })
+        .collect()
+}
+
+// Words of a headers segment of synthetic blocks: hash[0] then the header RLP
+fn header_corpus() -> Vec<Vec<u8>> {
+    SyntheticChain::new(42).header_words(0..WORDS as u64)
+}
+
+fn compress(path: &Path, words: &[Vec<u8>]) {
+    let mut compressor = Compressor::builder(path).fsync(false).build().unwrap();
+    for word in words {
+        compressor.add_word(word).unwrap();
+    }
+    compressor.compress().unwrap();
+}
+
+// Enum index over the words of `seg`, so ordinal i maps to word i
+fn build_index(seg: &Path, idx: &Path) -> RecSplitIndex {
+    let decompressor = Decompressor::new(seg).unwrap();
+    let mut rs = RecSplit::builder(idx, decompressor.count())
+        .enums(true)
+        .fsync(false)
+        .build()
+        .unwrap();
+    let mut getter = decompressor.make_getter();
+    let mut offset = 0;
+    let mut i = 0u64;
+    while getter.has_next() {
+        rs.add_key(&i.to_be_bytes(), offset).unwrap();
+        offset = getter.skip().0;
+        i += 1;
+    }
+    rs.build().unwrap();
+    RecSplitIndex::open(idx).unwrap()
+}
+
+fn corpora() -> [(&'static str, Vec<Vec<u8>>); 2] {
+    [("lorem", lorem_corpus()), ("headers", header_corpus())]
+}
+
+fn bench_compress(c: &mut Criterion) {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut group = c.benchmark_group("compress");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(WORDS as u64));
+    for (name, words) in corpora() {
+        let path = tmp_dir.path().join(format!("{}.seg", name));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &words, |b, words| {
+            b.iter(|| compress(&path, words))
+        });
+    }
+    group.finish();
+}
+
+fn bench_decompress(c: &mut Criterion) {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut decode = c.benchmark_group("decompress");
+    let mut segments = Vec::new();
+    for (name, words) in corpora() {
+        let path = tmp_dir.path().join(format!("{}.seg", name));
+        compress(&path, &words);
+        let bytes = words.iter().map(|w| w.len() as u64).sum();
+        segments.push((name, bytes, Decompressor::new(&path).unwrap()));
+    }
+    for (name, bytes, decompressor) in &segments {
+        decode.throughput(Throughput::Bytes(*bytes));
+        decode.bench_with_input(BenchmarkId::from_parameter(name), decompressor, |b, d| {
+            b.iter(|| {
+                let mut getter = d.make_getter();
+                let mut buf = Vec::new();
+                while getter.has_next() {
+                    buf.clear();
+                    buf = getter.next(buf).0;
+                }
+                buf.len()
+            })
+        });
+    }
+    decode.finish();
+
+    let mut skip = c.benchmark_group("skip");
+    skip.throughput(Throughput::Elements(WORDS as u64));
+    for (name, _, decompressor) in &segments {
+        skip.bench_with_input(BenchmarkId::from_parameter(name), decompressor, |b, d| {
+            b.iter(|| {
+                let mut getter = d.make_getter();
+                let mut offset = 0;
+                while getter.has_next() {
+                    offset = getter.skip().0;
+                }
+                offset
+            })
+        });
+    }
+    skip.finish();
+}
+
+fn bench_ordinal_lookup(c: &mut Criterion) {
+    let tmp_dir = TempDir::new().unwrap();
+    let seg = tmp_dir.path().join("headers.seg");
+    compress(&seg, &header_corpus());
+    let index = build_index(&seg, &tmp_dir.path().join("headers.idx"));
+
+    let mut group = c.benchmark_group("ordinal_lookup");
+    group.throughput(Throughput::Elements(WORDS as u64));
+    group.bench_function("headers", |b| {
+        b.iter(|| {
+            (0..WORDS as u64)
+                .map(|i| index.ordinal_lookup(i).unwrap())
+                .fold(0, u64::wrapping_add)
+        })
+    });
+    group.bench_function("headers_sequential", |b| {
+        b.iter(|| index.offsets(0).unwrap().fold(0, u64::wrapping_add))
+    });
+    group.finish();
+}
+
+fn bench_match_finder(c: &mut Criterion) {
+    let words = lorem_corpus();
+    let mut finder = MatchFinder::new();
+    for (i, word) in LOREM.split(' ').enumerate() {
+        finder.insert(Pattern::new(word.as_bytes().to_vec(), i as u64));
+    }
+    // Build the automaton outside the measurement
+    finder.find_longest_matches(b"lorem");
+
+    let mut group = c.benchmark_group("match_finder");
+    group.throughput(Throughput::Bytes(
+        words.iter().map(|w| w.len() as u64).sum(),
+    ));
+    group.bench_function("lorem", |b| {
+        b.iter(|| {
+            words
+                .iter()
+                .map(|word| finder.find_longest_matches(word).len())
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_compress,
+    bench_decompress,
+    bench_ordinal_lookup,
+    bench_match_finder
+);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+#![no_main]
+
+use erigon_dumper::decompress::Decompressor;
+use libfuzzer_sys::fuzz_target;
+use tempfile::TempDir;
+
+// Arbitrary bytes as a .seg file: opening, verifying and decoding it may
+// fail, but must never panic or loop forever
+fuzz_target!(|data: &[u8]| {
+    let tmp_dir = match TempDir::new() {
+        Ok(dir) => dir,
+        Err(_) => return,
+    };
+    let file_path = tmp_dir.path().join("fuzz.seg");
+    if std::fs::write(&file_path, data).is_err() {
+        return;
+    }
+
+    let decompressor = match Decompressor::new(&file_path) {
+        Ok(decompressor) => decompressor,
+        Err(_) => return,
+    };
+    let _ = decompressor.verify();
+
+    // Unchecked decoding may return garbage for corrupt words
+    let mut getter = decompressor.make_getter();
+    while getter.has_next() {
+        getter.match_prefix(b"fuzz");
+        getter.next(Vec::new());
+    }
+    let mut getter = decompressor.make_getter();
+    while getter.has_next() {
+        getter.skip();
+    }
+
+    let mut getter = decompressor.make_getter();
+    while getter.has_next() {
+        if getter.try_next(Vec::new()).is_err() {
+            break;
+        }
+    }
+});
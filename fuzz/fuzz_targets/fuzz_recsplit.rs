@@ -0,0 +1,27 @@
+#![no_main]
+
+use erigon_dumper::snapshots::recsplit::RecSplitIndex;
+use libfuzzer_sys::fuzz_target;
+use tempfile::TempDir;
+
+// Arbitrary bytes as a RecSplit .idx file: opening it may fail, but neither
+// opening nor lookups on whatever opened may panic
+fuzz_target!(|data: &[u8]| {
+    let tmp_dir = match TempDir::new() {
+        Ok(dir) => dir,
+        Err(_) => return,
+    };
+    let path = tmp_dir.path().join("fuzz.idx");
+    if std::fs::write(&path, data).is_err() {
+        return;
+    }
+
+    let idx = match RecSplitIndex::open(&path) {
+        Ok(idx) => idx,
+        Err(_) => return,
+    };
+    for i in 0..idx.key_count().min(64) {
+        idx.lookup(&i.to_be_bytes());
+        idx.ordinal_lookup(i);
+    }
+});
@@ -0,0 +1,480 @@
+//! Appending words to a finished segment
+//!
+//! A producer that builds a segment bit by bit can reopen it with
+//! [`AppendCompressor`] instead of compressing all of its words again. The
+//! words added are covered with the patterns already in the segment, so its
+//! pattern dictionary is kept as it is and no new one is built. When the new
+//! words only need positions the segment already has codes for, its words
+//! are copied as they are; otherwise the position codes are rebuilt and the
+//! words of the segment transcoded to them, their patterns and uncovered
+//! bytes unchanged.
+//!
+//! The segment is replaced like [`Compressor`](crate::Compressor) writes
+//! one: through a `.seg.tmp` renamed into place. Its checksum footer, if it
+//! has one, is recomputed; its index is not, and has to be built again.
+
+use crate::compress::{
+    append_checksum_footer, encode_varint, hash_word, CompressionStats, OptimizerMode, Pattern,
+    Position,
+};
+use crate::core::SegmentHeader;
+use crate::decompress::Decompressor;
+use crate::error::CompressionError;
+use crate::parallel_compress::{
+    cover_word, position_codes, write_compressed_file, CoverBuffers, CoverTally, MatchFinder,
+};
+use crate::workspace::TempWorkspace;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh64::Xxh64;
+
+/// Adds words to the end of an existing segment
+///
+/// Words are covered as they are added and staged in a workspace under the
+/// temporary directory; the segment is only rewritten by
+/// [`AppendCompressor::finish`]. Dropping the compressor before that leaves
+/// the segment untouched. Not meant to be shared: adding words takes
+/// `&mut self`.
+pub struct AppendCompressor {
+    segment: Decompressor,
+    output_file: PathBuf,
+    tmp_out_file_path: PathBuf,
+    // The pattern dictionary of the segment in file order, with the codes it
+    // gives each pattern; the sequential code of a pattern is its index
+    patterns: Vec<Pattern>,
+    // Likewise the position dictionary
+    positions: Vec<Position>,
+    match_finder: MatchFinder,
+    optimizer: OptimizerMode,
+    buffers: CoverBuffers,
+    // Positions and pattern uses of the words added
+    tally: CoverTally,
+    // Intermediate encoding of the words added
+    staged: BufWriter<File>,
+    staged_path: PathBuf,
+    encoded: Vec<u8>,
+    words: u64,
+    empty_words: u64,
+    // Checksum of all the words, when the segment has a footer
+    word_hasher: Option<Xxh64>,
+    // Holds the staged words; dropped last so they are closed first
+    workspace: TempWorkspace,
+}
+
+impl AppendCompressor {
+    /// Reopen the segment at `path` to add words to it, staging them under
+    /// `tmp_dir`
+    ///
+    /// A segment with a checksum footer has its words read once here, to
+    /// carry their checksum over to the new footer.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        tmp_dir: impl AsRef<Path>,
+    ) -> Result<Self, CompressionError> {
+        let output_file = path.into();
+        let segment = Decompressor::new(&output_file)?;
+        let file_name = segment.file_name().to_string();
+
+        let (pattern_depths, words): (Vec<u64>, Vec<&[u8]>) = segment.patterns().unzip();
+        let pattern_codes = codes_from_depths(&pattern_depths)?;
+        let mut match_finder = MatchFinder::new();
+        let mut patterns = Vec::with_capacity(words.len());
+        for (i, (word, (code, code_bits))) in words.into_iter().zip(pattern_codes).enumerate() {
+            let mut pattern = Pattern::new(word.to_vec(), 0);
+            pattern.sequential_code = i as u64;
+            pattern.code = code;
+            pattern.code_bits = code_bits;
+            pattern.depth = code_bits;
+            if !word.is_empty() {
+                match_finder.insert(pattern.clone());
+            }
+            patterns.push(pattern);
+        }
+
+        let (position_depths, values): (Vec<u64>, Vec<u64>) = segment.positions().unzip();
+        let positions = values
+            .into_iter()
+            .zip(codes_from_depths(&position_depths)?)
+            .map(|(pos, (code, code_bits))| Position {
+                uses: 0,
+                pos,
+                code,
+                code_bits,
+                depth: code_bits,
+            })
+            .collect();
+
+        let word_hasher = match segment.checksum() {
+            Some(_) => {
+                let mut hasher = Xxh64::new(0);
+                let mut getter = segment.make_getter();
+                let mut word = Vec::new();
+                while getter.has_next() {
+                    word.clear();
+                    word = getter.try_next(word)?.0;
+                    hash_word(&mut hasher, &word);
+                }
+                Some(hasher)
+            }
+            None => None,
+        };
+
+        let mut tmp_out_file_path = output_file.clone().into_os_string();
+        tmp_out_file_path.push(".tmp");
+        let tmp_out_file_path = PathBuf::from(tmp_out_file_path);
+        let mut workspace = TempWorkspace::new(tmp_dir, &file_name)?;
+        workspace.register(&tmp_out_file_path);
+        let staged_path = workspace.path(&file_name).with_extension("staged");
+        let staged = File::create(&staged_path).map_err(|e| CompressionError::FileCreate {
+            path: staged_path.display().to_string(),
+            source: e,
+        })?;
+
+        Ok(AppendCompressor {
+            segment,
+            output_file,
+            tmp_out_file_path,
+            patterns,
+            positions,
+            match_finder,
+            optimizer: OptimizerMode::Exact,
+            buffers: CoverBuffers::new(),
+            tally: CoverTally::default(),
+            staged: BufWriter::new(staged),
+            staged_path,
+            encoded: Vec::new(),
+            words: 0,
+            empty_words: 0,
+            word_hasher,
+            workspace,
+        })
+    }
+
+    /// How the words added are covered with the patterns of the segment
+    /// (default: [`OptimizerMode::Exact`])
+    pub fn optimizer(mut self, optimizer: OptimizerMode) -> Self {
+        self.optimizer = optimizer;
+        self
+    }
+
+    /// Add a word, covered with the patterns of the segment
+    pub fn add_word(&mut self, word: &[u8]) -> Result<(), CompressionError> {
+        self.add(word, true)
+    }
+
+    /// Add a word stored as it is
+    pub fn add_uncompressed_word(&mut self, word: &[u8]) -> Result<(), CompressionError> {
+        self.add(word, false)
+    }
+
+    fn add(&mut self, word: &[u8], compression: bool) -> Result<(), CompressionError> {
+        self.words += 1;
+        if word.is_empty() {
+            self.empty_words += 1;
+        }
+        if let Some(hasher) = &mut self.word_hasher {
+            hash_word(hasher, word);
+        }
+        *self.tally.pos_map.entry(word.len() as u64 + 1).or_insert(0) += 1;
+        *self.tally.pos_map.entry(0).or_insert(0) += 1;
+        self.encoded.clear();
+        cover_word(
+            false,
+            self.optimizer,
+            word,
+            compression,
+            &self.match_finder,
+            &mut self.buffers,
+            &mut self.tally,
+            &mut self.encoded,
+        );
+        self.staged.write_all(&self.encoded)?;
+        Ok(())
+    }
+
+    /// Words of the segment once the words added so far are appended
+    pub fn count(&self) -> usize {
+        self.segment.count() + self.words as usize
+    }
+
+    /// Rewrite the segment with the words added at its end, returning the
+    /// statistics of the new segment
+    pub fn finish(mut self) -> Result<CompressionStats, CompressionError> {
+        self.staged.flush()?;
+        let words = self.segment.count() as u64 + self.words;
+        let empty_words = self.segment.empty_words_count() as u64 + self.empty_words;
+
+        let mut out =
+            File::create(&self.tmp_out_file_path).map_err(|e| CompressionError::FileCreate {
+                path: self.tmp_out_file_path.display().to_string(),
+                source: e,
+            })?;
+        let known: HashSet<u64> = self.positions.iter().map(|p| p.pos).collect();
+        let copied = self.tally.pos_map.keys().all(|pos| known.contains(pos))
+            && self.write_with_positions(&mut out, words, empty_words)?;
+        if !copied {
+            self.write_with_new_positions(&mut out, words, empty_words)?;
+        }
+        if let Some(hasher) = &self.word_hasher {
+            append_checksum_footer(&self.tmp_out_file_path, hasher.digest())?;
+        }
+        out.sync_all()?;
+        drop(out);
+
+        let AppendCompressor {
+            segment,
+            output_file,
+            tmp_out_file_path,
+            mut workspace,
+            ..
+        } = self;
+        drop(segment);
+        fs::rename(&tmp_out_file_path, &output_file).map_err(|e| CompressionError::FileRename {
+            from: tmp_out_file_path.display().to_string(),
+            to: output_file.display().to_string(),
+            source: e,
+        })?;
+        workspace.forget(&tmp_out_file_path);
+        Ok(Decompressor::new(&output_file)?.stats())
+    }
+
+    // Every position the new words use has a code: copy the segment and
+    // append the new words, encoded with its dictionaries on their own;
+    // false if that can't be done
+    fn write_with_positions(
+        &mut self,
+        out: &mut File,
+        words: u64,
+        empty_words: u64,
+    ) -> Result<bool, CompressionError> {
+        let appended_path = self.staged_path.with_extension("appended");
+        let mut appended = File::create(&appended_path)?;
+        let (pattern_dict_size, pos_dict_size) = write_compressed_file(
+            &mut appended,
+            &self.staged_path,
+            &self.patterns,
+            &self.patterns,
+            &self.positions,
+            self.words,
+            self.empty_words,
+        )?;
+        drop(appended);
+        // Dictionaries written again with other varints would move the words
+        let stats = self.segment.stats();
+        if (pattern_dict_size, pos_dict_size) != (stats.pattern_dict_size, stats.pos_dict_size) {
+            return Ok(false);
+        }
+        let appended = fs::read(&appended_path)?;
+        let words_start = (32 + pattern_dict_size + pos_dict_size) as usize;
+
+        let mut w = BufWriter::new(&mut *out);
+        self.segment
+            .copy_words(0..self.segment.count() as u64, &mut w)?;
+        w.write_all(&appended[words_start..])?;
+        w.flush()?;
+        drop(w);
+        // The copy counts the words of the segment alone
+        out.seek(SeekFrom::Start(0))?;
+        let header = SegmentHeader {
+            word_count: words,
+            empty_word_count: empty_words,
+        };
+        out.write_all(&header.to_bytes())?;
+        out.seek(SeekFrom::End(0))?;
+        Ok(true)
+    }
+
+    // Some position is new: transcode the words of the segment to the
+    // intermediate encoding of the new words, ahead of them, and encode all
+    // of them with new position codes
+    fn write_with_new_positions(
+        &mut self,
+        out: &mut File,
+        words: u64,
+        empty_words: u64,
+    ) -> Result<(), CompressionError> {
+        let all_path = self.staged_path.with_extension("all");
+        let mut all = BufWriter::new(File::create(&all_path)?);
+        let mut pos_map = std::mem::take(&mut self.tally.pos_map);
+        // Patterns by their bytes: any entry with the same bytes decodes
+        // the same
+        let mut seq_codes: HashMap<&[u8], u64> = HashMap::new();
+        for pattern in &self.patterns {
+            seq_codes
+                .entry(&pattern.word)
+                .or_insert(pattern.sequential_code);
+        }
+
+        let mut num_buf = [0u8; 10];
+        let mut cover = Vec::new();
+        let mut getter = self.segment.make_getter();
+        while getter.has_next() {
+            let (word_len, uncovered) = getter.try_next_cover(&mut cover)?;
+            *pos_map.entry(word_len as u64 + 1).or_insert(0) += 1;
+            *pos_map.entry(0).or_insert(0) += 1;
+            let n = encode_varint(&mut num_buf, word_len as u64);
+            all.write_all(&num_buf[..n])?;
+            if word_len == 0 {
+                continue;
+            }
+            let n = encode_varint(&mut num_buf, cover.len() as u64);
+            all.write_all(&num_buf[..n])?;
+            let mut start = 0;
+            for &(pos, pattern) in &cover {
+                *pos_map.entry(pos).or_insert(0) += 1;
+                start += pos - 1;
+                let n = encode_varint(&mut num_buf, start);
+                all.write_all(&num_buf[..n])?;
+                let n = encode_varint(&mut num_buf, seq_codes[pattern]);
+                all.write_all(&num_buf[..n])?;
+            }
+            all.write_all(uncovered)?;
+        }
+        std::io::copy(&mut File::open(&self.staged_path)?, &mut all)?;
+        all.flush()?;
+        drop(all);
+
+        let positions = position_codes(&pos_map);
+        write_compressed_file(
+            out,
+            &all_path,
+            &self.patterns,
+            &self.patterns,
+            &positions,
+            words,
+            empty_words,
+        )?;
+        Ok(())
+    }
+}
+
+// Huffman codes of a dictionary from the depths of its entries, in file
+// order: the decoder's tables hand them out depth first, 0 bits first
+fn codes_from_depths(depths: &[u64]) -> Result<Vec<(u64, usize)>, CompressionError> {
+    fn assign(
+        depths: &[u64],
+        code: u64,
+        bits: usize,
+        codes: &mut Vec<(u64, usize)>,
+    ) -> Result<usize, CompressionError> {
+        match depths.first() {
+            None => Ok(0),
+            Some(&depth) if depth == bits as u64 => {
+                codes.push((code, bits));
+                Ok(1)
+            }
+            Some(&depth) if depth < bits as u64 || bits == 64 => {
+                Err(CompressionError::CorruptedData)
+            }
+            Some(_) => {
+                let b0 = assign(depths, code, bits + 1, codes)?;
+                let b1 = assign(&depths[b0..], code | 1 << bits, bits + 1, codes)?;
+                Ok(b0 + b1)
+            }
+        }
+    }
+
+    let mut codes = Vec::with_capacity(depths.len());
+    if assign(depths, 0, 0, &mut codes)? != depths.len() {
+        return Err(CompressionError::CorruptedData);
+    }
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SegmentChecksum;
+    use tempfile::TempDir;
+
+    fn word(i: u32) -> Vec<u8> {
+        format!("account {} balance {}", i % 97, i % 13).into_bytes()
+    }
+
+    #[test]
+    fn test_append_words() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("append.seg");
+        let mut compressor = crate::Compressor::builder(&path)
+            .fsync(false)
+            .min_pattern_score(4)
+            .sampling_factor(1)
+            .checksum(true)
+            .build()
+            .unwrap();
+        let mut words: Vec<Vec<u8>> = (0..2000).map(word).collect();
+        for word in &words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+        let original = fs::read(&path).unwrap();
+        let patterns: Vec<(u64, Vec<u8>)> = Decompressor::new(&path)
+            .unwrap()
+            .patterns()
+            .map(|(depth, pattern)| (depth, pattern.to_vec()))
+            .collect();
+
+        // Words like those of the segment need no new position: its words
+        // are kept byte for byte
+        let mut appender = AppendCompressor::open(&path, tmp_dir.path()).unwrap();
+        for i in 2000..2500 {
+            appender.add_word(&word(i)).unwrap();
+            words.push(word(i));
+        }
+        assert_eq!(appender.count(), 2500);
+        let stats = appender.finish().unwrap();
+        assert_eq!(stats.words, 2500);
+        let appended = fs::read(&path).unwrap();
+        let footer = original.len() - SegmentChecksum::parse_footer(&original).unwrap().1;
+        assert_eq!(appended[16..footer], original[16..footer]);
+
+        // A long word, an empty one and one stored as it is need new
+        // positions
+        let mut appender = AppendCompressor::open(&path, tmp_dir.path()).unwrap();
+        let long: Vec<u8> = (0..300).flat_map(word).collect();
+        appender.add_word(&long).unwrap();
+        appender.add_word(b"").unwrap();
+        appender.add_uncompressed_word(&word(7)).unwrap();
+        words.extend([long, Vec::new(), word(7)]);
+        appender.finish().unwrap();
+
+        let decompressor = Decompressor::new(&path).unwrap();
+        assert_eq!(decompressor.count(), words.len());
+        assert_eq!(decompressor.empty_words_count(), 1);
+        let kept: Vec<(u64, Vec<u8>)> = decompressor
+            .patterns()
+            .map(|(depth, pattern)| (depth, pattern.to_vec()))
+            .collect();
+        assert_eq!(kept, patterns);
+        decompressor.verify().unwrap();
+        decompressor.verify_checksum().unwrap();
+        let mut getter = decompressor.make_getter();
+        for word in &words {
+            assert_eq!(&getter.next(Vec::new()).0, word);
+        }
+        assert!(!getter.has_next());
+        // No staged words nor .seg.tmp are left behind
+        drop(compressor);
+        for entry in fs::read_dir(tmp_dir.path()).unwrap() {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            assert!(
+                !name.ends_with(".work") && !name.ends_with(".tmp"),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_codes_from_depths() {
+        assert_eq!(codes_from_depths(&[]).unwrap(), vec![]);
+        assert_eq!(codes_from_depths(&[0]).unwrap(), vec![(0, 0)]);
+        assert_eq!(
+            codes_from_depths(&[1, 2, 2]).unwrap(),
+            vec![(0, 1), (0b01, 2), (0b11, 2)]
+        );
+        assert!(codes_from_depths(&[2, 1]).is_err());
+    }
+}
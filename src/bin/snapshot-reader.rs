@@ -96,17 +96,20 @@ impl SnapshotReader {
     fn read_transactions(&self, start_tx_id: u64, count: u64) -> Vec<Vec<u8>> {
         let mut transactions = Vec::new();
 
-        for i in 0..count {
-            let tx_ordinal = start_tx_id + i;
-            if let Some(offset) = self.transactions_idx.ordinal_lookup(tx_ordinal) {
-                let mut getter = self.transactions_seg.make_getter();
-                getter.reset(offset);
-
-                if getter.has_next() {
-                    let (word, _) = getter.next(Vec::new());
-                    if !word.is_empty() {
-                        transactions.push(word);
-                    }
+        let offsets = self
+            .transactions_idx
+            .offsets(start_tx_id)
+            .into_iter()
+            .flatten()
+            .take(count as usize);
+        for offset in offsets {
+            let mut getter = self.transactions_seg.make_getter();
+            getter.reset(offset);
+
+            if getter.has_next() {
+                let (word, _) = getter.next(Vec::new());
+                if !word.is_empty() {
+                    transactions.push(word);
                 }
             }
         }
@@ -119,6 +122,7 @@ impl SnapshotReader {
             headers_count: self.headers_idx.key_count(),
             bodies_count: self.bodies_idx.key_count(),
             transactions_count: self.transactions_idx.key_count(),
+            tx_to_block_count: self.tx_to_block_idx.key_count(),
             headers_data_size: self.headers_seg.size(),
             bodies_data_size: self.bodies_seg.size(),
             transactions_data_size: self.transactions_seg.size(),
@@ -131,6 +135,7 @@ struct SnapshotStats {
     headers_count: u64,
     bodies_count: u64,
     transactions_count: u64,
+    tx_to_block_count: u64,
     headers_data_size: usize,
     bodies_data_size: usize,
     transactions_data_size: usize,
@@ -156,6 +161,7 @@ impl std::fmt::Display for SnapshotStats {
             "Transactions: {} entries, {} bytes",
             self.transactions_count, self.transactions_data_size
         )?;
+        writeln!(f, "Tx-to-block index: {} entries", self.tx_to_block_count)?;
         writeln!(
             f,
             "Total data: {} bytes",
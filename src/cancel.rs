@@ -0,0 +1,52 @@
+//! Cancellation of long operations
+//!
+//! Compression, [`Decompressor::verify_cancellable`] and
+//! [`SnapshotRepo::fsck_cancellable`] check a [`CancellationToken`] between
+//! words and phases, and give up with a `Cancelled` error once it is
+//! cancelled, like the Go code does with its `ctx`. A compression cancelled
+//! this way leaves no output and removes its intermediate files.
+//!
+//! [`Decompressor::verify_cancellable`]: crate::Decompressor::verify_cancellable
+//! [`SnapshotRepo::fsck_cancellable`]: crate::snapshots::SnapshotRepo::fsck_cancellable
+
+use crate::error::CompressionError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag to abort long operations
+///
+/// Clones share the flag, so a server keeps one clone and hands another to
+/// the operation; [`CancellationToken::cancel`] takes `&self` and may be
+/// called from any thread or task.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the operations holding a clone of the token to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // Err(Cancelled) once the token is cancelled
+    pub(crate) fn check(&self) -> Result<(), CompressionError> {
+        if self.is_cancelled() {
+            return Err(CompressionError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+// For operations that take the token optionally
+pub(crate) fn check(cancel: Option<&CancellationToken>) -> Result<(), CompressionError> {
+    cancel.map_or(Ok(()), CancellationToken::check)
+}
@@ -0,0 +1,33 @@
+//! Word transforms applied around compression
+//!
+//! Erigon reshapes some words before they are compressed so that patterns
+//! line up better: it strips key prefixes shared by a whole file, stores
+//! integers as deltas, reorders RLP fields. A [`WordCodec`] is the hook for
+//! such transforms: given to [`CompressorBuilder::word_codec`], every word is
+//! encoded before it is sampled for patterns and written; given to
+//! [`Decompressor::with_word_codec`], getters decode every word they return.
+//!
+//! The segment only holds the encoded words and says nothing about the
+//! codec, so a reader has to be given the same one the writer used.
+//!
+//! [`CompressorBuilder::word_codec`]: crate::compress::CompressorBuilder::word_codec
+//! [`Decompressor::with_word_codec`]: crate::decompress::Decompressor::with_word_codec
+
+/// A reversible transform of the words of a segment
+///
+/// `decode` must undo `encode`: decoding the encoded form of a word gives
+/// back the word. Both append to `out`, which may already hold data. Only
+/// words `encode` produced are decoded, so `decode` may assume its input is
+/// well formed.
+///
+/// A codec is shared by the compressor and by every getter of a decompressor,
+/// possibly on several threads, so it has to be `Send + Sync`; it gets no
+/// mutable access and should not keep state between words, since getters
+/// may start anywhere in the segment.
+pub trait WordCodec: Send + Sync {
+    /// Transform `word` into the form that is compressed
+    fn encode(&self, word: &[u8], out: &mut Vec<u8>);
+
+    /// Turn the stored form back into the word
+    fn decode(&self, stored: &[u8], out: &mut Vec<u8>);
+}
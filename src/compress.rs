@@ -1,12 +1,25 @@
 // Port of Erigon's compress.go
 // Original: go/src/compress.go
 
-use crate::error::CompressionError;
+use crate::cancel::{self, CancellationToken};
+use crate::codec::WordCodec;
+use crate::decompress::SafeReader;
+use crate::decompress::SegmentChecksum;
+use crate::error::{CompressError, CompressionError, ReadError};
+use crate::export::ExportFormat;
+use crate::metrics;
+use crate::output::{SegmentFile, SyncPolicy};
+use crate::trace::word_trace;
+use crate::word_source::WordSource;
+use crate::workspace::TempWorkspace;
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use xxhash_rust::xxh64::Xxh64;
 
 // From Go: Cfg struct - compression configuration
 #[derive(Debug, Clone)]
@@ -27,7 +40,96 @@ pub struct Cfg {
     // samplingFactor - skip superstrings if `superstringNumber % samplingFactor != 0`
     pub sampling_factor: u64,
 
+    // sampling picks the words the dictionary is built from; sampling_factor
+    // only applies to SamplingStrategy::EveryNth
+    pub sampling: SamplingStrategy,
+
+    // workers cover words in parallel; the output does not depend on it
     pub workers: usize,
+
+    // level selects whether a dictionary is built at all; see CompressionLevel
+    pub level: CompressionLevel,
+
+    // checksum appends a footer with xxhash64 checksums of the words and of
+    // the file before it; see SegmentChecksum. Erigon does not read it
+    pub checksum: bool,
+
+    // superstringMemoryLimit caps the bytes of sampled superstrings kept in
+    // RAM; past it they are spilled to a file in tmp_dir and streamed back
+    // while building the dictionary. None keeps them all in RAM like Go
+    pub superstring_memory_limit: Option<usize>,
+
+    // optimizer picks how words are covered by dictionary patterns; see
+    // OptimizerMode
+    pub optimizer: OptimizerMode,
+
+    // verifyCodes checks the Huffman codes before the dictionaries are
+    // written: prefix-free, within the depth the decompressor accepts, and
+    // decoding to their own entries. Always on in debug builds
+    pub verify_codes: bool,
+
+    // pruneDictionary runs the encoding twice: the second time without the
+    // patterns that cost more in the dictionary than their uses save in the
+    // first; see DictionaryBuilder::prune
+    pub prune_dictionary: bool,
+}
+
+/// How hard the compressor tries.
+///
+/// `Store` skips superstring sampling and dictionary building entirely and
+/// writes every word as a pass-through word (no patterns). The output is
+/// still a regular .seg file with an empty pattern dictionary, so Erigon and
+/// [`crate::Decompressor`] read it like any other segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionLevel {
+    #[default]
+    Default,
+    Store,
+}
+
+/// Which words the dictionary is built from.
+///
+/// `EveryNth` is Erigon's: only every `sampling_factor`-th superstring is
+/// sampled, so for sorted inputs the dictionary leans toward whatever sorts
+/// first in each superstring-sized stretch. The others pick words from the
+/// whole input and keep them in memory until [`Compressor::compress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplingStrategy {
+    #[default]
+    EveryNth,
+    /// A uniform random sample of `words` words (reservoir sampling),
+    /// reproducible for a given `seed`
+    Reservoir { words: usize, seed: u64 },
+    /// Words spread evenly over the input, as many as fit in `bytes` of
+    /// superstring (each word takes twice its length plus two bytes)
+    ByteBudget { bytes: usize },
+    /// The first `words` words
+    FirstN { words: usize },
+}
+
+/// How words are covered by the dictionary's patterns.
+///
+/// `Exact` is Erigon's dynamic program, which finds the cover that saves the
+/// most bytes. `Greedy` takes every longest match that saves bytes instead,
+/// for words of at least `min_word_len` bytes: on long words it is much
+/// faster, for a slightly worse ratio. Either way the segment reads the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptimizerMode {
+    #[default]
+    Exact,
+    Greedy {
+        min_word_len: usize,
+    },
+}
+
+impl OptimizerMode {
+    /// Whether a word of `len` bytes is covered greedily
+    pub fn is_greedy_for(self, len: usize) -> bool {
+        match self {
+            OptimizerMode::Exact => false,
+            OptimizerMode::Greedy { min_word_len } => len >= min_word_len,
+        }
+    }
 }
 
 impl Default for Cfg {
@@ -38,9 +140,16 @@ impl Default for Cfg {
             min_pattern_len: 5,
             max_pattern_len: 128,
             sampling_factor: 4,
+            sampling: SamplingStrategy::EveryNth,
             max_dict_patterns: 64 * 1024,
             dict_reducer_soft_limit: 1_000_000,
             workers: 1,
+            level: CompressionLevel::Default,
+            checksum: false,
+            superstring_memory_limit: None,
+            optimizer: OptimizerMode::Exact,
+            verify_codes: false,
+            prune_dictionary: false,
         }
     }
 }
@@ -49,15 +158,15 @@ impl Default for Cfg {
 // From Go: Compressor struct
 pub struct Compressor {
     cfg: Cfg,
-    output_file: String,
+    output_file: PathBuf,
     file_name: String, // File where to output the dictionary and compressed data
-    tmp_dir: String,   // temporary directory to use for ETL when building dictionary
+    tmp_dir: PathBuf,  // temporary directory to use for ETL when building dictionary
     log_prefix: String,
 
     // From Go: compress.go:105-116
-    superstrings: Vec<Vec<u8>>, // Collecting superstrings instead of using channels for now
+    superstrings: Superstrings, // Collecting superstrings instead of using channels for now
     uncompressed_file: Option<RawWordsFile>,
-    tmp_out_file_path: String,
+    tmp_out_file_path: PathBuf,
 
     // Buffer for "superstring" - transformation where each byte of a word, say b,
     // is turned into 2 bytes, 0x01 and b, and two zero bytes 0x00 0x00 are inserted after each word
@@ -66,10 +175,19 @@ pub struct Compressor {
     words_count: u64,
     superstring_count: u64,
     superstring_len: usize,
-
-    // Compression ratio after compression
-    ratio: CompressionRatio,
-    no_fsync: bool,
+    // Words kept by the sampling strategies other than EveryNth
+    sampler: WordSampler,
+
+    // Dictionary given with with_dictionary, or the one the last
+    // compression run built
+    dictionary: Option<DictionaryBuilder>,
+    // Sizes of the segment written by the last compression run
+    sizes: SizeBreakdown,
+    // Statistics of the last compression run
+    stats: CompressionStats,
+    sync: SyncPolicy,
+    // Write the segment with O_DIRECT, see CompressorBuilder::direct_io
+    direct_io: bool,
 
     // Go uses sync.WaitGroup, we'll use different synchronization when needed
     // wg: Arc<WaitGroup>,
@@ -77,55 +195,170 @@ pub struct Compressor {
     // suffixCollectors: Vec<etl::Collector>,
     lvl: log::Level,
     trace: bool,
+    progress: Option<ProgressFn>,
+    // Checked between words and phases, see CompressorBuilder::cancellation
+    cancel: Option<CancellationToken>,
+    // Export written next to the output after compression
+    companion: Option<ExportFormat>,
+    // Checksum of the words added so far, for the footer
+    word_hasher: Xxh64,
+    // Checkpoint after this many words, see CompressorBuilder::checkpoint_every
+    checkpoint_every: Option<u64>,
+    // Transform applied to every word added, see CompressorBuilder::word_codec
+    codec: Option<Arc<dyn WordCodec>>,
+    // Reused for the encoded form of the word being added
+    encoded: Vec<u8>,
+    state: CompressorState,
+    // Holds the intermediate files; dropped last so they are closed first
+    workspace: TempWorkspace,
+}
+
+/// Where a [`Compressor`] is in its run
+///
+/// Words are added while collecting; [`Compressor::compress`] moves on to
+/// compressed, after which adding words or compressing again is an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressorState {
+    Collecting,
+    /// `compress` was called, whether it succeeded or not
+    Compressed,
 }
 
+/// Progress callback, called with the number of words processed so far and
+/// the total number of words in the segment
+pub type ProgressFn = Box<dyn Fn(u64, u64) + Send + Sync>;
+
 impl Compressor {
     pub fn new(
         cfg: Cfg,
-        output_file: String,
-        tmp_dir: String,
+        output_file: impl Into<PathBuf>,
+        tmp_dir: impl Into<PathBuf>,
         log_prefix: String,
         lvl: log::Level,
+    ) -> std::result::Result<Self, CompressionError> {
+        Self::open(
+            cfg,
+            output_file.into(),
+            tmp_dir.into(),
+            log_prefix,
+            lvl,
+            None,
+        )
+    }
+
+    // A compressor with a new workspace, or the one of an earlier run to
+    // resume from its checkpoint
+    fn open(
+        cfg: Cfg,
+        output_file: PathBuf,
+        tmp_dir: PathBuf,
+        log_prefix: String,
+        lvl: log::Level,
+        resume_from: Option<&Path>,
     ) -> std::result::Result<Self, CompressionError> {
         // Go: compress.go:127-131
-        let path = PathBuf::from(&output_file);
-        let file_name = path
+        let file_name = output_file
             .file_name()
-            .ok_or_else(|| CompressionError::Other("Invalid output file path".to_string()))?
+            .ok_or_else(|| CompressError::InvalidOutputPath {
+                path: output_file.display().to_string(),
+            })?
             .to_string_lossy()
             .to_string();
 
         // tmpOutFilePath is a ".seg.tmp" file which will be renamed to ".seg" if everything succeeds
-        let tmp_out_file_path = format!("{}.tmp", output_file);
+        let mut tmp_out_file_path = output_file.clone().into_os_string();
+        tmp_out_file_path.push(".tmp");
+        let tmp_out_file_path = PathBuf::from(tmp_out_file_path);
+
+        // Intermediate files go to a workspace of this run, removed on drop
+        // even if compression fails; the .seg.tmp has to sit next to the
+        // output, so it is only registered
+        let mut workspace = match resume_from {
+            Some(dir) => TempWorkspace::reopen(dir)?,
+            None => TempWorkspace::new(&tmp_dir, &file_name)?,
+        };
+        workspace.register(&tmp_out_file_path);
 
         // Create uncompressed file path
         // Go: compress.go:133
-        let uncompressed_path = PathBuf::from(&tmp_dir)
-            .join(&file_name)
-            .with_extension("idt");
+        let uncompressed_path = workspace.path(&file_name).with_extension("idt");
 
         // Go: compress.go:134-137
-        let uncompressed_file = RawWordsFile::new(uncompressed_path.to_string_lossy().to_string())?;
+        let uncompressed_file = match resume_from {
+            Some(_) => RawWordsFile::resume(uncompressed_path)?,
+            None => RawWordsFile::new(uncompressed_path)?,
+        };
 
         // Note: Using synchronous superstring collection instead of Go's parallel workers/channels
-        Ok(Compressor {
+        let superstrings = Superstrings::new(
+            cfg.superstring_memory_limit,
+            workspace.path(&file_name).with_extension("superstrings"),
+        );
+        let sampler = WordSampler::new(cfg.sampling);
+        let mut compressor = Compressor {
             cfg,
             output_file,
             file_name,
             tmp_dir,
             log_prefix,
-            superstrings: Vec::new(),
+            superstrings,
             uncompressed_file: Some(uncompressed_file),
             tmp_out_file_path,
             superstring: Vec::with_capacity(1024 * 1024),
             words_count: 0,
             superstring_count: 0,
             superstring_len: 0,
-            ratio: 0.0,
-            no_fsync: false,
+            sampler,
+            dictionary: None,
+            sizes: SizeBreakdown::default(),
+            stats: CompressionStats::default(),
+            sync: SyncPolicy::OnClose,
+            direct_io: false,
             lvl,
             trace: lvl <= log::Level::Trace,
-        })
+            progress: None,
+            cancel: None,
+            companion: None,
+            word_hasher: Xxh64::new(0),
+            checkpoint_every: None,
+            codec: None,
+            encoded: Vec::new(),
+            state: CompressorState::Collecting,
+            workspace,
+        };
+        if resume_from.is_some() {
+            if let Err(e) = compressor.restore() {
+                // Left for a resume with the right settings
+                compressor.superstrings.keep = true;
+                compressor.workspace.keep();
+                return Err(e);
+            }
+        }
+        Ok(compressor)
+    }
+
+    /// Start building a compressor that writes to `output_file`
+    pub fn builder(output_file: impl Into<PathBuf>) -> CompressorBuilder {
+        CompressorBuilder::new(output_file)
+    }
+
+    /// Compress with a dictionary trained on another segment, e.g. one taken
+    /// from [`Compressor::dictionary`] or [`DictionaryBuilder::deserialize`]
+    ///
+    /// Words are then not sampled into superstrings and the suffix array
+    /// phase of [`Compressor::compress`] is skipped, which pays off when
+    /// compressing many similarly-shaped files such as header ranges. Call
+    /// before adding words.
+    pub fn with_dictionary(mut self, dict: DictionaryBuilder) -> Self {
+        self.dictionary = Some(dict);
+        self
+    }
+
+    /// The dictionary words are compressed with: the one given to
+    /// [`Compressor::with_dictionary`], or after [`Compressor::compress`]
+    /// the one it built
+    pub fn dictionary(&self) -> Option<&DictionaryBuilder> {
+        self.dictionary.as_ref()
     }
 
     // From Go: compress.go:182
@@ -133,21 +366,109 @@ impl Compressor {
         self.words_count
     }
 
+    pub fn state(&self) -> CompressorState {
+        self.state
+    }
+
+    fn ensure_collecting(&self) -> std::result::Result<(), CompressionError> {
+        match self.state {
+            CompressorState::Collecting => Ok(()),
+            CompressorState::Compressed => Err(CompressError::AlreadyCompressed {
+                file: self.file_name.clone(),
+            }
+            .into()),
+        }
+    }
+
+    // Every word added is counted once, by the compressor and by the file
+    // of raw words alike, and ends up in the segment
+    fn check_word_count(
+        &self,
+        place: &'static str,
+        actual: u64,
+    ) -> std::result::Result<(), CompressionError> {
+        if actual == self.words_count {
+            return Ok(());
+        }
+        Err(CompressError::WordCountMismatch {
+            file: self.file_name.clone(),
+            place,
+            added: self.words_count,
+            actual,
+        }
+        .into())
+    }
+
+    // Run `add` with the encoded form of `word` if there is a word codec
+    fn with_encoded<R>(&mut self, word: &[u8], add: impl FnOnce(&mut Self, &[u8]) -> R) -> R {
+        let Some(codec) = self.codec.clone() else {
+            return add(self, word);
+        };
+        let mut encoded = std::mem::take(&mut self.encoded);
+        encoded.clear();
+        codec.encode(word, &mut encoded);
+        let result = add(self, &encoded);
+        self.encoded = encoded;
+        result
+    }
+
     // From Go: AddWord method - compress.go:195-222
     // REVIEW Q: why is go using a channel here?
     pub fn add_word(&mut self, word: &[u8]) -> std::result::Result<(), CompressionError> {
+        self.ensure_collecting()?;
+        if self.cfg.level == CompressionLevel::Store {
+            // No dictionary will be built, so there is nothing to sample
+            return self.with_encoded(word, Self::append_uncompressed);
+        }
+        self.with_encoded(word, Self::append_word)
+    }
+
+    // Count, sample and store a word as it is to be compressed
+    fn append_word(&mut self, word: &[u8]) -> std::result::Result<(), CompressionError> {
         self.words_count += 1;
+        if self.cfg.checksum {
+            hash_word(&mut self.word_hasher, word);
+        }
+
+        if self.dictionary.is_some() {
+            // The dictionary is given, so there is nothing to sample
+            if let Some(ref mut file) = self.uncompressed_file {
+                file.append(word)?;
+            }
+            return self.checkpoint_if_due();
+        }
 
+        if self.cfg.sampling == SamplingStrategy::EveryNth {
+            self.sample_every_nth(word)?;
+        } else {
+            self.sampler.offer(self.cfg.sampling, word);
+        }
+
+        // Also write to uncompressed file like Go does
+        // Go: compress.go:221
+        if let Some(ref mut file) = self.uncompressed_file {
+            file.append(word)?;
+        }
+
+        self.checkpoint_if_due()
+    }
+
+    // Sample a word into the current superstring if it is one of every
+    // sampling_factor-th superstrings
+    fn sample_every_nth(&mut self, word: &[u8]) -> std::result::Result<(), CompressionError> {
         // Calculate length: 2*len(word) + 2 for the encoding
         let l = 2 * word.len() + 2;
 
         // Check if we need to start a new superstring
         if self.superstring_len + l > SUPERSTRING_LIMIT {
             // Go: compress.go:205-210
-            if self.superstring_count % self.cfg.sampling_factor == 0 {
+            if self
+                .superstring_count
+                .is_multiple_of(self.cfg.sampling_factor)
+            {
                 // Save current superstring
                 let ss = std::mem::replace(&mut self.superstring, Vec::with_capacity(1024 * 1024));
-                self.superstrings.push(ss);
+                self.superstrings.push(ss)?;
             }
             self.superstring_count += 1;
             self.superstring_len = 0;
@@ -157,21 +478,25 @@ impl Compressor {
 
         // Only add to superstring if we're sampling this one
         // Go: compress.go:214-221
-        if self.superstring_count % self.cfg.sampling_factor == 0 {
-            for &byte in word {
-                self.superstring.push(0x01);
-                self.superstring.push(byte);
-            }
-            self.superstring.push(0x00);
-            self.superstring.push(0x00);
+        if self
+            .superstring_count
+            .is_multiple_of(self.cfg.sampling_factor)
+        {
+            push_superstring_word(&mut self.superstring, word);
         }
+        Ok(())
+    }
 
-        // Also write to uncompressed file like Go does
-        // Go: compress.go:221
-        if let Some(ref mut file) = self.uncompressed_file {
-            file.append(word)?;
+    // Append a word picked by the other sampling strategies, starting a new
+    // superstring when the current one is full
+    fn append_to_superstring(&mut self, word: &[u8]) -> std::result::Result<(), CompressionError> {
+        if !self.superstring.is_empty()
+            && self.superstring.len() + 2 * word.len() + 2 > SUPERSTRING_LIMIT
+        {
+            let ss = std::mem::replace(&mut self.superstring, Vec::with_capacity(1024 * 1024));
+            self.superstrings.push(ss)?;
         }
-
+        push_superstring_word(&mut self.superstring, word);
         Ok(())
     }
 
@@ -180,185 +505,1360 @@ impl Compressor {
         &mut self,
         word: &[u8],
     ) -> std::result::Result<(), CompressionError> {
+        self.ensure_collecting()?;
+        self.with_encoded(word, Self::append_uncompressed)
+    }
+
+    // Count and store a word that is kept uncompressed
+    fn append_uncompressed(&mut self, word: &[u8]) -> std::result::Result<(), CompressionError> {
         self.words_count += 1;
+        if self.cfg.checksum {
+            hash_word(&mut self.word_hasher, word);
+        }
 
         if let Some(ref mut file) = self.uncompressed_file {
             file.append_uncompressed(word)?;
-            Ok(())
+            self.checkpoint_if_due()
         } else {
-            Err(CompressionError::Other(
-                "Uncompressed file not initialized".to_string(),
-            ))
+            Err(CompressError::UncompressedFileNotInitialized {
+                file: self.file_name.clone(),
+            }
+            .into())
+        }
+    }
+
+    /// Add every word of `source`, returning how many were added
+    ///
+    /// Words flagged uncompressed go through
+    /// [`Compressor::add_uncompressed_word`], the others through
+    /// [`Compressor::add_word`].
+    pub fn add_from(
+        &mut self,
+        mut source: impl WordSource,
+    ) -> std::result::Result<u64, CompressionError> {
+        let mut added = 0;
+        while let Some((word, compressed)) = source.next_word() {
+            if compressed {
+                self.add_word(&word)?;
+            } else {
+                self.add_uncompressed_word(&word)?;
+            }
+            added += 1;
+        }
+        match source.take_error() {
+            Some(e) => Err(e),
+            None => Ok(added),
         }
     }
 
+    /// Add every word of `source` and compress them, see
+    /// [`Compressor::add_from`]
+    pub fn compress_from(
+        &mut self,
+        source: impl WordSource,
+    ) -> std::result::Result<(), CompressionError> {
+        self.add_from(source)?;
+        self.compress()
+    }
+
     // From Go: Compress - compress.go:235-292
     pub fn compress(&mut self) -> std::result::Result<(), CompressionError> {
         use std::fs;
         use std::time::Instant;
 
+        self.ensure_collecting()?;
+        // Sampling is finished and the raw words consumed below, so even a
+        // failed run can't take more words
+        self.state = CompressorState::Compressed;
         let start = Instant::now();
-
-        // Flush uncompressed file
-        if let Some(ref mut uf) = self.uncompressed_file {
-            uf.flush()?;
-        }
-
-        // Add any remaining superstring
-        if !self.superstring.is_empty() {
-            let ss = std::mem::take(&mut self.superstring);
-            self.superstrings.push(ss);
-        }
-
-        log::info!(
-            "[{}] Building dictionary from {} superstrings",
-            self.log_prefix,
-            self.superstrings.len()
-        );
+        let _span = tracing::info_span!("compress", file = %self.file_name).entered();
+        self.finish_sampling()?;
+        cancel::check(self.cancel.as_ref())?;
+        let raw_count = self.uncompressed_file.as_ref().map_or(0, |uf| uf.count);
+        self.check_word_count("in the raw words file", raw_count)?;
+        let intermediate_path = self
+            .workspace
+            .path(&self.file_name)
+            .with_extension("intermediate");
 
         // Build dictionary from collected superstrings (synchronous version)
-        let dict_builder = self.build_dictionary_from_superstrings()?;
+        let dict_span = tracing::info_span!("dictionary").entered();
+        let dict_start = Instant::now();
+        let store_dict;
+        let dict_builder = match self.cfg.level {
+            CompressionLevel::Default => {
+                self.ensure_dictionary()?;
+                if self.checkpoint_every.is_some() {
+                    // A run killed while covering words resumes with the
+                    // dictionary instead of building it again
+                    self.checkpoint()?;
+                }
+                if let (true, Some(uf), Some(dict)) = (
+                    self.cfg.prune_dictionary,
+                    self.uncompressed_file.as_mut(),
+                    self.dictionary.as_mut(),
+                ) {
+                    prune_dictionary(
+                        self.trace,
+                        &self.cfg,
+                        &self.log_prefix,
+                        &intermediate_path,
+                        uf,
+                        dict,
+                        self.cancel.as_ref(),
+                    )?;
+                }
+                self.dictionary.as_ref().expect("dictionary set above")
+            }
+            CompressionLevel::Store => {
+                store_dict = DictionaryBuilder::new(self.cfg.dict_reducer_soft_limit);
+                &store_dict
+            }
+        };
+        let dict_time = dict_start.elapsed();
+        drop(dict_span);
+        tracing::info!(
+            patterns = dict_builder.len(),
+            superstrings = self.superstrings.len(),
+            elapsed = ?dict_time,
+            "[{}] Dictionary phase done",
+            self.log_prefix
+        );
 
         // Save dictionary for debugging if trace is enabled
         if self.trace {
-            let dict_path = PathBuf::from(&self.tmp_dir)
+            let dict_path = self
+                .tmp_dir
                 .join(&self.file_name)
                 .with_extension("dictionary.txt");
-            persist_dictionary(&dict_path, &dict_builder)?;
+            persist_dictionary(&dict_path, dict_builder)?;
         }
 
         // Create compressed file
-        let cf =
-            File::create(&self.tmp_out_file_path).map_err(|e| CompressionError::FileCreate {
-                path: self.tmp_out_file_path.clone(),
-                source: e,
-            })?;
+        let mut cf = SegmentFile::create(&self.tmp_out_file_path, self.sync, self.direct_io)?;
+        if cf.is_direct() {
+            tracing::debug!("[{}] Writing with direct IO", self.log_prefix);
+        }
 
         // Compress with pattern candidates
-        if let Some(ref mut uf) = self.uncompressed_file {
-            crate::parallel_compress::compress_with_pattern_candidates(
-                self.trace,
-                &self.cfg,
-                &self.log_prefix,
-                &self.tmp_out_file_path,
-                &mut cf.try_clone()?,
-                uf,
-                &dict_builder,
-            )?;
+        let uf = self.uncompressed_file.as_mut().ok_or_else(|| {
+            CompressError::UncompressedFileNotInitialized {
+                file: self.file_name.clone(),
+            }
+        })?;
+        self.stats = crate::parallel_compress::compress_with_pattern_candidates(
+            self.trace,
+            &self.cfg,
+            &self.log_prefix,
+            &intermediate_path,
+            &mut cf,
+            uf,
+            dict_builder,
+            self.progress.as_deref(),
+            self.cancel.as_ref(),
+        )?;
+        self.check_word_count("in the segment header", self.stats.words)?;
+
+        let cf = cf.finish()?;
+        if self.cfg.checksum {
+            append_checksum_footer(&self.tmp_out_file_path, self.word_hasher.digest())?;
         }
 
         // Sync and close file
         self.fsync(&cf)?;
         drop(cf);
+        // Last chance before the output appears
+        cancel::check(self.cancel.as_ref())?;
 
         // Rename temp file to final output
         fs::rename(&self.tmp_out_file_path, &self.output_file).map_err(|e| {
             CompressionError::FileRename {
-                from: self.tmp_out_file_path.clone(),
-                to: self.output_file.clone(),
+                from: self.tmp_out_file_path.display().to_string(),
+                to: self.output_file.display().to_string(),
                 source: e,
             }
         })?;
+        self.workspace.forget(&self.tmp_out_file_path);
+
+        self.stats.output_bytes = fs::metadata(&self.output_file)?.len();
+        self.sizes = SizeBreakdown::new(&self.stats, self.cfg.checksum);
+        if let Some(format) = self.companion {
+            let path = self.output_file.with_extension(format.extension());
+            let mut decompressor = crate::decompress::Decompressor::new(&self.output_file)?;
+            decompressor.codec = self.codec.clone();
+            crate::export::export_words(
+                &decompressor,
+                &path,
+                format,
+                self.sync != SyncPolicy::None,
+            )?;
+        }
+        if let Some(phases) = &mut self.stats.phases {
+            phases.dictionary = dict_time;
+            metrics::observe(
+                metrics::COMPRESS_DICTIONARY_SECONDS,
+                phases.dictionary.as_secs_f64(),
+            );
+            metrics::observe(metrics::COMPRESS_COVER_SECONDS, phases.cover.as_secs_f64());
+            metrics::observe(metrics::COMPRESS_WRITE_SECONDS, phases.write.as_secs_f64());
+        }
+
+        // Log completion
+        if self.lvl <= log::Level::Info {
+            tracing::info!(
+                uncompressed_bytes = self.sizes.uncompressed_bytes,
+                compressed_bytes = self.sizes.compressed_bytes,
+                dictionary_bytes = self.sizes.pattern_dict_bytes + self.sizes.pos_dict_bytes,
+                "[{}] Compress took {:?}, ratio: {}, file: {}",
+                self.log_prefix,
+                start.elapsed(),
+                ratio_to_string(self.sizes.ratio()),
+                self.file_name
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Project the outcome of [`Compressor::compress`] without writing the
+    /// segment, to tune [`Cfg`] before an hours-long run
+    ///
+    /// The dictionary is built as `compress` would build it, then about
+    /// `sample_words` words spread evenly over the input are covered with it
+    /// and encoded into a byte counter. Their size, and the time it took, are
+    /// scaled up to the whole input; the dictionaries are counted once, as
+    /// the sample used them, so on small inputs the projection runs low. The
+    /// dictionary is kept, so a `compress` that follows does not build it
+    /// again.
+    pub fn estimate(
+        &mut self,
+        sample_words: u64,
+    ) -> std::result::Result<CompressionEstimate, CompressionError> {
+        use std::time::Instant;
+
+        self.ensure_collecting()?;
+        self.finish_sampling()?;
+        let dict_start = Instant::now();
+        let store_dict;
+        let dict_builder = match self.cfg.level {
+            CompressionLevel::Default => {
+                self.ensure_dictionary()?;
+                self.dictionary.as_ref().expect("dictionary set above")
+            }
+            CompressionLevel::Store => {
+                store_dict = DictionaryBuilder::new(self.cfg.dict_reducer_soft_limit);
+                &store_dict
+            }
+        };
+        let dictionary_time = dict_start.elapsed();
+
+        let uf = self.uncompressed_file.as_mut().ok_or_else(|| {
+            CompressError::UncompressedFileNotInitialized {
+                file: self.file_name.clone(),
+            }
+        })?;
+        let input_bytes = std::fs::metadata(&uf.file_path)?.len();
+        let step = uf.count.div_ceil(sample_words.max(1)).max(1);
+        let mut sample = RawWordsFile::new(self.workspace.path("estimate.idt"))?;
+        let mut i = 0u64;
+        let mut word_bytes = 0u64;
+        uf.for_each(|word, compressed| {
+            word_bytes += word.len() as u64;
+            if i.is_multiple_of(step) {
+                if compressed {
+                    sample.append(word)?;
+                } else {
+                    sample.append_uncompressed(word)?;
+                }
+            }
+            i += 1;
+            Ok(())
+        })?;
+        sample.flush()?;
+        let sample_input_bytes = std::fs::metadata(&sample.file_path)?.len();
+
+        let cover_start = Instant::now();
+        let intermediate_path = self.workspace.path("estimate.intermediate");
+        // Pruned for the sample only; compress prunes for the whole input
+        let pruned;
+        let dict_builder = if self.cfg.prune_dictionary {
+            let mut dict = dict_builder.clone();
+            prune_dictionary(
+                self.trace,
+                &self.cfg,
+                &self.log_prefix,
+                &intermediate_path,
+                &mut sample,
+                &mut dict,
+                self.cancel.as_ref(),
+            )?;
+            pruned = dict;
+            &pruned
+        } else {
+            dict_builder
+        };
+        let mut counter = ByteCounter(0);
+        let stats = crate::parallel_compress::compress_with_pattern_candidates(
+            self.trace,
+            &self.cfg,
+            &self.log_prefix,
+            &intermediate_path,
+            &mut counter,
+            &mut sample,
+            dict_builder,
+            None,
+            self.cancel.as_ref(),
+        )?;
+        let cover_time = cover_start.elapsed();
+        let sampled_words = sample.count;
+        sample.close_and_remove()?;
+        std::fs::remove_file(&intermediate_path).ok();
+
+        // The header and dictionaries are written once; the words scale
+        let fixed_bytes = SEGMENT_HEADER_LEN + stats.pattern_dict_size + stats.pos_dict_size;
+        let scale = if sample_input_bytes == 0 {
+            1.0
+        } else {
+            input_bytes as f64 / sample_input_bytes as f64
+        };
+        let words_bytes = counter.0.saturating_sub(fixed_bytes) as f64 * scale;
+        let projected_output_bytes = fixed_bytes + words_bytes.round() as u64;
+        let estimate = CompressionEstimate {
+            sampled_words,
+            input_bytes,
+            sampled_output_bytes: counter.0,
+            projected_output_bytes,
+            ratio: word_bytes as f64 / projected_output_bytes as f64,
+            patterns: stats.patterns,
+            pattern_dict_size: stats.pattern_dict_size,
+            dictionary_time,
+            cover_time,
+            projected_time: dictionary_time + cover_time.mul_f64(scale),
+        };
+        tracing::info!(
+            "[{}] Estimated ratio {} from {} of {} words, file: {}",
+            self.log_prefix,
+            ratio_to_string(estimate.ratio),
+            sampled_words,
+            self.words_count,
+            self.file_name
+        );
+        Ok(estimate)
+    }
+
+    /// Directory of the intermediate files of this run, where checkpoints
+    /// are written
+    pub fn workspace(&self) -> &Path {
+        self.workspace.dir()
+    }
+
+    /// Save the state of the run to its workspace, so that after a crash
+    /// it can go on from here with [`CompressorBuilder::resume`]
+    ///
+    /// The words added so far are flushed to the raw words file, sampled
+    /// superstrings to spill files, and the sampling counters, the sampler
+    /// and the dictionary, if there is one yet, to a checkpoint file that
+    /// replaces the previous one. Words added after the last checkpoint are
+    /// dropped on resume; [`Compressor::count`] then tells which word to go
+    /// on from. Unless fsync is disabled, the files are synced first.
+    pub fn checkpoint(&mut self) -> std::result::Result<(), CompressionError> {
+        let sync = self.sync != SyncPolicy::None;
+        let uf = self.uncompressed_file.as_mut().ok_or_else(|| {
+            CompressError::UncompressedFileNotInitialized {
+                file: self.file_name.clone(),
+            }
+        })?;
+        uf.flush()?;
+        if sync {
+            uf.f.sync_data()?;
+        }
+        let (head_bytes, spill_bytes) = self.superstrings.checkpoint(sync)?;
+
+        let mut data = Vec::new();
+        data.extend_from_slice(CHECKPOINT_MAGIC);
+        data.extend_from_slice(&cfg_fingerprint(&self.cfg).to_be_bytes());
+        put_bytes(&mut data, self.file_name.as_bytes());
+        put_uvarint(&mut data, self.words_count);
+        put_uvarint(&mut data, self.superstring_count);
+        put_uvarint(&mut data, self.superstring_len as u64);
+        put_bytes(&mut data, &self.superstring);
+        put_uvarint(&mut data, self.superstrings.in_memory.len() as u64);
+        put_uvarint(&mut data, head_bytes);
+        put_uvarint(&mut data, self.superstrings.spilled as u64);
+        put_uvarint(&mut data, spill_bytes);
+        self.sampler.encode(&mut data);
+        match &self.dictionary {
+            Some(dict) => {
+                data.push(1);
+                put_bytes(&mut data, &dict.serialize());
+            }
+            None => data.push(0),
+        }
+
+        // Replace the previous checkpoint in one step
+        let path = self.workspace.path(CHECKPOINT_FILE);
+        let tmp_path = self.workspace.path(format!("{}.tmp", CHECKPOINT_FILE));
+        let mut f = File::create(&tmp_path).map_err(|e| CompressionError::FileCreate {
+            path: tmp_path.display().to_string(),
+            source: e,
+        })?;
+        f.write_all(&data)?;
+        if sync {
+            f.sync_data()?;
+        }
+        drop(f);
+        std::fs::rename(&tmp_path, &path).map_err(|e| CompressionError::FileRename {
+            from: tmp_path.display().to_string(),
+            to: path.display().to_string(),
+            source: e,
+        })?;
+        tracing::debug!(
+            "[{}] Checkpoint after {} words in {}",
+            self.log_prefix,
+            self.words_count,
+            self.workspace.dir().display()
+        );
+        Ok(())
+    }
+
+    /// Checkpoint and stop, keeping the workspace for
+    /// [`CompressorBuilder::resume`], whose directory is returned
+    pub fn suspend(mut self) -> std::result::Result<PathBuf, CompressionError> {
+        self.checkpoint()?;
+        self.superstrings.keep = true;
+        Ok(self.workspace.keep())
+    }
+
+    fn checkpoint_if_due(&mut self) -> std::result::Result<(), CompressionError> {
+        match self.checkpoint_every {
+            Some(every) if self.words_count.is_multiple_of(every) => self.checkpoint(),
+            _ => Ok(()),
+        }
+    }
+
+    // Load the checkpoint of the reopened workspace
+    fn restore(&mut self) -> std::result::Result<(), CompressionError> {
+        let path = self.workspace.path(CHECKPOINT_FILE);
+        let data = std::fs::read(&path).map_err(|e| CompressionError::FileOpen {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+        let malformed = CompressError::MalformedCheckpoint;
+        let mismatch = |reason: String| CompressError::CheckpointMismatch {
+            path: path.display().to_string(),
+            reason,
+        };
+        let mut r = SafeReader::new(&data);
+        if r.bytes(CHECKPOINT_MAGIC.len() as u64, "magic")
+            .map_err(malformed)?
+            != CHECKPOINT_MAGIC
+        {
+            return Err(mismatch("not a checkpoint".to_string()).into());
+        }
+        if r.u64_be("configuration").map_err(malformed)? != cfg_fingerprint(&self.cfg) {
+            return Err(mismatch("written with another configuration".to_string()).into());
+        }
+        let file_name = read_bytes(&mut r, "file name")?;
+        if file_name != self.file_name.as_bytes() {
+            return Err(mismatch(format!(
+                "written for {}",
+                String::from_utf8_lossy(file_name)
+            ))
+            .into());
+        }
+        let words_count = r.uvarint("word count").map_err(malformed)?;
+        self.superstring_count = r.uvarint("superstring count").map_err(malformed)?;
+        self.superstring_len = r.uvarint("superstring length").map_err(malformed)? as usize;
+        self.superstring = read_bytes(&mut r, "superstring")?.to_vec();
+        let in_memory = r.uvarint("superstrings in memory").map_err(malformed)?;
+        let head_bytes = r
+            .uvarint("superstrings in memory bytes")
+            .map_err(malformed)?;
+        let spilled = r.uvarint("superstrings spilled").map_err(malformed)?;
+        let spill_bytes = r.uvarint("superstrings spilled bytes").map_err(malformed)?;
+        self.superstrings
+            .restore(in_memory, head_bytes, spilled, spill_bytes)?;
+        self.sampler = WordSampler::decode(&mut r)?;
+        self.dictionary = match r.u8("dictionary").map_err(malformed)? {
+            0 => None,
+            _ => Some(DictionaryBuilder::deserialize(read_bytes(
+                &mut r,
+                "dictionary",
+            )?)?),
+        };
+        if !r.is_empty() {
+            return Err(
+                CompressError::MalformedCheckpoint(ReadError::TrailingBytes {
+                    what: "checkpoint",
+                    offset: r.offset(),
+                })
+                .into(),
+            );
+        }
+
+        // Drop the words added after the checkpoint and hash the others again
+        let uf = self.uncompressed_file.as_mut().ok_or_else(|| {
+            CompressError::UncompressedFileNotInitialized {
+                file: self.file_name.clone(),
+            }
+        })?;
+        if uf.count < words_count {
+            return Err(mismatch(format!(
+                "{} words checkpointed, {} in {}",
+                words_count,
+                uf.count,
+                uf.file_path.display()
+            ))
+            .into());
+        }
+        uf.truncate(words_count)?;
+        self.words_count = words_count;
+        if self.cfg.checksum {
+            let mut words = uf.words()?;
+            while let Some((word, _)) = words.next_word() {
+                hash_word(&mut self.word_hasher, &word);
+            }
+            if let Some(e) = words.take_error() {
+                return Err(e);
+            }
+            // Appending goes on after the last word
+            std::io::Seek::seek(&mut uf.f, std::io::SeekFrom::Start(uf.len))?;
+        }
+        tracing::info!(
+            "[{}] Resuming after {} words from {}",
+            self.log_prefix,
+            words_count,
+            self.workspace.dir().display()
+        );
+        Ok(())
+    }
+
+    // From Go: DisableFsync - compress.go:294
+    pub fn disable_fsync(&mut self) {
+        self.sync = SyncPolicy::None;
+    }
+
+    /// Length of the words added over the size of the segment written by
+    /// the last [`Compressor::compress`], dictionaries and footer included;
+    /// see [`SizeBreakdown::ratio`]
+    // From Go: Ratio getter
+    pub fn ratio(&self) -> CompressionRatio {
+        self.sizes.ratio()
+    }
+
+    /// Where the bytes of the segment written by the last
+    /// [`Compressor::compress`] went
+    pub fn size_breakdown(&self) -> SizeBreakdown {
+        self.sizes
+    }
+
+    /// Statistics of the segment written by the last [`Compressor::compress`]
+    pub fn stats(&self) -> &CompressionStats {
+        &self.stats
+    }
+
+    // From Go: fsync - compress.go:299-308
+    fn fsync(&self, file: &File) -> std::result::Result<(), CompressionError> {
+        SegmentFile::sync_on_close(self.sync, file)?;
+        Ok(())
+    }
+
+    // Flush the raw words and move what is left of the sample into
+    // superstrings
+    fn finish_sampling(&mut self) -> std::result::Result<(), CompressionError> {
+        if let Some(ref mut uf) = self.uncompressed_file {
+            uf.flush()?;
+        }
+
+        // Strategies that only know their sample at the end of the input
+        for word in self.sampler.take_words() {
+            self.append_to_superstring(&word)?;
+        }
+
+        // Add any remaining superstring
+        if !self.superstring.is_empty() {
+            let ss = std::mem::take(&mut self.superstring);
+            self.superstrings.push(ss)?;
+        }
+        Ok(())
+    }
+
+    // Build the dictionary unless one was given or built by an estimate
+    fn ensure_dictionary(&mut self) -> std::result::Result<(), CompressionError> {
+        if self.dictionary.is_none() {
+            tracing::info!(
+                "[{}] Building dictionary from {} superstrings ({} spilled to disk)",
+                self.log_prefix,
+                self.superstrings.len(),
+                self.superstrings.spilled()
+            );
+            self.dictionary = Some(self.build_dictionary_from_superstrings()?);
+        } else {
+            tracing::info!(
+                "[{}] Using the given dictionary of {} patterns",
+                self.log_prefix,
+                self.dictionary.as_ref().map_or(0, DictionaryBuilder::len)
+            );
+        }
+        Ok(())
+    }
+
+    // Build dictionary from superstrings (synchronous version of Go's DictionaryBuilderFromCollectors)
+    fn build_dictionary_from_superstrings(
+        &mut self,
+    ) -> std::result::Result<DictionaryBuilder, CompressionError> {
+        // This is the synchronous equivalent of Go's parallel_compress.go:916-947
+
+        // Create aggregator to collect patterns from all superstrings
+        let mut dict_aggregator = DictAggregator::new();
+
+        // Process each superstring to extract patterns (synchronous instead of parallel)
+        let cfg = &self.cfg;
+        let cancel = self.cancel.as_ref();
+        self.superstrings.for_each(|superstring| {
+            cancel::check(cancel)?;
+            if superstring.is_empty() {
+                return Ok(());
+            }
+
+            // Extract patterns from this superstring
+            let patterns = crate::parallel_compress::extract_patterns_from_single_superstring(
+                superstring,
+                cfg,
+            );
+
+            // Add patterns to aggregator
+            for pattern in patterns {
+                dict_aggregator.process_word(pattern.word, pattern.score)?;
+            }
+            Ok(())
+        })?;
+
+        // Finish aggregation
+        let collector = dict_aggregator.finish()?;
+
+        // Build dictionary from collected patterns
+        let mut dict_builder = DictionaryBuilder::new(self.cfg.dict_reducer_soft_limit);
+        dict_builder.load_from_collector(collector);
+
+        // Apply hard limit
+        dict_builder.finish(self.cfg.max_dict_patterns);
+
+        // Sort patterns (for compatibility, though heap already maintains order)
+        dict_builder.sort();
+
+        tracing::info!(
+            "[{}] Dictionary built with {} patterns",
+            self.log_prefix,
+            dict_builder.len()
+        );
+
+        Ok(dict_builder)
+    }
+}
+
+/// Builder for [`Compressor`] with typed paths and tuning knobs.
+///
+/// The temporary directory defaults to the directory of the output file and
+/// the log prefix defaults to the output file name.
+pub struct CompressorBuilder {
+    output_file: PathBuf,
+    tmp_dir: Option<PathBuf>,
+    log_prefix: Option<String>,
+    lvl: log::Level,
+    cfg: Cfg,
+    sync: SyncPolicy,
+    direct_io: bool,
+    progress: Option<ProgressFn>,
+    cancel: Option<CancellationToken>,
+    companion: Option<ExportFormat>,
+    checkpoint_every: Option<u64>,
+    codec: Option<Arc<dyn WordCodec>>,
+}
+
+impl CompressorBuilder {
+    pub fn new(output_file: impl Into<PathBuf>) -> Self {
+        CompressorBuilder {
+            output_file: output_file.into(),
+            tmp_dir: None,
+            log_prefix: None,
+            lvl: log::Level::Info,
+            cfg: Cfg::default(),
+            sync: SyncPolicy::OnClose,
+            direct_io: false,
+            progress: None,
+            cancel: None,
+            companion: None,
+            checkpoint_every: None,
+            codec: None,
+        }
+    }
+
+    /// Directory for intermediate files (defaults next to the output file)
+    pub fn tmp_dir(mut self, tmp_dir: impl Into<PathBuf>) -> Self {
+        self.tmp_dir = Some(tmp_dir.into());
+        self
+    }
+
+    pub fn log_prefix(mut self, log_prefix: impl Into<String>) -> Self {
+        self.log_prefix = Some(log_prefix.into());
+        self
+    }
+
+    pub fn log_level(mut self, lvl: log::Level) -> Self {
+        self.lvl = lvl;
+        self
+    }
+
+    /// Replace the whole configuration; later knob calls still apply on top
+    pub fn cfg(mut self, cfg: Cfg) -> Self {
+        self.cfg = cfg;
+        self
+    }
+
+    /// Cover words with patterns on `workers` threads of the `blocking`
+    /// pool (default: 1); the segment is byte for byte the same for any
+    /// number of workers
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.cfg.workers = workers;
+        self
+    }
+
+    pub fn sampling_factor(mut self, sampling_factor: u64) -> Self {
+        self.cfg.sampling_factor = sampling_factor;
+        self
+    }
+
+    pub fn sampling_strategy(mut self, sampling: SamplingStrategy) -> Self {
+        self.cfg.sampling = sampling;
+        self
+    }
+
+    pub fn min_pattern_score(mut self, min_pattern_score: u64) -> Self {
+        self.cfg.min_pattern_score = min_pattern_score;
+        self
+    }
+
+    pub fn pattern_len_range(mut self, min_pattern_len: usize, max_pattern_len: usize) -> Self {
+        self.cfg.min_pattern_len = min_pattern_len;
+        self.cfg.max_pattern_len = max_pattern_len;
+        self
+    }
+
+    pub fn max_dict_patterns(mut self, max_dict_patterns: usize) -> Self {
+        self.cfg.max_dict_patterns = max_dict_patterns;
+        self
+    }
+
+    pub fn dict_reducer_soft_limit(mut self, dict_reducer_soft_limit: usize) -> Self {
+        self.cfg.dict_reducer_soft_limit = dict_reducer_soft_limit;
+        self
+    }
+
+    pub fn level(mut self, level: CompressionLevel) -> Self {
+        self.cfg.level = level;
+        self
+    }
+
+    pub fn optimizer(mut self, optimizer: OptimizerMode) -> Self {
+        self.cfg.optimizer = optimizer;
+        self
+    }
+
+    /// Check the Huffman codes against the decompressor's tables before
+    /// writing them (default: false, always done in debug builds)
+    pub fn verify_codes(mut self, verify_codes: bool) -> Self {
+        self.cfg.verify_codes = verify_codes;
+        self
+    }
+
+    /// Encode twice, dropping the patterns that don't pay for their
+    /// dictionary entry in between (default: false); a smaller dictionary
+    /// for twice the encoding time
+    pub fn prune_dictionary(mut self, prune_dictionary: bool) -> Self {
+        self.cfg.prune_dictionary = prune_dictionary;
+        self
+    }
+
+    /// Keep at most `bytes` of sampled superstrings in memory, spilling the
+    /// rest to a file in the temporary directory (default: no limit). Each
+    /// superstring is up to 16 MiB, and the dictionary is the same either way.
+    pub fn superstring_memory_limit(mut self, bytes: usize) -> Self {
+        self.cfg.superstring_memory_limit = Some(bytes);
+        self
+    }
+
+    /// Append a checksum footer, checked by [`Decompressor::verify_checksum`]
+    /// (default: false). Erigon does not expect the footer, so only enable it
+    /// for segments read with this crate.
+    ///
+    /// [`Decompressor::verify_checksum`]: crate::Decompressor::verify_checksum
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.cfg.checksum = checksum;
+        self
+    }
+
+    /// Whether to fsync the output before renaming it into place (default:
+    /// true); shorthand for [`SyncPolicy::OnClose`] or [`SyncPolicy::None`]
+    pub fn fsync(mut self, fsync: bool) -> Self {
+        self.sync = if fsync {
+            SyncPolicy::OnClose
+        } else {
+            SyncPolicy::None
+        };
+        self
+    }
+
+    /// When the output is synced to disk (default: [`SyncPolicy::OnClose`])
+    pub fn sync_policy(mut self, sync: SyncPolicy) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Write the output with `O_DIRECT`, keeping it out of the page cache
+    /// (default: false). Linux only; elsewhere, and on filesystems without
+    /// direct IO, the output is written normally.
+    pub fn direct_io(mut self, direct_io: bool) -> Self {
+        self.direct_io = direct_io;
+        self
+    }
+
+    /// Called periodically while words are being compressed
+    pub fn progress(mut self, progress: impl Fn(u64, u64) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Give up with [`CompressionError::Cancelled`] once `cancel` is
+    /// cancelled; it is checked between words and between phases of
+    /// [`Compressor::compress`], which then writes no output
+    pub fn cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Also export the words next to the output, with the extension of
+    /// `format`, once compression is done (see [`crate::export`])
+    pub fn companion_export(mut self, format: ExportFormat) -> Self {
+        self.companion = Some(format);
+        self
+    }
+
+    /// [`Compressor::checkpoint`] every `words` words, and once more when
+    /// the dictionary is built
+    pub fn checkpoint_every(mut self, words: u64) -> Self {
+        self.checkpoint_every = Some(words.max(1));
+        self
+    }
+
+    /// Encode every word with `codec` before it is sampled and stored (see
+    /// [`crate::codec`]); read the segment back with
+    /// [`Decompressor::with_word_codec`](crate::decompress::Decompressor::with_word_codec).
+    /// The codec is not part of a checkpoint, so a resumed run has to be
+    /// given it again.
+    pub fn word_codec(mut self, codec: impl WordCodec + 'static) -> Self {
+        self.codec = Some(Arc::new(codec));
+        self
+    }
+
+    pub fn build(self) -> std::result::Result<Compressor, CompressionError> {
+        self.open(None)
+    }
+
+    /// Go on with the run whose workspace is `workspace`, from its last
+    /// [`Compressor::checkpoint`]
+    ///
+    /// The builder must be set up as it was for that run: the checkpoint is
+    /// refused for another output file name or configuration. Add the words
+    /// from number [`Compressor::count`] on, then compress as usual.
+    /// Workspaces with a checkpoint are listed by
+    /// [`crate::recover_or_clean`].
+    pub fn resume(
+        self,
+        workspace: impl AsRef<Path>,
+    ) -> std::result::Result<Compressor, CompressionError> {
+        self.open(Some(workspace.as_ref()))
+    }
+
+    fn open(self, resume_from: Option<&Path>) -> std::result::Result<Compressor, CompressionError> {
+        let cfg = self.cfg;
+        if cfg.min_pattern_len > cfg.max_pattern_len {
+            return Err(CompressionError::InvalidPatternLengthRange {
+                min: cfg.min_pattern_len,
+                max: cfg.max_pattern_len,
+            });
+        }
+        if cfg.workers == 0 {
+            return Err(CompressionError::InvalidConfig(
+                "workers must be at least 1".to_string(),
+            ));
+        }
+        if cfg.sampling_factor == 0 {
+            return Err(CompressionError::InvalidConfig(
+                "sampling_factor must be at least 1".to_string(),
+            ));
+        }
+        match cfg.sampling {
+            SamplingStrategy::Reservoir { words: 0, .. }
+            | SamplingStrategy::FirstN { words: 0 } => {
+                return Err(CompressionError::InvalidConfig(
+                    "sampling strategy must keep at least 1 word".to_string(),
+                ));
+            }
+            SamplingStrategy::ByteBudget { bytes: 0 } => {
+                return Err(CompressionError::InvalidConfig(
+                    "sampling byte budget must be at least 1".to_string(),
+                ));
+            }
+            _ => {}
+        }
+
+        let tmp_dir = match self.tmp_dir {
+            Some(dir) => dir,
+            None => match self.output_file.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            },
+        };
+        let log_prefix = match self.log_prefix {
+            Some(prefix) => prefix,
+            None => self
+                .output_file
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        };
+
+        let mut compressor = Compressor::open(
+            cfg,
+            self.output_file,
+            tmp_dir,
+            log_prefix,
+            self.lvl,
+            resume_from,
+        )?;
+        compressor.sync = self.sync;
+        compressor.direct_io = self.direct_io;
+        compressor.progress = self.progress;
+        compressor.cancel = self.cancel;
+        compressor.companion = self.companion;
+        compressor.checkpoint_every = self.checkpoint_every;
+        compressor.codec = self.codec;
+        Ok(compressor)
+    }
+}
+
+// superstringLimit limits how large can one "superstring" get before it is processed
+// CompressorSequential allocates 7 bytes for each uint of superstringLimit. For example,
+// superstingLimit 16m will result in 112Mb being allocated for various arrays
+// From Go: compress.go:310-313
+const SUPERSTRING_LIMIT: usize = 16 * 1024 * 1024;
+
+// Superstring encoding of a word: each byte b becomes 0x01 b, and the word
+// is terminated by 0x00 0x00
+fn push_superstring_word(superstring: &mut Vec<u8>, word: &[u8]) {
+    for &byte in word {
+        superstring.push(0x01);
+        superstring.push(byte);
+    }
+    superstring.push(0x00);
+    superstring.push(0x00);
+}
+
+// Name of the checkpoint file in a compressor's workspace
+pub(crate) const CHECKPOINT_FILE: &str = "checkpoint";
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"EDCKPT01";
+
+// Fingerprint of the settings a checkpoint is only valid for; the number of
+// workers may change between runs
+fn cfg_fingerprint(cfg: &Cfg) -> u64 {
+    let cfg = Cfg {
+        workers: 1,
+        ..cfg.clone()
+    };
+    xxhash_rust::xxh64::xxh64(format!("{:?}", cfg).as_bytes(), 0)
+}
+
+fn put_uvarint(data: &mut Vec<u8>, v: u64) {
+    let mut num_buf = [0u8; 10];
+    let n = encode_varint(&mut num_buf, v);
+    data.extend_from_slice(&num_buf[..n]);
+}
+
+// Length as a varint, then the bytes
+fn put_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+    put_uvarint(data, bytes.len() as u64);
+    data.extend_from_slice(bytes);
+}
+
+fn read_bytes<'a>(
+    r: &mut SafeReader<'a>,
+    what: &'static str,
+) -> std::result::Result<&'a [u8], CompressionError> {
+    let len = r
+        .uvarint(what)
+        .map_err(CompressError::MalformedCheckpoint)?;
+    Ok(r.bytes(len, what)
+        .map_err(CompressError::MalformedCheckpoint)?)
+}
+
+// Read `count` superstrings back from the first `bytes` bytes of `path`,
+// cutting off the rest of the file
+fn read_superstrings(
+    path: &Path,
+    count: u64,
+    bytes: u64,
+) -> std::result::Result<Vec<Vec<u8>>, CompressionError> {
+    use std::io::{BufReader, Read};
+
+    if count == 0 {
+        std::fs::remove_file(path).ok();
+        return Ok(Vec::new());
+    }
+    let f = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| CompressionError::FileOpen {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+    f.set_len(bytes)?;
+    let mut reader = BufReader::new(f);
+    let mut superstrings = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let mut len = [0u8; 8];
+        reader
+            .read_exact(&mut len)
+            .map_err(|_| CompressionError::UnexpectedEof)?;
+        let mut superstring = vec![0u8; u64::from_le_bytes(len) as usize];
+        reader
+            .read_exact(&mut superstring)
+            .map_err(|_| CompressionError::UnexpectedEof)?;
+        superstrings.push(superstring);
+    }
+    Ok(superstrings)
+}
+
+// Sampled superstrings in the order they were taken. Up to `limit` bytes stay
+// in memory; once a superstring would go past it, that one and all later ones
+// are appended to a spill file (u64 LE length, then the bytes), so reading
+// them back keeps the order and the dictionary doesn't depend on the limit
+//
+// A checkpoint also writes the ones in memory to a file next to the spill
+// file, in the same layout, so that they can be read back after a restart
+struct Superstrings {
+    in_memory: Vec<Vec<u8>>,
+    in_memory_bytes: usize,
+    limit: Option<usize>,
+    spill_path: PathBuf,
+    spill: Option<BufWriter<File>>,
+    spilled: usize,
+    spill_bytes: u64,
+    // Superstrings in memory written by checkpoints, and their bytes
+    checkpointed: usize,
+    checkpointed_bytes: u64,
+    // Leave the files behind on drop, for a suspended run
+    keep: bool,
+}
 
-        // Calculate compression ratio
-        if let Some(ref uf) = self.uncompressed_file {
-            self.ratio = calculate_ratio(&uf.file_path, &self.output_file)?;
+impl Superstrings {
+    fn new(limit: Option<usize>, spill_path: PathBuf) -> Self {
+        Superstrings {
+            in_memory: Vec::new(),
+            in_memory_bytes: 0,
+            limit,
+            spill_path,
+            spill: None,
+            spilled: 0,
+            spill_bytes: 0,
+            checkpointed: 0,
+            checkpointed_bytes: 0,
+            keep: false,
         }
+    }
 
-        // Log completion
-        if self.lvl <= log::Level::Info {
-            log::info!(
-                "[{}] Compress took {:?}, ratio: {}, file: {}",
-                self.log_prefix,
-                start.elapsed(),
-                ratio_to_string(self.ratio),
-                self.file_name
-            );
+    fn checkpoint_path(&self) -> PathBuf {
+        let mut path = self.spill_path.clone().into_os_string();
+        path.push(".head");
+        PathBuf::from(path)
+    }
+
+    // Write the superstrings in memory not written yet and flush the spill
+    // file, returning the bytes of both files
+    fn checkpoint(&mut self, sync: bool) -> std::result::Result<(u64, u64), CompressionError> {
+        if self.checkpointed < self.in_memory.len() {
+            let path = self.checkpoint_path();
+            let f = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| CompressionError::FileCreate {
+                    path: path.display().to_string(),
+                    source: e,
+                })?;
+            let mut w = BufWriter::new(f);
+            for superstring in &self.in_memory[self.checkpointed..] {
+                w.write_all(&(superstring.len() as u64).to_le_bytes())?;
+                w.write_all(superstring)?;
+                self.checkpointed_bytes += 8 + superstring.len() as u64;
+            }
+            let f = w.into_inner().map_err(|e| e.into_error())?;
+            if sync {
+                f.sync_data()?;
+            }
+            self.checkpointed = self.in_memory.len();
+        }
+        if let Some(w) = &mut self.spill {
+            w.flush()?;
+            if sync {
+                w.get_ref().sync_data()?;
+            }
         }
+        Ok((self.checkpointed_bytes, self.spill_bytes))
+    }
 
+    // Back to the state of a checkpoint, dropping what was written after it
+    fn restore(
+        &mut self,
+        in_memory: u64,
+        in_memory_bytes: u64,
+        spilled: u64,
+        spill_bytes: u64,
+    ) -> std::result::Result<(), CompressionError> {
+        self.in_memory = read_superstrings(&self.checkpoint_path(), in_memory, in_memory_bytes)?;
+        self.in_memory_bytes = self.in_memory.iter().map(Vec::len).sum();
+        self.checkpointed = self.in_memory.len();
+        self.checkpointed_bytes = in_memory_bytes;
+        self.spill = None;
+        self.spilled = spilled as usize;
+        self.spill_bytes = spill_bytes;
+        if spilled > 0 {
+            let f = std::fs::OpenOptions::new()
+                .write(true)
+                .open(&self.spill_path)
+                .map_err(|e| CompressionError::FileOpen {
+                    path: self.spill_path.display().to_string(),
+                    source: e,
+                })?;
+            f.set_len(spill_bytes)?;
+        }
         Ok(())
     }
 
-    // From Go: DisableFsync - compress.go:294
-    pub fn disable_fsync(&mut self) {
-        self.no_fsync = true;
+    fn len(&self) -> usize {
+        self.in_memory.len() + self.spilled
     }
 
-    // From Go: Ratio getter
-    pub fn ratio(&self) -> CompressionRatio {
-        self.ratio
+    // Number of superstrings written to the spill file
+    fn spilled(&self) -> usize {
+        self.spilled
     }
 
-    // From Go: fsync - compress.go:299-308
-    fn fsync(&self, file: &File) -> std::result::Result<(), CompressionError> {
-        if self.no_fsync {
+    fn push(&mut self, superstring: Vec<u8>) -> std::result::Result<(), CompressionError> {
+        let over_limit = self
+            .limit
+            .is_some_and(|limit| self.in_memory_bytes + superstring.len() > limit);
+        if self.spilled == 0 && !over_limit {
+            self.in_memory_bytes += superstring.len();
+            self.in_memory.push(superstring);
             return Ok(());
         }
-        file.sync_all()?;
+
+        if self.spill.is_none() {
+            // Start over from a stale file, or append after a read-back
+            let f = std::fs::OpenOptions::new()
+                .create(true)
+                .append(self.spilled > 0)
+                .write(true)
+                .truncate(self.spilled == 0)
+                .open(&self.spill_path)
+                .map_err(|e| CompressionError::FileCreate {
+                    path: self.spill_path.display().to_string(),
+                    source: e,
+                })?;
+            tracing::debug!(
+                "Spilling superstrings past {} bytes to {}",
+                self.in_memory_bytes,
+                self.spill_path.display()
+            );
+            self.spill = Some(BufWriter::new(f));
+        }
+        let w = self.spill.as_mut().expect("spill file created above");
+        w.write_all(&(superstring.len() as u64).to_le_bytes())?;
+        w.write_all(&superstring)?;
+        self.spilled += 1;
+        self.spill_bytes += 8 + superstring.len() as u64;
         Ok(())
     }
 
-    // Build dictionary from superstrings (synchronous version of Go's DictionaryBuilderFromCollectors)
-    fn build_dictionary_from_superstrings(
-        &mut self,
-    ) -> std::result::Result<DictionaryBuilder, CompressionError> {
-        // This is the synchronous equivalent of Go's parallel_compress.go:916-947
-
-        // Create aggregator to collect patterns from all superstrings
-        let mut dict_aggregator = DictAggregator::new();
+    // Call `f` with every superstring, in order; spilled ones are read back
+    // one at a time
+    fn for_each<F>(&mut self, mut f: F) -> std::result::Result<(), CompressionError>
+    where
+        F: FnMut(&[u8]) -> std::result::Result<(), CompressionError>,
+    {
+        use std::io::{BufReader, Read};
 
-        // Process each superstring to extract patterns (synchronous instead of parallel)
-        for superstring in &self.superstrings {
-            if superstring.is_empty() {
-                continue;
-            }
+        for superstring in &self.in_memory {
+            f(superstring)?;
+        }
+        if self.spilled == 0 {
+            return Ok(());
+        }
+        if let Some(mut w) = self.spill.take() {
+            w.flush()?;
+        }
 
-            // Extract patterns from this superstring
-            let patterns = crate::parallel_compress::extract_patterns_from_single_superstring(
-                superstring,
-                &self.cfg,
-            );
+        let file = File::open(&self.spill_path).map_err(|e| CompressionError::FileOpen {
+            path: self.spill_path.display().to_string(),
+            source: e,
+        })?;
+        let mut reader = BufReader::new(file);
+        let mut superstring = Vec::new();
+        for _ in 0..self.spilled {
+            let mut len = [0u8; 8];
+            reader
+                .read_exact(&mut len)
+                .map_err(|_| CompressionError::UnexpectedEof)?;
+            superstring.resize(u64::from_le_bytes(len) as usize, 0);
+            reader
+                .read_exact(&mut superstring)
+                .map_err(|_| CompressionError::UnexpectedEof)?;
+            f(&superstring)?;
+        }
+        Ok(())
+    }
+}
 
-            // Add patterns to aggregator
-            for pattern in patterns {
-                dict_aggregator.process_word(pattern.word, pattern.score)?;
+impl Drop for Superstrings {
+    fn drop(&mut self) {
+        if self.spilled > 0 && !self.keep {
+            self.spill = None;
+            if let Err(e) = std::fs::remove_file(&self.spill_path) {
+                tracing::warn!(
+                    "Failed to remove spilled superstrings {}: {}",
+                    self.spill_path.display(),
+                    e
+                );
             }
         }
+    }
+}
 
-        // Finish aggregation
-        let collector = dict_aggregator.finish()?;
-
-        // Build dictionary from collected patterns
-        let mut dict_builder = DictionaryBuilder::new(self.cfg.dict_reducer_soft_limit);
-        dict_builder.load_from_collector(collector);
+// Words picked by the sampling strategies other than EveryNth, which only
+// know at the end of the input which words they keep
+#[derive(Debug, Default)]
+struct WordSampler {
+    // Sampled words, with their index among the words offered
+    words: Vec<(u64, Vec<u8>)>,
+    seen: u64,
+    // ByteBudget: superstring bytes of `words`, which are every stride-th word
+    bytes: usize,
+    stride: u64,
+    // Reservoir: splitmix64 state
+    rng: u64,
+}
 
-        // Apply hard limit
-        dict_builder.finish(self.cfg.max_dict_patterns);
+impl WordSampler {
+    fn new(strategy: SamplingStrategy) -> Self {
+        WordSampler {
+            stride: 1,
+            rng: match strategy {
+                SamplingStrategy::Reservoir { seed, .. } => seed,
+                _ => 0,
+            },
+            ..Default::default()
+        }
+    }
 
-        // Sort patterns (for compatibility, though heap already maintains order)
-        dict_builder.sort();
+    fn offer(&mut self, strategy: SamplingStrategy, word: &[u8]) {
+        let index = self.seen;
+        self.seen += 1;
+        match strategy {
+            SamplingStrategy::EveryNth => {}
+            SamplingStrategy::FirstN { words } => {
+                if index < words as u64 {
+                    self.words.push((index, word.to_vec()));
+                }
+            }
+            SamplingStrategy::Reservoir { words, .. } => {
+                if self.words.len() < words {
+                    self.words.push((index, word.to_vec()));
+                } else {
+                    let slot = (self.next_random() % (index + 1)) as usize;
+                    if slot < words {
+                        self.words[slot] = (index, word.to_vec());
+                    }
+                }
+            }
+            SamplingStrategy::ByteBudget { bytes } => {
+                if !index.is_multiple_of(self.stride) {
+                    return;
+                }
+                self.bytes += 2 * word.len() + 2;
+                self.words.push((index, word.to_vec()));
+                // Over budget: keep half as many words, still evenly spread
+                while self.bytes > bytes && self.words.len() > 1 {
+                    self.stride *= 2;
+                    let stride = self.stride;
+                    self.words.retain(|(i, _)| i.is_multiple_of(stride));
+                    self.bytes = self.words.iter().map(|(_, w)| 2 * w.len() + 2).sum();
+                }
+            }
+        }
+    }
 
-        log::info!(
-            "[{}] Dictionary built with {} patterns",
-            self.log_prefix,
-            dict_builder.len()
-        );
+    fn encode(&self, data: &mut Vec<u8>) {
+        put_uvarint(data, self.seen);
+        put_uvarint(data, self.bytes as u64);
+        put_uvarint(data, self.stride);
+        put_uvarint(data, self.rng);
+        put_uvarint(data, self.words.len() as u64);
+        for (index, word) in &self.words {
+            put_uvarint(data, *index);
+            put_bytes(data, word);
+        }
+    }
 
-        Ok(dict_builder)
+    fn decode(r: &mut SafeReader<'_>) -> std::result::Result<Self, CompressionError> {
+        let malformed = CompressError::MalformedCheckpoint;
+        let mut sampler = WordSampler {
+            seen: r.uvarint("sampled words seen").map_err(malformed)?,
+            bytes: r.uvarint("sampled bytes").map_err(malformed)? as usize,
+            stride: r.uvarint("sampling stride").map_err(malformed)?,
+            rng: r.uvarint("sampling state").map_err(malformed)?,
+            words: Vec::new(),
+        };
+        let count = r.uvarint("sampled word count").map_err(malformed)?;
+        for _ in 0..count {
+            let index = r.uvarint("sampled word index").map_err(malformed)?;
+            sampler
+                .words
+                .push((index, read_bytes(r, "sampled word")?.to_vec()));
+        }
+        Ok(sampler)
     }
-}
 
-// superstringLimit limits how large can one "superstring" get before it is processed
-// CompressorSequential allocates 7 bytes for each uint of superstringLimit. For example,
-// superstingLimit 16m will result in 112Mb being allocated for various arrays
-// From Go: compress.go:310-313
-const SUPERSTRING_LIMIT: usize = 16 * 1024 * 1024;
+    // The sampled words in input order
+    fn take_words(&mut self) -> Vec<Vec<u8>> {
+        let mut words = std::mem::take(&mut self.words);
+        words.sort_unstable_by_key(|(index, _)| *index);
+        words.into_iter().map(|(_, word)| word).collect()
+    }
 
-// ETL buffer sizes from Go's etl package
-const ETL_BUFFER_OPTIMAL_SIZE: usize = 256 * 1024 * 1024; // 256MB
-const ETL_BUF_IO_SIZE: usize = 64 * 1024; // 64KB
+    fn next_random(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
 
 // From Go: DictionaryBuilder struct
+#[derive(Debug, Clone)]
 pub struct DictionaryBuilder {
     last_word: Vec<u8>,
     items: BinaryHeap<Pattern>, // Using BinaryHeap instead of slice for heap operations
@@ -472,6 +1972,78 @@ impl DictionaryBuilder {
         // For us, the heap maintains order and for_each/into_patterns handle sorting
         // This is a no-op for compatibility
     }
+
+    /// Drop the patterns that cost more than they save, given `patterns` as
+    /// an encoding pass left them, with their uses and codes
+    ///
+    /// See [`Pattern::gain_bits`]. Patterns no word used are kept, as they
+    /// are not written to the segment. Returns how many were dropped.
+    pub fn prune(&mut self, patterns: &[Pattern]) -> usize {
+        let losing: std::collections::HashSet<&[u8]> = patterns
+            .iter()
+            .filter(|p| p.uses > 0 && p.gain_bits() < 0)
+            .map(|p| p.word.as_slice())
+            .collect();
+        let before = self.items.len();
+        self.items.retain(|p| !losing.contains(p.word.as_slice()));
+        before - self.items.len()
+    }
+
+    /// Serialize the patterns and their scores, to reuse them for other
+    /// segments with [`Compressor::with_dictionary`]
+    ///
+    /// Format: pattern count (u64 big-endian), then per pattern in
+    /// [`DictionaryBuilder::for_each`] order its score, its length (both
+    /// varints) and its bytes.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(self.len() as u64).to_be_bytes());
+        let mut num_buf = [0u8; 10];
+        self.for_each(|score, word| {
+            let n = encode_varint(&mut num_buf, score);
+            data.extend_from_slice(&num_buf[..n]);
+            let n = encode_varint(&mut num_buf, word.len() as u64);
+            data.extend_from_slice(&num_buf[..n]);
+            data.extend_from_slice(word);
+        });
+        data
+    }
+
+    /// Read back a dictionary written by [`DictionaryBuilder::serialize`]
+    pub fn deserialize(data: &[u8]) -> std::result::Result<Self, CompressionError> {
+        let mut reader = SafeReader::new(data);
+        let count = reader
+            .u64_be("pattern count")
+            .map_err(CompressError::MalformedDictionary)?;
+        let mut items = BinaryHeap::new();
+        for _ in 0..count {
+            let score = reader
+                .uvarint("pattern score")
+                .map_err(CompressError::MalformedDictionary)?;
+            let len = reader
+                .uvarint("pattern length")
+                .map_err(CompressError::MalformedDictionary)?;
+            let word = reader
+                .bytes(len, "pattern")
+                .map_err(CompressError::MalformedDictionary)?;
+            items.push(Pattern::new(word.to_vec(), score));
+        }
+        if !reader.is_empty() {
+            return Err(
+                CompressError::MalformedDictionary(ReadError::TrailingBytes {
+                    what: "dictionary",
+                    offset: reader.offset(),
+                })
+                .into(),
+            );
+        }
+        Ok(DictionaryBuilder {
+            last_word: Vec::new(),
+            soft_limit: items.len().max(Cfg::default().dict_reducer_soft_limit),
+            items,
+            last_word_score: 0,
+        })
+    }
 }
 
 // Pattern represents a byte sequence to be used in compression dictionary
@@ -488,6 +2060,18 @@ pub struct Pattern {
 }
 
 impl Pattern {
+    /// Bits this pattern saves after an encoding pass: each of its `uses`
+    /// replaces its bytes with a `code_bits` code, and its dictionary entry
+    /// (depth, length and bytes) is paid once
+    pub fn gain_bits(&self) -> i64 {
+        let mut num_buf = [0u8; 10];
+        let len = self.word.len() as i64;
+        let entry = encode_varint(&mut num_buf, self.depth as u64)
+            + encode_varint(&mut num_buf, self.word.len() as u64)
+            + self.word.len();
+        self.uses as i64 * (8 * len - self.code_bits as i64) - 8 * entry as i64
+    }
+
     pub fn new(word: Vec<u8>, score: u64) -> Self {
         Pattern {
             word,
@@ -510,7 +2094,7 @@ pub fn pattern_list_cmp(a: &Pattern, b: &Pattern) -> std::cmp::Ordering {
     match a.uses.cmp(&b.uses) {
         Ordering::Equal => {
             // When uses are equal, compare by reverse of code
-            reverse_bits_64(b.code).cmp(&reverse_bits_64(a.code))
+            reverse_bits_64(a.code).cmp(&reverse_bits_64(b.code))
         }
         other => other,
     }
@@ -771,14 +2355,14 @@ impl PartialEq for PositionHuffWrapper {
 impl Eq for PositionHuffWrapper {}
 
 // From Go: BitWriter struct (from compress.go)
-pub struct BitWriter {
-    w: BufWriter<File>,
+pub struct BitWriter<W: Write> {
+    w: W,
     output_bits: usize,
     output_byte: u8,
 }
 
-impl BitWriter {
-    pub fn new(w: BufWriter<File>) -> Self {
+impl<W: Write> BitWriter<W> {
+    pub fn new(w: W) -> Self {
         BitWriter {
             w,
             output_bits: 0,
@@ -825,6 +2409,18 @@ impl BitWriter {
         self.w.flush()?;
         Ok(())
     }
+
+    // Write raw bytes (flushes pending bits first)
+    pub fn write_bytes(&mut self, data: &[u8]) -> std::result::Result<(), std::io::Error> {
+        self.flush()?;
+        self.w.write_all(data)
+    }
+
+    // Flush and return the underlying writer
+    pub fn into_inner(mut self) -> std::result::Result<W, std::io::Error> {
+        self.flush()?;
+        Ok(self.w)
+    }
 }
 
 // From Go: DynamicCell struct (from compress.go)
@@ -1054,7 +2650,7 @@ impl DictAggregator {
             self.collector.collect(self.last_word, self.last_word_score);
         }
 
-        log::debug!(
+        tracing::debug!(
             "DictAggregator: processed {} words into {} unique patterns",
             self.received_words,
             self.collector.len()
@@ -1072,44 +2668,262 @@ pub fn ratio_to_string(ratio: CompressionRatio) -> String {
     format!("{:.2}", ratio)
 }
 
+/// What a compressed segment is made of: its dictionaries, the Huffman code
+/// lengths in them and how well the patterns covered the input
+///
+/// [`Compressor::stats`] has all of it after a compression run. Decoded from
+/// a file header by [`crate::Decompressor::stats`], the fields only known
+/// while compressing (`input_bytes`, `covered_bytes`, `phases`) are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompressionStats {
+    pub words: u64,
+    pub empty_words: u64,
+    /// Patterns in the dictionary, i.e. those used by at least one word
+    pub patterns: usize,
+    /// Distinct values in the position dictionary (word lengths and pattern
+    /// positions)
+    pub positions: usize,
+    /// Serialized size of the pattern dictionary, in bytes
+    pub pattern_dict_size: u64,
+    /// Serialized size of the position dictionary, in bytes
+    pub pos_dict_size: u64,
+    /// `pattern_depths[d]` is the number of patterns with a `d`-bit code
+    pub pattern_depths: Vec<u64>,
+    /// `position_depths[d]` is the number of positions with a `d`-bit code
+    pub position_depths: Vec<u64>,
+    /// Total length of the words added, before compression
+    pub input_bytes: Option<u64>,
+    /// Bytes of the input replaced by patterns
+    pub covered_bytes: Option<u64>,
+    /// Size of the segment file
+    pub output_bytes: u64,
+    pub phases: Option<PhaseTimings>,
+}
+
+impl CompressionStats {
+    /// Percentage of the input covered by patterns, if known
+    pub fn coverage(&self) -> Option<f64> {
+        match (self.covered_bytes, self.input_bytes) {
+            (Some(_), Some(0)) => Some(0.0),
+            (Some(covered), Some(input)) => Some(100.0 * covered as f64 / input as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Where the bytes of a segment written by [`Compressor::compress`] went,
+/// next to the length of the words it holds
+///
+/// The parts add up to `compressed_bytes`, the size of the file. From
+/// [`Compressor::size_breakdown`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeBreakdown {
+    /// Total length of the words, encoded if there is a word codec
+    pub uncompressed_bytes: u64,
+    /// Size of the segment file
+    pub compressed_bytes: u64,
+    /// Word counts and dictionary sizes at the start of the file
+    pub header_bytes: u64,
+    pub pattern_dict_bytes: u64,
+    pub pos_dict_bytes: u64,
+    /// Huffman codes and uncovered bytes of the words
+    pub words_bytes: u64,
+    /// Checksum footer, see [`Cfg::checksum`]
+    pub footer_bytes: u64,
+}
+
+impl SizeBreakdown {
+    fn new(stats: &CompressionStats, checksum: bool) -> Self {
+        let footer_bytes = if checksum {
+            crate::core::FOOTER_LEN as u64
+        } else {
+            0
+        };
+        Self {
+            uncompressed_bytes: stats.input_bytes.unwrap_or_default(),
+            compressed_bytes: stats.output_bytes,
+            header_bytes: SEGMENT_HEADER_LEN,
+            pattern_dict_bytes: stats.pattern_dict_size,
+            pos_dict_bytes: stats.pos_dict_size,
+            words_bytes: stats.output_bytes.saturating_sub(
+                SEGMENT_HEADER_LEN + stats.pattern_dict_size + stats.pos_dict_size + footer_bytes,
+            ),
+            footer_bytes,
+        }
+    }
+
+    /// `uncompressed_bytes / compressed_bytes`, 0 before anything was
+    /// written
+    pub fn ratio(&self) -> CompressionRatio {
+        if self.compressed_bytes == 0 {
+            return 0.0;
+        }
+        self.uncompressed_bytes as f64 / self.compressed_bytes as f64
+    }
+
+    /// Percentage of the file taken by the two dictionaries
+    pub fn dictionary_share(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 0.0;
+        }
+        100.0 * (self.pattern_dict_bytes + self.pos_dict_bytes) as f64
+            / self.compressed_bytes as f64
+    }
+}
+
+/// Projected outcome of [`Compressor::compress`], from
+/// [`Compressor::estimate`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionEstimate {
+    /// Words the sample that was encoded holds
+    pub sampled_words: u64,
+    /// Size of the words added, as stored before compression
+    pub input_bytes: u64,
+    /// Size the sample encoded to, header and dictionaries included
+    pub sampled_output_bytes: u64,
+    /// Size the segment is projected to have
+    pub projected_output_bytes: u64,
+    /// Projected [`Compressor::ratio`]
+    pub ratio: CompressionRatio,
+    /// Patterns of the dictionary the sample used
+    pub patterns: usize,
+    /// Serialized size of the pattern dictionary, in bytes
+    pub pattern_dict_size: u64,
+    /// Building the dictionary, which `compress` would do the same way
+    pub dictionary_time: std::time::Duration,
+    /// Covering and encoding the sample
+    pub cover_time: std::time::Duration,
+    /// Projected time of `compress`, writing aside
+    pub projected_time: std::time::Duration,
+}
+
+// Encode `words` into a byte counter to see how the patterns of `dict`
+// fare, then prune those that don't pay off
+fn prune_dictionary(
+    trace: bool,
+    cfg: &Cfg,
+    log_prefix: &str,
+    intermediate_path: &Path,
+    words: &mut RawWordsFile,
+    dict: &mut DictionaryBuilder,
+    cancel: Option<&CancellationToken>,
+) -> std::result::Result<(), CompressionError> {
+    let (_, patterns) = crate::parallel_compress::encode_with_patterns(
+        trace,
+        cfg,
+        log_prefix,
+        intermediate_path,
+        &mut ByteCounter(0),
+        words,
+        dict,
+        None,
+        cancel,
+    )?;
+    let before = dict.len();
+    let pruned = dict.prune(&patterns);
+    tracing::info!(
+        "[{}] Pruned {} of {} dictionary patterns",
+        log_prefix,
+        pruned,
+        before
+    );
+    Ok(())
+}
+
+// Word count, empty word count and the sizes of both dictionaries
+const SEGMENT_HEADER_LEN: u64 = 32;
+
+// Writer that only counts the bytes written to it
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Wall-clock time spent in each phase of [`Compressor::compress`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTimings {
+    /// Building the dictionary from the sampled superstrings
+    pub dictionary: std::time::Duration,
+    /// Covering the words with patterns into the intermediate file
+    pub cover: std::time::Duration,
+    /// Building the Huffman codes and writing the segment file
+    pub write: std::time::Duration,
+}
+
+// Number of codes of each depth, indexed by depth
+pub(crate) fn depth_histogram(depths: impl IntoIterator<Item = u64>) -> Vec<u64> {
+    let mut histogram = Vec::new();
+    for depth in depths {
+        let depth = depth as usize;
+        if depth >= histogram.len() {
+            histogram.resize(depth + 1, 0);
+        }
+        histogram[depth] += 1;
+    }
+    histogram
+}
+
+// Words between two offsets kept in a RawWordsFile's index
+const RAW_WORDS_INDEX_STEP: u64 = 4096;
+
 // RawWordsFile represents a file with raw (uncompressed) words
 // Format: [varint_length][word_bytes]... where varint_length's LSB indicates compression
 // From Go: RawWordsFile struct
 pub struct RawWordsFile {
     f: File,
     w: BufWriter<File>,
-    pub file_path: String,
+    pub file_path: PathBuf,
     buf: [u8; 128], // Buffer for varint encoding - matches Go's 128 byte buffer
     pub count: u64,
+    // Byte length of the words counted so far
+    len: u64,
+    // Offset of every RAW_WORDS_INDEX_STEP-th word, from the first one
+    index: Vec<u64>,
 }
 
 // From Go: OpenRawWordsFile - compress.go:824-832
+//
+// Unlike Go, the words are counted with RawWordsFile::scan
 pub fn open_raw_words_file(
-    file_path: String,
+    file_path: impl Into<PathBuf>,
 ) -> std::result::Result<RawWordsFile, CompressionError> {
     use std::fs::OpenOptions;
+    let file_path = file_path.into();
     let f = OpenOptions::new()
         .read(true)
         .write(false)
         .open(&file_path)
         .map_err(|e| CompressionError::FileOpen {
-            path: file_path.clone(),
+            path: file_path.display().to_string(),
             source: e,
         })?;
     let w = BufWriter::new(f.try_clone()?);
 
-    Ok(RawWordsFile {
+    let mut file = RawWordsFile {
         f,
         w,
         file_path,
         buf: [0; 128],
         count: 0,
-    })
+        len: 0,
+        index: Vec::new(),
+    };
+    file.scan()?;
+    Ok(file)
 }
 
 impl RawWordsFile {
-    pub fn new(file_path: String) -> std::result::Result<Self, CompressionError> {
+    pub fn new(file_path: impl Into<PathBuf>) -> std::result::Result<Self, CompressionError> {
         // Go: compress.go:833-841
+        let file_path = file_path.into();
         // Open with read-write permissions so we can read back later
         use std::fs::OpenOptions;
         let f = OpenOptions::new()
@@ -1119,7 +2933,7 @@ impl RawWordsFile {
             .truncate(true)
             .open(&file_path)
             .map_err(|e| CompressionError::FileCreate {
-                path: file_path.clone(),
+                path: file_path.display().to_string(),
                 source: e,
             })?;
         let w = BufWriter::new(f.try_clone()?);
@@ -1130,30 +2944,133 @@ impl RawWordsFile {
             file_path,
             buf: [0; 128], // Match Go's buffer size
             count: 0,
+            len: 0,
+            index: Vec::new(),
         })
     }
 
+    /// Reopen the file of a run that was interrupted, to append to it again
+    ///
+    /// The words are counted with [`RawWordsFile::scan`] and a word torn by
+    /// the interruption is cut off, so that appending resumes right after
+    /// the last complete word, number [`RawWordsFile::count`].
+    pub fn resume(file_path: impl Into<PathBuf>) -> std::result::Result<Self, CompressionError> {
+        use std::fs::OpenOptions;
+        let file_path = file_path.into();
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&file_path)
+            .map_err(|e| CompressionError::FileOpen {
+                path: file_path.display().to_string(),
+                source: e,
+            })?;
+        let w = BufWriter::new(f.try_clone()?);
+
+        let mut file = RawWordsFile {
+            f,
+            w,
+            file_path,
+            buf: [0; 128],
+            count: 0,
+            len: 0,
+            index: Vec::new(),
+        };
+        file.scan()?;
+        file.f.set_len(file.len)?;
+        Ok(file)
+    }
+
+    /// Count the words of the file and index their offsets, returning the
+    /// count
+    ///
+    /// Reading stops at a word that was not completely written, such as the
+    /// last one of a run that crashed; the count and index only cover the
+    /// complete words before it. Words appended so far are flushed first,
+    /// and appending goes on after the last complete word.
+    pub fn scan(&mut self) -> std::result::Result<u64, CompressionError> {
+        use std::io::{BufReader, Seek, SeekFrom};
+
+        self.w.flush()?;
+        let file_len = self.f.metadata()?.len();
+        self.f.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::with_capacity(1 << 20, &self.f);
+        self.count = 0;
+        self.len = 0;
+        self.index.clear();
+        while self.len < file_len {
+            let l = match read_uvarint(&mut reader) {
+                Ok(l) => l,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            };
+            let end = self.len + encode_varint(&mut self.buf, l) as u64 + (l >> 1);
+            if end > file_len {
+                break;
+            }
+            reader.seek_relative((l >> 1) as i64)?;
+            if self.count.is_multiple_of(RAW_WORDS_INDEX_STEP) {
+                self.index.push(self.len);
+            }
+            self.count += 1;
+            self.len = end;
+        }
+        if self.len < file_len {
+            tracing::warn!(
+                "{}: ignoring {} bytes after the last complete word",
+                self.file_path.display(),
+                file_len - self.len
+            );
+        }
+        drop(reader);
+        self.f.seek(SeekFrom::Start(self.len))?;
+        Ok(self.count)
+    }
+
     // From Go: Append - compress.go:860-872
     pub fn append(&mut self, v: &[u8]) -> std::result::Result<(), CompressionError> {
-        self.count += 1;
         // For compressed words, the length prefix is shifted to make lowest bit zero
-        let n = encode_varint(&mut self.buf, 2 * v.len() as u64);
-        self.w.write_all(&self.buf[..n])?;
-        if !v.is_empty() {
-            self.w.write_all(v)?;
-        }
-        Ok(())
+        self.append_word(v, 2 * v.len() as u64)
     }
 
     // From Go: AppendUncompressed - compress.go:874-887
     pub fn append_uncompressed(&mut self, v: &[u8]) -> std::result::Result<(), CompressionError> {
-        self.count += 1;
         // For uncompressed words, the length prefix is shifted to make lowest bit one
-        let n = encode_varint(&mut self.buf, 2 * v.len() as u64 + 1);
+        self.append_word(v, 2 * v.len() as u64 + 1)
+    }
+
+    /// Cut the file after its first `words` words
+    pub fn truncate(&mut self, words: u64) -> std::result::Result<(), CompressionError> {
+        use std::io::Seek;
+
+        let words = words.min(self.count);
+        let offset = {
+            let mut reader = self.words_from(words)?;
+            match reader.take_error() {
+                Some(e) => return Err(e),
+                None => reader.offset,
+            }
+        };
+        self.f.set_len(offset)?;
+        self.f.seek(std::io::SeekFrom::Start(offset))?;
+        self.len = offset;
+        self.count = words;
+        self.index
+            .truncate(words.div_ceil(RAW_WORDS_INDEX_STEP) as usize);
+        Ok(())
+    }
+
+    fn append_word(&mut self, v: &[u8], prefix: u64) -> std::result::Result<(), CompressionError> {
+        if self.count.is_multiple_of(RAW_WORDS_INDEX_STEP) {
+            self.index.push(self.len);
+        }
+        self.count += 1;
+        let n = encode_varint(&mut self.buf, prefix);
         self.w.write_all(&self.buf[..n])?;
         if !v.is_empty() {
             self.w.write_all(v)?;
         }
+        self.len += (n + v.len()) as u64;
         Ok(())
     }
 
@@ -1184,51 +3101,118 @@ impl RawWordsFile {
     where
         F: FnMut(&[u8], bool) -> std::result::Result<(), CompressionError>,
     {
-        use std::io::{BufReader, Read, Seek};
-
-        // Seek to beginning
-        self.f.seek(std::io::SeekFrom::Start(0))?;
-        log::debug!(
+        tracing::debug!(
             "RawWordsFile::for_each - starting at position 0, count: {}",
             self.count
         );
+        let mut words = self.words()?;
+        while let Some((word, compressed)) = words.next_word() {
+            walker(&word, compressed)?;
+        }
+        match words.take_error() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
 
-        // Use 8MB buffer like Go does
-        const BUF_SIZE: usize = 8 * 1024 * 1024;
-        let mut reader = BufReader::with_capacity(BUF_SIZE, &self.f);
-        let mut buf = vec![0u8; 16 * 1024];
-
-        loop {
-            // Read varint length using our helper function
-            let mut l = match read_uvarint(&mut reader) {
-                Ok(val) => val,
-                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    log::debug!("RawWordsFile::for_each - EOF reached");
-                    return Ok(());
-                }
-                Err(e) => {
-                    log::debug!("RawWordsFile::for_each - Error reading varint: {:?}", e);
-                    return Err(CompressionError::from(e));
-                }
-            };
+    /// Read the words back from the first one, as a [`WordSource`]
+    ///
+    /// Words appended so far are flushed first.
+    pub fn words(&mut self) -> std::result::Result<RawWordsReader<'_>, CompressionError> {
+        self.words_from(0)
+    }
 
-            // Extract lowest bit as "uncompressed" flag
-            let compressed = (l & 1) == 0;
-            l >>= 1;
+    /// Like [`RawWordsFile::words`], from word number `first`, e.g. to pick
+    /// up a pass over the words where an interrupted run left it
+    ///
+    /// Reading starts at the closest indexed word before `first`, so this
+    /// skips over at most a few thousand words.
+    pub fn words_from(
+        &mut self,
+        first: u64,
+    ) -> std::result::Result<RawWordsReader<'_>, CompressionError> {
+        use std::io::Seek;
 
-            // Read word bytes
-            if buf.len() < l as usize {
-                buf.resize(l as usize, 0);
+        self.w.flush()?;
+        let slot = (first / RAW_WORDS_INDEX_STEP) as usize;
+        let (offset, skip) = match self.index.get(slot) {
+            Some(&offset) => (offset, first % RAW_WORDS_INDEX_STEP),
+            None => (self.len, 0),
+        };
+        self.f.seek(std::io::SeekFrom::Start(offset))?;
+        let mut words = RawWordsReader {
+            // Use 8MB buffer like Go does
+            reader: std::io::BufReader::with_capacity(8 * 1024 * 1024, &self.f),
+            buf: vec![0u8; 16 * 1024],
+            error: None,
+            offset,
+        };
+        for _ in 0..skip {
+            if words.next_word().is_none() {
+                break;
             }
-            reader.read_exact(&mut buf[..l as usize])?;
+        }
+        Ok(words)
+    }
+}
 
-            log::debug!(
-                "RawWordsFile::for_each - read word of length {}, compressed: {}",
-                l,
-                compressed
-            );
-            walker(&buf[..l as usize], compressed)?;
+/// The words of a [`RawWordsFile`], see [`RawWordsFile::words`]
+pub struct RawWordsReader<'a> {
+    reader: std::io::BufReader<&'a File>,
+    buf: Vec<u8>,
+    error: Option<CompressionError>,
+    // File offset of the next word
+    offset: u64,
+}
+
+impl WordSource for RawWordsReader<'_> {
+    fn next_word(&mut self) -> Option<(Cow<'_, [u8]>, bool)> {
+        use std::io::Read;
+
+        if self.error.is_some() {
+            return None;
+        }
+        // Read varint length using our helper function
+        let mut l = match read_uvarint(&mut self.reader) {
+            Ok(val) => val,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                tracing::debug!("RawWordsReader - EOF reached");
+                return None;
+            }
+            Err(e) => {
+                tracing::debug!("RawWordsReader - Error reading varint: {:?}", e);
+                self.error = Some(e.into());
+                return None;
+            }
+        };
+
+        let mut num_buf = [0u8; 10];
+        let n = encode_varint(&mut num_buf, l);
+
+        // Extract lowest bit as "uncompressed" flag
+        let compressed = (l & 1) == 0;
+        l >>= 1;
+
+        // Read word bytes
+        if self.buf.len() < l as usize {
+            self.buf.resize(l as usize, 0);
+        }
+        if let Err(e) = self.reader.read_exact(&mut self.buf[..l as usize]) {
+            self.error = Some(e.into());
+            return None;
         }
+        self.offset += n as u64 + l;
+
+        word_trace!(
+            "RawWordsReader - read word of length {}, compressed: {}",
+            l,
+            compressed
+        );
+        Some((Cow::Borrowed(&self.buf[..l as usize]), compressed))
+    }
+
+    fn take_error(&mut self) -> Option<CompressionError> {
+        self.error.take()
     }
 }
 
@@ -1245,8 +3229,47 @@ impl CompressionWord {
     }
 }
 
+// Feed a word to a word stream checksum, framed the way exports are
+// (uvarint length, then the bytes) so word boundaries count
+pub(crate) fn hash_word(hasher: &mut Xxh64, word: &[u8]) {
+    let mut len_buf = [0u8; 10];
+    let n = encode_varint(&mut len_buf, word.len() as u64);
+    hasher.update(&len_buf[..n]);
+    hasher.update(word);
+}
+
+// Hash the written segment at `path` and append the checksum footer to it
+pub(crate) fn append_checksum_footer(
+    path: &Path,
+    words: u64,
+) -> std::result::Result<(), CompressionError> {
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| CompressionError::FileOpen {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+    let mut hasher = Xxh64::new(0);
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = std::io::Read::read(&mut file, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let checksum = SegmentChecksum {
+        words,
+        payload: hasher.digest(),
+    };
+    file.write_all(&checksum.footer())?;
+    Ok(())
+}
+
 // Helper function to encode varint (like Go's binary.PutUvarint)
-fn encode_varint(buf: &mut [u8], mut x: u64) -> usize {
+pub(crate) fn encode_varint(buf: &mut [u8], mut x: u64) -> usize {
     let mut i = 0;
     while x >= 0x80 {
         buf[i] = (x as u8) | 0x80;
@@ -1281,6 +3304,12 @@ pub fn read_uvarint(reader: &mut impl std::io::Read) -> std::io::Result<u64> {
     }
 }
 
+// Helper function to decode varint from a byte slice (like Go's binary.Uvarint)
+// Returns the value and the number of bytes consumed
+pub(crate) fn decode_varint(data: &[u8]) -> std::result::Result<(u64, usize), CompressionError> {
+    Ok(crate::core::uvarint(data)?)
+}
+
 // Helper functions
 
 // From Go: bits.Reverse64 function
@@ -1303,34 +3332,6 @@ fn persist_dictionary(
     Ok(())
 }
 
-// From Go: calculateRatio - compress.go
-fn calculate_ratio(
-    uncompressed_path: &str,
-    compressed_path: &str,
-) -> std::result::Result<CompressionRatio, CompressionError> {
-    use std::fs;
-
-    let uncompressed_meta = fs::metadata(uncompressed_path)?;
-    let compressed_meta = fs::metadata(compressed_path)?;
-
-    let uncompressed_size = uncompressed_meta.len();
-    let compressed_size = compressed_meta.len();
-
-    log::debug!(
-        "Uncompressed size: {}, Compressed size: {}",
-        uncompressed_size,
-        compressed_size
-    );
-
-    if compressed_size == 0 {
-        // If compressed file is empty, return 0 ratio
-        return Ok(0.0);
-    }
-
-    let ratio = uncompressed_size as f64 / compressed_size as f64;
-    Ok(ratio)
-}
-
 // Include test module
 #[cfg(test)]
 #[path = "compress_test.rs"]
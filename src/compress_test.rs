@@ -19,8 +19,10 @@ mod tests {
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("compressed");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 100;
+        let cfg = Cfg {
+            min_pattern_score: 100,
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -63,11 +65,11 @@ mod tests {
         }
 
         for _i in 0..100 {
-            words.push(format!("longlongword").into_bytes());
+            words.push(b"longlongword".to_vec());
         }
 
         for _i in 0..10 {
-            words.push(format!("veryveryverylongword").into_bytes());
+            words.push(b"veryveryverylongword".to_vec());
         }
 
         for i in 0..200 {
@@ -106,9 +108,11 @@ mod tests {
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("compressed");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 1;
-        cfg.workers = 1; // Use single worker for now
+        let cfg = Cfg {
+            min_pattern_score: 1,
+            workers: 1, // Use single worker for now
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -143,13 +147,291 @@ mod tests {
             println!(
                 "Got word: {:?}, expected: {:?}",
                 std::str::from_utf8(&word),
-                std::str::from_utf8(&expected_word)
+                std::str::from_utf8(expected_word)
             );
             assert_eq!(word, expected_word.as_slice());
         }
         assert!(!getter.has_next());
     }
 
+    // Go test: TestCompressDict1 - full prepareDict word set
+    #[test]
+    fn test_compress_prepared_dict() {
+        use crate::decompress::Decompressor;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("compressed");
+
+        let cfg = Cfg {
+            min_pattern_score: 1,
+            ..Default::default()
+        };
+
+        let mut compressor = Compressor::new(
+            cfg,
+            file_path.to_string_lossy().to_string(),
+            tmp_dir.path().to_string_lossy().to_string(),
+            "test".to_string(),
+            log::Level::Debug,
+        )
+        .unwrap();
+
+        let words = prepare_dict();
+        for word in &words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+        drop(compressor);
+
+        let decompressor = Decompressor::new(&file_path).unwrap();
+        assert_eq!(decompressor.count(), words.len());
+        assert_eq!(decompressor.empty_words_count(), 100);
+
+        let mut getter = decompressor.make_getter();
+        for expected_word in &words {
+            assert!(getter.has_next());
+            let (word, _) = getter.next(Vec::new());
+            assert_eq!(word, expected_word.as_slice());
+        }
+        assert!(!getter.has_next());
+    }
+
+    // Patterns used in Fibonacci proportions get Huffman codes one bit
+    // deeper each, past 16 bits and through several chained decode tables
+    #[test]
+    fn test_deep_dictionary_round_trip() {
+        use crate::decompress::Decompressor;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("compressed");
+
+        // No byte is shared between patterns, so a word is covered by its
+        // own pattern only
+        let patterns: Vec<Vec<u8>> = (0..20u8)
+            .map(|k| (0..12).map(|j| k * 12 + j).collect())
+            .collect();
+        let mut dict = DictionaryBuilder::new(patterns.len());
+        for pattern in &patterns {
+            dict.process_word(pattern.clone(), 1);
+        }
+        let mut compressor = Compressor::builder(&file_path)
+            .fsync(false)
+            .build()
+            .unwrap()
+            .with_dictionary(dict);
+
+        let mut words = Vec::new();
+        let (mut uses, mut next) = (1, 1);
+        for pattern in &patterns {
+            words.extend(std::iter::repeat_n(pattern.clone(), uses));
+            (uses, next) = (next, uses + next);
+        }
+        for word in &words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+
+        let decompressor = Decompressor::new(&file_path).unwrap();
+        let deepest = decompressor.patterns().map(|(depth, _)| depth).max();
+        assert!(deepest.unwrap() > 16, "deepest code {:?}", deepest);
+        let mut getter = decompressor.make_getter();
+        for expected_word in &words {
+            assert!(getter.has_next());
+            assert_eq!(getter.next(Vec::new()).0, expected_word.as_slice());
+        }
+        assert!(!getter.has_next());
+    }
+
+    // Go helper: prepareDict - 100 rounds of nil, "long", "word" and
+    // "%d longlongword %d"
+    fn prepare_match_dict() -> (TempDir, crate::decompress::Decompressor) {
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("compressed");
+        let mut compressor = Compressor::builder(&file_path)
+            .min_pattern_score(1)
+            .workers(2)
+            .fsync(false)
+            .build()
+            .unwrap();
+        for i in 0..100 {
+            compressor.add_word(&[]).unwrap();
+            compressor.add_word(b"long").unwrap();
+            compressor.add_word(b"word").unwrap();
+            compressor
+                .add_word(format!("{} longlongword {}", i, i).as_bytes())
+                .unwrap();
+        }
+        compressor.compress().unwrap();
+        let decompressor = crate::decompress::Decompressor::new(&file_path).unwrap();
+        (tmp_dir, decompressor)
+    }
+
+    // Go test: TestCompressDict1 - the MatchPrefix checks
+    #[test]
+    fn test_compress_dict_match_prefix() {
+        let (_tmp_dir, decompressor) = prepare_match_dict();
+        let mut g = decompressor.make_getter();
+        let mut i = 0;
+        while g.has_next() {
+            // next word is empty
+            assert!(!g.match_prefix(b"long"));
+            assert!(g.match_prefix(b""));
+            let (word, _) = g.next(Vec::new());
+            assert!(word.is_empty());
+
+            // next word is `long`
+            assert!(g.match_prefix(b"long"));
+            assert!(!g.match_prefix(b"longlong"));
+            assert!(!g.match_prefix(b"wordnotmatch"));
+            assert!(!g.match_prefix(b"longnotmatch"));
+            assert!(g.match_prefix(b""));
+            g.next(Vec::new());
+
+            // next word is `word`
+            assert!(!g.match_prefix(b"long"));
+            assert!(!g.match_prefix(b"longlong"));
+            assert!(g.match_prefix(b"word"));
+            assert!(g.match_prefix(b""));
+            assert!(!g.match_prefix(b"wordnotmatch"));
+            assert!(!g.match_prefix(b"longnotmatch"));
+            g.next(Vec::new());
+
+            // next word is `%d longlongword %d`
+            let expect_prefix = format!("{} long", i);
+            assert!(g.match_prefix(i.to_string().as_bytes()));
+            assert!(g.match_prefix(expect_prefix.as_bytes()));
+            assert!(g.match_prefix(format!("{}long", expect_prefix).as_bytes()));
+            assert!(g.match_prefix(format!("{}longword ", expect_prefix).as_bytes()));
+            assert!(!g.match_prefix(b"wordnotmatch"));
+            assert!(!g.match_prefix(b"longnotmatch"));
+            assert!(g.match_prefix(b""));
+
+            let save_pos = g.offset();
+            let (word, next_pos) = g.next(Vec::new());
+            let expected = format!("{} longlongword {}", i, i);
+            assert_eq!(word, expected.as_bytes());
+            g.reset(save_pos);
+            assert!(g.match_prefix(expected.as_bytes()));
+            assert!(!g.match_prefix(format!("{}!", expected).as_bytes()));
+            g.reset(next_pos);
+            i += 1;
+        }
+        assert_eq!(i, 100);
+    }
+
+    // Go test: TestCompressDictCmp
+    #[test]
+    fn test_compress_dict_cmp() {
+        use std::cmp::Ordering::{Equal, Greater, Less};
+
+        let (_tmp_dir, decompressor) = prepare_match_dict();
+        let mut g = decompressor.make_getter();
+        let mut i = 0;
+        while g.has_next() {
+            // next word is empty
+            let save_pos = g.offset();
+            assert_eq!(g.match_cmp(b"long"), Greater);
+            assert_eq!(g.match_cmp(b""), Equal); // moves offset
+            assert_ne!(g.offset(), save_pos);
+            g.reset(save_pos);
+            let (word, _) = g.next(Vec::new());
+            assert!(word.is_empty());
+
+            // next word is `long`
+            let save_pos = g.offset();
+            assert_eq!(g.match_cmp(b"long"), Equal); // moves offset
+            g.reset(save_pos);
+            assert_eq!(g.match_cmp(b"longlong"), Greater);
+            assert_eq!(g.match_cmp(b"wordnotmatch"), Greater);
+            assert_eq!(g.match_cmp(b"longnotmatch"), Greater);
+            assert_eq!(g.match_cmp(b""), Less);
+            assert_eq!(g.offset(), save_pos);
+            g.next(Vec::new());
+
+            // next word is `word`
+            let save_pos = g.offset();
+            assert_eq!(g.match_cmp(b"long"), Less);
+            assert_eq!(g.match_cmp(b"longlong"), Less);
+            assert_eq!(g.match_cmp(b"word"), Equal); // moves offset
+            g.reset(save_pos);
+            assert_eq!(g.match_cmp(b""), Less);
+            assert_eq!(g.match_cmp(b"wordnotmatch"), Greater);
+            assert_eq!(g.match_cmp(b"longnotmatch"), Less);
+            g.next(Vec::new());
+
+            // next word is `%d longlongword %d`
+            let expect_prefix = format!("{} long", i);
+            assert_eq!(g.match_cmp(i.to_string().as_bytes()), Less);
+            assert_eq!(g.match_cmp(expect_prefix.as_bytes()), Less);
+            assert_eq!(
+                g.match_cmp(format!("{}long", expect_prefix).as_bytes()),
+                Less
+            );
+            assert_eq!(
+                g.match_cmp(format!("{}longword ", expect_prefix).as_bytes()),
+                Less
+            );
+            assert_eq!(g.match_cmp(b"wordnotmatch"), Greater);
+            assert_eq!(g.match_cmp(b"longnotmatch"), Greater);
+            assert_eq!(g.match_cmp(b""), Less);
+
+            let save_pos = g.offset();
+            let (word, next_pos) = g.next(Vec::new());
+            let expected = format!("{} longlongword {}", i, i);
+            assert_eq!(word, expected.as_bytes());
+            g.reset(save_pos);
+            assert_eq!(g.match_cmp(expected.as_bytes()), Equal);
+            assert_eq!(g.offset(), next_pos);
+            i += 1;
+        }
+        assert_eq!(i, 100);
+    }
+
+    // Go test: TestCompressDictCmp, MatchPrefixCmp against decoded words
+    #[test]
+    fn test_compress_dict_prefix_cmp() {
+        let (_tmp_dir, decompressor) = prepare_match_dict();
+        let mut g = decompressor.make_getter();
+        while g.has_next() {
+            let save_pos = g.offset();
+            let (word, next_pos) = g.next(Vec::new());
+            g.reset(save_pos);
+
+            let mut targets: Vec<Vec<u8>> = vec![
+                Vec::new(),
+                b"long".to_vec(),
+                b"longlong".to_vec(),
+                b"word".to_vec(),
+                b"5 longlongword 7".to_vec(),
+                word.clone(),
+                [word.as_slice(), b"!"].concat(),
+            ];
+            for cut in 1..word.len() {
+                targets.push(word[..cut].to_vec());
+                let mut changed = word.clone();
+                changed[cut] = changed[cut].wrapping_add(1);
+                targets.push(changed);
+            }
+            for target in &targets {
+                let head = &word[..word.len().min(target.len())];
+                assert_eq!(
+                    g.match_prefix_cmp(target),
+                    target.as_slice().cmp(head),
+                    "{:?} {:?}",
+                    String::from_utf8_lossy(target),
+                    String::from_utf8_lossy(&word)
+                );
+                assert_eq!(g.offset(), save_pos);
+                let cmp = g.match_cmp(target);
+                assert_eq!(cmp, target.as_slice().cmp(&word));
+                let expected_pos = if cmp.is_eq() { next_pos } else { save_pos };
+                assert_eq!(g.offset(), expected_pos);
+                g.reset(save_pos);
+            }
+            g.reset(next_pos);
+        }
+    }
+
     // Test for DictionaryBuilder (not in original Go tests, but useful)
     #[test]
     fn test_dictionary_builder_operations() {
@@ -214,6 +496,329 @@ mod tests {
         assert_eq!(word.order, 42);
     }
 
+    // Test reading a RawWordsFile back as a WordSource
+    #[test]
+    fn test_raw_words_file_words() {
+        use crate::word_source::WordSource;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let mut file = RawWordsFile::new(tmp_dir.path().join("words.idt")).unwrap();
+        file.append(b"compressed").unwrap();
+        file.append_uncompressed(b"stored").unwrap();
+        file.append(b"").unwrap();
+
+        let mut read = Vec::new();
+        let mut words = file.words().unwrap();
+        while let Some((word, compressed)) = words.next_word() {
+            read.push((word.into_owned(), compressed));
+        }
+        assert!(words.take_error().is_none());
+        assert_eq!(
+            read,
+            vec![
+                (b"compressed".to_vec(), true),
+                (b"stored".to_vec(), false),
+                (Vec::new(), true),
+            ]
+        );
+    }
+
+    // Test counting, resuming and seeking into an existing RawWordsFile
+    #[test]
+    fn test_raw_words_file_scan_and_resume() {
+        use crate::word_source::WordSource;
+        use std::io::Write;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("words.idt");
+        let word = |i: u64| format!("word {}", i).into_bytes();
+        let mut file = RawWordsFile::new(&path).unwrap();
+        for i in 0..10_000 {
+            file.append(&word(i)).unwrap();
+        }
+        file.close().unwrap();
+
+        let opened = open_raw_words_file(&path).unwrap();
+        assert_eq!(opened.count, 10_000);
+        drop(opened);
+
+        // A crash in the middle of writing a word leaves part of it behind
+        let mut f = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        f.write_all(&[40, b'w', b'o']).unwrap();
+        drop(f);
+
+        let mut file = RawWordsFile::resume(&path).unwrap();
+        assert_eq!(file.count, 10_000);
+        for i in 10_000..10_010 {
+            file.append(&word(i)).unwrap();
+        }
+        assert_eq!(file.scan().unwrap(), 10_010);
+
+        let mut words = file.words_from(8_200).unwrap();
+        for i in 8_200..10_010 {
+            let (read, compressed) = words.next_word().unwrap();
+            assert_eq!(read.as_ref(), word(i).as_slice());
+            assert!(compressed);
+        }
+        assert!(words.next_word().is_none());
+        assert!(words.take_error().is_none());
+        assert!(file.words_from(10_010).unwrap().next_word().is_none());
+    }
+
+    // Test resuming a crashed run from its last checkpoint
+    #[test]
+    fn test_checkpoint_resume() {
+        use crate::error::{CompressError, CompressionError};
+        use std::path::Path;
+
+        let word = |i: u64| format!("checkpointed word {} {}", i % 17, i).into_bytes();
+        let builder = |path: &Path, cfg: &Cfg| {
+            Compressor::builder(path)
+                .cfg(cfg.clone())
+                .checksum(true)
+                .fsync(false)
+                .checkpoint_every(300)
+        };
+
+        for cfg in [
+            Cfg {
+                min_pattern_score: 1,
+                ..Cfg::default()
+            },
+            Cfg {
+                min_pattern_score: 1,
+                sampling: SamplingStrategy::Reservoir {
+                    words: 100,
+                    seed: 7,
+                },
+                ..Cfg::default()
+            },
+        ] {
+            let tmp_dir = TempDir::new().unwrap();
+            let expected = tmp_dir.path().join("expected.seg");
+            let mut c = builder(&expected, &cfg).build().unwrap();
+            for i in 0..1000 {
+                c.add_word(&word(i)).unwrap();
+            }
+            c.compress().unwrap();
+
+            // Killed 50 words after the checkpoint at 900
+            let path = tmp_dir.path().join("resumed.seg");
+            let mut c = builder(&path, &cfg).build().unwrap();
+            for i in 0..950 {
+                c.add_word(&word(i)).unwrap();
+            }
+            let workspace = c.workspace().to_path_buf();
+            std::mem::forget(c);
+
+            let other = Cfg {
+                min_pattern_score: 2,
+                ..cfg.clone()
+            };
+            assert!(matches!(
+                builder(&path, &other).resume(&workspace),
+                Err(CompressionError::Compress(
+                    CompressError::CheckpointMismatch { .. }
+                ))
+            ));
+
+            let mut c = builder(&path, &cfg).resume(&workspace).unwrap();
+            assert_eq!(c.count(), 900);
+            for i in c.count()..1000 {
+                c.add_word(&word(i)).unwrap();
+            }
+            c.compress().unwrap();
+            drop(c);
+            assert_eq!(
+                std::fs::read(&path).unwrap(),
+                std::fs::read(&expected).unwrap()
+            );
+            assert!(!workspace.exists());
+        }
+    }
+
+    // Test suspending a run and finding it again with recover_or_clean
+    #[test]
+    fn test_suspend_resume() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("suspended.seg");
+        let mut c = Compressor::builder(&path).fsync(false).build().unwrap();
+        for i in 0..100u32 {
+            c.add_word(format!("word {}", i).as_bytes()).unwrap();
+        }
+        let workspace = c.suspend().unwrap();
+
+        let report = crate::recover_or_clean(tmp_dir.path()).unwrap();
+        assert_eq!(report.resumable, std::slice::from_ref(&workspace));
+        assert!(report.removed.is_empty());
+
+        let mut c = Compressor::builder(&path)
+            .fsync(false)
+            .resume(&workspace)
+            .unwrap();
+        assert_eq!(c.count(), 100);
+        c.compress().unwrap();
+        let d = crate::decompress::Decompressor::new(&path).unwrap();
+        assert_eq!(d.count(), 100);
+    }
+
+    #[test]
+    fn test_compressor_state() {
+        use crate::error::{CompressError, CompressionError};
+        let already_compressed = |result| {
+            matches!(
+                result,
+                Err(CompressionError::Compress(
+                    CompressError::AlreadyCompressed { .. }
+                ))
+            )
+        };
+
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("state.seg");
+        let mut c = Compressor::builder(&path).fsync(false).build().unwrap();
+        assert_eq!(c.state(), CompressorState::Collecting);
+        c.add_word(b"first word").unwrap();
+        c.add_uncompressed_word(b"second word").unwrap();
+        c.compress().unwrap();
+        assert_eq!(c.state(), CompressorState::Compressed);
+        assert!(already_compressed(c.add_word(b"late word")));
+        assert!(already_compressed(c.add_uncompressed_word(b"late word")));
+        assert!(already_compressed(c.compress()));
+        assert_eq!(c.count(), 2);
+        assert_eq!(
+            crate::decompress::Decompressor::new(&path).unwrap().count(),
+            2
+        );
+
+        // Counts that drifted apart leave no segment behind
+        let path = tmp_dir.path().join("mismatch.seg");
+        let mut c = Compressor::builder(&path).fsync(false).build().unwrap();
+        c.add_word(b"only word").unwrap();
+        c.words_count += 1;
+        assert!(matches!(
+            c.compress(),
+            Err(CompressionError::Compress(
+                CompressError::WordCountMismatch {
+                    added: 2,
+                    actual: 1,
+                    ..
+                }
+            ))
+        ));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_compress_cancelled() {
+        use crate::cancel::CancellationToken;
+        use crate::error::CompressionError;
+
+        let tmp_dir = TempDir::new().unwrap();
+        for level in [CompressionLevel::Default, CompressionLevel::Store] {
+            let path = tmp_dir.path().join(format!("cancelled-{:?}.seg", level));
+            let cancel = CancellationToken::new();
+            let mut c = Compressor::builder(&path)
+                .level(level)
+                .fsync(false)
+                .cancellation(cancel.clone())
+                .build()
+                .unwrap();
+            for i in 0..1000 {
+                c.add_word(format!("word {} of a cancelled run", i).as_bytes())
+                    .unwrap();
+            }
+            cancel.cancel();
+            assert!(matches!(c.compress(), Err(CompressionError::Cancelled)));
+            drop(c);
+            assert!(!path.exists());
+            // Nothing of the run is left behind
+            assert_eq!(std::fs::read_dir(tmp_dir.path()).unwrap().count(), 0);
+        }
+    }
+
+    // Strips a key prefix, flagging the words that had it
+    struct StripPrefix(&'static [u8]);
+
+    impl crate::codec::WordCodec for StripPrefix {
+        fn encode(&self, word: &[u8], out: &mut Vec<u8>) {
+            match word.strip_prefix(self.0) {
+                Some(rest) => {
+                    out.push(1);
+                    out.extend_from_slice(rest);
+                }
+                None => {
+                    out.push(0);
+                    out.extend_from_slice(word);
+                }
+            }
+        }
+
+        fn decode(&self, stored: &[u8], out: &mut Vec<u8>) {
+            if let Some((&flag, rest)) = stored.split_first() {
+                if flag == 1 {
+                    out.extend_from_slice(self.0);
+                }
+                out.extend_from_slice(rest);
+            }
+        }
+    }
+
+    #[test]
+    fn test_word_codec_round_trip() {
+        use crate::decompress::Decompressor;
+        const PREFIX: &[u8] = b"account/0xdeadbeef/";
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("codec.seg");
+        let mut words: Vec<Vec<u8>> = (0..200u32)
+            .map(|i| [PREFIX, format!("storage slot {}", i % 17).as_bytes()].concat())
+            .collect();
+        words.push(b"no prefix".to_vec());
+        words.push(Vec::new());
+
+        let mut c = Compressor::builder(&path)
+            .fsync(false)
+            .checksum(true)
+            .word_codec(StripPrefix(PREFIX))
+            .build()
+            .unwrap();
+        for (i, word) in words.iter().enumerate() {
+            if i % 50 == 0 {
+                c.add_uncompressed_word(word).unwrap();
+            } else {
+                c.add_word(word).unwrap();
+            }
+        }
+        c.compress().unwrap();
+
+        // Without the codec the words read back as stored
+        let stored = Decompressor::new(&path).unwrap();
+        let mut g = stored.make_getter();
+        let (word, _) = g.next(Vec::new());
+        assert_eq!(word, [&[1u8][..], b"storage slot 0"].concat());
+
+        let d = Decompressor::new(&path)
+            .unwrap()
+            .with_word_codec(StripPrefix(PREFIX));
+        d.verify_checksum().unwrap();
+        let mut g = d.make_getter();
+        assert!(g.match_prefix(&[1]));
+        for (i, expected) in words.iter().enumerate() {
+            let mut buf = vec![0xff];
+            buf = if i % 50 == 0 {
+                let (word, _) = g.next_uncompressed();
+                [buf, word].concat()
+            } else {
+                g.try_next(buf).unwrap().0
+            };
+            assert_eq!(&buf[1..], expected.as_slice(), "word {}", i);
+        }
+        assert!(!g.has_next());
+    }
+
     // Test Ring initialization
     #[test]
     fn test_ring_new() {
@@ -235,5 +840,446 @@ mod tests {
         assert_eq!(cfg.max_dict_patterns, 64 * 1024);
         assert_eq!(cfg.dict_reducer_soft_limit, 1_000_000);
         assert_eq!(cfg.workers, 1);
+        assert_eq!(cfg.sampling, SamplingStrategy::EveryNth);
+    }
+
+    #[test]
+    fn test_superstrings_spill_in_order() {
+        let tmp_dir = TempDir::new().unwrap();
+        let spill_path = tmp_dir.path().join("test.superstrings");
+        let mut superstrings = Superstrings::new(Some(25), spill_path.clone());
+        let inputs: Vec<Vec<u8>> = (0..6u8).map(|i| vec![i; 10]).collect();
+        for ss in &inputs {
+            superstrings.push(ss.clone()).unwrap();
+        }
+        // Two fit in 25 bytes; the rest go to disk, even ones that would fit
+        assert_eq!(superstrings.len(), 6);
+        assert_eq!(superstrings.spilled(), 4);
+
+        // Read back twice, in the order they were pushed
+        for _ in 0..2 {
+            let mut seen = Vec::new();
+            superstrings
+                .for_each(|ss| {
+                    seen.push(ss.to_vec());
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(seen, inputs);
+        }
+
+        assert!(spill_path.exists());
+        drop(superstrings);
+        assert!(!spill_path.exists());
+    }
+
+    #[test]
+    fn test_superstring_memory_limit_same_output() {
+        use crate::decompress::Decompressor;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let words: Vec<Vec<u8>> = (0..5000u32)
+            .map(|i| format!("account {} balance {}", i % 97, i % 13).into_bytes())
+            .collect();
+        let compress = |name: &str, limit: Option<usize>| {
+            let path = tmp_dir.path().join(name);
+            let mut builder = Compressor::builder(&path)
+                .fsync(false)
+                .min_pattern_score(4)
+                .sampling_factor(1);
+            if let Some(limit) = limit {
+                builder = builder.superstring_memory_limit(limit);
+            }
+            let mut compressor = builder.build().unwrap();
+            for word in &words {
+                compressor.add_word(word).unwrap();
+            }
+            compressor.compress().unwrap();
+            path
+        };
+
+        let unlimited = compress("unlimited.seg", None);
+        let spilled = compress("spilled.seg", Some(0));
+        assert_eq!(
+            std::fs::read(&unlimited).unwrap(),
+            std::fs::read(&spilled).unwrap()
+        );
+        assert!(!tmp_dir.path().join("spilled.superstrings").exists());
+
+        let decompressor = Decompressor::new(&spilled).unwrap();
+        assert!(decompressor.stats().patterns > 0);
+        let mut getter = decompressor.make_getter();
+        for word in &words {
+            assert_eq!(&getter.next(Vec::new()).0, word);
+        }
+    }
+
+    #[test]
+    fn test_greedy_optimizer_round_trip() {
+        use crate::decompress::Decompressor;
+
+        let tmp_dir = TempDir::new().unwrap();
+        // Long words made of a few recurring records, plus short ones
+        let words: Vec<Vec<u8>> = (0..400u32)
+            .map(|i| {
+                let records = if i % 4 == 0 { 1 } else { 40 };
+                (0..records)
+                    .map(|j| format!("record {} of kind {};", (i + j) % 11, j % 7))
+                    .collect::<String>()
+                    .into_bytes()
+            })
+            .collect();
+        let compress = |name: &str, optimizer: OptimizerMode| {
+            let path = tmp_dir.path().join(name);
+            let mut compressor = Compressor::builder(&path)
+                .fsync(false)
+                .min_pattern_score(4)
+                .sampling_factor(1)
+                .optimizer(optimizer)
+                .build()
+                .unwrap();
+            for word in &words {
+                compressor.add_word(word).unwrap();
+            }
+            compressor.compress().unwrap();
+            path
+        };
+
+        let exact = compress("exact.seg", OptimizerMode::Exact);
+        let greedy = compress("greedy.seg", OptimizerMode::Greedy { min_word_len: 100 });
+        for path in [&exact, &greedy] {
+            let decompressor = Decompressor::new(path).unwrap();
+            let mut getter = decompressor.make_getter();
+            for word in &words {
+                assert_eq!(&getter.next(Vec::new()).0, word);
+            }
+        }
+
+        // Greedy still compresses, not much worse than the exact cover
+        let exact_len = std::fs::metadata(&exact).unwrap().len();
+        let greedy_len = std::fs::metadata(&greedy).unwrap().len();
+        let input_len: usize = words.iter().map(Vec::len).sum();
+        assert!(greedy_len < input_len as u64 / 2);
+        assert!(greedy_len <= exact_len + exact_len / 10);
+
+        assert!(!OptimizerMode::Exact.is_greedy_for(usize::MAX));
+        assert!(!OptimizerMode::Greedy { min_word_len: 100 }.is_greedy_for(99));
+        assert!(OptimizerMode::Greedy { min_word_len: 100 }.is_greedy_for(100));
+    }
+
+    #[test]
+    fn test_workers_same_output() {
+        use crate::decompress::Decompressor;
+        use xxhash_rust::xxh64::xxh64;
+
+        let tmp_dir = TempDir::new().unwrap();
+        // More words than a batch, some empty, some stored uncompressed and
+        // some long enough for the greedy optimizer
+        let words: Vec<Vec<u8>> = (0..70_000u32)
+            .map(|i| match i % 500 {
+                0 => Vec::new(),
+                1 => (0..30)
+                    .map(|j| format!("record {} of kind {};", (i + j) % 11, j % 7))
+                    .collect::<String>()
+                    .into_bytes(),
+                _ => format!("account {} balance {}", i % 97, i % 13).into_bytes(),
+            })
+            .collect();
+        let compress = |workers: usize| {
+            let path = tmp_dir.path().join(format!("workers-{}.seg", workers));
+            let mut compressor = Compressor::builder(&path)
+                .fsync(false)
+                .min_pattern_score(4)
+                .sampling_factor(1)
+                .optimizer(OptimizerMode::Greedy { min_word_len: 100 })
+                .workers(workers)
+                .build()
+                .unwrap();
+            for (i, word) in words.iter().enumerate() {
+                if i % 7 == 3 {
+                    compressor.add_uncompressed_word(word).unwrap();
+                } else {
+                    compressor.add_word(word).unwrap();
+                }
+            }
+            compressor.compress().unwrap();
+            path
+        };
+
+        let single = compress(1);
+        let single_hash = xxh64(&std::fs::read(&single).unwrap(), 0);
+        for workers in [3, 8] {
+            let path = compress(workers);
+            assert_eq!(
+                xxh64(&std::fs::read(&path).unwrap(), 0),
+                single_hash,
+                "{} workers",
+                workers
+            );
+        }
+        let decompressor = Decompressor::new(&single).unwrap();
+        assert!(decompressor.stats().patterns > 0);
+        let mut getter = decompressor.make_getter();
+        for word in &words {
+            assert_eq!(&getter.next(Vec::new()).0, word);
+        }
+    }
+
+    #[test]
+    fn test_estimate_projects_compress() {
+        use crate::decompress::Decompressor;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("estimated.seg");
+        let words: Vec<Vec<u8>> = (0..20_000u32)
+            .map(|i| format!("account {} balance {}", i % 97, i % 13).into_bytes())
+            .collect();
+        let mut compressor = Compressor::builder(&path)
+            .fsync(false)
+            .min_pattern_score(4)
+            .sampling_factor(1)
+            .build()
+            .unwrap();
+        for word in &words {
+            compressor.add_word(word).unwrap();
+        }
+
+        let estimate = compressor.estimate(2000).unwrap();
+        assert_eq!(estimate.sampled_words, 2000);
+        assert!(estimate.patterns > 0);
+        assert!(!path.exists());
+        let dictionary = compressor.dictionary().unwrap().serialize();
+
+        compressor.compress().unwrap();
+        // The dictionary built for the estimate is the one compressed with
+        assert_eq!(compressor.dictionary().unwrap().serialize(), dictionary);
+        let actual = std::fs::metadata(&path).unwrap().len();
+        let projected = estimate.projected_output_bytes;
+        assert!(
+            projected.abs_diff(actual) < actual / 10,
+            "projected {} bytes, wrote {}",
+            projected,
+            actual
+        );
+        assert!((estimate.ratio - compressor.ratio()).abs() < compressor.ratio() / 10.0);
+
+        let decompressor = Decompressor::new(&path).unwrap();
+        let mut getter = decompressor.make_getter();
+        for word in &words {
+            assert_eq!(&getter.next(Vec::new()).0, word);
+        }
+    }
+
+    #[test]
+    fn test_size_breakdown() {
+        use crate::decompress::Decompressor;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("sizes.seg");
+        let words: Vec<Vec<u8>> = (0..5_000u32)
+            .map(|i| format!("storage key {} value {}", i % 31, i).into_bytes())
+            .collect();
+        let mut compressor = Compressor::builder(&path)
+            .fsync(false)
+            .min_pattern_score(4)
+            .checksum(true)
+            .build()
+            .unwrap();
+        for word in &words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+
+        let sizes = compressor.size_breakdown();
+        let word_bytes: usize = words.iter().map(Vec::len).sum();
+        assert_eq!(sizes.uncompressed_bytes, word_bytes as u64);
+        assert_eq!(
+            sizes.compressed_bytes,
+            std::fs::metadata(&path).unwrap().len()
+        );
+        assert_eq!(
+            sizes.header_bytes
+                + sizes.pattern_dict_bytes
+                + sizes.pos_dict_bytes
+                + sizes.words_bytes
+                + sizes.footer_bytes,
+            sizes.compressed_bytes
+        );
+        assert!(sizes.footer_bytes > 0 && sizes.words_bytes > 0);
+        let stats = Decompressor::new(&path).unwrap().stats();
+        assert_eq!(sizes.pattern_dict_bytes, stats.pattern_dict_size);
+        assert_eq!(sizes.pos_dict_bytes, stats.pos_dict_size);
+        let ratio = word_bytes as f64 / sizes.compressed_bytes as f64;
+        assert_eq!(compressor.ratio(), ratio);
+        assert!(compressor.ratio() > 1.0);
+        assert!(sizes.dictionary_share() > 0.0 && sizes.dictionary_share() < 100.0);
+    }
+
+    #[test]
+    fn test_prune_dictionary() {
+        use crate::decompress::Decompressor;
+
+        let used = |word: &[u8], uses, code_bits, depth| Pattern {
+            uses,
+            code_bits,
+            depth,
+            ..Pattern::new(word.to_vec(), 10)
+        };
+        // 5 bytes in 3 bits, twice: 74 bits saved, a 7 byte entry
+        assert_eq!(used(b"aaaaa", 2, 3, 3).gain_bits(), 2 * (40 - 3) - 56);
+        let patterns = [
+            used(b"aaaaa", 2, 3, 3),
+            used(b"bbbbbbbb", 1, 12, 12),
+            used(b"ccccc", 0, 0, 0),
+        ];
+        let mut dict = DictionaryBuilder::new(100);
+        for p in &patterns {
+            dict.process_word(p.word.clone(), p.score);
+        }
+        assert_eq!(dict.prune(&patterns), 1);
+        let mut kept = Vec::new();
+        dict.for_each(|_, word| kept.push(word.to_vec()));
+        assert_eq!(kept, [b"aaaaa".to_vec(), b"ccccc".to_vec()]);
+        assert_eq!(dict.prune(&patterns), 0);
+
+        let tmp_dir = TempDir::new().unwrap();
+        // Common records, and short fragments few words share
+        let words: Vec<Vec<u8>> = (0..5000u32)
+            .map(|i| {
+                format!(
+                    "account {} balance {} #{:05}",
+                    i % 97,
+                    i % 13,
+                    (i * 7919) % 2500
+                )
+                .into_bytes()
+            })
+            .collect();
+        let compress = |name: &str, prune: bool| {
+            let path = tmp_dir.path().join(name);
+            let mut compressor = Compressor::builder(&path)
+                .fsync(false)
+                .min_pattern_score(1)
+                .pattern_len_range(5, 8)
+                .sampling_factor(1)
+                .prune_dictionary(prune)
+                .build()
+                .unwrap();
+            for word in &words {
+                compressor.add_word(word).unwrap();
+            }
+            compressor.compress().unwrap();
+            (path, compressor.dictionary().unwrap().len())
+        };
+
+        let (full, full_patterns) = compress("full.seg", false);
+        let (pruned, pruned_patterns) = compress("pruned.seg", true);
+        assert!(pruned_patterns < full_patterns);
+        // Dropped patterns' bytes mostly end up in other patterns, so the
+        // size barely moves either way on an input this small
+        let full_len = std::fs::metadata(&full).unwrap().len();
+        let pruned_len = std::fs::metadata(&pruned).unwrap().len();
+        assert!(pruned_len <= full_len + full_len / 100);
+        let decompressor = Decompressor::new(&pruned).unwrap();
+        let mut getter = decompressor.make_getter();
+        for word in &words {
+            assert_eq!(&getter.next(Vec::new()).0, word);
+        }
+    }
+
+    #[test]
+    fn test_word_sampler() {
+        let words: Vec<Vec<u8>> = (0..1000u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let sample = |strategy| {
+            let mut sampler = WordSampler::new(strategy);
+            for word in &words {
+                sampler.offer(strategy, word);
+            }
+            sampler.take_words()
+        };
+
+        assert_eq!(sample(SamplingStrategy::FirstN { words: 10 }), words[..10]);
+
+        let reservoir = sample(SamplingStrategy::Reservoir { words: 50, seed: 1 });
+        assert_eq!(reservoir.len(), 50);
+        assert!(reservoir.windows(2).all(|w| w[0] < w[1]));
+        // Drawn from the whole input, reproducibly
+        assert!(reservoir.last().unwrap() > &words[500]);
+        let again = sample(SamplingStrategy::Reservoir { words: 50, seed: 1 });
+        assert_eq!(reservoir, again);
+
+        // Each 4-byte word takes 10 bytes of superstring
+        let budget = sample(SamplingStrategy::ByteBudget { bytes: 1000 });
+        assert!(budget.len() * 10 <= 1000);
+        assert!(budget.len() * 10 > 500);
+        // Every stride-th word, halving the sample each time it overflowed
+        let stride = u32::from_be_bytes(budget[1].as_slice().try_into().unwrap()) as usize;
+        assert!(stride.is_power_of_two());
+        assert_eq!(budget.len(), 1000usize.div_ceil(stride));
+        for (i, word) in budget.iter().enumerate() {
+            assert_eq!(word, &words[i * stride]);
+        }
+    }
+
+    #[test]
+    fn test_sampling_strategies_cover_sorted_input() {
+        use crate::decompress::Decompressor;
+
+        // Sorted input whose second half has patterns the first half lacks
+        let words: Vec<Vec<u8>> = (0..400)
+            .map(|i| {
+                let prefix = if i < 200 {
+                    "aaaa-prefix"
+                } else {
+                    "zzzz-suffix"
+                };
+                format!("{} {}", prefix, i % 10).into_bytes()
+            })
+            .collect();
+
+        let tmp_dir = TempDir::new().unwrap();
+        for (i, strategy) in [
+            SamplingStrategy::FirstN { words: 100 },
+            SamplingStrategy::Reservoir {
+                words: 100,
+                seed: 7,
+            },
+            SamplingStrategy::ByteBudget { bytes: 4096 },
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let file_path = tmp_dir.path().join(format!("sampled-{}.seg", i));
+            let mut compressor = Compressor::builder(&file_path)
+                .min_pattern_score(2)
+                .pattern_len_range(4, 64)
+                .sampling_strategy(strategy)
+                .fsync(false)
+                .build()
+                .unwrap();
+            for word in &words {
+                compressor.add_word(word).unwrap();
+            }
+            compressor.compress().unwrap();
+
+            let mut has_suffix_pattern = false;
+            compressor.dictionary().unwrap().for_each(|_, word| {
+                has_suffix_pattern |= word.windows(4).any(|w| w == b"zzzz");
+            });
+            let first_n = matches!(strategy, SamplingStrategy::FirstN { .. });
+            assert_eq!(has_suffix_pattern, !first_n, "{:?}", strategy);
+
+            let decompressor = Decompressor::new(&file_path).unwrap();
+            let mut getter = decompressor.make_getter();
+            for word in &words {
+                assert_eq!(&getter.next(Vec::new()).0, word);
+            }
+            assert!(!getter.has_next());
+        }
+
+        assert!(Compressor::builder(tmp_dir.path().join("bad.seg"))
+            .sampling_strategy(SamplingStrategy::ByteBudget { bytes: 0 })
+            .build()
+            .is_err());
     }
 }
@@ -0,0 +1,180 @@
+use super::{uvarint, DecodeError};
+
+/// Cursor over the word data of a segment, reading bit-packed codes
+///
+/// Codes are packed LSB-first: the low bits of a byte come first and a code
+/// may continue into the next byte. Positions and patterns are read bit by
+/// bit, while word lengths start and uncompressed bytes live on byte
+/// boundaries, so callers `align_to_byte` between the two.
+#[derive(Clone)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    byte_pos: usize,
+    bit_pos: usize, // 0..7 within data[byte_pos]
+}
+
+impl<'a> BitReader<'a> {
+    // Widest code peek_bits can return: it never reads more than 8 bytes and
+    // up to 7 bits of the first one are already consumed
+    pub const MAX_PEEK_BITS: usize = 57;
+
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            byte_pos: 0,
+            bit_pos: 0,
+        }
+    }
+
+    /// The whole underlying buffer, independent of the cursor
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Byte offset of the cursor (the partially consumed byte, if any)
+    pub fn position(&self) -> u64 {
+        self.byte_pos as u64
+    }
+
+    /// Bits already consumed of the byte at [`BitReader::position`]
+    pub fn bit_offset(&self) -> usize {
+        self.bit_pos
+    }
+
+    /// Move the cursor to the start of byte `offset`
+    pub fn seek(&mut self, offset: u64) {
+        self.restore(offset, 0);
+    }
+
+    // Move the cursor to bit `bit_offset` of byte `offset`; an offset past
+    // the address space is past the end of the buffer as well
+    pub(crate) fn restore(&mut self, offset: u64, bit_offset: usize) {
+        self.byte_pos = usize::try_from(offset).unwrap_or(usize::MAX);
+        self.bit_pos = bit_offset;
+    }
+
+    /// Bytes from the cursor's byte to the end of the buffer
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data.get(self.byte_pos..).unwrap_or(&[])
+    }
+
+    /// Read the next `bit_len` bits without consuming them
+    ///
+    /// Bits past the end of the buffer read as zero, as long as the cursor
+    /// itself is inside it; Erigon's encoder relies on that for the last code.
+    pub fn peek_bits(&self, bit_len: usize) -> Result<u64, DecodeError> {
+        debug_assert!(bit_len <= Self::MAX_PEEK_BITS);
+        if self.byte_pos >= self.data.len() {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let needed = (self.bit_pos + bit_len).div_ceil(8).max(1);
+        let end = (self.byte_pos + needed).min(self.data.len());
+        let mut code = 0u64;
+        for (i, &byte) in self.data[self.byte_pos..end].iter().enumerate() {
+            code |= (byte as u64) << (8 * i);
+        }
+        Ok((code >> self.bit_pos) & ((1u64 << bit_len) - 1))
+    }
+
+    /// Advance the cursor by `bit_len` bits
+    pub fn consume_bits(&mut self, bit_len: usize) {
+        self.bit_pos += bit_len;
+        self.byte_pos += self.bit_pos / 8;
+        self.bit_pos %= 8;
+    }
+
+    /// Skip the rest of a partially consumed byte
+    pub fn align_to_byte(&mut self) {
+        if self.bit_pos > 0 {
+            self.byte_pos += 1;
+            self.bit_pos = 0;
+        }
+    }
+
+    /// Read `len` whole bytes starting at the cursor's byte; the cursor must
+    /// be byte-aligned
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        debug_assert_eq!(self.bit_pos, 0, "read_bytes on an unaligned cursor");
+        let bytes = self
+            .byte_pos
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.byte_pos..end))
+            .ok_or(DecodeError::UnexpectedEof)?;
+        self.byte_pos += len;
+        Ok(bytes)
+    }
+
+    /// Read a uvarint starting at the cursor's byte, for positions of
+    /// segments without a position dictionary
+    pub fn read_uvarint(&mut self) -> Result<u64, DecodeError> {
+        let remaining = self.remaining();
+        if remaining.is_empty() {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (value, size) = uvarint(remaining)?;
+        self.byte_pos += size;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_reader_peek_and_consume() {
+        // LSB-first: 0b1011_0101, 0b0000_0011
+        let data = [0xb5, 0x03];
+        let mut reader = BitReader::new(&data);
+        assert_eq!(reader.peek_bits(3).unwrap(), 0b101);
+        // Peeking does not move the cursor
+        assert_eq!(reader.peek_bits(3).unwrap(), 0b101);
+        reader.consume_bits(3);
+        assert_eq!((reader.position(), reader.bit_offset()), (0, 3));
+        // A code spanning the byte boundary
+        assert_eq!(reader.peek_bits(7).unwrap(), 0b11_10110);
+        reader.consume_bits(7);
+        assert_eq!((reader.position(), reader.bit_offset()), (1, 2));
+        // Bits past the end of the buffer read as zero
+        assert_eq!(reader.peek_bits(9).unwrap(), 0);
+        reader.consume_bits(6);
+        assert!(matches!(
+            reader.peek_bits(1),
+            Err(DecodeError::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn test_bit_reader_wide_peek() {
+        let data = 0x0123_4567_89ab_cdefu64.to_le_bytes();
+        let mut reader = BitReader::new(&data);
+        reader.consume_bits(7);
+        assert_eq!(
+            reader.peek_bits(BitReader::MAX_PEEK_BITS).unwrap(),
+            0x0123_4567_89ab_cdef >> 7
+        );
+    }
+
+    #[test]
+    fn test_bit_reader_align_and_read_bytes() {
+        let data = [0xff, b'a', b'b', b'c'];
+        let mut reader = BitReader::new(&data);
+        // Aligning an aligned cursor is a no-op
+        reader.align_to_byte();
+        assert_eq!(reader.position(), 0);
+        reader.consume_bits(1);
+        reader.align_to_byte();
+        assert_eq!((reader.position(), reader.bit_offset()), (1, 0));
+        assert_eq!(reader.read_bytes(2).unwrap(), b"ab");
+        assert_eq!(reader.remaining(), b"c");
+        assert!(matches!(
+            reader.read_bytes(2),
+            Err(DecodeError::UnexpectedEof)
+        ));
+        // A failed read leaves the cursor in place
+        assert_eq!(reader.position(), 3);
+        reader.seek(0);
+        assert_eq!(reader.read_bytes(4).unwrap(), &data);
+        assert!(reader.remaining().is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+// From Go: decompress.go:140-146
+/// Smallest possible segment: the header and both dictionary sizes
+pub(crate) const COMPRESSED_MIN_SIZE: usize = 32;
+
+// Checksum footer: version (1 byte), words and payload checksums (8 bytes
+// each, big-endian like the header), footer length (4 bytes) and the magic.
+// Later versions may add fields before the length.
+const FOOTER_MAGIC: &[u8; 8] = b"SEGCKSUM";
+const FOOTER_VERSION: u8 = 1;
+pub(crate) const FOOTER_LEN: usize = 1 + 8 + 8 + 4 + FOOTER_MAGIC.len();
+
+/// Checksums from the optional segment footer (see [`Cfg::checksum`])
+///
+/// [`Cfg::checksum`]: crate::Cfg::checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentChecksum {
+    /// xxhash64 of the words, each prefixed with its uvarint length
+    pub words: u64,
+    /// xxhash64 of the file up to the footer
+    pub payload: u64,
+}
+
+impl SegmentChecksum {
+    #[cfg(feature = "std")]
+    pub(crate) fn footer(&self) -> [u8; FOOTER_LEN] {
+        let mut footer = [0u8; FOOTER_LEN];
+        footer[0] = FOOTER_VERSION;
+        footer[1..9].copy_from_slice(&self.words.to_be_bytes());
+        footer[9..17].copy_from_slice(&self.payload.to_be_bytes());
+        footer[17..21].copy_from_slice(&(FOOTER_LEN as u32).to_be_bytes());
+        footer[21..].copy_from_slice(FOOTER_MAGIC);
+        footer
+    }
+
+    // The checksums and length of the footer at the end of `data`, if any
+    pub(crate) fn parse_footer(data: &[u8]) -> Option<(Self, usize)> {
+        let len_at = data.len().checked_sub(FOOTER_MAGIC.len() + 4)?;
+        if &data[len_at + 4..] != FOOTER_MAGIC {
+            return None;
+        }
+        let len = u32::from_be_bytes(data[len_at..len_at + 4].try_into().ok()?) as usize;
+        if len < FOOTER_LEN || len > data.len().saturating_sub(COMPRESSED_MIN_SIZE) {
+            return None;
+        }
+        let footer = &data[data.len() - len..];
+        if footer[0] < FOOTER_VERSION {
+            return None;
+        }
+        let checksum = SegmentChecksum {
+            words: u64::from_be_bytes(footer[1..9].try_into().ok()?),
+            payload: u64::from_be_bytes(footer[9..17].try_into().ok()?),
+        };
+        Some((checksum, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_footer_round_trip() {
+        let checksum = SegmentChecksum {
+            words: 0x0102_0304_0506_0708,
+            payload: 9,
+        };
+        let mut data = vec![0u8; COMPRESSED_MIN_SIZE];
+        data.extend_from_slice(&checksum.footer());
+        assert_eq!(
+            data[COMPRESSED_MIN_SIZE + 1..][..8],
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(
+            SegmentChecksum::parse_footer(&data),
+            Some((checksum, FOOTER_LEN))
+        );
+        // A footer that would overlap the header is not one
+        assert_eq!(SegmentChecksum::parse_footer(&data[1..]), None);
+    }
+}
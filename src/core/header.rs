@@ -0,0 +1,65 @@
+//! Fixed-size headers of the on-disk structures, and their byte order
+//!
+//! Erigon writes every count, size and parameter of a header big-endian, as
+//! Go's `binary.BigEndian`. The bit-packed arrays after some headers, the
+//! Elias-Fano and Golomb-Rice words, are little-endian `u64`s instead: Go
+//! casts them to and from `[]uint64` in place, which is little-endian on the
+//! machines Erigon runs on. The header structs ([`SegmentHeader`] here, the
+//! index ones next to their readers) read with [`SafeReader`] and write with
+//! `to_bytes`, so each field's width and order is spelled out once.
+
+use super::{ReadError, SafeReader};
+
+// From Go: compress.go and decompress.go:140-146
+/// The word counts opening a segment, before its dictionaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SegmentHeader {
+    pub word_count: u64,
+    pub empty_word_count: u64,
+}
+
+impl SegmentHeader {
+    pub const LEN: usize = 16;
+
+    pub fn read(reader: &mut SafeReader<'_>) -> Result<Self, ReadError> {
+        Ok(SegmentHeader {
+            word_count: reader.u64_be("word count")?,
+            empty_word_count: reader.u64_be("empty word count")?,
+        })
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[..8].copy_from_slice(&self.word_count.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.empty_word_count.to_be_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_header_round_trip() {
+        let header = SegmentHeader {
+            word_count: 0x0102_0304_0506_0708,
+            empty_word_count: 3,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(bytes[..8], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(bytes[8..], [0, 0, 0, 0, 0, 0, 0, 3]);
+
+        let mut reader = SafeReader::new(&bytes);
+        assert_eq!(SegmentHeader::read(&mut reader).unwrap(), header);
+        assert!(reader.is_empty());
+        assert!(matches!(
+            SegmentHeader::read(&mut SafeReader::new(&bytes[..12])),
+            Err(ReadError::OutOfBounds {
+                what: "empty word count",
+                offset: 8,
+                ..
+            })
+        ));
+    }
+}
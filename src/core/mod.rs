@@ -0,0 +1,92 @@
+//! Segment decoding on `core` and `alloc` alone
+//!
+//! The bit reader, the Huffman tables of the pattern and position
+//! dictionaries, varints and the segment header: everything needed to decode
+//! the words of a segment that is already in memory, without a filesystem.
+//! Built with `default-features = false`, the crate is `no_std + alloc` and
+//! holds just this module, for light clients and embedded verifiers;
+//! [`Decompressor`](crate::Decompressor) decodes with the same code.
+
+mod bits;
+mod footer;
+mod header;
+mod reader;
+mod tables;
+mod varint;
+mod version;
+mod view;
+
+pub use bits::BitReader;
+pub use footer::SegmentChecksum;
+pub use header::SegmentHeader;
+pub use reader::{ReadError, SafeReader};
+pub use varint::uvarint;
+pub use version::{FormatVersion, NameVersion};
+pub use view::{SegmentView, Words};
+
+pub(crate) use footer::COMPRESSED_MIN_SIZE;
+#[cfg(feature = "std")]
+pub(crate) use footer::FOOTER_LEN;
+pub(crate) use tables::{next_pattern, next_pos, PatternTable, PosTable, TableBudget};
+#[cfg(feature = "std")]
+pub(crate) use view::{read_patterns, read_positions};
+
+use thiserror::Error;
+
+// From Go: decompress.go:140-146
+/// Deepest Huffman code a dictionary may hold
+pub const MAX_ALLOWED_DEPTH: u64 = 50;
+
+// From Go: decompress.go:156
+/// Pattern tables of up to this many bits are expanded into a slot per code;
+/// wider ones are condensed, trading lookup speed for memory
+pub const DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD: usize = 9;
+
+/// Heap bytes the pattern and position lookup tables of one segment may
+/// take together; corrupt or hostile dictionaries fail to open past it
+pub const DEFAULT_MAX_TABLE_BYTES: usize = 1 << 30;
+
+/// Failures decoding segment data held in memory
+///
+/// `dict` is "pattern" or "position", naming the dictionary being parsed.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error(transparent)]
+    Read(#[from] ReadError),
+
+    #[error("{dict} depth {depth} at offset {offset} exceeds maximum allowed depth {max}")]
+    DepthOverflow {
+        dict: &'static str,
+        offset: usize,
+        depth: u64,
+        max: u64,
+    },
+
+    #[error("Pattern of {size} bytes at offset {offset} exceeds dictionary size {dict_size}")]
+    PatternOutOfBounds {
+        offset: usize,
+        size: u64,
+        dict_size: usize,
+    },
+
+    #[error("{table} table build ran out of depth")]
+    TableDepthExhausted { table: &'static str },
+
+    #[error("{table} tables exceed the table memory limit of {limit} bytes")]
+    TableTooLarge { table: &'static str, limit: usize },
+
+    #[error("Varint longer than 10 bytes")]
+    VarintOverflow,
+
+    #[error("Varint truncated after {available} bytes")]
+    VarintTruncated { available: usize },
+
+    #[error("Segment of {size} bytes but no words in it")]
+    NoWords { size: u64 },
+
+    #[error("Corrupted compressed data")]
+    CorruptedData,
+
+    #[error("Unexpected end of data")]
+    UnexpectedEof,
+}
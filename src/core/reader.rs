@@ -0,0 +1,202 @@
+use thiserror::Error;
+
+/// A field of an on-disk structure that the bytes left cannot hold
+///
+/// `what` names the field and `offset` is where it starts in the file.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReadError {
+    #[error("{what} at offset {offset} needs {needed} bytes, only {available} left")]
+    OutOfBounds {
+        what: &'static str,
+        offset: u64,
+        needed: u64,
+        available: u64,
+    },
+
+    #[error("{what} at offset {offset} is a varint longer than 10 bytes")]
+    VarintOverflow { what: &'static str, offset: u64 },
+
+    #[error("Unexpected bytes after the {what} at offset {offset}")]
+    TrailingBytes { what: &'static str, offset: u64 },
+}
+
+/// Bounds-checked cursor over the byte-aligned parts of segment and index
+/// files: headers, dictionaries and fixed-size tables
+///
+/// Every read checks what is left first and reports an overrun as a
+/// [`ReadError`] naming the field, so a truncated or corrupted file fails to
+/// open instead of panicking on a slice index. Offsets in errors are relative
+/// to the start of the buffer the outermost reader was created on.
+#[derive(Clone)]
+pub struct SafeReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    base: u64, // offset of `data` in the outermost buffer
+}
+
+impl<'a> SafeReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        SafeReader {
+            data,
+            pos: 0,
+            base: 0,
+        }
+    }
+
+    /// Offset of the cursor in this reader's own buffer
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Offset of the cursor in the outermost buffer
+    pub fn offset(&self) -> u64 {
+        self.base + self.pos as u64
+    }
+
+    /// Number of bytes left after the cursor
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// The bytes left after the cursor, without consuming them
+    pub fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    fn out_of_bounds(&self, what: &'static str, needed: u64) -> ReadError {
+        ReadError::OutOfBounds {
+            what,
+            offset: self.offset(),
+            needed,
+            available: self.remaining() as u64,
+        }
+    }
+
+    /// Read the next `len` bytes
+    pub fn bytes(&mut self, len: u64, what: &'static str) -> Result<&'a [u8], ReadError> {
+        let Some(len) = usize::try_from(len)
+            .ok()
+            .filter(|&len| len <= self.remaining())
+        else {
+            return Err(self.out_of_bounds(what, len));
+        };
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Skip the next `len` bytes
+    pub fn skip(&mut self, len: u64, what: &'static str) -> Result<(), ReadError> {
+        self.bytes(len, what).map(|_| ())
+    }
+
+    /// Split off the next `len` bytes as a reader of their own, e.g. for a
+    /// length-prefixed dictionary
+    pub fn sub(&mut self, len: u64, what: &'static str) -> Result<SafeReader<'a>, ReadError> {
+        let base = self.offset();
+        let data = self.bytes(len, what)?;
+        Ok(SafeReader { data, pos: 0, base })
+    }
+
+    fn array<const N: usize>(&mut self, what: &'static str) -> Result<[u8; N], ReadError> {
+        let bytes = self.bytes(N as u64, what)?;
+        Ok(bytes.try_into().expect("bytes returns exactly N bytes"))
+    }
+
+    pub fn u8(&mut self, what: &'static str) -> Result<u8, ReadError> {
+        Ok(self.array::<1>(what)?[0])
+    }
+
+    pub fn u16_be(&mut self, what: &'static str) -> Result<u16, ReadError> {
+        self.array(what).map(u16::from_be_bytes)
+    }
+
+    pub fn u32_be(&mut self, what: &'static str) -> Result<u32, ReadError> {
+        self.array(what).map(u32::from_be_bytes)
+    }
+
+    pub fn u64_be(&mut self, what: &'static str) -> Result<u64, ReadError> {
+        self.array(what).map(u64::from_be_bytes)
+    }
+
+    /// Read an unsigned LEB128 varint, like Go's `binary.Uvarint`
+    pub fn uvarint(&mut self, what: &'static str) -> Result<u64, ReadError> {
+        let mut value = 0u64;
+        for (i, &byte) in self.rest().iter().enumerate() {
+            if i == 10 {
+                return Err(ReadError::VarintOverflow {
+                    what,
+                    offset: self.offset(),
+                });
+            }
+            value |= ((byte & 0x7F) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                self.pos += i + 1;
+                return Ok(value);
+            }
+        }
+        Err(self.out_of_bounds(what, self.remaining() as u64 + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_reader() {
+        let data = [0x00, 0x00, 0x01, 0x02, 0xac, 0x02, 0x80, 0x80];
+        let mut reader = SafeReader::new(&data);
+        assert_eq!(reader.u16_be("a").unwrap(), 0);
+        assert_eq!(reader.u16_be("b").unwrap(), 0x0102);
+        assert_eq!(reader.uvarint("c").unwrap(), 300);
+        assert_eq!(reader.remaining(), 2);
+
+        // Failed reads leave the cursor in place
+        assert_eq!(
+            reader.u32_be("d"),
+            Err(ReadError::OutOfBounds {
+                what: "d",
+                offset: 6,
+                needed: 4,
+                available: 2,
+            })
+        );
+        assert_eq!(
+            reader.uvarint("e"),
+            Err(ReadError::OutOfBounds {
+                what: "e",
+                offset: 6,
+                needed: 3,
+                available: 2,
+            })
+        );
+        assert!(reader.skip(u64::MAX, "f").is_err());
+        assert_eq!(reader.rest(), &[0x80, 0x80]);
+
+        // Offsets of a sub-reader are located in the outer buffer
+        let mut reader = SafeReader::new(&data);
+        reader.skip(2, "g").unwrap();
+        let mut sub = reader.sub(2, "h").unwrap();
+        assert_eq!(reader.offset(), 4);
+        assert_eq!(sub.u8("i").unwrap(), 1);
+        assert_eq!((sub.position(), sub.offset()), (1, 3));
+        assert!(matches!(
+            sub.u16_be("j"),
+            Err(ReadError::OutOfBounds { offset: 3, .. })
+        ));
+
+        let long = [0xff; 11];
+        assert_eq!(
+            SafeReader::new(&long).uvarint("k"),
+            Err(ReadError::VarintOverflow {
+                what: "k",
+                offset: 0,
+            })
+        );
+    }
+}
@@ -0,0 +1,716 @@
+use super::{BitReader, DecodeError};
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// Heap bytes the lookup tables of one segment may still allocate; builds
+/// fail with [`DecodeError::TableTooLarge`] before going over
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TableBudget {
+    remaining: usize,
+    limit: usize,
+}
+
+impl TableBudget {
+    pub(crate) fn new(limit: usize) -> Self {
+        TableBudget {
+            remaining: limit,
+            limit,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    fn charge(&mut self, table: &'static str, bytes: usize) -> Result<(), DecodeError> {
+        self.remaining = self
+            .remaining
+            .checked_sub(bytes)
+            .ok_or(DecodeError::TableTooLarge {
+                table,
+                limit: self.limit,
+            })?;
+        Ok(())
+    }
+}
+
+// From Go: decompress.go:39
+type Word = Vec<u8>; // plain text word associated with code from dictionary
+
+// From Go: decompress.go:41
+#[derive(Debug)]
+pub(crate) struct Codeword {
+    pattern: Word,                  // Pattern corresponding to entries
+    ptr: Option<Box<PatternTable>>, // pointer to deeper level tables
+    code: u32,                      // code associated with that word
+    len: u8,                        // Number of bits in the codes
+}
+
+// From Go: decompress.go:48
+#[derive(Debug)]
+pub(crate) struct PatternTable {
+    patterns: Vec<Option<Codeword>>,
+    bit_len: usize, // Number of bits to lookup in the table
+    // Tables wider than this many bits are condensed: each codeword is
+    // stored once, sorted by (len, code), instead of in every slot it covers
+    condense_threshold: usize,
+    // Condensed tables only: bit i is set if codewords of length i are present
+    condensed_lens: u16,
+}
+
+impl PatternTable {
+    // From Go: decompress.go:53
+    fn new(
+        bit_len: usize,
+        condense_threshold: usize,
+        budget: &mut TableBudget,
+    ) -> Result<Self, DecodeError> {
+        let size = if bit_len <= condense_threshold {
+            1 << bit_len
+        } else {
+            0 // Will use vec for sparse storage
+        };
+        budget.charge(
+            "pattern",
+            size_of::<Self>() + size * size_of::<Option<Codeword>>(),
+        )?;
+
+        Ok(PatternTable {
+            patterns: (0..size).map(|_| None).collect(),
+            bit_len,
+            condense_threshold,
+            condensed_lens: 0,
+        })
+    }
+
+    fn is_condensed(&self) -> bool {
+        self.bit_len > self.condense_threshold
+    }
+
+    // From Go: decompress.go:63
+    fn insert_word(&mut self, cw: Codeword, budget: &mut TableBudget) -> Result<(), DecodeError> {
+        if !self.is_condensed() {
+            if cw.len == 0 {
+                // Pointer to a deeper table occupies exactly one slot
+                let code = cw.code as usize;
+                self.patterns[code] = Some(cw);
+                return Ok(());
+            }
+            let code_step = 1u32 << cw.len;
+            let code_from = cw.code;
+            let code_to = if self.bit_len != cw.len as usize && cw.len > 0 {
+                code_from | (1u32 << self.bit_len)
+            } else {
+                cw.code + code_step
+            };
+
+            let mut c = code_from;
+            while c < code_to {
+                // Every slot holds its own copy of the pattern
+                budget.charge("pattern", cw.pattern.len())?;
+                let stored_cw = Codeword {
+                    pattern: cw.pattern.clone(),
+                    ptr: None, // only len == 0 codewords carry a table pointer
+                    code: cw.code,
+                    len: cw.len,
+                };
+                self.patterns[c as usize] = Some(stored_cw);
+                c += code_step;
+            }
+        } else {
+            // Keep condensed tables sorted for condensed_table_search
+            budget.charge("pattern", size_of::<Option<Codeword>>() + cw.pattern.len())?;
+            let key = (cw.len, cw.code);
+            let at = self
+                .patterns
+                .partition_point(|p| p.as_ref().map(|p| (p.len, p.code)) < Some(key));
+            self.condensed_lens |= 1 << cw.len;
+            self.patterns.insert(at, Some(cw));
+        }
+        Ok(())
+    }
+
+    // From Go: decompress.go:80
+    // Go scans condensed tables linearly, matching a codeword of length `len`
+    // when the code is `cw.code` plus a multiple of 2^len (check_distance).
+    // Codes are LSB-first, so that is a match on the low `len` bits; with
+    // entries sorted by (len, code) it takes one binary search per length.
+    // Table pointers (len 0) only match their exact code.
+    fn condensed_table_search(&self, code: u32) -> Option<&Codeword> {
+        if !self.is_condensed() {
+            return self.patterns.get(code as usize)?.as_ref();
+        }
+        let mut lens = self.condensed_lens;
+        while lens != 0 {
+            let len = lens.trailing_zeros() as u8;
+            lens &= lens - 1;
+            let masked = if len == 0 {
+                code
+            } else {
+                code & ((1u32 << len) - 1)
+            };
+            let found = self
+                .patterns
+                .binary_search_by_key(&(len, masked), |p| {
+                    p.as_ref().map_or((0, 0), |p| (p.len, p.code))
+                })
+                .ok();
+            if let Some(idx) = found {
+                return self.patterns[idx].as_ref();
+            }
+        }
+        None
+    }
+
+    /// Build the lookup tables of a pattern dictionary from its code depths,
+    /// both in dictionary order
+    pub(crate) fn build(
+        depths: &[u64],
+        patterns: &[Vec<u8>],
+        condense_threshold: usize,
+        budget: &mut TableBudget,
+    ) -> Result<Self, DecodeError> {
+        let max_depth = depths.iter().copied().max().unwrap_or(0);
+        let mut table = PatternTable::new(max_depth.min(9) as usize, condense_threshold, budget)?;
+        build_condensed_pattern_table(depths, patterns, &mut table, 0, 0, 0, max_depth, budget)?;
+        Ok(table)
+    }
+
+    // From Go: decompress.go:584 nextPattern
+    /// Decode the pattern code at the reader's cursor
+    pub(crate) fn decode(&self, reader: &mut BitReader<'_>) -> Result<&[u8], DecodeError> {
+        if self.bit_len == 0 {
+            return Ok(self
+                .patterns
+                .first()
+                .and_then(|cw| cw.as_ref())
+                .map(|cw| cw.pattern.as_slice())
+                .unwrap_or(&[]));
+        }
+
+        let mut table = self;
+        loop {
+            let code = reader.peek_bits(table.bit_len)? as u32;
+            let cw = table
+                .condensed_table_search(code)
+                .ok_or(DecodeError::CorruptedData)?;
+            if cw.len == 0 {
+                table = cw.ptr.as_ref().ok_or(DecodeError::CorruptedData)?;
+                reader.consume_bits(9);
+            } else {
+                reader.consume_bits(cw.len as usize);
+                return Ok(&cw.pattern);
+            }
+        }
+    }
+
+    // Number of slots in this table and the tables below it
+    #[cfg(feature = "std")]
+    pub(crate) fn entries(&self) -> usize {
+        self.patterns.len()
+            + self
+                .patterns
+                .iter()
+                .flatten()
+                .filter_map(|cw| cw.ptr.as_ref())
+                .map(|ptr| ptr.entries())
+                .sum::<usize>()
+    }
+
+    // Heap bytes held by this table and the tables below it
+    #[cfg(feature = "std")]
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.patterns.capacity() * size_of::<Option<Codeword>>()
+            + self
+                .patterns
+                .iter()
+                .flatten()
+                .map(|cw| {
+                    cw.pattern.capacity()
+                        + cw.ptr
+                            .as_ref()
+                            .map_or(0, |ptr| size_of::<Self>() + ptr.heap_bytes())
+                })
+                .sum::<usize>()
+    }
+}
+
+// From Go: decompress.go:99
+#[derive(Debug)]
+pub(crate) struct PosTable {
+    pos: Vec<u64>,
+    lens: Vec<u8>,
+    ptrs: Vec<Option<Box<PosTable>>>,
+    bit_len: usize,
+}
+
+impl PosTable {
+    fn new(bit_len: usize, budget: &mut TableBudget) -> Result<Self, DecodeError> {
+        let size = 1 << bit_len;
+        budget.charge("position", size_of::<Self>() + size * Self::SLOT_BYTES)?;
+        Ok(PosTable {
+            pos: vec![0; size],
+            lens: vec![0; size],
+            ptrs: (0..size).map(|_| None).collect(),
+            bit_len,
+        })
+    }
+
+    const SLOT_BYTES: usize = size_of::<u64>() + size_of::<u8>() + size_of::<Option<Box<Self>>>();
+
+    /// Build the lookup tables of a position dictionary from its code
+    /// depths, both in dictionary order
+    pub(crate) fn build(
+        depths: &[u64],
+        positions: &[u64],
+        budget: &mut TableBudget,
+    ) -> Result<Self, DecodeError> {
+        let max_depth = depths.iter().copied().max().unwrap_or(0);
+        let mut table = PosTable::new(max_depth.min(9) as usize, budget)?;
+        build_pos_table_recursive(depths, positions, &mut table, 0, 0, 0, max_depth, budget)?;
+        Ok(table)
+    }
+
+    // Heap bytes held by this table and the tables below it
+    #[cfg(feature = "std")]
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.pos.capacity() * size_of::<u64>()
+            + self.lens.capacity()
+            + self.ptrs.capacity() * size_of::<Option<Box<Self>>>()
+            + self
+                .ptrs
+                .iter()
+                .flatten()
+                .map(|ptr| size_of::<Self>() + ptr.heap_bytes())
+                .sum::<usize>()
+    }
+
+    // From Go: decompress.go:550 nextPos
+    /// Decode the position code at the reader's cursor
+    pub(crate) fn decode(&self, reader: &mut BitReader<'_>) -> Result<u64, DecodeError> {
+        if self.bit_len == 0 {
+            // Empty position table - the position is a varint in the data
+            if self.pos.is_empty() || (self.pos.len() == 1 && self.pos[0] == 0) {
+                return reader.read_uvarint();
+            }
+            return Ok(self.pos[0]);
+        }
+
+        let mut table = self;
+        loop {
+            let code = reader.peek_bits(table.bit_len)? as u32;
+            let (Some(&len), Some(&pos)) =
+                (table.lens.get(code as usize), table.pos.get(code as usize))
+            else {
+                return Err(DecodeError::CorruptedData);
+            };
+            if len == 0 {
+                // Navigate to deeper table
+                let Some(Some(next)) = table.ptrs.get(code as usize) else {
+                    return Err(DecodeError::CorruptedData);
+                };
+                table = next;
+                reader.consume_bits(9);
+            } else {
+                reader.consume_bits(len as usize);
+                return Ok(pos);
+            }
+        }
+    }
+}
+
+// Recursive pattern table builder (matching Go's buildCondensedPatternTable exactly)
+#[allow(clippy::too_many_arguments)]
+fn build_condensed_pattern_table(
+    depths: &[u64],
+    patterns: &[Vec<u8>],
+    table: &mut PatternTable,
+    code: u32,
+    bits: usize,
+    depth: u64,
+    max_depth: u64,
+    budget: &mut TableBudget,
+) -> Result<usize, DecodeError> {
+    if depths.is_empty() {
+        return Ok(0);
+    }
+
+    if depth == depths[0] {
+        let cw = Codeword {
+            pattern: patterns[0].clone(),
+            ptr: None,
+            code,
+            len: bits as u8,
+        };
+        table.insert_word(cw, budget)?;
+        return Ok(1);
+    }
+
+    if bits == 9 {
+        let bit_len = if max_depth > 9 { 9 } else { max_depth as usize };
+        let mut ptr = PatternTable::new(bit_len, table.condense_threshold, budget)?;
+        let consumed = build_condensed_pattern_table(
+            depths, patterns, &mut ptr, 0, 0, depth, max_depth, budget,
+        )?;
+
+        let cw = Codeword {
+            pattern: Vec::new(),
+            ptr: Some(Box::new(ptr)),
+            code,
+            len: 0,
+        };
+        table.insert_word(cw, budget)?;
+        return Ok(consumed);
+    }
+
+    if max_depth == 0 {
+        return Err(DecodeError::TableDepthExhausted { table: "pattern" });
+    }
+
+    // Recursive split like Go
+    let b0 = build_condensed_pattern_table(
+        depths,
+        patterns,
+        table,
+        code,
+        bits + 1,
+        depth + 1,
+        max_depth - 1,
+        budget,
+    )?;
+    let b1 = build_condensed_pattern_table(
+        &depths[b0..],
+        &patterns[b0..],
+        table,
+        (1u32 << bits) | code,
+        bits + 1,
+        depth + 1,
+        max_depth - 1,
+        budget,
+    )?;
+    Ok(b0 + b1)
+}
+
+// Recursive position table builder (matching Go's buildPosTable exactly)
+#[allow(clippy::too_many_arguments)]
+fn build_pos_table_recursive(
+    depths: &[u64],
+    positions: &[u64],
+    table: &mut PosTable,
+    code: u32,
+    bits: u8,
+    depth: u64,
+    max_depth: u64,
+    budget: &mut TableBudget,
+) -> Result<usize, DecodeError> {
+    if depths.is_empty() {
+        return Ok(0);
+    }
+
+    if depth == depths[0] {
+        let pos = positions[0];
+        if table.bit_len == bits as usize {
+            table.pos[code as usize] = pos;
+            table.lens[code as usize] = bits;
+        } else {
+            let code_step = 1u32 << bits;
+            let code_from = code;
+            let code_to = code | (1u32 << table.bit_len);
+            let mut c = code_from;
+            while c < code_to {
+                table.pos[c as usize] = pos;
+                table.lens[c as usize] = bits;
+                c += code_step;
+            }
+        }
+        return Ok(1);
+    }
+
+    // Handle bits == 9 case (matching Go's logic)
+    if bits == 9 {
+        let bit_len = if max_depth > 9 { 9 } else { max_depth as usize };
+        let mut new_table = PosTable::new(bit_len, budget)?;
+        table.pos[code as usize] = 0;
+        table.lens[code as usize] = 0;
+        let consumed = build_pos_table_recursive(
+            depths,
+            positions,
+            &mut new_table,
+            0,
+            0,
+            depth,
+            max_depth,
+            budget,
+        )?;
+        table.ptrs[code as usize] = Some(Box::new(new_table));
+        return Ok(consumed);
+    }
+
+    // Check for max_depth to prevent underflow (matching Go's check)
+    if max_depth == 0 {
+        return Err(DecodeError::TableDepthExhausted { table: "position" });
+    }
+
+    // Recursive split like Go
+    let b0 = build_pos_table_recursive(
+        depths,
+        positions,
+        table,
+        code,
+        bits + 1,
+        depth + 1,
+        max_depth - 1,
+        budget,
+    )?;
+    let b1 = build_pos_table_recursive(
+        &depths[b0..],
+        &positions[b0..],
+        table,
+        (1u32 << bits) | code,
+        bits + 1,
+        depth + 1,
+        max_depth - 1,
+        budget,
+    )?;
+    Ok(b0 + b1)
+}
+
+/// Decode the next position, aligning to a byte first if `clean`; without a
+/// table positions are varints
+pub(crate) fn next_pos(
+    table: Option<&PosTable>,
+    reader: &mut BitReader<'_>,
+    clean: bool,
+) -> Result<u64, DecodeError> {
+    if clean {
+        reader.align_to_byte();
+    }
+    match table {
+        Some(table) => table.decode(reader),
+        None => reader.read_uvarint(),
+    }
+}
+
+/// Decode the next pattern; a segment without patterns only has empty ones
+pub(crate) fn next_pattern<'t>(
+    table: Option<&'t PatternTable>,
+    reader: &mut BitReader<'_>,
+) -> Result<&'t [u8], DecodeError> {
+    match table {
+        Some(table) => table.decode(reader),
+        None => Ok(&[]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD;
+    use alloc::format;
+
+    // From Go: decompress.go:615-636
+    // Go's condensed-table matching rule, kept as the reference the indexed
+    // lookup in PatternTable::condensed_table_search is tested against
+    fn check_distance(power: usize, d: usize) -> bool {
+        let distances = build_condensed_word_distances();
+        distances.get(power).is_some_and(|d2| d2.contains(&d))
+    }
+
+    fn build_condensed_word_distances() -> Vec<Vec<usize>> {
+        let mut dist2 = vec![Vec::new(); 10];
+        for (i, slot) in dist2.iter_mut().enumerate().skip(1) {
+            let mut dl = Vec::new();
+            let mut j = 1 << i;
+            while j < 512 {
+                dl.push(j);
+                j += 1 << i;
+            }
+            *slot = dl;
+        }
+        dist2
+    }
+
+    #[test]
+    fn test_pattern_table() {
+        let budget = &mut TableBudget::unlimited();
+        let mut table =
+            PatternTable::new(4, DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD, budget).unwrap();
+        let cw = Codeword {
+            pattern: b"test".to_vec(),
+            ptr: None,
+            code: 5,
+            len: 3,
+        };
+        table.insert_word(cw, budget).unwrap();
+
+        assert!(table.condensed_table_search(5).is_some());
+    }
+
+    #[test]
+    fn test_condensed_table_search_matches_dense_table() {
+        // A complete prefix code: one codeword per depth 1..=8, then four of
+        // depth 10 that live in tables below a 9-bit pointer
+        let depths: Vec<u64> = (1..=8).chain([10, 10, 10, 10]).collect();
+        let patterns: Vec<Vec<u8>> = (0..depths.len())
+            .map(|i| format!("p{}", i).into_bytes())
+            .collect();
+        let build = |threshold| {
+            let budget = &mut TableBudget::unlimited();
+            let mut table = PatternTable::new(9, threshold, budget).unwrap();
+            build_condensed_pattern_table(&depths, &patterns, &mut table, 0, 0, 0, 10, budget)
+                .unwrap();
+            table
+        };
+        let dense = build(DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD);
+        let condensed = build(3);
+        assert!(condensed.entries() < dense.entries());
+
+        let key = |cw: &Codeword| (cw.code, cw.len, cw.pattern.clone());
+        for code in 0..512u32 {
+            let expected = dense.condensed_table_search(code).map(key);
+            assert!(expected.is_some(), "code {} not covered", code);
+            assert_eq!(
+                condensed.condensed_table_search(code).map(key),
+                expected,
+                "code {}",
+                code
+            );
+
+            // Go's linear scan finds the same, single codeword
+            let linear: Vec<_> = condensed
+                .patterns
+                .iter()
+                .flatten()
+                .filter(|cw| {
+                    let d = code.wrapping_sub(cw.code);
+                    cw.code == code || (d & 1 == 0 && check_distance(cw.len as usize, d as usize))
+                })
+                .map(key)
+                .collect();
+            assert_eq!(linear, expected.into_iter().collect::<Vec<_>>());
+        }
+    }
+
+    // A complete prefix code deeper than 16 bits: one codeword per depth
+    // 1..40, then two of depth 40, so decoding the deepest ones hops
+    // through four 9-bit tables
+    fn deep_code() -> (Vec<u64>, Vec<(u64, usize)>) {
+        const DEEPEST: u64 = 40;
+        let depths: Vec<u64> = (1..DEEPEST).chain([DEEPEST, DEEPEST]).collect();
+        // The builders give the left branch bit 0: codeword i is i ones and
+        // a zero, the last one all ones
+        let codes = depths
+            .iter()
+            .enumerate()
+            .map(|(i, &depth)| {
+                let ones = if i == depths.len() - 1 {
+                    depth
+                } else {
+                    i as u64
+                };
+                ((1u64 << ones) - 1, depth as usize)
+            })
+            .collect();
+        (depths, codes)
+    }
+
+    // Pack codes LSB-first, as the encoder does
+    fn pack(codes: &[(u64, usize)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut bit = 0;
+        for &(code, len) in codes {
+            for i in 0..len {
+                if bit / 8 == data.len() {
+                    data.push(0);
+                }
+                data[bit / 8] |= (((code >> i) & 1) as u8) << (bit % 8);
+                bit += 1;
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_deep_codes_through_chained_tables() {
+        let (depths, codes) = deep_code();
+        // Every codeword twice, in reverse order the second time
+        let stream: Vec<usize> = (0..codes.len()).chain((0..codes.len()).rev()).collect();
+        let data = pack(&stream.iter().map(|&i| codes[i]).collect::<Vec<_>>());
+
+        let patterns: Vec<Vec<u8>> = (0..depths.len())
+            .map(|i| format!("p{}", i).into_bytes())
+            .collect();
+        for threshold in [DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD, 3] {
+            let table =
+                PatternTable::build(&depths, &patterns, threshold, &mut TableBudget::unlimited())
+                    .unwrap();
+            let mut reader = BitReader::new(&data);
+            for &i in &stream {
+                assert_eq!(table.decode(&mut reader).unwrap(), patterns[i].as_slice());
+            }
+        }
+
+        let positions: Vec<u64> = (0..depths.len() as u64).map(|i| i * 1000 + 1).collect();
+        let table = PosTable::build(&depths, &positions, &mut TableBudget::unlimited()).unwrap();
+        let mut reader = BitReader::new(&data);
+        for &i in &stream {
+            assert_eq!(table.decode(&mut reader).unwrap(), positions[i]);
+        }
+    }
+
+    #[test]
+    fn test_table_budget() {
+        let (depths, _) = deep_code();
+        let patterns: Vec<Vec<u8>> = (0..depths.len()).map(|_| vec![7; 32]).collect();
+        let mut budget = TableBudget::unlimited();
+        let table = PatternTable::build(
+            &depths,
+            &patterns,
+            DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD,
+            &mut budget,
+        )
+        .unwrap();
+        let charged = usize::MAX - budget.remaining;
+        assert_eq!(charged, size_of::<PatternTable>() + table.heap_bytes());
+
+        // Short codes are copied into every slot they cover, which the
+        // budget counts before allocating them
+        let limit = charged - 1;
+        let err = PatternTable::build(
+            &depths,
+            &patterns,
+            DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD,
+            &mut TableBudget::new(limit),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            DecodeError::TableTooLarge {
+                table: "pattern",
+                limit
+            }
+        );
+        // Condensed tables store each pattern once
+        PatternTable::build(&depths, &patterns, 3, &mut TableBudget::new(limit)).unwrap();
+
+        let positions: Vec<u64> = (0..depths.len() as u64).collect();
+        let err = PosTable::build(&depths, &positions, &mut TableBudget::new(1024)).unwrap_err();
+        assert_eq!(
+            err,
+            DecodeError::TableTooLarge {
+                table: "position",
+                limit: 1024
+            }
+        );
+    }
+
+    #[test]
+    fn test_condensed_distances() {
+        assert!(check_distance(3, 8)); // 1 << 3 = 8
+        assert!(check_distance(4, 16)); // 1 << 4 = 16
+        assert!(!check_distance(3, 7)); // Not a valid distance
+    }
+}
@@ -0,0 +1,38 @@
+use super::DecodeError;
+
+// From Go: binary.Uvarint
+/// Decode the unsigned LEB128 varint at the start of `data`, returning the
+/// value and the number of bytes it took
+pub fn uvarint(data: &[u8]) -> Result<(u64, usize), DecodeError> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().enumerate() {
+        if i == 10 {
+            return Err(DecodeError::VarintOverflow);
+        }
+        value |= ((byte & 0x7F) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(DecodeError::VarintTruncated {
+        available: data.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_decode() {
+        let data = vec![0x96, 0x01]; // 150 in varint
+        let (value, size) = uvarint(&data).unwrap();
+        assert_eq!(value, 150);
+        assert_eq!(size, 2);
+        assert_eq!(
+            uvarint(&[0x96]),
+            Err(DecodeError::VarintTruncated { available: 1 })
+        );
+        assert_eq!(uvarint(&[0xff; 11]), Err(DecodeError::VarintOverflow));
+    }
+}
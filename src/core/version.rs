@@ -0,0 +1,110 @@
+//! Versions of Erigon files: the prefix of their names and the layout of
+//! their contents
+//!
+//! Erigon prefixes its file names with a version: `v1-…` for the original
+//! files, then `v1.1-…` and `v2.0-…` as details changed. Segments and
+//! indexes are decoded from what the files themselves hold, so the prefix
+//! is informational when a file is opened by path; directory scans use it
+//! to leave out files of versions this crate does not know. The layout a
+//! file was decoded with is its [`FormatVersion`].
+
+use core::fmt;
+
+/// Version prefix of an Erigon file name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NameVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl NameVersion {
+    pub const V1_0: NameVersion = NameVersion::new(1, 0);
+    pub const V1_1: NameVersion = NameVersion::new(1, 1);
+    pub const V2_0: NameVersion = NameVersion::new(2, 0);
+
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    /// Version of a file named like `v1-000000-000500-headers.seg` or
+    /// `v1.1-accounts.0-64.kv`; `None` without a version prefix
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let (version, _) = name.strip_prefix('v')?.split_once('-')?;
+        let (major, minor) = version.split_once('.').unwrap_or((version, "0"));
+        Some(Self::new(major.parse().ok()?, minor.parse().ok()?))
+    }
+
+    /// Whether Erigon has named files with this version so far: 1.x and
+    /// 2.x, which share the segment and index layout
+    pub fn is_known(&self) -> bool {
+        (1..=2).contains(&self.major)
+    }
+}
+
+impl fmt::Display for NameVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}", self.major, self.minor)
+    }
+}
+
+/// Layout of a segment or index, as read from the file's header and
+/// features byte rather than its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FormatVersion {
+    /// The original layout: every segment, and indexes without an
+    /// existence filter
+    V1_0,
+    /// Indexes built with `LESS_FALSE_POSITIVES`, whose existence filter
+    /// follows the enum offsets; Erigon names them `v1.1` and later
+    V1_1,
+}
+
+impl FormatVersion {
+    /// The file name version that introduced this layout
+    pub const fn name_version(self) -> NameVersion {
+        match self {
+            FormatVersion::V1_0 => NameVersion::V1_0,
+            FormatVersion::V1_1 => NameVersion::V1_1,
+        }
+    }
+}
+
+impl fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.name_version().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_file_name() {
+        let version = |name| NameVersion::from_file_name(name);
+        assert_eq!(
+            version("v1-000000-000500-headers.seg"),
+            Some(NameVersion::V1_0)
+        );
+        assert_eq!(version("v1.1-accounts.0-64.kv"), Some(NameVersion::V1_1));
+        assert_eq!(
+            version("v2.0-000000-000500-bodies.idx"),
+            Some(NameVersion::V2_0)
+        );
+        assert_eq!(version("compressed"), None);
+        assert_eq!(version("vx-000000-000500-headers.seg"), None);
+        assert_eq!(version("v1.x-accounts.0-64.kv"), None);
+
+        assert!(NameVersion::V1_0 < NameVersion::V1_1);
+        assert!(NameVersion::V2_0.is_known());
+        assert!(!NameVersion::new(3, 0).is_known());
+        assert_eq!(NameVersion::V1_1.to_string(), "v1.1");
+    }
+
+    #[test]
+    fn test_format_version() {
+        assert!(FormatVersion::V1_0 < FormatVersion::V1_1);
+        assert_eq!(FormatVersion::V1_1.name_version(), NameVersion::V1_1);
+        assert_eq!(FormatVersion::V1_0.to_string(), "v1.0");
+    }
+}
@@ -0,0 +1,353 @@
+use super::{
+    next_pattern, next_pos, BitReader, DecodeError, PatternTable, PosTable, SafeReader,
+    SegmentChecksum, SegmentHeader, TableBudget, COMPRESSED_MIN_SIZE,
+    DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD, DEFAULT_MAX_TABLE_BYTES, MAX_ALLOWED_DEPTH,
+};
+use alloc::vec::Vec;
+
+// From Go: decompress.go:243-260
+/// Code depths and patterns of a serialized pattern dictionary, with no
+/// code deeper than `max_depth`
+pub(crate) fn read_patterns(
+    mut dict: SafeReader<'_>,
+    max_depth: u64,
+) -> Result<(Vec<u64>, Vec<Vec<u8>>), DecodeError> {
+    let dict_size = dict.remaining();
+    let mut depths = Vec::new();
+    let mut patterns = Vec::new();
+    while !dict.is_empty() {
+        let offset = dict.position();
+        let depth = dict.uvarint("pattern depth")?;
+        if depth > max_depth {
+            return Err(DecodeError::DepthOverflow {
+                dict: "pattern",
+                offset,
+                depth,
+                max: max_depth,
+            });
+        }
+        let size = dict.uvarint("pattern size")?;
+        if size > dict.remaining() as u64 {
+            return Err(DecodeError::PatternOutOfBounds {
+                offset: dict.position(),
+                size,
+                dict_size,
+            });
+        }
+        depths.push(depth);
+        patterns.push(dict.bytes(size, "pattern")?.to_vec());
+    }
+    Ok((depths, patterns))
+}
+
+// From Go: decompress.go:299-312
+/// Code depths and positions of a serialized position dictionary, with no
+/// code deeper than `max_depth`
+pub(crate) fn read_positions(
+    mut dict: SafeReader<'_>,
+    max_depth: u64,
+) -> Result<(Vec<u64>, Vec<u64>), DecodeError> {
+    let mut depths = Vec::new();
+    let mut positions = Vec::new();
+    while !dict.is_empty() {
+        let offset = dict.position();
+        let depth = dict.uvarint("position depth")?;
+        if depth > max_depth {
+            return Err(DecodeError::DepthOverflow {
+                dict: "position",
+                offset,
+                depth,
+                max: max_depth,
+            });
+        }
+        depths.push(depth);
+        positions.push(dict.uvarint("position")?);
+    }
+    Ok((depths, positions))
+}
+
+/// A segment held in memory, decoded without a filesystem
+///
+/// The header and both dictionaries are parsed up front; words are then
+/// decoded on demand from the borrowed buffer by [`Words`].
+pub struct SegmentView<'a> {
+    words: &'a [u8],
+    word_count: u64,
+    empty_word_count: u64,
+    patterns: Option<PatternTable>,
+    positions: PosTable,
+    // Longest pattern in the dictionary, bounds the length of a word
+    max_pattern_len: usize,
+    checksum: Option<SegmentChecksum>,
+}
+
+impl<'a> SegmentView<'a> {
+    /// Parse the segment file contents in `data`
+    pub fn new(data: &'a [u8]) -> Result<Self, DecodeError> {
+        let (checksum, words_end) = match SegmentChecksum::parse_footer(data) {
+            Some((checksum, footer_len)) => (Some(checksum), data.len() - footer_len),
+            None => (None, data.len()),
+        };
+        let mut reader = SafeReader::new(&data[..words_end]);
+        let SegmentHeader {
+            word_count,
+            empty_word_count,
+        } = SegmentHeader::read(&mut reader)?;
+
+        let size = reader.u64_be("pattern dictionary size")?;
+        let (depths, patterns) =
+            read_patterns(reader.sub(size, "pattern dictionary")?, MAX_ALLOWED_DEPTH)?;
+        let max_pattern_len = patterns.iter().map(Vec::len).max().unwrap_or(0);
+        let mut budget = TableBudget::new(DEFAULT_MAX_TABLE_BYTES);
+        let patterns = if size > 0 {
+            Some(PatternTable::build(
+                &depths,
+                &patterns,
+                DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD,
+                &mut budget,
+            )?)
+        } else {
+            None
+        };
+
+        let size = reader.u64_be("position dictionary size")?;
+        let (depths, positions) =
+            read_positions(reader.sub(size, "position dictionary")?, MAX_ALLOWED_DEPTH)?;
+        // From Go: decompress.go:335
+        if word_count == 0 && size == 0 && words_end > COMPRESSED_MIN_SIZE {
+            return Err(DecodeError::NoWords {
+                size: words_end as u64,
+            });
+        }
+        let positions = PosTable::build(&depths, &positions, &mut budget)?;
+
+        Ok(SegmentView {
+            words: reader.rest(),
+            word_count,
+            empty_word_count,
+            patterns,
+            positions,
+            max_pattern_len,
+            checksum,
+        })
+    }
+
+    /// Number of words, as recorded in the header
+    pub fn word_count(&self) -> u64 {
+        self.word_count
+    }
+
+    pub fn empty_word_count(&self) -> u64 {
+        self.empty_word_count
+    }
+
+    /// Checksums of the footer, if the segment has one
+    pub fn checksum(&self) -> Option<SegmentChecksum> {
+        self.checksum
+    }
+
+    /// The compressed words, after the dictionaries and before the footer
+    pub fn data(&self) -> &'a [u8] {
+        self.words
+    }
+
+    /// Decode the words from the first one
+    pub fn words(&self) -> Words<'_> {
+        self.words_at(0)
+    }
+
+    /// Decode the words from `offset` into [`SegmentView::data`], the value
+    /// `.idx` files store for each word
+    pub fn words_at(&self, offset: u64) -> Words<'_> {
+        let mut reader = BitReader::new(self.words);
+        reader.seek(offset);
+        Words {
+            patterns: self.patterns.as_ref(),
+            positions: &self.positions,
+            reader,
+            max_pattern_len: self.max_pattern_len,
+        }
+    }
+}
+
+/// Cursor decoding the words of a [`SegmentView`] one after the other
+///
+/// As an iterator it yields owned words and stops after the first error;
+/// [`Words::next_into`] reuses a buffer instead.
+pub struct Words<'v> {
+    patterns: Option<&'v PatternTable>,
+    positions: &'v PosTable,
+    reader: BitReader<'v>,
+    max_pattern_len: usize,
+}
+
+impl Words<'_> {
+    /// Offset of the next word in [`SegmentView::data`]
+    pub fn offset(&self) -> u64 {
+        self.reader.position()
+    }
+
+    pub fn has_next(&self) -> bool {
+        !self.reader.remaining().is_empty()
+    }
+
+    /// Decode the next word into `buf`, replacing its contents
+    ///
+    /// Positions and patterns are checked against the word and the data, so
+    /// corrupted input fails with [`DecodeError::CorruptedData`] or
+    /// [`DecodeError::UnexpectedEof`] rather than panicking.
+    // From Go: decompress.go:669 Getter.Next
+    pub fn next_into(&mut self, buf: &mut Vec<u8>) -> Result<(), DecodeError> {
+        buf.clear();
+        let word_len = next_pos(Some(self.positions), &mut self.reader, true)?.saturating_sub(1); // 0 is the terminator
+        if word_len == 0 {
+            self.reader.align_to_byte();
+            return Ok(());
+        }
+        // Each byte of the word is either stored uncovered or part of a
+        // pattern, and each pattern takes at least a bit
+        let remaining = self.reader.remaining().len() as u64;
+        if word_len > remaining.saturating_mul(8 * self.max_pattern_len as u64 + 1) {
+            return Err(DecodeError::CorruptedData);
+        }
+        let word_len = usize::try_from(word_len).map_err(|_| DecodeError::CorruptedData)?;
+        buf.resize(word_len, 0);
+
+        // First pass: place the patterns, checking they stay in the word
+        let codes = self.reader.clone();
+        let mut buf_pos = 0usize;
+        loop {
+            let pos = next_pos(Some(self.positions), &mut self.reader, false)?;
+            if pos == 0 {
+                break;
+            }
+            buf_pos = usize::try_from(pos - 1)
+                .ok()
+                .and_then(|gap| buf_pos.checked_add(gap))
+                .filter(|&buf_pos| buf_pos <= word_len)
+                .ok_or(DecodeError::CorruptedData)?;
+            let pattern = next_pattern(self.patterns, &mut self.reader)?;
+            let end = buf_pos + pattern.len();
+            if end > word_len {
+                return Err(DecodeError::CorruptedData);
+            }
+            buf[buf_pos..end].copy_from_slice(pattern);
+        }
+        self.reader.align_to_byte();
+
+        // Second pass over the same codes: fill the gaps between patterns
+        // with the uncovered bytes that follow them
+        let mut codes = codes;
+        let mut buf_pos = 0usize;
+        let mut last_uncovered = 0usize;
+        loop {
+            let pos = next_pos(Some(self.positions), &mut codes, false)?;
+            if pos == 0 {
+                break;
+            }
+            buf_pos += pos as usize - 1;
+            if buf_pos > last_uncovered {
+                let bytes = self.reader.read_bytes(buf_pos - last_uncovered)?;
+                buf[last_uncovered..buf_pos].copy_from_slice(bytes);
+            }
+            last_uncovered = buf_pos + next_pattern(self.patterns, &mut codes)?.len();
+        }
+        if word_len > last_uncovered {
+            let bytes = self.reader.read_bytes(word_len - last_uncovered)?;
+            buf[last_uncovered..].copy_from_slice(bytes);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Words<'_> {
+    type Item = Result<Vec<u8>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.has_next() {
+            return None;
+        }
+        let mut word = Vec::new();
+        let result = self.next_into(&mut word);
+        if result.is_err() {
+            // The rest can't be decoded; stop instead of failing forever
+            self.reader.seek(u64::MAX);
+        }
+        Some(result.map(|()| word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Compressor, Decompressor};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_segment_view_matches_decompressor() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("view.seg");
+        let mut compressor = Compressor::builder(&path)
+            .min_pattern_score(1)
+            .checksum(true)
+            .build()
+            .unwrap();
+        let words: Vec<Vec<u8>> = (0..2000u32)
+            .map(|i| match i % 7 {
+                0 => Vec::new(),
+                _ => format!("key {} of the view test, value {}", i % 50, i).into_bytes(),
+            })
+            .collect();
+        for word in &words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let view = SegmentView::new(&data).unwrap();
+        let d = Decompressor::new(&path).unwrap();
+        assert!(d.is_compressed());
+        assert_eq!(view.word_count(), words.len() as u64);
+        assert_eq!(view.empty_word_count(), d.empty_words_count() as u64);
+        assert_eq!(view.checksum(), d.checksum().copied());
+
+        let decoded: Vec<Vec<u8>> = view.words().collect::<Result<_, _>>().unwrap();
+        assert_eq!(decoded, words);
+
+        // Offsets agree with the getter's, so .idx offsets work too
+        let mut g = d.make_getter();
+        let mut it = view.words();
+        for _ in 0..10 {
+            g.skip();
+            it.next().unwrap().unwrap();
+        }
+        assert_eq!(it.offset(), g.offset());
+        let mut from = view.words_at(g.offset());
+        assert_eq!(from.next().unwrap().unwrap(), words[10]);
+
+        // Corrupted words fail instead of panicking, and end the iteration
+        let mut bad = data[..data.len() - 29].to_vec(); // without the footer
+        let words_start = bad.len() - view.data().len();
+        bad[words_start..words_start + 64].fill(0xff);
+        let bad_view = SegmentView::new(&bad).unwrap();
+        let results: Vec<_> = bad_view.words().collect();
+        assert!(results.iter().any(Result::is_err));
+        assert!(results.last().unwrap().is_err());
+
+        // A truncated header
+        assert!(matches!(
+            SegmentView::new(&data[..20]),
+            Err(DecodeError::Read(_))
+        ));
+
+        // The empty segment is its bare header, and nothing may follow it
+        let empty = [0u8; COMPRESSED_MIN_SIZE];
+        let view = SegmentView::new(&empty).unwrap();
+        assert_eq!(view.word_count(), 0);
+        assert_eq!(view.words().count(), 0);
+        assert!(matches!(
+            SegmentView::new(&[0u8; COMPRESSED_MIN_SIZE + 1]),
+            Err(DecodeError::NoWords { size: 33 })
+        ));
+    }
+}
@@ -1,118 +1,98 @@
 // Port of Erigon's decompress.go
 // Original: go/src/decompress.go
 
-use crate::error::CompressionError;
+use crate::cancel::{self, CancellationToken};
+use crate::codec::WordCodec;
+use crate::compress::{depth_histogram, hash_word, CompressionStats, Pattern, Position};
+use crate::core::{
+    next_pattern, next_pos, read_patterns, read_positions, DecodeError, FormatVersion, NameVersion,
+    PatternTable, PosTable, SegmentHeader, TableBudget, COMPRESSED_MIN_SIZE, MAX_ALLOWED_DEPTH,
+};
+use crate::error::{CompressError, CompressionError, DecompressError, IndexError, ReadError};
+
+pub use crate::core::{
+    BitReader, SafeReader, SegmentChecksum, DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD,
+    DEFAULT_MAX_TABLE_BYTES,
+};
+use crate::export::{ExportFormat, WordSeparator};
+use crate::metrics;
+use crate::offsets::OffsetTable;
+use crate::readahead::{Prefetcher, ReadAhead};
+use crate::snapshots::mapped::{Access, FileData};
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::trace::word_trace;
 use std::fs::File;
-use std::io::Read;
+use std::ops::Range;
 use std::path::Path;
+use std::sync::Arc;
 use std::time::SystemTime;
-
-// From Go: decompress.go:39
-type Word = Vec<u8>; // plain text word associated with code from dictionary
-
-// From Go: decompress.go:41
-#[derive(Debug)]
-struct Codeword {
-    pattern: Word,                  // Pattern corresponding to entries
-    ptr: Option<Box<PatternTable>>, // pointer to deeper level tables
-    code: u16,                      // code associated with that word
-    len: u8,                        // Number of bits in the codes
+use xxhash_rust::xxh64::{xxh64, Xxh64};
+
+/// Summary of a successful [`Decompressor::verify`] run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub words: u64,
+    pub empty_words: u64,
+    /// Sum of the decompressed lengths of all words
+    pub total_word_bytes: u64,
 }
 
-// From Go: decompress.go:48
-#[derive(Debug)]
-struct PatternTable {
-    patterns: Vec<Option<Codeword>>,
-    bit_len: usize, // Number of bits to lookup in the table
+/// Heap bytes held by the lookup tables of a [`Decompressor`], from
+/// [`Decompressor::memory_usage`]
+///
+/// The segment data itself is memory-mapped and not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub pattern_tables: usize,
+    pub position_tables: usize,
 }
 
-impl PatternTable {
-    // From Go: decompress.go:53
-    fn new(bit_len: usize) -> Self {
-        let size = if bit_len <= CONDENSE_PATTERN_TABLE_BIT_THRESHOLD {
-            1 << bit_len
-        } else {
-            0 // Will use vec for sparse storage
-        };
-
-        PatternTable {
-            patterns: (0..size).map(|_| None).collect(),
-            bit_len,
-        }
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.pattern_tables + self.position_tables
     }
+}
 
-    // From Go: decompress.go:63
-    fn insert_word(&mut self, cw: Codeword) {
-        if self.bit_len <= CONDENSE_PATTERN_TABLE_BIT_THRESHOLD {
-            let code_step = (1u16) << cw.len;
-            let code_from = cw.code;
-            let code_to = if self.bit_len != cw.len as usize && cw.len > 0 {
-                code_from | (1u16 << self.bit_len)
-            } else {
-                cw.code + code_step
-            };
+// Word count, empty word count and pattern dictionary size
+const HEADER_LEN: usize = 24;
 
-            let mut c = code_from;
-            while c < code_to {
-                // Store reference to the same codeword
-                let stored_cw = Codeword {
-                    pattern: cw.pattern.clone(),
-                    ptr: None, // ptr is always None for simple codewords
-                    code: cw.code,
-                    len: cw.len,
-                };
-                self.patterns[c as usize] = Some(stored_cw);
-                c += code_step;
-            }
-        } else {
-            // For sparse tables, just append
-            self.patterns.push(Some(cw));
-        }
-    }
+/// Patterns of a segment as stored in its dictionary, from
+/// [`Decompressor::patterns`]: each Huffman code depth with its bytes
+pub struct Patterns<'a> {
+    reader: SafeReader<'a>,
+}
 
-    // From Go: decompress.go:80
-    fn condensed_table_search(&self, code: u16) -> Option<&Codeword> {
-        if self.bit_len <= CONDENSE_PATTERN_TABLE_BIT_THRESHOLD {
-            self.patterns.get(code as usize)?.as_ref()
-        } else {
-            // Linear search for sparse tables
-            for pattern in &self.patterns {
-                if let Some(cw) = pattern {
-                    if cw.code == code {
-                        return Some(cw);
-                    }
-                    let d = code - cw.code;
-                    if d & 1 != 0 {
-                        continue;
-                    }
-                    if check_distance(cw.len as usize, d as usize) {
-                        return Some(cw);
-                    }
-                }
-            }
-            None
+impl<'a> Iterator for Patterns<'a> {
+    type Item = (u64, &'a [u8]);
+
+    // The dictionary was validated when the file was opened
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.reader.is_empty() {
+            return None;
         }
+        let depth = self.reader.uvarint("pattern depth").ok()?;
+        let len = self.reader.uvarint("pattern size").ok()?;
+        let pattern = self.reader.bytes(len, "pattern").ok()?;
+        Some((depth, pattern))
     }
 }
 
-// From Go: decompress.go:99
-#[derive(Debug)]
-struct PosTable {
-    pos: Vec<u64>,
-    lens: Vec<u8>,
-    ptrs: Vec<Option<Box<PosTable>>>,
-    bit_len: usize,
+/// Positions of a segment as stored in its dictionary, from
+/// [`Decompressor::positions`]: each Huffman code depth with its position
+pub struct Positions<'a> {
+    reader: SafeReader<'a>,
 }
 
-impl PosTable {
-    fn new(bit_len: usize) -> Self {
-        let size = 1 << bit_len;
-        PosTable {
-            pos: vec![0; size],
-            lens: vec![0; size],
-            ptrs: (0..size).map(|_| None).collect(),
-            bit_len,
+impl Iterator for Positions<'_> {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.reader.is_empty() {
+            return None;
         }
+        let depth = self.reader.uvarint("position depth").ok()?;
+        let pos = self.reader.uvarint("position").ok()?;
+        Some((depth, pos))
     }
 }
 
@@ -121,243 +101,308 @@ pub struct Decompressor {
     f: Option<File>,
     dict: Option<PatternTable>,
     pos_dict: Option<PosTable>,
-    data: Vec<u8>,
+    data: FileData,
     words_start: u64,
+    // End of the words: before the checksum footer if there is one, or
+    // after the last word if opened leniently
+    words_end: u64,
+    // End of the data the footer's payload checksum covers
+    payload_end: u64,
+    checksum: Option<SegmentChecksum>,
+    name_version: Option<NameVersion>,
     size: i64,
     mod_time: SystemTime,
     words_count: u64,
     empty_words_count: u64,
     serialized_dict_size: u64,
     dict_words: usize,
+    serialized_pos_dict_size: u64,
+    pos_dict_words: usize,
+    // Number of codes of each depth in the pattern and position dictionaries
+    pattern_depths: Vec<u64>,
+    position_depths: Vec<u64>,
+    // Longest pattern in the dictionary, bounds the length of a word
+    max_pattern_len: usize,
     file_path: String,
     file_name: String,
+    // Enum `.idx` of the segment, for random access by word ordinal
+    index: Option<RecSplitIndex>,
+    // Sampled word offsets, for near-random access without an index
+    offsets: Option<OffsetTable>,
+    // Decodes the words getters return, see Decompressor::with_word_codec
+    pub(crate) codec: Option<Arc<dyn WordCodec>>,
+}
+
+// From Go: decompress.go:158 init() - the threshold can be overridden with
+// DECOMPRESS_CONDENSITY, within the same bounds Go accepts
+const CONDENSITY_ENV: &str = "DECOMPRESS_CONDENSITY";
+const CONDENSITY_RANGE: std::ops::RangeInclusive<usize> = 3..=9;
+
+fn condense_threshold_from_env() -> Result<usize, CompressionError> {
+    match std::env::var(CONDENSITY_ENV) {
+        Ok(v) if !v.is_empty() => v.parse().map_err(|_| {
+            CompressionError::InvalidConfig(format!("{} is not a number: {:?}", CONDENSITY_ENV, v))
+        }),
+        _ => Ok(DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD),
+    }
+}
+
+/// How [`Decompressor`]s open segments
+#[derive(Debug, Clone)]
+pub struct DecompressorOptions {
+    max_depth: u64,
+    condense_threshold: usize,
+    max_table_bytes: usize,
+    strict: bool,
+    verify: bool,
+}
+
+impl Default for DecompressorOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: MAX_ALLOWED_DEPTH,
+            condense_threshold: DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD,
+            max_table_bytes: DEFAULT_MAX_TABLE_BYTES,
+            strict: true,
+            verify: false,
+        }
+    }
 }
 
-// From Go: decompress.go:140-146
-const MAX_ALLOWED_DEPTH: u64 = 50;
-const COMPRESSED_MIN_SIZE: usize = 32;
+impl DecompressorOptions {
+    /// Erigon's defaults; unlike [`Decompressor::new`] this does not read
+    /// `DECOMPRESS_CONDENSITY`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deepest dictionary code accepted, 1..=[`MAX_ALLOWED_DEPTH`]; segments
+    /// with deeper codes fail to open with [`DecompressError::DepthOverflow`]
+    pub fn max_depth(mut self, max_depth: u64) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Condense pattern tables wider than `bit_threshold` bits (3..=9).
+    /// Lower values use less memory for large dictionaries at the cost of
+    /// slower pattern lookups.
+    pub fn condense_threshold(mut self, bit_threshold: usize) -> Self {
+        self.condense_threshold = bit_threshold;
+        self
+    }
+
+    /// Most heap bytes the pattern and position tables of a segment may
+    /// take, [`DEFAULT_MAX_TABLE_BYTES`] by default; segments needing more
+    /// fail to open with [`DecompressError::TableTooLarge`] before the
+    /// tables are allocated
+    ///
+    /// Condensing pattern tables (see
+    /// [`DecompressorOptions::condense_threshold`]) lets large dictionaries
+    /// fit in less.
+    pub fn max_table_bytes(mut self, bytes: usize) -> Self {
+        self.max_table_bytes = bytes;
+        self
+    }
 
-// From Go: decompress.go:156
-const CONDENSE_PATTERN_TABLE_BIT_THRESHOLD: usize = 9;
+    /// Whether bytes after the last declared word are an error (the
+    /// default) or ignored
+    ///
+    /// Strict segments keep them, so that [`Decompressor::verify`] fails on
+    /// them. Lenient ones walk the words on open and end the segment after
+    /// the last one, with a warning, so that getters never decode them.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
 
-// REVIEW: missing we are not implementing go's init()
-//
-// REVIEW: missing SetDecompressionTableCondensity (sp)
+    /// Run [`Decompressor::verify`] before returning from
+    /// [`DecompressorOptions::open`]
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Open the segment at `path` with these options
+    pub fn open(&self, path: impl AsRef<Path>) -> Result<Decompressor, CompressionError> {
+        if !(1..=MAX_ALLOWED_DEPTH).contains(&self.max_depth) {
+            return Err(CompressionError::InvalidConfig(format!(
+                "maximum dictionary depth must be in 1..={}, got {}",
+                MAX_ALLOWED_DEPTH, self.max_depth
+            )));
+        }
+        if !CONDENSITY_RANGE.contains(&self.condense_threshold) {
+            return Err(CompressionError::InvalidConfig(format!(
+                "pattern table condense threshold must be in {:?}, got {}",
+                CONDENSITY_RANGE, self.condense_threshold
+            )));
+        }
+        let mut d = Decompressor::open(path.as_ref(), self)?;
+        if !self.strict {
+            d.trim_trailing();
+        }
+        if self.verify {
+            d.verify()?;
+        }
+        Ok(d)
+    }
+}
 
 impl Decompressor {
     // From Go: decompress.go:177
+    /// Open a segment; the pattern table condensity comes from the
+    /// `DECOMPRESS_CONDENSITY` environment variable if set, like in Erigon
     pub fn new(compressed_file_path: impl AsRef<Path>) -> Result<Self, CompressionError> {
-        let path = compressed_file_path.as_ref();
+        Self::with_condense_threshold(compressed_file_path, condense_threshold_from_env()?)
+    }
+
+    /// Open a segment, condensing pattern tables wider than `bit_threshold`
+    /// bits, see [`DecompressorOptions::condense_threshold`]
+    // From Go: decompress.go:173 SetDecompressionTableCondensity
+    pub fn with_condense_threshold(
+        compressed_file_path: impl AsRef<Path>,
+        bit_threshold: usize,
+    ) -> Result<Self, CompressionError> {
+        DecompressorOptions::new()
+            .condense_threshold(bit_threshold)
+            .open(compressed_file_path)
+    }
+
+    fn open(path: &Path, options: &DecompressorOptions) -> Result<Self, CompressionError> {
         let file_name = path
             .file_name()
-            .ok_or_else(|| CompressionError::Other("Invalid file path".to_string()))?
+            .ok_or_else(|| DecompressError::InvalidPath {
+                path: path.to_string_lossy().to_string(),
+            })?
             .to_string_lossy()
             .to_string();
+        let _span = tracing::debug_span!("open_segment", file = %file_name).entered();
 
-        let mut f = File::open(path)?;
+        let f = File::open(path)?;
         let metadata = f.metadata()?;
         let size = metadata.len() as i64;
 
         if size < COMPRESSED_MIN_SIZE as i64 {
-            return Err(CompressionError::Other(format!(
-                "File {} too small: {} bytes, expected at least {} bytes",
-                file_name, size, COMPRESSED_MIN_SIZE
-            )));
-        }
-
-        let mut data = Vec::with_capacity(size as usize);
-        f.read_to_end(&mut data)?;
-
-        // Read header
-        let words_count = u64::from_be_bytes(data[0..8].try_into().unwrap());
-        let empty_words_count = u64::from_be_bytes(data[8..16].try_into().unwrap());
-        let pattern_dict_size = u64::from_be_bytes(data[16..24].try_into().unwrap());
-        log::debug!("Pattern dictionary size: {}", pattern_dict_size);
-
-        if 24 + pattern_dict_size > size as u64 {
-            return Err(CompressionError::Other(format!(
-                "Invalid pattern dictionary size {} in file {}",
-                pattern_dict_size, file_name
-            )));
-        }
-
-        // Parse pattern dictionary (inline like Go does)
-        let pattern_dict_data = &data[24..24 + pattern_dict_size as usize];
-        let dict_size = pattern_dict_data.len();
-        let mut dict_pos = 0usize;
-
-        let mut depths = Vec::new();
-        let mut patterns = Vec::new();
-        let mut pattern_max_depth = 0u64;
-
-        // Read patterns from dictionary (Go: decompress.go:243-260)
-        while dict_pos < dict_size {
-            let (depth, ns) = decode_varint(&pattern_dict_data[dict_pos..])?;
-            if depth > MAX_ALLOWED_DEPTH {
-                return Err(CompressionError::Other(format!(
-                    "Pattern depth {} exceeds maximum allowed depth {}",
-                    depth, MAX_ALLOWED_DEPTH
-                )));
+            return Err(DecompressError::FileTooSmall {
+                file: file_name,
+                size: size as u64,
+                min: COMPRESSED_MIN_SIZE as u64,
             }
+            .into());
+        }
 
-            depths.push(depth);
-            if depth > pattern_max_depth {
-                pattern_max_depth = depth;
+        // Segments are mapped or read whole, which on 32-bit targets caps
+        // their size
+        if usize::try_from(metadata.len()).is_err() {
+            return Err(DecompressError::FileTooLarge {
+                file: file_name,
+                size: metadata.len(),
             }
-            dict_pos += ns;
-
-            let (pattern_size, ns) = decode_varint(&pattern_dict_data[dict_pos..])?;
-            dict_pos += ns;
+            .into());
+        }
+        let data = FileData::open(&f)?;
+        let (checksum, words_end) = match SegmentChecksum::parse_footer(&data) {
+            Some((checksum, footer_len)) => (Some(checksum), data.len() - footer_len),
+            None => (None, data.len()),
+        };
 
-            if dict_pos + pattern_size as usize > dict_size {
-                return Err(CompressionError::Other(
-                    "Pattern size exceeds dictionary bounds".to_string(),
-                ));
+        // Read header
+        let mut reader = SafeReader::new(&data[..words_end]);
+        let SegmentHeader {
+            word_count: words_count,
+            empty_word_count: empty_words_count,
+        } = SegmentHeader::read(&mut reader).map_err(malformed(&file_name))?;
+        let pattern_dict_size = reader
+            .u64_be("pattern dictionary size")
+            .map_err(malformed(&file_name))?;
+        tracing::debug!("Pattern dictionary size: {}", pattern_dict_size);
+
+        if pattern_dict_size > reader.remaining() as u64 {
+            return Err(DecompressError::DictionarySize {
+                file: file_name,
+                dict: "pattern",
+                size: pattern_dict_size,
+                available: reader.remaining() as u64,
             }
-
-            patterns.push(pattern_dict_data[dict_pos..dict_pos + pattern_size as usize].to_vec());
-            dict_pos += pattern_size as usize;
+            .into());
         }
-        let dict_words = patterns.len();
 
-        // Build pattern huffman tree (Go: decompress.go:263-275)
+        // Parse pattern dictionary (Go: decompress.go:243-275)
+        let pattern_dict_reader = reader
+            .sub(pattern_dict_size, "pattern dictionary")
+            .map_err(malformed(&file_name))?;
+        let (depths, patterns) = read_patterns(pattern_dict_reader, options.max_depth)
+            .map_err(dict_error(&file_name))?;
+        let dict_words = patterns.len();
+        let max_pattern_len = patterns.iter().map(Vec::len).max().unwrap_or(0);
+        let mut budget = TableBudget::new(options.max_table_bytes);
         let dict = if pattern_dict_size > 0 {
-            let bit_len = if pattern_max_depth > 9 {
-                9
-            } else {
-                pattern_max_depth as usize
-            };
-            let mut table = PatternTable::new(bit_len);
-            build_condensed_pattern_table(
-                &depths,
-                &patterns,
-                &mut table,
-                0,
-                0,
-                0,
-                pattern_max_depth,
-            )?;
-            Some(table)
+            Some(
+                PatternTable::build(&depths, &patterns, options.condense_threshold, &mut budget)
+                    .map_err(dict_error(&file_name))?,
+            )
         } else {
             None
         };
 
         // Read position dictionary size
-        let pos_dict_start = 24 + pattern_dict_size as usize;
-        if pos_dict_start + 8 > size as usize {
-            return Err(CompressionError::Other(format!(
-                "File {} too small to contain position dictionary size",
-                file_name
-            )));
-        }
-        let pos_dict_size =
-            u64::from_be_bytes(data[pos_dict_start..pos_dict_start + 8].try_into().unwrap());
-        log::debug!("Position dictionary size: {}", pos_dict_size);
-
-        if pos_dict_start + 8 + pos_dict_size as usize > size as usize {
-            return Err(CompressionError::Other(format!(
-                "Invalid position dictionary size {} in file {}",
-                pos_dict_size, file_name
-            )));
+        if reader.remaining() < 8 {
+            return Err(DecompressError::FileTooSmall {
+                file: file_name,
+                size: size as u64,
+                min: reader.offset() + 8,
+            }
+            .into());
         }
-
-        // Parse position dictionary (inline like Go does)
-        let pos_dict_data = &data[pos_dict_start + 8..pos_dict_start + 8 + pos_dict_size as usize];
-        let dict_size = pos_dict_data.len();
-        let mut dict_pos = 0;
-
-        let mut pos_depths = Vec::new();
-        let mut positions = Vec::new();
-        let mut pos_max_depth = 0u64;
-
-        // Read positions from dictionary (Go: decompress.go:299-312)
-        while dict_pos < dict_size {
-            let (depth, ns) = decode_varint(&pos_dict_data[dict_pos..])?;
-            if depth > MAX_ALLOWED_DEPTH {
-                return Err(CompressionError::Other(format!(
-                    "Position depth {} exceeds maximum allowed depth {}",
-                    depth, MAX_ALLOWED_DEPTH
-                )));
+        let pos_dict_size = reader
+            .u64_be("position dictionary size")
+            .map_err(malformed(&file_name))?;
+        tracing::debug!("Position dictionary size: {}", pos_dict_size);
+
+        if pos_dict_size > reader.remaining() as u64 {
+            return Err(DecompressError::DictionarySize {
+                file: file_name,
+                dict: "position",
+                size: pos_dict_size,
+                available: reader.remaining() as u64,
             }
+            .into());
+        }
 
-            pos_depths.push(depth);
-            if depth > pos_max_depth {
-                pos_max_depth = depth;
+        // Parse position dictionary (Go: decompress.go:299-332)
+        let pos_dict_reader = reader
+            .sub(pos_dict_size, "position dictionary")
+            .map_err(malformed(&file_name))?;
+
+        // From Go: decompress.go:335, a segment without words is its bare
+        // header; the footer is ours and not counted
+        if words_count == 0 && pos_dict_size == 0 && words_end > COMPRESSED_MIN_SIZE {
+            return Err(DecompressError::NoWords {
+                file: file_name,
+                size: words_end as u64,
             }
-            dict_pos += ns;
-
-            let (pos, ns) = decode_varint(&pos_dict_data[dict_pos..])?;
-            dict_pos += ns;
-            positions.push(pos);
+            .into());
         }
-
-        // Debug logging for positions
-        log::debug!(
+        let (pos_depths, positions) =
+            read_positions(pos_dict_reader, options.max_depth).map_err(dict_error(&file_name))?;
+        tracing::debug!(
             "Parsing position dictionary: {} positions, max_depth={}",
             positions.len(),
-            pos_max_depth
+            pos_depths.iter().max().unwrap_or(&0)
+        );
+        let pos_dict = Some(
+            PosTable::build(&pos_depths, &positions, &mut budget)
+                .map_err(dict_error(&file_name))?,
         );
-        for j in 0..positions.len() {
-            log::debug!(
-                "  Position[{}]: depth={}, pos={}",
-                j,
-                pos_depths[j],
-                positions[j]
-            );
-        }
-
-        // Build position huffman tree (Go: decompress.go:314-332)
-        let pos_dict = if pos_dict_size > 0 {
-            let bit_len = if pos_max_depth > 9 {
-                9
-            } else {
-                pos_max_depth as usize
-            };
-            let mut table = PosTable::new(bit_len);
-            build_pos_table_recursive(&pos_depths, &positions, &mut table, 0, 0, 0, pos_max_depth)?;
-            Some(table)
-        } else {
-            // Empty position dictionary
-            let mut table = PosTable::new(0);
-            if !table.pos.is_empty() {
-                table.pos[0] = 0;
-            }
-            Some(table)
-        };
-        if let Some(ref pd) = pos_dict {
-            log::debug!(
-                "Position dict: bit_len: {}, pos[0]: {:?}",
-                pd.bit_len,
-                if pd.pos.is_empty() {
-                    None
-                } else {
-                    Some(pd.pos[0])
-                }
-            );
-        }
-
-        // Debug: print how codes map to positions
-        if let Some(ref pd) = pos_dict {
-            log::debug!("Position table mappings (code -> position):");
-            for i in 0..16.min(pd.pos.len()) {
-                if pd.lens[i] > 0 {
-                    log::debug!(
-                        "  code {} -> position {}, bits {}",
-                        i,
-                        pd.pos[i],
-                        pd.lens[i]
-                    );
-                }
-            }
-        }
 
-        let words_start_offset = 8 + pos_dict_size; // 8 for pos dict size + pos dict data
-        let words_start = 24 + pattern_dict_size + words_start_offset;
+        let words_start = reader.offset();
 
-        log::debug!(
-            "Decompressor initialized: words_start: {}, file_size: {}",
+        tracing::debug!(
+            words = words_count,
+            patterns = dict_words,
+            positions = positions.len(),
             words_start,
-            size
+            size,
+            "Decompressor initialized"
         );
 
         Ok(Decompressor {
@@ -366,14 +411,26 @@ impl Decompressor {
             pos_dict,
             data,
             words_start,
+            words_end: words_end as u64,
+            payload_end: words_end as u64,
+            checksum,
+            name_version: NameVersion::from_file_name(&file_name),
             size,
             mod_time: metadata.modified()?,
             words_count,
             empty_words_count,
             serialized_dict_size: pattern_dict_size,
             dict_words,
+            serialized_pos_dict_size: pos_dict_size,
+            pos_dict_words: positions.len(),
+            pattern_depths: depth_histogram(depths),
+            position_depths: depth_histogram(pos_depths),
+            max_pattern_len,
             file_path: path.to_string_lossy().to_string(),
             file_name,
+            index: None,
+            offsets: None,
+            codec: None,
         })
     }
 
@@ -386,20 +443,145 @@ impl Decompressor {
         self.empty_words_count as usize
     }
 
+    // From Go: decompress.go:632
     pub fn size(&self) -> usize {
-        self.data.len()
+        self.size as usize
+    }
+
+    // From Go: decompress.go:636
+    pub fn mod_time(&self) -> SystemTime {
+        self.mod_time
+    }
+
+    // From Go: decompress.go:656
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Number of patterns in the pattern dictionary
+    pub fn dict_words(&self) -> usize {
+        self.dict_words
+    }
+
+    /// Dictionary statistics as recorded in the file header, for comparing
+    /// the dictionaries of different segments; see [`CompressionStats`] for
+    /// what is only known while compressing
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            words: self.words_count,
+            empty_words: self.empty_words_count,
+            patterns: self.dict_words,
+            positions: self.pos_dict_words,
+            pattern_dict_size: self.serialized_dict_size,
+            pos_dict_size: self.serialized_pos_dict_size,
+            pattern_depths: self.pattern_depths.clone(),
+            position_depths: self.position_depths.clone(),
+            input_bytes: None,
+            covered_bytes: None,
+            output_bytes: self.size as u64,
+            phases: None,
+        }
+    }
+
+    /// The pattern dictionary in file order, as `(depth, pattern)` pairs
+    pub fn patterns(&self) -> Patterns<'_> {
+        let start = HEADER_LEN;
+        let end = start + self.serialized_dict_size as usize;
+        Patterns {
+            reader: SafeReader::new(&self.data[start..end]),
+        }
+    }
+
+    /// The position dictionary in file order, as `(depth, position)` pairs
+    ///
+    /// Position 0 marks the end of a word's patterns; the others are stored
+    /// one higher than the gap they encode.
+    pub fn positions(&self) -> Positions<'_> {
+        let start = HEADER_LEN + self.serialized_dict_size as usize + 8;
+        let end = start + self.serialized_pos_dict_size as usize;
+        Positions {
+            reader: SafeReader::new(&self.data[start..end]),
+        }
+    }
+
+    /// Print the header and both dictionaries to `writer`, one entry per
+    /// line, for comparing files without a hex editor
+    pub fn dump_dictionary(
+        &self,
+        writer: &mut impl std::io::Write,
+    ) -> Result<(), CompressionError> {
+        writeln!(
+            writer,
+            "{}: {} words ({} empty)",
+            self.file_name, self.words_count, self.empty_words_count
+        )?;
+        writeln!(
+            writer,
+            "patterns: {} ({} bytes)",
+            self.dict_words, self.serialized_dict_size
+        )?;
+        for (i, (depth, pattern)) in self.patterns().enumerate() {
+            writeln!(
+                writer,
+                "  {:>6} depth {:>2} len {:>4} {}",
+                i,
+                depth,
+                pattern.len(),
+                hex::encode(pattern)
+            )?;
+        }
+        writeln!(
+            writer,
+            "positions: {} ({} bytes)",
+            self.pos_dict_words, self.serialized_pos_dict_size
+        )?;
+        for (i, (depth, pos)) in self.positions().enumerate() {
+            writeln!(writer, "  {:>6} depth {:>2} pos {}", i, depth, pos)?;
+        }
+        Ok(())
     }
 
     /// Check if this decompressor uses pattern compression
     /// Returns false if pattern dictionary is empty (uncompressed format)
+    /// Total number of slots in the pattern lookup tables, a measure of
+    /// their memory use (see [`Decompressor::with_condense_threshold`])
+    pub fn pattern_table_entries(&self) -> usize {
+        self.dict.as_ref().map_or(0, PatternTable::entries)
+    }
+
+    /// Heap bytes held by the pattern and position lookup tables, for
+    /// budgeting memory across many open segments (see
+    /// [`DecompressorOptions::max_table_bytes`])
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            pattern_tables: self.dict.as_ref().map_or(0, PatternTable::heap_bytes),
+            position_tables: self.pos_dict.as_ref().map_or(0, PosTable::heap_bytes),
+        }
+    }
+
     pub fn is_compressed(&self) -> bool {
         self.dict.is_some() && self.serialized_dict_size > 0
     }
 
+    /// Decode every word getters return with `codec`, the one the segment
+    /// was compressed with (see [`crate::codec`])
+    ///
+    /// Only the words are decoded: [`Getter::match_prefix`],
+    /// [`Getter::match_cmp`], [`Getter::next_raw`], [`Decompressor::copy_words`]
+    /// and the checksum footer all work on the words as stored.
+    pub fn with_word_codec(mut self, codec: impl WordCodec + 'static) -> Self {
+        self.codec = Some(Arc::new(codec));
+        self
+    }
+
     // From Go: decompress.go:648
-    pub fn make_getter(&self) -> Getter {
-        let data = self.data[self.words_start as usize..].to_vec();
-        log::debug!(
+    pub fn make_getter(&self) -> Getter<'_> {
+        let data = &self.data[self.words_start as usize..self.words_end as usize];
+        tracing::debug!(
             "Getter data (first 20 bytes): {:02x?}",
             &data[..data.len().min(20)]
         );
@@ -407,175 +589,672 @@ impl Decompressor {
             pattern_dict: self.dict.as_ref(),
             pos_dict: self.pos_dict.as_ref(),
             file_name: self.file_name.clone(),
-            data,
-            data_p: 0,
-            data_bit: 0,
+            reader: BitReader::new(data),
+            max_pattern_len: self.max_pattern_len,
             trace: false,
+            segment: Some(self),
+            prefetcher: None,
         }
     }
 
-    pub fn close(mut self) {
-        self.f = None;
+    // Apply `read_ahead` to the words in `range`, getter offsets; returns
+    // the prefetcher if one was asked for
+    fn read_ahead(&self, read_ahead: ReadAhead, range: Range<u64>) -> Option<Prefetcher> {
+        let start = self.words_start as usize;
+        let file_range = start + range.start as usize..start + range.end as usize;
+        let access = match read_ahead {
+            ReadAhead::Default => return None,
+            ReadAhead::Sequential | ReadAhead::Prefetch { .. } => Access::Sequential,
+            ReadAhead::WillNeed => Access::WillNeed,
+        };
+        if let Err(e) = self.data.advise(access, file_range) {
+            tracing::debug!("{}: {:?} advice failed: {}", self.file_name, access, e);
+        }
+        let ReadAhead::Prefetch { window } = read_ahead else {
+            return None;
+        };
+        let path = Path::new(&self.file_path);
+        Prefetcher::spawn(path, self.words_start, range.start, range.end, window)
+            .inspect_err(|e| tracing::debug!("{}: no prefetch: {}", self.file_name, e))
+            .ok()
     }
-}
 
-// Recursive pattern table builder (matching Go's buildCondensedPatternTable exactly)
-fn build_condensed_pattern_table(
-    depths: &[u64],
-    patterns: &[Vec<u8>],
-    table: &mut PatternTable,
-    code: u16,
-    bits: usize,
-    depth: u64,
-    max_depth: u64,
-) -> Result<usize, CompressionError> {
-    if depths.is_empty() {
-        return Ok(0);
-    }
-
-    if depth == depths[0] {
-        let pattern = patterns[0].clone();
-        let cw = Codeword {
-            pattern: pattern.clone(),
-            ptr: None,
-            code,
-            len: bits as u8,
+    /// Walk every word in the segment and check that it decodes within
+    /// bounds and that the word counts match the header. Intended for
+    /// checking snapshots after they have been downloaded.
+    pub fn verify(&self) -> Result<VerifyReport, CompressionError> {
+        self.verify_inner(None, None)
+    }
+
+    // Lenient opening: end the words after the last declared one. A word
+    // that does not decode leaves the segment as it is, for getters and
+    // verify to report.
+    fn trim_trailing(&mut self) {
+        let mut getter = self.make_getter();
+        for _ in 0..self.words_count {
+            if !getter.has_next() || getter.try_skip().is_err() {
+                return;
+            }
+        }
+        let end = self.words_start + getter.offset();
+        if end < self.words_end {
+            tracing::warn!(
+                "{}: ignoring {} trailing bytes after {} words",
+                self.file_name,
+                self.words_end - end,
+                self.words_count
+            );
+            self.words_end = end;
+        }
+    }
+
+    /// Like [`Decompressor::verify`], but also cross-checks the companion
+    /// `.idx`: the key count must match the word count and, for enum
+    /// indexes, every ordinal must point at the start of the matching word.
+    pub fn verify_with_index(&self, idx: &RecSplitIndex) -> Result<VerifyReport, CompressionError> {
+        self.verify_inner(Some(idx), None)
+    }
+
+    /// [`Decompressor::verify`], or [`Decompressor::verify_with_index`]
+    /// with `idx`, giving up with [`CompressionError::Cancelled`] once
+    /// `cancel` is cancelled
+    pub fn verify_cancellable(
+        &self,
+        idx: Option<&RecSplitIndex>,
+        cancel: &CancellationToken,
+    ) -> Result<VerifyReport, CompressionError> {
+        self.verify_inner(idx, Some(cancel))
+    }
+
+    fn verify_inner(
+        &self,
+        idx: Option<&RecSplitIndex>,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<VerifyReport, CompressionError> {
+        let fail = |reason: String| CompressionError::VerificationFailed {
+            file: self.file_name.clone(),
+            reason,
         };
-        log::debug!(
-            "Inserting pattern code={}, len={}, pattern={:?}",
-            code,
-            bits,
-            String::from_utf8_lossy(&pattern)
+
+        if let Some(idx) = idx {
+            if idx.key_count() != self.words_count {
+                return Err(fail(format!(
+                    "index has {} keys, segment has {} words",
+                    idx.key_count(),
+                    self.words_count
+                )));
+            }
+        }
+        // Walked alongside the words rather than looked up for each
+        let mut expected_offsets = match idx.filter(|idx| idx.is_enum()) {
+            Some(idx) if self.words_count > 0 => Some(
+                idx.offsets(0)
+                    .ok_or_else(|| fail("index offsets do not decode".to_string()))?,
+            ),
+            _ => None,
+        };
+
+        let mut report = VerifyReport::default();
+        let mut getter = self.make_getter();
+        while getter.has_next() {
+            cancel::check(cancel)?;
+            let offset = getter.offset();
+            if report.words >= self.words_count {
+                return Err(fail(format!(
+                    "trailing data at offset {} after {} words",
+                    offset, self.words_count
+                )));
+            }
+
+            if let Some(offsets) = &mut expected_offsets {
+                let expected = offsets.next();
+                if expected != Some(offset) {
+                    return Err(fail(format!(
+                        "index offset {:?} for word {} does not match segment offset {}",
+                        expected, report.words, offset
+                    )));
+                }
+            }
+
+            let word_len = getter.try_skip().map_err(|e| {
+                fail(format!(
+                    "word {} at offset {} does not decode: {}",
+                    report.words, offset, e
+                ))
+            })?;
+
+            report.words += 1;
+            if word_len == 0 {
+                report.empty_words += 1;
+            }
+            report.total_word_bytes += word_len as u64;
+        }
+
+        if report.words != self.words_count {
+            return Err(fail(format!(
+                "header declares {} words, found {}",
+                self.words_count, report.words
+            )));
+        }
+        if report.empty_words != self.empty_words_count {
+            return Err(fail(format!(
+                "header declares {} empty words, found {}",
+                self.empty_words_count, report.empty_words
+            )));
+        }
+
+        tracing::debug!(
+            "Verified {}: {} words ({} empty), {} bytes",
+            self.file_name,
+            report.words,
+            report.empty_words,
+            report.total_word_bytes
         );
-        table.insert_word(cw);
-        return Ok(1);
+        Ok(report)
     }
 
-    if bits == 9 {
-        let bit_len = if max_depth > 9 { 9 } else { max_depth as usize };
-        let mut ptr = PatternTable::new(bit_len);
-        let consumed =
-            build_condensed_pattern_table(depths, patterns, &mut ptr, 0, 0, depth, max_depth)?;
+    /// Version prefix of the segment's file name, if it has one
+    pub fn name_version(&self) -> Option<NameVersion> {
+        self.name_version
+    }
+
+    /// Layout the segment was decoded with. Every segment version Erigon
+    /// has named so far shares the header and dictionaries this crate
+    /// reads, so this is [`FormatVersion::V1_0`] whatever the file name says
+    pub fn format_version(&self) -> FormatVersion {
+        FormatVersion::V1_0
+    }
+
+    /// The checksums of the segment's footer, if it has one
+    pub fn checksum(&self) -> Option<&SegmentChecksum> {
+        self.checksum.as_ref()
+    }
 
-        let cw = Codeword {
-            pattern: Vec::new(),
-            ptr: Some(Box::new(ptr)),
-            code,
-            len: 0,
+    /// Check the file and its decoded words against the checksum footer
+    ///
+    /// Fails with [`CompressionError::VerificationFailed`] if the segment
+    /// has no footer or either checksum differs.
+    pub fn verify_checksum(&self) -> Result<(), CompressionError> {
+        let fail = |reason: String| CompressionError::VerificationFailed {
+            file: self.file_name.clone(),
+            reason,
         };
-        table.insert_word(cw);
-        return Ok(consumed);
+        let expected = self
+            .checksum
+            .ok_or_else(|| fail("segment has no checksum footer".to_string()))?;
+
+        let payload = xxh64(&self.data[..self.payload_end as usize], 0);
+        if payload != expected.payload {
+            return Err(fail(format!(
+                "payload checksum {:016x}, footer says {:016x}",
+                payload, expected.payload
+            )));
+        }
+
+        let mut hasher = Xxh64::new(0);
+        let mut getter = self.make_getter();
+        let mut word = Vec::new();
+        while getter.has_next() {
+            word.clear();
+            getter.peek().try_skip()?;
+            word = getter.next_stored(word).0;
+            hash_word(&mut hasher, &word);
+        }
+        let words = hasher.digest();
+        if words != expected.words {
+            return Err(fail(format!(
+                "word checksum {:016x}, footer says {:016x}",
+                words, expected.words
+            )));
+        }
+        Ok(())
+    }
+
+    /// Attach the segment's enum `.idx`, making [`Decompressor::word_offset`]
+    /// and [`Decompressor::get_word`] O(1)
+    pub fn attach_index(&mut self, idx: RecSplitIndex) -> Result<(), CompressionError> {
+        if !idx.is_enum() {
+            return Err(IndexError::WrongKind {
+                file: self.file_name.clone(),
+                is_enum: false,
+            }
+            .into());
+        }
+        if idx.key_count() != self.words_count {
+            return Err(IndexError::KeyCountMismatch {
+                file: self.file_name.clone(),
+                expected: self.words_count,
+                actual: idx.key_count(),
+            }
+            .into());
+        }
+        self.index = Some(idx);
+        Ok(())
     }
 
-    if max_depth == 0 {
-        return Err(CompressionError::Other(
-            "buildCondensedPatternTable: maxDepth reached zero".to_string(),
-        ));
+    /// The attached `.idx`, if any
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.index.as_ref()
     }
 
-    // Recursive split like Go
-    let b0 = build_condensed_pattern_table(
-        depths,
-        patterns,
-        table,
-        code,
-        bits + 1,
-        depth + 1,
-        max_depth - 1,
-    )?;
-    let b1 = build_condensed_pattern_table(
-        &depths[b0..],
-        &patterns[b0..],
-        table,
-        (1u16 << bits) | code,
-        bits + 1,
-        depth + 1,
-        max_depth - 1,
-    )?;
-    Ok(b0 + b1)
-}
+    /// Scan the segment once, recording the offset of every `stride`-th
+    /// word, for [`Decompressor::attach_offset_table`] or a sidecar file
+    /// (see [`OffsetTable::save`])
+    pub fn build_offset_table(&self, stride: u64) -> Result<OffsetTable, CompressionError> {
+        OffsetTable::build(self, stride)
+    }
 
-// Recursive position table builder (matching Go's buildPosTable exactly)
-fn build_pos_table_recursive(
-    depths: &[u64],
-    positions: &[u64],
-    table: &mut PosTable,
-    code: u16,
-    bits: u8,
-    depth: u64,
-    max_depth: u64,
-) -> Result<usize, CompressionError> {
-    if depths.is_empty() {
-        return Ok(0);
+    /// Attach sampled word offsets, making [`Decompressor::word_offset`]
+    /// skip fewer than `stride` words when there is no `.idx`
+    pub fn attach_offset_table(&mut self, table: OffsetTable) -> Result<(), CompressionError> {
+        table
+            .check(self)
+            .map_err(|reason| DecompressError::OffsetTable {
+                file: self.file_name.clone(),
+                reason,
+            })?;
+        self.offsets = Some(table);
+        Ok(())
     }
 
-    if depth == depths[0] {
-        let pos = positions[0];
-        if table.bit_len == bits as usize {
-            table.pos[code as usize] = pos;
-            table.lens[code as usize] = bits;
-        } else {
-            let code_step = 1u16 << bits;
-            let code_from = code;
-            let code_to = code | (1u16 << table.bit_len);
-            let mut c = code_from;
-            while c < code_to {
-                table.pos[c as usize] = pos;
-                table.lens[c as usize] = bits;
-                c += code_step;
+    /// The attached offset table, if any
+    pub fn offset_table(&self) -> Option<&OffsetTable> {
+        self.offsets.as_ref()
+    }
+
+    /// Offset of the `i`-th word, to pass to [`Getter::reset`]
+    ///
+    /// Without an attached index the words before it are skipped one by
+    /// one, from the nearest sampled word if an offset table is attached.
+    pub fn word_offset(&self, i: u64) -> Option<u64> {
+        if i >= self.words_count {
+            return None;
+        }
+        if let Some(idx) = &self.index {
+            return idx.ordinal_lookup(i);
+        }
+        let mut getter = self.make_getter();
+        let (from, offset) = match self.offsets.as_ref().and_then(|t| t.floor(i)) {
+            Some(sampled) => sampled,
+            None => {
+                tracing::debug!("No index for {}, skipping to word {}", self.file_name, i);
+                (0, 0)
+            }
+        };
+        getter.reset(offset);
+        for _ in from..i {
+            if !getter.has_next() {
+                return None;
             }
+            getter.try_skip().ok()?;
         }
-        log::debug!("  Table[code={}] = pos={}, len={}", code, pos, bits);
-        return Ok(1);
-    }
-
-    // Handle bits == 9 case (matching Go's logic)
-    if bits == 9 {
-        let bit_len = if max_depth > 9 { 9 } else { max_depth as usize };
-        let mut new_table = PosTable::new(bit_len);
-        table.pos[code as usize] = 0;
-        table.lens[code as usize] = 0;
-        // Note: In Go, this creates a new sub-table via ptrs field
-        // For now, we'll just continue with the same table
-        // This is a simplification that may need to be revisited
-        return build_pos_table_recursive(
-            depths,
-            positions,
-            &mut new_table,
-            0,
-            0,
+        Some(getter.offset())
+    }
+
+    /// Binary search a segment whose words are sorted, like
+    /// [`slice::binary_search_by`]: `cmp` orders each probed word against the
+    /// target, and the result is `Ok` with the ordinal of a matching word or
+    /// `Err` with the ordinal the target would be inserted at
+    ///
+    /// Probes are located through the attached `.idx`; without one the
+    /// words are compared one by one from the start instead.
+    pub fn binary_search_by(
+        &self,
+        mut cmp: impl FnMut(&[u8]) -> std::cmp::Ordering,
+    ) -> Result<std::result::Result<u64, u64>, CompressionError> {
+        let mut getter = self.make_getter();
+        let mut word = Vec::new();
+        let Some(idx) = &self.index else {
+            tracing::debug!("No index for {}, searching word by word", self.file_name);
+            let mut ordinal = 0;
+            while getter.has_next() {
+                word.clear();
+                word = getter.try_next(word)?.0;
+                match cmp(&word) {
+                    std::cmp::Ordering::Less => ordinal += 1,
+                    std::cmp::Ordering::Equal => return Ok(Ok(ordinal)),
+                    std::cmp::Ordering::Greater => return Ok(Err(ordinal)),
+                }
+            }
+            return Ok(Err(ordinal));
+        };
+
+        let (mut lo, mut hi) = (0, self.words_count);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let offset = idx
+                .ordinal_lookup(mid)
+                .ok_or(IndexError::MissingOffset { ordinal: mid })?;
+            getter.reset(offset);
+            word.clear();
+            word = getter.try_next(word)?.0;
+            match cmp(&word) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Equal => return Ok(Ok(mid)),
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        Ok(Err(lo))
+    }
+
+    /// The `i`-th word, or `None` if there are not that many words
+    pub fn get_word(&self, i: u64) -> Option<Vec<u8>> {
+        let offset = self.word_offset(i)?;
+        let mut getter = self.make_getter();
+        getter.reset(offset);
+        getter.has_next().then(|| getter.next(Vec::new()).0)
+    }
+
+    /// Write every word to `path` in `format`, for tools that don't read
+    /// `.seg` files (see [`crate::export`]); returns the number of words
+    pub fn export(
+        &self,
+        path: impl AsRef<Path>,
+        format: ExportFormat,
+    ) -> Result<u64, CompressionError> {
+        crate::export::export_words(self, path.as_ref(), format, false)
+    }
+
+    /// Stream every word to `writer`, delimited by `separator`; returns the
+    /// number of words
+    ///
+    /// Words are decoded into one reused buffer, so nothing is allocated per
+    /// word. `writer` gets a few small writes per word and is best buffered.
+    pub fn decompress_to(
+        &self,
+        mut writer: impl std::io::Write,
+        separator: WordSeparator,
+    ) -> Result<u64, CompressionError> {
+        let words = crate::export::write_words(self, &mut writer, &separator)?;
+        writer.flush()?;
+        Ok(words)
+    }
+
+    /// Write the words in `range` to `writer` as a segment of their own,
+    /// copying their bytes and both dictionaries without decoding them;
+    /// returns the number of bytes written
+    ///
+    /// The copy has no checksum footer, and its dictionaries may hold
+    /// patterns and positions none of its words use.
+    pub fn copy_words(
+        &self,
+        range: Range<u64>,
+        mut writer: impl std::io::Write,
+    ) -> Result<u64, CompressionError> {
+        if range.start > range.end || range.end > self.words_count {
+            return Err(DecompressError::WordRange {
+                file: self.file_name.clone(),
+                start: range.start,
+                end: range.end,
+                count: self.words_count,
+            }
+            .into());
+        }
+        let mut getter = self.make_getter();
+        if !range.is_empty() {
+            let offset = self
+                .word_offset(range.start)
+                .ok_or(CompressionError::CorruptedData)?;
+            getter.reset(offset);
+        }
+        let start = getter.offset() as usize;
+        let mut empty_words = 0u64;
+        for _ in range.clone() {
+            if !getter.has_next() {
+                return Err(CompressionError::UnexpectedEof);
+            }
+            if getter.next_raw()?.1 == 0 {
+                empty_words += 1;
+            }
+        }
+        let words = &getter.reader.data()[start..getter.offset() as usize];
+
+        // The header after the two counts is the dictionaries, with their sizes
+        let dictionaries = &self.data[SegmentHeader::LEN..self.words_start as usize];
+        let header = SegmentHeader {
+            word_count: range.end - range.start,
+            empty_word_count: empty_words,
+        };
+        writer.write_all(&header.to_bytes())?;
+        writer.write_all(dictionaries)?;
+        writer.write_all(words)?;
+        Ok((SegmentHeader::LEN + dictionaries.len() + words.len()) as u64)
+    }
+
+    pub fn close(mut self) {
+        self.f = None;
+    }
+}
+
+// A read failure located in `file`
+fn malformed(file: &str) -> impl Fn(ReadError) -> DecompressError + '_ {
+    move |source| DecompressError::Read {
+        file: file.to_string(),
+        source,
+    }
+}
+
+// Attach the file name to a failure of the core dictionary parser
+fn dict_error(file: &str) -> impl Fn(DecodeError) -> CompressionError + '_ {
+    move |e| match e {
+        DecodeError::Read(source) => malformed(file)(source).into(),
+        DecodeError::DepthOverflow {
+            dict,
+            offset,
             depth,
-            max_depth,
-        );
+            max,
+        } => DecompressError::DepthOverflow {
+            file: file.to_string(),
+            dict,
+            offset,
+            depth,
+            max,
+        }
+        .into(),
+        DecodeError::PatternOutOfBounds {
+            offset,
+            size,
+            dict_size,
+        } => DecompressError::PatternOutOfBounds {
+            file: file.to_string(),
+            offset,
+            size,
+            dict_size,
+        }
+        .into(),
+        DecodeError::TableTooLarge { table, limit } => DecompressError::TableTooLarge {
+            file: file.to_string(),
+            table,
+            limit,
+        }
+        .into(),
+        e => e.into(),
     }
+}
 
-    // Check for max_depth to prevent underflow (matching Go's check)
-    if max_depth == 0 {
-        return Err(CompressionError::Other(
-            "buildPosTable: max_depth reached zero".to_string(),
-        ));
+// Compare the bytes of `target` at `at..` with `chunk`, the word's bytes
+// from `at`, cut at the word's `len` compared bytes; `None` while they agree
+fn cmp_chunk(target: &[u8], at: usize, chunk: &[u8], len: usize) -> Option<std::cmp::Ordering> {
+    let end = at.saturating_add(chunk.len()).min(len);
+    if at >= end {
+        return None;
     }
+    let chunk = &chunk[..end - at];
+    let target = target.get(at..).unwrap_or_default();
+    let n = chunk.len().min(target.len());
+    match target[..n].cmp(&chunk[..n]) {
+        // `target` ends inside the word
+        std::cmp::Ordering::Equal if n < chunk.len() => Some(std::cmp::Ordering::Less),
+        std::cmp::Ordering::Equal => None,
+        cmp => Some(cmp),
+    }
+}
 
-    // Recursive split like Go
-    let b0 = build_pos_table_recursive(
-        depths,
-        positions,
-        table,
-        code,
-        bits + 1,
-        depth + 1,
-        max_depth - 1,
+/// Check the Huffman codes the compressor assigned, in dictionary order,
+/// before they are written
+///
+/// Every code must be as long as its depth, no deeper than
+/// [`MAX_ALLOWED_DEPTH`], and prefix-free; and the tables built from the
+/// depths alone, as [`Decompressor::new`] builds them, must decode each code
+/// back to its own entry. A dictionary sorted differently from how the codes
+/// were assigned fails the last check.
+pub(crate) fn verify_codes(
+    patterns: &[Pattern],
+    positions: &[Position],
+) -> Result<(), CompressionError> {
+    check_code_shapes(
+        "pattern",
+        patterns.iter().map(|p| (p.code, p.code_bits, p.depth)),
     )?;
-    let b1 = build_pos_table_recursive(
-        &depths[b0..],
-        &positions[b0..],
-        table,
-        (1u16 << bits) | code,
-        bits + 1,
-        depth + 1,
-        max_depth - 1,
+    check_code_shapes(
+        "position",
+        positions.iter().map(|p| (p.code, p.code_bits, p.depth)),
     )?;
-    Ok(b0 + b1)
+
+    // A single entry has an empty code, decoded without reading any bits
+    if patterns.len() > 1 {
+        let depths: Vec<u64> = patterns.iter().map(|p| p.depth as u64).collect();
+        let words: Vec<Vec<u8>> = patterns.iter().map(|p| p.word.clone()).collect();
+        let table = PatternTable::build(
+            &depths,
+            &words,
+            DEFAULT_CONDENSE_PATTERN_TABLE_BIT_THRESHOLD,
+            &mut TableBudget::unlimited(),
+        )?;
+        for (i, p) in patterns.iter().enumerate() {
+            let buf = code_bytes(p.code);
+            let mut getter = Getter::for_tables(Some(&table), None, &buf);
+            let found = getter.try_next_pattern()?;
+            if found != p.word.as_slice() || getter.bits_read() != p.code_bits {
+                return Err(invalid_code(
+                    "pattern",
+                    i,
+                    format!(
+                        "code {:b} decodes to {} ({} bits), expected {} ({} bits)",
+                        p.code,
+                        hex::encode(found),
+                        getter.bits_read(),
+                        hex::encode(&p.word),
+                        p.code_bits
+                    ),
+                ));
+            }
+        }
+    }
+    if positions.len() > 1 {
+        let depths: Vec<u64> = positions.iter().map(|p| p.depth as u64).collect();
+        let values: Vec<u64> = positions.iter().map(|p| p.pos).collect();
+        let table = PosTable::build(&depths, &values, &mut TableBudget::unlimited())?;
+        for (i, p) in positions.iter().enumerate() {
+            let buf = code_bytes(p.code);
+            let mut getter = Getter::for_tables(None, Some(&table), &buf);
+            let found = getter.try_next_pos(false)?;
+            if found != p.pos || getter.bits_read() != p.code_bits {
+                return Err(invalid_code(
+                    "position",
+                    i,
+                    format!(
+                        "code {:b} decodes to {} ({} bits), expected {} ({} bits)",
+                        p.code,
+                        found,
+                        getter.bits_read(),
+                        p.pos,
+                        p.code_bits
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn invalid_code(dict: &'static str, index: usize, reason: String) -> CompressionError {
+    CompressError::InvalidHuffmanCode {
+        dict,
+        index,
+        reason,
+    }
+    .into()
+}
+
+// A code as the getter reads it, LSB first, padded for its lookahead
+fn code_bytes(code: u64) -> [u8; 16] {
+    let mut buf = [0u8; 16];
+    buf[..8].copy_from_slice(&code.to_le_bytes());
+    buf
+}
+
+// Check `(code, code_bits, depth)` of each entry: lengths and depths agree
+// and stay within MAX_ALLOWED_DEPTH, and no code is a prefix of another,
+// walking the codes LSB first through a binary trie
+fn check_code_shapes(
+    dict: &'static str,
+    codes: impl ExactSizeIterator<Item = (u64, usize, usize)>,
+) -> Result<(), CompressionError> {
+    const NONE: u32 = u32::MAX;
+    let single = codes.len() == 1;
+    // Children of each trie node, and which nodes end a code
+    let mut children = vec![[NONE; 2]];
+    let mut is_code = vec![false];
+    for (i, (code, bits, depth)) in codes.enumerate() {
+        if bits != depth {
+            return Err(invalid_code(
+                dict,
+                i,
+                format!("{} bits but depth {}", bits, depth),
+            ));
+        }
+        if depth as u64 > MAX_ALLOWED_DEPTH || (bits == 0 && !single) {
+            return Err(invalid_code(dict, i, format!("depth {}", depth)));
+        }
+        if bits < 64 && code >> bits != 0 {
+            return Err(invalid_code(
+                dict,
+                i,
+                format!("code {:b} longer than {} bits", code, bits),
+            ));
+        }
+        let mut node = 0;
+        for b in 0..bits {
+            if is_code[node] {
+                return Err(invalid_code(dict, i, "extends a shorter code".to_string()));
+            }
+            let bit = ((code >> b) & 1) as usize;
+            if children[node][bit] == NONE {
+                children[node][bit] = children.len() as u32;
+                children.push([NONE; 2]);
+                is_code.push(false);
+            }
+            node = children[node][bit] as usize;
+        }
+        if is_code[node] || children[node] != [NONE; 2] {
+            return Err(invalid_code(
+                dict,
+                i,
+                "is a prefix of another code".to_string(),
+            ));
+        }
+        is_code[node] = true;
+    }
+    Ok(())
+}
+
+/// Position of a [`Getter`], from [`Getter::save_state`]
+///
+/// Restoring it with [`Getter::restore_state`] resumes decoding exactly where
+/// the getter was, so callers can look ahead and come back without
+/// re-seeking through the index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GetterState {
+    offset: u64,
+    bit_offset: usize,
+}
+
+impl GetterState {
+    /// Byte offset the getter was at
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
 }
 
 // From Go: decompress.go:537
@@ -583,10 +1262,12 @@ pub struct Getter<'a> {
     pattern_dict: Option<&'a PatternTable>,
     pos_dict: Option<&'a PosTable>,
     file_name: String,
-    data: Vec<u8>,
-    pub data_p: u64, // Current position in data
-    data_bit: usize, // Current bit position (0..7)
+    reader: BitReader<'a>,
+    max_pattern_len: usize,
     trace: bool,
+    // The segment read, for readahead; `None` when checking bare tables
+    segment: Option<&'a Decompressor>,
+    prefetcher: Option<Prefetcher>,
 }
 
 impl<'a> Getter<'a> {
@@ -599,207 +1280,201 @@ impl<'a> Getter<'a> {
         &self.file_name
     }
 
-    // From Go: decompress.go:550
-    fn next_pos(&mut self, clean: bool) -> u64 {
-        log::debug!(
-            "next_pos called, clean: {}, data_p: {}, data_bit: {}",
-            clean,
-            self.data_p,
-            self.data_bit
-        );
-        if clean && self.data_bit > 0 {
-            self.data_p += 1;
-            self.data_bit = 0;
+    // A getter decoding codes from `data` with bare tables, to check them
+    fn for_tables(
+        pattern_dict: Option<&'a PatternTable>,
+        pos_dict: Option<&'a PosTable>,
+        data: &'a [u8],
+    ) -> Self {
+        Getter {
+            pattern_dict,
+            pos_dict,
+            file_name: String::new(),
+            reader: BitReader::new(data),
+            max_pattern_len: 0,
+            trace: false,
+            segment: None,
+            prefetcher: None,
         }
+    }
 
-        let table = match self.pos_dict {
-            Some(t) => t,
-            None => {
-                // No position dictionary - read varint directly from data
-                if self.data_p >= self.data.len() as u64 {
-                    return 0;
-                }
-                let (pos, size) =
-                    decode_varint(&self.data[self.data_p as usize..]).unwrap_or((0, 0));
-                self.data_p += size as u64;
-                return pos;
-            }
+    /// Read ahead of the getter as it moves from its position to the end of
+    /// the segment
+    ///
+    /// Replaces an earlier setting, stopping its prefetch reads if it had
+    /// any. Advice only applies to memory mapped segments; where there is
+    /// none, or the segment can't be reopened for prefetching, this does
+    /// nothing.
+    pub fn read_ahead(&mut self, read_ahead: ReadAhead) {
+        self.read_ahead_until(read_ahead, self.reader.data().len() as u64);
+    }
+
+    /// Like [`Getter::read_ahead`], for a scan that stops at byte offset
+    /// `end`
+    pub fn read_ahead_until(&mut self, read_ahead: ReadAhead, end: u64) {
+        self.prefetcher = None;
+        let Some(segment) = self.segment else {
+            return;
         };
+        let start = self.offset();
+        let end = end.min(self.reader.data().len() as u64).max(start);
+        self.prefetcher = segment.read_ahead(read_ahead, start..end);
+    }
 
-        if table.bit_len == 0 {
-            log::debug!("next_pos: table.bit_len is 0");
-            // Empty position table - read varint directly from data
-            if table.pos.is_empty() || (table.pos.len() == 1 && table.pos[0] == 0) {
-                log::debug!("next_pos: reading varint from data");
-                if self.data_p >= self.data.len() as u64 {
-                    return 0;
-                }
-                let (pos, size) =
-                    decode_varint(&self.data[self.data_p as usize..]).unwrap_or((0, 0));
-                self.data_p += size as u64;
-                return pos;
-            }
-            return table.pos[0];
+    // Let the prefetcher know where the getter is
+    fn advance_prefetcher(&self) {
+        if let Some(prefetcher) = &self.prefetcher {
+            prefetcher.advance(self.reader.position());
         }
+    }
 
-        let mut current_table = table;
-        log::debug!(
-            "next_pos: entering main loop with bit_len: {}",
-            current_table.bit_len
-        );
-        loop {
-            let mut code = (self.data[self.data_p as usize] >> self.data_bit) as u16;
-            if 8 - self.data_bit < current_table.bit_len
-                && (self.data_p as usize) + 1 < self.data.len()
-            {
-                code |= (self.data[self.data_p as usize + 1] as u16) << (8 - self.data_bit);
-            }
-            code &= (1u16 << current_table.bit_len) - 1;
-
-            log::debug!(
-                "next_pos: byte={:08b}, data_bit={}, bit_len={}, raw_code={}, final_code={}",
-                self.data[self.data_p as usize],
-                self.data_bit,
-                current_table.bit_len,
-                (self.data[self.data_p as usize] >> self.data_bit),
-                code
-            );
+    // Bits consumed since the start of the data
+    fn bits_read(&self) -> usize {
+        self.reader.position() as usize * 8 + self.reader.bit_offset()
+    }
 
-            let l = current_table.lens[code as usize];
-            let pos_val = current_table.pos[code as usize];
-            log::debug!(
-                "next_pos: lens[{}] = {}, pos[{}] = {}",
-                code,
-                l,
-                code,
-                pos_val
-            );
-            if l == 0 {
-                // Navigate to deeper table
-                if let Some(ref next_table) = current_table.ptrs[code as usize] {
-                    log::debug!("next_pos: navigating to deeper table");
-                    current_table = next_table;
-                    self.data_bit += 9;
-                } else {
-                    log::debug!("next_pos: no deeper table, returning 0");
-                    return 0;
-                }
-            } else {
-                self.data_bit += l as usize;
-                let pos = current_table.pos[code as usize];
-                self.data_p += (self.data_bit / 8) as u64;
-                self.data_bit %= 8;
-                log::debug!("next_pos returning position: {}", pos);
-                return pos;
-            }
+    /// Byte offset of the next word, the value `.idx` files store
+    pub fn offset(&self) -> u64 {
+        self.reader.position()
+    }
 
-            self.data_p += (self.data_bit / 8) as u64;
-            self.data_bit %= 8;
+    /// Bookmark the current position
+    pub fn save_state(&self) -> GetterState {
+        GetterState {
+            offset: self.reader.position(),
+            bit_offset: self.reader.bit_offset(),
         }
     }
 
-    // From Go: decompress.go:584
-    fn next_pattern(&mut self) -> Vec<u8> {
-        let table = match self.pattern_dict {
-            Some(t) => t,
-            None => return Vec::new(),
-        };
+    /// Go back to a position bookmarked with [`Getter::save_state`] on a
+    /// getter of the same segment
+    pub fn restore_state(&mut self, state: GetterState) {
+        self.reader.restore(state.offset, state.bit_offset);
+        self.advance_prefetcher();
+    }
 
-        if table.bit_len == 0 {
-            return table.patterns[0]
-                .as_ref()
-                .map(|cw| cw.pattern.clone())
-                .unwrap_or_default();
-        }
+    // From Go: decompress.go:550
+    fn next_pos(&mut self, clean: bool) -> u64 {
+        self.try_next_pos(clean).unwrap_or_else(|e| {
+            tracing::error!(
+                "next_pos failed at data_p={}: {}",
+                self.reader.position(),
+                e
+            );
+            // The rest of the data can't be decoded; stop iteration instead
+            // of failing at the same spot forever
+            self.reader.seek(self.reader.data().len() as u64);
+            0
+        })
+    }
 
-        let mut current_table = table;
-        loop {
-            let mut code = (self.data[self.data_p as usize] >> self.data_bit) as u16;
-            if 8 - self.data_bit < current_table.bit_len
-                && (self.data_p as usize) + 1 < self.data.len()
-            {
-                code |= (self.data[self.data_p as usize + 1] as u16) << (8 - self.data_bit);
-            }
-            code &= (1u16 << current_table.bit_len) - 1;
+    // Upper bound on the length of the next word: each byte is either stored
+    // uncovered or part of a pattern, and each pattern takes at least a bit
+    fn max_word_len(&self) -> u64 {
+        let remaining = self.reader.remaining().len() as u64;
+        remaining.saturating_mul(8 * self.max_pattern_len as u64 + 1)
+    }
 
-            log::debug!(
-                "next_pattern: reading at data_p={}, data_bit={}, code={}",
-                self.data_p,
-                self.data_bit,
-                code
-            );
+    // Bounds-checked variant of next_pos: running off the end of the data or
+    // hitting a code with no table entry is reported instead of panicking
+    fn try_next_pos(&mut self, clean: bool) -> Result<u64, CompressionError> {
+        word_trace!(
+            "next_pos called, clean: {}, data_p: {}, data_bit: {}",
+            clean,
+            self.reader.position(),
+            self.reader.bit_offset()
+        );
+        Ok(next_pos(self.pos_dict, &mut self.reader, clean)?)
+    }
 
-            if let Some(cw) = current_table.condensed_table_search(code) {
-                log::debug!(
-                    "next_pattern: found pattern with code={}, len={}, pattern={:?}",
-                    cw.code,
-                    cw.len,
-                    String::from_utf8_lossy(&cw.pattern)
+    // From Go: decompress.go:584
+    fn next_pattern(&mut self) -> Vec<u8> {
+        match self.try_next_pattern() {
+            Ok(pattern) => pattern.to_vec(),
+            Err(e) => {
+                tracing::error!(
+                    "next_pattern failed at data_p={}: {}",
+                    self.reader.position(),
+                    e
                 );
-                let l = cw.len;
-                if l == 0 {
-                    if let Some(ref ptr) = cw.ptr {
-                        current_table = ptr;
-                        self.data_bit += 9;
-                    } else {
-                        return Vec::new();
-                    }
-                } else {
-                    self.data_bit += l as usize;
-                    let pattern = cw.pattern.clone();
-                    self.data_p += (self.data_bit / 8) as u64;
-                    self.data_bit %= 8;
-                    return pattern;
-                }
-            } else {
-                return Vec::new();
+                Vec::new()
             }
-
-            self.data_p += (self.data_bit / 8) as u64;
-            self.data_bit %= 8;
         }
     }
 
+    // Bounds-checked variant of next_pattern that borrows the pattern from the
+    // dictionary instead of cloning it
+    fn try_next_pattern(&mut self) -> Result<&'a [u8], CompressionError> {
+        Ok(next_pattern(self.pattern_dict, &mut self.reader)?)
+    }
+
     // From Go: decompress.go:657
     pub fn reset(&mut self, offset: u64) {
-        self.data_p = offset;
-        self.data_bit = 0;
+        self.reader.seek(offset);
+        self.advance_prefetcher();
     }
 
     // From Go: decompress.go:662
     pub fn has_next(&self) -> bool {
-        self.data_p < self.data.len() as u64
+        self.reader.position() < self.reader.data().len() as u64
+    }
+
+    /// Append the next word to `buf`, decoded with the word codec of the
+    /// segment if it has one; returns the buffer and the position after the
+    /// word
+    pub fn next(&mut self, buf: Vec<u8>) -> (Vec<u8>, u64) {
+        let offset = buf.len();
+        let (buf, pos) = self.next_stored(buf);
+        (self.decode_word(buf, offset), pos)
     }
 
+    // Decode the stored word at `offset..` of `buf` in place
+    fn decode_word(&self, mut buf: Vec<u8>, offset: usize) -> Vec<u8> {
+        if let Some(codec) = self.segment.and_then(|segment| segment.codec.as_deref()) {
+            let stored = buf.split_off(offset);
+            codec.decode(&stored, &mut buf);
+        }
+        buf
+    }
+
+    // The next word as stored in the segment
     // From Go: decompress.go:669
-    pub fn next(&mut self, mut buf: Vec<u8>) -> (Vec<u8>, u64) {
-        log::debug!(
+    fn next_stored(&mut self, mut buf: Vec<u8>) -> (Vec<u8>, u64) {
+        self.advance_prefetcher();
+        let data = self.reader.data();
+        word_trace!(
             "Getter::next called, data_p: {}, data_len: {}, next 10 bytes: {:02x?}",
-            self.data_p,
-            self.data.len(),
-            &self.data
-                [self.data_p as usize..std::cmp::min(self.data_p as usize + 10, self.data.len())]
+            self.reader.position(),
+            data.len(),
+            &self.reader.remaining()[..self.reader.remaining().len().min(10)]
         );
-        let save_pos = self.data_p;
+        let save_pos = self.reader.position();
         let mut word_len = self.next_pos(true);
-        log::debug!("Got word_len (raw): {}", word_len);
+        word_trace!("Got word_len (raw): {}", word_len);
 
         word_len = word_len.saturating_sub(1); // because when creating huffman tree we do ++, because 0 is terminator
-        log::debug!("Adjusted word_len: {}", word_len);
+        word_trace!("Adjusted word_len: {}", word_len);
+        if word_len > self.max_word_len() {
+            tracing::error!(
+                "Word length {} at data_p={} exceeds the remaining data",
+                word_len,
+                save_pos
+            );
+            self.reader.seek(data.len() as u64);
+            return (buf, self.reader.position());
+        }
 
         if word_len == 0 {
-            if self.data_bit > 0 {
-                self.data_p += 1;
-                self.data_bit = 0;
-            }
-            log::debug!("Returning empty word");
+            self.reader.align_to_byte();
+            metrics::increment(metrics::WORDS_DECOMPRESSED, 1);
+            word_trace!("Returning empty word");
             // Empty word
-            return (buf, self.data_p);
+            return (buf, self.reader.position());
         }
 
         let buf_offset = buf.len();
         buf.resize(buf_offset + word_len as usize, 0);
-        log::debug!(
+        word_trace!(
             "Resized buffer to {} bytes (word_len={})",
             buf.len(),
             word_len
@@ -808,63 +1483,65 @@ impl<'a> Getter<'a> {
         // First pass: fill in the patterns
         let mut buf_pos = buf_offset;
         let mut pos = self.next_pos(false);
-        log::debug!("First pass - initial position: {}", pos);
+        word_trace!("First pass - initial position: {}", pos);
         let mut pattern_count = 0;
         while pos != 0 {
             pattern_count += 1;
-            log::debug!("Pattern {}: position={}", pattern_count, pos);
-            buf_pos += pos as usize - 1;
+            word_trace!("Pattern {}: position={}", pattern_count, pos);
+            buf_pos = buf_pos.saturating_add(pos as usize - 1);
             let pattern = self.next_pattern();
-            log::debug!(
+            word_trace!(
                 "Pattern {}: content={:?} (len={})",
                 pattern_count,
                 String::from_utf8_lossy(&pattern),
                 pattern.len()
             );
-            if buf_pos + pattern.len() <= buf.len() {
-                buf[buf_pos..buf_pos + pattern.len()].copy_from_slice(&pattern);
-                log::debug!(
-                    "Pattern {}: copied to buffer at pos {}",
-                    pattern_count,
-                    buf_pos
-                );
-            } else {
-                log::error!(
-                    "Pattern {} would overflow buffer: buf_pos={}, pattern_len={}, buf_len={}",
+            // Positions are validated against the word length before any
+            // copy; one pointing past the word means the data is corrupted
+            // and the second pass could not place uncovered bytes either
+            if buf_pos.saturating_add(pattern.len()) > buf.len() {
+                tracing::error!(
+                    "Pattern {} at data_p={} overflows the word: buf_pos={}, pattern_len={}, buf_len={}",
                     pattern_count,
+                    save_pos,
                     buf_pos,
                     pattern.len(),
                     buf.len()
                 );
+                buf.truncate(buf_offset);
+                self.reader.seek(data.len() as u64);
+                return (buf, self.reader.position());
             }
+            buf[buf_pos..buf_pos + pattern.len()].copy_from_slice(&pattern);
+            word_trace!(
+                "Pattern {}: copied to buffer at pos {}",
+                pattern_count,
+                buf_pos
+            );
             pos = self.next_pos(false);
-            log::debug!("Pattern {}: next position={}", pattern_count, pos);
+            word_trace!("Pattern {}: next position={}", pattern_count, pos);
         }
-        log::debug!("First pass complete: processed {} patterns", pattern_count);
+        word_trace!("First pass complete: processed {} patterns", pattern_count);
 
-        if self.data_bit > 0 {
-            self.data_p += 1;
-            self.data_bit = 0;
-        }
-        let post_loop_pos = self.data_p;
-        log::debug!("post_loop_pos: {}", post_loop_pos);
+        self.reader.align_to_byte();
+        let mut post_loop_pos = self.reader.position();
+        word_trace!("post_loop_pos: {}", post_loop_pos);
 
         // Reset to read positions again
-        self.data_p = save_pos;
-        self.data_bit = 0;
+        self.reader.seek(save_pos);
         self.next_pos(true); // Reset the state
-        log::debug!("Reset to save_pos: {}", save_pos);
+        word_trace!("Reset to save_pos: {}", save_pos);
 
         // Second pass: fill in uncovered data
         buf_pos = buf_offset;
         let mut last_uncovered = buf_offset;
         pos = self.next_pos(false);
-        log::debug!("Second pass - initial position: {}", pos);
+        word_trace!("Second pass - initial position: {}", pos);
         let mut uncovered_pattern_count = 0;
         while pos != 0 {
             uncovered_pattern_count += 1;
-            buf_pos += pos as usize - 1;
-            log::debug!(
+            buf_pos = buf_pos.saturating_add(pos as usize - 1);
+            word_trace!(
                 "Uncovered pattern {}: buf_pos={}, last_uncovered={}",
                 uncovered_pattern_count,
                 buf_pos,
@@ -872,7 +1549,7 @@ impl<'a> Getter<'a> {
             );
             if buf_pos > last_uncovered {
                 let dif = buf_pos - last_uncovered;
-                log::debug!(
+                word_trace!(
                     "Uncovered pattern {}: copying {} bytes from pos {} to buffer range [{}..{}]",
                     uncovered_pattern_count,
                     dif,
@@ -880,22 +1557,23 @@ impl<'a> Getter<'a> {
                     last_uncovered,
                     buf_pos
                 );
-                if post_loop_pos as usize + dif <= self.data.len() && buf_pos <= buf.len() {
+                if post_loop_pos as usize + dif <= data.len() && buf_pos <= buf.len() {
                     buf[last_uncovered..buf_pos].copy_from_slice(
-                        &self.data[post_loop_pos as usize..post_loop_pos as usize + dif],
+                        &data[post_loop_pos as usize..post_loop_pos as usize + dif],
                     );
+                    post_loop_pos += dif as u64;
                 } else {
-                    log::error!(
+                    tracing::error!(
                         "Not enough uncovered data: need {} bytes at pos {}, but data len is {}",
                         dif,
                         post_loop_pos,
-                        self.data.len()
+                        data.len()
                     );
                 }
             }
             let pattern_len = self.next_pattern().len();
-            last_uncovered = buf_pos + pattern_len;
-            log::debug!(
+            last_uncovered = buf_pos.saturating_add(pattern_len);
+            word_trace!(
                 "Uncovered pattern {}: pattern_len={}, last_uncovered={}",
                 uncovered_pattern_count,
                 pattern_len,
@@ -903,13 +1581,13 @@ impl<'a> Getter<'a> {
             );
             pos = self.next_pos(false);
         }
-        log::debug!(
+        word_trace!(
             "Second pass complete: processed {} uncovered patterns",
             uncovered_pattern_count
         );
 
         // Fill any remaining uncovered data
-        log::debug!(
+        word_trace!(
             "Final uncovered check: buf_offset={}, word_len={}, last_uncovered={}",
             buf_offset,
             word_len,
@@ -917,29 +1595,26 @@ impl<'a> Getter<'a> {
         );
         if buf_offset + word_len as usize > last_uncovered {
             let dif = buf_offset + word_len as usize - last_uncovered;
-            log::debug!(
+            word_trace!(
                 "Copying {} final uncovered bytes from position {} to buffer range [{}..{}]",
                 dif,
                 post_loop_pos,
                 last_uncovered,
                 last_uncovered + dif
             );
-            if post_loop_pos as usize + dif <= self.data.len() {
-                let mut final_data = vec![0u8; dif];
-                final_data.copy_from_slice(
-                    &self.data[post_loop_pos as usize..post_loop_pos as usize + dif],
-                );
-                buf[last_uncovered..last_uncovered + dif].copy_from_slice(&final_data);
-                log::debug!(
+            if post_loop_pos as usize + dif <= data.len() {
+                let final_data = &data[post_loop_pos as usize..post_loop_pos as usize + dif];
+                buf[last_uncovered..last_uncovered + dif].copy_from_slice(final_data);
+                word_trace!(
                     "Final uncovered data: {:?}",
-                    String::from_utf8_lossy(&final_data)
+                    String::from_utf8_lossy(final_data)
                 );
             } else {
-                log::error!(
+                tracing::error!(
                     "Not enough data: need {} bytes at pos {}, but data len is {}",
                     dif,
                     post_loop_pos,
-                    self.data.len()
+                    data.len()
                 );
             }
         }
@@ -947,66 +1622,245 @@ impl<'a> Getter<'a> {
         // Calculate how many uncovered bytes we need to skip
         let final_pos = buf_offset + word_len as usize;
         if final_pos > last_uncovered {
-            self.data_p = post_loop_pos + (final_pos - last_uncovered) as u64;
+            self.reader
+                .seek(post_loop_pos + (final_pos - last_uncovered) as u64);
         } else {
-            self.data_p = post_loop_pos;
+            self.reader.seek(post_loop_pos);
         }
-        self.data_bit = 0;
 
-        log::debug!(
+        word_trace!(
             "Final reconstructed word: {:?}",
             String::from_utf8_lossy(&buf[buf_offset..])
         );
-        (buf, self.data_p)
+        metrics::increment(metrics::WORDS_DECOMPRESSED, 1);
+        metrics::increment(metrics::BYTES_READ, word_len);
+        (buf, self.reader.position())
     }
 
-    // From Go: decompress.go:738-788
-    pub fn match_prefix(&self, prefix: &[u8]) -> bool {
-        if prefix.is_empty() {
-            return true;
-        }
+    /// Bounds-checked [`Getter::next`] for files that may be corrupt
+    ///
+    /// The word is walked with the checks of [`Decompressor::verify`] before
+    /// it is decoded, so a malformed word is reported instead of panicking or
+    /// decoding garbage. On error the getter is left where it was.
+    pub fn try_next(&mut self, buf: Vec<u8>) -> Result<(Vec<u8>, u64), CompressionError> {
+        self.peek().try_skip()?;
+        Ok(self.next(buf))
+    }
 
-        // Create a temporary getter to peek at the next word
-        let mut temp_getter = Getter {
+    // A copy of this getter to decode ahead with, leaving `self` in place
+    fn peek(&self) -> Getter<'a> {
+        Getter {
             pattern_dict: self.pattern_dict,
             pos_dict: self.pos_dict,
-            file_name: self.file_name.clone(),
-            data: self.data.clone(),
-            data_p: self.data_p,
-            data_bit: self.data_bit,
+            file_name: String::new(),
+            reader: self.reader.clone(),
+            max_pattern_len: self.max_pattern_len,
             trace: false,
+            segment: None,
+            prefetcher: None,
+        }
+    }
+
+    /// Whether the next word starts with `prefix`, without moving past it
+    ///
+    /// Compares the prefix against patterns and uncovered bytes as they are
+    /// decoded and stops at the first mismatch; the word is never assembled.
+    // From Go: decompress.go:813 MatchPrefix
+    pub fn match_prefix(&self, prefix: &[u8]) -> bool {
+        let mut g = self.peek();
+        let save_pos = g.reader.position();
+        let word_len = g.next_pos(true).saturating_sub(1) as usize; // 0 is the terminator
+        let prefix_len = prefix.len();
+        if word_len == 0 || word_len < prefix_len {
+            return prefix_len == word_len;
+        }
+
+        // First pass: patterns, only as far as the prefix goes
+        let mut buf_pos = 0usize;
+        let mut pos = g.next_pos(false);
+        while pos != 0 {
+            buf_pos = buf_pos.saturating_add(pos as usize - 1);
+            let pattern = g.try_next_pattern().unwrap_or_default();
+            if buf_pos < prefix_len {
+                let len = pattern.len().min(prefix_len - buf_pos);
+                if prefix[buf_pos..buf_pos + len] != pattern[..len] {
+                    return false;
+                }
+            }
+            pos = g.next_pos(false);
+        }
+        g.reader.align_to_byte();
+        let mut post_loop_pos = g.reader.position() as usize;
+
+        // Second pass: bytes between the patterns
+        g.reader.seek(save_pos);
+        g.next_pos(true);
+        let data = g.reader.data();
+        let mut last_uncovered = 0;
+        buf_pos = 0;
+        pos = g.next_pos(false);
+        while pos != 0 && last_uncovered < prefix_len {
+            buf_pos = buf_pos.saturating_add(pos as usize - 1);
+            if buf_pos > last_uncovered {
+                let dif = buf_pos - last_uncovered;
+                let len = dif.min(prefix_len - last_uncovered);
+                if data.get(post_loop_pos..post_loop_pos + len)
+                    != Some(&prefix[last_uncovered..last_uncovered + len])
+                {
+                    return false;
+                }
+                post_loop_pos = post_loop_pos.saturating_add(dif);
+            }
+            last_uncovered = buf_pos.saturating_add(g.try_next_pattern().map_or(0, <[u8]>::len));
+            pos = g.next_pos(false);
+        }
+        if prefix_len > last_uncovered {
+            let len = prefix_len - last_uncovered;
+            if data.get(post_loop_pos..post_loop_pos + len) != Some(&prefix[last_uncovered..]) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Compare `buf` with the next word, as `buf.cmp(word)`
+    ///
+    /// On `Equal` the getter moves past the word, like [`Getter::next`];
+    /// otherwise it stays in place. The word is compared piece by piece in
+    /// byte order as it is decoded and never assembled; the comparison stops
+    /// at the first differing byte.
+    // From Go: decompress.go:896 MatchCmp
+    pub fn match_cmp(&mut self, buf: &[u8]) -> std::cmp::Ordering {
+        // A malformed word sorts after everything, like a missing one
+        let Some((cmp, next_pos)) = self.cmp_word(buf, false) else {
+            return std::cmp::Ordering::Less;
+        };
+        if cmp.is_eq() {
+            self.advance_prefetcher();
+            self.reader.seek(next_pos);
+        }
+        cmp
+    }
+
+    /// Compare `prefix` with as many bytes of the next word, without moving
+    /// past it
+    ///
+    /// `Equal` if the word starts with `prefix`, so this is the ordering
+    /// binary searches over sorted keys need to find the first key with a
+    /// prefix. `prefix` sorts after a shorter word it starts with.
+    // From Go: decompress.go:843 MatchPrefixCmp
+    pub fn match_prefix_cmp(&self, prefix: &[u8]) -> std::cmp::Ordering {
+        self.cmp_word(prefix, true)
+            .map_or(std::cmp::Ordering::Less, |(cmp, _)| cmp)
+    }
+
+    // Compare `target` with the next word, or its first `target.len()` bytes
+    // if `prefix`; returns the ordering and the position after the word,
+    // which is only known when the whole word was compared. `None` if the
+    // word runs past the data.
+    fn cmp_word(&self, target: &[u8], prefix: bool) -> Option<(std::cmp::Ordering, u64)> {
+        if self.segment.is_some_and(|segment| segment.codec.is_some()) {
+            // Stored words are encoded, only whole decoded words compare
+            let (word, next_pos) = self.peek_decoded();
+            let word = if prefix {
+                &word[..word.len().min(target.len())]
+            } else {
+                &word[..]
+            };
+            return Some((target.cmp(word), next_pos));
+        }
+
+        let mut g = self.peek();
+        let save_pos = g.reader.position();
+        let word_len = g.next_pos(true).saturating_sub(1) as usize; // 0 is the terminator
+        if word_len == 0 {
+            g.reader.align_to_byte();
+            return Some((target.len().cmp(&0), g.reader.position()));
+        }
+        // Bytes of the word that take part in the comparison
+        let len = if prefix {
+            word_len.min(target.len())
+        } else {
+            word_len
         };
 
-        // Decompress the next word to check prefix
-        let (word, _) = temp_getter.next(Vec::new());
+        // Skip the patterns to find where the uncovered bytes start
+        let mut pos = g.next_pos(false);
+        while pos != 0 {
+            g.try_next_pattern().ok()?;
+            pos = g.next_pos(false);
+        }
+        g.reader.align_to_byte();
+        let mut uncovered = g.reader.position() as usize;
+
+        // Then walk uncovered bytes and patterns in word order
+        g.reader.seek(save_pos);
+        g.next_pos(true);
+        let data = g.reader.data();
+        let mut buf_pos = 0usize;
+        let mut last_uncovered = 0usize;
+        pos = g.next_pos(false);
+        while pos != 0 && last_uncovered < len {
+            buf_pos = buf_pos.saturating_add(pos as usize - 1);
+            if buf_pos > last_uncovered {
+                let dif = buf_pos - last_uncovered;
+                let chunk = data.get(uncovered..uncovered.checked_add(dif)?)?;
+                if let Some(cmp) = cmp_chunk(target, last_uncovered, chunk, len) {
+                    return Some((cmp, 0));
+                }
+                uncovered += dif;
+            }
+            let pattern = g.try_next_pattern().ok()?;
+            if let Some(cmp) = cmp_chunk(target, buf_pos, pattern, len) {
+                return Some((cmp, 0));
+            }
+            last_uncovered = buf_pos.saturating_add(pattern.len());
+            pos = g.next_pos(false);
+        }
+        // Whole words are read to the end, leaving `uncovered` after them
+        if len > last_uncovered {
+            let dif = word_len - last_uncovered;
+            let chunk = data.get(uncovered..uncovered.checked_add(dif)?)?;
+            if let Some(cmp) = cmp_chunk(target, last_uncovered, chunk, len) {
+                return Some((cmp, 0));
+            }
+            uncovered += dif;
+        }
+        Some((target.len().cmp(&len), uncovered as u64))
+    }
 
-        // Check if word starts with prefix
-        word.starts_with(prefix)
+    // The next decoded word and the position after it, leaving `self` in
+    // place
+    fn peek_decoded(&self) -> (Vec<u8>, u64) {
+        let mut g = self.peek();
+        g.segment = self.segment;
+        g.next(Vec::new())
     }
 
     // From Go: decompress.go:756-790
     pub fn skip(&mut self) -> (u64, usize) {
-        log::debug!("skip() called at data_p={}", self.data_p);
+        self.advance_prefetcher();
+        word_trace!("skip() called at data_p={}", self.reader.position());
         let mut word_len = self.next_pos(true);
-        log::debug!("skip(): word_len raw={}", word_len);
+        word_trace!("skip(): word_len raw={}", word_len);
 
         word_len = word_len.saturating_sub(1); // because when create huffman tree we do ++, because 0 is terminator
-        log::debug!("skip(): word_len adjusted={}", word_len);
+        word_trace!("skip(): word_len adjusted={}", word_len);
 
         if word_len == 0 {
-            if self.data_bit > 0 {
-                self.data_p += 1;
-                self.data_bit = 0;
-            }
-            log::debug!("skip(): empty word, returning data_p={}", self.data_p);
-            return (self.data_p, 0);
+            self.reader.align_to_byte();
+            word_trace!(
+                "skip(): empty word, returning data_p={}",
+                self.reader.position()
+            );
+            return (self.reader.position(), 0);
         }
 
         let word_len_int = word_len as usize;
 
         // Following Go's implementation exactly
         let mut add = 0u64;
-        let mut buf_pos = 0;
+        let mut buf_pos = 0usize;
         let mut last_uncovered = 0;
 
         // Read patterns and positions, calculating uncovered bytes as we go
@@ -1014,208 +1868,235 @@ impl<'a> Getter<'a> {
         let mut pattern_count = 0;
         while pos != 0 {
             pattern_count += 1;
-            buf_pos += pos as usize - 1;
+            buf_pos = buf_pos.saturating_add(pos as usize - 1);
 
             if buf_pos > last_uncovered {
                 add += (buf_pos - last_uncovered) as u64;
             }
 
             let pattern = self.next_pattern();
-            log::debug!(
+            word_trace!(
                 "skip(): pattern {}: pos={}, len={}, buf_pos={}",
                 pattern_count,
                 pos,
                 pattern.len(),
                 buf_pos
             );
-            last_uncovered = buf_pos + pattern.len();
+            last_uncovered = buf_pos.saturating_add(pattern.len());
 
             pos = self.next_pos(false);
         }
-        log::debug!("skip(): skipped {} patterns", pattern_count);
+        word_trace!("skip(): skipped {} patterns", pattern_count);
 
-        if self.data_bit > 0 {
-            self.data_p += 1;
-            self.data_bit = 0;
-        }
+        self.reader.align_to_byte();
 
         if word_len_int > last_uncovered {
             add += (word_len_int - last_uncovered) as u64;
         }
 
         // Uncovered characters
-        self.data_p += add;
-        log::debug!(
+        self.reader.seek(self.reader.position().saturating_add(add));
+        word_trace!(
             "skip(): final data_p={}, add={}, next 10 bytes: {:02x?}",
-            self.data_p,
+            self.reader.position(),
             add,
-            &self.data
-                [self.data_p as usize..std::cmp::min(self.data_p as usize + 10, self.data.len())]
+            &self.reader.remaining()[..self.reader.remaining().len().min(10)]
         );
 
-        (self.data_p, word_len_int)
+        (self.reader.position(), word_len_int)
+    }
+
+    /// The next word as stored, without decoding it: the bytes of its codes
+    /// and uncovered parts, and its length once decoded
+    ///
+    /// Words start on byte boundaries, so the bytes can be written into
+    /// another segment with the same dictionaries, as
+    /// [`Decompressor::copy_words`] does.
+    pub fn next_raw(&mut self) -> Result<(&'a [u8], usize), CompressionError> {
+        self.advance_prefetcher();
+        let start = self.reader.position() as usize;
+        let word_len = self.try_skip()?;
+        let end = self.reader.position() as usize;
+        Ok((&self.reader.data()[start..end], word_len))
+    }
+
+    // Bounds-checked skip used by Decompressor::verify. Mirrors skip() but
+    // fails if any position, pattern or uncovered byte range falls outside
+    // the word or the segment data. Returns the word length.
+    pub(crate) fn try_skip(&mut self) -> Result<usize, CompressionError> {
+        let word_len = self.try_next_pos(true)?.saturating_sub(1) as usize;
+        if word_len == 0 {
+            self.reader.align_to_byte();
+            return Ok(0);
+        }
+
+        let mut add = 0usize;
+        let mut buf_pos = 0usize;
+        let mut last_uncovered = 0usize;
+
+        let mut pos = self.try_next_pos(false)?;
+        while pos != 0 {
+            buf_pos = buf_pos
+                .checked_add(pos as usize - 1)
+                .filter(|&buf_pos| buf_pos <= word_len)
+                .ok_or(CompressionError::CorruptedData)?;
+            if buf_pos > last_uncovered {
+                add += buf_pos - last_uncovered;
+            }
+            last_uncovered = buf_pos + self.try_next_pattern()?.len();
+            if last_uncovered > word_len {
+                return Err(CompressionError::CorruptedData);
+            }
+            pos = self.try_next_pos(false)?;
+        }
+
+        self.reader.align_to_byte();
+        if word_len > last_uncovered {
+            add += word_len - last_uncovered;
+        }
+        self.reader.read_bytes(add)?;
+
+        Ok(word_len)
+    }
+
+    // The structure of the next word, with the checks of try_skip: its
+    // length and uncovered bytes, with its patterns and their positions as
+    // stored (distance from the previous pattern plus one) left in `cover`
+    pub(crate) fn try_next_cover(
+        &mut self,
+        cover: &mut Vec<(u64, &'a [u8])>,
+    ) -> Result<(usize, &'a [u8]), CompressionError> {
+        cover.clear();
+        let word_len = self.try_next_pos(true)?.saturating_sub(1) as usize;
+        if word_len == 0 {
+            self.reader.align_to_byte();
+            return Ok((0, &[]));
+        }
+
+        let mut add = 0usize;
+        let mut buf_pos = 0usize;
+        let mut last_uncovered = 0usize;
+
+        let mut pos = self.try_next_pos(false)?;
+        while pos != 0 {
+            buf_pos = buf_pos
+                .checked_add(pos as usize - 1)
+                .filter(|&buf_pos| buf_pos <= word_len)
+                .ok_or(CompressionError::CorruptedData)?;
+            if buf_pos > last_uncovered {
+                add += buf_pos - last_uncovered;
+            }
+            let pattern = self.try_next_pattern()?;
+            last_uncovered = buf_pos + pattern.len();
+            if last_uncovered > word_len {
+                return Err(CompressionError::CorruptedData);
+            }
+            cover.push((pos, pattern));
+            pos = self.try_next_pos(false)?;
+        }
+
+        self.reader.align_to_byte();
+        if word_len > last_uncovered {
+            add += word_len - last_uncovered;
+        }
+        Ok((word_len, self.reader.read_bytes(add)?))
     }
 
     // From Go: decompress.go:740-753
     pub fn next_uncompressed(&mut self) -> (Vec<u8>, u64) {
+        self.advance_prefetcher();
         let mut word_len = self.next_pos(true);
         word_len = word_len.saturating_sub(1); // because when create huffman tree we do ++, because 0 is terminator
 
         if word_len == 0 {
-            if self.data_bit > 0 {
-                self.data_p += 1;
-                self.data_bit = 0;
-            }
-            return (Vec::new(), self.data_p);
+            self.reader.align_to_byte();
+            metrics::increment(metrics::WORDS_DECOMPRESSED, 1);
+            return (self.decode_word(Vec::new(), 0), self.reader.position());
         }
 
         // Skip position data
         self.next_pos(false);
-        if self.data_bit > 0 {
-            self.data_p += 1;
-            self.data_bit = 0;
-        }
+        self.reader.align_to_byte();
 
         // Read uncompressed data
-        let word = if self.data_p + word_len <= self.data.len() as u64 {
-            self.data[self.data_p as usize..(self.data_p + word_len) as usize].to_vec()
-        } else {
-            Vec::new()
-        };
-
-        self.data_p += word_len;
-        (word, self.data_p)
+        let start = self.reader.position();
+        let word = self
+            .reader
+            .read_bytes(word_len as usize)
+            .map(<[u8]>::to_vec)
+            .unwrap_or_default();
+
+        self.reader.seek(start + word_len);
+        metrics::increment(metrics::WORDS_DECOMPRESSED, 1);
+        metrics::increment(metrics::BYTES_READ, word.len() as u64);
+        (self.decode_word(word, 0), self.reader.position())
     }
 
     // From Go: decompress.go:793-810
     pub fn skip_uncompressed(&mut self) -> Result<(u64, usize), CompressionError> {
+        self.advance_prefetcher();
         let mut word_len = self.next_pos(true);
         word_len = word_len.saturating_sub(1); // because when create huffman tree we do ++, because 0 is terminator
 
         if word_len == 0 {
-            if self.data_bit > 0 {
-                self.data_p += 1;
-                self.data_bit = 0;
-            }
-            return Ok((self.data_p, 0));
+            self.reader.align_to_byte();
+            return Ok((self.reader.position(), 0));
         }
 
         // Skip position data
         self.next_pos(false);
-        if self.data_bit > 0 {
-            self.data_p += 1;
-            self.data_bit = 0;
-        }
+        self.reader.align_to_byte();
 
         // Skip uncompressed data
-        self.data_p += word_len;
-        Ok((self.data_p, word_len as usize))
+        self.reader.seek(self.reader.position() + word_len);
+        Ok((self.reader.position(), word_len as usize))
     }
 
-    pub fn match_prefix_uncompressed(&self, _prefix: &[u8]) -> bool {
-        // For uncompressed data, we would need to peek at the data
-        // This is a simplified implementation
-        false
-    }
-
-    pub fn match_cmp(&self, _prefix: &[u8]) -> std::cmp::Ordering {
-        // For compressed data comparison
-        // This is a simplified implementation
-        std::cmp::Ordering::Equal
-    }
-
-    pub fn match_cmp_uncompressed(&self, _prefix: &[u8]) -> std::cmp::Ordering {
-        // For uncompressed data comparison
-        // This is a simplified implementation
-        std::cmp::Ordering::Equal
-    }
-
-    pub fn size(&self) -> usize {
-        self.data.len()
-    }
-}
-
-// From Go: decompress.go:615-636
-fn check_distance(power: usize, d: usize) -> bool {
-    lazy_static::lazy_static! {
-        static ref CONDENSED_WORD_DISTANCES: Vec<Vec<usize>> = build_condensed_word_distances();
-    }
-
-    if power >= CONDENSED_WORD_DISTANCES.len() {
-        return false;
-    }
-
-    CONDENSED_WORD_DISTANCES[power].contains(&d)
-}
-
-fn build_condensed_word_distances() -> Vec<Vec<usize>> {
-    let mut dist2 = vec![Vec::new(); 10];
-    for i in 1..=9 {
-        let mut dl = Vec::new();
-        let mut j = 1 << i;
-        while j < 512 {
-            dl.push(j);
-            j += 1 << i;
+    // The next word of a file written with `add_uncompressed_word`, without
+    // moving past it; `None` if it runs past the end of the data
+    fn peek_uncompressed(&self) -> Option<(&'a [u8], u64)> {
+        let mut g = self.peek();
+        let word_len = g.next_pos(true).saturating_sub(1); // 0 is the terminator
+        if word_len != 0 {
+            g.next_pos(false); // Position terminator
         }
-        dist2[i] = dl;
+        g.reader.align_to_byte();
+        let word = g.reader.read_bytes(word_len as usize).ok()?;
+        Some((word, g.reader.position()))
     }
-    dist2
-}
-
-// Decode a varint from bytes
-fn decode_varint(data: &[u8]) -> Result<(u64, usize), CompressionError> {
-    let mut value = 0u64;
-    let mut shift = 0;
-
-    for (i, &byte) in data.iter().enumerate() {
-        if i == 10 {
-            return Err(CompressionError::Other("Varint too long".to_string()));
-        }
-
-        value |= ((byte & 0x7F) as u64) << shift;
-
-        if byte & 0x80 == 0 {
-            return Ok((value, i + 1));
-        }
 
-        shift += 7;
+    /// [`Getter::match_prefix`] for words added with `add_uncompressed_word`
+    // From Go: decompress.go:956 MatchPrefixUncompressed
+    pub fn match_prefix_uncompressed(&self, prefix: &[u8]) -> bool {
+        self.peek_uncompressed()
+            .is_some_and(|(word, _)| word.starts_with(prefix))
     }
 
-    Err(CompressionError::Other(
-        "Unexpected end of varint".to_string(),
-    ))
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_pattern_table() {
-        let mut table = PatternTable::new(4);
-        let cw = Codeword {
-            pattern: b"test".to_vec(),
-            ptr: None,
-            code: 5,
-            len: 3,
-        };
-        table.insert_word(cw);
-
-        assert!(table.condensed_table_search(5).is_some());
+    /// [`Getter::match_prefix_cmp`] for words added with
+    /// `add_uncompressed_word`
+    pub fn match_prefix_cmp_uncompressed(&self, prefix: &[u8]) -> std::cmp::Ordering {
+        self.peek_uncompressed()
+            .map_or(std::cmp::Ordering::Less, |(word, _)| {
+                prefix.cmp(&word[..word.len().min(prefix.len())])
+            })
     }
 
-    #[test]
-    fn test_varint_decode() {
-        let data = vec![0x96, 0x01]; // 150 in varint
-        let (value, size) = decode_varint(&data).unwrap();
-        assert_eq!(value, 150);
-        assert_eq!(size, 2);
+    /// [`Getter::match_cmp`] for words added with `add_uncompressed_word`;
+    /// also moves past the word on `Equal`
+    // From Go: decompress.go:977 MatchCmpUncompressed
+    pub fn match_cmp_uncompressed(&mut self, buf: &[u8]) -> std::cmp::Ordering {
+        // A truncated word sorts after everything, like a missing one
+        let Some((word, next_pos)) = self.peek_uncompressed() else {
+            return std::cmp::Ordering::Less;
+        };
+        let cmp = buf.cmp(word);
+        if cmp.is_eq() {
+            self.reader.seek(next_pos);
+        }
+        cmp
     }
 
-    #[test]
-    fn test_condensed_distances() {
-        assert!(check_distance(3, 8)); // 1 << 3 = 8
-        assert!(check_distance(4, 16)); // 1 << 4 = 16
-        assert!(!check_distance(3, 7)); // Not a valid distance
+    pub fn size(&self) -> usize {
+        self.reader.data().len()
     }
 }
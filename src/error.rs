@@ -1,9 +1,12 @@
 // Error types for compression/decompression operations
 // Port of error handling from Go code
 
+use crate::core::DecodeError;
 use std::io;
 use thiserror::Error;
 
+pub use crate::core::ReadError;
+
 #[derive(Error, Debug)]
 pub enum CompressionError {
     // IO related errors
@@ -66,6 +69,9 @@ pub enum CompressionError {
     #[error("Unexpected end of file")]
     UnexpectedEof,
 
+    #[error("Verification of {file} failed: {reason}")]
+    VerificationFailed { file: String, reason: String },
+
     // Configuration errors
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
@@ -77,27 +83,201 @@ pub enum CompressionError {
     #[error("Collector error: {0}")]
     CollectorError(String),
 
+    // Subsystem errors
+    #[error(transparent)]
+    Compress(#[from] CompressError),
+
+    #[error(transparent)]
+    Decompress(#[from] DecompressError),
+
+    #[error(transparent)]
+    Index(#[from] IndexError),
+
+    // Failures of the core decoder not covered by the variants above
+    #[error(transparent)]
+    Decode(DecodeError),
+
     // General errors
     #[error("Operation cancelled")]
     Cancelled,
 
     #[error("Not implemented: {0}")]
     NotImplemented(String),
+}
 
-    #[error("{0}")]
-    Other(String),
+impl From<DecodeError> for CompressionError {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::UnexpectedEof => CompressionError::UnexpectedEof,
+            DecodeError::CorruptedData => CompressionError::CorruptedData,
+            DecodeError::TableDepthExhausted { table } => {
+                DecompressError::TableDepthExhausted { table }.into()
+            }
+            DecodeError::VarintOverflow => DecompressError::VarintOverflow.into(),
+            DecodeError::VarintTruncated { available } => {
+                DecompressError::VarintTruncated { available }.into()
+            }
+            e => CompressionError::Decode(e),
+        }
+    }
 }
 
 // Note: Not using type alias per rust-specific-rules.md
 // Use std::result::Result<T, CompressionError> in function signatures
 
-// Helper functions to create common errors (matching Go patterns)
-impl CompressionError {
-    pub fn io_error(msg: &str) -> Self {
-        CompressionError::Other(msg.to_string())
-    }
+/// Failures specific to writing a compressed file
+#[derive(Error, Debug)]
+pub enum CompressError {
+    #[error("Invalid output file path: {path}")]
+    InvalidOutputPath { path: String },
 
-    pub fn wrap_io(err: io::Error, context: &str) -> Self {
-        CompressionError::Other(format!("{}: {}", context, err))
-    }
+    #[error("Uncompressed word file of {file} not initialized")]
+    UncompressedFileNotInitialized { file: String },
+
+    #[error("Malformed serialized dictionary: {0}")]
+    MalformedDictionary(#[source] ReadError),
+
+    #[error("Malformed compression checkpoint: {0}")]
+    MalformedCheckpoint(#[source] ReadError),
+
+    #[error("Checkpoint {path} can't be resumed: {reason}")]
+    CheckpointMismatch { path: String, reason: String },
+
+    #[error("{file} was already compressed: no more words can be added, nor compressed again")]
+    AlreadyCompressed { file: String },
+
+    #[error("{file}: {added} words added, but {actual} {place}")]
+    WordCountMismatch {
+        file: String,
+        place: &'static str,
+        added: u64,
+        actual: u64,
+    },
+
+    #[error("Invalid Huffman code for {dict} {index}: {reason}")]
+    InvalidHuffmanCode {
+        dict: &'static str,
+        index: usize,
+        reason: String,
+    },
+}
+
+/// Failures specific to reading a compressed file
+///
+/// `dict` is "pattern" or "position", naming the dictionary being parsed.
+#[derive(Error, Debug)]
+pub enum DecompressError {
+    #[error("Invalid file path: {path}")]
+    InvalidPath { path: String },
+
+    #[error("File {file} too small: {size} bytes, expected at least {min} bytes")]
+    FileTooSmall { file: String, size: u64, min: u64 },
+
+    #[error("File {file} of {size} bytes does not fit in the address space")]
+    FileTooLarge { file: String, size: u64 },
+
+    #[error("File {file} of {size} bytes but no words in it")]
+    NoWords { file: String, size: u64 },
+
+    #[error("Invalid {dict} dictionary size {size} in {file}: only {available} bytes available")]
+    DictionarySize {
+        file: String,
+        dict: &'static str,
+        size: u64,
+        available: u64,
+    },
+
+    #[error("Malformed {file}: {source}")]
+    Read {
+        file: String,
+        #[source]
+        source: ReadError,
+    },
+
+    #[error(
+        "{dict} depth {depth} at offset {offset} of {file} exceeds maximum allowed depth {max}"
+    )]
+    DepthOverflow {
+        file: String,
+        dict: &'static str,
+        offset: usize,
+        depth: u64,
+        max: u64,
+    },
+
+    #[error(
+        "Pattern of {size} bytes at offset {offset} of {file} exceeds dictionary size {dict_size}"
+    )]
+    PatternOutOfBounds {
+        file: String,
+        offset: usize,
+        size: u64,
+        dict_size: usize,
+    },
+
+    #[error("Words {start}..{end} out of range of the {count} words in {file}")]
+    WordRange {
+        file: String,
+        start: u64,
+        end: u64,
+        count: u64,
+    },
+
+    #[error("{table} table build ran out of depth")]
+    TableDepthExhausted { table: &'static str },
+
+    #[error("Offset table {file} does not fit: {reason}")]
+    OffsetTable { file: String, reason: String },
+
+    #[error("{table} tables of {file} exceed the table memory limit of {limit} bytes")]
+    TableTooLarge {
+        file: String,
+        table: &'static str,
+        limit: usize,
+    },
+
+    #[error("Varint longer than 10 bytes")]
+    VarintOverflow,
+
+    #[error("Varint truncated after {available} bytes")]
+    VarintTruncated { available: usize },
+}
+
+/// Failures building, reading or pairing a segment index
+#[derive(Error, Debug)]
+pub enum IndexError {
+    #[error("Index of {file} has {actual} keys, expected {expected}")]
+    KeyCountMismatch {
+        file: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Index of {file} has the wrong kind (enum: {is_enum})")]
+    WrongKind { file: String, is_enum: bool },
+
+    #[error("Index salt {actual:#010x} does not match datadir salt {expected:#010x}")]
+    SaltMismatch { expected: u32, actual: u32 },
+
+    #[error("Enum index offsets must not decrease: {offset} after {previous}")]
+    DecreasingOffset { offset: u64, previous: u64 },
+
+    #[error("Not enough start seeds for level {level}")]
+    MissingStartSeed { level: usize },
+
+    #[error("No offset for ordinal {ordinal} in index")]
+    MissingOffset { ordinal: u64 },
+
+    #[error("Varint too large at offset {offset}")]
+    VarintOverflow { offset: usize },
+
+    #[error("Invalid index parameters: {0}")]
+    InvalidParameters(String),
+
+    #[error("Malformed index {file}: {source}")]
+    Read {
+        file: String,
+        #[source]
+        source: ReadError,
+    },
 }
@@ -0,0 +1,349 @@
+//! Plain exports of segment words, for tools that don't read `.seg` files
+//!
+//! Every format holds the same stream: each word in segment order, as its
+//! length (uvarint) followed by its bytes. [`ExportFormat::Raw`] writes the
+//! stream as is; the snappy (`snappy` feature, framing format) and zstd
+//! (`zstd` feature, one frame) formats compress it with a codec most data
+//! tools can read.
+//!
+//! [`Decompressor::decompress_to`] streams the words to any writer instead,
+//! a pipe to another process for one, delimited as a [`WordSeparator`] says.
+
+use crate::compress::{encode_varint, read_uvarint};
+use crate::decompress::Decompressor;
+use crate::error::CompressionError;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+/// Layout of an exported word stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Uncompressed length-prefixed words
+    Raw,
+    /// The raw stream in snappy's framing format
+    #[cfg(feature = "snappy")]
+    Snappy,
+    /// The raw stream as a zstd frame
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl ExportFormat {
+    /// File extension of the format, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Raw => "raw",
+            #[cfg(feature = "snappy")]
+            ExportFormat::Snappy => "sz",
+            #[cfg(feature = "zstd")]
+            ExportFormat::Zstd => "zst",
+        }
+    }
+}
+
+/// How the words streamed by [`Decompressor::decompress_to`] are delimited
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordSeparator {
+    /// Each word after its length as a uvarint, the stream of an export
+    Uvarint,
+    /// Each word after its length as 4 big-endian bytes
+    U32Be,
+    /// These bytes after each word, a newline for text words; words that
+    /// hold them can't be told apart
+    After(Vec<u8>),
+    /// Each word in lowercase hex on a line of its own, for binary words
+    /// read by line-oriented tools
+    HexLines,
+}
+
+/// Write the words of `decompressor` to `writer`, returning how many
+///
+/// One buffer is reused for every word. `writer` is not buffered here.
+pub(crate) fn write_words(
+    decompressor: &Decompressor,
+    writer: &mut dyn Write,
+    separator: &WordSeparator,
+) -> Result<u64, CompressionError> {
+    let mut getter = decompressor.make_getter();
+    let mut word = Vec::new();
+    let mut len_buf = [0u8; 10];
+    let mut words = 0u64;
+    while getter.has_next() {
+        word.clear();
+        word = getter.next(word).0;
+        match separator {
+            WordSeparator::Uvarint => {
+                let n = encode_varint(&mut len_buf, word.len() as u64);
+                writer.write_all(&len_buf[..n])?;
+                writer.write_all(&word)?;
+            }
+            WordSeparator::U32Be => {
+                let len =
+                    u32::try_from(word.len()).map_err(|_| CompressionError::WordTooLarge {
+                        size: word.len(),
+                        max: u32::MAX as usize,
+                    })?;
+                writer.write_all(&len.to_be_bytes())?;
+                writer.write_all(&word)?;
+            }
+            WordSeparator::After(separator) => {
+                writer.write_all(&word)?;
+                writer.write_all(separator)?;
+            }
+            WordSeparator::HexLines => {
+                let mut line = hex::encode(&word).into_bytes();
+                line.push(b'\n');
+                writer.write_all(&line)?;
+            }
+        }
+        words += 1;
+    }
+    Ok(words)
+}
+
+enum Sink {
+    Raw(BufWriter<File>),
+    #[cfg(feature = "snappy")]
+    Snappy(Box<snap::write::FrameEncoder<File>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Sink {
+    fn new(file: File, format: ExportFormat) -> io::Result<Self> {
+        Ok(match format {
+            ExportFormat::Raw => Sink::Raw(BufWriter::new(file)),
+            #[cfg(feature = "snappy")]
+            ExportFormat::Snappy => Sink::Snappy(Box::new(snap::write::FrameEncoder::new(file))),
+            #[cfg(feature = "zstd")]
+            ExportFormat::Zstd => Sink::Zstd(zstd::Encoder::new(BufWriter::new(file), ZSTD_LEVEL)?),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Sink::Raw(w) => w,
+            #[cfg(feature = "snappy")]
+            Sink::Snappy(w) => w,
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(w) => w,
+        }
+    }
+
+    // Flush everything buffered, ending the compressed stream
+    fn finish(self) -> io::Result<File> {
+        match self {
+            Sink::Raw(w) => w.into_inner().map_err(|e| e.into_error()),
+            #[cfg(feature = "snappy")]
+            Sink::Snappy(w) => w.into_inner().map_err(|e| e.into_error()),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(w) => w.finish()?.into_inner().map_err(|e| e.into_error()),
+        }
+    }
+}
+
+/// Write the words of `decompressor` to `path` in `format`, returning the
+/// number of words written
+///
+/// The file is written under a `.tmp` name and renamed into place once
+/// complete, so readers never see a partial export.
+pub(crate) fn export_words(
+    decompressor: &Decompressor,
+    path: &Path,
+    format: ExportFormat,
+    fsync: bool,
+) -> Result<u64, CompressionError> {
+    let tmp_path = format!("{}.tmp", path.display());
+    let file = File::create(&tmp_path).map_err(|e| CompressionError::FileCreate {
+        path: tmp_path.clone(),
+        source: e,
+    })?;
+    let mut sink = Sink::new(file, format)?;
+    let words = write_words(decompressor, sink.writer(), &WordSeparator::Uvarint)?;
+
+    let file = sink.finish()?;
+    if fsync {
+        file.sync_all()?;
+    }
+    drop(file);
+    fs::rename(&tmp_path, path).map_err(|e| CompressionError::FileRename {
+        from: tmp_path,
+        to: path.display().to_string(),
+        source: e,
+    })?;
+    tracing::debug!(
+        "Exported {} words of {} to {}",
+        words,
+        decompressor.file_name(),
+        path.display()
+    );
+    Ok(words)
+}
+
+/// Read back the words of an export, for consumers in Rust
+pub fn read_export(
+    path: impl AsRef<Path>,
+    format: ExportFormat,
+) -> Result<Vec<Vec<u8>>, CompressionError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| CompressionError::FileOpen {
+        path: path.display().to_string(),
+        source: e,
+    })?;
+    let mut reader: Box<dyn Read> = match format {
+        ExportFormat::Raw => Box::new(io::BufReader::new(file)),
+        #[cfg(feature = "snappy")]
+        ExportFormat::Snappy => Box::new(snap::read::FrameDecoder::new(file)),
+        #[cfg(feature = "zstd")]
+        ExportFormat::Zstd => Box::new(zstd::Decoder::new(file)?),
+    };
+
+    let mut words = Vec::new();
+    loop {
+        // A clean end of the stream can only fall between two words
+        let mut first = [0u8; 1];
+        if reader.read(&mut first)? == 0 {
+            return Ok(words);
+        }
+        let len = read_uvarint(&mut (&first[..]).chain(&mut reader))?;
+        let mut word = vec![0u8; len as usize];
+        reader
+            .read_exact(&mut word)
+            .map_err(|_| CompressionError::UnexpectedEof)?;
+        words.push(word);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compressor;
+    use tempfile::TempDir;
+
+    fn words() -> Vec<Vec<u8>> {
+        (0..500u32)
+            .map(|i| match i % 5 {
+                0 => Vec::new(),
+                _ => format!("word {} of the export test", i % 37)
+                    .repeat(1 + (i % 3) as usize)
+                    .into_bytes(),
+            })
+            .collect()
+    }
+
+    fn formats() -> Vec<ExportFormat> {
+        vec![
+            ExportFormat::Raw,
+            #[cfg(feature = "snappy")]
+            ExportFormat::Snappy,
+            #[cfg(feature = "zstd")]
+            ExportFormat::Zstd,
+        ]
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let tmp_dir = TempDir::new().unwrap();
+        let seg = tmp_dir.path().join("test.seg");
+        let mut compressor = Compressor::builder(&seg).fsync(false).build().unwrap();
+        for word in words() {
+            compressor.add_word(&word).unwrap();
+        }
+        compressor.compress().unwrap();
+
+        let decompressor = Decompressor::new(&seg).unwrap();
+        for format in formats() {
+            let path = seg.with_extension(format.extension());
+            assert_eq!(decompressor.export(&path, format).unwrap(), 500);
+            assert_eq!(read_export(&path, format).unwrap(), words(), "{:?}", format);
+        }
+
+        // Raw is the documented plain layout
+        let raw = std::fs::read(seg.with_extension("raw")).unwrap();
+        assert_eq!(raw[0], 0);
+        assert_eq!(raw[1] as usize, "word 1 of the export test".len() * 2);
+    }
+
+    #[test]
+    fn test_compressor_writes_companion() {
+        let tmp_dir = TempDir::new().unwrap();
+        for format in formats() {
+            let seg = tmp_dir.path().join(format!("{}.seg", format.extension()));
+            let mut compressor = Compressor::builder(&seg)
+                .fsync(false)
+                .companion_export(format)
+                .build()
+                .unwrap();
+            for word in words() {
+                compressor.add_word(&word).unwrap();
+            }
+            compressor.compress().unwrap();
+
+            let companion = seg.with_extension(format.extension());
+            assert_eq!(read_export(&companion, format).unwrap(), words());
+            assert!(!Path::new(&format!("{}.tmp", companion.display())).exists());
+        }
+    }
+
+    #[test]
+    fn test_decompress_to_separators() {
+        let tmp_dir = TempDir::new().unwrap();
+        let seg = tmp_dir.path().join("test.seg");
+        let mut compressor = Compressor::builder(&seg).fsync(false).build().unwrap();
+        for word in words() {
+            compressor.add_word(&word).unwrap();
+        }
+        compressor.compress().unwrap();
+        let decompressor = Decompressor::new(&seg).unwrap();
+        let stream = |separator: WordSeparator| {
+            let mut out = Vec::new();
+            assert_eq!(
+                decompressor.decompress_to(&mut out, separator).unwrap(),
+                500
+            );
+            out
+        };
+
+        // The export stream, written to memory
+        let raw = seg.with_extension("raw");
+        decompressor.export(&raw, ExportFormat::Raw).unwrap();
+        assert_eq!(stream(WordSeparator::Uvarint), std::fs::read(&raw).unwrap());
+
+        let out = stream(WordSeparator::U32Be);
+        let mut rest = out.as_slice();
+        for word in words() {
+            let (len, tail) = rest.split_at(4);
+            let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+            assert_eq!(&tail[..len], word.as_slice());
+            rest = &tail[len..];
+        }
+        assert!(rest.is_empty());
+
+        let out = stream(WordSeparator::After(b"\n".to_vec()));
+        let lines: Vec<&[u8]> = out.split(|&b| b == b'\n').collect();
+        assert_eq!(lines.len(), 501);
+        assert!(lines[..500]
+            .iter()
+            .zip(words())
+            .all(|(line, word)| *line == word));
+
+        let out = String::from_utf8(stream(WordSeparator::HexLines)).unwrap();
+        let decoded: Vec<Vec<u8>> = out.lines().map(|line| hex::decode(line).unwrap()).collect();
+        assert_eq!(decoded, words());
+    }
+
+    #[test]
+    fn test_read_export_truncated() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("bad.raw");
+        std::fs::write(&path, [5, b'a', b'b']).unwrap();
+        assert!(matches!(
+            read_export(&path, ExportFormat::Raw),
+            Err(CompressionError::UnexpectedEof)
+        ));
+    }
+}
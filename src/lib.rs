@@ -1,14 +1,91 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod core;
+
+#[cfg(feature = "std")]
+pub mod append;
+#[cfg(feature = "std")]
+pub mod cancel;
+#[cfg(feature = "std")]
+pub mod codec;
+#[cfg(feature = "std")]
 pub mod compress;
+#[cfg(feature = "std")]
 pub mod decompress;
+#[cfg(feature = "std")]
 pub mod error;
+#[cfg(feature = "std")]
+pub mod export;
+// Always built so the crate can record metrics, public with the feature
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(all(feature = "std", not(feature = "metrics")))]
+mod metrics;
+#[cfg(feature = "std")]
+pub mod offsets;
+#[cfg(feature = "std")]
+pub mod output;
+#[cfg(feature = "std")]
 pub mod parallel_compress;
+#[cfg(feature = "std")]
+pub mod readahead;
+#[cfg(feature = "std")]
 pub mod seg_reader;
+#[cfg(feature = "std")]
+pub mod segment;
+#[cfg(feature = "std")]
 pub mod snapshots;
+#[cfg(all(feature = "std", any(test, feature = "testutil")))]
+pub mod testutil;
+#[cfg(feature = "std")]
+mod trace;
+#[cfg(feature = "std")]
+pub mod word_source;
+#[cfg(feature = "std")]
+pub mod workspace;
 
 // Re-export main types
-pub use compress::{Cfg, Compressor, DictionaryBuilder, Pattern};
-pub use decompress::{Decompressor, Getter};
-pub use error::CompressionError;
+pub use crate::core::{
+    BitReader, DecodeError, FormatVersion, NameVersion, ReadError, SafeReader, SegmentHeader,
+    SegmentView, Words,
+};
+#[cfg(feature = "std")]
+pub use append::AppendCompressor;
+#[cfg(feature = "std")]
+pub use cancel::CancellationToken;
+#[cfg(feature = "std")]
+pub use codec::WordCodec;
+#[cfg(feature = "std")]
+pub use compress::{
+    Cfg, CompressionEstimate, CompressionLevel, CompressionStats, Compressor, CompressorBuilder,
+    CompressorState, DictionaryBuilder, OptimizerMode, Pattern, PhaseTimings, SamplingStrategy,
+    SizeBreakdown,
+};
+#[cfg(feature = "std")]
+pub use decompress::{
+    Decompressor, DecompressorOptions, Getter, GetterState, MemoryUsage, Patterns, Positions,
+    SegmentChecksum, VerifyReport,
+};
+#[cfg(feature = "std")]
+pub use error::{CompressError, CompressionError, DecompressError, IndexError};
+#[cfg(feature = "std")]
+pub use export::{read_export, ExportFormat, WordSeparator};
+#[cfg(feature = "std")]
+pub use offsets::OffsetTable;
+#[cfg(feature = "std")]
+pub use output::SyncPolicy;
+#[cfg(feature = "std")]
 pub use parallel_compress::{
-    compress_with_pattern_candidates, cover_word_by_patterns, CompressionQueue,
+    compress_with_pattern_candidates, cover_word_by_patterns, cover_word_greedy, CompressionQueue,
+    CoverBuffers,
 };
+#[cfg(feature = "std")]
+pub use readahead::ReadAhead;
+#[cfg(feature = "std")]
+pub use snapshots::{BlockNumber, TxIndex, TxNum};
+#[cfg(feature = "std")]
+pub use word_source::{SegmentSource, WordSource};
+#[cfg(feature = "std")]
+pub use workspace::{recover_or_clean, CleanupReport, TempWorkspace};
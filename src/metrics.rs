@@ -0,0 +1,154 @@
+//! Counters and histograms of what the crate does
+//!
+//! With the `metrics` feature, a [`MetricsSink`] installed with
+//! [`set_sink`] receives the counts below as they happen: adapt it to
+//! prometheus, metrics-rs or anything else. Names follow the Prometheus
+//! conventions. Without the feature, or before a sink is installed,
+//! recording costs nothing more than an atomic load.
+
+// Cache names are only recorded with the `cache` feature
+#![cfg_attr(not(feature = "metrics"), allow(dead_code))]
+
+/// Words decoded by getters
+pub const WORDS_DECOMPRESSED: &str = "erigon_dumper_words_decompressed_total";
+/// Bytes of the words decoded by getters
+pub const BYTES_READ: &str = "erigon_dumper_bytes_read_total";
+/// Key and ordinal lookups in RecSplit indexes
+pub const INDEX_LOOKUPS: &str = "erigon_dumper_index_lookups_total";
+/// Lookups a `CachedReader` served from its cache
+pub const CACHE_HITS: &str = "erigon_dumper_cache_hits_total";
+/// Lookups a `CachedReader` had to decode
+pub const CACHE_MISSES: &str = "erigon_dumper_cache_misses_total";
+/// Lookups of a file that was already open in a snapshot repo
+pub const OPEN_FILE_HITS: &str = "erigon_dumper_open_file_hits_total";
+/// Lookups that had to open the file
+pub const OPEN_FILE_MISSES: &str = "erigon_dumper_open_file_misses_total";
+/// Time spent building dictionaries, per compressed file
+pub const COMPRESS_DICTIONARY_SECONDS: &str = "erigon_dumper_compress_dictionary_seconds";
+/// Time spent covering words with patterns, per compressed file
+pub const COMPRESS_COVER_SECONDS: &str = "erigon_dumper_compress_cover_seconds";
+/// Time spent writing segments, per compressed file
+pub const COMPRESS_WRITE_SECONDS: &str = "erigon_dumper_compress_write_seconds";
+
+/// Where the crate's metrics go
+///
+/// Both methods default to doing nothing. They are called from whichever
+/// thread does the work being counted, so implementations update their
+/// values internally.
+#[cfg(feature = "metrics")]
+pub trait MetricsSink: Send + Sync {
+    /// Add `value` to counter `name`
+    fn increment_counter(&self, name: &'static str, value: u64) {
+        let _ = (name, value);
+    }
+
+    /// Record one observation of histogram `name`
+    fn record_histogram(&self, name: &'static str, value: f64) {
+        let _ = (name, value);
+    }
+}
+
+/// A sink dropping everything, as if none was installed
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopSink;
+
+#[cfg(feature = "metrics")]
+impl MetricsSink for NoopSink {}
+
+#[cfg(feature = "metrics")]
+static SINK: std::sync::OnceLock<Box<dyn MetricsSink>> = std::sync::OnceLock::new();
+
+/// Install the sink for the rest of the process; hands `sink` back if one
+/// is installed already
+#[cfg(feature = "metrics")]
+pub fn set_sink(sink: Box<dyn MetricsSink>) -> std::result::Result<(), Box<dyn MetricsSink>> {
+    SINK.set(sink)
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn increment(name: &'static str, value: u64) {
+    if let Some(sink) = SINK.get() {
+        sink.increment_counter(name, value);
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn observe(name: &'static str, value: f64) {
+    if let Some(sink) = SINK.get() {
+        sink.record_histogram(name, value);
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub(crate) fn increment(_: &'static str, _: u64) {}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub(crate) fn observe(_: &'static str, _: f64) {}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use crate::compress::Compressor;
+    use crate::decompress::Decompressor;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        counters: Mutex<HashMap<&'static str, u64>>,
+        histograms: Mutex<HashMap<&'static str, Vec<f64>>>,
+    }
+
+    impl MetricsSink for &'static Recorder {
+        fn increment_counter(&self, name: &'static str, value: u64) {
+            *self.counters.lock().unwrap().entry(name).or_default() += value;
+        }
+
+        fn record_histogram(&self, name: &'static str, value: f64) {
+            self.histograms
+                .lock()
+                .unwrap()
+                .entry(name)
+                .or_default()
+                .push(value);
+        }
+    }
+
+    #[test]
+    fn test_sink_receives_metrics() {
+        let recorder: &'static Recorder = Box::leak(Box::default());
+        assert!(set_sink(Box::new(recorder)).is_ok());
+        assert!(set_sink(Box::new(NoopSink)).is_err());
+        let counter = |name| recorder.counters.lock().unwrap().get(name).copied();
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("words.seg");
+        let mut compressor = Compressor::builder(&path).fsync(false).build().unwrap();
+        for i in 0..100u32 {
+            compressor
+                .add_word(format!("word {}", i).as_bytes())
+                .unwrap();
+        }
+        compressor.compress().unwrap();
+        assert!(recorder
+            .histograms
+            .lock()
+            .unwrap()
+            .contains_key(COMPRESS_WRITE_SECONDS));
+
+        // Other tests decode words too, so counts only grow by at least ours
+        let words_before = counter(WORDS_DECOMPRESSED).unwrap_or(0);
+        let bytes_before = counter(BYTES_READ).unwrap_or(0);
+        let decompressor = Decompressor::new(&path).unwrap();
+        let mut getter = decompressor.make_getter();
+        let mut bytes = 0;
+        while getter.has_next() {
+            bytes += getter.next(Vec::new()).0.len() as u64;
+        }
+        assert!(counter(WORDS_DECOMPRESSED).unwrap() >= words_before + 100);
+        assert!(counter(BYTES_READ).unwrap() >= bytes_before + bytes);
+    }
+}
@@ -0,0 +1,202 @@
+//! Sampled word offsets, for reaching words by ordinal without an `.idx`
+//!
+//! An [`OffsetTable`] holds the offset of every `stride`-th word of a
+//! segment, found in one scan by [`Decompressor::build_offset_table`]. A word
+//! is then reached by resetting a getter to the sampled word before it and
+//! skipping at most `stride - 1` words, at 8 bytes of memory per `stride`
+//! words. Tables can be kept in memory or saved next to the segment; a saved
+//! table records the word count and size of its segment, so that one left
+//! over from another version of the file is refused when attached.
+
+use crate::decompress::Decompressor;
+use crate::error::{CompressionError, DecompressError};
+use crate::SafeReader;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Magic, stride, word count, segment size and number of offsets
+const MAGIC: &[u8; 8] = b"EDOFFS01";
+const HEADER_LEN: usize = MAGIC.len() + 4 * 8;
+
+/// Offsets of every `stride`-th word of a segment, from
+/// [`Decompressor::build_offset_table`]
+///
+/// Attached with [`Decompressor::attach_offset_table`], it speeds up
+/// [`Decompressor::word_offset`] and the getters built on it when the
+/// segment has no `.idx`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffsetTable {
+    stride: u64,
+    word_count: u64,
+    segment_size: u64,
+    // Getter offset of words 0, stride, 2 * stride...
+    offsets: Vec<u64>,
+}
+
+impl OffsetTable {
+    pub(crate) fn build(d: &Decompressor, stride: u64) -> Result<Self, CompressionError> {
+        if stride == 0 {
+            return Err(CompressionError::InvalidConfig(
+                "offset table stride must be at least 1".to_string(),
+            ));
+        }
+        let word_count = d.count() as u64;
+        let mut offsets = Vec::with_capacity(word_count.div_ceil(stride) as usize);
+        let mut getter = d.make_getter();
+        for i in 0..word_count {
+            if !getter.has_next() {
+                return Err(CompressionError::UnexpectedEof);
+            }
+            if i % stride == 0 {
+                offsets.push(getter.offset());
+            }
+            getter.try_skip()?;
+        }
+        Ok(OffsetTable {
+            stride,
+            word_count,
+            segment_size: d.size() as u64,
+            offsets,
+        })
+    }
+
+    /// Words between two sampled offsets
+    pub fn stride(&self) -> u64 {
+        self.stride
+    }
+
+    /// Words in the segment the table was built from
+    pub fn word_count(&self) -> u64 {
+        self.word_count
+    }
+
+    /// Number of sampled offsets
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Heap bytes held by the sampled offsets
+    pub fn memory_usage(&self) -> usize {
+        self.offsets.capacity() * std::mem::size_of::<u64>()
+    }
+
+    /// The last sampled word at or before word `i`, as its ordinal and
+    /// offset; `None` past the last word
+    pub fn floor(&self, i: u64) -> Option<(u64, u64)> {
+        if i >= self.word_count {
+            return None;
+        }
+        let slot = i / self.stride;
+        Some((slot * self.stride, self.offsets[slot as usize]))
+    }
+
+    // Whether the table was built from `d`, or a reason it was not
+    pub(crate) fn check(&self, d: &Decompressor) -> Result<(), String> {
+        if self.word_count != d.count() as u64 {
+            return Err(format!(
+                "built for {} words, the segment has {}",
+                self.word_count,
+                d.count()
+            ));
+        }
+        if self.segment_size != d.size() as u64 {
+            return Err(format!(
+                "built for a segment of {} bytes, it has {}",
+                self.segment_size,
+                d.size()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write the table to `writer`; returns the number of bytes written
+    pub fn write_to(&self, mut writer: impl Write) -> Result<u64, CompressionError> {
+        writer.write_all(MAGIC)?;
+        for v in [
+            self.stride,
+            self.word_count,
+            self.segment_size,
+            self.offsets.len() as u64,
+        ] {
+            writer.write_all(&v.to_be_bytes())?;
+        }
+        for offset in &self.offsets {
+            writer.write_all(&offset.to_be_bytes())?;
+        }
+        writer.flush()?;
+        Ok((HEADER_LEN + self.offsets.len() * 8) as u64)
+    }
+
+    /// Save the table to a sidecar file at `path`
+    ///
+    /// The file is not synced: it can always be built again.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CompressionError> {
+        let path = path.as_ref();
+        let f = File::create(path).map_err(|e| CompressionError::FileCreate {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+        self.write_to(BufWriter::new(f))?;
+        Ok(())
+    }
+
+    /// Load a table saved with [`OffsetTable::save`]
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, CompressionError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| CompressionError::FileOpen {
+            path: path.display().to_string(),
+            source: e,
+        })?;
+        Self::from_bytes(&data, &path.display().to_string())
+    }
+
+    fn from_bytes(data: &[u8], file: &str) -> Result<Self, CompressionError> {
+        let malformed = |source| DecompressError::Read {
+            file: file.to_string(),
+            source,
+        };
+        let invalid = |reason: String| DecompressError::OffsetTable {
+            file: file.to_string(),
+            reason,
+        };
+        let mut r = SafeReader::new(data);
+        if r.bytes(MAGIC.len() as u64, "magic").map_err(malformed)? != MAGIC {
+            return Err(invalid("not an offset table".to_string()).into());
+        }
+        let stride = r.u64_be("stride").map_err(malformed)?;
+        let word_count = r.u64_be("word count").map_err(malformed)?;
+        let segment_size = r.u64_be("segment size").map_err(malformed)?;
+        let len = r.u64_be("offset count").map_err(malformed)?;
+        if stride == 0 || len != word_count.div_ceil(stride) {
+            return Err(invalid(format!(
+                "{} offsets do not sample {} words every {}",
+                len, word_count, stride
+            ))
+            .into());
+        }
+        if len > r.remaining() as u64 / 8 {
+            return Err(invalid(format!("{} offsets in {} bytes", len, r.remaining())).into());
+        }
+        let offsets = (0..len)
+            .map(|_| r.u64_be("offset"))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(malformed)?;
+        if !r.is_empty() {
+            return Err(invalid(format!("{} trailing bytes", r.remaining())).into());
+        }
+        if offsets.windows(2).any(|w| w[0] > w[1]) {
+            return Err(invalid("offsets decrease".to_string()).into());
+        }
+        Ok(OffsetTable {
+            stride,
+            word_count,
+            segment_size,
+            offsets,
+        })
+    }
+}
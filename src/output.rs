@@ -0,0 +1,267 @@
+//! How the compressor writes segment files to disk
+//!
+//! Producers writing terabytes of snapshots care about when data reaches the
+//! disk and how much of the page cache it takes on the way. [`SyncPolicy`]
+//! picks when the `.seg` is synced, and direct IO (Linux only) writes it
+//! around the page cache entirely.
+
+use crate::error::CompressionError;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Alignment of buffers, offsets and lengths for direct IO; a multiple of
+/// the logical block size of every common filesystem
+const DIRECT_IO_ALIGN: usize = 4096;
+
+/// Size of the aligned buffer of direct IO writes
+const DIRECT_IO_BUF: usize = 256 * DIRECT_IO_ALIGN;
+
+/// When a segment file is synced to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    /// Never; the OS writes the file back when it sees fit
+    None,
+    /// Once, before the file is renamed into place
+    #[default]
+    OnClose,
+    /// Every time this many bytes have been written since the last sync, and
+    /// before the rename. Keeps dirty pages from piling up while a large
+    /// segment is written
+    Periodic(u64),
+}
+
+/// Writer for a segment file, applying a [`SyncPolicy`] and optionally
+/// direct IO
+pub(crate) struct SegmentFile {
+    file: File,
+    policy: SyncPolicy,
+    unsynced: u64,
+    // Aligned buffer for direct IO: `raw[start..start + DIRECT_IO_BUF]`,
+    // filled up to `len`
+    direct: Option<DirectBuf>,
+    written: u64,
+}
+
+struct DirectBuf {
+    raw: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl DirectBuf {
+    fn new() -> Self {
+        let raw = vec![0u8; DIRECT_IO_BUF + DIRECT_IO_ALIGN];
+        let start = raw.as_ptr().align_offset(DIRECT_IO_ALIGN);
+        DirectBuf { raw, start, len: 0 }
+    }
+
+    fn buf(&mut self) -> &mut [u8] {
+        &mut self.raw[self.start..self.start + DIRECT_IO_BUF]
+    }
+}
+
+impl SegmentFile {
+    /// Create `path`, with direct IO if asked and the filesystem supports it
+    pub(crate) fn create(
+        path: &Path,
+        policy: SyncPolicy,
+        direct_io: bool,
+    ) -> Result<Self, CompressionError> {
+        let create_err = |e| CompressionError::FileCreate {
+            path: path.display().to_string(),
+            source: e,
+        };
+        let mut direct = None;
+        let file = if direct_io {
+            match open_direct(path) {
+                Ok(file) => {
+                    direct = Some(DirectBuf::new());
+                    file
+                }
+                // tmpfs and some network filesystems refuse O_DIRECT (EINVAL)
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::InvalidInput | io::ErrorKind::Unsupported
+                    ) =>
+                {
+                    tracing::warn!(
+                        "Direct IO not supported for {}, writing through the page cache",
+                        path.display()
+                    );
+                    File::create(path).map_err(create_err)?
+                }
+                Err(e) => return Err(create_err(e)),
+            }
+        } else {
+            File::create(path).map_err(create_err)?
+        };
+        Ok(SegmentFile {
+            file,
+            policy,
+            unsynced: 0,
+            direct,
+            written: 0,
+        })
+    }
+
+    /// Whether writes bypass the page cache
+    pub(crate) fn is_direct(&self) -> bool {
+        self.direct.is_some()
+    }
+
+    fn write_through(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(data)?;
+        if let SyncPolicy::Periodic(bytes) = self.policy {
+            self.unsynced += data.len() as u64;
+            if self.unsynced >= bytes {
+                self.file.sync_data()?;
+                self.unsynced = 0;
+            }
+        }
+        Ok(())
+    }
+
+    // Write out the full direct IO buffer
+    fn flush_direct(&mut self) -> io::Result<()> {
+        if let Some(mut direct) = self.direct.take() {
+            let len = direct.len;
+            let result = self.write_through(&direct.buf()[..len]);
+            direct.len = 0;
+            self.direct = Some(direct);
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Write out what is buffered and hand back the file, not yet synced
+    ///
+    /// Direct IO can only write whole blocks, so the last one is padded and
+    /// the file truncated to its length afterwards.
+    pub(crate) fn finish(mut self) -> Result<File, CompressionError> {
+        if let Some(mut direct) = self.direct.take() {
+            let len = direct.len;
+            let padded = len.next_multiple_of(DIRECT_IO_ALIGN);
+            direct.buf()[len..padded].fill(0);
+            self.write_through(&direct.buf()[..padded])?;
+            self.file.set_len(self.written)?;
+        }
+        Ok(self.file)
+    }
+
+    /// Sync the file if the policy asks for it at all
+    pub(crate) fn sync_on_close(policy: SyncPolicy, file: &File) -> io::Result<()> {
+        match policy {
+            SyncPolicy::None => Ok(()),
+            SyncPolicy::OnClose | SyncPolicy::Periodic(_) => file.sync_all(),
+        }
+    }
+}
+
+impl Write for SegmentFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.written += data.len() as u64;
+        if self.direct.is_none() {
+            self.write_through(data)?;
+            return Ok(data.len());
+        }
+        let mut rest = data;
+        while !rest.is_empty() {
+            let direct = self.direct.as_mut().expect("direct IO checked above");
+            let len = direct.len;
+            let n = rest.len().min(DIRECT_IO_BUF - len);
+            direct.buf()[len..len + n].copy_from_slice(&rest[..n]);
+            direct.len += n;
+            rest = &rest[n..];
+            if direct.len == DIRECT_IO_BUF {
+                self.flush_direct()?;
+            }
+        }
+        Ok(data.len())
+    }
+
+    // Direct IO keeps partial blocks until finish
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_direct(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_direct(path: &Path) -> io::Result<File> {
+    tracing::debug!("No direct IO for {} on this platform", path.display());
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compressor;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_segment_file_direct_and_periodic() {
+        let tmp_dir = TempDir::new().unwrap();
+        // More than one direct IO buffer, ending mid-block
+        let data: Vec<u8> = (0..DIRECT_IO_BUF * 2 + 1234)
+            .map(|i| (i * 31 % 251) as u8)
+            .collect();
+        for (name, policy, direct_io) in [
+            ("plain", SyncPolicy::None, false),
+            ("direct", SyncPolicy::OnClose, true),
+            ("periodic", SyncPolicy::Periodic(100_000), true),
+        ] {
+            let path = tmp_dir.path().join(name);
+            let mut f = SegmentFile::create(&path, policy, direct_io).unwrap();
+            for chunk in data.chunks(7919) {
+                f.write_all(chunk).unwrap();
+            }
+            let file = f.finish().unwrap();
+            SegmentFile::sync_on_close(policy, &file).unwrap();
+            drop(file);
+            assert_eq!(std::fs::read(&path).unwrap(), data, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_compressor_sync_options_same_output() {
+        let tmp_dir = TempDir::new().unwrap();
+        let compress = |name: &str, sync: SyncPolicy, direct_io: bool| {
+            let path = tmp_dir.path().join(name);
+            let mut compressor = Compressor::builder(&path)
+                .sync_policy(sync)
+                .direct_io(direct_io)
+                .checksum(true)
+                .build()
+                .unwrap();
+            for i in 0..2000u32 {
+                compressor
+                    .add_word(format!("word {} of the sync test", i % 300).as_bytes())
+                    .unwrap();
+            }
+            compressor.compress().unwrap();
+            std::fs::read(&path).unwrap()
+        };
+        let plain = compress("plain.seg", SyncPolicy::None, false);
+        assert_eq!(compress("direct.seg", SyncPolicy::OnClose, true), plain);
+        assert_eq!(
+            compress("periodic.seg", SyncPolicy::Periodic(4096), true),
+            plain
+        );
+        crate::Decompressor::new(tmp_dir.path().join("direct.seg"))
+            .unwrap()
+            .verify_checksum()
+            .unwrap();
+    }
+}
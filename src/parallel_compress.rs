@@ -1,9 +1,79 @@
 // Port of Erigon's parallel_compress.go
 // Original: go/src/parallel_compress.go
 
-use crate::compress::{CompressionWord, Pattern, PatternHuff, Position, PositionHuff, Ring};
+use crate::cancel::{self, CancellationToken};
+use crate::compress::{
+    decode_varint, depth_histogram, encode_varint, reverse_bits_64, BitWriter, CompressionStats,
+    CompressionWord, Pattern, PatternHeap, PatternHuff, PatternHuffWrapper, PhaseTimings, Position,
+    PositionHeap, PositionHuff, PositionHuffWrapper, Ring,
+};
+use crate::core::SegmentHeader;
 use crate::error::CompressionError;
-use radix_trie::Trie;
+use crate::trace::word_trace;
+use aho_corasick::{AhoCorasick, MatchKind};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+/// Scratch space for covering words, reused from one word to the next
+///
+/// [`cover_word_by_patterns`] and [`cover_word_greedy`] write the cover of a
+/// word here rather than returning it, and keep their dynamic programming
+/// cells and matches here too: once the buffers have grown to the longest
+/// word seen, covering a word allocates nothing. The cover of a word is
+/// overwritten by the next one.
+#[derive(Default)]
+pub struct CoverBuffers {
+    output: Vec<u8>,
+    uncovered: Vec<usize>,
+    used_patterns: Vec<u64>,
+    cell_ring: Ring,
+    patterns: Vec<usize>,
+    matches: Vec<MatchSpan>,
+    longest: Vec<Option<(usize, usize)>>,
+    chosen: Vec<usize>,
+}
+
+impl CoverBuffers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Intermediate encoding of the last word covered
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Ranges of the last word that no pattern covers, as start/end pairs
+    pub fn uncovered(&self) -> &[usize] {
+        &self.uncovered
+    }
+
+    /// Number of bytes of the last word that no pattern covers
+    pub fn uncovered_len(&self) -> usize {
+        self.uncovered
+            .chunks_exact(2)
+            .map(|range| range[1] - range[0])
+            .sum()
+    }
+
+    /// Sequential codes of the patterns the last word was covered with
+    pub fn used_patterns(&self) -> &[u64] {
+        &self.used_patterns
+    }
+
+    // The cover of a word without patterns: all of it uncovered
+    fn set_uncovered(&mut self, input: &[u8]) {
+        self.output.clear();
+        self.output.push(0); // Encoding of 0 in VarUint is 1 zero byte
+        self.output.extend_from_slice(input);
+        self.uncovered.clear();
+        self.uncovered.push(0);
+        self.uncovered.push(input.len());
+        self.used_patterns.clear();
+    }
+}
 
 // From Go: coverWordByPatterns function
 // Go: parallel_compress.go:42
@@ -11,38 +81,38 @@ pub fn cover_word_by_patterns(
     trace: bool,
     input: &[u8],
     match_finder: &MatchFinder,
-    output: &mut Vec<u8>,
-    uncovered: &mut Vec<usize>,
-    patterns: &mut Vec<usize>,
-    cell_ring: &mut Ring,
+    buffers: &mut CoverBuffers,
     pos_map: &mut std::collections::HashMap<u64, u64>,
-) -> (Vec<u8>, Vec<usize>, Vec<u64>) {
-    // Return used pattern sequential codes
+) {
     // Go: parallel_compress.go:42-179
 
-    log::debug!(
+    word_trace!(
         "cover_word_by_patterns: input = '{}'",
         String::from_utf8_lossy(input)
     );
 
-    // Clear output buffer
-    output.clear();
-
     // Find all pattern matches in the input
-    let matches = match_finder.find_longest_matches(input);
+    match_finder.find_matches_into(input, &mut buffers.longest, &mut buffers.matches);
 
     // Go: parallel_compress.go:45-48
-    if matches.is_empty() {
+    if buffers.matches.is_empty() {
         // No patterns found - encode as uncompressed
-        output.push(0); // Encoding of 0 in VarUint is 1 zero byte
-        output.extend_from_slice(input);
-        return (output.clone(), uncovered.clone(), Vec::new());
+        buffers.set_uncovered(input);
+        return;
     }
 
+    let CoverBuffers {
+        cell_ring,
+        patterns,
+        matches,
+        chosen,
+        ..
+    } = &mut *buffers;
+
     if trace {
-        println!("Cluster | input = {:?}", input);
-        for match_info in &matches {
-            println!(
+        word_trace!("Cluster | input = {:?}", input);
+        for match_info in matches.iter() {
+            word_trace!(
                 " [{:?} {}-{}]",
                 &input[match_info.start..match_info.end],
                 match_info.start,
@@ -73,7 +143,8 @@ pub fn cover_word_by_patterns(
     // Go: parallel_compress.go:68-128
     // Dynamic programming to find optimal pattern coverage
     for i in (0..matches.len()).rev() {
-        let f = &matches[i];
+        let f = matches[i];
+        let f_score = match_finder.pattern(f.pattern).score;
         let first_cell = cell_ring.get(0);
         let mut max_compression = first_cell.compression;
         let mut max_score = first_cell.score;
@@ -82,7 +153,7 @@ pub fn cover_word_by_patterns(
 
         for e in 0..cell_ring.len() {
             let cell = cell_ring.get(e);
-            let mut comp = cell.compression as i32 - 4; // Cost of encoding pattern
+            let mut comp = cell.compression - 4; // Cost of encoding pattern
 
             if cell.cover_start >= f.end {
                 comp += (f.end - f.start) as i32;
@@ -90,7 +161,7 @@ pub fn cover_word_by_patterns(
                 comp += (cell.cover_start - f.start) as i32;
             }
 
-            let score = cell.score + f.pattern.score;
+            let score = cell.score + f_score;
 
             if comp > max_compression || (comp == max_compression && score > max_score) {
                 max_compression = comp;
@@ -139,25 +210,82 @@ pub fn cover_word_by_patterns(
     let optim_cell = cell_ring.get(0);
     // REVIEW: missing if trace block
 
-    // Count number of patterns
-    let mut pattern_count = 0u64;
+    // Collect the chosen matches, first to last
+    chosen.clear();
     let mut pattern_idx = optim_cell.pattern_idx;
     while pattern_idx != 0 {
-        pattern_count += 1;
         if pattern_idx + 1 >= patterns.len() {
-            log::warn!(
+            tracing::warn!(
                 "Pattern index out of bounds: {} >= {}",
                 pattern_idx + 1,
                 patterns.len()
             );
             break;
         }
+        chosen.push(patterns[pattern_idx]);
         // REVIEW: why +1? look at go code
         pattern_idx = patterns[pattern_idx + 1];
     }
 
+    encode_cover(trace, input, match_finder, buffers, pos_map)
+}
+
+/// Cover `input` with every non-overlapping longest match that saves bytes,
+/// without the optimization of [`cover_word_by_patterns`]
+///
+/// Much cheaper on long words, for a slightly worse cover; the output has
+/// the same layout. Used for [`crate::compress::OptimizerMode::Greedy`].
+pub fn cover_word_greedy(
+    trace: bool,
+    input: &[u8],
+    match_finder: &MatchFinder,
+    buffers: &mut CoverBuffers,
+    pos_map: &mut std::collections::HashMap<u64, u64>,
+) {
+    match_finder.find_matches_into(input, &mut buffers.longest, &mut buffers.matches);
+    if buffers.matches.is_empty() {
+        // As in cover_word_by_patterns: no patterns, all of it uncovered
+        buffers.set_uncovered(input);
+        return;
+    }
+    // The matches don't overlap, so each one is taken when it is longer than
+    // the cost the exact cover assumes for encoding a pattern
+    buffers.chosen.clear();
+    buffers.chosen.extend(
+        buffers
+            .matches
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.end - m.start > 4)
+            .map(|(i, _)| i),
+    );
+    encode_cover(trace, input, match_finder, buffers, pos_map)
+}
+
+// Write the cover of `input` by `buffers.matches[buffers.chosen]` (ascending,
+// non-overlapping) in the intermediate layout: pattern count, then absolute
+// position and sequential code of each pattern, then the uncovered bytes
+// Go: parallel_compress.go:129-178
+fn encode_cover(
+    trace: bool,
+    input: &[u8],
+    match_finder: &MatchFinder,
+    buffers: &mut CoverBuffers,
+    pos_map: &mut std::collections::HashMap<u64, u64>,
+) {
+    let CoverBuffers {
+        output,
+        uncovered,
+        used_patterns,
+        matches,
+        chosen,
+        ..
+    } = buffers;
+    output.clear();
+    let pattern_count = chosen.len() as u64;
+
     if trace {
-        println!("Pattern count for word: {}", pattern_count);
+        word_trace!("Pattern count for word: {}", pattern_count);
     }
 
     // Write pattern count
@@ -166,7 +294,7 @@ pub fn cover_word_by_patterns(
     output.extend_from_slice(&num_buf[..n]);
 
     if trace {
-        println!(
+        word_trace!(
             "Writing pattern count: {} (bytes: {:?})",
             pattern_count,
             &num_buf[..n]
@@ -174,15 +302,14 @@ pub fn cover_word_by_patterns(
     }
 
     // Write patterns and track uncovered regions
-    pattern_idx = optim_cell.pattern_idx;
     let mut last_start = 0;
     let mut last_uncovered = 0;
     uncovered.clear();
-    let mut used_patterns = Vec::new(); // Track which patterns were used
+    used_patterns.clear(); // Track which patterns were used
 
-    while pattern_idx != 0 {
-        let pattern_match_idx = patterns[pattern_idx];
-        let pattern_match = &matches[pattern_match_idx];
+    for &match_idx in chosen.iter() {
+        let pattern_match = matches[match_idx];
+        let pattern = match_finder.pattern(pattern_match.pattern);
 
         if pattern_match.start > last_uncovered {
             uncovered.push(last_uncovered);
@@ -200,14 +327,15 @@ pub fn cover_word_by_patterns(
         output.extend_from_slice(&num_buf[..n]);
 
         if trace {
-            println!(
+            word_trace!(
                 "Writing pattern position: absolute={}, relative={}",
-                pattern_match.start, relative_pos
+                pattern_match.start,
+                relative_pos
             );
         }
 
         // Write pattern's SEQUENTIAL code (not Huffman code) to intermediate file
-        let seq_code = pattern_match.pattern.sequential_code;
+        let seq_code = pattern.sequential_code;
         let n = encode_varint(&mut num_buf, seq_code);
         output.extend_from_slice(&num_buf[..n]);
 
@@ -215,14 +343,12 @@ pub fn cover_word_by_patterns(
         used_patterns.push(seq_code);
 
         if trace {
-            println!(
+            word_trace!(
                 "Writing pattern sequential code: {} for pattern '{}'",
-                pattern_match.pattern.sequential_code,
-                String::from_utf8_lossy(&pattern_match.pattern.word)
+                pattern.sequential_code,
+                String::from_utf8_lossy(&pattern.word)
             );
         }
-
-        pattern_idx = patterns[pattern_idx + 1];
     }
 
     // Track position 0 for terminator encoding (but don't write it to intermediate file)
@@ -237,8 +363,6 @@ pub fn cover_word_by_patterns(
     for i in (0..uncovered.len()).step_by(2) {
         output.extend_from_slice(&input[uncovered[i]..uncovered[i + 1]]);
     }
-
-    (output.clone(), uncovered.clone(), used_patterns)
 }
 
 // REVIEW coverWordsByPatternsWorker missing - is this functionality covered? Are we doing this but
@@ -252,18 +376,170 @@ pub type CompressionQueue = Vec<CompressionWord>;
 
 // From Go: compressWithPatternCandidates function (main compression pipeline)
 // Go: parallel_compress.go:238
+#[allow(clippy::too_many_arguments)]
 pub fn compress_with_pattern_candidates(
     trace: bool,
     cfg: &crate::compress::Cfg,
     log_prefix: &str,
-    segment_file_path: &str,
-    cf: &mut std::fs::File,
+    intermediate_path: &Path,
+    cf: &mut impl std::io::Write,
     uncompressed_file: &mut crate::compress::RawWordsFile,
     dict_builder: &crate::compress::DictionaryBuilder,
+    progress: Option<&(dyn Fn(u64, u64) + Send + Sync)>,
+    cancel: Option<&CancellationToken>,
+) -> std::result::Result<CompressionStats, CompressionError> {
+    let (stats, _) = encode_with_patterns(
+        trace,
+        cfg,
+        log_prefix,
+        intermediate_path,
+        cf,
+        uncompressed_file,
+        dict_builder,
+        progress,
+        cancel,
+    )?;
+    Ok(stats)
+}
+
+// Words and bytes of a batch covered by several workers; a batch is held
+// in memory, so its bytes are capped too
+const COVER_BATCH_WORDS: usize = 64 * 1024;
+const COVER_BATCH_BYTES: usize = 64 << 20;
+
+// What covering words adds up to, summed over the words in any order
+#[derive(Default)]
+pub(crate) struct CoverTally {
+    // Uses of every position
+    pub(crate) pos_map: HashMap<u64, u64>,
+    // Uses of every pattern, by sequential code
+    pub(crate) pattern_uses: HashMap<u64, u64>,
+    // Bytes of the compressed words covered with patterns
+    pub(crate) covered_bytes: u64,
+    // Bytes of the intermediate encoding after the length prefixes
+    pub(crate) intermediate_bytes: u64,
+}
+
+impl CoverTally {
+    fn merge(&mut self, other: CoverTally) {
+        for (pos, uses) in other.pos_map {
+            *self.pos_map.entry(pos).or_insert(0) += uses;
+        }
+        for (code, uses) in other.pattern_uses {
+            *self.pattern_uses.entry(code).or_insert(0) += uses;
+        }
+        self.covered_bytes += other.covered_bytes;
+        self.intermediate_bytes += other.intermediate_bytes;
+    }
+}
+
+// Append the intermediate encoding of `word` to `out`: its length, then its
+// cover, or a 0 and the word itself if it is not compressed
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn cover_word(
+    trace: bool,
+    optimizer: crate::compress::OptimizerMode,
+    word: &[u8],
+    compression: bool,
+    match_finder: &MatchFinder,
+    buffers: &mut CoverBuffers,
+    tally: &mut CoverTally,
+    out: &mut Vec<u8>,
+) {
+    let mut num_buf = [0u8; 10];
+    let n = encode_varint(&mut num_buf, word.len() as u64);
+    out.extend_from_slice(&num_buf[..n]);
+    if word.is_empty() {
+        return;
+    }
+    if !compression {
+        // Go: parallel_compress.go:382-388
+        out.push(0);
+        out.extend_from_slice(word);
+        tally.intermediate_bytes += 1 + word.len() as u64;
+        return;
+    }
+
+    // Go: parallel_compress.go:376
+    if optimizer.is_greedy_for(word.len()) {
+        cover_word_greedy(trace, word, match_finder, buffers, &mut tally.pos_map);
+    } else {
+        cover_word_by_patterns(trace, word, match_finder, buffers, &mut tally.pos_map);
+    }
+    tally.covered_bytes += (word.len() - buffers.uncovered_len()) as u64;
+    for &seq_code in buffers.used_patterns() {
+        *tally.pattern_uses.entry(seq_code).or_insert(0) += 1;
+    }
+    out.extend_from_slice(buffers.output());
+    tally.intermediate_bytes += buffers.output().len() as u64;
+}
+
+// Cover `words` as `workers` runs of consecutive words on the `blocking`
+// thread pool, then write the runs out in word order: a word's cover only
+// depends on the word and the tallies are sums, so the intermediate file is
+// the same for any number of workers
+#[allow(clippy::too_many_arguments)]
+fn cover_in_parallel(
+    trace: bool,
+    optimizer: crate::compress::OptimizerMode,
+    workers: usize,
+    match_finder: &Arc<MatchFinder>,
+    words: Vec<(Vec<u8>, bool)>,
+    tally: &mut CoverTally,
+    w: &mut impl Write,
 ) -> std::result::Result<(), CompressionError> {
-    use std::collections::HashMap;
+    let per_run = words.len().div_ceil(workers).max(1);
+    let mut words = words.into_iter().peekable();
+    let mut tasks = Vec::with_capacity(workers);
+    while words.peek().is_some() {
+        let run: Vec<(Vec<u8>, bool)> = words.by_ref().take(per_run).collect();
+        let match_finder = Arc::clone(match_finder);
+        tasks.push(blocking::unblock(move || {
+            let mut buffers = CoverBuffers::new();
+            let mut tally = CoverTally::default();
+            let mut out = Vec::new();
+            for (word, compression) in &run {
+                cover_word(
+                    trace,
+                    optimizer,
+                    word,
+                    *compression,
+                    &match_finder,
+                    &mut buffers,
+                    &mut tally,
+                    &mut out,
+                );
+            }
+            (out, tally)
+        }));
+    }
+    for task in tasks {
+        let (out, run_tally) = futures_lite::future::block_on(task);
+        w.write_all(&out)?;
+        tally.merge(run_tally);
+    }
+    Ok(())
+}
+
+// compress_with_pattern_candidates, also returning every pattern of the
+// dictionary with the uses and Huffman code the encoding gave it
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn encode_with_patterns(
+    trace: bool,
+    cfg: &crate::compress::Cfg,
+    log_prefix: &str,
+    intermediate_path: &Path,
+    cf: &mut impl std::io::Write,
+    uncompressed_file: &mut crate::compress::RawWordsFile,
+    dict_builder: &crate::compress::DictionaryBuilder,
+    progress: Option<&(dyn Fn(u64, u64) + Send + Sync)>,
+    cancel: Option<&CancellationToken>,
+) -> std::result::Result<(CompressionStats, Vec<Pattern>), CompressionError> {
     use std::fs::File;
-    use std::io::{BufWriter, Write};
+    use std::io::BufWriter;
+    use std::time::Instant;
+
+    let cover_start = Instant::now();
 
     // Go: parallel_compress.go:243-255
     // Build pattern dictionary and trie
@@ -286,9 +562,9 @@ pub fn compress_with_pattern_candidates(
 
     // Following Go's approach: use sequential codes during processing,
     // build Huffman codes AFTER processing all words
-    log::debug!("Pattern dictionary with sequential codes:");
+    word_trace!("Pattern dictionary with sequential codes:");
     for (i, pattern) in code2pattern.iter().enumerate() {
-        log::debug!(
+        word_trace!(
             "  Pattern {}: '{}' (score {}, seq_code {})",
             i,
             String::from_utf8_lossy(&pattern.word),
@@ -300,9 +576,9 @@ pub fn compress_with_pattern_candidates(
     // We'll build Huffman codes AFTER processing all words (like Go does)
     // For now, just set up for processing with sequential codes
 
-    log::debug!("Starting word processing with sequential codes:");
+    word_trace!("Starting word processing with sequential codes:");
     for (i, p) in code2pattern.iter().enumerate() {
-        log::debug!(
+        word_trace!(
             "  Pattern {}: '{}' (depth {}, score {}, code {}, bits {}, seq_code {})",
             i,
             String::from_utf8_lossy(&p.word),
@@ -317,48 +593,39 @@ pub fn compress_with_pattern_candidates(
     // match_finder already has patterns with sequential codes
     // Position codes will be built after processing words
 
-    if cfg.workers > 1 {
-        // Multi-worker mode not yet implemented
-        log::warn!(
-            "[{}] Multi-worker compression not yet implemented, using single worker",
-            log_prefix
-        );
-    }
-
     // Go: parallel_compress.go:296-303
     // Create intermediate file for first pass
-    let intermediate_path = format!("{}.tmp", segment_file_path);
-    let intermediate_file = File::create(&intermediate_path)?;
+    let intermediate_file = File::create(intermediate_path)?;
     let mut intermediate_w = BufWriter::new(intermediate_file);
 
-    // Position map for uncompressed words
-    let mut uncomp_pos_map: HashMap<u64, u64> = HashMap::new();
-
-    // Variables for single-worker mode
-    let mut output = Vec::with_capacity(256);
-    let mut uncovered = vec![0; 256];
-    let mut patterns = Vec::with_capacity(256);
-    let mut cell_ring = Ring::new();
-
-    // Track pattern uses (since we can't mutate patterns in MatchFinder)
-    let mut pattern_uses: HashMap<u64, u64> = HashMap::new(); // sequential_code -> uses
-
-    let mut input_size = 0u64;
-    let mut output_size = 0u64;
+    // Positions (of uncompressed words too) and pattern uses of all words
+    let mut tally = CoverTally::default();
+    let mut word_bytes = 0u64;
     let mut in_count = 0u64;
     let mut empty_words_count = 0u64;
     let total_words = uncompressed_file.count;
 
-    log::debug!(
-        "[{}] Starting to process {} words from uncompressed file",
+    // One worker covers the words as they come; more cover batches of them
+    let workers = cfg.workers.max(1);
+    let match_finder = Arc::new(match_finder);
+    let mut buffers = CoverBuffers::new();
+    let mut output = Vec::new();
+    let mut batch: Vec<(Vec<u8>, bool)> = Vec::new();
+    let mut batch_bytes = 0;
+
+    tracing::debug!(
+        "[{}] Starting to process {} words from uncompressed file with {} workers",
         log_prefix,
-        total_words
+        total_words,
+        workers
     );
 
     // Go: parallel_compress.go:309-410
     // Process each word
+    let cover_span = tracing::info_span!("cover", words = total_words).entered();
     uncompressed_file.for_each(|v, compression| {
-        log::debug!(
+        cancel::check(cancel)?;
+        word_trace!(
             "Processing word, len: {}, compressed: {}",
             v.len(),
             compression
@@ -368,68 +635,93 @@ pub fn compress_with_pattern_candidates(
             empty_words_count += 1;
         }
         let word_len = v.len() as u64;
-
-        // Write length prefix
-        let mut num_buf = [0u8; 10];
-        let n = encode_varint(&mut num_buf, word_len);
-        intermediate_w.write_all(&num_buf[..n]).ok();
-
-        if word_len > 0 {
-            if compression {
-                // Go: parallel_compress.go:376
-                // Apply pattern compression
-                let (compressed, _uncovered, used_patterns) = cover_word_by_patterns(
+        word_bytes += word_len;
+        *tally.pos_map.entry(word_len + 1).or_insert(0) += 1;
+        *tally.pos_map.entry(0).or_insert(0) += 1;
+
+        if workers == 1 {
+            output.clear();
+            cover_word(
+                trace,
+                cfg.optimizer,
+                v,
+                compression,
+                &match_finder,
+                &mut buffers,
+                &mut tally,
+                &mut output,
+            );
+            intermediate_w.write_all(&output)?;
+        } else {
+            batch.push((v.to_vec(), compression));
+            batch_bytes += v.len();
+            if batch.len() >= COVER_BATCH_WORDS || batch_bytes >= COVER_BATCH_BYTES {
+                cover_in_parallel(
                     trace,
-                    v,
+                    cfg.optimizer,
+                    workers,
                     &match_finder,
-                    &mut output,
-                    &mut uncovered,
-                    &mut patterns,
-                    &mut cell_ring,
-                    &mut uncomp_pos_map,
-                );
-
-                // Track pattern uses from this word
-                for seq_code in used_patterns {
-                    *pattern_uses.entry(seq_code).or_insert(0) += 1;
-                }
-                intermediate_w.write_all(&compressed).ok();
-                output_size += compressed.len() as u64;
-            } else {
-                // Go: parallel_compress.go:382-388
-                // No compression - write 0 byte + raw word
-                intermediate_w.write_all(&[0]).ok();
-                intermediate_w.write_all(v).ok();
-                output_size += 1 + v.len() as u64;
+                    std::mem::take(&mut batch),
+                    &mut tally,
+                    &mut intermediate_w,
+                )?;
+                batch_bytes = 0;
             }
         }
 
-        input_size += 1 + word_len;
-        *uncomp_pos_map.entry(word_len + 1).or_insert(0) += 1;
-        *uncomp_pos_map.entry(0).or_insert(0) += 1;
-
         // Progress logging
-        if in_count % 100000 == 0 {
-            log::trace!(
+        if in_count.is_multiple_of(100000) {
+            tracing::trace!(
                 "[{}] Compression preprocessing: {:.2}%",
                 log_prefix,
                 100.0 * in_count as f64 / total_words as f64
             );
+            if let Some(progress) = progress {
+                progress(in_count, total_words);
+            }
         }
 
         Ok(())
     })?;
+    if !batch.is_empty() {
+        cover_in_parallel(
+            trace,
+            cfg.optimizer,
+            workers,
+            &match_finder,
+            batch,
+            &mut tally,
+            &mut intermediate_w,
+        )?;
+    }
+    let CoverTally {
+        pos_map: uncomp_pos_map,
+        pattern_uses,
+        covered_bytes,
+        intermediate_bytes,
+    } = tally;
+
+    if let Some(progress) = progress {
+        progress(in_count, total_words);
+    }
 
     // Flush intermediate file
     intermediate_w.flush()?;
     drop(intermediate_w);
-
-    log::debug!(
-        "[{}] Intermediate file written, processing {} words, {} empty",
-        log_prefix,
-        in_count,
-        empty_words_count
+    let cover_time = cover_start.elapsed();
+    drop(cover_span);
+    tracing::info!(
+        words = in_count,
+        empty_words = empty_words_count,
+        input_bytes = word_bytes,
+        covered_bytes,
+        intermediate_bytes,
+        elapsed = ?cover_time,
+        "[{}] Cover phase done",
+        log_prefix
     );
+    let write_start = Instant::now();
+    let write_span = tracing::info_span!("write").entered();
 
     // Go: parallel_compress.go:453-525
     // NOW build Huffman codes based on actual pattern usage
@@ -458,7 +750,7 @@ pub fn compress_with_pattern_candidates(
         }
     });
 
-    log::debug!("Building Huffman codes for {} patterns", pattern_list.len());
+    tracing::debug!("Building Huffman codes for {} patterns", pattern_list.len());
 
     // Build Huffman codes
     let mut pattern_huff = PatternHuffBuilder::new(pattern_list);
@@ -480,7 +772,7 @@ pub fn compress_with_pattern_candidates(
     // Sort pattern list for dictionary writing (Go line 551)
     pattern_list.sort_by(crate::compress::pattern_list_cmp);
 
-    log::debug!("Pattern Huffman codes built");
+    tracing::debug!("Pattern Huffman codes built");
 
     // Go: parallel_compress.go:533-625
     // Build Huffman codes for positions
@@ -488,9 +780,67 @@ pub fn compress_with_pattern_candidates(
     // Pattern Huffman codes already built earlier
     // Use the existing pattern_huff from earlier
 
-    // Build Huffman codes for positions
+    let positions = position_codes(&uncomp_pos_map);
+
+    if cfg!(debug_assertions) || cfg.verify_codes {
+        crate::decompress::verify_codes(&pattern_list, &positions)?;
+    }
+
+    // Write final compressed file
+    // Pass both arrays: code2pattern for sequential lookup, pattern_list for dictionary
+    let (pattern_dict_size, pos_dict_size) = write_compressed_file(
+        cf,
+        intermediate_path,
+        &code2pattern,
+        &pattern_list,
+        &positions,
+        in_count,
+        empty_words_count,
+    )?;
+
+    // Clean up intermediate file
+    std::fs::remove_file(intermediate_path).ok();
+    let write_time = write_start.elapsed();
+    drop(write_span);
+
+    tracing::info!(
+        patterns = pattern_list.len(),
+        positions = positions.len(),
+        pattern_dict_size,
+        pos_dict_size,
+        elapsed = ?write_time,
+        "[{}] Write phase done",
+        log_prefix
+    );
+
+    let stats = CompressionStats {
+        words: in_count,
+        empty_words: empty_words_count,
+        patterns: pattern_list.len(),
+        positions: positions.len(),
+        pattern_dict_size,
+        pos_dict_size,
+        pattern_depths: depth_histogram(pattern_list.iter().map(|p| p.depth as u64)),
+        position_depths: depth_histogram(positions.iter().map(|p| p.depth as u64)),
+        input_bytes: Some(word_bytes),
+        covered_bytes: Some(covered_bytes),
+        output_bytes: 0,
+        phases: Some(PhaseTimings {
+            dictionary: std::time::Duration::ZERO,
+            cover: cover_time,
+            write: write_time,
+        }),
+    };
+    Ok((stats, code2pattern))
+}
+
+// Huffman codes for the positions of `pos_map` and their uses, in the
+// order of the position dictionary
+pub(crate) fn position_codes(pos_map: &HashMap<u64, u64>) -> Vec<Position> {
+    // Build Huffman codes for positions, from a list in position order
+    // whatever order the map holds them in
     let mut positions = Vec::new();
-    for (pos, &uses) in &uncomp_pos_map {
+    for (pos, &uses) in pos_map {
         positions.push(Position {
             uses,
             pos: *pos,
@@ -499,18 +849,19 @@ pub fn compress_with_pattern_candidates(
             depth: 0,
         });
     }
-    log::debug!(
+    positions.sort_by_key(|p| p.pos);
+    tracing::debug!(
         "Building position huffman codes for {} positions",
         positions.len()
     );
     for p in &positions {
-        log::debug!("  Position {} with {} uses", p.pos, p.uses);
+        word_trace!("  Position {} with {} uses", p.pos, p.uses);
     }
     let mut position_huff = PositionHuffBuilder::new(positions);
     position_huff.build_huffman_codes();
-    log::debug!("After huffman building:");
+    word_trace!("After huffman building:");
     for p in &position_huff.positions {
-        log::debug!(
+        word_trace!(
             "  Position {}: depth={}, code={}, bits={}",
             p.pos,
             p.depth,
@@ -526,9 +877,9 @@ pub fn compress_with_pattern_candidates(
         .positions
         .sort_by(crate::compress::position_list_cmp);
 
-    log::debug!("After final position sorting for dictionary:");
+    word_trace!("After final position sorting for dictionary:");
     for p in &position_huff.positions {
-        log::debug!(
+        word_trace!(
             "  Position {}: depth={}, code={}, bits={}",
             p.pos,
             p.depth,
@@ -536,31 +887,7 @@ pub fn compress_with_pattern_candidates(
             p.code_bits
         );
     }
-
-    // Write final compressed file
-    // Pass both arrays: code2pattern for sequential lookup, pattern_list for dictionary
-    write_compressed_file(
-        cf,
-        &intermediate_path,
-        &code2pattern,
-        &pattern_list,
-        &position_huff.positions,
-        in_count,
-        empty_words_count,
-    )?;
-
-    // Clean up intermediate file
-    std::fs::remove_file(&intermediate_path).ok();
-
-    log::info!(
-        "[{}] Compression complete: input_size={}, output_size={}, ratio={:.2}",
-        log_prefix,
-        input_size,
-        output_size,
-        input_size as f64 / output_size as f64
-    );
-
-    Ok(())
+    position_huff.positions
 }
 
 // REVIEW: why not extract patterns in many superstrings? why use this new function?
@@ -664,119 +991,6 @@ pub fn extract_patterns_in_superstrings(
         .collect()
 }
 
-// Original full implementation (kept for reference but not used)
-fn _extract_patterns_in_superstrings_old(
-    superstrings: Vec<Vec<u8>>,
-    cfg: &crate::compress::Cfg,
-) -> Vec<Pattern> {
-    // Go: parallel_compress.go:744-824
-    use cdivsufsort::sort_in_place;
-    use std::collections::HashMap;
-
-    let mut pattern_map: HashMap<Vec<u8>, u64> = HashMap::new();
-    let min_pattern_len = cfg.min_pattern_len;
-    let max_pattern_len = cfg.max_pattern_len;
-    let min_pattern_score = cfg.min_pattern_score;
-
-    for superstring in superstrings {
-        if superstring.is_empty() {
-            continue;
-        }
-
-        // Build suffix array using divsufsort
-        // Go: parallel_compress.go:764
-        let mut sa = vec![0i32; superstring.len()];
-        sort_in_place(&superstring, &mut sa);
-
-        // Filter out suffixes that start with odd positions
-        // Go: parallel_compress.go:769-778
-        let n = sa.len() / 2;
-        let mut filtered = Vec::with_capacity(n);
-        for &pos in &sa {
-            if pos & 1 == 0 {
-                filtered.push(pos >> 1); // Divide by 2 to get actual position
-            }
-        }
-
-        // Build inverse suffix array
-        // Go: parallel_compress.go:779-787
-        let mut inv = vec![0i32; filtered.len()];
-        for (i, &pos) in filtered.iter().enumerate() {
-            if (pos as usize) < inv.len() {
-                inv[pos as usize] = i as i32;
-            }
-        }
-
-        // Compute LCP array using Kasai's algorithm
-        // Go: parallel_compress.go:789-823
-        let mut lcp = vec![0i32; filtered.len()];
-        let mut k = 0;
-
-        for i in 0..filtered.len() {
-            if inv[i] == (filtered.len() - 1) as i32 {
-                k = 0;
-                lcp[inv[i] as usize] = 0;
-                continue;
-            }
-
-            let j = filtered[(inv[i] + 1) as usize] as usize;
-            let i_pos = i;
-
-            // Compare characters at positions i+k and j+k
-            // Go: parallel_compress.go:814
-            while i_pos + k < filtered.len()
-                && j + k < filtered.len()
-                && i_pos * 2 + k * 2 < superstring.len()
-                && j * 2 + k * 2 < superstring.len()
-                && superstring[i_pos * 2 + k * 2] != 0
-                && superstring[j * 2 + k * 2] != 0
-                && superstring[i_pos * 2 + k * 2 + 1] == superstring[j * 2 + k * 2 + 1]
-            {
-                k += 1;
-            }
-
-            lcp[inv[i] as usize] = k as i32;
-
-            if k > 0 {
-                k -= 1;
-            }
-        }
-
-        // Extract patterns based on LCP values
-        // Patterns are substrings that appear multiple times (LCP > 0)
-        // and meet length and score requirements
-        for i in 0..lcp.len() {
-            let prefix_len = lcp[i] as usize;
-
-            if prefix_len >= min_pattern_len && prefix_len <= max_pattern_len {
-                let pos = filtered[i] as usize;
-
-                // Extract the pattern bytes from the superstring
-                // Remember: superstring uses 2 bytes per character
-                let mut pattern_bytes = Vec::with_capacity(prefix_len);
-                for j in 0..prefix_len {
-                    if pos * 2 + j * 2 + 1 < superstring.len() {
-                        pattern_bytes.push(superstring[pos * 2 + j * 2 + 1]);
-                    }
-                }
-
-                // Increment score for this pattern
-                *pattern_map.entry(pattern_bytes).or_insert(0) += 1;
-            }
-        }
-    }
-
-    // Convert to Pattern objects and filter by score
-    let mut patterns = Vec::new();
-    for (word, score) in pattern_map {
-        if score >= min_pattern_score {
-            patterns.push(Pattern::new(word, score));
-        }
-    }
-
-    patterns
-}
-
 // REVIEW: missing DictionaryBuilderFromCollector
 
 // REVIEW: missing PersistDictionary
@@ -785,64 +999,139 @@ fn _extract_patterns_in_superstrings_old(
 
 // Helper struct for pattern matching
 // Replaces patricia.MatchFinder2 from Go
+//
+/// Finds dictionary patterns in a word using an Aho-Corasick automaton, so a
+/// scan is linear in the input length plus the number of matches.
+///
+/// The automaton is built on the first search after an insert and then
+/// shared by every worker searching through the same `MatchFinder`, so
+/// searches take `&self`; `insert` takes `&mut self` and discards the stale
+/// automaton.
 pub struct MatchFinder {
-    trie: Trie<Vec<u8>, Box<Pattern>>, // Maps pattern bytes to Pattern objects
-    patterns: Vec<Box<Pattern>>,       // Keep patterns alive
+    patterns: Vec<Pattern>, // Pattern id in the automaton is the index here
+    automaton: OnceLock<Option<AhoCorasick>>,
+}
+
+impl Default for MatchFinder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MatchFinder {
     pub fn new() -> Self {
         MatchFinder {
-            trie: Trie::new(),
             patterns: Vec::new(),
+            automaton: OnceLock::new(),
         }
     }
 
     pub fn insert(&mut self, pattern: Pattern) {
-        let pattern_box = Box::new(pattern);
-        let pattern_ptr = pattern_box.clone();
-        self.trie.insert(pattern_box.word.clone(), pattern_ptr);
-        self.patterns.push(pattern_box);
+        self.patterns.push(pattern);
+        self.automaton = OnceLock::new();
+    }
+
+    fn automaton(&self) -> Option<&AhoCorasick> {
+        self.automaton
+            .get_or_init(|| {
+                match AhoCorasick::builder()
+                    .match_kind(MatchKind::Standard)
+                    .build(self.patterns.iter().map(|p| &p.word))
+                {
+                    Ok(ac) => Some(ac),
+                    Err(e) => {
+                        tracing::error!(
+                            "Failed to build matcher for {} patterns: {}",
+                            self.patterns.len(),
+                            e
+                        );
+                        None
+                    }
+                }
+            })
+            .as_ref()
+    }
+
+    fn pattern(&self, idx: usize) -> &Pattern {
+        &self.patterns[idx]
     }
 
     // Find all patterns that match starting at any position in input
     // This is equivalent to Go's FindLongestMatches
     pub fn find_longest_matches(&self, input: &[u8]) -> Vec<Match> {
+        let mut longest = Vec::new();
         let mut matches = Vec::new();
+        self.find_matches_into(input, &mut longest, &mut matches);
+        matches
+            .into_iter()
+            .map(|m| Match {
+                pattern: Box::new(self.patterns[m.pattern].clone()),
+                start: m.start,
+                end: m.end,
+            })
+            .collect()
+    }
+
+    // As find_longest_matches, into `matches` and with `longest` as scratch,
+    // both cleared first; matches refer to patterns by index
+    fn find_matches_into(
+        &self,
+        input: &[u8],
+        longest: &mut Vec<Option<(usize, usize)>>,
+        matches: &mut Vec<MatchSpan>,
+    ) {
+        matches.clear();
+        if input.is_empty() || self.patterns.is_empty() {
+            return;
+        }
+        let ac = match self.automaton() {
+            Some(ac) => ac,
+            None => return,
+        };
+
+        // Longest pattern starting at each position: (end, pattern index).
+        // Later inserts win when the same word was inserted twice.
+        longest.clear();
+        longest.resize(input.len(), None);
+        for m in ac.find_overlapping_iter(input) {
+            if m.is_empty() {
+                continue;
+            }
+            let idx = m.pattern().as_usize();
+            let slot = &mut longest[m.start()];
+            match *slot {
+                Some((end, prev)) if end > m.end() || (end == m.end() && prev > idx) => {}
+                _ => *slot = Some((m.end(), idx)),
+            }
+        }
 
-        // For each starting position in input
-        for start in 0..input.len() {
-            // Check all possible pattern lengths from this position
-            for end in (start + 1)..=input.len().min(start + 128) {
-                // Max pattern len
-                if let Some(pattern) = self.trie.get(&input[start..end]) {
-                    matches.push(Match {
-                        pattern: pattern.clone(),
+        // Keep the longest match at each start that does not overlap the
+        // previously kept one
+        let mut start = 0;
+        while start < input.len() {
+            match longest[start] {
+                Some((end, pattern)) => {
+                    matches.push(MatchSpan {
+                        pattern,
                         start,
                         end,
                     });
+                    start = end;
                 }
+                None => start += 1,
             }
         }
-
-        // Sort matches by start position, then by length (longest first)
-        matches.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| b.end.cmp(&a.end)));
-
-        // Remove overlapping shorter matches
-        let mut filtered = Vec::new();
-        let mut last_end = 0;
-
-        for m in matches {
-            if m.start >= last_end {
-                last_end = m.end;
-                filtered.push(m);
-            }
-        }
-
-        filtered
     }
 }
 
+// A Match without its pattern, which is an index into MatchFinder::patterns
+#[derive(Clone, Copy)]
+struct MatchSpan {
+    pattern: usize,
+    start: usize,
+    end: usize,
+}
+
 // Equivalent to Go's Match struct
 pub struct Match {
     pub pattern: Box<Pattern>, // The pattern that matched
@@ -864,8 +1153,6 @@ impl PatternHuffBuilder {
     pub fn build_huffman_codes(&mut self) {
         // Go: parallel_compress.go:454-524
         // Build Huffman tree and assign codes to patterns
-        use std::cmp::Ordering;
-        use std::collections::BinaryHeap;
 
         // Patterns are already sorted before being passed to this builder
         // Don't sort again - it would break the order!
@@ -873,37 +1160,16 @@ impl PatternHuffBuilder {
         if self.patterns.is_empty() {
             return;
         }
-
-        // Wrapper for heap ordering
-        struct HuffNode {
-            node: Box<PatternHuff>,
-        }
-
-        impl Ord for HuffNode {
-            fn cmp(&self, other: &Self) -> Ordering {
-                // Reverse order for min-heap
-                other
-                    .node
-                    .uses
-                    .cmp(&self.node.uses)
-                    .then_with(|| other.node.tie_breaker.cmp(&self.node.tie_breaker))
-            }
-        }
-
-        impl PartialOrd for HuffNode {
-            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-                Some(self.cmp(other))
-            }
-        }
-
-        impl Eq for HuffNode {}
-        impl PartialEq for HuffNode {
-            fn eq(&self, other: &Self) -> bool {
-                self.node.uses == other.node.uses && self.node.tie_breaker == other.node.tie_breaker
-            }
+        // A lone pattern takes no bits, and is left with its sequential
+        // code otherwise
+        if let [pattern] = self.patterns.as_mut_slice() {
+            pattern.code = 0;
+            pattern.code_bits = 0;
+            pattern.depth = 0;
+            return;
         }
 
-        let mut heap: BinaryHeap<HuffNode> = BinaryHeap::new();
+        let mut heap = PatternHeap::new();
         let mut i = 0;
         let mut tie_breaker = 0u64;
 
@@ -921,13 +1187,13 @@ impl PatternHuffBuilder {
             // Take first child (0 bit)
             if !heap.is_empty()
                 && (i >= self.patterns.len()
-                    || heap.peek().unwrap().node.uses < self.patterns[i].uses)
+                    || heap.peek().unwrap().inner.uses < self.patterns[i].uses)
             {
                 // Take from heap
                 let mut node = heap.pop().unwrap();
-                node.node.add_zero(&mut self.patterns);
-                h.uses += node.node.uses;
-                h.h0 = Some(node.node);
+                node.inner.add_zero(&mut self.patterns);
+                h.uses += node.inner.uses;
+                h.h0 = Some(node.inner);
             } else {
                 // Take from list - store the INDEX
                 self.patterns[i].code = 0;
@@ -940,13 +1206,13 @@ impl PatternHuffBuilder {
             // Take second child (1 bit)
             if !heap.is_empty()
                 && (i >= self.patterns.len()
-                    || heap.peek().unwrap().node.uses < self.patterns[i].uses)
+                    || heap.peek().unwrap().inner.uses < self.patterns[i].uses)
             {
                 // Take from heap
                 let mut node = heap.pop().unwrap();
-                node.node.add_one(&mut self.patterns);
-                h.uses += node.node.uses;
-                h.h1 = Some(node.node);
+                node.inner.add_one(&mut self.patterns);
+                h.uses += node.inner.uses;
+                h.h1 = Some(node.inner);
             } else {
                 // Take from list - store the INDEX
                 self.patterns[i].code = 1;
@@ -957,12 +1223,12 @@ impl PatternHuffBuilder {
             }
 
             tie_breaker += 1;
-            heap.push(HuffNode { node: Box::new(h) });
+            heap.push(PatternHuffWrapper { inner: Box::new(h) });
         }
 
         // Set depths from root
         if let Some(mut root) = heap.pop() {
-            root.node.set_depth(0, &mut self.patterns);
+            root.inner.set_depth(0, &mut self.patterns);
             // Patterns are now updated directly, no need to extract
         }
     }
@@ -982,8 +1248,6 @@ impl PositionHuffBuilder {
     pub fn build_huffman_codes(&mut self) {
         // Go: parallel_compress.go:554-625
         // Build Huffman tree and assign codes to positions
-        use std::cmp::Ordering;
-        use std::collections::BinaryHeap;
 
         // Sort positions by uses (frequency) - least used first, then by reverse64(code) as tiebreaker
         // Initially, code contains the position value itself (pos)
@@ -995,45 +1259,23 @@ impl PositionHuffBuilder {
             }
         });
 
-        log::debug!("Position list order for tree building:");
+        word_trace!("Position list order for tree building:");
         for (i, p) in self.positions.iter().enumerate() {
-            log::debug!("  [{}] Position {} with {} uses", i, p.pos, p.uses);
+            word_trace!("  [{}] Position {} with {} uses", i, p.pos, p.uses);
         }
 
         if self.positions.is_empty() {
             return;
         }
-
-        // Wrapper for heap ordering
-        struct HuffNode {
-            node: Box<PositionHuff>,
-        }
-
-        impl Ord for HuffNode {
-            fn cmp(&self, other: &Self) -> Ordering {
-                // Reverse order for min-heap
-                other
-                    .node
-                    .uses
-                    .cmp(&self.node.uses)
-                    .then_with(|| other.node.tie_breaker.cmp(&self.node.tie_breaker))
-            }
-        }
-
-        impl PartialOrd for HuffNode {
-            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-                Some(self.cmp(other))
-            }
-        }
-
-        impl Eq for HuffNode {}
-        impl PartialEq for HuffNode {
-            fn eq(&self, other: &Self) -> bool {
-                self.node.uses == other.node.uses && self.node.tie_breaker == other.node.tie_breaker
-            }
+        // Likewise a lone position, left with the position as its code
+        if let [position] = self.positions.as_mut_slice() {
+            position.code = 0;
+            position.code_bits = 0;
+            position.depth = 0;
+            return;
         }
 
-        let mut heap: BinaryHeap<HuffNode> = BinaryHeap::new();
+        let mut heap = PositionHeap::new();
         let mut i = 0;
         let mut tie_breaker = 0u64;
 
@@ -1051,13 +1293,13 @@ impl PositionHuffBuilder {
             // Take first child (h0/p0) - exactly like Go lines 585-596
             if !heap.is_empty()
                 && (i >= self.positions.len()
-                    || heap.peek().unwrap().node.uses < self.positions[i].uses)
+                    || heap.peek().unwrap().inner.uses < self.positions[i].uses)
             {
                 // Take h0 from heap
                 let mut node = heap.pop().unwrap();
-                node.node.add_zero(&mut self.positions); // AddZero AFTER pop like Go
-                h.uses += node.node.uses;
-                h.h0 = Some(node.node);
+                node.inner.add_zero(&mut self.positions); // AddZero AFTER pop like Go
+                h.uses += node.inner.uses;
+                h.h0 = Some(node.inner);
             } else {
                 // Take p0 from list - store the index
                 h.p0 = Some(i);
@@ -1070,13 +1312,13 @@ impl PositionHuffBuilder {
             // Take second child (h1/p1) - exactly like Go lines 598-609
             if !heap.is_empty()
                 && (i >= self.positions.len()
-                    || heap.peek().unwrap().node.uses < self.positions[i].uses)
+                    || heap.peek().unwrap().inner.uses < self.positions[i].uses)
             {
                 // Take h1 from heap
                 let mut node = heap.pop().unwrap();
-                node.node.add_one(&mut self.positions); // AddOne AFTER pop like Go
-                h.uses += node.node.uses;
-                h.h1 = Some(node.node);
+                node.inner.add_one(&mut self.positions); // AddOne AFTER pop like Go
+                h.uses += node.inner.uses;
+                h.h1 = Some(node.inner);
             } else {
                 // Take p1 from list - store the index
                 h.p1 = Some(i);
@@ -1087,145 +1329,38 @@ impl PositionHuffBuilder {
             }
 
             tie_breaker += 1;
-            heap.push(HuffNode { node: Box::new(h) });
+            heap.push(PositionHuffWrapper { inner: Box::new(h) });
         }
 
         // Set depths from root
         if let Some(mut root) = heap.pop() {
-            root.node.set_depth(0, &mut self.positions);
-        }
-    }
-}
-
-// Helper to reverse bits (matches Go's bits.Reverse64)
-fn reverse_bits_64(code: u64) -> u64 {
-    // Go's bits.Reverse64 reverses all 64 bits
-    let mut result = 0u64;
-    let mut val = code;
-    for _ in 0..64 {
-        result = (result << 1) | (val & 1);
-        val >>= 1;
-    }
-    result
-}
-
-// Helper to encode varint (matches Go's binary.PutUvarint)
-fn encode_varint(buf: &mut [u8], mut x: u64) -> usize {
-    let mut i = 0;
-    while x >= 0x80 {
-        buf[i] = (x as u8) | 0x80;
-        x >>= 7;
-        i += 1;
-    }
-    buf[i] = x as u8;
-    i + 1
-}
-
-// Helper to decode varint
-fn decode_varint(data: &[u8]) -> Result<(u64, usize), CompressionError> {
-    let mut value = 0u64;
-    let mut shift = 0;
-
-    for (i, &byte) in data.iter().enumerate() {
-        if i == 10 {
-            return Err(CompressionError::Other("Varint too long".to_string()));
-        }
-
-        value |= ((byte & 0x7F) as u64) << shift;
-
-        if byte & 0x80 == 0 {
-            return Ok((value, i + 1));
-        }
-
-        shift += 7;
-    }
-
-    Err(CompressionError::Other(
-        "Unexpected end of varint".to_string(),
-    ))
-}
-
-// BitWriter for Huffman encoding
-// From Go: compress.go:636
-struct BitWriter<W: std::io::Write> {
-    w: W,
-    output_bits: usize,
-    output_byte: u8,
-}
-
-impl<W: std::io::Write> BitWriter<W> {
-    fn new(w: W) -> Self {
-        BitWriter {
-            w,
-            output_bits: 0,
-            output_byte: 0,
-        }
-    }
-
-    fn encode(&mut self, mut code: u64, mut code_bits: usize) -> std::io::Result<()> {
-        while code_bits > 0 {
-            let bits_used = if self.output_bits + code_bits > 8 {
-                8 - self.output_bits
-            } else {
-                code_bits
-            };
-
-            let mask = (1u64 << bits_used) - 1;
-            self.output_byte |= ((code & mask) << self.output_bits) as u8;
-            code >>= bits_used;
-            code_bits -= bits_used;
-            self.output_bits += bits_used;
-
-            if self.output_bits == 8 {
-                self.w.write_all(&[self.output_byte])?;
-                self.output_bits = 0;
-                self.output_byte = 0;
-            }
+            root.inner.set_depth(0, &mut self.positions);
         }
-        Ok(())
-    }
-
-    fn flush(&mut self) -> std::io::Result<()> {
-        if self.output_bits > 0 {
-            self.w.write_all(&[self.output_byte])?;
-            self.output_bits = 0;
-            self.output_byte = 0;
-        }
-        self.w.flush()
-    }
-
-    // Helper to write raw bytes (flushes bits first)
-    fn write_bytes(&mut self, data: &[u8]) -> std::io::Result<()> {
-        self.flush()?;
-        self.w.write_all(data)
-    }
-
-    // Get the inner writer (consumes self)
-    fn into_inner(mut self) -> std::io::Result<W> {
-        self.flush()?;
-        Ok(self.w)
     }
 }
 
 // Write the final compressed file with Huffman tables
-fn write_compressed_file(
-    cf: &mut std::fs::File,
-    intermediate_path: &str,
+pub(crate) fn write_compressed_file(
+    cf: &mut impl std::io::Write,
+    intermediate_path: &Path,
     code2pattern: &[Pattern], // Original order for sequential code lookup
     pattern_list: &[Pattern], // Sorted order for dictionary
     positions: &[Position],
     word_count: u64,
     empty_words_count: u64,
-) -> std::result::Result<(), CompressionError> {
+) -> std::result::Result<(u64, u64), CompressionError> {
     use std::collections::HashMap;
-    use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+    use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 
     let mut w = BufWriter::new(cf);
     let mut intermediate = std::fs::File::open(intermediate_path)?;
 
     // Write header (Go: parallel_compress.go:535-543)
-    w.write_all(&word_count.to_be_bytes())?; // Words count
-    w.write_all(&empty_words_count.to_be_bytes())?; // Empty words count
+    let header = SegmentHeader {
+        word_count,
+        empty_word_count: empty_words_count,
+    };
+    w.write_all(&header.to_bytes())?;
 
     // Write pattern dictionary
     let mut pattern_dict_data = Vec::new();
@@ -1234,12 +1369,12 @@ fn write_compressed_file(
     // pattern_list is already sorted for dictionary writing
     let sorted_patterns_refs: Vec<_> = pattern_list.iter().collect();
 
-    log::debug!(
+    tracing::debug!(
         "Writing {} patterns to dictionary (sorted order):",
         sorted_patterns_refs.len()
     );
     for (i, pattern) in sorted_patterns_refs.iter().enumerate() {
-        log::debug!(
+        word_trace!(
             "  Dict[{}]: '{}' (depth {}, code {}, seq_code {})",
             i,
             String::from_utf8_lossy(&pattern.word),
@@ -1261,9 +1396,9 @@ fn write_compressed_file(
     let mut pos_dict_data = Vec::new();
 
     // Use positions in the order they were passed (already sorted by main function)
-    log::debug!("Position dictionary write order:");
+    word_trace!("Position dictionary write order:");
     for (i, position) in positions.iter().enumerate() {
-        log::debug!(
+        word_trace!(
             "  [{}] Position {} (depth={}, code={}, bits={})",
             i,
             position.pos,
@@ -1288,7 +1423,7 @@ fn write_compressed_file(
     let mut pos2code: HashMap<u64, &Position> = HashMap::new();
     for position in positions {
         pos2code.insert(position.pos, position);
-        log::debug!(
+        word_trace!(
             "pos2code[{}] = Position(code={}, bits={})",
             position.pos,
             position.code,
@@ -1296,7 +1431,7 @@ fn write_compressed_file(
         );
     }
 
-    log::debug!(
+    tracing::debug!(
         "Position map has {} entries, contains 0: {}",
         pos2code.len(),
         pos2code.contains_key(&0)
@@ -1328,13 +1463,13 @@ fn write_compressed_file(
             break; // EOF
         }
 
-        log::debug!(
+        word_trace!(
             "Read {} bytes for word length: {:02x?}",
             bytes_read,
             &len_buf[..bytes_read]
         );
         let (word_len, _) = decode_varint(&len_buf[..bytes_read]).map_err(|e| {
-            log::error!(
+            tracing::error!(
                 "Failed to decode word length varint from bytes {:02x?}: {}",
                 &len_buf[..bytes_read],
                 e
@@ -1344,7 +1479,7 @@ fn write_compressed_file(
 
         // Encode word length+1 with position huffman code
         if words_written < 3 {
-            log::debug!(
+            word_trace!(
                 "Word {}: encoding position {} (word_len + 1 = {} + 1)",
                 words_written + 1,
                 word_len + 1,
@@ -1353,7 +1488,7 @@ fn write_compressed_file(
         }
         if let Some(pos_code) = pos2code.get(&(word_len + 1)) {
             if words_written < 3 {
-                log::debug!(
+                word_trace!(
                     "  Using huffman code: {:b} ({} bits)",
                     pos_code.code,
                     pos_code.code_bits
@@ -1370,7 +1505,7 @@ fn write_compressed_file(
             // Empty word
             bit_writer.flush()?;
             words_written += 1;
-            log::trace!("Wrote empty word, total: {}", words_written);
+            word_trace!("Wrote empty word, total: {}", words_written);
             continue; // Move to next word
         }
 
@@ -1390,22 +1525,21 @@ fn write_compressed_file(
         }
 
         let (pattern_count, _) = decode_varint(&pattern_count_buf[..bytes_read])?;
-        // Debug: peek at word content for first few words
-        if words_written < 3 {
-            let cur_pos = reader.stream_position()?;
-            let mut peek_data = vec![0u8; 20.min(word_len as usize)];
-            let bytes_to_read = peek_data.len();
-            reader.read_exact(&mut peek_data)?;
-            reader.seek(std::io::SeekFrom::Start(cur_pos))?;
-            log::debug!(
+        // Debug: peek at word content for first few words. The rest of a
+        // fully covered word's record may be shorter than the word, or
+        // missing at the end of the file
+        if cfg!(feature = "detailed-trace") && words_written < 3 {
+            let buffered = reader.fill_buf()?;
+            let peek_data = &buffered[..buffered.len().min(20).min(word_len as usize)];
+            word_trace!(
                 "Word {} intermediate data (first {} bytes): {:02x?}",
                 words_written + 1,
-                bytes_to_read,
-                &peek_data
+                peek_data.len(),
+                peek_data
             );
         }
 
-        log::debug!(
+        word_trace!(
             "Word {} (length {}): pattern_count = {} (bytes: {:02x?})",
             words_written + 1,
             word_len,
@@ -1424,14 +1558,14 @@ fn write_compressed_file(
             // Copy uncovered bytes (the entire word)
             let mut word_data = vec![0u8; word_len as usize];
             reader.read_exact(&mut word_data)?;
-            log::debug!(
+            word_trace!(
                 "Writing {} uncovered bytes for word with no patterns",
                 word_len
             );
             bit_writer.write_bytes(&word_data)?;
         } else {
             // Process patterns
-            log::debug!(
+            word_trace!(
                 "Processing {} patterns for word {} (length {})",
                 pattern_count,
                 words_written + 1,
@@ -1458,11 +1592,11 @@ fn write_compressed_file(
                 }
 
                 let (pos, _) = decode_varint(&pos_buf[..bytes_read])?;
-                log::debug!("  Pattern {}: absolute position = {}", i, pos);
+                word_trace!("  Pattern {}: absolute position = {}", i, pos);
 
                 // Calculate relative position for encoding (matching Go: pos - lastPos + 1)
                 let relative_pos = pos - last_pos + 1;
-                log::debug!(
+                word_trace!(
                     "  Pattern {}: relative position = {} (pos {} - last_pos {} + 1)",
                     i,
                     relative_pos,
@@ -1494,7 +1628,7 @@ fn write_compressed_file(
                 }
 
                 let (pattern_code, _) = decode_varint(&code_buf[..bytes_read])?;
-                log::debug!(
+                word_trace!(
                     "  Pattern {}: code = {} (bytes: {:02x?})",
                     i,
                     pattern_code,
@@ -1504,7 +1638,7 @@ fn write_compressed_file(
                 // Look up pattern by sequential code which IS the array index in code2pattern!
                 if pattern_code < code2pattern.len() as u64 {
                     let pattern = &code2pattern[pattern_code as usize];
-                    log::debug!(
+                    word_trace!(
                         "  Pattern {}: sequential_code={} maps to '{}' with Huffman code={}, bits={}",
                         i,
                         pattern_code,
@@ -1513,7 +1647,7 @@ fn write_compressed_file(
                         pattern.code_bits
                     );
                     bit_writer.encode(pattern.code, pattern.code_bits)?;
-                    log::debug!(
+                    word_trace!(
                         "  Pattern {}: encoded pattern '{}', length = {}",
                         i,
                         String::from_utf8_lossy(&pattern.word),
@@ -1521,7 +1655,7 @@ fn write_compressed_file(
                     );
 
                     // Track uncovered bytes (use pos not last_pos, as pos is current position)
-                    log::debug!(
+                    word_trace!(
                         "  Pattern {}: checking pos {} > last_uncovered {}",
                         i,
                         pos as usize,
@@ -1529,7 +1663,7 @@ fn write_compressed_file(
                     );
                     if pos as usize > last_uncovered {
                         uncovered_count += pos as usize - last_uncovered;
-                        log::debug!(
+                        word_trace!(
                             "  Pattern {}: added {} uncovered bytes (pos {} > last_uncovered {})",
                             i,
                             pos as usize - last_uncovered,
@@ -1537,7 +1671,7 @@ fn write_compressed_file(
                             last_uncovered
                         );
                     } else {
-                        log::debug!(
+                        word_trace!(
                             "  Pattern {}: no uncovered bytes added (pos {} <= last_uncovered {})",
                             i,
                             pos,
@@ -1545,9 +1679,9 @@ fn write_compressed_file(
                         );
                     }
                     last_uncovered = pos as usize + pattern.word.len();
-                    log::debug!("  Pattern {}: last_uncovered now = {}", i, last_uncovered);
+                    word_trace!("  Pattern {}: last_uncovered now = {}", i, last_uncovered);
                 } else {
-                    log::debug!(
+                    word_trace!(
                         "  Pattern {}: code {} not found in dictionary!",
                         i,
                         pattern_code
@@ -1560,14 +1694,14 @@ fn write_compressed_file(
             // Calculate total uncovered bytes
             if word_len as usize > last_uncovered {
                 uncovered_count += word_len as usize - last_uncovered;
-                log::debug!(
+                word_trace!(
                     "  Final: added {} uncovered bytes at end (word_len {} > last_uncovered {})",
                     word_len as usize - last_uncovered,
                     word_len,
                     last_uncovered
                 );
             }
-            log::debug!(
+            word_trace!(
                 "  Total uncovered_count: {} for word length {}",
                 uncovered_count,
                 word_len
@@ -1580,7 +1714,7 @@ fn write_compressed_file(
             bit_writer.flush()?;
 
             // Copy uncovered bytes
-            log::debug!(
+            word_trace!(
                 "Calculated uncovered_count: {} for word {} (length {})",
                 uncovered_count,
                 words_written + 1,
@@ -1589,33 +1723,33 @@ fn write_compressed_file(
             if uncovered_count > 0 {
                 let mut uncovered_data = vec![0u8; uncovered_count];
                 reader.read_exact(&mut uncovered_data)?;
-                log::debug!(
+                word_trace!(
                     "Read {} uncovered bytes: {:02x?}",
                     uncovered_data.len(),
                     &uncovered_data[..uncovered_data.len().min(10)]
                 );
                 bit_writer.write_bytes(&uncovered_data)?;
             } else {
-                log::debug!("No uncovered bytes to read for word {}", words_written + 1);
+                word_trace!("No uncovered bytes to read for word {}", words_written + 1);
             }
         }
 
         words_written += 1;
-        log::trace!("Wrote word {}, length: {}", words_written, word_len);
-        log::debug!(
+        word_trace!("Wrote word {}, length: {}", words_written, word_len);
+        word_trace!(
             "--- End of word {} processing, moving to next word ---",
             words_written
         );
     }
 
-    log::debug!("Total words written to compressed file: {}", words_written);
+    tracing::debug!("Total words written to compressed file: {}", words_written);
 
     // Finish with BitWriter and get back the underlying writer
     let w = bit_writer.into_inner()?;
     w.flush()?;
 
-    log::debug!("Compressed file written successfully");
-    Ok(())
+    tracing::debug!("Compressed file written successfully");
+    Ok((pattern_dict_data.len() as u64, pos_dict_data.len() as u64))
 }
 
 // From Go: extractPatternsInSuperstrings function
@@ -1649,7 +1783,7 @@ fn build_lcp_array(text: &[u8], sa: &[i32]) -> Vec<i32> {
                 j += 1;
             }
 
-            lcp[rank[i]] = h as i32;
+            lcp[rank[i]] = h;
 
             if h > 0 {
                 h -= 1;
@@ -1668,6 +1802,8 @@ pub struct CompressionWorker {
     input_size: usize,
     output_size: usize,
     pos_map: std::collections::HashMap<u64, u64>,
+    // Scratch buffers reused across words
+    buffers: CoverBuffers,
 }
 
 impl CompressionWorker {
@@ -1685,15 +1821,43 @@ impl CompressionWorker {
             input_size: 0,
             output_size: 0,
             pos_map: std::collections::HashMap::new(),
+            buffers: CoverBuffers::new(),
         }
     }
 
     pub fn process_word(&mut self, word: CompressionWord) -> CompressionWord {
         // Go: parallel_compress.go:187-203
         // Process a single word for compression
+        self.input_size += 1 + word.word.len();
+        cover_word_by_patterns(
+            false,
+            &word.word,
+            &self.trie,
+            &mut self.buffers,
+            &mut self.pos_map,
+        );
+        let output = self.buffers.output().to_vec();
+        self.output_size += output.len();
+        word_trace!(
+            "worker {}: word {} {} -> {} bytes",
+            self.id,
+            word.order,
+            word.word.len(),
+            output.len()
+        );
+        CompressionWord::new(output, word.order)
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.input_size
+    }
+
+    pub fn output_size(&self) -> usize {
+        self.output_size
+    }
 
-        // TODO: Implement actual compression logic
-        word
+    pub fn pos_map(&self) -> &std::collections::HashMap<u64, u64> {
+        &self.pos_map
     }
 }
 
@@ -1714,6 +1878,31 @@ mod tests {
         assert_eq!(matches[0].end, 5);
     }
 
+    #[test]
+    fn test_match_finder_longest_non_overlapping() {
+        let mut mf = MatchFinder::new();
+        mf.insert(Pattern::new(b"abc".to_vec(), 1));
+        mf.insert(Pattern::new(b"abcdef".to_vec(), 1));
+        mf.insert(Pattern::new(b"cde".to_vec(), 1));
+        mf.insert(Pattern::new(b"fgh".to_vec(), 1));
+
+        let spans = |mf: &MatchFinder, input: &[u8]| -> Vec<(usize, usize)> {
+            mf.find_longest_matches(input)
+                .iter()
+                .map(|m| (m.start, m.end))
+                .collect()
+        };
+
+        // Longest match at 0 swallows "cde"; "fgh" overlaps it at 5 and is dropped
+        assert_eq!(spans(&mf, b"abcdefgh"), vec![(0, 6)]);
+        assert_eq!(spans(&mf, b"xabcxcdefgh"), vec![(1, 4), (5, 8), (8, 11)]);
+        assert!(spans(&mf, b"xyz").is_empty());
+
+        // Inserting after a search rebuilds the automaton
+        mf.insert(Pattern::new(b"xyz".to_vec(), 1));
+        assert_eq!(spans(&mf, b"xyz"), vec![(0, 3)]);
+    }
+
     #[test]
     fn test_pattern_huff_builder() {
         let mut patterns = vec![
@@ -1730,6 +1919,111 @@ mod tests {
         // Verify codes were assigned
         assert!(builder.patterns[0].code_bits > 0);
         assert!(builder.patterns[1].code_bits > 0);
+
+        // A lone pattern takes no bits, whatever its sequential code
+        let mut pattern = Pattern::new(b"test3".to_vec(), 100);
+        pattern.uses = 5;
+        pattern.code = 2;
+        let mut builder = PatternHuffBuilder::new(vec![pattern]);
+        builder.build_huffman_codes();
+        assert_eq!(builder.patterns[0].code, 0);
+        assert_eq!(builder.patterns[0].code_bits, 0);
+    }
+
+    #[test]
+    fn test_verify_codes() {
+        use crate::compress::{pattern_list_cmp, position_list_cmp};
+        use crate::decompress::verify_codes;
+        use crate::error::CompressError;
+
+        // Skewed uses give codes of several depths, some deeper than 9 bits
+        let mut patterns: Vec<Pattern> = (0..40u64)
+            .map(|i| {
+                let mut p = Pattern::new(format!("pattern{}", i).into_bytes(), 0);
+                p.uses = 1 << (i / 2);
+                p.sequential_code = i;
+                p
+            })
+            .collect();
+        patterns.sort_by_key(|p| p.uses);
+        let mut builder = PatternHuffBuilder::new(patterns);
+        builder.build_huffman_codes();
+        let mut patterns = builder.patterns;
+        patterns.sort_by(pattern_list_cmp);
+        assert!(patterns.iter().any(|p| p.depth > 9));
+
+        let positions: Vec<Position> = (0..20u64)
+            .map(|pos| Position {
+                uses: 1000 / (pos + 1),
+                pos,
+                code: pos,
+                code_bits: 0,
+                depth: 0,
+            })
+            .collect();
+        let mut position_huff = PositionHuffBuilder::new(positions);
+        position_huff.build_huffman_codes();
+        let mut positions = position_huff.positions;
+        positions.sort_by(position_list_cmp);
+
+        verify_codes(&patterns, &positions).unwrap();
+
+        let bad_code = |patterns: &[Pattern], positions: &[Position]| match verify_codes(
+            patterns, positions,
+        ) {
+            Err(CompressionError::Compress(CompressError::InvalidHuffmanCode {
+                dict,
+                index,
+                ..
+            })) => (dict, index),
+            other => panic!("expected an invalid code, got {:?}", other.err()),
+        };
+        // Out of dictionary order the decompressor's tables disagree
+        let mut swapped = patterns.clone();
+        let last = swapped.len() - 1;
+        swapped.swap(0, last);
+        assert_eq!(bad_code(&swapped, &positions).0, "pattern");
+        // One code a prefix of another
+        let mut prefixed = positions.clone();
+        prefixed[1].code = prefixed[0].code;
+        prefixed[1].code_bits = prefixed[0].code_bits;
+        prefixed[1].depth = prefixed[0].depth;
+        assert_eq!(bad_code(&patterns, &prefixed), ("position", 1));
+        let mut deep = patterns.clone();
+        deep[0].code_bits = 51;
+        deep[0].depth = 51;
+        assert_eq!(bad_code(&deep, &positions), ("pattern", 0));
+    }
+
+    #[test]
+    fn test_cover_buffers_reused() {
+        let mut mf = MatchFinder::new();
+        let mut pattern = Pattern::new(b"abcdef".to_vec(), 10);
+        pattern.sequential_code = 7;
+        mf.insert(pattern);
+
+        let cover = |buffers: &mut CoverBuffers, input: &[u8]| {
+            let mut pos_map = std::collections::HashMap::new();
+            cover_word_by_patterns(false, input, &mf, buffers, &mut pos_map);
+            (
+                buffers.output().to_vec(),
+                buffers.uncovered().to_vec(),
+                buffers.used_patterns().to_vec(),
+            )
+        };
+
+        let mut buffers = CoverBuffers::new();
+        let long = cover(&mut buffers, b"xxabcdefyyabcdefzz");
+        assert_eq!(long.1, vec![0, 2, 8, 10, 16, 18]);
+        assert_eq!(long.2, vec![7, 7]);
+        assert_eq!(buffers.uncovered_len(), 6);
+
+        // Nothing of the longer word is left over in the reused buffers
+        let short = cover(&mut buffers, b"abcdefq");
+        assert_eq!(short, cover(&mut CoverBuffers::new(), b"abcdefq"));
+        assert_eq!(short.1, vec![6, 7]);
+        let none = cover(&mut buffers, b"xyz");
+        assert_eq!(none, (b"\0xyz".to_vec(), vec![0, 3], vec![]));
     }
 
     #[test]
@@ -1743,6 +2037,10 @@ mod tests {
         let word = CompressionWord::new(b"test".to_vec(), 1);
         let processed = worker.process_word(word);
 
-        assert_eq!(processed.word, b"test");
+        // No pattern matches: encoded as a zero pattern count followed by the raw word
+        assert_eq!(processed.word, b"\0test");
+        assert_eq!(processed.order, 1);
+        assert_eq!(worker.input_size(), 5);
+        assert_eq!(worker.output_size(), 5);
     }
 }
@@ -0,0 +1,215 @@
+//! Readahead for sequential scans of a segment
+//!
+//! A [`Getter`](crate::Getter) scanning a segment that is not in the page
+//! cache waits on the disk for each page it touches. [`ReadAhead`] tells the
+//! kernel about the scan with `madvise`, or reads the file ahead of the
+//! getter on the `blocking` thread pool so that its pages are cached by the
+//! time it gets there. Reads are issued as tasks when the getter nears the
+//! end of what was read, so the prefetcher works under any async runtime or
+//! none.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Largest read a prefetch task issues at once
+const PREFETCH_CHUNK: usize = 1 << 20;
+
+/// How a getter reads ahead of its position, see
+/// [`Getter::read_ahead`](crate::Getter::read_ahead)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadAhead {
+    /// Leave it to the kernel's own readahead
+    #[default]
+    Default,
+    /// `madvise(MADV_SEQUENTIAL)`: the kernel reads further ahead and frees
+    /// pages soon after they were read
+    Sequential,
+    /// `madvise(MADV_WILLNEED)`: the kernel starts reading the whole range
+    /// in at once
+    WillNeed,
+    /// `MADV_SEQUENTIAL` plus background reads `window` bytes ahead of the
+    /// getter, for spinning disks and network filesystems where the
+    /// kernel's readahead falls short
+    Prefetch { window: u64 },
+}
+
+impl ReadAhead {
+    /// [`ReadAhead::Prefetch`] with a 64 MiB window
+    pub const PREFETCH: ReadAhead = ReadAhead::Prefetch { window: 64 << 20 };
+}
+
+// Where the getter is and how far the reads got, as file offsets, and
+// whether a read task is under way
+struct Progress {
+    consumed: AtomicU64,
+    fetched: AtomicU64,
+    end: AtomicU64,
+    window: u64,
+    running: AtomicBool,
+    stop: AtomicBool,
+}
+
+impl Progress {
+    // Whether the getter is less than half a window from the end of what was
+    // read, or went back
+    fn wants_fetch(&self) -> bool {
+        let consumed = self.consumed.load(Ordering::SeqCst);
+        let fetched = self.fetched.load(Ordering::SeqCst);
+        (fetched < consumed.saturating_add(self.window / 2)
+            && fetched < self.end.load(Ordering::SeqCst))
+            || fetched > consumed.saturating_add(self.window)
+    }
+}
+
+/// Reads of a file ahead of a getter, until dropped
+///
+/// The getter reports its position through [`Prefetcher::advance`], which
+/// takes `&self`: the position is shared with the read tasks and updated
+/// internally. At most one read task runs at a time; once dropped, a
+/// running task stops after its current chunk.
+pub(crate) struct Prefetcher {
+    progress: Arc<Progress>,
+    file: Arc<File>,
+    // File offset of getter offset 0
+    base: u64,
+}
+
+impl Prefetcher {
+    /// Start reading `path` from file offset `base + from` up to
+    /// `base + end`, `window` bytes ahead of the getter at a time
+    pub(crate) fn spawn(
+        path: &Path,
+        base: u64,
+        from: u64,
+        end: u64,
+        window: u64,
+    ) -> io::Result<Self> {
+        let file = Arc::new(File::open(path)?);
+        let progress = Arc::new(Progress {
+            consumed: AtomicU64::new(base + from),
+            fetched: AtomicU64::new(base + from),
+            end: AtomicU64::new(base + end),
+            window: window.max(1),
+            running: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+        });
+        let prefetcher = Self {
+            progress,
+            file,
+            base,
+        };
+        prefetcher.fetch();
+        Ok(prefetcher)
+    }
+
+    /// The getter moved to `offset`
+    pub(crate) fn advance(&self, offset: u64) {
+        self.progress
+            .consumed
+            .store(self.base + offset, Ordering::SeqCst);
+        if self.progress.wants_fetch() {
+            self.fetch();
+        }
+    }
+
+    // Start a read task unless one is under way
+    fn fetch(&self) {
+        if self.progress.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let progress = Arc::clone(&self.progress);
+        let file = Arc::clone(&self.file);
+        blocking::unblock(move || loop {
+            prefetch(&file, &progress);
+            progress.running.store(false, Ordering::SeqCst);
+            // The getter may have moved on after the last check of the
+            // reads, and left the next one to this task
+            if progress.stop.load(Ordering::Acquire)
+                || !progress.wants_fetch()
+                || progress.running.swap(true, Ordering::SeqCst)
+            {
+                break;
+            }
+        })
+        .detach();
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        self.progress.stop.store(true, Ordering::Release);
+    }
+}
+
+// Read until `window` bytes past the getter or the end of the range
+fn prefetch(mut file: &File, progress: &Progress) {
+    let mut buf = vec![0u8; PREFETCH_CHUNK];
+    let mut pos = progress.fetched.load(Ordering::SeqCst);
+    while !progress.stop.load(Ordering::Acquire) {
+        let consumed = progress.consumed.load(Ordering::SeqCst);
+        if pos < consumed || pos > consumed.saturating_add(progress.window) {
+            pos = consumed;
+        }
+        let end = progress.end.load(Ordering::SeqCst);
+        let target = consumed.saturating_add(progress.window).min(end);
+        if pos >= target {
+            progress.fetched.store(pos, Ordering::SeqCst);
+            return;
+        }
+        let len = (target - pos).min(PREFETCH_CHUNK as u64) as usize;
+        let read = file
+            .seek(SeekFrom::Start(pos))
+            .and_then(|_| file.read(&mut buf[..len]));
+        match read {
+            Ok(0) => progress.end.store(pos, Ordering::SeqCst),
+            Ok(n) => pos += n as u64,
+            Err(e) => {
+                tracing::debug!("Prefetch stopped at offset {}: {}", pos, e);
+                progress.end.store(pos, Ordering::SeqCst);
+            }
+        }
+        progress.fetched.store(pos, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait_for(progress: &Progress, fetched: u64) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while progress.fetched.load(Ordering::Relaxed) != fetched {
+            assert!(
+                Instant::now() < deadline,
+                "prefetch did not reach {}",
+                fetched
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_prefetcher_follows_getter() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("data");
+        std::fs::write(&path, vec![1u8; 100_000]).unwrap();
+
+        // Offsets are relative to byte 100, as getter offsets are relative
+        // to the start of the words
+        let prefetcher = Prefetcher::spawn(&path, 100, 0, 90_000, 10_000).unwrap();
+        wait_for(&prefetcher.progress, 10_100);
+        prefetcher.advance(20_000);
+        wait_for(&prefetcher.progress, 30_100);
+        // Never past the end of the range
+        prefetcher.advance(85_000);
+        wait_for(&prefetcher.progress, 90_100);
+        // Back to the start, as after a reset
+        prefetcher.advance(0);
+        wait_for(&prefetcher.progress, 10_100);
+        drop(prefetcher);
+    }
+}
@@ -7,6 +7,7 @@ pub enum FileCompression {
     None = 0b00,
     Keys = 0b01,
     Vals = 0b10,
+    KeysAndVals = 0b11,
 }
 
 impl FileCompression {
@@ -29,20 +30,18 @@ impl std::ops::BitOr for FileCompression {
     type Output = Self;
 
     fn bitor(self, rhs: Self) -> Self::Output {
+        // Every two-bit combination is a variant
         unsafe { std::mem::transmute((self as u8) | (rhs as u8)) }
     }
 }
 
 impl std::fmt::Display for FileCompression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = if *self == (FileCompression::Keys | FileCompression::Vals) {
-            "kv"
-        } else {
-            match *self {
-                FileCompression::None => "none",
-                FileCompression::Keys => "k",
-                FileCompression::Vals => "v",
-            }
+        let s = match *self {
+            FileCompression::None => "none",
+            FileCompression::Keys => "k",
+            FileCompression::Vals => "v",
+            FileCompression::KeysAndVals => "kv",
         };
         write!(f, "{}", s)
     }
@@ -114,12 +113,25 @@ impl<'a> Reader<'a> {
         }
     }
 
-    pub fn match_cmp(&self, prefix: &[u8]) -> std::cmp::Ordering {
+    pub fn match_prefix_cmp(&self, prefix: &[u8]) -> std::cmp::Ordering {
         if self.compression.contains(FileCompression::Keys) {
-            self.getter.match_cmp(prefix)
+            self.getter.match_prefix_cmp(prefix)
+        } else {
+            self.getter.match_prefix_cmp_uncompressed(prefix)
+        }
+    }
+
+    pub fn match_cmp(&mut self, buf: &[u8]) -> std::cmp::Ordering {
+        let cmp = if self.compression.contains(FileCompression::Keys) {
+            self.getter.match_cmp(buf)
         } else {
-            self.getter.match_cmp_uncompressed(prefix)
+            self.getter.match_cmp_uncompressed(buf)
+        };
+        // Moved past the key on a match, the value is next
+        if cmp.is_eq() {
+            self.next_value = true;
         }
+        cmp
     }
 }
 
@@ -147,7 +159,7 @@ impl SegmentReader {
         })
     }
 
-    pub fn make_reader(&self) -> Reader {
+    pub fn make_reader(&self) -> Reader<'_> {
         Reader::new(self.decompressor.make_getter(), self.compression)
     }
 
@@ -172,11 +184,9 @@ pub fn detect_compress_type(decompressor: &Decompressor) -> FileCompression {
         getter.reset(0);
         let mut compressed = false;
         for _ in 0..100 {
-            if getter.has_next() {
-                if getter.skip_uncompressed().is_err() {
-                    compressed = true;
-                    break;
-                }
+            if getter.has_next() && getter.skip_uncompressed().is_err() {
+                compressed = true;
+                break;
             }
             if getter.has_next() {
                 getter.skip();
@@ -192,11 +202,9 @@ pub fn detect_compress_type(decompressor: &Decompressor) -> FileCompression {
             if getter.has_next() {
                 getter.skip();
             }
-            if getter.has_next() {
-                if getter.skip_uncompressed().is_err() {
-                    compressed = true;
-                    break;
-                }
+            if getter.has_next() && getter.skip_uncompressed().is_err() {
+                compressed = true;
+                break;
             }
         }
         compressed
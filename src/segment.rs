@@ -0,0 +1,756 @@
+//! Whole-segment tooling: printing, searching, comparing, merging and
+//! splitting the words of a `.seg`
+//!
+//! Words are streamed through a [`Getter`](crate::Getter) one at a time, so
+//! these work on segments of any size.
+
+use crate::compress::{Cfg, Compressor};
+use crate::decompress::Decompressor;
+use crate::error::CompressionError;
+use crate::snapshots::bor::{span_id_at, BorEvent};
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::snapshots::repo::BLOCKS_PER_FILE_UNIT;
+use crate::snapshots::{
+    self, build_block_index, BlockNumber, BodiesReader, SnapshotError, SnapshotFile, SnapshotType,
+};
+use crate::word_source::SegmentSource;
+use aho_corasick::AhoCorasick;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::Write;
+use std::ops::{Bound, Range, RangeBounds};
+use std::path::{Path, PathBuf};
+
+/// How [`cat`] prints each word
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WordFormat {
+    /// The word's bytes as they are, followed by a newline
+    #[default]
+    Raw,
+    /// Lowercase hex, one word per line
+    Hex,
+    /// The word decoded as RLP, lists in brackets and strings in hex; words
+    /// that are not a single RLP item are printed in hex
+    Rlp,
+}
+
+/// A word of a segment that contains the searched bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    /// Position of the word in the segment
+    pub ordinal: u64,
+    /// Offset of the word, to pass to [`Getter::reset`](crate::Getter::reset)
+    pub offset: u64,
+    /// Byte position of the first occurrence within the word
+    pub position: usize,
+}
+
+/// How two segments differ, from [`diff`]; pairs are `(a, b)`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SegDiff {
+    pub words: (u64, u64),
+    pub empty_words: (u64, u64),
+    /// Ordinal of the first word that differs, or that only one segment has
+    pub first_divergent_word: Option<u64>,
+    /// Words that differ among the ordinals both segments have
+    pub differing_words: u64,
+    pub patterns: DictDiff,
+    pub positions: DictDiff,
+}
+
+impl SegDiff {
+    /// Whether both segments hold the same words, however they are encoded
+    pub fn same_words(&self) -> bool {
+        self.first_divergent_word.is_none()
+    }
+
+    /// Whether both segments have the same dictionaries, up to order
+    pub fn same_dictionaries(&self) -> bool {
+        self.patterns.is_same() && self.positions.is_same()
+    }
+}
+
+/// How a dictionary of two segments differs; entries are compared by value,
+/// patterns by their bytes and positions by the position
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DictDiff {
+    pub entries: (usize, usize),
+    pub only_in_a: usize,
+    pub only_in_b: usize,
+    /// Entries of both whose Huffman code has a different depth
+    pub depth_changes: usize,
+}
+
+impl DictDiff {
+    fn new<K: Eq + Hash>(
+        a: impl IntoIterator<Item = (u64, K)>,
+        b: impl IntoIterator<Item = (u64, K)>,
+    ) -> Self {
+        let a: HashMap<K, u64> = a.into_iter().map(|(depth, k)| (k, depth)).collect();
+        let mut diff = DictDiff {
+            entries: (a.len(), 0),
+            ..Default::default()
+        };
+        let mut common = 0;
+        for (depth, k) in b {
+            diff.entries.1 += 1;
+            match a.get(&k) {
+                Some(&a_depth) => {
+                    common += 1;
+                    if a_depth != depth {
+                        diff.depth_changes += 1;
+                    }
+                }
+                None => diff.only_in_b += 1,
+            }
+        }
+        diff.only_in_a = diff.entries.0 - common;
+        diff
+    }
+
+    pub fn is_same(&self) -> bool {
+        self.only_in_a == 0 && self.only_in_b == 0 && self.depth_changes == 0
+    }
+}
+
+/// Compare the segments `a` and `b` word by word and their dictionaries,
+/// e.g. a segment written by this crate against one written by Erigon
+pub fn diff(a: impl AsRef<Path>, b: impl AsRef<Path>) -> Result<SegDiff, CompressionError> {
+    let a = Decompressor::new(a)?;
+    let b = Decompressor::new(b)?;
+    let mut diff = SegDiff {
+        words: (a.count() as u64, b.count() as u64),
+        empty_words: (a.empty_words_count() as u64, b.empty_words_count() as u64),
+        patterns: DictDiff::new(a.patterns(), b.patterns()),
+        positions: DictDiff::new(a.positions(), b.positions()),
+        ..Default::default()
+    };
+
+    let mut getter_a = a.make_getter();
+    let mut getter_b = b.make_getter();
+    let (mut word_a, mut word_b) = (Vec::new(), Vec::new());
+    let mut ordinal = 0;
+    while getter_a.has_next() && getter_b.has_next() {
+        word_a.clear();
+        word_a = getter_a.try_next(word_a)?.0;
+        word_b.clear();
+        word_b = getter_b.try_next(word_b)?.0;
+        if word_a != word_b {
+            diff.differing_words += 1;
+            diff.first_divergent_word.get_or_insert(ordinal);
+        }
+        ordinal += 1;
+    }
+    if getter_a.has_next() || getter_b.has_next() {
+        diff.first_divergent_word.get_or_insert(ordinal);
+    }
+    Ok(diff)
+}
+
+/// Write the words of `path` whose ordinals fall in `range` to `writer`,
+/// returning the number of words written
+pub fn cat(
+    path: impl AsRef<Path>,
+    range: impl RangeBounds<u64>,
+    format: WordFormat,
+    writer: &mut impl Write,
+) -> Result<u64, CompressionError> {
+    let decompressor = Decompressor::new(path)?;
+    let count = decompressor.count() as u64;
+    let from = match range.start_bound() {
+        Bound::Included(&from) => from,
+        Bound::Excluded(&from) => from.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let to = match range.end_bound() {
+        Bound::Included(&to) => to.saturating_add(1),
+        Bound::Excluded(&to) => to,
+        Bound::Unbounded => count,
+    }
+    .min(count);
+    if from >= to {
+        return Ok(0);
+    }
+
+    let mut getter = decompressor.make_getter();
+    // word_offset is only None past the end, ruled out above
+    getter.reset(decompressor.word_offset(from).unwrap_or_default());
+    let mut word = Vec::new();
+    let mut line = String::new();
+    for _ in from..to {
+        word.clear();
+        word = getter.try_next(word)?.0;
+        match format {
+            WordFormat::Raw => writer.write_all(&word)?,
+            WordFormat::Hex => writer.write_all(hex::encode(&word).as_bytes())?,
+            WordFormat::Rlp => {
+                line.clear();
+                if !write_rlp(&mut line, &word) {
+                    line.clear();
+                    line.push_str(&hex::encode(&word));
+                }
+                writer.write_all(line.as_bytes())?;
+            }
+        }
+        writer.write_all(b"\n")?;
+    }
+    Ok(to - from)
+}
+
+/// Find the words of `path` that contain `pattern`
+///
+/// An empty pattern matches every word.
+pub fn grep(path: impl AsRef<Path>, pattern: &[u8]) -> Result<Vec<GrepMatch>, CompressionError> {
+    let decompressor = Decompressor::new(path)?;
+    let searcher = AhoCorasick::new([pattern])
+        .map_err(|e| CompressionError::InvalidConfig(format!("grep pattern: {}", e)))?;
+
+    let mut matches = Vec::new();
+    let mut getter = decompressor.make_getter();
+    let mut word = Vec::new();
+    let mut ordinal = 0;
+    while getter.has_next() {
+        let offset = getter.offset();
+        word.clear();
+        word = getter.try_next(word)?.0;
+        if let Some(found) = searcher.find(&word) {
+            matches.push(GrepMatch {
+                ordinal,
+                offset,
+                position: found.start(),
+            });
+        }
+        ordinal += 1;
+    }
+    Ok(matches)
+}
+
+/// Merge the segments `inputs`, in order, into one at `output`, returning
+/// the number of words
+///
+/// Words are streamed from each input and compressed again with a
+/// dictionary trained on all of them, as Erigon's merge stage does. If
+/// `output` is named like an Erigon block segment, the inputs must be
+/// segments of its type covering its range back to back, and its `.idx` is
+/// rebuilt (see [`build_block_index`]); the txNums of a transactions segment
+/// are counted from the `base_data_id` of the first input's `.idx`.
+pub fn merge(
+    inputs: &[impl AsRef<Path>],
+    output: impl AsRef<Path>,
+    cfg: Cfg,
+) -> snapshots::Result<u64> {
+    let output = output.as_ref();
+    let target = SnapshotFile::parse(output);
+    if let Some(target) = &target {
+        check_merge_inputs(inputs, target)?;
+    }
+
+    let mut compressor = Compressor::builder(output)
+        .cfg(cfg)
+        .log_prefix("merge")
+        .build()?;
+    for input in inputs {
+        let decompressor = Decompressor::new(input)?;
+        compressor.add_from(SegmentSource::new(&decompressor))?;
+    }
+    let words = compressor.count();
+    compressor.compress()?;
+
+    if let Some(target) = target {
+        let base_data_id = match target.kind {
+            SnapshotType::Transactions | SnapshotType::Senders => {
+                let idx_path = inputs[0].as_ref().with_extension("idx");
+                if !idx_path.exists() {
+                    return Err(SnapshotError::IndexNotAvailable);
+                }
+                RecSplitIndex::open(&idx_path)?.base_data_id()
+            }
+            _ => target.from_block,
+        };
+        build_block_index(output, target.kind, base_data_id, true)?;
+    }
+    tracing::info!(
+        "Merged {} segments into {} ({} words)",
+        inputs.len(),
+        output.display(),
+        words
+    );
+    Ok(words)
+}
+
+/// Extract the words of the blocks in each of `ranges` from the block
+/// segment `input` into a smaller segment next to it, returning their paths
+///
+/// This is the inverse of [`merge`], e.g. to cut test fixtures out of
+/// mainnet segments. Range bounds must be multiples of 1000 blocks (slots,
+/// for caplin types) within the input's range, so the outputs get Erigon
+/// names, and each output gets its own `.idx`. The input's `.idx` maps
+/// blocks to word ordinals; transactions need the bodies segment of the same
+/// range next to them to find the txNums of the blocks.
+pub fn split(
+    input: impl AsRef<Path>,
+    ranges: &[Range<BlockNumber>],
+    cfg: Cfg,
+) -> snapshots::Result<Vec<PathBuf>> {
+    let input = input.as_ref();
+    let file = SnapshotFile::parse(input).ok_or_else(|| {
+        SnapshotError::InvalidPath(format!("{} is not a block segment", input.display()))
+    })?;
+    let idx_path = file.index_path();
+    if !idx_path.exists() {
+        return Err(SnapshotError::IndexNotAvailable);
+    }
+    let idx = RecSplitIndex::open(&idx_path)?;
+    let decompressor = Decompressor::new(input)?;
+
+    let mut outputs = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        let (from, to) = (range.start.get(), range.end.get());
+        if from >= to
+            || from < file.from_block
+            || to > file.to_block
+            || (from, to) == (file.from_block, file.to_block)
+            || !from.is_multiple_of(BLOCKS_PER_FILE_UNIT)
+            || !to.is_multiple_of(BLOCKS_PER_FILE_UNIT)
+        {
+            return Err(SnapshotError::InvalidPath(format!(
+                "Can't split blocks {}-{} out of {}",
+                from,
+                to,
+                input.display()
+            )));
+        }
+        let (words, base_data_id) = split_words(&file, &idx, &decompressor, range)?;
+        let output = input.with_file_name(format!(
+            "v{}-{:06}-{:06}-{}.seg",
+            file.version,
+            from / BLOCKS_PER_FILE_UNIT,
+            to / BLOCKS_PER_FILE_UNIT,
+            file.kind
+        ));
+
+        let mut compressor = Compressor::builder(&output)
+            .cfg(cfg.clone())
+            .log_prefix("split")
+            .build()?;
+        let mut getter = decompressor.make_getter();
+        if let Some(offset) = decompressor.word_offset(words.start) {
+            getter.reset(offset);
+        }
+        let mut word = Vec::new();
+        for _ in words.clone() {
+            word.clear();
+            word = getter.try_next(word)?.0;
+            compressor.add_word(&word)?;
+        }
+        compressor.compress()?;
+        build_block_index(&output, file.kind, base_data_id, true)?;
+        tracing::info!(
+            "Split {} words of blocks {}-{} into {}",
+            words.end - words.start,
+            from,
+            to,
+            output.display()
+        );
+        outputs.push(output);
+    }
+    Ok(outputs)
+}
+
+// Ordinals of the words of the blocks in `range` and the base data id of
+// the segment holding them
+fn split_words(
+    file: &SnapshotFile,
+    idx: &RecSplitIndex,
+    decompressor: &Decompressor,
+    range: &Range<BlockNumber>,
+) -> snapshots::Result<(Range<u64>, u64)> {
+    let first = idx.base_data_id();
+    let (from, to) = match file.kind {
+        SnapshotType::Headers
+        | SnapshotType::Bodies
+        | SnapshotType::BeaconBlocks
+        | SnapshotType::BlobSidecars => (range.start.get(), range.end.get()),
+        SnapshotType::Transactions | SnapshotType::Senders => {
+            let bodies = BodiesReader::new(&file.sibling(SnapshotType::Bodies))?;
+            let body = |number: u64| {
+                bodies
+                    .body_by_number(BlockNumber(number))?
+                    .ok_or(SnapshotError::BlockNotFound(number))
+            };
+            let last = body(range.end.get() - 1)?;
+            (
+                body(range.start.get())?.base_tx_num.get(),
+                last.base_tx_num.get() + u64::from(last.tx_count),
+            )
+        }
+        SnapshotType::BorSpans => (
+            span_id_at(range.start),
+            span_id_at(BlockNumber(range.end.get() - 1)) + 1,
+        ),
+        SnapshotType::BorEvents => {
+            // Events are keyed by block hash, so their blocks are read from
+            // the words
+            let mut getter = decompressor.make_getter();
+            let (mut start, mut end) = (None, 0);
+            let mut ordinal = 0;
+            while getter.has_next() {
+                let event = BorEvent::decode(&getter.try_next(Vec::new())?.0)?;
+                if range.contains(&BlockNumber(event.block_number)) {
+                    start.get_or_insert(ordinal);
+                    end = ordinal + 1;
+                }
+                ordinal += 1;
+            }
+            let start = start.unwrap_or(end);
+            return Ok((start..end, range.start.get()));
+        }
+    };
+    let words = from.saturating_sub(first)..to.saturating_sub(first);
+    if words.end > decompressor.count() as u64 {
+        return Err(SnapshotError::InvalidFormat(format!(
+            "{} has {} words, blocks up to {} need {}",
+            file.path.display(),
+            decompressor.count(),
+            range.end,
+            words.end
+        )));
+    }
+    Ok((words, from))
+}
+
+// The inputs of a merge into `target` must be its type and tile its range
+fn check_merge_inputs(inputs: &[impl AsRef<Path>], target: &SnapshotFile) -> snapshots::Result<()> {
+    let mut next_block = target.from_block;
+    for input in inputs {
+        let input = input.as_ref();
+        let file = SnapshotFile::parse(input)
+            .filter(|file| file.kind == target.kind && file.from_block == next_block)
+            .ok_or_else(|| {
+                SnapshotError::InvalidPath(format!(
+                    "{} does not continue {} segments at block {}",
+                    input.display(),
+                    target.kind,
+                    next_block
+                ))
+            })?;
+        next_block = file.to_block;
+    }
+    if next_block != target.to_block {
+        return Err(SnapshotError::InvalidPath(format!(
+            "Inputs end at block {}, {} at {}",
+            next_block,
+            target.path.display(),
+            target.to_block
+        )));
+    }
+    Ok(())
+}
+
+// Append `data` pretty-printed as RLP to `out`, returning false if it is not
+// exactly one RLP item
+fn write_rlp(out: &mut String, mut data: &[u8]) -> bool {
+    write_rlp_item(out, &mut data) && data.is_empty()
+}
+
+fn write_rlp_item(out: &mut String, data: &mut &[u8]) -> bool {
+    let Ok(header) = alloy_rlp::Header::decode(data) else {
+        return false;
+    };
+    if header.payload_length > data.len() {
+        return false;
+    }
+    let (mut payload, rest) = data.split_at(header.payload_length);
+    *data = rest;
+    if !header.list {
+        out.push_str("0x");
+        out.push_str(&hex::encode(payload));
+        return true;
+    }
+    out.push('[');
+    let mut first = true;
+    while !payload.is_empty() {
+        if !first {
+            out.push_str(", ");
+        }
+        first = false;
+        if !write_rlp_item(out, &mut payload) {
+            return false;
+        }
+    }
+    out.push(']');
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compressor;
+    use tempfile::TempDir;
+
+    fn write_segment(dir: &Path, words: &[Vec<u8>]) -> std::path::PathBuf {
+        write_named_segment(dir, "test.seg", words, 1024)
+    }
+
+    fn write_named_segment(
+        dir: &Path,
+        name: &str,
+        words: &[Vec<u8>],
+        min_pattern_score: u64,
+    ) -> std::path::PathBuf {
+        let path = dir.join(name);
+        let mut compressor = Compressor::builder(&path)
+            .fsync(false)
+            .min_pattern_score(min_pattern_score)
+            .build()
+            .unwrap();
+        for word in words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_merge() {
+        let tmp_dir = TempDir::new().unwrap();
+        let words: Vec<Vec<u8>> = (0..300)
+            .map(|i| format!("merged word {}", i % 40).into_bytes())
+            .collect();
+        let a = write_named_segment(tmp_dir.path(), "a.seg", &words[..100], 1024);
+        let b = write_named_segment(tmp_dir.path(), "b.seg", &words[100..], 1024);
+        let output = tmp_dir.path().join("ab.seg");
+        let cfg = Cfg {
+            min_pattern_score: 1,
+            ..Default::default()
+        };
+        assert_eq!(merge(&[&a, &b], &output, cfg).unwrap(), 300);
+
+        let merged = Decompressor::new(&output).unwrap();
+        let mut getter = merged.make_getter();
+        for word in &words {
+            assert_eq!(&getter.next(Vec::new()).0, word);
+        }
+        assert!(!getter.has_next());
+        // The dictionary is trained on the merged words
+        assert!(merged.patterns().count() > 0);
+        assert!(!output.with_extension("idx").exists());
+    }
+
+    #[test]
+    fn test_merge_block_segments() {
+        use crate::snapshots::{BlockNumber, HeaderSegmentWriter, HeadersReader};
+        use alloy_consensus::Header;
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let headers: Vec<Header> = (0..2000)
+            .map(|number| Header {
+                number,
+                gas_limit: 30_000_000,
+                timestamp: 1_600_000_000 + number * 12,
+                ..Default::default()
+            })
+            .collect();
+        let mut inputs = Vec::new();
+        for range in [0..1000, 1000..2000] {
+            let mut writer =
+                HeaderSegmentWriter::with_compressor(tmp_dir.path(), range.start, range.end, |b| {
+                    b.level(crate::CompressionLevel::Store)
+                })
+                .unwrap();
+            writer.disable_fsync();
+            for header in &headers[range.start as usize..range.end as usize] {
+                writer.add_header(header).unwrap();
+            }
+            inputs.push(writer.finish().unwrap().0);
+        }
+
+        // Inputs must tile the output's range
+        let output = tmp_dir.path().join("v1-000000-000002-headers.seg");
+        assert!(merge(&inputs[..1], &output, Cfg::default()).is_err());
+        assert!(merge(&[&inputs[1], &inputs[0]], &output, Cfg::default()).is_err());
+
+        assert_eq!(merge(&inputs, &output, Cfg::default()).unwrap(), 2000);
+        let reader = HeadersReader::new(&output).unwrap();
+        assert_eq!(reader.count(), 2000);
+        assert_eq!(reader.first_block(), Some(BlockNumber(0)));
+        for number in [0, 999, 1000, 1999] {
+            let header = &headers[number as usize];
+            assert_eq!(
+                reader
+                    .header_by_number(BlockNumber(number))
+                    .unwrap()
+                    .as_ref(),
+                Some(header)
+            );
+            assert_eq!(
+                reader.header_by_hash(header.hash_slow()).unwrap().as_ref(),
+                Some(header)
+            );
+        }
+        Decompressor::new(&output)
+            .unwrap()
+            .verify_with_index(reader.index().unwrap())
+            .unwrap();
+
+        // Splitting the merged segment gives back the inputs
+        let split_dir = tmp_dir.path().join("split");
+        std::fs::create_dir(&split_dir).unwrap();
+        let merged = split_dir.join("v1-000000-000002-headers.seg");
+        std::fs::rename(&output, &merged).unwrap();
+        std::fs::rename(output.with_extension("idx"), merged.with_extension("idx")).unwrap();
+        for bad in [0..500, 1000..3000, 0..2000, 1000..1000] {
+            let range = BlockNumber(bad.start)..BlockNumber(bad.end);
+            assert!(split(&merged, &[range], Cfg::default()).is_err());
+        }
+        let parts = split(
+            &merged,
+            &[
+                BlockNumber(0)..BlockNumber(1000),
+                BlockNumber(1000)..BlockNumber(2000),
+            ],
+            Cfg::default(),
+        )
+        .unwrap();
+        for (part, input) in parts.iter().zip(&inputs) {
+            assert_eq!(part.file_name(), input.file_name());
+            assert!(diff(part, input).unwrap().same_words());
+        }
+        let reader = HeadersReader::new(&parts[1]).unwrap();
+        assert_eq!(reader.first_block(), Some(BlockNumber(1000)));
+        let header = &headers[1500];
+        assert_eq!(
+            reader.header_by_number(BlockNumber(1500)).unwrap().as_ref(),
+            Some(header)
+        );
+        assert_eq!(
+            reader.header_by_hash(header.hash_slow()).unwrap().as_ref(),
+            Some(header)
+        );
+        assert_eq!(reader.header_by_number(BlockNumber(999)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_cat_range_and_formats() {
+        let tmp_dir = TempDir::new().unwrap();
+        let words: Vec<Vec<u8>> = (0..100)
+            .map(|i| format!("word number {}", i).into_bytes())
+            .collect();
+        let path = write_segment(tmp_dir.path(), &words);
+
+        let mut out = Vec::new();
+        assert_eq!(cat(&path, 10..13, WordFormat::Raw, &mut out).unwrap(), 3);
+        assert_eq!(out, b"word number 10\nword number 11\nword number 12\n");
+
+        out.clear();
+        assert_eq!(cat(&path, 98.., WordFormat::Hex, &mut out).unwrap(), 2);
+        assert_eq!(
+            std::str::from_utf8(&out).unwrap(),
+            format!(
+                "{}\n{}\n",
+                hex::encode("word number 98"),
+                hex::encode("word number 99")
+            )
+        );
+
+        out.clear();
+        assert_eq!(cat(&path, .., WordFormat::Raw, &mut out).unwrap(), 100);
+        assert_eq!(cat(&path, 100..200, WordFormat::Raw, &mut out).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_cat_rlp() {
+        let tmp_dir = TempDir::new().unwrap();
+        // [1, "ab", ""] and [[1, "ab", ""], 5]
+        let list = vec![0xc5, 0x01, 0x82, b'a', b'b', 0x80];
+        let mut nested = vec![0xc7];
+        nested.extend_from_slice(&list);
+        nested.push(0x05);
+        let words = vec![list, nested, vec![0xff, 0x01], Vec::new()];
+        let path = write_segment(tmp_dir.path(), &words);
+
+        let mut out = Vec::new();
+        cat(&path, .., WordFormat::Rlp, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[0x01, 0x6162, 0x]\n[[0x01, 0x6162, 0x], 0x05]\nff01\n\n"
+        );
+    }
+
+    #[test]
+    fn test_grep() {
+        let tmp_dir = TempDir::new().unwrap();
+        let words: Vec<Vec<u8>> = (0..300)
+            .map(|i| match i {
+                17 => b"prefix needle suffix".to_vec(),
+                250 => b"needle".to_vec(),
+                _ => format!("haystack word {}", i).into_bytes(),
+            })
+            .collect();
+        let path = write_segment(tmp_dir.path(), &words);
+
+        let matches = grep(&path, b"needle").unwrap();
+        assert_eq!(
+            matches
+                .iter()
+                .map(|m| (m.ordinal, m.position))
+                .collect::<Vec<_>>(),
+            [(17, 7), (250, 0)]
+        );
+
+        // The offsets lead back to the matching words
+        let decompressor = Decompressor::new(&path).unwrap();
+        let mut getter = decompressor.make_getter();
+        getter.reset(matches[1].offset);
+        assert_eq!(getter.next(Vec::new()).0, b"needle");
+
+        assert!(grep(&path, b"absent").unwrap().is_empty());
+        assert_eq!(grep(&path, b"").unwrap().len(), 300);
+    }
+
+    #[test]
+    fn test_diff() {
+        let tmp_dir = TempDir::new().unwrap();
+        let words: Vec<Vec<u8>> = (0..2000)
+            .map(|i| format!("transaction payload {} of block {}", i % 97, i / 10).into_bytes())
+            .collect();
+        let a = write_named_segment(tmp_dir.path(), "a.seg", &words, 1);
+
+        let same = diff(&a, &a).unwrap();
+        assert!(same.same_words() && same.same_dictionaries());
+        assert_eq!(same.words, (2000, 2000));
+        assert_eq!(same.differing_words, 0);
+        assert!(same.patterns.entries.0 > 0);
+
+        // The same words with a smaller dictionary
+        let b = write_named_segment(tmp_dir.path(), "b.seg", &words, 10_000);
+        let d = diff(&a, &b).unwrap();
+        assert!(d.same_words());
+        assert!(!d.same_dictionaries());
+        assert!(d.patterns.only_in_a > 0);
+        assert_eq!(
+            d.patterns.entries.0 - d.patterns.only_in_a,
+            d.patterns.entries.1 - d.patterns.only_in_b
+        );
+
+        // Two changed words and one missing at the end
+        let mut changed = words.clone();
+        changed[700] = b"changed".to_vec();
+        changed[1500].push(b'!');
+        changed.pop();
+        let c = write_named_segment(tmp_dir.path(), "c.seg", &changed, 1);
+        let d = diff(&a, &c).unwrap();
+        assert_eq!(d.words, (2000, 1999));
+        assert_eq!(d.first_divergent_word, Some(700));
+        assert_eq!(d.differing_words, 2);
+
+        // Only the length differs
+        changed = words.clone();
+        changed.push(Vec::new());
+        let e = write_named_segment(tmp_dir.path(), "e.seg", &changed, 1);
+        let d = diff(&a, &e).unwrap();
+        assert_eq!(d.empty_words, (0, 1));
+        assert_eq!(d.first_divergent_word, Some(2000));
+        assert_eq!(d.differing_words, 0);
+    }
+}
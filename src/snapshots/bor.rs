@@ -0,0 +1,475 @@
+//! Readers for Polygon (bor) block segments
+//!
+//! `borevents` segments hold the state sync events committed to each block,
+//! one word per event: the block hash (32 bytes), block number and event id
+//! (8 bytes big-endian each), then the event's RLP encoding. Their `.idx`
+//! maps a block hash to the offset of the block's first event.
+//!
+//! `borspans` segments hold one Heimdall span per word in span id order,
+//! JSON-encoded as Heimdall serves it.
+
+use crate::decompress::{Decompressor, Getter};
+use crate::error::IndexError;
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::snapshots::types::BlockNumber;
+use crate::snapshots::{Result, SnapshotError};
+use alloy_primitives::B256;
+use std::path::Path;
+
+// From Erigon: polygon/heimdall/span_id.go
+const SPAN_LENGTH: u64 = 6400;
+const ZEROTH_SPAN_END: u64 = 255;
+
+/// Id of the Heimdall span block `block` belongs to
+///
+/// Span 0 covers blocks 0..=255, every later span 6400 blocks.
+// From Erigon: polygon/heimdall/span_id.go SpanIdAt
+pub fn span_id_at(block: BlockNumber) -> u64 {
+    let block = block.get();
+    if block > ZEROTH_SPAN_END {
+        1 + (block - ZEROTH_SPAN_END - 1) / SPAN_LENGTH
+    } else {
+        0
+    }
+}
+
+/// A state sync event, as stored in a `borevents` segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct BorEvent {
+    pub block_hash: B256,
+    pub block_number: u64,
+    pub event_id: u64,
+    /// RLP encoding of the event record
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "alloy_primitives::serde_hex::serialize")
+    )]
+    pub data: Vec<u8>,
+}
+
+// Block hash, block number and event id
+const EVENT_PREFIX_LEN: usize = 32 + 8 + 8;
+
+impl BorEvent {
+    /// Decode a `borevents` segment word
+    pub fn decode(word: &[u8]) -> Result<Self> {
+        if word.len() < EVENT_PREFIX_LEN {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Bor event word of {} bytes, expected at least {}",
+                word.len(),
+                EVENT_PREFIX_LEN
+            )));
+        }
+        Ok(BorEvent {
+            block_hash: B256::from_slice(&word[..32]),
+            block_number: u64::from_be_bytes(word[32..40].try_into().unwrap()),
+            event_id: u64::from_be_bytes(word[40..48].try_into().unwrap()),
+            data: word[EVENT_PREFIX_LEN..].to_vec(),
+        })
+    }
+
+    /// Encode as a `borevents` segment word
+    pub fn encode(&self) -> Vec<u8> {
+        let mut word = Vec::with_capacity(EVENT_PREFIX_LEN + self.data.len());
+        word.extend_from_slice(self.block_hash.as_slice());
+        word.extend_from_slice(&self.block_number.to_be_bytes());
+        word.extend_from_slice(&self.event_id.to_be_bytes());
+        word.extend_from_slice(&self.data);
+        word
+    }
+}
+
+/// Reader for `borevents` segments
+pub struct BorEventsReader {
+    decompressor: Decompressor,
+    // Block hash to first event offset index (`.idx`), if one was found
+    index: Option<RecSplitIndex>,
+}
+
+impl BorEventsReader {
+    /// Open an events segment, along with the `.idx` next to it if any
+    pub fn new(path: &Path) -> Result<Self> {
+        let idx_path = path.with_extension("idx");
+        let index = if idx_path.exists() {
+            Some(RecSplitIndex::open(&idx_path)?)
+        } else {
+            None
+        };
+        Self::with_index(path, index)
+    }
+
+    /// Open an events segment with an explicitly given index (or none)
+    pub fn with_index(path: &Path, index: Option<RecSplitIndex>) -> Result<Self> {
+        let decompressor = Decompressor::new(path)?;
+        if let Some(idx) = &index {
+            if idx.is_enum() {
+                return Err(IndexError::WrongKind {
+                    file: decompressor.file_name().to_string(),
+                    is_enum: true,
+                }
+                .into());
+            }
+        }
+        Ok(Self {
+            decompressor,
+            index,
+        })
+    }
+
+    /// Number of events in the segment
+    pub fn count(&self) -> usize {
+        self.decompressor.count()
+    }
+
+    /// The block hash index, if one is attached
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.index.as_ref()
+    }
+
+    pub fn make_getter(&self) -> BorEventGetter<'_> {
+        BorEventGetter {
+            getter: self.decompressor.make_getter(),
+        }
+    }
+
+    /// The events of the block with hash `hash`, in event id order
+    ///
+    /// Uses the index when available, otherwise scans the segment.
+    pub fn events_by_block_hash(&self, hash: B256) -> Result<Vec<BorEvent>> {
+        let mut getter = self.make_getter();
+        match &self.index {
+            Some(idx) => {
+                // Unknown hashes map somewhere too; the hash check below
+                // then finds no events
+                let Some(offset) = idx.lookup(hash.as_slice()) else {
+                    return Ok(Vec::new());
+                };
+                getter.reset(offset);
+            }
+            None => tracing::debug!("No bor events index, scanning for {:?}", hash),
+        }
+        let mut events = Vec::new();
+        while getter.has_next() {
+            let event = getter.next()?;
+            if event.block_hash == hash {
+                events.push(event);
+            } else if !events.is_empty() || self.index.is_some() {
+                break;
+            }
+        }
+        Ok(events)
+    }
+
+    /// The events of block `number`, in event id order
+    pub fn events_by_block_number(&self, number: BlockNumber) -> Result<Vec<BorEvent>> {
+        let number = number.get();
+        let mut getter = self.make_getter();
+        let mut events = Vec::new();
+        while getter.has_next() {
+            let event = getter.next()?;
+            // Events are stored in block order
+            if event.block_number > number {
+                break;
+            }
+            if event.block_number == number {
+                events.push(event);
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Iterator over the events of a `borevents` segment
+pub struct BorEventGetter<'a> {
+    getter: Getter<'a>,
+}
+
+impl<'a> BorEventGetter<'a> {
+    pub fn has_next(&self) -> bool {
+        self.getter.has_next()
+    }
+
+    /// Read the next event
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<BorEvent> {
+        let (word, _) = self.getter.next(Vec::new());
+        BorEvent::decode(&word)
+    }
+
+    /// Skip the next event without decoding it
+    pub fn skip(&mut self) {
+        self.getter.skip();
+    }
+
+    /// Move to the event starting at `offset`
+    pub fn reset(&mut self, offset: u64) {
+        self.getter.reset(offset);
+    }
+}
+
+/// A Heimdall span, as stored in a `borspans` segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct BorSpan {
+    pub id: u64,
+    pub start_block: u64,
+    pub end_block: u64,
+    /// The span as Heimdall served it, validator set included
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_span_json"))]
+    pub json: Vec<u8>,
+}
+
+// The span JSON is embedded as is, not as a string
+#[cfg(feature = "serde")]
+fn serialize_span_json<S: serde::Serializer>(
+    json: &[u8],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let value: serde_json::Value =
+        serde_json::from_slice(json).map_err(serde::ser::Error::custom)?;
+    serde::Serialize::serialize(&value, serializer)
+}
+
+impl BorSpan {
+    /// Decode a `borspans` segment word
+    pub fn decode(word: &[u8]) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_slice(word)?;
+        let field = |name: &str| {
+            value.get(name).and_then(|v| v.as_u64()).ok_or_else(|| {
+                SnapshotError::InvalidFormat(format!("Bor span without a numeric {}", name))
+            })
+        };
+        Ok(BorSpan {
+            id: field("span_id")?,
+            start_block: field("start_block")?,
+            end_block: field("end_block")?,
+            json: word.to_vec(),
+        })
+    }
+}
+
+/// Reader for `borspans` segments
+pub struct BorSpansReader {
+    decompressor: Decompressor,
+    // Spans are stored by consecutive ids from this one
+    first_span_id: Option<u64>,
+}
+
+impl BorSpansReader {
+    pub fn new(path: &Path) -> Result<Self> {
+        let decompressor = Decompressor::new(path)?;
+        let first_span_id = match decompressor.get_word(0) {
+            Some(word) => Some(BorSpan::decode(&word)?.id),
+            None => None,
+        };
+        Ok(Self {
+            decompressor,
+            first_span_id,
+        })
+    }
+
+    /// Number of spans in the segment
+    pub fn count(&self) -> usize {
+        self.decompressor.count()
+    }
+
+    pub fn make_getter(&self) -> BorSpanGetter<'_> {
+        BorSpanGetter {
+            getter: self.decompressor.make_getter(),
+        }
+    }
+
+    /// The span with id `id`, or `None` if this segment does not have it
+    pub fn span(&self, id: u64) -> Result<Option<BorSpan>> {
+        let Some(i) = self.first_span_id.and_then(|first| id.checked_sub(first)) else {
+            return Ok(None);
+        };
+        let Some(word) = self.decompressor.get_word(i) else {
+            return Ok(None);
+        };
+        let span = BorSpan::decode(&word)?;
+        if span.id != id {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Span {} stored where span {} belongs",
+                span.id, id
+            )));
+        }
+        Ok(Some(span))
+    }
+
+    /// The span block `block` belongs to, if this segment has it
+    pub fn span_for_block(&self, block: BlockNumber) -> Result<Option<BorSpan>> {
+        self.span(span_id_at(block))
+    }
+}
+
+/// Iterator over the spans of a `borspans` segment
+pub struct BorSpanGetter<'a> {
+    getter: Getter<'a>,
+}
+
+impl<'a> BorSpanGetter<'a> {
+    pub fn has_next(&self) -> bool {
+        self.getter.has_next()
+    }
+
+    /// Read the next span
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<BorSpan> {
+        let (word, _) = self.getter.next(Vec::new());
+        BorSpan::decode(&word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::recsplit::RecSplit;
+    use crate::testutil::write_segment;
+    use std::path::PathBuf;
+
+    // Three events for every other block from 1000, ids counting from 10
+    fn events() -> Vec<BorEvent> {
+        (0..30u64)
+            .map(|i| {
+                let block_number = 1000 + 2 * (i / 3);
+                BorEvent {
+                    block_hash: B256::with_last_byte(block_number as u8),
+                    block_number,
+                    event_id: 10 + i,
+                    data: vec![0xc0 + (i % 3) as u8; 20],
+                }
+            })
+            .collect()
+    }
+
+    fn write_events(dir: &Path, with_index: bool) -> PathBuf {
+        let path = dir.join("v1-000000-000500-borevents.seg");
+        let events = events();
+        write_segment(
+            &path,
+            &events.iter().map(BorEvent::encode).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        if with_index {
+            let decompressor = Decompressor::new(&path).unwrap();
+            let mut getter = decompressor.make_getter();
+            let mut first_offsets = Vec::new();
+            let mut offset = 0;
+            for (i, event) in events.iter().enumerate() {
+                if i == 0 || events[i - 1].block_hash != event.block_hash {
+                    first_offsets.push((event.block_hash, offset));
+                }
+                offset = getter.skip().0;
+            }
+            let mut rs = RecSplit::builder(path.with_extension("idx"), first_offsets.len())
+                .fsync(false)
+                .build()
+                .unwrap();
+            for (hash, offset) in first_offsets {
+                rs.add_key(hash.as_slice(), offset).unwrap();
+            }
+            rs.build().unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_events_reader() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let expected = events();
+        for with_index in [false, true] {
+            let path = write_events(tmp_dir.path(), with_index);
+            let reader = BorEventsReader::new(&path).unwrap();
+            assert_eq!(reader.index().is_some(), with_index);
+            assert_eq!(reader.count(), expected.len());
+
+            let mut getter = reader.make_getter();
+            for event in &expected {
+                assert_eq!(&getter.next().unwrap(), event);
+            }
+            assert!(!getter.has_next());
+
+            let block = &expected[3..6];
+            assert_eq!(
+                reader.events_by_block_hash(block[0].block_hash).unwrap(),
+                block
+            );
+            assert_eq!(
+                reader.events_by_block_number(BlockNumber(1002)).unwrap(),
+                block
+            );
+            assert!(reader
+                .events_by_block_number(BlockNumber(1001))
+                .unwrap()
+                .is_empty());
+            assert!(reader
+                .events_by_block_hash(B256::repeat_byte(0xee))
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn test_spans_reader() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("v1-000000-000500-borspans.seg");
+        let spans: Vec<Vec<u8>> = (0..5u64)
+            .map(|id| {
+                let (start, end) = match id {
+                    0 => (0, ZEROTH_SPAN_END),
+                    id => (
+                        ZEROTH_SPAN_END + 1 + (id - 1) * SPAN_LENGTH,
+                        ZEROTH_SPAN_END + id * SPAN_LENGTH,
+                    ),
+                };
+                format!(
+                    r#"{{"span_id":{},"start_block":{},"end_block":{},"validator_set":{{}},"selected_producers":[],"bor_chain_id":"137"}}"#,
+                    id, start, end
+                )
+                .into_bytes()
+            })
+            .collect();
+        write_segment(&path, &spans).unwrap();
+
+        let reader = BorSpansReader::new(&path).unwrap();
+        assert_eq!(reader.count(), 5);
+        let span = reader.span(3).unwrap().unwrap();
+        assert_eq!(
+            (span.id, span.start_block, span.end_block),
+            (3, 13056, 19455)
+        );
+        assert_eq!(span.json, spans[3]);
+        assert_eq!(reader.span(5).unwrap(), None);
+
+        for block in [0, 255, 256, 6655, 6656, 19455, 19456] {
+            let span = reader.span_for_block(BlockNumber(block)).unwrap().unwrap();
+            assert!((span.start_block..=span.end_block).contains(&block));
+        }
+
+        let mut getter = reader.make_getter();
+        let mut ids = Vec::new();
+        while getter.has_next() {
+            ids.push(getter.next().unwrap().id);
+        }
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+
+        assert!(matches!(
+            BorSpan::decode(br#"{"span_id":1}"#),
+            Err(SnapshotError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            BorSpan::decode(b"not json"),
+            Err(SnapshotError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,392 @@
+//! Reader for Erigon 3 `.bt` B-tree index files
+//!
+//! A `.bt` file sits next to a `.kv` domain file (see
+//! [`DomainReader`](crate::snapshots::DomainReader)) and supports ordered
+//! seeks over its sorted keys. Based on erigon-lib/state/btree_index.go and
+//! bps_tree.go.
+//!
+//! Layout:
+//! - eliasfano32 sequence of the offsets of every key word in the `.kv`
+//!   file, indexed by key ordinal
+//! - optionally, the sampled B-tree nodes: node count (u64 BE), then for
+//!   each node its key ordinal (u64 BE), key length (u16 BE) and key
+//!
+//! Only every M-th key is kept as a node; a seek narrows the ordinal range
+//! with the nodes and finishes with a binary search that reads keys from the
+//! `.kv` file. Files written without nodes get them sampled on open.
+
+use crate::error::IndexError;
+use crate::seg_reader::SegmentReader;
+use crate::snapshots::mapped::FileData;
+use crate::snapshots::recsplit::{ef32_get, ef32_size};
+use crate::snapshots::{Result, SnapshotError};
+use std::cmp::Ordering;
+use std::fs::File;
+use std::path::Path;
+
+/// Keys per node when the nodes are sampled on open
+// From Go: btree_index.go DefaultBtreeM
+pub const DEFAULT_BTREE_M: u64 = 256;
+
+/// A sampled key of the B-tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Node {
+    // Ordinal of the key in the `.kv` file
+    di: u64,
+    key: Vec<u8>,
+}
+
+/// A key/value pair found by [`BtIndex::seek`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BtEntry {
+    /// Ordinal of the key in the `.kv` file
+    pub ordinal: u64,
+    /// Offset of the key word in the `.kv` file
+    pub offset: u64,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+/// Reader for `.bt` index files
+///
+/// The index does not own the `.kv` file it points into; lookups take its
+/// [`SegmentReader`] instead.
+pub struct BtIndex {
+    mmap: Option<FileData>,
+    key_count: u64,
+    nodes: Vec<Node>,
+}
+
+impl BtIndex {
+    /// Open the `.bt` file at `path`; `kv` is the `.kv` file it indexes,
+    /// needed to sample nodes if the file has none
+    pub fn open(path: &Path, kv: &SegmentReader) -> Result<Self> {
+        let file = File::open(path)?;
+        // An empty domain file gets an empty index, which can't be mapped
+        if file.metadata()?.len() == 0 {
+            return Ok(Self {
+                mmap: None,
+                key_count: 0,
+                nodes: Vec::new(),
+            });
+        }
+        let mmap = FileData::open(&file)?;
+
+        if mmap.len() < 16 {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Index file {} truncated in Elias-Fano header",
+                path.display()
+            )));
+        }
+        let ef_size = ef32_size(&mmap);
+        if ef_size > mmap.len() {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Index file truncated in Elias-Fano data: need {} bytes, have {}",
+                ef_size,
+                mmap.len()
+            )));
+        }
+        let key_count = u64::from_be_bytes(mmap[0..8].try_into().unwrap()) + 1;
+        if key_count != (kv.count() / 2) as u64 {
+            return Err(IndexError::KeyCountMismatch {
+                file: kv.file_name().to_string(),
+                expected: (kv.count() / 2) as u64,
+                actual: key_count,
+            }
+            .into());
+        }
+
+        let mut idx = Self {
+            mmap: None,
+            key_count,
+            nodes: decode_nodes(&mmap[ef_size..], key_count)?,
+        };
+        idx.mmap = Some(mmap);
+        if idx.nodes.is_empty() {
+            idx.nodes = idx.sample_nodes(kv, DEFAULT_BTREE_M)?;
+        }
+        Ok(idx)
+    }
+
+    /// Number of keys indexed
+    pub fn key_count(&self) -> u64 {
+        self.key_count
+    }
+
+    /// Number of B-tree nodes kept in memory
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Offset of the `ordinal`-th key word in the `.kv` file
+    pub fn offset(&self, ordinal: u64) -> Option<u64> {
+        if ordinal >= self.key_count {
+            return None;
+        }
+        ef32_get(self.mmap.as_deref()?, ordinal)
+    }
+
+    /// Find the first key that is `>= key`, or `None` if every key is smaller
+    // From Go: bps_tree.go BpsTree.Seek
+    pub fn seek(&self, kv: &SegmentReader, key: &[u8]) -> Result<Option<BtEntry>> {
+        let (mut l, mut r) = self.node_bounds(key);
+        while l < r {
+            let m = (l + r) / 2;
+            if self.key_at(kv, m)?.as_slice() < key {
+                l = m + 1;
+            } else {
+                r = m;
+            }
+        }
+        if l >= self.key_count {
+            return Ok(None);
+        }
+        self.entry_at(kv, l).map(Some)
+    }
+
+    /// Get the value stored for exactly `key`
+    pub fn get(&self, kv: &SegmentReader, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .seek(kv, key)?
+            .filter(|entry| entry.key == key)
+            .map(|entry| entry.value))
+    }
+
+    // Ordinal range `[l, r)` the first key `>= key` falls into, from the nodes
+    // From Go: bps_tree.go BpsTree.bs
+    fn node_bounds(&self, key: &[u8]) -> (u64, u64) {
+        let (mut dl, mut dr) = (0, self.key_count);
+        let (mut l, mut r) = (0, self.nodes.len());
+        while l < r {
+            let m = (l + r) / 2;
+            let node = &self.nodes[m];
+            match node.key.as_slice().cmp(key) {
+                Ordering::Equal => return (node.di, node.di),
+                Ordering::Greater => {
+                    r = m;
+                    dr = node.di;
+                }
+                Ordering::Less => {
+                    l = m + 1;
+                    dl = node.di + 1;
+                }
+            }
+        }
+        (dl, dr)
+    }
+
+    fn key_at(&self, kv: &SegmentReader, ordinal: u64) -> Result<Vec<u8>> {
+        let mut reader = kv.make_reader();
+        reader.reset(self.checked_offset(ordinal)?);
+        Ok(reader.next(Vec::new()).0)
+    }
+
+    // From Go: btree_index.go BtIndex.dataLookup
+    fn entry_at(&self, kv: &SegmentReader, ordinal: u64) -> Result<BtEntry> {
+        let offset = self.checked_offset(ordinal)?;
+        let mut reader = kv.make_reader();
+        reader.reset(offset);
+        let (key, _) = reader.next(Vec::new());
+        if !reader.has_next() {
+            return Err(SnapshotError::UnexpectedEof {
+                context: format!("value of key {} in {}", ordinal, kv.file_name()),
+            });
+        }
+        let (value, _) = reader.next(Vec::new());
+        Ok(BtEntry {
+            ordinal,
+            offset,
+            key,
+            value,
+        })
+    }
+
+    fn checked_offset(&self, ordinal: u64) -> Result<u64> {
+        Ok(self
+            .offset(ordinal)
+            .ok_or(IndexError::MissingOffset { ordinal })?)
+    }
+
+    // Nodes for files written without them: every `m`-th key
+    // From Go: bps_tree.go BpsTree.WarmUp
+    fn sample_nodes(&self, kv: &SegmentReader, m: u64) -> Result<Vec<Node>> {
+        (0..self.key_count)
+            .step_by(m as usize)
+            .map(|di| {
+                Ok(Node {
+                    di,
+                    key: self.key_at(kv, di)?,
+                })
+            })
+            .collect()
+    }
+}
+
+// From Go: bps_tree.go decodeListNodes
+fn decode_nodes(data: &[u8], key_count: u64) -> Result<Vec<Node>> {
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+    let truncated = || SnapshotError::InvalidFormat("Index file truncated in B-tree nodes".into());
+    let count = u64::from_be_bytes(data.get(..8).ok_or_else(truncated)?.try_into().unwrap());
+    let mut pos = 8;
+    // Each node takes at least 10 bytes, which bounds a bogus count
+    let capacity = usize::try_from(count.min(key_count))
+        .unwrap_or(usize::MAX)
+        .min(data.len() / 10);
+    let mut nodes: Vec<Node> = Vec::with_capacity(capacity);
+    for _ in 0..count {
+        let header = data.get(pos..pos + 10).ok_or_else(truncated)?;
+        let di = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let key_len = u16::from_be_bytes(header[8..10].try_into().unwrap()) as usize;
+        pos += 10;
+        let key = data.get(pos..pos + key_len).ok_or_else(truncated)?.to_vec();
+        pos += key_len;
+
+        if di >= key_count || nodes.last().is_some_and(|prev| prev.di >= di) {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "B-tree node {} for key {} out of order or out of {} keys",
+                nodes.len(),
+                di,
+                key_count
+            )));
+        }
+        nodes.push(Node { di, key });
+    }
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::Compressor;
+    use crate::seg_reader::FileCompression;
+    use crate::snapshots::recsplit::build_elias_fano32;
+    use std::path::PathBuf;
+
+    // Even keys only, so every odd key falls between two stored ones
+    fn pairs(n: u32) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..n)
+            .map(|i| {
+                let key = (i * 2).to_be_bytes().to_vec();
+                (key, format!("value-{}", i).into_bytes())
+            })
+            .collect()
+    }
+
+    // Write the `.kv` file and return it with the offsets of its key words
+    fn write_kv(dir: &Path, pairs: &[(Vec<u8>, Vec<u8>)]) -> (PathBuf, Vec<u64>) {
+        let kv_path = dir.join("v1-storage.0-64.kv");
+        let mut compressor = Compressor::builder(&kv_path).fsync(false).build().unwrap();
+        for (key, value) in pairs {
+            compressor.add_word(key).unwrap();
+            compressor.add_uncompressed_word(value).unwrap();
+        }
+        compressor.compress().unwrap();
+
+        let kv = SegmentReader::new(&kv_path, FileCompression::Keys).unwrap();
+        let mut reader = kv.make_reader();
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        while reader.has_next() {
+            offsets.push(offset);
+            reader.skip();
+            offset = reader.skip().0;
+        }
+        (kv_path, offsets)
+    }
+
+    fn write_bt(path: &Path, offsets: &[u64], nodes: &[Node]) {
+        let mut data = build_elias_fano32(offsets, *offsets.last().unwrap());
+        if !nodes.is_empty() {
+            data.extend_from_slice(&(nodes.len() as u64).to_be_bytes());
+            for node in nodes {
+                data.extend_from_slice(&node.di.to_be_bytes());
+                data.extend_from_slice(&(node.key.len() as u16).to_be_bytes());
+                data.extend_from_slice(&node.key);
+            }
+        }
+        std::fs::write(path, data).unwrap();
+    }
+
+    fn check_seeks(idx: &BtIndex, kv: &SegmentReader, pairs: &[(Vec<u8>, Vec<u8>)]) {
+        // Every key costs a binary search over the `.kv` file, so only a
+        // sample of them
+        for (i, (key, value)) in pairs.iter().enumerate().step_by(3) {
+            assert_eq!(idx.get(kv, key).unwrap().as_ref(), Some(value));
+
+            // An absent key seeks to the next stored one
+            let mut between = key.clone();
+            *between.last_mut().unwrap() += 1;
+            assert_eq!(idx.get(kv, &between).unwrap(), None);
+            let next = idx.seek(kv, &between).unwrap();
+            assert_eq!(
+                next.map(|e| e.ordinal),
+                pairs.get(i + 1).map(|_| i as u64 + 1)
+            );
+        }
+        let first = idx.seek(kv, &[]).unwrap().unwrap();
+        assert_eq!((first.ordinal, first.offset), (0, 0));
+        assert_eq!(first.key, pairs[0].0);
+        assert_eq!(idx.seek(kv, &[0xff; 8]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_seek_with_encoded_nodes() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let pairs = pairs(300);
+        let (kv_path, offsets) = write_kv(tmp_dir.path(), &pairs);
+        let bt_path = kv_path.with_extension("bt");
+        let nodes: Vec<Node> = (0..pairs.len() as u64)
+            .step_by(32)
+            .map(|di| Node {
+                di,
+                key: pairs[di as usize].0.clone(),
+            })
+            .collect();
+        write_bt(&bt_path, &offsets, &nodes);
+
+        let kv = SegmentReader::new(&kv_path, FileCompression::Keys).unwrap();
+        let idx = BtIndex::open(&bt_path, &kv).unwrap();
+        assert_eq!(idx.key_count(), 300);
+        assert_eq!(idx.node_count(), nodes.len());
+        assert_eq!(idx.offset(1), Some(offsets[1]));
+        assert_eq!(idx.offset(300), None);
+        check_seeks(&idx, &kv, &pairs);
+    }
+
+    #[test]
+    fn test_seek_samples_nodes_when_absent() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let pairs = pairs(600);
+        let (kv_path, offsets) = write_kv(tmp_dir.path(), &pairs);
+        let bt_path = kv_path.with_extension("bt");
+        write_bt(&bt_path, &offsets, &[]);
+
+        let kv = SegmentReader::new(&kv_path, FileCompression::Keys).unwrap();
+        let idx = BtIndex::open(&bt_path, &kv).unwrap();
+        assert_eq!(idx.node_count(), 3);
+        check_seeks(&idx, &kv, &pairs);
+    }
+
+    #[test]
+    fn test_rejects_mismatched_index() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let pairs = pairs(10);
+        let (kv_path, offsets) = write_kv(tmp_dir.path(), &pairs);
+        let kv = SegmentReader::new(&kv_path, FileCompression::Keys).unwrap();
+        let bt_path = kv_path.with_extension("bt");
+
+        write_bt(&bt_path, &offsets[..5], &[]);
+        assert!(BtIndex::open(&bt_path, &kv).is_err());
+
+        let node = |di| Node {
+            di,
+            key: Vec::new(),
+        };
+        write_bt(&bt_path, &offsets, &[node(4), node(2)]);
+        assert!(BtIndex::open(&bt_path, &kv).is_err());
+        write_bt(&bt_path, &offsets, &[node(10)]);
+        assert!(BtIndex::open(&bt_path, &kv).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! LRU caching of decoded snapshot words
+//!
+//! RPC-style workloads ask for the same recent blocks over and over, and each
+//! lookup through a plain reader decompresses and decodes the word again.
+//! [`CachedReader`] wraps a headers or bodies reader and keeps the most
+//! recently decoded items, along with the ordinals that block hashes
+//! resolved to through the index.
+
+use crate::metrics;
+use crate::snapshots::reader::{BodiesReader, HeadersReader, StoredBody};
+use crate::snapshots::set::SegmentSet;
+use crate::snapshots::types::BlockNumber;
+use crate::snapshots::{Result, SnapshotError};
+use alloy_consensus::Header;
+use alloy_primitives::B256;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// A reader whose words [`CachedReader`] can keep decoded
+pub trait CacheSource {
+    /// What the `i`-th word decodes to
+    type Item: Clone;
+
+    /// Number of the first block of the snapshot, from its index
+    fn first_block(&self) -> Option<BlockNumber>;
+
+    /// Decode the `i`-th word, `None` past the last one
+    fn item(&self, i: u64) -> Result<Option<Self::Item>>;
+}
+
+impl CacheSource for HeadersReader {
+    type Item = (B256, Header);
+
+    fn first_block(&self) -> Option<BlockNumber> {
+        HeadersReader::first_block(self)
+    }
+
+    fn item(&self, i: u64) -> Result<Option<Self::Item>> {
+        self.header(i)
+    }
+}
+
+impl CacheSource for BodiesReader {
+    type Item = StoredBody;
+
+    fn first_block(&self) -> Option<BlockNumber> {
+        BodiesReader::first_block(self)
+    }
+
+    fn item(&self, i: u64) -> Result<Option<Self::Item>> {
+        self.body(i)
+    }
+}
+
+/// The headers of all segments, the `i`-th word being that of the set's
+/// first block plus `i`
+impl CacheSource for SegmentSet<HeadersReader> {
+    type Item = (B256, Header);
+
+    fn first_block(&self) -> Option<BlockNumber> {
+        self.ids().map(|ids| BlockNumber(ids.start))
+    }
+
+    fn item(&self, i: u64) -> Result<Option<Self::Item>> {
+        let Some(first) = self.ids().map(|ids| ids.start) else {
+            return Ok(None);
+        };
+        match self.locate(first + i) {
+            Some((reader, i)) => reader.header(i),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The bodies of all segments, numbered like the headers of a set
+impl CacheSource for SegmentSet<BodiesReader> {
+    type Item = StoredBody;
+
+    fn first_block(&self) -> Option<BlockNumber> {
+        self.ids().map(|ids| BlockNumber(ids.start))
+    }
+
+    fn item(&self, i: u64) -> Result<Option<Self::Item>> {
+        let Some(first) = self.ids().map(|ids| ids.start) else {
+            return Ok(None);
+        };
+        match self.locate(first + i) {
+            Some((reader, i)) => reader.body(i),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Hits and misses of a [`CachedReader`] since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// A reader that keeps its most recently decoded items in an LRU
+///
+/// Lookups take `&self` and the caches are updated internally, so a
+/// `CachedReader` can be shared between the threads or tasks serving
+/// requests. Words past the end of the snapshot are not cached, and neither
+/// are decoding errors.
+pub struct CachedReader<R: CacheSource> {
+    reader: R,
+    items: Mutex<LruCache<u64, R::Item>>,
+    // Block hash to the ordinal the index resolved it to, `None` for hashes
+    // the snapshot does not hold
+    ordinals: Mutex<LruCache<B256, Option<u64>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<R: CacheSource> CachedReader<R> {
+    /// Wrap `reader`, keeping up to `capacity` decoded items and as many
+    /// resolved hashes
+    pub fn new(reader: R, capacity: NonZeroUsize) -> Self {
+        Self {
+            reader,
+            items: Mutex::new(LruCache::new(capacity)),
+            ordinals: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Keep up to `capacity` resolved hashes instead of as many as items
+    pub fn with_lookup_capacity(mut self, capacity: NonZeroUsize) -> Self {
+        self.ordinals = Mutex::new(LruCache::new(capacity));
+        self
+    }
+
+    /// The wrapped reader, for lookups that bypass the cache
+    pub fn inner(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// The `i`-th item of the snapshot, decoded only if it is not cached
+    pub fn get(&self, i: u64) -> Result<Option<R::Item>> {
+        if let Some(item) = lock(&self.items).get(&i) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            metrics::increment(metrics::CACHE_HITS, 1);
+            return Ok(Some(item.clone()));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        metrics::increment(metrics::CACHE_MISSES, 1);
+        // Decode without holding the lock, so that other lookups go on
+        let item = self.reader.item(i)?;
+        if let Some(item) = &item {
+            lock(&self.items).put(i, item.clone());
+        }
+        Ok(item)
+    }
+
+    /// The item of block `number`; needs an index attached
+    pub fn get_by_number(&self, number: BlockNumber) -> Result<Option<R::Item>> {
+        let first = self
+            .reader
+            .first_block()
+            .ok_or(SnapshotError::IndexNotAvailable)?;
+        match number.ordinal_from(first) {
+            Some(i) => self.get(i),
+            None => Ok(None),
+        }
+    }
+
+    /// Number of decoded items held
+    pub fn len(&self) -> usize {
+        lock(&self.items).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every cached item and resolved hash
+    pub fn clear(&self) {
+        lock(&self.items).clear();
+        lock(&self.ordinals).clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl CachedReader<HeadersReader> {
+    /// See [`HeadersReader::header_by_number`]
+    pub fn header_by_number(&self, number: BlockNumber) -> Result<Option<Header>> {
+        Ok(self.get_by_number(number)?.map(|(_, header)| header))
+    }
+
+    /// See [`HeadersReader::header_by_hash`]
+    ///
+    /// The ordinal the index resolves `hash` to is cached as well. Without
+    /// an index the segment is scanned and nothing is cached.
+    pub fn header_by_hash(&self, hash: B256) -> Result<Option<Header>> {
+        let Some(idx) = self.reader.index() else {
+            return self.reader.header_by_hash(hash);
+        };
+        let cached = lock(&self.ordinals).get(&hash).copied();
+        let ordinal = match cached {
+            Some(ordinal) => ordinal,
+            None => {
+                // A perfect hash maps unknown keys somewhere too, so the
+                // header landed on is checked before the ordinal is kept
+                let ordinal = match idx.lookup(hash.as_slice()) {
+                    Some(i) => self
+                        .get(i)?
+                        .and_then(|(found, _)| (found == hash).then_some(i)),
+                    None => None,
+                };
+                lock(&self.ordinals).put(hash, ordinal);
+                ordinal
+            }
+        };
+        match ordinal {
+            Some(i) => Ok(self.get(i)?.map(|(_, header)| header)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl CachedReader<BodiesReader> {
+    /// See [`BodiesReader::body_by_number`]
+    pub fn body_by_number(&self, number: BlockNumber) -> Result<Option<StoredBody>> {
+        self.get_by_number(number)
+    }
+}
+
+// A panic while holding the lock leaves the cache consistent, as it only
+// ever happens outside of the LRU's own methods
+fn lock<T>(cache: &Mutex<T>) -> MutexGuard<'_, T> {
+    cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::tests::write_headers;
+    use std::path::Path;
+
+    fn headers(dir: &Path) -> HeadersReader {
+        HeadersReader::new(&write_headers(dir, 1000..2000)).unwrap()
+    }
+
+    #[test]
+    fn test_cached_headers() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cached = CachedReader::new(headers(tmp_dir.path()), NonZeroUsize::new(2).unwrap());
+
+        let header = cached.header_by_number(BlockNumber(1010)).unwrap().unwrap();
+        assert_eq!(header.number, 1010);
+        assert_eq!(cached.stats(), CacheStats { hits: 0, misses: 1 });
+        assert_eq!(
+            cached.header_by_number(BlockNumber(1010)).unwrap(),
+            Some(header.clone())
+        );
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 1 });
+
+        // The least recently used item goes once capacity is reached
+        cached.header_by_number(BlockNumber(1011)).unwrap();
+        cached.header_by_number(BlockNumber(1012)).unwrap();
+        assert_eq!(cached.len(), 2);
+        cached.header_by_number(BlockNumber(1010)).unwrap();
+        assert_eq!(cached.stats(), CacheStats { hits: 1, misses: 4 });
+
+        assert_eq!(cached.header_by_number(BlockNumber(2000)).unwrap(), None);
+        assert_eq!(cached.header_by_number(BlockNumber(999)).unwrap(), None);
+        assert_eq!(cached.len(), 2);
+
+        cached.clear();
+        assert!(cached.is_empty());
+    }
+
+    #[test]
+    fn test_cached_header_by_hash() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let cached = CachedReader::new(headers(tmp_dir.path()), NonZeroUsize::new(8).unwrap());
+        let hash = cached.inner().header(42).unwrap().unwrap().0;
+
+        let header = cached.header_by_hash(hash).unwrap().unwrap();
+        assert_eq!(header.number, 1042);
+        let stats = cached.stats();
+        assert_eq!(cached.header_by_hash(hash).unwrap(), Some(header));
+        assert_eq!(cached.stats().hits, stats.hits + 1);
+        assert_eq!(cached.stats().misses, stats.misses);
+
+        assert_eq!(
+            cached.header_by_hash(B256::repeat_byte(0xab)).unwrap(),
+            None
+        );
+        // Unknown hashes are remembered too
+        let stats = cached.stats();
+        assert_eq!(
+            cached.header_by_hash(B256::repeat_byte(0xab)).unwrap(),
+            None
+        );
+        assert_eq!(cached.stats(), stats);
+    }
+}
@@ -0,0 +1,595 @@
+//! Readers for caplin (beacon chain) segments
+//!
+//! Caplin segments hold one word per slot, empty for slots without a block,
+//! and their `.idx` is an enum index whose base data id is the first slot.
+//!
+//! A `beaconblocks` word is a snappy frame stream of the fork version
+//! (1 byte), the block body root (32 bytes) and a chunk holding the
+//! SSZ-encoded `SignedBeaconBlock`: an 8-byte big-endian length whose first
+//! byte is replaced by the chunk type, then the data.
+//!
+//! A `blobsidecars` word is the slot's `BlobSidecar`s SSZ-encoded back to
+//! back.
+
+use crate::decompress::{Decompressor, Getter};
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::snapshots::{Result, SnapshotError};
+use alloy_primitives::B256;
+use std::io::Read;
+use std::path::Path;
+
+// From Erigon: cl/persistence/format/chunk_encoding/chunks.go ChunkDataType
+const CHUNK_DATA_TYPE: u8 = 0;
+const CHUNK_PREFIX_LEN: usize = 8;
+
+/// Length of an SSZ-encoded (Deneb) `BlobSidecar`
+pub const BLOB_SIDECAR_SSZ_LEN: usize = 131_928;
+
+/// A beacon block, as stored in a `beaconblocks` segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct BeaconBlock {
+    /// Fork version the block was produced under (0 for phase0, 4 for Deneb)
+    pub version: u8,
+    pub body_root: B256,
+    /// SSZ encoding of the `SignedBeaconBlock`
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "alloy_primitives::serde_hex::serialize")
+    )]
+    pub ssz: Vec<u8>,
+}
+
+impl BeaconBlock {
+    /// Decode a `beaconblocks` segment word; empty words are missed slots
+    pub fn decode(word: &[u8]) -> Result<Option<Self>> {
+        if word.is_empty() {
+            return Ok(None);
+        }
+        let mut data = Vec::new();
+        snap::read::FrameDecoder::new(word).read_to_end(&mut data)?;
+        let Some((meta, chunk)) = data.split_at_checked(1 + 32 + CHUNK_PREFIX_LEN) else {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Beacon block word of {} bytes after snappy",
+                data.len()
+            )));
+        };
+        let version = meta[0];
+        let body_root = B256::from_slice(&meta[1..33]);
+        let mut prefix: [u8; CHUNK_PREFIX_LEN] = meta[33..].try_into().unwrap();
+        if prefix[0] != CHUNK_DATA_TYPE {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Beacon block chunk of type {}",
+                prefix[0]
+            )));
+        }
+        prefix[0] = 0;
+        let len = u64::from_be_bytes(prefix);
+        if len != chunk.len() as u64 {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Beacon block chunk of {} bytes, {} stored",
+                len,
+                chunk.len()
+            )));
+        }
+        Ok(Some(BeaconBlock {
+            version,
+            body_root,
+            ssz: chunk.to_vec(),
+        }))
+    }
+
+    /// Encode as a `beaconblocks` segment word
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut prefix = (self.ssz.len() as u64).to_be_bytes();
+        prefix[0] = CHUNK_DATA_TYPE;
+        let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+        std::io::Write::write_all(&mut encoder, &[self.version])?;
+        std::io::Write::write_all(&mut encoder, self.body_root.as_slice())?;
+        std::io::Write::write_all(&mut encoder, &prefix)?;
+        std::io::Write::write_all(&mut encoder, &self.ssz)?;
+        encoder
+            .into_inner()
+            .map_err(|e| SnapshotError::Io(e.into_error()))
+    }
+}
+
+#[cfg(feature = "caplin-types")]
+pub use types::*;
+
+#[cfg(feature = "caplin-types")]
+mod types {
+    use super::BeaconBlock;
+    use crate::snapshots::{Result, SnapshotError};
+    use alloy_primitives::B256;
+
+    /// Fork a beacon block was produced under
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum BeaconFork {
+        Phase0,
+        Altair,
+        Bellatrix,
+        Capella,
+        Deneb,
+        Electra,
+    }
+
+    impl BeaconFork {
+        // From Erigon: cl/clparams/version.go StateVersion
+        pub fn from_version(version: u8) -> Option<Self> {
+            Some(match version {
+                0 => BeaconFork::Phase0,
+                1 => BeaconFork::Altair,
+                2 => BeaconFork::Bellatrix,
+                3 => BeaconFork::Capella,
+                4 => BeaconFork::Deneb,
+                5 => BeaconFork::Electra,
+                _ => return None,
+            })
+        }
+    }
+
+    /// The fields of a `BeaconBlockHeader`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct BeaconBlockHeader {
+        pub slot: u64,
+        pub proposer_index: u64,
+        pub parent_root: B256,
+        pub state_root: B256,
+        pub body_root: B256,
+    }
+
+    /// A `SignedBeaconBlockHeader`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SignedBeaconBlockHeader {
+        pub message: BeaconBlockHeader,
+        pub signature: [u8; 96],
+    }
+
+    // Message offset, signature
+    const SIGNED_FIXED_LEN: usize = 4 + 96;
+    // Slot, proposer index, parent and state roots, body offset
+    const BLOCK_FIXED_LEN: usize = 8 + 8 + 32 + 32 + 4;
+
+    impl BeaconBlock {
+        /// The fork of [`BeaconBlock::version`], `None` for unknown versions
+        pub fn fork(&self) -> Option<BeaconFork> {
+            BeaconFork::from_version(self.version)
+        }
+
+        /// Decode the block's signed header
+        ///
+        /// Only the fixed part of the SSZ encoding is read; the body root
+        /// comes from the segment word rather than being hashed.
+        pub fn header(&self) -> Result<SignedBeaconBlockHeader> {
+            let ssz = &self.ssz;
+            if ssz.len() < SIGNED_FIXED_LEN {
+                return Err(SnapshotError::InvalidFormat(format!(
+                    "SignedBeaconBlock of {} bytes",
+                    ssz.len()
+                )));
+            }
+            let message_offset = u32::from_le_bytes(ssz[..4].try_into().unwrap()) as usize;
+            let signature = ssz[4..SIGNED_FIXED_LEN].try_into().unwrap();
+            let Some(message) = ssz
+                .get(message_offset..)
+                .filter(|message| message.len() >= BLOCK_FIXED_LEN)
+            else {
+                return Err(SnapshotError::InvalidFormat(format!(
+                    "BeaconBlock at offset {} of a {} byte SignedBeaconBlock",
+                    message_offset,
+                    ssz.len()
+                )));
+            };
+            Ok(SignedBeaconBlockHeader {
+                message: BeaconBlockHeader {
+                    slot: u64::from_le_bytes(message[..8].try_into().unwrap()),
+                    proposer_index: u64::from_le_bytes(message[8..16].try_into().unwrap()),
+                    parent_root: B256::from_slice(&message[16..48]),
+                    state_root: B256::from_slice(&message[48..80]),
+                    body_root: self.body_root,
+                },
+                signature,
+            })
+        }
+    }
+}
+
+// A segment with one word per slot and an optional slot-ordered enum index
+struct SlotSegment {
+    decompressor: Decompressor,
+}
+
+impl SlotSegment {
+    fn open(path: &Path, index: Option<RecSplitIndex>) -> Result<Self> {
+        let mut decompressor = Decompressor::new(path)?;
+        if let Some(idx) = index {
+            decompressor.attach_index(idx)?;
+        }
+        Ok(Self { decompressor })
+    }
+
+    fn open_with_sibling_index(path: &Path) -> Result<Self> {
+        let idx_path = path.with_extension("idx");
+        let index = if idx_path.exists() {
+            Some(RecSplitIndex::open(&idx_path)?)
+        } else {
+            None
+        };
+        Self::open(path, index)
+    }
+
+    fn first_slot(&self) -> Option<u64> {
+        self.decompressor.index().map(|idx| idx.base_data_id())
+    }
+
+    fn word_at_slot(&self, slot: u64) -> Result<Option<Vec<u8>>> {
+        let first_slot = self.first_slot().ok_or(SnapshotError::IndexNotAvailable)?;
+        Ok(slot
+            .checked_sub(first_slot)
+            .and_then(|i| self.decompressor.get_word(i)))
+    }
+}
+
+/// Reader for `beaconblocks` segments
+pub struct BeaconBlocksReader {
+    segment: SlotSegment,
+}
+
+impl BeaconBlocksReader {
+    /// Open a beacon blocks segment, along with the `.idx` next to it if any
+    pub fn new(path: &Path) -> Result<Self> {
+        Ok(Self {
+            segment: SlotSegment::open_with_sibling_index(path)?,
+        })
+    }
+
+    /// Open a beacon blocks segment with an explicitly given index (or none)
+    pub fn with_index(path: &Path, index: Option<RecSplitIndex>) -> Result<Self> {
+        Ok(Self {
+            segment: SlotSegment::open(path, index)?,
+        })
+    }
+
+    /// Number of slots in the segment, missed ones included
+    pub fn count(&self) -> usize {
+        self.segment.decompressor.count()
+    }
+
+    /// First slot of the segment, taken from the index
+    pub fn first_slot(&self) -> Option<u64> {
+        self.segment.first_slot()
+    }
+
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.segment.decompressor.index()
+    }
+
+    /// The block at `slot`, `None` if the slot was missed or is not in this
+    /// segment
+    ///
+    /// Needs an index attached.
+    pub fn block(&self, slot: u64) -> Result<Option<BeaconBlock>> {
+        match self.segment.word_at_slot(slot)? {
+            Some(word) => BeaconBlock::decode(&word),
+            None => Ok(None),
+        }
+    }
+
+    pub fn make_getter(&self) -> BeaconBlockGetter<'_> {
+        BeaconBlockGetter {
+            getter: self.segment.decompressor.make_getter(),
+        }
+    }
+}
+
+/// Iterator over the slots of a `beaconblocks` segment
+pub struct BeaconBlockGetter<'a> {
+    getter: Getter<'a>,
+}
+
+impl<'a> BeaconBlockGetter<'a> {
+    pub fn has_next(&self) -> bool {
+        self.getter.has_next()
+    }
+
+    /// Read the next slot's block, `None` for a missed slot
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<BeaconBlock>> {
+        let (word, _) = self.getter.next(Vec::new());
+        BeaconBlock::decode(&word)
+    }
+
+    /// Skip the next slot without decoding it
+    pub fn skip(&mut self) {
+        self.getter.skip();
+    }
+}
+
+/// Reader for `blobsidecars` segments
+pub struct BlobSidecarsReader {
+    segment: SlotSegment,
+}
+
+impl BlobSidecarsReader {
+    /// Open a blob sidecars segment, along with the `.idx` next to it if any
+    pub fn new(path: &Path) -> Result<Self> {
+        Ok(Self {
+            segment: SlotSegment::open_with_sibling_index(path)?,
+        })
+    }
+
+    /// Open a blob sidecars segment with an explicitly given index (or none)
+    pub fn with_index(path: &Path, index: Option<RecSplitIndex>) -> Result<Self> {
+        Ok(Self {
+            segment: SlotSegment::open(path, index)?,
+        })
+    }
+
+    /// Number of slots in the segment
+    pub fn count(&self) -> usize {
+        self.segment.decompressor.count()
+    }
+
+    /// First slot of the segment, taken from the index
+    pub fn first_slot(&self) -> Option<u64> {
+        self.segment.first_slot()
+    }
+
+    /// The SSZ-encoded sidecars of `slot`, in blob index order
+    ///
+    /// Needs an index attached.
+    pub fn sidecars(&self, slot: u64) -> Result<Vec<Vec<u8>>> {
+        match self.segment.word_at_slot(slot)? {
+            Some(word) => split_sidecars(&word),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn make_getter(&self) -> BlobSidecarGetter<'_> {
+        BlobSidecarGetter {
+            getter: self.segment.decompressor.make_getter(),
+        }
+    }
+}
+
+fn split_sidecars(word: &[u8]) -> Result<Vec<Vec<u8>>> {
+    if !word.len().is_multiple_of(BLOB_SIDECAR_SSZ_LEN) {
+        return Err(SnapshotError::InvalidFormat(format!(
+            "Blob sidecars word of {} bytes is not a multiple of {}",
+            word.len(),
+            BLOB_SIDECAR_SSZ_LEN
+        )));
+    }
+    Ok(word
+        .chunks(BLOB_SIDECAR_SSZ_LEN)
+        .map(<[u8]>::to_vec)
+        .collect())
+}
+
+/// Iterator over the slots of a `blobsidecars` segment
+pub struct BlobSidecarGetter<'a> {
+    getter: Getter<'a>,
+}
+
+impl<'a> BlobSidecarGetter<'a> {
+    pub fn has_next(&self) -> bool {
+        self.getter.has_next()
+    }
+
+    /// Read the next slot's SSZ-encoded sidecars
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Vec<Vec<u8>>> {
+        let (word, _) = self.getter.next(Vec::new());
+        split_sidecars(&word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::recsplit::RecSplit;
+    use crate::testutil::write_segment;
+    use std::path::PathBuf;
+
+    const FIRST_SLOT: u64 = 8_000_000;
+
+    // Compress `words` and build the slot index next to them
+    fn write_slot_segment(path: &Path, words: &[Vec<u8>]) {
+        write_segment(path, words).unwrap();
+
+        let decompressor = Decompressor::new(path).unwrap();
+        let mut getter = decompressor.make_getter();
+        let mut rs = RecSplit::builder(path.with_extension("idx"), words.len())
+            .enums(true)
+            .base_data_id(FIRST_SLOT)
+            .fsync(false)
+            .build()
+            .unwrap();
+        let mut offset = 0;
+        for i in 0..words.len() as u64 {
+            let mut key = Vec::new();
+            let mut n = i;
+            while n >= 0x80 {
+                key.push(n as u8 | 0x80);
+                n >>= 7;
+            }
+            key.push(n as u8);
+            rs.add_key(&key, offset).unwrap();
+            offset = getter.skip().0;
+        }
+        rs.build().unwrap();
+    }
+
+    // A SignedBeaconBlock with an empty-ish body
+    fn signed_block_ssz(slot: u64) -> Vec<u8> {
+        let mut ssz = 100u32.to_le_bytes().to_vec();
+        ssz.extend_from_slice(&[0xaa; 96]);
+        ssz.extend_from_slice(&slot.to_le_bytes());
+        ssz.extend_from_slice(&(slot % 7).to_le_bytes());
+        ssz.extend_from_slice(&[slot as u8; 32]);
+        ssz.extend_from_slice(&[0x22; 32]);
+        ssz.extend_from_slice(&84u32.to_le_bytes());
+        ssz.extend_from_slice(&[0x33; 200]);
+        ssz
+    }
+
+    // Blocks for 12 slots, every fourth one missed
+    fn blocks() -> Vec<Option<BeaconBlock>> {
+        (0..12u64)
+            .map(|i| {
+                (i % 4 != 3).then(|| BeaconBlock {
+                    version: 4,
+                    body_root: B256::with_last_byte(i as u8),
+                    ssz: signed_block_ssz(FIRST_SLOT + i),
+                })
+            })
+            .collect()
+    }
+
+    fn write_blocks(dir: &Path) -> PathBuf {
+        let path = dir.join("v1-008000-008001-beaconblocks.seg");
+        let words: Vec<Vec<u8>> = blocks()
+            .iter()
+            .map(|block| block.as_ref().map_or(Vec::new(), |b| b.encode().unwrap()))
+            .collect();
+        write_slot_segment(&path, &words);
+        path
+    }
+
+    #[test]
+    fn test_beacon_blocks_reader() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = write_blocks(tmp_dir.path());
+        let expected = blocks();
+
+        let reader = BeaconBlocksReader::new(&path).unwrap();
+        assert_eq!(reader.count(), expected.len());
+        assert_eq!(reader.first_slot(), Some(FIRST_SLOT));
+        for (i, block) in expected.iter().enumerate() {
+            assert_eq!(&reader.block(FIRST_SLOT + i as u64).unwrap(), block);
+        }
+        assert_eq!(reader.block(FIRST_SLOT - 1).unwrap(), None);
+        assert_eq!(reader.block(FIRST_SLOT + 12).unwrap(), None);
+
+        let mut getter = reader.make_getter();
+        for block in &expected {
+            assert_eq!(&getter.next().unwrap(), block);
+        }
+        assert!(!getter.has_next());
+
+        let unindexed = BeaconBlocksReader::with_index(&path, None).unwrap();
+        assert!(matches!(
+            unindexed.block(FIRST_SLOT),
+            Err(SnapshotError::IndexNotAvailable)
+        ));
+    }
+
+    #[test]
+    fn test_beacon_block_word_errors() {
+        let block = BeaconBlock {
+            version: 3,
+            body_root: B256::ZERO,
+            ssz: vec![1, 2, 3],
+        };
+        let word = block.encode().unwrap();
+        assert_eq!(BeaconBlock::decode(&word).unwrap(), Some(block));
+        assert_eq!(BeaconBlock::decode(&[]).unwrap(), None);
+        assert!(matches!(
+            BeaconBlock::decode(&word[..word.len() - 1]),
+            Err(SnapshotError::Io(_))
+        ));
+        assert!(matches!(
+            BeaconBlock::decode(b"not snappy"),
+            Err(SnapshotError::Io(_))
+        ));
+
+        // Valid snappy holding too little to be a block
+        let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+        std::io::Write::write_all(&mut encoder, &[4; 20]).unwrap();
+        assert!(matches!(
+            BeaconBlock::decode(&encoder.into_inner().unwrap()),
+            Err(SnapshotError::InvalidFormat(_))
+        ));
+    }
+
+    #[cfg(feature = "caplin-types")]
+    #[test]
+    fn test_beacon_block_header() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let reader = BeaconBlocksReader::new(&write_blocks(tmp_dir.path())).unwrap();
+
+        let block = reader.block(FIRST_SLOT + 5).unwrap().unwrap();
+        assert_eq!(block.fork(), Some(BeaconFork::Deneb));
+        let header = block.header().unwrap();
+        assert_eq!(header.signature, [0xaa; 96]);
+        assert_eq!(
+            header.message,
+            BeaconBlockHeader {
+                slot: FIRST_SLOT + 5,
+                proposer_index: (FIRST_SLOT + 5) % 7,
+                parent_root: B256::repeat_byte((FIRST_SLOT + 5) as u8),
+                state_root: B256::repeat_byte(0x22),
+                body_root: B256::with_last_byte(5),
+            }
+        );
+
+        let truncated = BeaconBlock {
+            ssz: block.ssz[..150].to_vec(),
+            ..block
+        };
+        assert!(matches!(
+            truncated.header(),
+            Err(SnapshotError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_blob_sidecars_reader() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("v1-008000-008001-blobsidecars.seg");
+        // Blob bytes from an LCG, so the sidecars do not all look alike
+        let sidecar = |slot: u64, index: u64| {
+            let mut state = slot * 31 + index;
+            let mut ssz: Vec<u8> = (0..BLOB_SIDECAR_SSZ_LEN)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    (state >> 56) as u8
+                })
+                .collect();
+            ssz[..8].copy_from_slice(&index.to_le_bytes());
+            ssz
+        };
+        // Slot i carries i % 3 blobs
+        let words: Vec<Vec<u8>> = (0..4u64)
+            .map(|i| (0..i % 3).flat_map(|index| sidecar(i, index)).collect())
+            .collect();
+        write_slot_segment(&path, &words);
+
+        let reader = BlobSidecarsReader::new(&path).unwrap();
+        assert_eq!(reader.count(), 4);
+        assert!(reader.sidecars(FIRST_SLOT).unwrap().is_empty());
+        assert_eq!(
+            reader.sidecars(FIRST_SLOT + 2).unwrap(),
+            [sidecar(2, 0), sidecar(2, 1)]
+        );
+        assert!(reader.sidecars(FIRST_SLOT + 4).unwrap().is_empty());
+
+        let mut getter = reader.make_getter();
+        let counts: Vec<usize> =
+            std::iter::from_fn(|| getter.has_next().then(|| getter.next().unwrap().len()))
+                .collect();
+        assert_eq!(counts, [0, 1, 2, 0]);
+
+        assert!(matches!(
+            split_sidecars(&[0; 10]),
+            Err(SnapshotError::InvalidFormat(_))
+        ));
+    }
+}
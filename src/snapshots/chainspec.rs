@@ -0,0 +1,294 @@
+//! Fork schedules of the chains Erigon serves
+//!
+//! Each fork adds fields to the header: London the base fee, Shanghai the
+//! withdrawals root, Cancun the blob gas fields and the parent beacon block
+//! root, Prague the requests hash. [`validate_header`] checks a header has
+//! exactly the fields of the forks active at its number and timestamp, which
+//! catches segments of another chain, or decoded with the wrong format,
+//! before any hash is compared.
+//!
+//! Activation data from the chain configs of go-ethereum (mainnet, sepolia,
+//! holesky), Erigon (gnosis) and bor (polygon).
+
+use crate::snapshots::reader::ChainValidation;
+use crate::snapshots::{ChainViolation, Result, SnapshotError};
+use alloy_consensus::Header;
+
+// From go-ethereum: params/protocol_params.go BlobTxBlobGasPerBlob
+const BLOB_GAS_PER_BLOB: u64 = 1 << 17;
+
+/// A chain and when its forks activate
+///
+/// London activates at a block number, the forks after the merge at a
+/// timestamp; `None` for a fork the chain never takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainSpec {
+    pub name: &'static str,
+    pub chain_id: u64,
+    pub london_block: Option<u64>,
+    pub shanghai_time: Option<u64>,
+    pub cancun_time: Option<u64>,
+    pub prague_time: Option<u64>,
+}
+
+impl ChainSpec {
+    pub const MAINNET: Self = Self {
+        name: "mainnet",
+        chain_id: 1,
+        london_block: Some(12_965_000),
+        shanghai_time: Some(1_681_338_455),
+        cancun_time: Some(1_710_338_135),
+        prague_time: Some(1_746_612_311),
+    };
+
+    pub const SEPOLIA: Self = Self {
+        name: "sepolia",
+        chain_id: 11_155_111,
+        london_block: Some(1_735_371),
+        shanghai_time: Some(1_677_557_088),
+        cancun_time: Some(1_706_655_072),
+        prague_time: Some(1_741_159_776),
+    };
+
+    pub const HOLESKY: Self = Self {
+        name: "holesky",
+        chain_id: 17_000,
+        london_block: Some(0),
+        shanghai_time: Some(1_696_000_704),
+        cancun_time: Some(1_707_305_664),
+        prague_time: Some(1_740_434_112),
+    };
+
+    pub const GNOSIS: Self = Self {
+        name: "gnosis",
+        chain_id: 100,
+        london_block: Some(19_040_000),
+        shanghai_time: Some(1_690_889_660),
+        cancun_time: Some(1_710_181_820),
+        prague_time: Some(1_746_021_820),
+    };
+
+    /// Polygon PoS: bor forks to London but its headers never carry
+    /// withdrawals nor Cancun fields
+    pub const POLYGON: Self = Self {
+        name: "polygon",
+        chain_id: 137,
+        london_block: Some(23_850_000),
+        shanghai_time: None,
+        cancun_time: None,
+        prague_time: None,
+    };
+
+    pub const ALL: [Self; 5] = [
+        Self::MAINNET,
+        Self::SEPOLIA,
+        Self::HOLESKY,
+        Self::GNOSIS,
+        Self::POLYGON,
+    ];
+
+    /// The chain named `name` as Erigon names it, e.g. in `--chain`
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|spec| spec.name == name)
+    }
+
+    pub fn by_chain_id(chain_id: u64) -> Option<Self> {
+        Self::ALL.into_iter().find(|spec| spec.chain_id == chain_id)
+    }
+
+    /// The checks of consecutive headers of this chain
+    pub fn validation(&self) -> ChainValidation {
+        ChainValidation {
+            london_block: self.london_block,
+        }
+    }
+
+    pub fn is_london(&self, number: u64) -> bool {
+        self.london_block.is_some_and(|block| number >= block)
+    }
+
+    pub fn is_shanghai(&self, timestamp: u64) -> bool {
+        self.shanghai_time.is_some_and(|time| timestamp >= time)
+    }
+
+    pub fn is_cancun(&self, timestamp: u64) -> bool {
+        self.cancun_time.is_some_and(|time| timestamp >= time)
+    }
+
+    pub fn is_prague(&self, timestamp: u64) -> bool {
+        self.prague_time.is_some_and(|time| timestamp >= time)
+    }
+
+    // The first fork-dependent field `header` has without its fork or lacks
+    // with it
+    fn check(&self, header: &Header) -> Option<ChainViolation> {
+        let time = header.timestamp;
+        let fields = [
+            (
+                "base fee",
+                "London",
+                self.is_london(header.number),
+                header.base_fee_per_gas.is_some(),
+            ),
+            (
+                "withdrawals root",
+                "Shanghai",
+                self.is_shanghai(time),
+                header.withdrawals_root.is_some(),
+            ),
+            (
+                "blob gas used",
+                "Cancun",
+                self.is_cancun(time),
+                header.blob_gas_used.is_some(),
+            ),
+            (
+                "excess blob gas",
+                "Cancun",
+                self.is_cancun(time),
+                header.excess_blob_gas.is_some(),
+            ),
+            (
+                "parent beacon block root",
+                "Cancun",
+                self.is_cancun(time),
+                header.parent_beacon_block_root.is_some(),
+            ),
+            (
+                "requests hash",
+                "Prague",
+                self.is_prague(time),
+                header.requests_hash.is_some(),
+            ),
+        ];
+        for (field, fork, active, present) in fields {
+            match (active, present) {
+                (true, false) => return Some(ChainViolation::MissingField { field, fork }),
+                (false, true) => return Some(ChainViolation::UnexpectedField { field, fork }),
+                _ => {}
+            }
+        }
+        header
+            .blob_gas_used
+            .filter(|used| used % BLOB_GAS_PER_BLOB != 0)
+            .map(ChainViolation::BlobGasUsed)
+    }
+}
+
+/// Check `header` has the fields of the forks of `spec` active at its
+/// number and timestamp, and no others
+///
+/// Fails with [`SnapshotError::InvalidHeader`]. Field values are not
+/// checked against the parent; see [`ChainValidation`] for that.
+pub fn validate_header(header: &Header, spec: &ChainSpec) -> Result<()> {
+    match spec.check(header) {
+        Some(violation) => Err(SnapshotError::InvalidHeader {
+            number: header.number,
+            hash: header.hash_slow(),
+            violation,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::B256;
+
+    #[test]
+    fn test_validate_header() {
+        let spec = ChainSpec::MAINNET;
+        let frontier = Header {
+            number: 1,
+            timestamp: 1_438_269_988,
+            ..Default::default()
+        };
+        let london = Header {
+            number: 12_965_000,
+            timestamp: 1_628_166_822,
+            base_fee_per_gas: Some(1_000_000_000),
+            ..Default::default()
+        };
+        let shanghai = Header {
+            number: 17_034_870,
+            timestamp: 1_681_338_455,
+            withdrawals_root: Some(B256::ZERO),
+            ..london.clone()
+        };
+        let cancun = Header {
+            number: 19_426_587,
+            timestamp: 1_710_338_135,
+            blob_gas_used: Some(2 * BLOB_GAS_PER_BLOB),
+            excess_blob_gas: Some(0),
+            parent_beacon_block_root: Some(B256::ZERO),
+            ..shanghai.clone()
+        };
+        for header in [&frontier, &london, &shanghai, &cancun] {
+            validate_header(header, &spec).unwrap();
+        }
+
+        let violation = |header: &Header| match validate_header(header, &spec) {
+            Err(SnapshotError::InvalidHeader { violation, .. }) => Some(violation),
+            Ok(()) => None,
+            Err(e) => panic!("{}", e),
+        };
+        // The block before London with a base fee, and London without
+        let early = Header {
+            number: 12_964_999,
+            ..london.clone()
+        };
+        assert_eq!(
+            violation(&early),
+            Some(ChainViolation::UnexpectedField {
+                field: "base fee",
+                fork: "London"
+            })
+        );
+        let no_base_fee = Header {
+            base_fee_per_gas: None,
+            ..london.clone()
+        };
+        assert_eq!(
+            violation(&no_base_fee),
+            Some(ChainViolation::MissingField {
+                field: "base fee",
+                fork: "London"
+            })
+        );
+        // A Cancun header a second before Cancun
+        let early = Header {
+            timestamp: 1_710_338_134,
+            ..cancun.clone()
+        };
+        assert_eq!(
+            violation(&early),
+            Some(ChainViolation::UnexpectedField {
+                field: "blob gas used",
+                fork: "Cancun"
+            })
+        );
+        let partial_blob = Header {
+            blob_gas_used: Some(BLOB_GAS_PER_BLOB + 1),
+            ..cancun.clone()
+        };
+        assert_eq!(
+            violation(&partial_blob),
+            Some(ChainViolation::BlobGasUsed(BLOB_GAS_PER_BLOB + 1))
+        );
+        // A Shanghai header is foreign to polygon
+        let bor = Header {
+            number: 60_000_000,
+            ..shanghai.clone()
+        };
+        assert!(validate_header(&bor, &ChainSpec::POLYGON)
+            .unwrap_err()
+            .to_string()
+            .contains("withdrawals root before Shanghai"));
+
+        assert_eq!(ChainSpec::by_name("gnosis"), Some(ChainSpec::GNOSIS));
+        assert_eq!(ChainSpec::by_chain_id(17_000), Some(ChainSpec::HOLESKY));
+        assert_eq!(ChainSpec::by_name("ropsten"), None);
+        assert_eq!(ChainSpec::MAINNET.validation(), ChainValidation::MAINNET);
+    }
+}
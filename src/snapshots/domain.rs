@@ -0,0 +1,622 @@
+use crate::decompress::Decompressor;
+use crate::error::IndexError;
+use crate::seg_reader::{detect_compress_type, FileCompression, Reader, SegmentReader};
+use crate::snapshots::btree::BtIndex;
+use crate::snapshots::existence::ExistenceFilter;
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::snapshots::salt::{read_salt, SaltKind};
+use crate::snapshots::{Result, SnapshotError};
+use std::path::Path;
+
+/// Reader for Erigon 3 domain files (`accounts.0-64.kv`, `storage.0-64.kv`, ...)
+///
+/// A `.kv` file is a seg file of alternating key and value words, sorted by
+/// key. Keys and values are compressed independently of each other, as given
+/// by the domain's [`FileCompression`]. Lookups go through the accessors next
+/// to the file when there are any: the `.kvi` RecSplit index mapping each key
+/// to the offset of its key word, or the `.bt` B-tree index, which also
+/// serves [`DomainReader::seek`] and where [`DomainReader::range`] and
+/// [`DomainReader::scan_prefix`] start. Without them the file is scanned.
+/// A `.kvei` existence filter, when there is one, turns away absent keys
+/// before any of that.
+pub struct DomainReader {
+    segment: SegmentReader,
+    // Key-to-offset RecSplit index (`.kvi`), if one was found
+    index: Option<RecSplitIndex>,
+    // B-tree index (`.bt`), if one was found
+    bt_index: Option<BtIndex>,
+    // Existence filter (`.kvei`) and the salt its keys were hashed with
+    existence: Option<(ExistenceFilter, u32)>,
+}
+
+impl DomainReader {
+    /// Open a domain file, detecting its compression from the first words
+    ///
+    /// Detection is a heuristic; prefer [`DomainReader::with_compression`]
+    /// when the domain's compression is known. `.kvi`, `.bt` and `.kvei`
+    /// files with the same stem next to the file are opened as well.
+    pub fn new(path: &Path) -> Result<Self> {
+        let decompressor = Decompressor::new(path)?;
+        let compression = detect_compress_type(&decompressor);
+        drop(decompressor);
+        Self::with_compression(path, compression)
+    }
+
+    /// Open a domain file whose keys/values are compressed as `compression`
+    pub fn with_compression(path: &Path, compression: FileCompression) -> Result<Self> {
+        let segment = Self::open_segment(path, compression)?;
+        let idx_path = path.with_extension("kvi");
+        let index = if idx_path.exists() {
+            Some(RecSplitIndex::open(&idx_path)?)
+        } else {
+            None
+        };
+        let bt_path = path.with_extension("bt");
+        let bt_index = if bt_path.exists() {
+            Some(BtIndex::open(&bt_path, &segment)?)
+        } else {
+            None
+        };
+        let mut reader = Self::from_parts(segment, index, bt_index)?;
+        let filter_path = path.with_extension("kvei");
+        if filter_path.exists() {
+            match reader.state_salt(path)? {
+                Some(salt) => reader.attach_existence(ExistenceFilter::open(&filter_path)?, salt),
+                None => tracing::debug!(
+                    "No state salt for {}, ignoring its existence filter",
+                    path.display()
+                ),
+            }
+        }
+        Ok(reader)
+    }
+
+    // The salt `.kvei` keys are hashed with: the `.kvi` index's, or the
+    // datadir's `salt-state.txt` next to the domain directory
+    fn state_salt(&self, path: &Path) -> Result<Option<u32>> {
+        if let Some(idx) = &self.index {
+            return Ok(Some(idx.salt()));
+        }
+        for dir in path.ancestors().skip(1).take(2) {
+            if let Some(salt) = read_salt(dir, SaltKind::State)? {
+                return Ok(Some(salt));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Open a domain file with an explicitly given `.kvi` index (or none)
+    pub fn with_index(
+        path: &Path,
+        compression: FileCompression,
+        index: Option<RecSplitIndex>,
+    ) -> Result<Self> {
+        Self::from_parts(Self::open_segment(path, compression)?, index, None)
+    }
+
+    fn open_segment(path: &Path, compression: FileCompression) -> Result<SegmentReader> {
+        let segment = SegmentReader::new(path, compression)?;
+        if !segment.count().is_multiple_of(2) {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Domain file {} has an odd number of words ({})",
+                path.display(),
+                segment.count()
+            )));
+        }
+        Ok(segment)
+    }
+
+    fn from_parts(
+        segment: SegmentReader,
+        index: Option<RecSplitIndex>,
+        bt_index: Option<BtIndex>,
+    ) -> Result<Self> {
+        if let Some(idx) = &index {
+            if idx.is_enum() {
+                return Err(IndexError::WrongKind {
+                    file: segment.file_name().to_string(),
+                    is_enum: true,
+                }
+                .into());
+            }
+            if idx.key_count() != (segment.count() / 2) as u64 {
+                return Err(IndexError::KeyCountMismatch {
+                    file: segment.file_name().to_string(),
+                    expected: (segment.count() / 2) as u64,
+                    actual: idx.key_count(),
+                }
+                .into());
+            }
+        }
+        Ok(Self {
+            segment,
+            index,
+            bt_index,
+            existence: None,
+        })
+    }
+
+    /// Number of key/value pairs in the file
+    pub fn count(&self) -> usize {
+        self.segment.count() / 2
+    }
+
+    /// How keys and values are compressed
+    pub fn compression(&self) -> FileCompression {
+        self.segment.compression()
+    }
+
+    /// The `.kvi` index, if one is attached
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.index.as_ref()
+    }
+
+    /// The `.bt` index, if one is attached
+    pub fn bt_index(&self) -> Option<&BtIndex> {
+        self.bt_index.as_ref()
+    }
+
+    /// Check lookups against the `.kvei` existence filter of the file,
+    /// whose keys were hashed with `salt`
+    pub fn attach_existence(&mut self, filter: ExistenceFilter, salt: u32) {
+        self.existence = Some((filter, salt));
+    }
+
+    /// The `.kvei` existence filter, if one is attached
+    pub fn existence_filter(&self) -> Option<&ExistenceFilter> {
+        self.existence.as_ref().map(|(filter, _)| filter)
+    }
+
+    /// `false` if `key` is certainly not in the file, as told by the
+    /// existence filter, or the `.kvi` index's own filter
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        !self.filtered_out(key) && self.index.as_ref().is_none_or(|idx| idx.may_contain(key))
+    }
+
+    fn filtered_out(&self, key: &[u8]) -> bool {
+        self.existence
+            .as_ref()
+            .is_some_and(|(filter, salt)| !filter.may_contain(key, *salt))
+    }
+
+    /// Get the value stored for `key`, or `None` if the file does not have it
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if self.filtered_out(key) {
+            return Ok(None);
+        }
+        match (&self.index, &self.bt_index) {
+            (Some(idx), _) => self.get_indexed(idx, key),
+            (None, Some(bt)) => bt.get(&self.segment, key),
+            (None, None) => Ok(self
+                .seek_scan(key)?
+                .filter(|(found, _)| found == key)
+                .map(|(_, value)| value)),
+        }
+    }
+
+    /// Find the first key/value pair whose key is `>= key`
+    pub fn seek(&self, key: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match &self.bt_index {
+            Some(bt) => Ok(bt
+                .seek(&self.segment, key)?
+                .map(|entry| (entry.key, entry.value))),
+            None => self.seek_scan(key),
+        }
+    }
+
+    /// The key/value pairs with keys in `start..end` (to the last key if
+    /// `end` is `None`), in key order
+    ///
+    /// The `.bt` index finds where the range starts, from there on the pairs
+    /// are streamed from the file.
+    pub fn range(&self, start: &[u8], end: Option<&[u8]>) -> Result<DomainRange<'_>> {
+        let mut reader = self.segment.make_reader();
+        let mut done = false;
+        match &self.bt_index {
+            Some(bt) => match bt.seek(&self.segment, start)? {
+                Some(entry) => reader.reset(entry.offset),
+                None => done = true,
+            },
+            None => {
+                tracing::debug!(
+                    "No domain B-tree index, scanning for {}",
+                    hex::encode(start)
+                );
+                let mut offset = 0;
+                let mut key = Vec::new();
+                while reader.has_next() {
+                    key.clear();
+                    (key, _) = reader.next(key);
+                    if key.as_slice() >= start {
+                        break;
+                    }
+                    offset = reader.skip().0;
+                }
+                reader.reset(offset);
+            }
+        }
+        Ok(DomainRange {
+            reader,
+            end: end.map(<[u8]>::to_vec),
+            key: Vec::new(),
+            value: Vec::new(),
+            done,
+        })
+    }
+
+    /// Call `f` with every key/value pair whose key starts with `prefix`, in
+    /// key order, until it returns `false`
+    ///
+    /// As keys are sorted the pairs are contiguous, so only they are read,
+    /// e.g. the storage slots of one contract with its address as `prefix`.
+    /// Returns the number of pairs `f` was called with.
+    pub fn scan_prefix(
+        &self,
+        prefix: &[u8],
+        mut f: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<u64> {
+        let end = prefix_end(prefix);
+        let mut range = self.range(prefix, end.as_deref())?;
+        let mut count = 0;
+        while range.read_next()? {
+            count += 1;
+            if !f(&range.key, &range.value) {
+                break;
+            }
+        }
+        Ok(count)
+    }
+
+    fn get_indexed(&self, idx: &RecSplitIndex, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        // A perfect hash maps unknown keys somewhere too, so the key we land
+        // on is checked against the requested one
+        let Some(offset) = idx.lookup(key) else {
+            return Ok(None);
+        };
+        let mut reader = self.segment.make_reader();
+        reader.reset(offset);
+        if !reader.has_next() {
+            return Ok(None);
+        }
+        let (found, _) = reader.next(Vec::new());
+        if found != key {
+            return Ok(None);
+        }
+        if !reader.has_next() {
+            return Err(SnapshotError::UnexpectedEof {
+                context: format!("value of key {}", hex::encode(key)),
+            });
+        }
+        Ok(Some(reader.next(Vec::new()).0))
+    }
+
+    fn seek_scan(&self, key: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        tracing::debug!("No domain B-tree index, scanning for {}", hex::encode(key));
+        let mut reader = self.segment.make_reader();
+        while reader.has_next() {
+            let (found, _) = reader.next(Vec::new());
+            if found.as_slice() < key {
+                reader.skip();
+                continue;
+            }
+            // Keys are sorted, so this is the first one not below `key`
+            if !reader.has_next() {
+                return Err(SnapshotError::UnexpectedEof {
+                    context: format!("value of key {}", hex::encode(&found)),
+                });
+            }
+            let (value, _) = reader.next(Vec::new());
+            return Ok(Some((found, value)));
+        }
+        Ok(None)
+    }
+}
+
+/// Iterator over the key/value pairs of a range of keys, created by
+/// [`DomainReader::range`]
+///
+/// Once an item is an error the iterator is done.
+pub struct DomainRange<'a> {
+    reader: Reader<'a>,
+    // First key past the range
+    end: Option<Vec<u8>>,
+    // The pair read last, buffers reused from one pair to the next
+    key: Vec<u8>,
+    value: Vec<u8>,
+    done: bool,
+}
+
+impl DomainRange<'_> {
+    // Read the next pair into `key` and `value`, false past the range
+    fn read_next(&mut self) -> Result<bool> {
+        if self.done || !self.reader.has_next() {
+            self.done = true;
+            return Ok(false);
+        }
+        self.key.clear();
+        (self.key, _) = self.reader.next(std::mem::take(&mut self.key));
+        if self.end.as_ref().is_some_and(|end| self.key >= *end) {
+            self.done = true;
+            return Ok(false);
+        }
+        if !self.reader.has_next() {
+            self.done = true;
+            return Err(SnapshotError::UnexpectedEof {
+                context: format!("value of key {}", hex::encode(&self.key)),
+            });
+        }
+        self.value.clear();
+        (self.value, _) = self.reader.next(std::mem::take(&mut self.value));
+        Ok(true)
+    }
+}
+
+impl Iterator for DomainRange<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_next() {
+            Ok(true) => Some(Ok((self.key.clone(), self.value.clone()))),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+// Smallest key above every key starting with `prefix`, `None` if there is
+// none (an empty prefix or one of only 0xff bytes)
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&b| b != 0xff)?;
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+    Some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::Compressor;
+    use crate::snapshots::recsplit::{build_elias_fano32, RecSplit};
+    use std::path::PathBuf;
+
+    // Sorted account-like keys with values of varying length
+    fn pairs(n: u32) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..n)
+            .map(|i| {
+                let mut key = vec![0xaa; 16];
+                key.extend_from_slice(&(i * 3).to_be_bytes());
+                let value = vec![(i % 7) as u8; (i % 40) as usize];
+                (key, value)
+            })
+            .collect()
+    }
+
+    fn write_domain(
+        dir: &Path,
+        pairs: &[(Vec<u8>, Vec<u8>)],
+        compression: FileCompression,
+        accessor: Option<&str>,
+    ) -> PathBuf {
+        let kv_path = dir.join("v1-accounts.0-64.kv");
+        let mut compressor = Compressor::builder(&kv_path).fsync(false).build().unwrap();
+        let mut add = |word: &[u8], flag: FileCompression| {
+            if compression.contains(flag) {
+                compressor.add_word(word).unwrap();
+            } else {
+                compressor.add_uncompressed_word(word).unwrap();
+            }
+        };
+        for (key, value) in pairs {
+            add(key, FileCompression::Keys);
+            add(value, FileCompression::Vals);
+        }
+        compressor.compress().unwrap();
+
+        let segment = SegmentReader::new(&kv_path, compression).unwrap();
+        let mut reader = segment.make_reader();
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        while reader.has_next() {
+            offsets.push(offset);
+            reader.skip();
+            offset = reader.skip().0;
+        }
+
+        match accessor {
+            Some("kvi") => {
+                let mut rs = RecSplit::builder(kv_path.with_extension("kvi"), pairs.len())
+                    .bucket_size(100)
+                    .less_false_positives(true)
+                    .salt(7)
+                    .fsync(false)
+                    .build()
+                    .unwrap();
+                for ((key, _), &offset) in pairs.iter().zip(&offsets) {
+                    rs.add_key(key, offset).unwrap();
+                }
+                rs.build().unwrap();
+            }
+            Some("kvei") => {
+                let salt =
+                    crate::snapshots::salt::read_or_create_salt(dir, SaltKind::State).unwrap();
+                let mut filter = ExistenceFilter::new(pairs.len() as u64);
+                for (key, _) in pairs {
+                    filter.add_hash(crate::snapshots::recsplit::hash_key(key, salt).0);
+                }
+                filter
+                    .write(&kv_path.with_extension("kvei"), false)
+                    .unwrap();
+            }
+            Some("bt") => {
+                // Without encoded nodes, sampled on open
+                let data = build_elias_fano32(&offsets, *offsets.last().unwrap());
+                std::fs::write(kv_path.with_extension("bt"), data).unwrap();
+            }
+            _ => {}
+        }
+        kv_path
+    }
+
+    fn check_lookups(reader: &DomainReader, pairs: &[(Vec<u8>, Vec<u8>)]) {
+        assert_eq!(reader.count(), pairs.len());
+        for (key, value) in pairs {
+            assert_eq!(reader.get(key).unwrap().as_ref(), Some(value));
+        }
+        // Absent keys: between, before and after the stored ones
+        let mut between = pairs[10].0.clone();
+        *between.last_mut().unwrap() += 1;
+        for key in [between, vec![0x00; 20], vec![0xff; 20], Vec::new()] {
+            assert_eq!(reader.get(&key).unwrap(), None);
+        }
+    }
+
+    fn check_seeks(reader: &DomainReader, pairs: &[(Vec<u8>, Vec<u8>)]) {
+        let mut between = pairs[10].0.clone();
+        *between.last_mut().unwrap() += 1;
+        assert_eq!(reader.seek(&between).unwrap().as_ref(), Some(&pairs[11]));
+        assert_eq!(
+            reader.seek(&pairs[10].0).unwrap().as_ref(),
+            Some(&pairs[10])
+        );
+        assert_eq!(reader.seek(&[]).unwrap().as_ref(), Some(&pairs[0]));
+        assert_eq!(reader.seek(&[0xff; 20]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_get_with_existence_filter() {
+        let pairs = pairs(500);
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let kv_path = write_domain(tmp_dir.path(), &pairs, FileCompression::Keys, Some("kvei"));
+        let reader = DomainReader::with_compression(&kv_path, FileCompression::Keys).unwrap();
+        assert_eq!(
+            reader.existence_filter().map(ExistenceFilter::key_count),
+            Some(500)
+        );
+        check_lookups(&reader, &pairs);
+        assert!(pairs.iter().all(|(key, _)| reader.may_contain(key)));
+        let rejected = (0..1000u32)
+            .filter(|i| !reader.may_contain(&i.to_be_bytes()))
+            .count();
+        assert!(rejected > 950, "{} rejected", rejected);
+    }
+
+    #[test]
+    fn test_get_with_kvi_index() {
+        let pairs = pairs(500);
+        for compression in [
+            FileCompression::None,
+            FileCompression::Keys,
+            FileCompression::Vals,
+            FileCompression::Keys | FileCompression::Vals,
+        ] {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let kv_path = write_domain(tmp_dir.path(), &pairs, compression, Some("kvi"));
+            let reader = DomainReader::with_compression(&kv_path, compression).unwrap();
+            assert!(reader.index().is_some(), "{}", compression);
+            check_lookups(&reader, &pairs);
+        }
+    }
+
+    #[test]
+    fn test_get_without_index() {
+        // Every lookup is a scan, keep the file small
+        let pairs = pairs(50);
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let compression = FileCompression::Keys;
+        let kv_path = write_domain(tmp_dir.path(), &pairs, compression, None);
+
+        let reader = DomainReader::with_compression(&kv_path, compression).unwrap();
+        assert!(reader.index().is_none());
+        check_lookups(&reader, &pairs);
+        check_seeks(&reader, &pairs);
+    }
+
+    #[test]
+    fn test_get_and_seek_with_bt_index() {
+        let pairs = pairs(500);
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let compression = FileCompression::Vals;
+        let kv_path = write_domain(tmp_dir.path(), &pairs, compression, Some("bt"));
+
+        let reader = DomainReader::with_compression(&kv_path, compression).unwrap();
+        assert!(reader.index().is_none());
+        assert_eq!(reader.bt_index().map(BtIndex::node_count), Some(2));
+        check_lookups(&reader, &pairs);
+        check_seeks(&reader, &pairs);
+    }
+
+    // Storage-like keys: 20-byte addresses followed by a 32-byte slot,
+    // including an address of 0xff bytes and one that prefixes the next
+    fn storage_pairs() -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut pairs = Vec::new();
+        for (a, address) in [[0x11; 20], [0x12; 20], [0xff; 20]].iter().enumerate() {
+            for slot in 0..(40 + a as u64 * 30) {
+                let mut key = address.to_vec();
+                key.extend_from_slice(&[0; 24]);
+                key.extend_from_slice(&slot.to_be_bytes());
+                pairs.push((key, slot.to_le_bytes().to_vec()));
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn test_range_and_scan_prefix() {
+        let pairs = storage_pairs();
+        for accessor in [Some("bt"), None] {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let compression = FileCompression::Keys;
+            let kv_path = write_domain(tmp_dir.path(), &pairs, compression, accessor);
+            let reader = DomainReader::with_compression(&kv_path, compression).unwrap();
+
+            // Every slot of one contract, and nothing else
+            let mut slots = Vec::new();
+            let count = reader
+                .scan_prefix(&[0x12; 20], |key, value| {
+                    assert!(key.starts_with(&[0x12; 20]));
+                    slots.push(u64::from_le_bytes(value.try_into().unwrap()));
+                    true
+                })
+                .unwrap();
+            assert_eq!(count, 70);
+            assert_eq!(slots, (0..70).collect::<Vec<_>>());
+            // Up to the end of the file, and stopping early
+            assert_eq!(reader.scan_prefix(&[0xff; 20], |_, _| true).unwrap(), 100);
+            assert_eq!(reader.scan_prefix(&[0x11], |_, _| false).unwrap(), 1);
+            assert_eq!(reader.scan_prefix(&[0x13], |_, _| true).unwrap(), 0);
+            assert_eq!(reader.scan_prefix(&[], |_, _| true).unwrap(), 210);
+
+            // Half-open ranges, between and on stored keys
+            let range: Vec<_> = reader
+                .range(&pairs[35].0, Some(&pairs[45].0))
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+            assert_eq!(range, pairs[35..45]);
+            let mut between = pairs[99].0.clone();
+            *between.last_mut().unwrap() += 1;
+            let range: Vec<_> = reader
+                .range(&between, None)
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+            assert_eq!(range, pairs[100..]);
+            assert_eq!(reader.range(&[0xff; 60], None).unwrap().count(), 0);
+            assert_eq!(
+                reader
+                    .range(&pairs[5].0, Some(&pairs[5].0))
+                    .unwrap()
+                    .count(),
+                0
+            );
+        }
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(&[1, 2]), Some(vec![1, 3]));
+        assert_eq!(prefix_end(&[1, 0xff, 0xff]), Some(vec![2]));
+        assert_eq!(prefix_end(&[0xff]), None);
+        assert_eq!(prefix_end(&[]), None);
+    }
+}
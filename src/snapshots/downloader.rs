@@ -0,0 +1,566 @@
+//! Downloads of snapshot files from Erigon's webseeds
+//!
+//! Besides BitTorrent, Erigon publishes its snapshots on HTTP webseeds:
+//! every file sits at `<webseed>/<name>` with its torrent at
+//! `<webseed>/<name>.torrent`, and `<webseed>/manifest.txt` lists the names.
+//! The preverified `<chain>.toml` files of erigon-snapshot also pin the
+//! infohash of every file.
+//!
+//! [`Downloader`] fetches the files of a [`Manifest`] that a snapshot
+//! directory lacks. Partial downloads are kept as `<name>.part` and resumed
+//! with range requests, and a file only gets its final name once it matches
+//! the piece hashes of its torrent, so that
+//! [`SnapshotRepo`](crate::snapshots::SnapshotRepo) never picks up a
+//! truncated or corrupt segment.
+
+use crate::snapshots::manifest::{is_relative_name, Manifest, ManifestEntry};
+use crate::snapshots::torrent::{torrent_path, TorrentInfo};
+use crate::snapshots::{Result, SnapshotError};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Name of the file list at the root of a webseed
+const MANIFEST_NAME: &str = "manifest.txt";
+
+// Largest manifest or torrent read into memory
+const MAX_METADATA_LEN: u64 = 64 << 20;
+
+/// Where a [`Downloader`] gets its bytes from
+pub trait Transport {
+    /// GET `url`, asking for the bytes from `offset` on when it is not 0
+    ///
+    /// Returns the offset the body starts at along with the body: `offset`
+    /// if the range was honoured, 0 if the whole file is sent instead.
+    fn get(&self, url: &str, offset: u64) -> Result<(u64, Box<dyn Read>)>;
+}
+
+/// [`Transport`] over HTTP and HTTPS, blocking the calling thread
+pub struct HttpTransport {
+    agent: ureq::Agent,
+}
+
+impl HttpTransport {
+    /// Connect and read timeouts of 30 seconds
+    pub fn new() -> Self {
+        Self::with_timeout(Duration::from_secs(30))
+    }
+
+    /// Give up on a connection or a stalled read after `timeout`
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(timeout)
+                .timeout_read(timeout)
+                .build(),
+        }
+    }
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for HttpTransport {
+    fn get(&self, url: &str, offset: u64) -> Result<(u64, Box<dyn Read>)> {
+        let mut request = self.agent.get(url);
+        if offset > 0 {
+            request = request.set("Range", &format!("bytes={}-", offset));
+        }
+        // Error statuses come back as errors, redirects are followed
+        let response = request.call().map_err(|e| download_error(url, e))?;
+        let start = if response.status() == 206 {
+            response
+                .header("Content-Range")
+                .and_then(content_range_start)
+                .ok_or_else(|| download_error(url, "206 without a valid Content-Range"))?
+        } else {
+            0
+        };
+        Ok((start, Box::new(response.into_reader())))
+    }
+}
+
+/// What [`Downloader::download_missing`] did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadReport {
+    /// Files fetched, in manifest order
+    pub downloaded: Vec<PathBuf>,
+    /// Files of the manifest that were already there
+    pub present: usize,
+}
+
+/// Fetches the files of a manifest into a snapshot directory
+///
+/// A file is tried on each webseed in turn, up to
+/// [`Downloader::attempts`] times. Every attempt resumes from what earlier
+/// ones left in `<name>.part`. The torrent is fetched from the same webseed,
+/// checked against the infohash the manifest pins, if any, and written next
+/// to the file as Erigon does.
+pub struct Downloader<T = HttpTransport> {
+    dir: PathBuf,
+    webseeds: Vec<String>,
+    transport: T,
+    attempts: usize,
+}
+
+impl Downloader {
+    /// Download into `dir`, the directory [`SnapshotRepo::open`] reads,
+    /// from `webseeds` over HTTP
+    ///
+    /// [`SnapshotRepo::open`]: crate::snapshots::SnapshotRepo::open
+    pub fn new(dir: impl AsRef<Path>, webseeds: impl IntoIterator<Item = String>) -> Self {
+        Self::with_transport(dir, webseeds, HttpTransport::new())
+    }
+}
+
+impl<T: Transport> Downloader<T> {
+    /// [`Downloader::new`] with another transport
+    pub fn with_transport(
+        dir: impl AsRef<Path>,
+        webseeds: impl IntoIterator<Item = String>,
+        transport: T,
+    ) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            webseeds: webseeds.into_iter().collect(),
+            transport,
+            attempts: 3,
+        }
+    }
+
+    /// Try each file up to `attempts` times before giving up, 3 by default
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The `manifest.txt` of the first webseed that serves one
+    pub fn fetch_manifest(&self) -> Result<Manifest> {
+        let mut last_error = None;
+        for webseed in &self.webseeds {
+            let url = join_url(webseed, MANIFEST_NAME);
+            match self.fetch_metadata(&url) {
+                Ok(data) => {
+                    let text = String::from_utf8(data).map_err(|_| {
+                        SnapshotError::InvalidFormat(format!("{} is not UTF-8", url))
+                    })?;
+                    return Manifest::parse(&text);
+                }
+                Err(e) => {
+                    tracing::warn!("No manifest from {}: {}", webseed, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| no_webseeds(MANIFEST_NAME)))
+    }
+
+    /// The entries of `manifest` whose file is not in the directory yet
+    pub fn missing<'m>(
+        &self,
+        manifest: &'m Manifest,
+    ) -> impl Iterator<Item = &'m ManifestEntry> + 'm {
+        let dir = self.dir.clone();
+        manifest
+            .entries
+            .iter()
+            .filter(move |entry| !dir.join(&entry.name).exists())
+    }
+
+    /// Download every missing file of `manifest`, stopping at the first
+    /// one that fails
+    ///
+    /// Running it again resumes where it stopped.
+    pub fn download_missing(&self, manifest: &Manifest) -> Result<DownloadReport> {
+        let mut report = DownloadReport::default();
+        let missing: Vec<_> = self.missing(manifest).collect();
+        report.present = manifest.entries.len() - missing.len();
+        for entry in missing {
+            report.downloaded.push(self.download(entry)?);
+        }
+        Ok(report)
+    }
+
+    /// Download the file of `entry`, returning its path
+    pub fn download(&self, entry: &ManifestEntry) -> Result<PathBuf> {
+        if !is_relative_name(&entry.name) {
+            return Err(SnapshotError::InvalidPath(entry.name.clone()));
+        }
+        let path = self.dir.join(&entry.name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut last_error = None;
+        for attempt in 0..self.attempts {
+            let Some(webseed) = self.webseeds.get(attempt % self.webseeds.len().max(1)) else {
+                break;
+            };
+            match self.try_download(webseed, entry, &path) {
+                Ok(()) => return Ok(path),
+                Err(e) => {
+                    tracing::warn!(
+                        "Attempt {} at {} from {} failed: {}",
+                        attempt + 1,
+                        entry.name,
+                        webseed,
+                        e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| no_webseeds(&entry.name)))
+    }
+
+    fn try_download(&self, webseed: &str, entry: &ManifestEntry, path: &Path) -> Result<()> {
+        let url = join_url(webseed, &entry.name);
+        let torrent_url = format!("{}.torrent", url);
+        let torrent_data = self.fetch_metadata(&torrent_url)?;
+        let torrent = TorrentInfo::parse(&torrent_data)?;
+        if let Some(expected) = &entry.info_hash {
+            let actual = torrent.info_hash_hex();
+            if actual != *expected {
+                return Err(SnapshotError::TorrentMismatch(format!(
+                    "{} has infohash {}, the manifest pins {}",
+                    torrent_url, actual, expected
+                )));
+            }
+        }
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        if file_name != Some(torrent.name.as_str()) {
+            return Err(SnapshotError::TorrentMismatch(format!(
+                "{} is the torrent of {}",
+                torrent_url, torrent.name
+            )));
+        }
+
+        let part = part_path(path);
+        let mut have = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+        if have > torrent.length {
+            have = 0;
+        }
+        if have < torrent.length {
+            if have > 0 {
+                tracing::debug!("Resuming {} at byte {}", entry.name, have);
+            }
+            let (start, body) = self.transport.get(&url, have)?;
+            if start != have && start != 0 {
+                return Err(download_error(
+                    &url,
+                    format!("asked for byte {} on, got byte {} on", have, start),
+                ));
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&part)?;
+            file.set_len(start)?;
+            let mut writer = BufWriter::new(file);
+            writer.seek(SeekFrom::Start(start))?;
+            // What was received before an error is kept for the next attempt
+            let copied = io::copy(&mut body.take(torrent.length - start), &mut writer);
+            let file = writer.into_inner().map_err(|e| e.into_error())?;
+            let copied = copied.map_err(|e| download_error(&url, e))?;
+            if start + copied < torrent.length {
+                return Err(download_error(
+                    &url,
+                    format!(
+                        "body ended at byte {} of {}",
+                        start + copied,
+                        torrent.length
+                    ),
+                ));
+            }
+            file.sync_all()?;
+        }
+        if let Err(e) = torrent.verify_data(&part) {
+            fs::remove_file(&part)?;
+            return Err(e);
+        }
+
+        // The torrent goes first, so that the file is never without it
+        fs::write(torrent_path(path), &torrent_data)?;
+        fs::rename(&part, path)?;
+        tracing::info!("Downloaded {} ({} bytes)", entry.name, torrent.length);
+        Ok(())
+    }
+
+    // Whole body of a small file, such as a torrent or the manifest
+    fn fetch_metadata(&self, url: &str) -> Result<Vec<u8>> {
+        let (_, body) = self.transport.get(url, 0)?;
+        let mut data = Vec::new();
+        body.take(MAX_METADATA_LEN)
+            .read_to_end(&mut data)
+            .map_err(|e| download_error(url, e))?;
+        Ok(data)
+    }
+}
+
+fn join_url(webseed: &str, name: &str) -> String {
+    format!("{}/{}", webseed.trim_end_matches('/'), name)
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+// First byte of `bytes <first>-<last>/<length>`
+fn content_range_start(value: &str) -> Option<u64> {
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (first, _) = range.split_once('-')?;
+    first.trim().parse().ok()
+}
+
+fn download_error(url: &str, reason: impl ToString) -> SnapshotError {
+    SnapshotError::Download {
+        url: url.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+fn no_webseeds(name: &str) -> SnapshotError {
+    download_error(name, "no webseeds configured")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::torrent::verify_file_against_torrent;
+    use crate::snapshots::{SnapshotRepo, SnapshotType};
+    use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    const SEED: &str = "http://seed.test/mainnet";
+    const HEADERS: &str = "v1-000000-000500-headers.seg";
+
+    // Files by URL, with the requests made for them
+    #[derive(Default)]
+    struct MemoryTransport {
+        files: HashMap<String, Vec<u8>>,
+        // The next file body breaks off after this many bytes
+        fail_after: Cell<Option<usize>>,
+        // Send whole files, as servers without range support do
+        ignore_range: bool,
+        requests: RefCell<Vec<(String, u64)>>,
+    }
+
+    impl MemoryTransport {
+        fn serve(&mut self, name: &str, data: Vec<u8>) {
+            self.files.insert(join_url(SEED, name), data);
+        }
+    }
+
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::ConnectionReset.into())
+        }
+    }
+
+    impl Transport for MemoryTransport {
+        fn get(&self, url: &str, offset: u64) -> Result<(u64, Box<dyn Read>)> {
+            self.requests.borrow_mut().push((url.to_string(), offset));
+            let data = self
+                .files
+                .get(url)
+                .ok_or_else(|| download_error(url, "404 Not Found"))?;
+            let start = if self.ignore_range { 0 } else { offset };
+            let body = data[start as usize..].to_vec();
+            let fail_after = match url.ends_with(".torrent") {
+                true => None,
+                false => self.fail_after.take(),
+            };
+            Ok(match fail_after {
+                Some(len) => (
+                    start,
+                    Box::new(Cursor::new(body[..len].to_vec()).chain(Broken)),
+                ),
+                None => (start, Box::new(Cursor::new(body))),
+            })
+        }
+    }
+
+    // Bencoded single-file torrent for `name`
+    fn make_torrent(name: &str, content: &[u8], piece_length: usize) -> Vec<u8> {
+        let mut pieces = Vec::new();
+        for piece in content.chunks(piece_length) {
+            pieces.extend_from_slice(digest(&SHA1_FOR_LEGACY_USE_ONLY, piece).as_ref());
+        }
+        let mut torrent = format!(
+            "d4:infod6:lengthi{}e4:name{}:{}12:piece lengthi{}e6:pieces{}:",
+            content.len(),
+            name.len(),
+            name,
+            piece_length,
+            pieces.len()
+        )
+        .into_bytes();
+        torrent.extend_from_slice(&pieces);
+        torrent.extend_from_slice(b"ee");
+        torrent
+    }
+
+    fn content(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_mul(seed)).collect()
+    }
+
+    // Serves `name` with its torrent, returning its manifest entry
+    fn publish(transport: &mut MemoryTransport, name: &str, data: Vec<u8>) -> ManifestEntry {
+        let file_name = name.rsplit('/').next().unwrap();
+        let torrent = make_torrent(file_name, &data, 4096);
+        let info_hash = TorrentInfo::parse(&torrent).unwrap().info_hash_hex();
+        transport.serve(&format!("{}.torrent", name), torrent);
+        transport.serve(name, data);
+        ManifestEntry {
+            name: name.to_string(),
+            info_hash: Some(info_hash),
+        }
+    }
+
+    #[test]
+    fn test_download_missing_resumes() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let mut transport = MemoryTransport::default();
+        let headers = publish(&mut transport, HEADERS, content(20_000, 7));
+        let accounts = publish(
+            &mut transport,
+            "domain/v1-accounts.0-64.kv",
+            content(5000, 3),
+        );
+        let manifest = Manifest {
+            entries: vec![headers.clone(), accounts],
+        };
+        // The first body breaks off, the second attempt picks it up
+        transport.fail_after.set(Some(9000));
+        let downloader = Downloader::with_transport(tmp_dir.path(), [SEED.to_string()], transport);
+
+        let report = downloader.download_missing(&manifest).unwrap();
+        assert_eq!(report.present, 0);
+        assert_eq!(report.downloaded.len(), 2);
+        let url = join_url(SEED, HEADERS);
+        assert!(downloader
+            .transport
+            .requests
+            .borrow()
+            .contains(&(url, 9000)));
+
+        let path = tmp_dir.path().join(HEADERS);
+        assert_eq!(fs::read(&path).unwrap(), content(20_000, 7));
+        assert!(!part_path(&path).exists());
+        verify_file_against_torrent(&path).unwrap();
+        assert!(tmp_dir
+            .path()
+            .join("domain/v1-accounts.0-64.kv.torrent")
+            .exists());
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        assert_eq!(repo.files_of(SnapshotType::Headers).count(), 1);
+
+        // Nothing left to fetch
+        downloader.transport.requests.borrow_mut().clear();
+        let report = downloader.download_missing(&manifest).unwrap();
+        assert_eq!((report.present, report.downloaded.len()), (2, 0));
+        assert!(downloader.transport.requests.borrow().is_empty());
+
+        // A server without range support sends the whole file again
+        fs::remove_file(&path).unwrap();
+        fs::write(part_path(&path), &content(20_000, 7)[..5000]).unwrap();
+        let mut transport = MemoryTransport {
+            ignore_range: true,
+            ..Default::default()
+        };
+        publish(&mut transport, HEADERS, content(20_000, 7));
+        let downloader = Downloader::with_transport(tmp_dir.path(), [SEED.to_string()], transport);
+        downloader.download(&headers).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), content(20_000, 7));
+    }
+
+    #[test]
+    fn test_download_rejects_bad_files() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let mut transport = MemoryTransport::default();
+        let mut entry = publish(&mut transport, HEADERS, content(10_000, 5));
+        let good_hash = entry.info_hash.replace("00".repeat(20));
+        let downloader =
+            Downloader::with_transport(tmp_dir.path(), [SEED.to_string()], transport).attempts(1);
+
+        // The torrent is not the one the manifest pins
+        let err = downloader.download(&entry).unwrap_err().to_string();
+        assert!(err.contains("manifest pins"), "{}", err);
+
+        // The data does not match the torrent's pieces
+        entry.info_hash = good_hash;
+        let mut transport = downloader.transport;
+        let mut corrupt = content(10_000, 5);
+        corrupt[5000] ^= 1;
+        transport.serve(HEADERS, corrupt);
+        let downloader =
+            Downloader::with_transport(tmp_dir.path(), [SEED.to_string()], transport).attempts(1);
+        let err = downloader.download(&entry).unwrap_err().to_string();
+        assert!(err.contains("piece 1"), "{}", err);
+        let path = tmp_dir.path().join(HEADERS);
+        assert!(!path.exists());
+        assert!(!part_path(&path).exists());
+
+        // Unknown on the webseed
+        let missing = ManifestEntry {
+            name: "v1-000500-001000-headers.seg".to_string(),
+            info_hash: None,
+        };
+        assert!(matches!(
+            downloader.download(&missing),
+            Err(SnapshotError::Download { .. })
+        ));
+    }
+
+    #[test]
+    fn test_download_written_manifest() {
+        let source = tempfile::TempDir::new().unwrap();
+        let bodies = source.path().join("v1-000000-000500-bodies.seg");
+        fs::write(&bodies, content(3_000_000, 11)).unwrap();
+        let manifest = SnapshotRepo::open(source.path())
+            .unwrap()
+            .write_manifest(&source.path().join("mainnet.toml"))
+            .unwrap();
+
+        // A webseed serving the directory as it is
+        let mut transport = MemoryTransport::default();
+        for name in [
+            "v1-000000-000500-bodies.seg",
+            "v1-000000-000500-bodies.seg.torrent",
+        ] {
+            transport.serve(name, fs::read(source.path().join(name)).unwrap());
+        }
+        transport.serve(MANIFEST_NAME, manifest.to_list().into_bytes());
+        let target = tempfile::TempDir::new().unwrap();
+        let downloader = Downloader::with_transport(target.path(), [SEED.to_string()], transport);
+        assert_eq!(downloader.fetch_manifest().unwrap().entries.len(), 1);
+
+        let report = downloader.download_missing(&manifest).unwrap();
+        assert_eq!(report.downloaded.len(), 1);
+        assert_eq!(
+            fs::read(&report.downloaded[0]).unwrap(),
+            fs::read(&bodies).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(content_range_start("bytes 100-199/200"), Some(100));
+        assert_eq!(content_range_start("bytes */200"), None);
+        assert_eq!(join_url("http://a/b/", "c.seg"), "http://a/b/c.seg");
+    }
+}
@@ -0,0 +1,443 @@
+//! Double Elias-Fano encoding used by RecSplit to store, for every bucket, the
+//! cumulative number of keys and the bit position of its Golomb-Rice code
+//! Based on the Go implementation in erigon-lib/recsplit/eliasfano16
+//!
+//! Both sequences share one lower-bits array (interleaved) and have their own
+//! upper-bits arrays. The jump table holds 16-bit offsets, hence "eliasfano16".
+//! Numbers in the header are big-endian, the bit arrays are little-endian u64
+//! words (Go writes the in-memory slice as-is).
+
+use crate::core::{ReadError, SafeReader};
+
+const LOG2Q: u64 = 8;
+const Q: u64 = 1 << LOG2Q;
+const Q_MASK: u64 = Q - 1;
+const SUPER_Q: u64 = 1 << 14;
+const Q_PER_SUPER_Q: u64 = SUPER_Q / Q; // 64
+const SUPER_Q_SIZE: u64 = 1 + Q_PER_SUPER_Q / 4; // 1 + 64/4 = 17
+
+/// Select the n-th set bit in a u64 (0-indexed)
+pub(crate) fn select64(word: u64, n: usize) -> usize {
+    let mut remaining = n;
+    let mut word = word;
+
+    for i in 0..64 {
+        if word & 1 != 0 {
+            if remaining == 0 {
+                return i;
+            }
+            remaining -= 1;
+        }
+        word >>= 1;
+    }
+
+    63
+}
+
+// Number of bits needed for the lower part of each value
+fn lower_bits_len(u: u64, count: u64) -> u64 {
+    let ratio = u / count;
+    if ratio == 0 {
+        0
+    } else {
+        63 - ratio.leading_zeros() as u64
+    }
+}
+
+// From Go: eliasfano16 DoubleEliasFano.Write
+/// The five numbers opening a double Elias-Fano index, big-endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DoubleEliasFanoHeader {
+    pub num_buckets: u64,
+    pub u_cum_keys: u64,
+    pub u_position: u64,
+    pub cum_keys_min_delta: u64,
+    pub pos_min_delta: u64,
+}
+
+impl DoubleEliasFanoHeader {
+    pub const LEN: usize = 40;
+
+    pub fn read(reader: &mut SafeReader<'_>) -> Result<Self, ReadError> {
+        Ok(DoubleEliasFanoHeader {
+            num_buckets: reader.u64_be("bucket count")?,
+            u_cum_keys: reader.u64_be("cumulative keys universe")?,
+            u_position: reader.u64_be("position universe")?,
+            cum_keys_min_delta: reader.u64_be("cumulative keys min delta")?,
+            pos_min_delta: reader.u64_be("position min delta")?,
+        })
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        for (chunk, v) in bytes.chunks_exact_mut(8).zip([
+            self.num_buckets,
+            self.u_cum_keys,
+            self.u_position,
+            self.cum_keys_min_delta,
+            self.pos_min_delta,
+        ]) {
+            chunk.copy_from_slice(&v.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+// From Go: DoubleEliasFano struct (read side only)
+pub(crate) struct DoubleEliasFano<'a> {
+    data: &'a [u8],
+    num_buckets: u64,
+    cum_keys_min_delta: u64,
+    pos_min_delta: u64,
+    l_cum_keys: u64,
+    l_position: u64,
+    lower_bits_mask_cum_keys: u64,
+    lower_bits_mask_position: u64,
+    // Word offsets of the sub-arrays inside `data`
+    upper_bits_cum_keys: usize,
+    upper_bits_position: usize,
+    jump: usize,
+}
+
+impl<'a> DoubleEliasFano<'a> {
+    // From Go: eliasfano16 DoubleEliasFano.Read - returns the structure and
+    // the number of bytes it occupies
+    pub(crate) fn read(r: &'a [u8]) -> Option<(Self, usize)> {
+        let DoubleEliasFanoHeader {
+            num_buckets,
+            u_cum_keys,
+            u_position,
+            cum_keys_min_delta,
+            pos_min_delta,
+        } = DoubleEliasFanoHeader::read(&mut SafeReader::new(r)).ok()?;
+        // Both upper bit arrays take a bit per bucket, also keeps the sums
+        // below from overflowing on garbage headers
+        if num_buckets >= r.len() as u64 * 8 {
+            return None;
+        }
+
+        // From Go: deriveFields
+        let l_position = lower_bits_len(u_position, num_buckets + 1);
+        let l_cum_keys = lower_bits_len(u_cum_keys, num_buckets + 1);
+        if l_cum_keys * 2 + l_position > 56 {
+            return None;
+        }
+        let words_lower_bits = ((num_buckets + 1) * (l_cum_keys + l_position)).div_ceil(64) + 1;
+        let words_cum_keys = (num_buckets + 1 + (u_cum_keys >> l_cum_keys)).div_ceil(64);
+        let words_position = (num_buckets + 1 + (u_position >> l_position)).div_ceil(64);
+        let total_words =
+            words_lower_bits + words_cum_keys + words_position + Self::jump_size_words(num_buckets);
+
+        // Checked as u64 first, the casts below rely on it on 32-bit targets
+        let size = usize::try_from(
+            total_words
+                .checked_mul(8)?
+                .checked_add(DoubleEliasFanoHeader::LEN as u64)?,
+        )
+        .ok()?;
+        if r.len() < size {
+            return None;
+        }
+
+        Some((
+            DoubleEliasFano {
+                data: &r[DoubleEliasFanoHeader::LEN..size],
+                num_buckets,
+                cum_keys_min_delta,
+                pos_min_delta,
+                l_cum_keys,
+                l_position,
+                lower_bits_mask_cum_keys: (1u64 << l_cum_keys) - 1,
+                lower_bits_mask_position: (1u64 << l_position) - 1,
+                upper_bits_cum_keys: words_lower_bits as usize,
+                upper_bits_position: (words_lower_bits + words_cum_keys) as usize,
+                jump: (words_lower_bits + words_cum_keys + words_position) as usize,
+            },
+            size,
+        ))
+    }
+
+    // From Go: jumpSizeWords
+    fn jump_size_words(num_buckets: u64) -> u64 {
+        let mut size = ((num_buckets + 1) / SUPER_Q) * SUPER_Q_SIZE * 2; // Whole blocks
+        if !(num_buckets + 1).is_multiple_of(SUPER_Q) {
+            size += (1 + ((num_buckets + 1) % SUPER_Q).div_ceil(Q).div_ceil(4)) * 2;
+            // Partial block
+        }
+        size
+    }
+
+    fn word(&self, i: usize) -> Option<u64> {
+        let bytes = self.data.get(i * 8..i * 8 + 8)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    fn jump16(&self, idx16: u64) -> Option<u64> {
+        let shift = 16 * (idx16 % 4);
+        Some((self.word(self.jump + (idx16 / 4) as usize)? >> shift) & 0xffff)
+    }
+
+    // From Go: eliasfano16 get2
+    // Returns (cum_keys, position) plus the state Get3 needs to continue:
+    // (window_cum_keys, select_cum_keys, curr_word_cum_keys, lower, cum_delta)
+    #[allow(clippy::type_complexity)]
+    fn get2(&self, i: u64) -> Option<(u64, u64, u64, usize, usize, u64, u64)> {
+        let pos_lower = i * (self.l_cum_keys + self.l_position);
+        let idx64 = (pos_lower / 64) as usize;
+        let shift = pos_lower % 64;
+        let mut lower = self.word(idx64)? >> shift;
+        if shift > 0 {
+            lower |= self.word(idx64 + 1)? << (64 - shift);
+        }
+
+        let jump_super_q = (i / SUPER_Q) * SUPER_Q_SIZE * 2;
+        let jump_inside_super_q = (i % SUPER_Q) / Q;
+        let idx16 = 4 * (jump_super_q + 2) + 2 * jump_inside_super_q;
+        let jump_cum_keys = self.word(self.jump + jump_super_q as usize)? + self.jump16(idx16)?;
+        let jump_position =
+            self.word(self.jump + jump_super_q as usize + 1)? + self.jump16(idx16 + 1)?;
+
+        let mut curr_word_cum_keys = (jump_cum_keys / 64) as usize;
+        let mut curr_word_position = (jump_position / 64) as usize;
+        let mut window_cum_keys = self.word(self.upper_bits_cum_keys + curr_word_cum_keys)?
+            & (u64::MAX << (jump_cum_keys % 64));
+        let mut window_position = self.word(self.upper_bits_position + curr_word_position)?
+            & (u64::MAX << (jump_position % 64));
+        let mut delta_cum_keys = (i & Q_MASK) as usize;
+        let mut delta_position = (i & Q_MASK) as usize;
+
+        let mut bit_count = window_cum_keys.count_ones() as usize;
+        while bit_count <= delta_cum_keys {
+            curr_word_cum_keys += 1;
+            window_cum_keys = self.word(self.upper_bits_cum_keys + curr_word_cum_keys)?;
+            delta_cum_keys -= bit_count;
+            bit_count = window_cum_keys.count_ones() as usize;
+        }
+        let mut bit_count = window_position.count_ones() as usize;
+        while bit_count <= delta_position {
+            curr_word_position += 1;
+            window_position = self.word(self.upper_bits_position + curr_word_position)?;
+            delta_position -= bit_count;
+            bit_count = window_position.count_ones() as usize;
+        }
+
+        let select_cum_keys = select64(window_cum_keys, delta_cum_keys);
+        // Wrapping like Go's uint64, so corrupt data decodes to garbage
+        // (rejected by the caller) rather than panicking
+        let cum_delta = i.wrapping_mul(self.cum_keys_min_delta);
+        let cum_keys = (((curr_word_cum_keys as u64 * 64 + select_cum_keys as u64)
+            .wrapping_sub(i)
+            << self.l_cum_keys)
+            | (lower & self.lower_bits_mask_cum_keys))
+            .wrapping_add(cum_delta);
+
+        lower >>= self.l_cum_keys;
+        let select_position = select64(window_position, delta_position);
+        let bit_delta = i.wrapping_mul(self.pos_min_delta);
+        let position = (((curr_word_position as u64 * 64 + select_position as u64)
+            .wrapping_sub(i)
+            << self.l_position)
+            | (lower & self.lower_bits_mask_position))
+            .wrapping_add(bit_delta);
+
+        Some((
+            cum_keys,
+            position,
+            window_cum_keys,
+            select_cum_keys,
+            curr_word_cum_keys,
+            lower,
+            cum_delta,
+        ))
+    }
+
+    /// Cumulative key count of bucket `i` and `i + 1`, and the Golomb-Rice
+    /// bit position of bucket `i`: (cum_keys, cum_keys_next, position)
+    // From Go: eliasfano16 Get3
+    pub(crate) fn get3(&self, i: u64) -> Option<(u64, u64, u64)> {
+        if i >= self.num_buckets {
+            return None;
+        }
+        let (
+            cum_keys,
+            position,
+            mut window_cum_keys,
+            select_cum_keys,
+            mut curr_word_cum_keys,
+            mut lower,
+            cum_delta,
+        ) = self.get2(i)?;
+        window_cum_keys &= (u64::MAX << select_cum_keys) << 1;
+        while window_cum_keys == 0 {
+            curr_word_cum_keys += 1;
+            window_cum_keys = self.word(self.upper_bits_cum_keys + curr_word_cum_keys)?;
+        }
+
+        lower >>= self.l_position;
+        let cum_keys_next = (((curr_word_cum_keys as u64 * 64
+            + window_cum_keys.trailing_zeros() as u64)
+            .wrapping_sub(i + 1)
+            << self.l_cum_keys)
+            | (lower & self.lower_bits_mask_cum_keys))
+            .wrapping_add(cum_delta)
+            .wrapping_add(self.cum_keys_min_delta);
+        Some((cum_keys, cum_keys_next, position))
+    }
+}
+
+// From Go: eliasfano16 setBits - bits are set in monotonic order, so the
+// second word needs no masking
+pub(crate) fn set_bits(bits: &mut [u64], start: u64, width: u64, value: u64) {
+    let shift = start & 63;
+    let idx64 = (start >> 6) as usize;
+    let mask = ((1u64 << width) - 1) << shift;
+    bits[idx64] = (bits[idx64] & !mask) | (value << shift);
+    if shift + width > 64 {
+        // changes two 64-bit words
+        bits[idx64 + 1] = value >> (64 - shift);
+    }
+}
+
+// From Go: eliasfano16 set
+pub(crate) fn set(bits: &mut [u64], pos: u64) {
+    bits[(pos / 64) as usize] |= 1u64 << (pos % 64);
+}
+
+/// Build and serialize a double Elias-Fano index over two monotone sequences
+/// of equal length (bucket count + 1), in the layout [`DoubleEliasFano::read`]
+/// expects
+// From Go: eliasfano16 DoubleEliasFano.Build + Write
+pub(crate) fn build_double_elias_fano(cum_keys: &[u64], position: &[u64]) -> Vec<u8> {
+    assert_eq!(cum_keys.len(), position.len());
+    let num_buckets = cum_keys.len() as u64 - 1;
+    let mut pos_min_delta = u64::MAX;
+    let mut cum_keys_min_delta = u64::MAX;
+    for i in 1..cum_keys.len() {
+        cum_keys_min_delta = cum_keys_min_delta.min(cum_keys[i] - cum_keys[i - 1]);
+        pos_min_delta = pos_min_delta.min(position[i] - position[i - 1]);
+    }
+    let nb = num_buckets as usize;
+    let u_position = position[nb]
+        .wrapping_sub(num_buckets.wrapping_mul(pos_min_delta))
+        .wrapping_add(1);
+    let u_cum_keys = cum_keys[nb]
+        .wrapping_sub(num_buckets.wrapping_mul(cum_keys_min_delta))
+        .wrapping_add(1);
+
+    // From Go: deriveFields
+    let l_position = lower_bits_len(u_position, num_buckets + 1);
+    let l_cum_keys = lower_bits_len(u_cum_keys, num_buckets + 1);
+    let lower_bits_mask_cum_keys = (1u64 << l_cum_keys) - 1;
+    let lower_bits_mask_position = (1u64 << l_position) - 1;
+    let words_lower_bits =
+        (((num_buckets + 1) * (l_cum_keys + l_position)).div_ceil(64) + 1) as usize;
+    let words_cum_keys = (num_buckets + 1 + (u_cum_keys >> l_cum_keys)).div_ceil(64) as usize;
+    let words_position = (num_buckets + 1 + (u_position >> l_position)).div_ceil(64) as usize;
+    let jump_words = DoubleEliasFano::jump_size_words(num_buckets) as usize;
+
+    let mut lower_bits = vec![0u64; words_lower_bits];
+    let mut upper_bits_cum_keys = vec![0u64; words_cum_keys];
+    let mut upper_bits_position = vec![0u64; words_position];
+    let mut jump = vec![0u64; jump_words];
+
+    let mut cum_delta = 0u64;
+    let mut bit_delta = 0u64;
+    for i in 0..=num_buckets {
+        let ck = cum_keys[i as usize].wrapping_sub(cum_delta);
+        let pos = position[i as usize].wrapping_sub(bit_delta);
+        if l_cum_keys != 0 {
+            set_bits(
+                &mut lower_bits,
+                i * (l_cum_keys + l_position),
+                l_cum_keys,
+                ck & lower_bits_mask_cum_keys,
+            );
+        }
+        set(&mut upper_bits_cum_keys, (ck >> l_cum_keys) + i);
+        if l_position != 0 {
+            set_bits(
+                &mut lower_bits,
+                i * (l_cum_keys + l_position) + l_cum_keys,
+                l_position,
+                pos & lower_bits_mask_position,
+            );
+        }
+        set(&mut upper_bits_position, (pos >> l_position) + i);
+        cum_delta = cum_delta.wrapping_add(cum_keys_min_delta);
+        bit_delta = bit_delta.wrapping_add(pos_min_delta);
+    }
+
+    // Jump tables: 64-bit base per super-block, then 16-bit offsets every Q ones;
+    // the cum_keys and position entries are interleaved
+    for (upper, second) in [(&upper_bits_cum_keys, 0u64), (&upper_bits_position, 1u64)] {
+        let mut c = 0u64;
+        let mut last_super_q = 0u64;
+        for (i, &word) in upper.iter().enumerate() {
+            for b in 0..64u64 {
+                if word & (1u64 << b) == 0 {
+                    continue;
+                }
+                let bit = i as u64 * 64 + b;
+                if c & (SUPER_Q - 1) == 0 {
+                    last_super_q = bit;
+                    jump[((c / SUPER_Q) * (SUPER_Q_SIZE * 2) + second) as usize] = last_super_q;
+                }
+                if c & Q_MASK == 0 {
+                    let offset = bit - last_super_q;
+                    assert!(offset < (1 << 16));
+                    let jump_super_q = (c / SUPER_Q) * (SUPER_Q_SIZE * 2);
+                    let jump_inside_super_q = 2 * ((c % SUPER_Q) / Q) + second;
+                    let idx64 = (jump_super_q + 2 + (jump_inside_super_q >> 2)) as usize;
+                    let shift = 16 * (jump_inside_super_q % 4);
+                    let mask = 0xffffu64 << shift;
+                    jump[idx64] = (jump[idx64] & !mask) | (offset << shift);
+                }
+                c += 1;
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(
+        DoubleEliasFanoHeader::LEN
+            + 8 * (words_lower_bits + words_cum_keys + words_position + jump_words),
+    );
+    let header = DoubleEliasFanoHeader {
+        num_buckets,
+        u_cum_keys,
+        u_position,
+        cum_keys_min_delta,
+        pos_min_delta,
+    };
+    out.extend_from_slice(&header.to_bytes());
+    for word in lower_bits
+        .iter()
+        .chain(&upper_bits_cum_keys)
+        .chain(&upper_bits_position)
+        .chain(&jump)
+    {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_double_elias_fano_header_round_trip() {
+        let cum_keys = [0, 3, 7, 12];
+        let position = [0, 40, 90, 150];
+        let data = build_double_elias_fano(&cum_keys, &position);
+        let header = DoubleEliasFanoHeader::read(&mut SafeReader::new(&data)).unwrap();
+        assert_eq!(header.num_buckets, 3);
+        assert_eq!(header.cum_keys_min_delta, 3);
+        assert_eq!(header.pos_min_delta, 40);
+        assert_eq!(data[..DoubleEliasFanoHeader::LEN], header.to_bytes());
+
+        let (ef, size) = DoubleEliasFano::read(&data).unwrap();
+        assert_eq!(size, data.len());
+        assert_eq!(ef.num_buckets, 3);
+        assert!(DoubleEliasFano::read(&data[..DoubleEliasFanoHeader::LEN - 1]).is_none());
+    }
+}
@@ -0,0 +1,664 @@
+//! Era1 archives of pre-merge blocks
+//!
+//! Era1 is the format execution clients exchange chain history in: an
+//! e2store file of up to 8192 consecutive blocks, each stored as its header,
+//! body and receipts (snappy framed RLP) and its total difficulty, followed
+//! by the SSZ accumulator root of the blocks' hashes and total difficulties
+//! and an index of where each block starts. Files are named
+//! `<network>-<epoch>-<root>.era1`, epoch `n` holding blocks from
+//! `n * 8192` on, with the first 4 bytes of the accumulator root.
+//!
+//! [`export_era1`] writes era1 files from the headers, bodies and
+//! transactions segments of a snapshot directory, and [`import_era1`] writes
+//! those segments back from era1 files. Receipts are not part of block
+//! snapshots, so the export takes them from the caller. The post-merge `.era`
+//! files of the consensus layer also need beacon states, which snapshots do
+//! not have, so they are not written.
+//!
+//! Based on go-ethereum internal/era and the e2store specification.
+
+use crate::compress::Compressor;
+use crate::snapshots::reader::{
+    BodiesReader, HeadersReader, StoredBody, StoredTransaction, TransactionsReader,
+};
+use crate::snapshots::repo::{SnapshotFile, SnapshotRepo, SnapshotType};
+use crate::snapshots::set::SegmentSet;
+use crate::snapshots::types::{BlockNumber, TxIndex, TxNum};
+use crate::snapshots::writer::{build_block_index, build_transactions_index};
+use crate::snapshots::{HeaderSegmentWriter, Result, SnapshotError};
+use alloy_consensus::{BlockBody, Header, ReceiptEnvelope, TxEnvelope};
+use alloy_eips::eip2718::Encodable2718;
+use alloy_primitives::{B256, U256};
+use alloy_rlp::{Decodable, Encodable};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Blocks in an era1 file, and in an epoch
+pub const BLOCKS_PER_ERA1: u64 = 8192;
+
+// e2store entry types, stored little-endian
+const TYPE_VERSION: u16 = 0x3265;
+const TYPE_COMPRESSED_HEADER: u16 = 0x03;
+const TYPE_COMPRESSED_BODY: u16 = 0x04;
+const TYPE_COMPRESSED_RECEIPTS: u16 = 0x05;
+const TYPE_TOTAL_DIFFICULTY: u16 = 0x06;
+const TYPE_ACCUMULATOR: u16 = 0x07;
+const TYPE_BLOCK_INDEX: u16 = 0x3266;
+
+// Type, length and reserved bytes before each entry's data
+const ENTRY_HEADER_LEN: usize = 8;
+
+// Depth of the accumulator's merkle tree, log2 of BLOCKS_PER_ERA1
+const ACCUMULATOR_DEPTH: usize = 13;
+
+/// A block as an era1 file holds it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Era1Block {
+    pub header: Header,
+    pub body: BlockBody<TxEnvelope>,
+    pub receipts: Vec<ReceiptEnvelope>,
+    /// Total difficulty of the chain up to and including this block
+    pub total_difficulty: U256,
+}
+
+/// Writer of one era1 file
+///
+/// Blocks are added in order, at most [`BLOCKS_PER_ERA1`] of them, and
+/// [`Era1Writer::finish`] appends the accumulator and the block index.
+pub struct Era1Writer<W: Write> {
+    writer: W,
+    // Bytes written so far
+    written: u64,
+    first_block: Option<u64>,
+    offsets: Vec<u64>,
+    // Hash and total difficulty of each block, for the accumulator
+    records: Vec<(B256, U256)>,
+}
+
+impl<W: Write> Era1Writer<W> {
+    pub fn new(writer: W) -> Result<Self> {
+        let mut era1 = Self {
+            writer,
+            written: 0,
+            first_block: None,
+            offsets: Vec::new(),
+            records: Vec::new(),
+        };
+        era1.write_entry(TYPE_VERSION, &[])?;
+        Ok(era1)
+    }
+
+    /// Number of blocks added so far
+    pub fn count(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Add the next block; its number must follow the previous one
+    pub fn add(&mut self, block: &Era1Block) -> Result<()> {
+        let first = *self.first_block.get_or_insert(block.header.number);
+        let expected = first + self.offsets.len() as u64;
+        if block.header.number != expected || self.offsets.len() as u64 >= BLOCKS_PER_ERA1 {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Expected block {} in era1 file from {}, got {}",
+                expected, first, block.header.number
+            )));
+        }
+        self.offsets.push(self.written);
+        self.write_entry(TYPE_COMPRESSED_HEADER, &snappy(&rlp(&block.header))?)?;
+        self.write_entry(TYPE_COMPRESSED_BODY, &snappy(&rlp(&block.body))?)?;
+        self.write_entry(TYPE_COMPRESSED_RECEIPTS, &snappy(&rlp(&block.receipts))?)?;
+        self.write_entry(
+            TYPE_TOTAL_DIFFICULTY,
+            &block.total_difficulty.to_le_bytes::<32>(),
+        )?;
+        self.records
+            .push((block.header.hash_slow(), block.total_difficulty));
+        Ok(())
+    }
+
+    /// Write the accumulator and the block index; returns the writer and
+    /// the accumulator root
+    pub fn finish(mut self) -> Result<(W, B256)> {
+        let root = accumulator_root(&self.records);
+        self.write_entry(TYPE_ACCUMULATOR, root.as_slice())?;
+
+        // Offsets are relative to the start of the index entry
+        let index_start = self.written as i64;
+        let mut index = Vec::with_capacity(16 + 8 * self.offsets.len());
+        index.extend_from_slice(&self.first_block.unwrap_or(0).to_le_bytes());
+        for &offset in &self.offsets {
+            index.extend_from_slice(&(offset as i64 - index_start).to_le_bytes());
+        }
+        index.extend_from_slice(&(self.offsets.len() as u64).to_le_bytes());
+        self.write_entry(TYPE_BLOCK_INDEX, &index)?;
+        self.writer.flush()?;
+        Ok((self.writer, root))
+    }
+
+    fn write_entry(&mut self, kind: u16, data: &[u8]) -> Result<()> {
+        let len = u32::try_from(data.len()).map_err(|_| {
+            SnapshotError::InvalidFormat(format!("Era1 entry of {} bytes", data.len()))
+        })?;
+        let mut header = [0u8; ENTRY_HEADER_LEN];
+        header[..2].copy_from_slice(&kind.to_le_bytes());
+        header[2..6].copy_from_slice(&len.to_le_bytes());
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        self.written += (ENTRY_HEADER_LEN + data.len()) as u64;
+        Ok(())
+    }
+}
+
+/// Reader of one era1 file, held in memory
+pub struct Era1Reader {
+    data: Vec<u8>,
+    first_block: u64,
+    // Absolute offset of each block's header entry
+    offsets: Vec<u64>,
+    accumulator: B256,
+}
+
+impl Era1Reader {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let invalid = |what: &str| SnapshotError::InvalidFormat(format!("Era1 file {}", what));
+        let (kind, _) = entry(&data, 0)?;
+        if kind != TYPE_VERSION {
+            return Err(invalid("does not start with a version entry"));
+        }
+        let count = data
+            .len()
+            .checked_sub(8)
+            .map(|at| u64::from_le_bytes(data[at..].try_into().unwrap()))
+            .ok_or_else(|| invalid("is truncated"))?;
+        let index_start = usize::try_from(count)
+            .ok()
+            .and_then(|count| count.checked_mul(8))
+            .and_then(|len| data.len().checked_sub(ENTRY_HEADER_LEN + 16 + len))
+            .ok_or_else(|| invalid("has a block count past its size"))?;
+        let (kind, index) = entry(&data, index_start)?;
+        if kind != TYPE_BLOCK_INDEX || index.len() as u64 != 16 + 8 * count {
+            return Err(invalid("does not end with a block index"));
+        }
+        let word = |i: usize| u64::from_le_bytes(index[8 * i..8 * i + 8].try_into().unwrap());
+        let first_block = word(0);
+        let offsets = (1..=count as usize)
+            .map(|i| {
+                let offset = index_start as i64 + word(i) as i64;
+                u64::try_from(offset)
+                    .ok()
+                    .filter(|&offset| offset < index_start as u64)
+                    .ok_or_else(|| invalid("has a block offset out of bounds"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // The accumulator comes right before the index
+        let accumulator_start = index_start
+            .checked_sub(ENTRY_HEADER_LEN + 32)
+            .ok_or_else(|| invalid("has no accumulator"))?;
+        let (kind, root) = entry(&data, accumulator_start)?;
+        if kind != TYPE_ACCUMULATOR || root.len() != 32 {
+            return Err(invalid("has no accumulator before its index"));
+        }
+        let accumulator = B256::from_slice(root);
+        Ok(Self {
+            data,
+            first_block,
+            offsets,
+            accumulator,
+        })
+    }
+
+    /// Number of the first block
+    pub fn first_block(&self) -> BlockNumber {
+        BlockNumber(self.first_block)
+    }
+
+    /// Number of blocks
+    pub fn count(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// The blocks the file holds
+    pub fn blocks(&self) -> Range<u64> {
+        self.first_block..self.first_block + self.offsets.len() as u64
+    }
+
+    /// Accumulator root the file records
+    pub fn accumulator(&self) -> B256 {
+        self.accumulator
+    }
+
+    /// Block `number`, `None` if the file does not hold it
+    pub fn block(&self, number: BlockNumber) -> Result<Option<Era1Block>> {
+        let Some(i) = number.ordinal_from(self.first_block()) else {
+            return Ok(None);
+        };
+        let Some(&offset) = self.offsets.get(i as usize) else {
+            return Ok(None);
+        };
+        let mut at = offset as usize;
+        let mut next = |expected: u16| -> Result<&[u8]> {
+            let (kind, data) = entry(&self.data, at)?;
+            if kind != expected {
+                return Err(SnapshotError::InvalidFormat(format!(
+                    "Era1 entry of type {:#x} where {:#x} was expected for block {}",
+                    kind, expected, number
+                )));
+            }
+            at += ENTRY_HEADER_LEN + data.len();
+            Ok(data)
+        };
+        let header = Header::decode(&mut unsnappy(next(TYPE_COMPRESSED_HEADER)?)?.as_slice())?;
+        let body = BlockBody::decode(&mut unsnappy(next(TYPE_COMPRESSED_BODY)?)?.as_slice())?;
+        let receipts = Vec::<ReceiptEnvelope>::decode(
+            &mut unsnappy(next(TYPE_COMPRESSED_RECEIPTS)?)?.as_slice(),
+        )?;
+        let total_difficulty = next(TYPE_TOTAL_DIFFICULTY)?;
+        if total_difficulty.len() != 32 || header.number != number.get() {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Era1 block {} is malformed",
+                number
+            )));
+        }
+        Ok(Some(Era1Block {
+            header,
+            body,
+            receipts,
+            total_difficulty: U256::from_le_slice(total_difficulty),
+        }))
+    }
+
+    /// Check the accumulator root against the blocks' hashes and total
+    /// difficulties
+    pub fn verify(&self) -> Result<()> {
+        let mut records = Vec::with_capacity(self.count());
+        for number in self.blocks() {
+            let block = self
+                .block(BlockNumber(number))?
+                .ok_or(SnapshotError::BlockNotFound(number))?;
+            records.push((block.header.hash_slow(), block.total_difficulty));
+        }
+        let actual = accumulator_root(&records);
+        if actual != self.accumulator {
+            return Err(SnapshotError::HashMismatch {
+                expected: self.accumulator,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Name of the era1 file of `epoch` with accumulator `root`
+pub fn era1_file_name(network: &str, epoch: u64, root: B256) -> String {
+    format!("{}-{:05}-{}.era1", network, epoch, hex::encode(&root[..4]))
+}
+
+/// Write the era1 files of `epochs` into `dir` from the block segments of
+/// `repo`; returns their paths
+///
+/// `total_difficulty` is that of the chain before the first epoch, zero
+/// from genesis on. `receipts` gives the receipts of each block from its
+/// header and transactions. Export stops at the merge, the first block
+/// without difficulty, leaving the last file short as other clients do.
+pub fn export_era1(
+    repo: &SnapshotRepo,
+    dir: &Path,
+    network: &str,
+    epochs: Range<u64>,
+    mut total_difficulty: U256,
+    mut receipts: impl FnMut(&Header, &[TxEnvelope]) -> Result<Vec<ReceiptEnvelope>>,
+) -> Result<Vec<PathBuf>> {
+    let headers = SegmentSet::<HeadersReader>::open(repo)?;
+    let bodies = SegmentSet::<BodiesReader>::open(repo)?;
+    let transactions = SegmentSet::<TransactionsReader>::open(repo)?;
+
+    let mut paths = Vec::new();
+    'epochs: for epoch in epochs {
+        let tmp_path = dir.join(format!("{}-{:05}.era1.tmp", network, epoch));
+        let mut writer = Era1Writer::new(BufWriter::new(File::create(&tmp_path)?))?;
+        let first = epoch * BLOCKS_PER_ERA1;
+        let mut merged = false;
+        for number in first..first + BLOCKS_PER_ERA1 {
+            let header = headers
+                .header(BlockNumber(number))?
+                .ok_or(SnapshotError::BlockNotFound(number))?;
+            if header.difficulty.is_zero() && number > 0 {
+                merged = true;
+                break;
+            }
+            let body = bodies
+                .body(BlockNumber(number))?
+                .ok_or(SnapshotError::BlockNotFound(number))?;
+            let stored = match body.tx_num(TxIndex(0)) {
+                Some(tx_num) => transactions
+                    .locate(tx_num.get())
+                    .ok_or(SnapshotError::BlockNotFound(number))?
+                    .0
+                    .transactions(&body)?,
+                None => Vec::new(),
+            };
+            let txs = stored
+                .iter()
+                .map(StoredTransaction::envelope)
+                .collect::<Result<Vec<_>>>()?;
+            total_difficulty += header.difficulty;
+            let block = Era1Block {
+                receipts: receipts(&header, &txs)?,
+                header,
+                body: BlockBody {
+                    transactions: txs,
+                    ommers: body.ommers,
+                    withdrawals: body.withdrawals.map(Into::into),
+                },
+                total_difficulty,
+            };
+            writer.add(&block)?;
+        }
+        if writer.count() == 0 {
+            drop(writer);
+            std::fs::remove_file(&tmp_path)?;
+            break 'epochs;
+        }
+        let (file, root) = writer.finish()?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        let path = dir.join(era1_file_name(network, epoch, root));
+        std::fs::rename(&tmp_path, &path)?;
+        tracing::debug!("Wrote {}", path.display());
+        paths.push(path);
+        if merged {
+            break;
+        }
+    }
+    Ok(paths)
+}
+
+/// Write the headers, bodies and transactions segments of `blocks`, with
+/// their indexes, into `dir` from the era1 files holding them; returns the
+/// TxNum after the last block's transactions
+///
+/// `blocks` is a block file range, its bounds multiples of 1000, and
+/// `first_tx_num` the TxNum its first block starts at: zero from genesis
+/// on, otherwise the one the previous range returned. Transaction senders
+/// are recovered from their signatures.
+pub fn import_era1(
+    era1: &[Era1Reader],
+    dir: &Path,
+    blocks: Range<u64>,
+    first_tx_num: TxNum,
+    fsync: bool,
+) -> Result<TxNum> {
+    let mut headers = HeaderSegmentWriter::new(dir, blocks.start, blocks.end)?;
+    if !fsync {
+        headers.disable_fsync();
+    }
+    let segment = |kind| {
+        let stem = SnapshotFile::stem(kind, blocks.start, blocks.end);
+        dir.join(format!("{}.seg", stem))
+    };
+    let compressor = |path: &Path, prefix: &str| {
+        Compressor::builder(path)
+            .log_prefix(prefix)
+            .fsync(fsync)
+            .build()
+    };
+    let bodies_path = segment(SnapshotType::Bodies);
+    let transactions_path = segment(SnapshotType::Transactions);
+    let mut bodies = compressor(&bodies_path, "bodies")?;
+    let mut transactions = compressor(&transactions_path, "transactions")?;
+
+    let mut tx_num = first_tx_num;
+    for number in blocks.clone() {
+        let block = era1
+            .iter()
+            .find(|file| file.blocks().contains(&number))
+            .map(|file| file.block(BlockNumber(number)))
+            .transpose()?
+            .flatten()
+            .ok_or(SnapshotError::BlockNotFound(number))?;
+        headers.add_header(&block.header)?;
+
+        let txs = &block.body.transactions;
+        let body = StoredBody {
+            base_tx_num: tx_num,
+            tx_count: txs.len() as u32 + 2,
+            ommers: block.body.ommers,
+            withdrawals: block.body.withdrawals.map(|w| w.into_inner()),
+        };
+        bodies.add_word(&body.encode())?;
+        // System transactions around the block's own are empty words
+        transactions.add_word(&[])?;
+        for tx in txs {
+            let sender = tx.recover_signer().map_err(|e| {
+                SnapshotError::InvalidFormat(format!(
+                    "Transaction {} of block {}: {}",
+                    tx.tx_hash(),
+                    number,
+                    e
+                ))
+            })?;
+            let mut word = vec![tx.tx_hash()[0]];
+            word.extend_from_slice(sender.as_slice());
+            word.extend_from_slice(&tx.encoded_2718());
+            transactions.add_word(&word)?;
+        }
+        transactions.add_word(&[])?;
+        tx_num = tx_num.offset(txs.len() as u64 + 2);
+    }
+
+    headers.finish()?;
+    bodies.compress()?;
+    transactions.compress()?;
+    build_block_index(&bodies_path, SnapshotType::Bodies, blocks.start, fsync)?;
+    build_transactions_index(&transactions_path, &bodies_path, fsync)?;
+    Ok(tx_num)
+}
+
+fn rlp(value: &impl Encodable) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.length());
+    value.encode(&mut out);
+    out
+}
+
+fn snappy(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+    encoder.write_all(data)?;
+    encoder.into_inner().map_err(|e| e.into_error().into())
+}
+
+fn unsnappy(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    snap::read::FrameDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+// The type and data of the e2store entry at `at`
+fn entry(data: &[u8], at: usize) -> Result<(u16, &[u8])> {
+    let eof = || SnapshotError::UnexpectedEof {
+        context: format!("era1 entry at {}", at),
+    };
+    let header = data.get(at..at + ENTRY_HEADER_LEN).ok_or_else(eof)?;
+    let kind = u16::from_le_bytes([header[0], header[1]]);
+    let len = u32::from_le_bytes(header[2..6].try_into().unwrap()) as usize;
+    let start = at + ENTRY_HEADER_LEN;
+    let body = data.get(start..start + len).ok_or_else(eof)?;
+    Ok((kind, body))
+}
+
+fn sha256(left: &[u8], right: &[u8]) -> [u8; 32] {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    context.update(left);
+    context.update(right);
+    context.finish().as_ref().try_into().unwrap()
+}
+
+// SSZ hash_tree_root of List[HeaderRecord, 8192], a header record being
+// the block hash and the total difficulty as a little-endian uint256
+fn accumulator_root(records: &[(B256, U256)]) -> B256 {
+    let mut layer: Vec<[u8; 32]> = records
+        .iter()
+        .map(|(hash, td)| sha256(hash.as_slice(), &td.to_le_bytes::<32>()))
+        .collect();
+    let mut zero = [0u8; 32];
+    for _ in 0..ACCUMULATOR_DEPTH {
+        if layer.len() % 2 == 1 {
+            layer.push(zero);
+        }
+        layer = layer
+            .chunks(2)
+            .map(|pair| sha256(&pair[0], &pair[1]))
+            .collect();
+        zero = sha256(&zero, &zero);
+    }
+    let root = layer.first().copied().unwrap_or(zero);
+    let mut length = [0u8; 32];
+    length[..8].copy_from_slice(&(records.len() as u64).to_le_bytes());
+    B256::from(sha256(&root, &length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::tests::signed_tx;
+    use alloy_consensus::{Receipt, ReceiptWithBloom};
+    use alloy_primitives::Bloom;
+
+    // A chain of `count` blocks with two transactions every 100 blocks; the
+    // last block is past the merge
+    fn chain(count: u64) -> Vec<Era1Block> {
+        let mut blocks: Vec<Era1Block> = Vec::new();
+        let mut total_difficulty = U256::ZERO;
+        for number in 0..count {
+            let difficulty = if number + 1 == count {
+                U256::ZERO
+            } else {
+                U256::from(1000 + number)
+            };
+            total_difficulty += difficulty;
+            let tx_count = if number % 100 == 99 { 2 } else { 0 };
+            let transactions: Vec<TxEnvelope> =
+                (0..tx_count).map(|i| signed_tx(number + i)).collect();
+            let receipts = (0..transactions.len() as u64)
+                .map(|i| {
+                    ReceiptEnvelope::Legacy(ReceiptWithBloom {
+                        receipt: Receipt {
+                            status: true.into(),
+                            cumulative_gas_used: 21_000 * (i as u128 + 1),
+                            logs: Vec::new(),
+                        },
+                        logs_bloom: Bloom::default(),
+                    })
+                })
+                .collect();
+            let header = Header {
+                number,
+                parent_hash: blocks
+                    .last()
+                    .map_or(B256::ZERO, |parent| parent.header.hash_slow()),
+                difficulty,
+                ..Default::default()
+            };
+            blocks.push(Era1Block {
+                header,
+                body: BlockBody {
+                    transactions,
+                    ommers: Vec::new(),
+                    withdrawals: None,
+                },
+                receipts,
+                total_difficulty,
+            });
+        }
+        blocks
+    }
+
+    fn write(blocks: &[Era1Block]) -> (Vec<u8>, B256) {
+        let mut writer = Era1Writer::new(Vec::new()).unwrap();
+        for block in blocks {
+            writer.add(block).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_era1_round_trip() {
+        let blocks = chain(300);
+        let (data, root) = write(&blocks[100..]);
+        let era1 = Era1Reader::from_bytes(data.clone()).unwrap();
+        assert_eq!(era1.first_block(), BlockNumber(100));
+        assert_eq!(era1.blocks(), 100..300);
+        assert_eq!(era1.accumulator(), root);
+        for block in &blocks[100..] {
+            let number = BlockNumber(block.header.number);
+            assert_eq!(era1.block(number).unwrap().as_ref(), Some(block));
+        }
+        assert_eq!(era1.block(BlockNumber(99)).unwrap(), None);
+        assert_eq!(era1.block(BlockNumber(300)).unwrap(), None);
+        era1.verify().unwrap();
+        assert_eq!(era1_file_name("mainnet", 3, root)[..14], *"mainnet-00003-");
+
+        // Blocks out of order
+        let mut writer = Era1Writer::new(Vec::new()).unwrap();
+        writer.add(&blocks[1]).unwrap();
+        assert!(writer.add(&blocks[3]).is_err());
+
+        // A total difficulty changed under the accumulator
+        let mut tampered = blocks[100..].to_vec();
+        tampered[5].total_difficulty += U256::from(1);
+        let (mut data_tampered, _) = write(&tampered);
+        let accumulator = data_tampered.len() - (ENTRY_HEADER_LEN + 16 + 8 * 200) - 32;
+        data_tampered[accumulator..accumulator + 32].copy_from_slice(root.as_slice());
+        assert!(matches!(
+            Era1Reader::from_bytes(data_tampered).unwrap().verify(),
+            Err(SnapshotError::HashMismatch { .. })
+        ));
+        assert!(Era1Reader::from_bytes(data[..data.len() - 1].to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_import_then_export() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let segments = tmp_dir.path().join("segments");
+        std::fs::create_dir(&segments).unwrap();
+        let blocks = chain(1000);
+        let era1 = Era1Reader::from_bytes(write(&blocks).0).unwrap();
+
+        let next = import_era1(&[era1], &segments, 0..1000, TxNum(0), false).unwrap();
+        let txs: u64 = blocks
+            .iter()
+            .map(|block| block.body.transactions.len() as u64 + 2)
+            .sum();
+        assert_eq!(next, TxNum(txs));
+
+        // The export stops before the merge block
+        let repo = SnapshotRepo::open(&segments).unwrap();
+        let receipts: std::collections::HashMap<u64, Vec<ReceiptEnvelope>> = blocks
+            .iter()
+            .map(|block| (block.header.number, block.receipts.clone()))
+            .collect();
+        let paths = export_era1(
+            &repo,
+            tmp_dir.path(),
+            "test",
+            0..2,
+            U256::ZERO,
+            |header, txs| {
+                assert_eq!(txs.len(), receipts[&header.number].len());
+                Ok(receipts[&header.number].clone())
+            },
+        )
+        .unwrap();
+        let (expected, root) = write(&blocks[..999]);
+        assert_eq!(
+            paths,
+            [tmp_dir.path().join(era1_file_name("test", 0, root))]
+        );
+        assert_eq!(std::fs::read(&paths[0]).unwrap(), expected);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::{CompressionError, IndexError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -5,15 +6,26 @@ pub enum SnapshotError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
-    #[error("Decompression error: {0}")]
-    Decompression(String),
+    #[error(transparent)]
+    Segment(CompressionError),
 
-    #[error("Index file error: {0}")]
-    Index(String),
+    #[error(transparent)]
+    Index(#[from] IndexError),
 
     #[error("RLP decoding error: {0}")]
     Rlp(#[from] alloy_rlp::Error),
 
+    #[error("JSON decoding error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+
+    #[cfg(feature = "mdbx")]
+    #[error("MDBX error: {0}")]
+    Mdbx(#[from] libmdbx::Error),
+
     #[error("Invalid snapshot format: {0}")]
     InvalidFormat(String),
 
@@ -32,8 +44,106 @@ pub enum SnapshotError {
     #[error("Invalid file path: {0}")]
     InvalidPath(String),
 
+    #[error("RecSplit collision: duplicate key fingerprint {0:#x}, rebuild with another salt")]
+    Collision(u64),
+
+    #[error("Torrent mismatch: {0}")]
+    TorrentMismatch(String),
+
+    #[error("Download of {url} failed: {reason}")]
+    Download { url: String, reason: String },
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
     #[error("Unexpected EOF while reading {context}")]
     UnexpectedEof { context: String },
+
+    #[error("Pruning would leave {files} without data for {from}..{to}")]
+    RetentionGap { files: String, from: u64, to: u64 },
+
+    #[error("Invalid header {number} ({hash:?}): {violation}")]
+    InvalidHeader {
+        number: u64,
+        hash: alloy_primitives::B256,
+        violation: ChainViolation,
+    },
+
+    #[error("Senders segment has {senders} words for {transactions} transactions")]
+    SendersMismatch { senders: usize, transactions: usize },
+
+    #[error("Body of block {number} does not match its header: {violation}")]
+    InvalidBody {
+        number: u64,
+        violation: BodyViolation,
+    },
+}
+
+/// Consensus rule a header breaks with respect to its parent, or to the
+/// forks of its chain
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ChainViolation {
+    #[error("parent hash {actual:?}, previous header is {expected:?}")]
+    ParentHash {
+        expected: alloy_primitives::B256,
+        actual: alloy_primitives::B256,
+    },
+
+    #[error("number {actual} where {expected} belongs")]
+    Number { expected: u64, actual: u64 },
+
+    #[error("timestamp {timestamp} not after the parent's {parent}")]
+    Timestamp { parent: u64, timestamp: u64 },
+
+    #[error("gas limit {gas_limit} too far from the parent's {parent}")]
+    GasLimit { parent: u64, gas_limit: u64 },
+
+    #[error("gas limit {0} below the minimum")]
+    GasLimitTooLow(u64),
+
+    #[error("{field} before {fork}")]
+    UnexpectedField {
+        field: &'static str,
+        fork: &'static str,
+    },
+
+    #[error("no {field} from {fork} on")]
+    MissingField {
+        field: &'static str,
+        fork: &'static str,
+    },
+
+    #[error("blob gas used {0} is not a whole number of blobs")]
+    BlobGasUsed(u64),
+}
+
+/// Root a block body does not hash to, with respect to its header
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum BodyViolation {
+    #[error("ommers hash {actual:?}, header has {expected:?}")]
+    OmmersHash {
+        expected: alloy_primitives::B256,
+        actual: alloy_primitives::B256,
+    },
+
+    /// `None` on one side when only the header or only the body has
+    /// withdrawals
+    #[error("withdrawals root {actual:?}, header has {expected:?}")]
+    WithdrawalsRoot {
+        expected: Option<alloy_primitives::B256>,
+        actual: Option<alloy_primitives::B256>,
+    },
+}
+
+// Index errors surfaced while reading a segment stay index errors
+impl From<CompressionError> for SnapshotError {
+    fn from(err: CompressionError) -> Self {
+        match err {
+            CompressionError::Index(err) => SnapshotError::Index(err),
+            CompressionError::Cancelled => SnapshotError::Cancelled,
+            err => SnapshotError::Segment(err),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, SnapshotError>;
@@ -0,0 +1,227 @@
+//! Existence filters of domain files (`.kvei`)
+//!
+//! Next to a domain's `.kv`, Erigon keeps a bloom filter of its keys so that
+//! lookups of absent keys skip the file without touching its index. The
+//! filter is holiman/bloomfilter's: `k` random 64-bit keys, each XORed with
+//! the key's hash to pick one of `m` bits, serialized big-endian behind a
+//! magic, followed by a SHA-384 of the body, all gzipped. Files of fewer
+//! than two keys are left empty and let every key through.
+//!
+//! Newer Erigon versions write binary fuse filters instead; those are
+//! rejected as an unknown format.
+
+use crate::snapshots::recsplit::{hash_key, random_salt, remix};
+use crate::snapshots::{Result, SnapshotError};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ring::digest::{digest, SHA384};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// From Go: holiman/bloomfilter/v2 binarymarshaler.go headerMagic
+const MAGIC: &[u8] = b"\0\0\0\0\0\0\0\0v02\n";
+const HASH_LEN: usize = 48;
+
+// From Go: erigon-lib/state/existence_filter.go NewExistenceFilter
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// A bloom filter of the keys of a domain file
+#[derive(Debug, Clone, Default)]
+pub struct ExistenceFilter {
+    // XORed with the key hash, one per bit probed; none for empty filters
+    keys: Vec<u64>,
+    // Number of bits
+    m: u64,
+    // Number of keys added
+    n: u64,
+    bits: Vec<u64>,
+}
+
+impl ExistenceFilter {
+    /// An empty filter sized for `key_count` keys at a 1% false positive
+    /// rate; with fewer than two keys it lets every key through, like
+    /// Erigon's
+    pub fn new(key_count: u64) -> Self {
+        if key_count < 2 {
+            return Self::default();
+        }
+        // From Go: bloomfilter OptimalM / OptimalK
+        let ln2 = std::f64::consts::LN_2;
+        let m = (-(key_count as f64) * FALSE_POSITIVE_RATE.ln() / (ln2 * ln2)).ceil() as u64;
+        let k = ((m as f64 / key_count as f64) * ln2).ceil().max(1.0) as u64;
+        let mut seed = random_salt() as u64;
+        let keys = (0..k)
+            .map(|_| {
+                seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+                remix(seed)
+            })
+            .collect();
+        Self {
+            keys,
+            m,
+            n: 0,
+            bits: vec![0; m.div_ceil(64) as usize],
+        }
+    }
+
+    /// Read a `.kvei` file
+    pub fn open(path: &Path) -> Result<Self> {
+        let compressed = fs::read(path)?;
+        if compressed.is_empty() {
+            return Ok(Self::default());
+        }
+        let invalid = |reason: &str| {
+            SnapshotError::InvalidFormat(format!("Existence filter {}: {}", path.display(), reason))
+        };
+        let mut data = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut data)
+            .map_err(|_| invalid("not a gzipped bloom filter"))?;
+        let body = data
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("unknown filter format"))?;
+        if body.len() < 24 + HASH_LEN || !(body.len() - HASH_LEN).is_multiple_of(8) {
+            return Err(invalid("truncated"));
+        }
+        let (body, hash) = body.split_at(body.len() - HASH_LEN);
+        if digest(&SHA384, body).as_ref() != hash {
+            return Err(invalid("hash mismatch"));
+        }
+
+        let mut words = body
+            .chunks_exact(8)
+            .map(|word| u64::from_be_bytes(word.try_into().unwrap()));
+        let (k, n, m) = (
+            words.next().unwrap_or(0),
+            words.next().unwrap_or(0),
+            words.next().unwrap_or(0),
+        );
+        let words: Vec<u64> = words.collect();
+        if k == 0 || m < 2 || words.len() as u64 != k.saturating_add(m.div_ceil(64)) {
+            return Err(invalid("inconsistent sizes"));
+        }
+        let (keys, bits) = words.split_at(k as usize);
+        Ok(Self {
+            keys: keys.to_vec(),
+            m,
+            n,
+            bits: bits.to_vec(),
+        })
+    }
+
+    /// Write the filter as a `.kvei` file, through a `.tmp` file renamed
+    /// into place
+    pub fn write(&self, path: &Path, fsync: bool) -> Result<()> {
+        let mut tmp_path = path.to_path_buf().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let mut file = File::create(&tmp_path)?;
+        if !self.keys.is_empty() {
+            let mut body = Vec::with_capacity(24 + 8 * (self.keys.len() + self.bits.len()));
+            for word in [self.keys.len() as u64, self.n, self.m]
+                .iter()
+                .chain(&self.keys)
+                .chain(&self.bits)
+            {
+                body.extend_from_slice(&word.to_be_bytes());
+            }
+            let mut gz = GzEncoder::new(&mut file, Compression::default());
+            gz.write_all(MAGIC)?;
+            gz.write_all(&body)?;
+            gz.write_all(digest(&SHA384, &body).as_ref())?;
+            gz.finish()?;
+        }
+        if fsync {
+            file.sync_all()?;
+        }
+        drop(file);
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Whether the filter lets every key through
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Number of keys added when the filter was built
+    pub fn key_count(&self) -> u64 {
+        self.n
+    }
+
+    // From Go: bloomfilter Filter.AddHash
+    /// Add a key by its hash, see [`ExistenceFilter::may_contain`]
+    pub fn add_hash(&mut self, hash: u64) {
+        for key in &self.keys {
+            let bit = (hash ^ key) % self.m;
+            self.bits[(bit >> 6) as usize] |= 1 << (bit & 63);
+        }
+        self.n += 1;
+    }
+
+    // From Go: bloomfilter Filter.ContainsHash
+    /// `false` if no key with this hash was added
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        self.keys.iter().all(|key| {
+            let bit = (hash ^ key) % self.m;
+            self.bits
+                .get((bit >> 6) as usize)
+                .is_some_and(|word| word >> (bit & 63) & 1 == 1)
+        })
+    }
+
+    /// `false` if `key` is not in the file; keys are hashed as Erigon
+    /// does, with murmur3 and the datadir's state salt
+    pub fn may_contain(&self, key: &[u8], salt: u32) -> bool {
+        self.contains_hash(hash_key(key, salt).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_round_trip() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("v1-accounts.0-64.kvei");
+        let salt = 5;
+        let mut filter = ExistenceFilter::new(1000);
+        for i in 0..1000u32 {
+            filter.add_hash(hash_key(&i.to_be_bytes(), salt).0);
+        }
+        filter.write(&path, false).unwrap();
+
+        let filter = ExistenceFilter::open(&path).unwrap();
+        assert_eq!(filter.key_count(), 1000);
+        assert!((0..1000u32).all(|i| filter.may_contain(&i.to_be_bytes(), salt)));
+        let false_positives = (1000..11_000u32)
+            .filter(|i| filter.may_contain(&i.to_be_bytes(), salt))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        // Any corruption of the body fails the hash check
+        let mut data = Vec::new();
+        GzDecoder::new(fs::read(&path).unwrap().as_slice())
+            .read_to_end(&mut data)
+            .unwrap();
+        data[MAGIC.len() + 30] ^= 1;
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&data).unwrap();
+        fs::write(&path, gz.finish().unwrap()).unwrap();
+        assert!(ExistenceFilter::open(&path).is_err());
+
+        // Tiny files are written empty and let everything through
+        let filter = ExistenceFilter::new(1);
+        filter.write(&path, false).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        let filter = ExistenceFilter::open(&path).unwrap();
+        assert!(filter.is_empty());
+        assert!(filter.may_contain(b"anything", salt));
+
+        fs::write(&path, b"fuse filter").unwrap();
+        assert!(ExistenceFilter::open(&path).is_err());
+    }
+}
@@ -0,0 +1,688 @@
+//! Tabular exports of decoded chain data, for analytics tools
+//!
+//! The headers or transactions of a block range are written one row each,
+//! with the columns the caller picks, as CSV or (`parquet` feature) Parquet
+//! files that DuckDB or Spark ingest directly. Numbers of up to 64 bits are
+//! integer columns; hashes, addresses and byte strings are 0x-prefixed hex
+//! and wider amounts decimal strings. Receipts are not part of block
+//! snapshots, so there is no table of them.
+
+use crate::snapshots::reader::{
+    BodiesReader, HeadersReader, StoredTransaction, TransactionsReader,
+};
+use crate::snapshots::types::{BlockNumber, TxIndex};
+use crate::snapshots::{Result, SnapshotError};
+use alloy_consensus::{Header, Transaction as _, TxEnvelope, Typed2718};
+use alloy_primitives::{hex, B256};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// File format of a table export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    /// Comma separated, with a header line; nulls are empty fields
+    Csv,
+    /// Snappy compressed Parquet
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl TableFormat {
+    /// File extension of the format, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            TableFormat::Parquet => "parquet",
+        }
+    }
+}
+
+// A value of a row; integer columns only hold `Int`, text columns `Text`
+enum Cell {
+    Int(Option<u64>),
+    Text(Option<String>),
+}
+
+fn hex_cell(bytes: impl AsRef<[u8]>) -> Cell {
+    Cell::Text(Some(hex::encode_prefixed(bytes)))
+}
+
+fn decimal_cell(value: impl ToString) -> Cell {
+    Cell::Text(Some(value.to_string()))
+}
+
+/// Column of a headers export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderColumn {
+    Number,
+    Hash,
+    ParentHash,
+    Timestamp,
+    Miner,
+    GasLimit,
+    GasUsed,
+    BaseFeePerGas,
+    Difficulty,
+    StateRoot,
+    TransactionsRoot,
+    ReceiptsRoot,
+    WithdrawalsRoot,
+    BlobGasUsed,
+    ExcessBlobGas,
+    ExtraData,
+}
+
+impl HeaderColumn {
+    pub const ALL: &'static [HeaderColumn] = &[
+        HeaderColumn::Number,
+        HeaderColumn::Hash,
+        HeaderColumn::ParentHash,
+        HeaderColumn::Timestamp,
+        HeaderColumn::Miner,
+        HeaderColumn::GasLimit,
+        HeaderColumn::GasUsed,
+        HeaderColumn::BaseFeePerGas,
+        HeaderColumn::Difficulty,
+        HeaderColumn::StateRoot,
+        HeaderColumn::TransactionsRoot,
+        HeaderColumn::ReceiptsRoot,
+        HeaderColumn::WithdrawalsRoot,
+        HeaderColumn::BlobGasUsed,
+        HeaderColumn::ExcessBlobGas,
+        HeaderColumn::ExtraData,
+    ];
+
+    /// Name of the column in the exported file
+    pub fn name(self) -> &'static str {
+        match self {
+            HeaderColumn::Number => "number",
+            HeaderColumn::Hash => "hash",
+            HeaderColumn::ParentHash => "parent_hash",
+            HeaderColumn::Timestamp => "timestamp",
+            HeaderColumn::Miner => "miner",
+            HeaderColumn::GasLimit => "gas_limit",
+            HeaderColumn::GasUsed => "gas_used",
+            HeaderColumn::BaseFeePerGas => "base_fee_per_gas",
+            HeaderColumn::Difficulty => "difficulty",
+            HeaderColumn::StateRoot => "state_root",
+            HeaderColumn::TransactionsRoot => "transactions_root",
+            HeaderColumn::ReceiptsRoot => "receipts_root",
+            HeaderColumn::WithdrawalsRoot => "withdrawals_root",
+            HeaderColumn::BlobGasUsed => "blob_gas_used",
+            HeaderColumn::ExcessBlobGas => "excess_blob_gas",
+            HeaderColumn::ExtraData => "extra_data",
+        }
+    }
+
+    fn is_int(self) -> bool {
+        matches!(
+            self,
+            HeaderColumn::Number
+                | HeaderColumn::Timestamp
+                | HeaderColumn::GasLimit
+                | HeaderColumn::GasUsed
+                | HeaderColumn::BaseFeePerGas
+                | HeaderColumn::BlobGasUsed
+                | HeaderColumn::ExcessBlobGas
+        )
+    }
+
+    fn cell(self, hash: B256, header: &Header) -> Cell {
+        match self {
+            HeaderColumn::Number => Cell::Int(Some(header.number)),
+            HeaderColumn::Hash => hex_cell(hash),
+            HeaderColumn::ParentHash => hex_cell(header.parent_hash),
+            HeaderColumn::Timestamp => Cell::Int(Some(header.timestamp)),
+            HeaderColumn::Miner => hex_cell(header.beneficiary),
+            HeaderColumn::GasLimit => Cell::Int(Some(header.gas_limit)),
+            HeaderColumn::GasUsed => Cell::Int(Some(header.gas_used)),
+            HeaderColumn::BaseFeePerGas => Cell::Int(header.base_fee_per_gas),
+            HeaderColumn::Difficulty => decimal_cell(header.difficulty),
+            HeaderColumn::StateRoot => hex_cell(header.state_root),
+            HeaderColumn::TransactionsRoot => hex_cell(header.transactions_root),
+            HeaderColumn::ReceiptsRoot => hex_cell(header.receipts_root),
+            HeaderColumn::WithdrawalsRoot => {
+                Cell::Text(header.withdrawals_root.map(hex::encode_prefixed))
+            }
+            HeaderColumn::BlobGasUsed => Cell::Int(header.blob_gas_used),
+            HeaderColumn::ExcessBlobGas => Cell::Int(header.excess_blob_gas),
+            HeaderColumn::ExtraData => hex_cell(&header.extra_data),
+        }
+    }
+}
+
+/// Column of a transactions export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionColumn {
+    BlockNumber,
+    BlockHash,
+    TransactionIndex,
+    TxNum,
+    Hash,
+    Type,
+    From,
+    /// Null for contract creations
+    To,
+    Nonce,
+    Value,
+    Gas,
+    /// The effective gas price, at the block's base fee for dynamic fee
+    /// transactions
+    GasPrice,
+    /// Null for legacy and access list transactions
+    MaxFeePerGas,
+    /// Null for legacy and access list transactions
+    MaxPriorityFeePerGas,
+    Input,
+}
+
+impl TransactionColumn {
+    pub const ALL: &'static [TransactionColumn] = &[
+        TransactionColumn::BlockNumber,
+        TransactionColumn::BlockHash,
+        TransactionColumn::TransactionIndex,
+        TransactionColumn::TxNum,
+        TransactionColumn::Hash,
+        TransactionColumn::Type,
+        TransactionColumn::From,
+        TransactionColumn::To,
+        TransactionColumn::Nonce,
+        TransactionColumn::Value,
+        TransactionColumn::Gas,
+        TransactionColumn::GasPrice,
+        TransactionColumn::MaxFeePerGas,
+        TransactionColumn::MaxPriorityFeePerGas,
+        TransactionColumn::Input,
+    ];
+
+    /// Name of the column in the exported file
+    pub fn name(self) -> &'static str {
+        match self {
+            TransactionColumn::BlockNumber => "block_number",
+            TransactionColumn::BlockHash => "block_hash",
+            TransactionColumn::TransactionIndex => "transaction_index",
+            TransactionColumn::TxNum => "tx_num",
+            TransactionColumn::Hash => "hash",
+            TransactionColumn::Type => "type",
+            TransactionColumn::From => "from",
+            TransactionColumn::To => "to",
+            TransactionColumn::Nonce => "nonce",
+            TransactionColumn::Value => "value",
+            TransactionColumn::Gas => "gas",
+            TransactionColumn::GasPrice => "gas_price",
+            TransactionColumn::MaxFeePerGas => "max_fee_per_gas",
+            TransactionColumn::MaxPriorityFeePerGas => "max_priority_fee_per_gas",
+            TransactionColumn::Input => "input",
+        }
+    }
+
+    fn is_int(self) -> bool {
+        matches!(
+            self,
+            TransactionColumn::BlockNumber
+                | TransactionColumn::TransactionIndex
+                | TransactionColumn::TxNum
+                | TransactionColumn::Type
+                | TransactionColumn::Nonce
+                | TransactionColumn::Gas
+        )
+    }
+
+    fn cell(self, tx: &TxRow<'_>) -> Cell {
+        let envelope = &tx.envelope;
+        match self {
+            TransactionColumn::BlockNumber => Cell::Int(Some(tx.header.number)),
+            TransactionColumn::BlockHash => hex_cell(tx.block_hash),
+            TransactionColumn::TransactionIndex => Cell::Int(Some(tx.index.get())),
+            TransactionColumn::TxNum => Cell::Int(Some(tx.stored.tx_num.get())),
+            TransactionColumn::Hash => hex_cell(envelope.tx_hash()),
+            TransactionColumn::Type => Cell::Int(Some(u64::from(envelope.ty()))),
+            TransactionColumn::From => hex_cell(tx.stored.sender),
+            TransactionColumn::To => Cell::Text(envelope.to().map(hex::encode_prefixed)),
+            TransactionColumn::Nonce => Cell::Int(Some(envelope.nonce())),
+            TransactionColumn::Value => decimal_cell(envelope.value()),
+            TransactionColumn::Gas => Cell::Int(Some(envelope.gas_limit())),
+            TransactionColumn::GasPrice => {
+                decimal_cell(envelope.effective_gas_price(tx.header.base_fee_per_gas))
+            }
+            TransactionColumn::MaxFeePerGas => Cell::Text(
+                envelope
+                    .is_dynamic_fee()
+                    .then(|| envelope.max_fee_per_gas().to_string()),
+            ),
+            TransactionColumn::MaxPriorityFeePerGas => Cell::Text(
+                envelope
+                    .max_priority_fee_per_gas()
+                    .map(|fee| fee.to_string()),
+            ),
+            TransactionColumn::Input => hex_cell(envelope.input()),
+        }
+    }
+}
+
+// A transaction with the block it is in
+struct TxRow<'a> {
+    header: &'a Header,
+    block_hash: B256,
+    index: TxIndex,
+    stored: &'a StoredTransaction,
+    envelope: TxEnvelope,
+}
+
+/// Write the headers of `blocks` that `headers` holds to `path`, returning
+/// the number of rows written
+///
+/// Needs the headers index, like [`HeadersReader::iter_range`]. The file is
+/// written under a `.tmp` name and renamed into place once complete.
+pub fn export_headers(
+    headers: &HeadersReader,
+    blocks: Range<BlockNumber>,
+    columns: &[HeaderColumn],
+    format: TableFormat,
+    path: &Path,
+) -> Result<u64> {
+    let mut table = TableWriter::create(
+        path,
+        format,
+        columns.iter().map(|c| (c.name(), c.is_int())).collect(),
+    )?;
+    for item in headers.iter_range(blocks.start, blocks.end)? {
+        let (hash, header) = item?;
+        table.push(columns.iter().map(|c| c.cell(hash, &header)).collect())?;
+    }
+    table.finish()
+}
+
+/// Write the transactions of the blocks in `blocks` to `path`, returning
+/// the number of rows written
+///
+/// System transactions are left out. All three snapshots need their
+/// indexes; blocks missing from the bodies snapshot are an error.
+pub fn export_transactions(
+    headers: &HeadersReader,
+    bodies: &BodiesReader,
+    transactions: &TransactionsReader,
+    blocks: Range<BlockNumber>,
+    columns: &[TransactionColumn],
+    format: TableFormat,
+    path: &Path,
+) -> Result<u64> {
+    let mut table = TableWriter::create(
+        path,
+        format,
+        columns.iter().map(|c| (c.name(), c.is_int())).collect(),
+    )?;
+    for item in headers.iter_range(blocks.start, blocks.end)? {
+        let (block_hash, header) = item?;
+        let body = bodies
+            .body_by_number(BlockNumber(header.number))?
+            .ok_or(SnapshotError::BlockNotFound(header.number))?;
+        for (i, stored) in transactions.transactions(&body)?.iter().enumerate() {
+            let tx = TxRow {
+                header: &header,
+                block_hash,
+                index: TxIndex(i as u64),
+                stored,
+                envelope: stored.envelope()?,
+            };
+            table.push(columns.iter().map(|c| c.cell(&tx)).collect())?;
+        }
+    }
+    table.finish()
+}
+
+// Rows buffered before they go to the Parquet writer as one batch
+#[cfg(feature = "parquet")]
+const PARQUET_BATCH_ROWS: usize = 8192;
+
+enum Sink {
+    Csv(BufWriter<File>),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<parquet_sink::ParquetSink>),
+}
+
+// Rows written under a temporary name, renamed into place by `finish`
+struct TableWriter<'a> {
+    sink: Sink,
+    path: &'a Path,
+    tmp_path: String,
+    rows: u64,
+}
+
+impl<'a> TableWriter<'a> {
+    // `columns` holds the name of each column and whether it is an integer
+    // one
+    fn create(
+        path: &'a Path,
+        format: TableFormat,
+        columns: Vec<(&'static str, bool)>,
+    ) -> Result<Self> {
+        let tmp_path = format!("{}.tmp", path.display());
+        let file = File::create(&tmp_path)?;
+        let sink = match format {
+            TableFormat::Csv => {
+                let mut writer = BufWriter::new(file);
+                let names: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+                writeln!(writer, "{}", names.join(","))?;
+                Sink::Csv(writer)
+            }
+            #[cfg(feature = "parquet")]
+            TableFormat::Parquet => {
+                Sink::Parquet(Box::new(parquet_sink::ParquetSink::new(file, &columns)?))
+            }
+        };
+        Ok(Self {
+            sink,
+            path,
+            tmp_path,
+            rows: 0,
+        })
+    }
+
+    fn push(&mut self, row: Vec<Cell>) -> Result<()> {
+        match &mut self.sink {
+            Sink::Csv(writer) => {
+                // Values are numbers and hex, which never need quoting
+                for (i, cell) in row.into_iter().enumerate() {
+                    if i > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    match cell {
+                        Cell::Int(Some(n)) => write!(writer, "{}", n)?,
+                        Cell::Text(Some(s)) => writer.write_all(s.as_bytes())?,
+                        Cell::Int(None) | Cell::Text(None) => {}
+                    }
+                }
+                writer.write_all(b"\n")?;
+            }
+            #[cfg(feature = "parquet")]
+            Sink::Parquet(sink) => sink.push(row)?,
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<u64> {
+        match self.sink {
+            Sink::Csv(writer) => {
+                writer.into_inner().map_err(|e| e.into_error())?;
+            }
+            #[cfg(feature = "parquet")]
+            Sink::Parquet(sink) => sink.finish()?,
+        }
+        fs::rename(&self.tmp_path, self.path)?;
+        tracing::debug!("Exported {} rows to {}", self.rows, self.path.display());
+        Ok(self.rows)
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_sink {
+    use super::{Cell, PARQUET_BATCH_ROWS};
+    use crate::snapshots::Result;
+    use arrow_array::builder::{StringBuilder, UInt64Builder};
+    use arrow_array::{ArrayRef, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema, SchemaRef};
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+    use std::fs::File;
+    use std::sync::Arc;
+
+    enum Column {
+        Int(UInt64Builder),
+        Text(StringBuilder),
+    }
+
+    pub(super) struct ParquetSink {
+        writer: ArrowWriter<File>,
+        schema: SchemaRef,
+        columns: Vec<Column>,
+        buffered: usize,
+    }
+
+    impl ParquetSink {
+        pub(super) fn new(file: File, columns: &[(&'static str, bool)]) -> Result<Self> {
+            let fields: Vec<Field> = columns
+                .iter()
+                .map(|&(name, is_int)| {
+                    let data_type = if is_int {
+                        DataType::UInt64
+                    } else {
+                        DataType::Utf8
+                    };
+                    Field::new(name, data_type, true)
+                })
+                .collect();
+            let schema = Arc::new(Schema::new(fields));
+            let props = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let writer = ArrowWriter::try_new(file, schema.clone(), Some(props))?;
+            let columns = columns
+                .iter()
+                .map(|&(_, is_int)| {
+                    if is_int {
+                        Column::Int(UInt64Builder::new())
+                    } else {
+                        Column::Text(StringBuilder::new())
+                    }
+                })
+                .collect();
+            Ok(Self {
+                writer,
+                schema,
+                columns,
+                buffered: 0,
+            })
+        }
+
+        pub(super) fn push(&mut self, row: Vec<Cell>) -> Result<()> {
+            for (column, cell) in self.columns.iter_mut().zip(row) {
+                match (column, cell) {
+                    (Column::Int(builder), Cell::Int(value)) => builder.append_option(value),
+                    (Column::Text(builder), Cell::Text(value)) => builder.append_option(value),
+                    _ => unreachable!("cell kinds follow their column's"),
+                }
+            }
+            self.buffered += 1;
+            if self.buffered == PARQUET_BATCH_ROWS {
+                self.flush()?;
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            let arrays: Vec<ArrayRef> = self
+                .columns
+                .iter_mut()
+                .map(|column| -> ArrayRef {
+                    match column {
+                        Column::Int(builder) => Arc::new(builder.finish()),
+                        Column::Text(builder) => Arc::new(builder.finish()),
+                    }
+                })
+                .collect();
+            let batch = RecordBatch::try_new(self.schema.clone(), arrays)
+                .map_err(parquet::errors::ParquetError::from)?;
+            self.writer.write(&batch)?;
+            self.buffered = 0;
+            Ok(())
+        }
+
+        pub(super) fn finish(mut self) -> Result<()> {
+            if self.buffered > 0 {
+                self.flush()?;
+            }
+            self.writer.close()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::reader::StoredBody;
+    use crate::snapshots::types::TxNum;
+    use crate::snapshots::HeaderSegmentWriter;
+    use crate::testutil::tests::{signed_tx, write_indexed};
+    use alloy_eips::eip2718::Encodable2718;
+    use alloy_primitives::{keccak256, Address};
+    use tempfile::TempDir;
+
+    // Headers, bodies and transactions of blocks 0..1000, block b holding
+    // b % 3 transactions
+    fn write_snapshots(dir: &Path) -> (HeadersReader, BodiesReader, TransactionsReader) {
+        let mut writer = HeaderSegmentWriter::new(dir, 0, 1000).unwrap();
+        writer.disable_fsync();
+        let mut bodies = Vec::new();
+        let mut tx_words = Vec::new();
+        let mut nonce = 0;
+        for b in 0..1000u64 {
+            writer
+                .add_header(&Header {
+                    number: b,
+                    timestamp: 1_000 + b,
+                    gas_limit: 30_000_000,
+                    base_fee_per_gas: Some(10),
+                    ..Default::default()
+                })
+                .unwrap();
+            let n = b % 3;
+            bodies.push(
+                StoredBody {
+                    base_tx_num: TxNum(tx_words.len() as u64),
+                    tx_count: n as u32 + 2,
+                    ..Default::default()
+                }
+                .encode(),
+            );
+            tx_words.push(Vec::new());
+            for _ in 0..n {
+                let envelope = signed_tx(nonce);
+                nonce += 1;
+                let mut word = vec![envelope.tx_hash()[0]];
+                word.extend_from_slice(Address::with_last_byte(0xee).as_slice());
+                word.extend_from_slice(&envelope.encoded_2718());
+                tx_words.push(word);
+            }
+            tx_words.push(Vec::new());
+        }
+        let (headers_path, _) = writer.finish().unwrap();
+
+        let bodies_path = dir.join("v1-000000-001000-bodies.seg");
+        write_indexed(&bodies_path, &bodies, 0, |i, _| i.to_be_bytes().to_vec());
+        let txs_path = dir.join("v1-000000-001000-transactions.seg");
+        write_indexed(&txs_path, &tx_words, 0, |i, word| {
+            if word.is_empty() {
+                i.to_be_bytes().to_vec()
+            } else {
+                keccak256(&word[21..]).to_vec()
+            }
+        });
+        (
+            HeadersReader::new(&headers_path).unwrap(),
+            BodiesReader::new(&bodies_path).unwrap(),
+            TransactionsReader::new(&txs_path).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let tmp_dir = TempDir::new().unwrap();
+        let (headers, bodies, transactions) = write_snapshots(tmp_dir.path());
+
+        let path = tmp_dir.path().join("headers.csv");
+        let columns = [
+            HeaderColumn::Number,
+            HeaderColumn::Timestamp,
+            HeaderColumn::WithdrawalsRoot,
+            HeaderColumn::Hash,
+        ];
+        let rows = export_headers(
+            &headers,
+            BlockNumber(10)..BlockNumber(20),
+            &columns,
+            TableFormat::Csv,
+            &path,
+        )
+        .unwrap();
+        assert_eq!(rows, 10);
+        let csv = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], "number,timestamp,withdrawals_root,hash");
+        let (hash, _) = headers.header(10).unwrap().unwrap();
+        assert_eq!(lines[1], format!("10,1010,,{:?}", hash));
+        assert!(!Path::new(&format!("{}.tmp", path.display())).exists());
+
+        // Blocks 3..6 hold 0, 1 and 2 transactions
+        let path = tmp_dir.path().join("transactions.csv");
+        let rows = export_transactions(
+            &headers,
+            &bodies,
+            &transactions,
+            BlockNumber(3)..BlockNumber(6),
+            TransactionColumn::ALL,
+            TableFormat::Csv,
+            &path,
+        )
+        .unwrap();
+        assert_eq!(rows, 3);
+        let csv = fs::read_to_string(&path).unwrap();
+        let lines: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+        assert_eq!(lines[0].len(), TransactionColumn::ALL.len());
+        assert_eq!(lines[0][11], "gas_price");
+        // Block 4 holds nonce 3, a dynamic fee transaction paying the base
+        // fee and its tip; block 5 nonces 4, a legacy one, and 5
+        let row = |i: usize, columns: Range<usize>| lines[i][columns].join(",");
+        assert_eq!(row(1, 0..1), "4");
+        assert_eq!(row(1, 2..4), "0,12");
+        assert_eq!(row(1, 5..6), "2");
+        assert_eq!(row(1, 8..14), "3,3,21000,12,50,2");
+        assert_eq!(row(2, 3..4), "15");
+        assert_eq!(row(2, 5..6), "0");
+        assert_eq!(row(2, 11..15), "30,,,0x");
+        assert_eq!(row(3, 2..4), "1,16");
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn test_export_parquet() {
+        use arrow_array::cast::AsArray;
+        use arrow_array::types::UInt64Type;
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let (headers, _, _) = write_snapshots(tmp_dir.path());
+        let path = tmp_dir.path().join("headers.parquet");
+        let rows = export_headers(
+            &headers,
+            BlockNumber(0)..BlockNumber(1000),
+            HeaderColumn::ALL,
+            TableFormat::Parquet,
+            &path,
+        )
+        .unwrap();
+        assert_eq!(rows, 1000);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut numbers = Vec::new();
+        for batch in reader {
+            let batch = batch.unwrap();
+            assert_eq!(batch.num_columns(), HeaderColumn::ALL.len());
+            assert_eq!(batch.schema().field(1).name(), "hash");
+            let column = batch.column(0).as_primitive::<UInt64Type>();
+            numbers.extend(column.values().iter().copied());
+            assert_eq!(batch.column(12).null_count(), batch.num_rows());
+        }
+        assert_eq!(numbers, (0..1000).collect::<Vec<_>>());
+    }
+}
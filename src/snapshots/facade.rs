@@ -0,0 +1,470 @@
+//! One entry point to a whole snapshot directory
+//!
+//! [`Snapshots::open`] scans the directory once and opens what the other
+//! modules read separately: the block segments of [`SnapshotRepo`], the
+//! headers, bodies and transactions as [`SegmentSet`]s, and optionally the
+//! state files behind a [`TemporalReader`]. [`Snapshots::capabilities`]
+//! reports what the directory holds, for callers that need to know which
+//! lookups can succeed before making them.
+
+#[cfg(feature = "cache")]
+use crate::snapshots::cache::CachedReader;
+use crate::snapshots::reader::{
+    BodiesReader, HeadersReader, StoredBody, StoredTransaction, TransactionsReader,
+};
+use crate::snapshots::repo::{SnapshotFile, SnapshotRepo, SnapshotType};
+use crate::snapshots::set::{SegmentSet, SegmentSetMember};
+use crate::snapshots::state::{state_files, STATE_ACCESSORS};
+use crate::snapshots::temporal::TemporalReader;
+use crate::snapshots::types::{BlockNumber, TxNum};
+use crate::snapshots::{Result, SnapshotError};
+use alloy_consensus::Header;
+#[cfg(feature = "cache")]
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Headers and bodies, kept decoded in an LRU with the `cache` feature
+#[cfg(feature = "cache")]
+type Blocks<T> = CachedReader<SegmentSet<T>>;
+#[cfg(not(feature = "cache"))]
+type Blocks<T> = SegmentSet<T>;
+
+/// What [`Snapshots::open`] opens, and how
+#[derive(Debug, Clone)]
+pub struct SnapshotsConfig {
+    dir: PathBuf,
+    open_file_limit: Option<usize>,
+    state: bool,
+    verify_bodies: bool,
+    #[cfg(feature = "cache")]
+    cache_capacity: NonZeroUsize,
+}
+
+impl SnapshotsConfig {
+    /// Read the snapshot directory `dir`: its block segments, not its state
+    /// files
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            open_file_limit: None,
+            state: false,
+            verify_bodies: false,
+            #[cfg(feature = "cache")]
+            cache_capacity: NonZeroUsize::new(1024).unwrap(),
+        }
+    }
+
+    /// See [`SnapshotRepo::with_open_file_limit`]
+    pub fn open_file_limit(mut self, limit: usize) -> Self {
+        self.open_file_limit = Some(limit);
+        self
+    }
+
+    /// Also open the state files, for [`Snapshots::state`] (default: false)
+    pub fn state(mut self, state: bool) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Check each body [`Snapshots::body`] returns against its header, see
+    /// [`StoredBody::verify`] (default: false)
+    pub fn verify_bodies(mut self, verify: bool) -> Self {
+        self.verify_bodies = verify;
+        self
+    }
+
+    /// Keep up to `capacity` headers and as many bodies decoded (default:
+    /// 1024)
+    #[cfg(feature = "cache")]
+    pub fn cache_capacity(mut self, capacity: NonZeroUsize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+}
+
+/// The block segments and state files of a snapshot directory
+///
+/// Everything is opened up front and lookups take `&self`; the caches of
+/// open files and decoded blocks are updated internally, so one `Snapshots`
+/// serves all the threads or tasks reading the directory. Segments without
+/// their `.idx` cannot be addressed and are left out of the lookups;
+/// [`Snapshots::capabilities`] lists them.
+pub struct Snapshots {
+    repo: SnapshotRepo,
+    headers: Blocks<HeadersReader>,
+    bodies: Blocks<BodiesReader>,
+    transactions: SegmentSet<TransactionsReader>,
+    state: Option<TemporalReader>,
+    verify_bodies: bool,
+}
+
+impl Snapshots {
+    pub fn open(config: SnapshotsConfig) -> Result<Self> {
+        let mut repo = SnapshotRepo::open(&config.dir)?;
+        if let Some(limit) = config.open_file_limit {
+            repo = repo.with_open_file_limit(limit);
+        }
+        let headers = indexed_set(&repo)?;
+        let bodies = indexed_set(&repo)?;
+        #[cfg(feature = "cache")]
+        let (headers, bodies) = (
+            CachedReader::new(headers, config.cache_capacity),
+            CachedReader::new(bodies, config.cache_capacity),
+        );
+        let transactions = indexed_set(&repo)?;
+        let state = match config.state {
+            true => Some(TemporalReader::open(&config.dir)?),
+            false => None,
+        };
+        Ok(Self {
+            repo,
+            headers,
+            bodies,
+            transactions,
+            state,
+            verify_bodies: config.verify_bodies,
+        })
+    }
+
+    pub fn repo(&self) -> &SnapshotRepo {
+        &self.repo
+    }
+
+    pub fn headers(&self) -> &SegmentSet<HeadersReader> {
+        #[cfg(feature = "cache")]
+        return self.headers.inner();
+        #[cfg(not(feature = "cache"))]
+        return &self.headers;
+    }
+
+    pub fn bodies(&self) -> &SegmentSet<BodiesReader> {
+        #[cfg(feature = "cache")]
+        return self.bodies.inner();
+        #[cfg(not(feature = "cache"))]
+        return &self.bodies;
+    }
+
+    pub fn transactions(&self) -> &SegmentSet<TransactionsReader> {
+        &self.transactions
+    }
+
+    /// The state files, if [`SnapshotsConfig::state`] asked for them
+    pub fn state(&self) -> Option<&TemporalReader> {
+        self.state.as_ref()
+    }
+
+    /// The cached headers, with their hit and miss counts
+    #[cfg(feature = "cache")]
+    pub fn header_cache(&self) -> &CachedReader<SegmentSet<HeadersReader>> {
+        &self.headers
+    }
+
+    /// The cached bodies, with their hit and miss counts
+    #[cfg(feature = "cache")]
+    pub fn body_cache(&self) -> &CachedReader<SegmentSet<BodiesReader>> {
+        &self.bodies
+    }
+
+    /// The header of block `number`, `None` if no indexed segment holds it
+    pub fn header(&self, number: BlockNumber) -> Result<Option<Header>> {
+        #[cfg(feature = "cache")]
+        return match self.headers.inner().ids() {
+            Some(_) => Ok(self
+                .headers
+                .get_by_number(number)?
+                .map(|(_, header)| header)),
+            None => Ok(None),
+        };
+        #[cfg(not(feature = "cache"))]
+        return self.headers.header(number);
+    }
+
+    /// The body of block `number`, `None` if no indexed segment holds it
+    ///
+    /// With [`SnapshotsConfig::verify_bodies`] the body is checked against
+    /// its header, which must be there too.
+    pub fn body(&self, number: BlockNumber) -> Result<Option<StoredBody>> {
+        #[cfg(feature = "cache")]
+        let body = match self.bodies.inner().ids() {
+            Some(_) => self.bodies.get_by_number(number)?,
+            None => None,
+        };
+        #[cfg(not(feature = "cache"))]
+        let body = self.bodies.body(number)?;
+        if let Some(body) = body.as_ref().filter(|_| self.verify_bodies) {
+            let header = self
+                .header(number)?
+                .ok_or(SnapshotError::BlockNotFound(number.get()))?;
+            body.verify(&header)?;
+        }
+        Ok(body)
+    }
+
+    /// See [`SegmentSet::transaction`]
+    pub fn transaction(&self, tx_num: TxNum) -> Result<Option<StoredTransaction>> {
+        self.transactions.transaction(tx_num)
+    }
+
+    /// The segments and state files of the directory, with the ranges they
+    /// cover and whether their accessors are there
+    ///
+    /// State files are looked for on disk whether or not they were opened.
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        let segments = self
+            .repo
+            .files()
+            .iter()
+            .map(|file| SegmentInfo {
+                kind: file.kind,
+                path: file.path.clone(),
+                blocks: file.from_block..file.to_block,
+                indexed: file.index_path().exists(),
+            })
+            .collect();
+
+        let files = state_files(self.repo.dir())?;
+        let mut state = Vec::new();
+        for file in &files {
+            let Some((_, extensions)) = STATE_ACCESSORS
+                .iter()
+                .find(|(extension, _)| *extension == file.extension)
+            else {
+                continue;
+            };
+            let stem = file.path.file_stem();
+            let mut accessors: Vec<String> = files
+                .iter()
+                .filter(|other| {
+                    other.path.file_stem() == stem && extensions.contains(&other.extension.as_str())
+                })
+                .map(|other| other.extension.clone())
+                .collect();
+            accessors.sort();
+            state.push(StateFileInfo {
+                name: file.name.clone(),
+                extension: file.extension.clone(),
+                path: file.path.clone(),
+                steps: file.from_step..file.to_step,
+                accessors,
+            });
+        }
+        state.sort_by(|a, b| {
+            (&a.name, &a.extension, a.steps.start, a.steps.end).cmp(&(
+                &b.name,
+                &b.extension,
+                b.steps.start,
+                b.steps.end,
+            ))
+        });
+        Ok(Capabilities { segments, state })
+    }
+}
+
+// The segments of `T`'s type that have their `.idx`
+fn indexed_set<T: SegmentSetMember>(repo: &SnapshotRepo) -> Result<SegmentSet<T>> {
+    SegmentSet::from_files(repo.files_of(T::KIND).filter(|file: &&SnapshotFile| {
+        let indexed = file.index_path().exists();
+        if !indexed {
+            tracing::debug!("Skipping {}: no index", file.path.display());
+        }
+        indexed
+    }))
+}
+
+/// What a snapshot directory holds, from [`Snapshots::capabilities`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct Capabilities {
+    /// Block segments, sorted by type and block range
+    pub segments: Vec<SegmentInfo>,
+    /// State files holding data, sorted by name, extension and steps
+    pub state: Vec<StateFileInfo>,
+}
+
+/// A block segment of [`Capabilities`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct SegmentInfo {
+    pub kind: SnapshotType,
+    pub path: PathBuf,
+    /// Blocks from the file name; slots for caplin types
+    pub blocks: Range<u64>,
+    /// Whether the `.idx` is there
+    pub indexed: bool,
+}
+
+/// A state file of [`Capabilities`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct StateFileInfo {
+    /// Domain or index name, e.g. "accounts" or "logaddrs"
+    pub name: String,
+    /// "kv", "v" or "ef"
+    pub extension: String,
+    pub path: PathBuf,
+    pub steps: Range<u64>,
+    /// Extensions of the accessors found for the file, e.g. "kvi" or "bt"
+    pub accessors: Vec<String>,
+}
+
+impl Capabilities {
+    /// The types there are segments of, in order
+    pub fn kinds(&self) -> Vec<SnapshotType> {
+        let mut kinds: Vec<SnapshotType> = self.segments.iter().map(|s| s.kind).collect();
+        kinds.dedup();
+        kinds
+    }
+
+    /// The blocks segments of `kind` cover, adjacent and overlapping files
+    /// merged
+    pub fn block_ranges(&self, kind: SnapshotType) -> Vec<Range<u64>> {
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        for segment in self.segments.iter().filter(|s| s.kind == kind) {
+            match ranges.last_mut() {
+                Some(last) if segment.blocks.start <= last.end => {
+                    last.end = last.end.max(segment.blocks.end);
+                }
+                _ => ranges.push(segment.blocks.clone()),
+            }
+        }
+        ranges
+    }
+
+    /// Segments whose `.idx` is missing
+    pub fn unindexed(&self) -> impl Iterator<Item = &SegmentInfo> {
+        self.segments.iter().filter(|s| !s.indexed)
+    }
+
+    /// The steps the files of a state name and extension cover, e.g.
+    /// `("accounts", "kv")`, adjacent and overlapping files merged
+    pub fn step_ranges(&self, name: &str, extension: &str) -> Vec<Range<u64>> {
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        for file in self
+            .state
+            .iter()
+            .filter(|f| f.name == name && f.extension == extension)
+        {
+            match ranges.last_mut() {
+                Some(last) if file.steps.start <= last.end => {
+                    last.end = last.end.max(file.steps.end);
+                }
+                _ => ranges.push(file.steps.clone()),
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::history::tests::{write_history, Changes};
+    use crate::testutil::tests::write_headers;
+    use crate::testutil::write_segment;
+
+    #[test]
+    fn test_snapshots_verify_bodies() {
+        use crate::snapshots::build_block_index;
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        write_headers(dir, 0..1000);
+        let path = dir.join(SnapshotFile::stem(SnapshotType::Bodies, 0, 1000) + ".seg");
+        let bodies: Vec<Vec<u8>> = (0..1000u64)
+            .map(|number| {
+                StoredBody {
+                    base_tx_num: TxNum(number * 2),
+                    tx_count: 2,
+                    // The headers have none
+                    ommers: match number {
+                        7 => vec![Header::default()],
+                        _ => Vec::new(),
+                    },
+                    ..Default::default()
+                }
+                .encode()
+            })
+            .collect();
+        write_segment(&path, &bodies).unwrap();
+        build_block_index(&path, SnapshotType::Bodies, 0, false).unwrap();
+
+        let snapshots = Snapshots::open(SnapshotsConfig::new(dir)).unwrap();
+        assert_eq!(
+            snapshots
+                .body(BlockNumber(7))
+                .unwrap()
+                .unwrap()
+                .ommers
+                .len(),
+            1
+        );
+        let snapshots = Snapshots::open(SnapshotsConfig::new(dir).verify_bodies(true)).unwrap();
+        assert!(snapshots.body(BlockNumber(6)).unwrap().is_some());
+        assert!(matches!(
+            snapshots.body(BlockNumber(7)),
+            Err(SnapshotError::InvalidBody { number: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn test_snapshots_capabilities() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        for range in [0..1000, 1000..2000, 3000..4000] {
+            write_headers(dir, range);
+        }
+        let unindexed = dir.join(SnapshotFile::stem(SnapshotType::Headers, 3000, 4000) + ".idx");
+        std::fs::remove_file(&unindexed).unwrap();
+        let changes: Vec<(Vec<u8>, Changes)> = vec![(vec![0xaa; 20], vec![(5, vec![1])])];
+        write_history(dir, "v1-code.0-1", &changes, true);
+        write_history(dir, "v1-code.1-2", &changes, false);
+
+        let snapshots = Snapshots::open(SnapshotsConfig::new(dir).state(true)).unwrap();
+        assert!(snapshots.state().is_some());
+        assert_eq!(snapshots.headers().ids(), Some(0..2000));
+        let header = snapshots.header(BlockNumber(1500)).unwrap().unwrap();
+        assert_eq!(header.number, 1500);
+        assert!(snapshots.header(BlockNumber(3500)).unwrap().is_none());
+        assert!(snapshots.body(BlockNumber(0)).unwrap().is_none());
+        assert!(snapshots.transaction(TxNum(0)).unwrap().is_none());
+
+        let capabilities = snapshots.capabilities().unwrap();
+        assert_eq!(capabilities.kinds(), [SnapshotType::Headers]);
+        assert_eq!(
+            capabilities.block_ranges(SnapshotType::Headers),
+            [0..2000, 3000..4000]
+        );
+        assert!(capabilities.block_ranges(SnapshotType::Bodies).is_empty());
+        let missing: Vec<_> = capabilities.unindexed().map(|s| &s.path).collect();
+        assert_eq!(missing, [&unindexed.with_extension("seg")]);
+
+        let code: Vec<_> = capabilities
+            .state
+            .iter()
+            .map(|f| (f.extension.as_str(), f.steps.clone(), f.accessors.clone()))
+            .collect();
+        assert_eq!(
+            code,
+            [
+                ("ef", 0..1, vec!["efi".to_string()]),
+                ("ef", 1..2, vec![]),
+                ("v", 0..1, vec!["vi".to_string()]),
+                ("v", 1..2, vec![]),
+            ]
+        );
+        assert_eq!(capabilities.step_ranges("code", "v"), vec![0..2]);
+    }
+}
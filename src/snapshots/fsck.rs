@@ -0,0 +1,515 @@
+//! Cross-file consistency checks of a snapshot directory
+//!
+//! Each segment and index can be fine on its own and still not fit the
+//! others: an index built for an older segment, a transactions segment cut
+//! short, a range nobody downloaded. [`SnapshotRepo::fsck`] reads the
+//! headers, bodies and transactions segments of a directory, the ones other
+//! segments do not cover, and reports every mismatch it finds between them
+//! instead of stopping at the first.
+
+use crate::cancel::{self, CancellationToken};
+use crate::decompress::Decompressor;
+use crate::snapshots::reader::StoredBody;
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::snapshots::repo::{SnapshotFile, SnapshotRepo, SnapshotType};
+use crate::snapshots::{Result, SnapshotError};
+use alloy_consensus::Header;
+use alloy_rlp::Decodable;
+use std::path::{Path, PathBuf};
+
+/// What [`SnapshotRepo::fsck`] found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct FsckReport {
+    /// Segments checked
+    pub segments: usize,
+    pub violations: Vec<FsckViolation>,
+}
+
+impl FsckReport {
+    /// Whether no violation was found
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// A mismatch between the files of a snapshot directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(
+        tag = "violation",
+        rename_all = "camelCase",
+        rename_all_fields = "camelCase"
+    )
+)]
+pub enum FsckViolation {
+    /// A file could not be opened or decoded
+    Unreadable { path: PathBuf, error: String },
+    /// Blocks between two segments of `kind` that none holds
+    Gap {
+        kind: SnapshotType,
+        from: u64,
+        to: u64,
+    },
+    /// A segment that the segment of another type over `from..to` needs
+    Missing {
+        kind: SnapshotType,
+        from: u64,
+        to: u64,
+    },
+    /// A block segment without one word per block of its range
+    WordCount {
+        segment: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    /// An index with another number of keys than its segment has words
+    KeyCount {
+        index: PathBuf,
+        words: u64,
+        keys: u64,
+    },
+    /// An index whose `base_data_id` is not the id of its segment's first
+    /// word: a block number, or the first TxNum of the bodies
+    BaseDataId {
+        index: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    /// A header that is not the block its position says; only the first
+    /// of a segment is reported
+    HeaderNumber {
+        segment: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+    /// A body whose first TxNum does not follow the previous body's
+    /// transactions; only the first of a segment is reported
+    TxNum {
+        segment: PathBuf,
+        block: u64,
+        expected: u64,
+        actual: u64,
+    },
+    /// Bodies counting other transactions than the transactions segment of
+    /// their range holds
+    TransactionCount {
+        bodies: PathBuf,
+        transactions: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+impl SnapshotRepo {
+    /// Check that the headers, bodies and transactions segments of the
+    /// directory and their indexes agree with each other
+    ///
+    /// Every segment is read whole, so this takes about as long as reading
+    /// the directory once. Segments without an index are checked without
+    /// it. Only failures to list the directory are errors; files that can't
+    /// be read are reported as [`FsckViolation::Unreadable`].
+    pub fn fsck(&self) -> Result<FsckReport> {
+        self.fsck_inner(None)
+    }
+
+    /// [`SnapshotRepo::fsck`], giving up with [`SnapshotError::Cancelled`]
+    /// once `cancel` is cancelled; it is checked between segments and words
+    pub fn fsck_cancellable(&self, cancel: &CancellationToken) -> Result<FsckReport> {
+        self.fsck_inner(Some(cancel))
+    }
+
+    fn fsck_inner(&self, cancel: Option<&CancellationToken>) -> Result<FsckReport> {
+        let mut report = FsckReport::default();
+        for file in self.visible(SnapshotType::Headers, &mut report) {
+            cancel::check(cancel)?;
+            report.segments += 1;
+            match self.check_headers(file, cancel, &mut report) {
+                Err(SnapshotError::Cancelled) => return Err(SnapshotError::Cancelled),
+                Err(e) => report.unreadable(&file.path, e),
+                Ok(()) => {}
+            }
+        }
+        // TxNum the next body starts at, while bodies are contiguous
+        let mut next_tx_num = None;
+        let mut previous_end = None;
+        for file in self.visible(SnapshotType::Bodies, &mut report) {
+            cancel::check(cancel)?;
+            report.segments += 1;
+            if previous_end != Some(file.from_block) {
+                next_tx_num = None;
+            }
+            previous_end = Some(file.to_block);
+            match self.check_bodies(file, next_tx_num, cancel, &mut report) {
+                Ok(next) => next_tx_num = next,
+                Err(SnapshotError::Cancelled) => return Err(SnapshotError::Cancelled),
+                Err(e) => {
+                    report.unreadable(&file.path, e);
+                    next_tx_num = None;
+                }
+            }
+        }
+        // Transactions segments are counted with their bodies
+        report.segments += self.visible(SnapshotType::Transactions, &mut report).len();
+        tracing::debug!(
+            "Checked {} segments of {}: {} violations",
+            report.segments,
+            self.dir().display(),
+            report.violations.len()
+        );
+        Ok(report)
+    }
+
+    // The segments of `kind` no bigger one covers, in block order; gaps
+    // between them are reported
+    fn visible(&self, kind: SnapshotType, report: &mut FsckReport) -> Vec<&SnapshotFile> {
+        let mut files: Vec<&SnapshotFile> = self.files_of(kind).collect();
+        files.sort_by_key(|file| (file.from_block, std::cmp::Reverse(file.to_block)));
+        let mut visible: Vec<&SnapshotFile> = Vec::new();
+        for file in files {
+            match visible.last() {
+                Some(last) if file.to_block <= last.to_block => continue,
+                Some(last) if file.from_block > last.to_block => {
+                    report.violations.push(FsckViolation::Gap {
+                        kind,
+                        from: last.to_block,
+                        to: file.from_block,
+                    })
+                }
+                _ => {}
+            }
+            visible.push(file);
+        }
+        visible
+    }
+
+    fn check_headers(
+        &self,
+        file: &SnapshotFile,
+        cancel: Option<&CancellationToken>,
+        report: &mut FsckReport,
+    ) -> Result<()> {
+        let segment = self.segment(file)?;
+        report.check_word_count(file, &segment);
+        let index = self.index(file)?;
+        report.check_index(
+            &file.index_path(),
+            index.as_deref(),
+            &segment,
+            file.from_block,
+        );
+
+        let mut getter = segment.make_getter();
+        let mut word = Vec::new();
+        let mut expected = file.from_block;
+        while getter.has_next() {
+            cancel::check(cancel)?;
+            word.clear();
+            word = getter.try_next(word)?.0;
+            // Empty words stand for blocks without a header
+            if let Some(rlp) = word.get(1..) {
+                let header = Header::decode(&mut &rlp[..])?;
+                if header.number != expected {
+                    report.violations.push(FsckViolation::HeaderNumber {
+                        segment: file.path.clone(),
+                        expected,
+                        actual: header.number,
+                    });
+                    break;
+                }
+            }
+            expected += 1;
+        }
+        Ok(())
+    }
+
+    // Returns the TxNum the body after the segment should start at
+    fn check_bodies(
+        &self,
+        file: &SnapshotFile,
+        mut next_tx_num: Option<u64>,
+        cancel: Option<&CancellationToken>,
+        report: &mut FsckReport,
+    ) -> Result<Option<u64>> {
+        let segment = self.segment(file)?;
+        report.check_word_count(file, &segment);
+        let index = self.index(file)?;
+        report.check_index(
+            &file.index_path(),
+            index.as_deref(),
+            &segment,
+            file.from_block,
+        );
+
+        let mut getter = segment.make_getter();
+        let mut word = Vec::new();
+        let mut block = file.from_block;
+        let mut first_tx_num = None;
+        let mut tx_count = 0u64;
+        let mut reported = false;
+        while getter.has_next() {
+            cancel::check(cancel)?;
+            word.clear();
+            word = getter.try_next(word)?.0;
+            let body = StoredBody::decode(&word)?;
+            let base = body.base_tx_num.get();
+            match next_tx_num {
+                Some(expected) if expected != base && !reported => {
+                    report.violations.push(FsckViolation::TxNum {
+                        segment: file.path.clone(),
+                        block,
+                        expected,
+                        actual: base,
+                    });
+                    reported = true;
+                }
+                _ => {}
+            }
+            first_tx_num.get_or_insert(base);
+            tx_count += u64::from(body.tx_count);
+            next_tx_num = Some(base + u64::from(body.tx_count));
+            block += 1;
+        }
+
+        let transactions = self
+            .files_of(SnapshotType::Transactions)
+            .find(|tx| (tx.from_block, tx.to_block) == (file.from_block, file.to_block));
+        match transactions {
+            Some(transactions) => {
+                if let Err(e) =
+                    self.check_transactions(file, transactions, first_tx_num, tx_count, report)
+                {
+                    report.unreadable(&transactions.path, e);
+                }
+            }
+            None => report.violations.push(FsckViolation::Missing {
+                kind: SnapshotType::Transactions,
+                from: file.from_block,
+                to: file.to_block,
+            }),
+        }
+        Ok(next_tx_num)
+    }
+
+    fn check_transactions(
+        &self,
+        bodies: &SnapshotFile,
+        file: &SnapshotFile,
+        first_tx_num: Option<u64>,
+        tx_count: u64,
+        report: &mut FsckReport,
+    ) -> Result<()> {
+        let segment = self.segment(file)?;
+        if segment.count() as u64 != tx_count {
+            report.violations.push(FsckViolation::TransactionCount {
+                bodies: bodies.path.clone(),
+                transactions: file.path.clone(),
+                expected: tx_count,
+                actual: segment.count() as u64,
+            });
+        }
+        let index = self.index(file)?;
+        if let Some(first_tx_num) = first_tx_num {
+            report.check_index(&file.index_path(), index.as_deref(), &segment, first_tx_num);
+        }
+        let stem = file
+            .path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let to_block_path = file.path.with_file_name(format!("{}-to-block.idx", stem));
+        if to_block_path.exists() {
+            let to_block = RecSplitIndex::open(&to_block_path)?;
+            report.check_index(&to_block_path, Some(&to_block), &segment, file.from_block);
+        }
+        Ok(())
+    }
+}
+
+impl FsckReport {
+    fn unreadable(&mut self, path: &Path, error: impl std::fmt::Display) {
+        self.violations.push(FsckViolation::Unreadable {
+            path: path.to_path_buf(),
+            error: error.to_string(),
+        });
+    }
+
+    fn check_word_count(&mut self, file: &SnapshotFile, segment: &Decompressor) {
+        let expected = file.to_block - file.from_block;
+        if segment.count() as u64 != expected {
+            self.violations.push(FsckViolation::WordCount {
+                segment: file.path.clone(),
+                expected,
+                actual: segment.count() as u64,
+            });
+        }
+    }
+
+    fn check_index(
+        &mut self,
+        path: &Path,
+        index: Option<&RecSplitIndex>,
+        segment: &Decompressor,
+        base_data_id: u64,
+    ) {
+        let Some(index) = index else {
+            return;
+        };
+        if index.key_count() != segment.count() as u64 {
+            self.violations.push(FsckViolation::KeyCount {
+                index: path.to_path_buf(),
+                words: segment.count() as u64,
+                keys: index.key_count(),
+            });
+        }
+        if index.base_data_id() != base_data_id {
+            self.violations.push(FsckViolation::BaseDataId {
+                index: path.to_path_buf(),
+                expected: base_data_id,
+                actual: index.base_data_id(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::writer::{build_block_index, build_transactions_index};
+    use crate::snapshots::TxNum;
+    use crate::testutil::tests::{fixture_chain, write_headers};
+    use crate::testutil::write_segment;
+
+    // Headers of `range` but the one of block `skip`, without an index,
+    // since the writer only takes whole ranges
+    fn write_headers_skipping(dir: &Path, range: std::ops::Range<u64>, skip: u64) {
+        let stem = SnapshotFile::stem(SnapshotType::Headers, range.start, range.end);
+        let mut words = fixture_chain().header_words(range.clone());
+        words.remove((skip - range.start) as usize);
+        write_segment(&dir.join(format!("{}.seg", stem)), &words).unwrap();
+    }
+
+    // Bodies and transactions of `range`, one transaction a block between
+    // the system ones, from TxNum `first`; `missing` transactions are left
+    // out of the end of the segment
+    fn write_blocks(dir: &Path, range: std::ops::Range<u64>, first: u64, missing: usize) {
+        let path = |kind| {
+            let stem = SnapshotFile::stem(kind, range.start, range.end);
+            dir.join(format!("{}.seg", stem))
+        };
+        let bodies_path = path(SnapshotType::Bodies);
+        let transactions_path = path(SnapshotType::Transactions);
+        let mut bodies = Vec::new();
+        let mut words = Vec::new();
+        for (i, block) in range.clone().enumerate() {
+            bodies.push(
+                StoredBody {
+                    base_tx_num: TxNum(first + 3 * i as u64),
+                    tx_count: 3,
+                    ..Default::default()
+                }
+                .encode(),
+            );
+            let mut tx = vec![0u8; 21];
+            tx.extend_from_slice(format!("tx of block {}", block).as_bytes());
+            words.extend([Vec::new(), tx, Vec::new()]);
+        }
+        words.truncate(words.len() - missing);
+        write_segment(&bodies_path, &bodies).unwrap();
+        write_segment(&transactions_path, &words).unwrap();
+        build_block_index(&bodies_path, SnapshotType::Bodies, range.start, false).unwrap();
+        if missing == 0 {
+            build_transactions_index(&transactions_path, &bodies_path, false).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fsck_consistent() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        for range in [0..1000, 1000..2000] {
+            write_headers(tmp_dir.path(), range.clone());
+            write_blocks(tmp_dir.path(), range.clone(), range.start * 3, 0);
+        }
+        // Covered by the two above
+        write_headers(tmp_dir.path(), 0..2000);
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        let report = repo.fsck().unwrap();
+        assert!(report.is_ok(), "{:?}", report.violations);
+        assert_eq!(report.segments, 5);
+
+        let cancel = CancellationToken::new();
+        assert_eq!(repo.fsck_cancellable(&cancel).unwrap(), report);
+        cancel.cancel();
+        assert!(matches!(
+            repo.fsck_cancellable(&cancel),
+            Err(SnapshotError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_fsck_violations() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        write_headers(dir, 0..1000);
+        // A header left out shifts the rest of the segment
+        write_headers_skipping(dir, 1000..2000, 1500);
+        write_headers(dir, 3000..4000);
+        // Transactions cut short; the second range starts at the wrong TxNum
+        write_blocks(dir, 0..1000, 0, 2);
+        write_blocks(dir, 1000..2000, 5000, 0);
+        // An index of another range
+        let stem = |kind, from| dir.join(SnapshotFile::stem(kind, from, from + 1000));
+        std::fs::copy(
+            stem(SnapshotType::Headers, 0).with_extension("idx"),
+            stem(SnapshotType::Headers, 3000).with_extension("idx"),
+        )
+        .unwrap();
+
+        let report = SnapshotRepo::open(dir).unwrap().fsck().unwrap();
+        let path = |kind, from: u64, extension| stem(kind, from).with_extension(extension);
+        assert_eq!(
+            report.violations,
+            [
+                FsckViolation::Gap {
+                    kind: SnapshotType::Headers,
+                    from: 2000,
+                    to: 3000
+                },
+                FsckViolation::WordCount {
+                    segment: path(SnapshotType::Headers, 1000, "seg"),
+                    expected: 1000,
+                    actual: 999
+                },
+                FsckViolation::HeaderNumber {
+                    segment: path(SnapshotType::Headers, 1000, "seg"),
+                    expected: 1500,
+                    actual: 1501
+                },
+                FsckViolation::BaseDataId {
+                    index: path(SnapshotType::Headers, 3000, "idx"),
+                    expected: 3000,
+                    actual: 0
+                },
+                FsckViolation::TransactionCount {
+                    bodies: path(SnapshotType::Bodies, 0, "seg"),
+                    transactions: path(SnapshotType::Transactions, 0, "seg"),
+                    expected: 3000,
+                    actual: 2998
+                },
+                FsckViolation::TxNum {
+                    segment: path(SnapshotType::Bodies, 1000, "seg"),
+                    block: 1000,
+                    expected: 3000,
+                    actual: 5000
+                },
+            ]
+        );
+        assert_eq!(report.segments, 7);
+    }
+}
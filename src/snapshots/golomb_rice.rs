@@ -0,0 +1,227 @@
+//! Golomb-Rice coded stream of RecSplit bucket seeds
+//! Based on the Go implementation in erigon-lib/recsplit/golomb_rice.go
+//!
+//! The stream is an array of little-endian u64 words. Each tree node of a
+//! bucket stores a fixed-width part (the low `log2golomb` bits of the seed)
+//! and a unary part; the fixed parts of a bucket come first, followed by the
+//! unary parts, so the reader keeps two cursors.
+
+use crate::snapshots::elias_fano::select64;
+
+// From Go: golomb_rice.go:28 - optimal Golomb-Rice parameters for leaves
+pub(crate) const BIJ_MEMO: [u32; 25] = [
+    0, 0, 0, 1, 3, 4, 5, 7, 8, 10, 11, 12, 14, 15, 16, 18, 19, 21, 22, 23, 25, 26, 28, 29, 30,
+];
+
+// From Go: GolombRice struct (builder side)
+#[derive(Default)]
+pub(crate) struct GolombRiceBuilder {
+    data: Vec<u64>,
+    bit_count: usize,
+}
+
+impl GolombRiceBuilder {
+    // From Go: golomb_rice.go:40 appendUnaryAll
+    pub(crate) fn append_unary_all(&mut self, unary: &[u64]) {
+        let bit_inc: usize = unary.iter().map(|&u| u as usize + 1).sum();
+        let target_size = (self.bit_count + bit_inc).div_ceil(64);
+        if self.data.len() < target_size {
+            self.data.resize(target_size, 0);
+        }
+
+        for &u in unary {
+            self.bit_count += u as usize;
+            self.data[self.bit_count / 64] |= 1u64 << (self.bit_count & 63);
+            self.bit_count += 1;
+        }
+    }
+
+    // From Go: golomb_rice.go:61 appendFixed
+    // Golomb parameters are powers of two, so the log2 is passed in
+    pub(crate) fn append_fixed(&mut self, v: u64, log2golomb: usize) {
+        if log2golomb == 0 {
+            return;
+        }
+        let lower_bits = v & ((1u64 << log2golomb) - 1);
+        let used_bits = self.bit_count & 63;
+        let target_size = (self.bit_count + log2golomb).div_ceil(64);
+        if self.data.len() < target_size {
+            self.data.resize(target_size, 0);
+        }
+        let mut append_ptr = self.bit_count / 64;
+        let mut cur_word = self.data[append_ptr] | (lower_bits << used_bits);
+        if used_bits + log2golomb > 64 {
+            // New value overflows to the next element
+            self.data[append_ptr] = cur_word;
+            append_ptr += 1;
+            cur_word = lower_bits >> (64 - used_bits);
+        }
+        self.data[append_ptr] = cur_word;
+        self.bit_count += log2golomb;
+    }
+
+    pub(crate) fn bits(&self) -> usize {
+        self.bit_count
+    }
+
+    // From Go: golomb_rice.go:162 Write - word count (big-endian) followed
+    // by the words themselves (little-endian)
+    pub(crate) fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.data.len() as u64).to_be_bytes());
+        for word in &self.data {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+}
+
+// From Go: GolombRiceReader struct
+pub(crate) struct GolombRiceReader<'a> {
+    data: &'a [u8],
+    curr_fixed_offset: usize,
+    curr_window_unary: u64,
+    curr_ptr_unary: usize,
+    valid_lower_bits_unary: usize,
+}
+
+impl<'a> GolombRiceReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        GolombRiceReader {
+            data,
+            curr_fixed_offset: 0,
+            curr_window_unary: 0,
+            curr_ptr_unary: 0,
+            valid_lower_bits_unary: 0,
+        }
+    }
+
+    fn word(&self, i: usize) -> Option<u64> {
+        let bytes = self.data.get(i * 8..i * 8 + 8)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    // From Go: golomb_rice.go:104 ReadReset
+    pub(crate) fn read_reset(&mut self, bit_pos: usize, unary_offset: usize) -> Option<()> {
+        self.curr_fixed_offset = bit_pos;
+        let unary_pos = bit_pos + unary_offset;
+        self.curr_ptr_unary = unary_pos / 64;
+        self.curr_window_unary = self.word(self.curr_ptr_unary)? >> (unary_pos & 63);
+        self.curr_ptr_unary += 1;
+        self.valid_lower_bits_unary = 64 - (unary_pos & 63);
+        Some(())
+    }
+
+    // From Go: golomb_rice.go:113 SkipSubtree
+    pub(crate) fn skip_subtree(&mut self, nodes: usize, fixed_len: usize) -> Option<()> {
+        if nodes == 0 {
+            return None;
+        }
+        let mut missing = nodes;
+        let mut cnt = self.curr_window_unary.count_ones() as usize;
+        while cnt < missing {
+            self.curr_window_unary = self.word(self.curr_ptr_unary)?;
+            self.curr_ptr_unary += 1;
+            missing -= cnt;
+            self.valid_lower_bits_unary = 64;
+            cnt = self.curr_window_unary.count_ones() as usize;
+        }
+        let sel = select64(self.curr_window_unary, missing - 1);
+        self.curr_window_unary >>= sel;
+        self.curr_window_unary >>= 1;
+        self.valid_lower_bits_unary -= sel + 1;
+
+        self.curr_fixed_offset += fixed_len;
+        Some(())
+    }
+
+    // From Go: golomb_rice.go:134 ReadNext
+    pub(crate) fn read_next(&mut self, log2golomb: usize) -> Option<u64> {
+        let mut result = 0u64;
+        if self.curr_window_unary == 0 {
+            result += self.valid_lower_bits_unary as u64;
+            self.curr_window_unary = self.word(self.curr_ptr_unary)?;
+            self.curr_ptr_unary += 1;
+            self.valid_lower_bits_unary = 64;
+            while self.curr_window_unary == 0 {
+                result += 64;
+                self.curr_window_unary = self.word(self.curr_ptr_unary)?;
+                self.curr_ptr_unary += 1;
+            }
+        }
+
+        let pos = self.curr_window_unary.trailing_zeros() as usize;
+
+        self.curr_window_unary >>= pos;
+        self.curr_window_unary >>= 1;
+        self.valid_lower_bits_unary -= pos + 1;
+
+        result += pos as u64;
+        result <<= log2golomb;
+
+        let idx64 = self.curr_fixed_offset >> 6;
+        let shift = self.curr_fixed_offset & 63;
+        let mut fixed = self.word(idx64)? >> shift;
+        if shift + log2golomb > 64 {
+            fixed |= self.word(idx64 + 1)? << (64 - shift);
+        }
+        result |= fixed & ((1u64 << log2golomb) - 1);
+        self.curr_fixed_offset += log2golomb;
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lay values out as RecSplit does for a bucket: all fixed parts, then
+    // all unary parts
+    fn encode(values: &[u64], log2golomb: usize) -> (Vec<u8>, usize) {
+        let mut builder = GolombRiceBuilder::default();
+        for &v in values {
+            builder.append_fixed(v, log2golomb);
+        }
+        let fixed_bits = builder.bits();
+        let unary: Vec<u64> = values.iter().map(|&v| v >> log2golomb).collect();
+        builder.append_unary_all(&unary);
+        assert_eq!(
+            builder.bits(),
+            fixed_bits + unary.iter().map(|&u| u as usize + 1).sum::<usize>()
+        );
+        let mut out = Vec::new();
+        builder.write_to(&mut out);
+        let words = u64::from_be_bytes(out[..8].try_into().unwrap()) as usize;
+        assert_eq!(out.len(), 8 + words * 8);
+        (out[8..].to_vec(), fixed_bits)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Values crossing word boundaries in both parts, and long unary runs
+        let values: Vec<u64> = (0..300u64).map(|i| (i * 7919) % 5000).collect();
+        for log2golomb in [0, 1, 5, 13, 31] {
+            let (data, fixed_bits) = encode(&values, log2golomb);
+            let mut reader = GolombRiceReader::new(&data);
+            reader.read_reset(0, fixed_bits).unwrap();
+            for (i, &v) in values.iter().enumerate() {
+                assert_eq!(reader.read_next(log2golomb), Some(v), "value {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_skip_subtree() {
+        let values = [3u64, 200, 17, 64, 5];
+        let log2golomb = 4;
+        let (data, fixed_bits) = encode(&values, log2golomb);
+        let mut reader = GolombRiceReader::new(&data);
+        reader.read_reset(0, fixed_bits).unwrap();
+        reader.skip_subtree(2, 2 * log2golomb).unwrap();
+        assert_eq!(reader.read_next(log2golomb), Some(17));
+        reader.skip_subtree(1, log2golomb).unwrap();
+        assert_eq!(reader.read_next(log2golomb), Some(5));
+        assert!(reader.skip_subtree(0, 0).is_none());
+
+        // Reading past the data fails instead of panicking
+        assert_eq!(reader.read_next(log2golomb), None);
+    }
+}
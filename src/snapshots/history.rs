@@ -0,0 +1,514 @@
+//! Readers for Erigon 3 history files
+//!
+//! A domain's history is kept in two files per step range. The inverted
+//! index (`.ef`) maps each key to the eliasfano32 sequence of the txNums
+//! that changed it, as alternating key and sequence words sorted by key; its
+//! `.efi` accessor maps a key to the offset of its key word. The history
+//! values (`.v`) hold, for every key and txNum of the inverted index in the
+//! same order, the value the key had before that txNum; its `.vi` accessor
+//! maps the txNum (8 bytes big-endian) followed by the key to the offset of
+//! the value word. Based on erigon-lib/state/inverted_index.go and
+//! history.go.
+
+use crate::decompress::Decompressor;
+use crate::error::IndexError;
+use crate::seg_reader::{detect_compress_type, FileCompression, SegmentReader};
+use crate::snapshots::postings::PostingList;
+use crate::snapshots::recsplit::{ef32_get, ef32_size, EfIterator, RecSplitIndex};
+use crate::snapshots::{Result, SnapshotError};
+use std::path::Path;
+
+/// The txNums of an eliasfano32 sequence from an inverted index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxNumSequence {
+    data: Vec<u8>,
+    len: u64,
+}
+
+impl TxNumSequence {
+    fn new(data: Vec<u8>) -> Result<Self> {
+        if data.len() < 16 || ef32_size(&data) > data.len() {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Truncated txNum sequence of {} bytes",
+                data.len()
+            )));
+        }
+        let len = u64::from_be_bytes(data[..8].try_into().unwrap()) + 1;
+        Ok(Self { data, len })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The `i`-th txNum of the sequence
+    pub fn get(&self, i: u64) -> Option<u64> {
+        if i >= self.len {
+            return None;
+        }
+        ef32_get(&self.data, i)
+    }
+
+    /// Position and value of the first txNum `>= tx_num`
+    // From Go: eliasfano32 EliasFano.Search
+    pub fn search(&self, tx_num: u64) -> Option<(u64, u64)> {
+        let (mut l, mut r) = (0, self.len);
+        while l < r {
+            let m = (l + r) / 2;
+            if self.get(m)? < tx_num {
+                l = m + 1;
+            } else {
+                r = m;
+            }
+        }
+        self.get(l).map(|found| (l, found))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        EfIterator::new(&self.data, 0).into_iter().flatten()
+    }
+
+    // Values from the `from`-th on, `None` past the end
+    pub(super) fn iter_from(&self, from: u64) -> Option<EfIterator<'_>> {
+        EfIterator::new(&self.data, from)
+    }
+
+    /// The postings of this sequence alone, see [`PostingList`]
+    pub fn postings(&self) -> PostingList<'_> {
+        PostingList::new(std::slice::from_ref(self))
+    }
+}
+
+/// Reader for inverted index files (`accounts.0-64.ef`, ...)
+///
+/// Lookups go through the `.efi` accessor next to the file when there is
+/// one; otherwise the file is scanned.
+pub struct InvertedIndexReader {
+    segment: SegmentReader,
+    // Key-to-offset RecSplit index (`.efi`), if one was found
+    index: Option<RecSplitIndex>,
+}
+
+impl InvertedIndexReader {
+    /// Open an inverted index file, detecting its compression from the
+    /// first words
+    pub fn new(path: &Path) -> Result<Self> {
+        let decompressor = Decompressor::new(path)?;
+        let compression = detect_compress_type(&decompressor);
+        drop(decompressor);
+        Self::with_compression(path, compression)
+    }
+
+    /// Open an inverted index file whose keys/sequences are compressed as
+    /// `compression`, with the `.efi` next to it if any
+    pub fn with_compression(path: &Path, compression: FileCompression) -> Result<Self> {
+        let idx_path = path.with_extension("efi");
+        let index = if idx_path.exists() {
+            Some(RecSplitIndex::open(&idx_path)?)
+        } else {
+            None
+        };
+        Self::with_index(path, compression, index)
+    }
+
+    /// Open an inverted index file with an explicitly given `.efi` (or none)
+    pub fn with_index(
+        path: &Path,
+        compression: FileCompression,
+        index: Option<RecSplitIndex>,
+    ) -> Result<Self> {
+        let segment = SegmentReader::new(path, compression)?;
+        if !segment.count().is_multiple_of(2) {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Inverted index {} has an odd number of words ({})",
+                path.display(),
+                segment.count()
+            )));
+        }
+        if let Some(idx) = &index {
+            check_accessor(idx, &segment, (segment.count() / 2) as u64)?;
+        }
+        Ok(Self { segment, index })
+    }
+
+    /// Number of keys in the file
+    pub fn count(&self) -> usize {
+        self.segment.count() / 2
+    }
+
+    /// The `.efi` index, if one is attached
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.index.as_ref()
+    }
+
+    /// The txNums at which `key` changed, `None` if the file does not have
+    /// the key
+    pub fn tx_nums(&self, key: &[u8]) -> Result<Option<TxNumSequence>> {
+        let sequence = match &self.index {
+            Some(idx) => self.sequence_indexed(idx, key)?,
+            None => self.sequence_scan(key)?,
+        };
+        sequence.map(TxNumSequence::new).transpose()
+    }
+
+    /// The first txNum `>= tx_num` at which `key` changed
+    // From Go: inverted_index.go InvertedIndexRoTx.seekInFiles
+    pub fn seek(&self, key: &[u8], tx_num: u64) -> Result<Option<u64>> {
+        Ok(self
+            .tx_nums(key)?
+            .and_then(|seq| seq.search(tx_num))
+            .map(|(_, found)| found))
+    }
+
+    /// Position of the (`key`, `tx_num`) change among all changes of the
+    /// file, which is where its value sits in the matching `.v` file
+    ///
+    /// Always a scan, as the sequences of the keys before `key` are counted.
+    pub fn change_ordinal(&self, key: &[u8], tx_num: u64) -> Result<Option<u64>> {
+        let mut reader = self.segment.make_reader();
+        let mut ordinal = 0;
+        while reader.has_next() {
+            let (found, _) = reader.next(Vec::new());
+            if !reader.has_next() {
+                return Err(unexpected_eof(&found));
+            }
+            let (data, _) = reader.next(Vec::new());
+            let seq = TxNumSequence::new(data)?;
+            match found.as_slice().cmp(key) {
+                std::cmp::Ordering::Less => ordinal += seq.len(),
+                std::cmp::Ordering::Equal => {
+                    return Ok(seq
+                        .search(tx_num)
+                        .filter(|&(_, found)| found == tx_num)
+                        .map(|(i, _)| ordinal + i))
+                }
+                std::cmp::Ordering::Greater => break,
+            }
+        }
+        Ok(None)
+    }
+
+    fn sequence_indexed(&self, idx: &RecSplitIndex, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        // A perfect hash maps unknown keys somewhere too, so the key we land
+        // on is checked against the requested one
+        let Some(offset) = idx.lookup(key) else {
+            return Ok(None);
+        };
+        let mut reader = self.segment.make_reader();
+        reader.reset(offset);
+        if !reader.has_next() {
+            return Ok(None);
+        }
+        let (found, _) = reader.next(Vec::new());
+        if found != key {
+            return Ok(None);
+        }
+        if !reader.has_next() {
+            return Err(unexpected_eof(key));
+        }
+        Ok(Some(reader.next(Vec::new()).0))
+    }
+
+    fn sequence_scan(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        tracing::debug!(
+            "No inverted index accessor, scanning for {}",
+            hex::encode(key)
+        );
+        let mut reader = self.segment.make_reader();
+        while reader.has_next() {
+            let (found, _) = reader.next(Vec::new());
+            match found.as_slice().cmp(key) {
+                std::cmp::Ordering::Less => {
+                    reader.skip();
+                }
+                std::cmp::Ordering::Equal => {
+                    if !reader.has_next() {
+                        return Err(unexpected_eof(key));
+                    }
+                    return Ok(Some(reader.next(Vec::new()).0));
+                }
+                // Keys are sorted
+                std::cmp::Ordering::Greater => break,
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Reader for history value files (`accounts.0-64.v`, ...)
+///
+/// The values are found through the `.vi` accessor next to the file when
+/// there is one; otherwise their position is counted in the matching
+/// inverted index.
+pub struct HistoryReader {
+    segment: SegmentReader,
+    // (txNum, key)-to-offset RecSplit index (`.vi`), if one was found
+    index: Option<RecSplitIndex>,
+}
+
+impl HistoryReader {
+    /// Open a history file, with the `.vi` next to it if any
+    ///
+    /// Only [`FileCompression::Keys`] of `compression` matters: every word
+    /// is read the way Erigon reads a key after a reset.
+    pub fn new(path: &Path, compression: FileCompression) -> Result<Self> {
+        let idx_path = path.with_extension("vi");
+        let index = if idx_path.exists() {
+            Some(RecSplitIndex::open(&idx_path)?)
+        } else {
+            None
+        };
+        Self::with_index(path, compression, index)
+    }
+
+    /// Open a history file with an explicitly given `.vi` (or none)
+    pub fn with_index(
+        path: &Path,
+        compression: FileCompression,
+        index: Option<RecSplitIndex>,
+    ) -> Result<Self> {
+        let segment = SegmentReader::new(path, compression)?;
+        if let Some(idx) = &index {
+            check_accessor(idx, &segment, segment.count() as u64)?;
+        }
+        Ok(Self { segment, index })
+    }
+
+    /// Number of values in the file
+    pub fn count(&self) -> usize {
+        self.segment.count()
+    }
+
+    /// The `.vi` index, if one is attached
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.index.as_ref()
+    }
+
+    /// The value `key` had before `tx_num`, which must be one of its changes
+    /// in `ii`, the inverted index of the same step range
+    ///
+    /// `None` if `ii` has no such change.
+    // From Go: history.go HistoryRoTx.historySeekInFiles
+    pub fn get(
+        &self,
+        ii: &InvertedIndexReader,
+        key: &[u8],
+        tx_num: u64,
+    ) -> Result<Option<Vec<u8>>> {
+        let offset = match &self.index {
+            Some(idx) => {
+                // The accessor can't tell unknown pairs apart, so the change
+                // is checked in the inverted index first
+                let known = ii
+                    .tx_nums(key)?
+                    .and_then(|seq| seq.search(tx_num))
+                    .is_some_and(|(_, found)| found == tx_num);
+                if !known {
+                    return Ok(None);
+                }
+                let mut history_key = tx_num.to_be_bytes().to_vec();
+                history_key.extend_from_slice(key);
+                idx.lookup(&history_key)
+            }
+            None => match ii.change_ordinal(key, tx_num)? {
+                Some(ordinal) => Some(self.offset_scan(ordinal)?),
+                None => return Ok(None),
+            },
+        };
+        let Some(offset) = offset else {
+            return Ok(None);
+        };
+        let mut reader = self.segment.make_reader();
+        reader.reset(offset);
+        if !reader.has_next() {
+            return Err(unexpected_eof(key));
+        }
+        Ok(Some(reader.next(Vec::new()).0))
+    }
+
+    fn offset_scan(&self, ordinal: u64) -> Result<u64> {
+        tracing::debug!("No history accessor, skipping to value {}", ordinal);
+        let mut reader = self.segment.make_reader();
+        let mut offset = 0;
+        for _ in 0..ordinal {
+            // Resetting reads each word as a key, like the lookup does
+            reader.reset(offset);
+            if !reader.has_next() {
+                return Err(SnapshotError::UnexpectedEof {
+                    context: format!("history value {} in {}", ordinal, self.segment.file_name()),
+                });
+            }
+            offset = reader.skip().0;
+        }
+        Ok(offset)
+    }
+}
+
+fn check_accessor(idx: &RecSplitIndex, segment: &SegmentReader, keys: u64) -> Result<()> {
+    if idx.is_enum() {
+        return Err(IndexError::WrongKind {
+            file: segment.file_name().to_string(),
+            is_enum: true,
+        }
+        .into());
+    }
+    if idx.key_count() != keys {
+        return Err(IndexError::KeyCountMismatch {
+            file: segment.file_name().to_string(),
+            expected: keys,
+            actual: idx.key_count(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn unexpected_eof(key: &[u8]) -> SnapshotError {
+    SnapshotError::UnexpectedEof {
+        context: format!("txNums of key {}", hex::encode(key)),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::compress::Compressor;
+    use crate::snapshots::recsplit::{build_elias_fano32, RecSplit};
+    use std::path::PathBuf;
+
+    /// A key's changes: txNum and the value the key had before it
+    pub(crate) type Changes = Vec<(u64, Vec<u8>)>;
+
+    /// The eliasfano32 sequence of `tx_nums`, sorted and not empty
+    pub(crate) fn sequence(tx_nums: &[u64]) -> TxNumSequence {
+        TxNumSequence::new(build_elias_fano32(tx_nums, *tx_nums.last().unwrap())).unwrap()
+    }
+
+    /// Write `<stem>.ef` and `<stem>.v` into `dir` for `history` (sorted by
+    /// key), with their accessors if `accessors`
+    pub(crate) fn write_history(
+        dir: &Path,
+        stem: &str,
+        history: &[(Vec<u8>, Changes)],
+        accessors: bool,
+    ) -> (PathBuf, PathBuf) {
+        let ef_path = dir.join(format!("{}.ef", stem));
+        let v_path = dir.join(format!("{}.v", stem));
+
+        let mut compressor = Compressor::builder(&ef_path).fsync(false).build().unwrap();
+        for (key, changes) in history {
+            let tx_nums: Vec<u64> = changes.iter().map(|&(tx_num, _)| tx_num).collect();
+            compressor.add_word(key).unwrap();
+            compressor
+                .add_uncompressed_word(&build_elias_fano32(&tx_nums, *tx_nums.last().unwrap()))
+                .unwrap();
+        }
+        compressor.compress().unwrap();
+
+        let mut compressor = Compressor::builder(&v_path).fsync(false).build().unwrap();
+        for (_, changes) in history {
+            for (_, value) in changes {
+                compressor.add_word(value).unwrap();
+            }
+        }
+        compressor.compress().unwrap();
+
+        if accessors {
+            let ef = SegmentReader::new(&ef_path, FileCompression::Keys).unwrap();
+            let mut reader = ef.make_reader();
+            let mut rs = RecSplit::builder(ef_path.with_extension("efi"), history.len())
+                .fsync(false)
+                .build()
+                .unwrap();
+            let mut offset = 0;
+            for (key, _) in history {
+                rs.add_key(key, offset).unwrap();
+                reader.skip();
+                offset = reader.skip().0;
+            }
+            rs.build().unwrap();
+
+            let changes = history.iter().map(|(_, changes)| changes.len()).sum();
+            let v = Decompressor::new(&v_path).unwrap();
+            let mut getter = v.make_getter();
+            let mut rs = RecSplit::builder(v_path.with_extension("vi"), changes)
+                .fsync(false)
+                .build()
+                .unwrap();
+            let mut offset = 0;
+            for (key, changes) in history {
+                for (tx_num, _) in changes {
+                    let mut history_key = tx_num.to_be_bytes().to_vec();
+                    history_key.extend_from_slice(key);
+                    rs.add_key(&history_key, offset).unwrap();
+                    offset = getter.skip().0;
+                }
+            }
+            rs.build().unwrap();
+        }
+        (ef_path, v_path)
+    }
+
+    // Keys changed at a few txNums each, keyed like accounts
+    fn history() -> Vec<(Vec<u8>, Changes)> {
+        (0..40u8)
+            .map(|i| {
+                let changes = (0..1 + i % 4)
+                    .map(|j| (i as u64 * 7 + j as u64 * 100, vec![i; j as usize]))
+                    .collect();
+                (vec![0x10 + i; 20], changes)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tx_num_sequence() {
+        let tx_nums = [3, 10, 11, 400, 90_000];
+        let seq = TxNumSequence::new(build_elias_fano32(&tx_nums, 90_000)).unwrap();
+        assert_eq!(seq.len(), 5);
+        assert_eq!(seq.iter().collect::<Vec<_>>(), tx_nums);
+        assert_eq!(seq.search(0), Some((0, 3)));
+        assert_eq!(seq.search(11), Some((2, 11)));
+        assert_eq!(seq.search(12), Some((3, 400)));
+        assert_eq!(seq.search(90_001), None);
+        assert!(TxNumSequence::new(vec![0; 10]).is_err());
+    }
+
+    #[test]
+    fn test_history_lookups() {
+        let history = history();
+        for accessors in [false, true] {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let (ef_path, v_path) =
+                write_history(tmp_dir.path(), "v1-accounts.0-1", &history, accessors);
+            let ii =
+                InvertedIndexReader::with_compression(&ef_path, FileCompression::Keys).unwrap();
+            let values = HistoryReader::new(&v_path, FileCompression::Keys).unwrap();
+            assert_eq!(ii.index().is_some(), accessors);
+            assert_eq!(values.index().is_some(), accessors);
+            assert_eq!(ii.count(), history.len());
+
+            for (key, changes) in &history {
+                let tx_nums: Vec<u64> = ii.tx_nums(key).unwrap().unwrap().iter().collect();
+                assert_eq!(tx_nums, changes.iter().map(|c| c.0).collect::<Vec<_>>());
+                for (tx_num, value) in changes {
+                    assert_eq!(ii.seek(key, *tx_num).unwrap(), Some(*tx_num));
+                    assert_eq!(
+                        ii.seek(key, tx_num.saturating_sub(1)).unwrap(),
+                        Some(*tx_num)
+                    );
+                    assert_eq!(values.get(&ii, key, *tx_num).unwrap().as_ref(), Some(value));
+                }
+                let last = changes.last().unwrap().0;
+                assert_eq!(ii.seek(key, last + 1).unwrap(), None);
+                assert_eq!(values.get(&ii, key, last + 1).unwrap(), None);
+            }
+            assert_eq!(ii.tx_nums(&[0xff; 20]).unwrap(), None);
+            assert_eq!(values.get(&ii, &[0xff; 20], 0).unwrap(), None);
+        }
+    }
+}
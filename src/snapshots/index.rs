@@ -1,12 +1,13 @@
+use crate::error::IndexError;
+use crate::snapshots::mapped::FileData;
 use crate::snapshots::{Result, SnapshotError};
-use memmap2::Mmap;
 use std::fs::File;
 use std::path::Path;
 
 /// Reader for Erigon/recsplit index files (.idx)
 /// These files allow O(1) lookup of offsets in the corresponding .seg file
 pub struct IndexReader {
-    mmap: Mmap,
+    mmap: FileData,
     bucket_size: u16,
     leaf_size: u8,
     base_data_id: u64,
@@ -20,7 +21,7 @@ impl IndexReader {
     /// Open an index file
     pub fn new(path: &Path) -> Result<Self> {
         let file = File::open(path)?;
-        let mmap = unsafe { Mmap::map(&file)? };
+        let mmap = FileData::open(&file)?;
 
         // Parse the recsplit index header
         // Format is from erigon-lib/recsplit/index.go
@@ -36,7 +37,7 @@ impl IndexReader {
         let enum_index = leaf_size & 0x80 != 0;
         let leaf_size = leaf_size & 0x7f;
 
-        let bucket_count = (key_count + bucket_size as u64 - 1) / bucket_size as u64;
+        let bucket_count = key_count.div_ceil(bucket_size as u64);
 
         // Read base data ID (used for enum indexes)
         let base_data_id = if enum_index { reader.read_u64()? } else { 0 };
@@ -79,10 +80,10 @@ impl IndexReader {
 
         // Calculate bucket and position
         let bucket_id = adjusted_key / self.bucket_size as u64;
-        let bucket_offset = self.get_bucket_offset(bucket_id);
+        let bucket_offset = self.get_bucket_offset(bucket_id)?;
 
         // Read the offset from the bucket
-        let reader = IndexFileReader::new(&self.mmap[bucket_offset..]);
+        let reader = IndexFileReader::new(self.mmap.get(bucket_offset..)?);
 
         // In enum mode, offsets are stored sequentially
         let position_in_bucket = (adjusted_key % self.bucket_size as u64) as usize;
@@ -104,20 +105,23 @@ impl IndexReader {
         None
     }
 
-    /// Get the offset of a bucket in the index file
-    fn get_bucket_offset(&self, bucket_id: u64) -> usize {
+    /// Get the offset of a bucket in the index file, `None` if the bucket
+    /// table doesn't fit in the file
+    fn get_bucket_offset(&self, bucket_id: u64) -> Option<usize> {
         // Skip header (16 bytes) + base_data_id (8 bytes if enum)
-        let header_size = 16 + if self.enum_index { 8 } else { 0 };
+        let header_size: usize = 16 + if self.enum_index { 8 } else { 0 };
 
-        // Skip to the bucket offsets table at the end
-        let bucket_table_offset = self.mmap.len() - (self.bucket_count * 8) as usize;
+        // Skip to the bucket offsets table at the end; sizes are checked
+        // as u64 so a bogus bucket count can't wrap on 32-bit targets
+        let table_len = usize::try_from(self.bucket_count.checked_mul(8)?).ok()?;
+        let bucket_table_offset = self.mmap.len().checked_sub(table_len)?;
 
         // Read the bucket offset
-        let offset_pos = bucket_table_offset + (bucket_id * 8) as usize;
-        let offset_bytes = &self.mmap[offset_pos..offset_pos + 8];
+        let offset_pos = bucket_table_offset + usize::try_from(bucket_id * 8).ok()?;
+        let offset_bytes = self.mmap.get(offset_pos..offset_pos + 8)?;
         let offset = u64::from_le_bytes(offset_bytes.try_into().unwrap());
 
-        header_size + offset as usize
+        header_size.checked_add(usize::try_from(offset).ok()?)
     }
 
     /// Read an offset at a specific position in a bucket
@@ -149,6 +153,16 @@ impl IndexReader {
         self.base_data_id
     }
 
+    /// Get the leaf size (without the enum flag bit)
+    pub fn leaf_size(&self) -> u8 {
+        self.leaf_size
+    }
+
+    /// Get the number of bits used by the RecSplit encoding
+    pub fn rec_split_bits(&self) -> u8 {
+        self.rec_split_bits
+    }
+
     /// Ordinal lookup - returns offset for i-th element (0-based)
     /// This is what Erigon uses for headers
     pub fn ordinal_lookup(&self, ordinal: u64) -> Option<u64> {
@@ -237,7 +251,7 @@ impl<'a> IndexFileReader<'a> {
 
             shift += 7;
             if shift >= 64 {
-                return Err(SnapshotError::Index("Varint too large".to_string()));
+                return Err(IndexError::VarintOverflow { offset: self.pos }.into());
             }
         }
 
@@ -250,9 +264,14 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_index_reader_creation() {
-        // This test would need an actual .idx file
-        // For now, just test that the module compiles
-        assert_eq!(2 + 2, 4);
+    fn test_index_reader_truncated_header() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("truncated.idx");
+        std::fs::write(&path, [0u8; 5]).unwrap();
+
+        assert!(matches!(
+            IndexReader::new(&path),
+            Err(SnapshotError::UnexpectedEof { .. })
+        ));
     }
 }
@@ -0,0 +1,255 @@
+//! Ethereum JSON-RPC shapes of snapshot data
+//!
+//! Converts what the readers return into the `alloy_rpc_types` objects of
+//! `eth_getBlockByNumber` and `eth_getTransactionByHash`. Serialized with
+//! `serde_json` they have the fields of a node's responses, so a dump can be
+//! diffed directly against a live node.
+
+use crate::snapshots::reader::{
+    BodiesReader, HeadersReader, StoredBody, StoredTransaction, TransactionsReader,
+};
+use crate::snapshots::types::{BlockNumber, TxIndex};
+use crate::snapshots::{Result, SnapshotError};
+use alloy_consensus::{BlockBody, Header, Transaction as _, TxEnvelope};
+use alloy_eips::eip4895::Withdrawals;
+use alloy_primitives::{Sealed, B256, U256};
+use alloy_rlp::Encodable;
+use alloy_rpc_types::{Block, BlockTransactions, Transaction};
+
+/// Header `hash` as RPC returns it
+///
+/// Snapshots hold neither the total difficulty nor, on their own, the size
+/// of the block, so both are left out; [`block`] fills in the size.
+pub fn header(hash: B256, header: Header) -> alloy_rpc_types::Header {
+    alloy_rpc_types::Header::from_consensus(Sealed::new_unchecked(header, hash), None, None)
+}
+
+/// Transaction `index` of block `block_hash`, whose header is `header`
+///
+/// The gas price of dynamic fee transactions is the effective one, paid at
+/// the block's base fee, as nodes report it.
+pub fn transaction(
+    tx: &StoredTransaction,
+    block_hash: B256,
+    header: &Header,
+    index: TxIndex,
+) -> Result<Transaction> {
+    let inner = tx.envelope()?;
+    let effective_gas_price = inner.effective_gas_price(header.base_fee_per_gas);
+    Ok(Transaction {
+        inner,
+        block_hash: Some(block_hash),
+        block_number: Some(header.number),
+        transaction_index: Some(index.get()),
+        effective_gas_price: Some(effective_gas_price),
+        from: tx.sender,
+    })
+}
+
+/// Block `hash` with its body and transactions, as `eth_getBlockByNumber`
+/// returns it; `full` picks transaction objects over their hashes
+///
+/// `transactions` are those of `body`, without the system transactions, as
+/// [`TransactionsReader::transactions`] reads them.
+pub fn block(
+    hash: B256,
+    header: Header,
+    body: &StoredBody,
+    transactions: &[StoredTransaction],
+    full: bool,
+) -> Result<Block> {
+    if transactions.len() as u64 != body.transaction_count() {
+        return Err(SnapshotError::InvalidFormat(format!(
+            "Block {} has {} transactions, {} given",
+            header.number,
+            body.transaction_count(),
+            transactions.len()
+        )));
+    }
+    let envelopes = transactions
+        .iter()
+        .map(StoredTransaction::envelope)
+        .collect::<Result<Vec<TxEnvelope>>>()?;
+    let withdrawals = body.withdrawals.clone().map(Withdrawals::new);
+
+    // The size is that of the block's RLP, as on the wire
+    let consensus = alloy_consensus::Block {
+        header,
+        body: BlockBody {
+            transactions: envelopes,
+            ommers: body.ommers.clone(),
+            withdrawals: withdrawals.clone(),
+        },
+    };
+    let size = consensus.length();
+    let alloy_consensus::Block {
+        header,
+        body: decoded,
+    } = consensus;
+
+    let transactions = if full {
+        let txs = transactions
+            .iter()
+            .zip(decoded.transactions)
+            .enumerate()
+            .map(|(i, (tx, inner))| Transaction {
+                effective_gas_price: Some(inner.effective_gas_price(header.base_fee_per_gas)),
+                inner,
+                block_hash: Some(hash),
+                block_number: Some(header.number),
+                transaction_index: Some(i as u64),
+                from: tx.sender,
+            })
+            .collect();
+        BlockTransactions::Full(txs)
+    } else {
+        BlockTransactions::Hashes(
+            decoded
+                .transactions
+                .iter()
+                .map(|tx| *tx.tx_hash())
+                .collect(),
+        )
+    };
+    Ok(Block {
+        header: alloy_rpc_types::Header::from_consensus(
+            Sealed::new_unchecked(header, hash),
+            None,
+            Some(U256::from(size)),
+        ),
+        uncles: body.ommers.iter().map(Header::hash_slow).collect(),
+        transactions,
+        withdrawals,
+    })
+}
+
+/// Block `number` read from the headers, bodies and transactions snapshots
+/// that hold it, `None` if the headers snapshot does not; all three need
+/// their indexes
+pub fn block_by_number(
+    headers: &HeadersReader,
+    bodies: &BodiesReader,
+    transactions: &TransactionsReader,
+    number: BlockNumber,
+    full: bool,
+) -> Result<Option<Block>> {
+    let Some(header) = headers.header_by_number(number)? else {
+        return Ok(None);
+    };
+    let body = bodies
+        .body_by_number(number)?
+        .ok_or(SnapshotError::BlockNotFound(number.get()))?;
+    let txs = transactions.transactions(&body)?;
+    let hash = header.hash_slow();
+    block(hash, header, &body, &txs, full).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::types::TxNum;
+    use alloy_consensus::{SignableTransaction, TxEip1559, TxLegacy};
+    use alloy_eips::eip2718::Encodable2718;
+    use alloy_eips::eip4895::Withdrawal;
+    use alloy_primitives::{Address, PrimitiveSignature, TxKind};
+    use serde_json::json;
+
+    fn stored(tx_num: u64, envelope: &TxEnvelope) -> StoredTransaction {
+        StoredTransaction {
+            tx_num: TxNum(tx_num),
+            hash_prefix: envelope.tx_hash()[0],
+            sender: Address::with_last_byte(0xee),
+            encoded: envelope.encoded_2718(),
+        }
+    }
+
+    #[test]
+    fn test_block_json() {
+        let signature = PrimitiveSignature::new(U256::from(1), U256::from(2), false);
+        let legacy: TxEnvelope = TxLegacy {
+            chain_id: Some(1),
+            nonce: 1,
+            gas_price: 30_000_000_000,
+            gas_limit: 21_000,
+            to: TxKind::Call(Address::with_last_byte(1)),
+            ..Default::default()
+        }
+        .into_signed(signature)
+        .into();
+        let dynamic: TxEnvelope = TxEip1559 {
+            chain_id: 1,
+            nonce: 2,
+            gas_limit: 21_000,
+            max_fee_per_gas: 50_000_000_000,
+            max_priority_fee_per_gas: 2_000_000_000,
+            to: TxKind::Call(Address::with_last_byte(2)),
+            ..Default::default()
+        }
+        .into_signed(signature)
+        .into();
+        let txs = vec![stored(11, &legacy), stored(12, &dynamic)];
+        let body = StoredBody {
+            base_tx_num: TxNum(10),
+            tx_count: 4,
+            ommers: Vec::new(),
+            withdrawals: Some(vec![Withdrawal {
+                index: 1,
+                validator_index: 2,
+                address: Address::with_last_byte(3),
+                amount: 4,
+            }]),
+        };
+        let header = Header {
+            number: 0x1234,
+            gas_limit: 30_000_000,
+            base_fee_per_gas: Some(10_000_000_000),
+            ..Default::default()
+        };
+        let hash = header.hash_slow();
+
+        let block_hashes = block(hash, header.clone(), &body, &txs, false).unwrap();
+        let value = serde_json::to_value(&block_hashes).unwrap();
+        assert_eq!(value["hash"], json!(hash));
+        assert_eq!(value["number"], json!("0x1234"));
+        assert_eq!(value["baseFeePerGas"], json!("0x2540be400"));
+        assert_eq!(value["uncles"], json!([]));
+        assert_eq!(
+            value["transactions"],
+            json!([legacy.tx_hash(), dynamic.tx_hash()])
+        );
+        assert_eq!(value["withdrawals"][0]["validatorIndex"], json!("0x2"));
+        assert!(value.get("totalDifficulty").is_none());
+
+        // The size is the length of the block's RLP
+        let rlp = alloy_rlp::encode(alloy_consensus::Block {
+            header: header.clone(),
+            body: BlockBody {
+                transactions: vec![legacy.clone(), dynamic.clone()],
+                ommers: Vec::new(),
+                withdrawals: Some(Withdrawals::new(body.withdrawals.clone().unwrap())),
+            },
+        });
+        assert_eq!(value["size"], json!(format!("{:#x}", rlp.len())));
+
+        let value =
+            serde_json::to_value(block(hash, header.clone(), &body, &txs, true).unwrap()).unwrap();
+        let full = value["transactions"].as_array().unwrap();
+        assert_eq!(full.len(), 2);
+        assert_eq!(full[0]["hash"], json!(legacy.tx_hash()));
+        assert_eq!(full[0]["blockHash"], json!(hash));
+        assert_eq!(full[0]["blockNumber"], json!("0x1234"));
+        assert_eq!(full[0]["transactionIndex"], json!("0x0"));
+        assert_eq!(full[0]["from"], json!(Address::with_last_byte(0xee)));
+        assert_eq!(full[0]["gasPrice"], json!("0x6fc23ac00"));
+        // Base fee plus the tip, under the fee cap
+        assert_eq!(full[1]["transactionIndex"], json!("0x1"));
+        assert_eq!(full[1]["gasPrice"], json!("0x2cb417800"));
+        assert_eq!(full[1]["maxFeePerGas"], json!("0xba43b7400"));
+
+        let single = transaction(&txs[1], hash, &header, TxIndex(1)).unwrap();
+        assert_eq!(serde_json::to_value(single).unwrap(), full[1]);
+
+        // The transactions must be the body's
+        assert!(block(hash, header, &body, &txs[..1], false).is_err());
+    }
+}
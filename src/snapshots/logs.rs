@@ -0,0 +1,443 @@
+//! eth_getLogs-style log queries over block ranges
+//!
+//! A [`LogFilter`] matches logs by emitting address and by topic, with the
+//! semantics of `eth_getLogs`: any of the addresses, and at each topic
+//! position any of the topics given for it; an empty list matches anything.
+//!
+//! Headers carry a logs bloom saying which addresses and topics a block's
+//! logs may hold. A [`BloomIndex`] transposes the blooms of a block range
+//! into one sorted list of blocks per bloom bit, as geth's bloombits do, so
+//! the candidate blocks of a filter are found by merging a few lists instead
+//! of rereading every header. Blooms have false positives: the receipts of
+//! the candidates are read and their logs checked one by one.
+//!
+//! Receipts are not part of block snapshots, so the filter gets them from
+//! the caller, per block; an era1 archive or a node can provide them.
+
+use crate::snapshots::reader::HeadersReader;
+use crate::snapshots::set::SegmentSet;
+use crate::snapshots::types::{BlockNumber, TxIndex};
+use crate::snapshots::Result;
+use alloy_consensus::ReceiptEnvelope;
+use alloy_primitives::{keccak256, Address, Bloom, Log, B256};
+use std::ops::Range;
+
+// Bits of a logs bloom
+const BLOOM_BITS: usize = 2048;
+
+/// The logs blooms of a block range, transposed to blocks per bloom bit
+///
+/// Built once and then shared read-only by any number of filters.
+pub struct BloomIndex {
+    blocks: Range<u64>,
+    // Sorted numbers of the blocks whose bloom has the bit set, by bit
+    bits: Vec<Vec<u64>>,
+}
+
+impl BloomIndex {
+    /// Index the blooms of the headers of `blocks`
+    ///
+    /// Blocks no segment holds are left out, so they are never candidates.
+    /// Reads the headers in order and needs their indexes, like
+    /// [`HeadersReader::iter_range`].
+    pub fn build(headers: &SegmentSet<HeadersReader>, blocks: Range<u64>) -> Result<Self> {
+        let mut index = Self {
+            blocks: blocks.clone(),
+            bits: vec![Vec::new(); BLOOM_BITS],
+        };
+        for (_, ids, reader) in headers.segments() {
+            let from = ids.start.max(blocks.start);
+            let to = ids.end.min(blocks.end);
+            if from >= to {
+                continue;
+            }
+            for item in reader.iter_range(BlockNumber(from), BlockNumber(to))? {
+                let (_, header) = item?;
+                index.add(header.number, &header.logs_bloom);
+            }
+        }
+        Ok(index)
+    }
+
+    fn add(&mut self, number: u64, bloom: &Bloom) {
+        for (i, byte) in bloom.as_slice().iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    self.bits[i * 8 + bit].push(number);
+                }
+            }
+        }
+    }
+
+    /// The block range the index was built over
+    pub fn blocks(&self) -> Range<u64> {
+        self.blocks.clone()
+    }
+
+    /// Sorted numbers of the blocks whose bloom may contain `input`, an
+    /// address or topic
+    pub fn candidates(&self, input: &[u8]) -> Vec<u64> {
+        let mut positions = bloom_positions(input).into_iter();
+        let Some(first) = positions.next() else {
+            return Vec::new();
+        };
+        let mut blocks = self.bits[first].clone();
+        for position in positions {
+            blocks = intersect(&blocks, &self.bits[position]);
+        }
+        blocks
+    }
+}
+
+// Positions in `bits` of the three bloom bits `input` sets, as
+// `Bloom::accrue` sets them: 11-bit numbers from the first six bytes of the
+// input's hash, counted from the end of the bloom
+fn bloom_positions(input: &[u8]) -> [usize; 3] {
+    let hash = keccak256(input);
+    std::array::from_fn(|i| {
+        let bit = (((hash[2 * i] as usize) << 8) | hash[2 * i + 1] as usize) % BLOOM_BITS;
+        let byte = BLOOM_BITS / 8 - 1 - bit / 8;
+        byte * 8 + bit % 8
+    })
+}
+
+fn intersect(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    short
+        .iter()
+        .copied()
+        .filter(|block| long.binary_search(block).is_ok())
+        .collect()
+}
+
+fn union(lists: impl IntoIterator<Item = Vec<u64>>) -> Vec<u64> {
+    let mut blocks: Vec<u64> = lists.into_iter().flatten().collect();
+    blocks.sort_unstable();
+    blocks.dedup();
+    blocks
+}
+
+/// A log a [`LogFilter`] matched, with where it was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct MatchedLog {
+    pub block: BlockNumber,
+    /// Transaction whose receipt holds the log
+    pub transaction: TxIndex,
+    /// Position of the log among all logs of the block
+    pub log_index: u64,
+    pub log: Log,
+}
+
+/// Address and topic filter over the logs of a block range
+///
+/// `receipts` gives the receipts of a block, one per transaction in order;
+/// it is only called for blocks whose bloom matches. The filter borrows the
+/// index, so several filters can run over one index.
+pub struct LogFilter<'a, F> {
+    index: &'a BloomIndex,
+    receipts: F,
+    addresses: Vec<Address>,
+    topics: [Vec<B256>; 4],
+}
+
+impl<'a, F> LogFilter<'a, F>
+where
+    F: FnMut(BlockNumber) -> Result<Vec<ReceiptEnvelope>>,
+{
+    /// A filter matching every log of the blocks in `index`
+    pub fn new(index: &'a BloomIndex, receipts: F) -> Self {
+        Self {
+            index,
+            receipts,
+            addresses: Vec::new(),
+            topics: Default::default(),
+        }
+    }
+
+    /// Also match logs emitted by `address`
+    pub fn address(mut self, address: Address) -> Self {
+        self.addresses.push(address);
+        self
+    }
+
+    /// Also match logs with `topic` at `position`, 0 to 3
+    ///
+    /// # Panics
+    ///
+    /// If `position` is 4 or more; logs have at most four topics.
+    pub fn topic(mut self, position: usize, topic: B256) -> Self {
+        self.topics[position].push(topic);
+        self
+    }
+
+    /// Whether `log` passes the filter
+    pub fn matches(&self, log: &Log) -> bool {
+        if !self.addresses.is_empty() && !self.addresses.contains(&log.address) {
+            return false;
+        }
+        let topics = log.topics();
+        self.topics.iter().enumerate().all(|(position, wanted)| {
+            wanted.is_empty() || topics.get(position).is_some_and(|t| wanted.contains(t))
+        })
+    }
+
+    /// Sorted numbers of the blocks in `range` whose blooms may hold
+    /// matching logs; without criteria, every block of the index
+    pub fn candidates(&self, range: Range<u64>) -> Vec<u64> {
+        let addresses = (!self.addresses.is_empty()).then(|| {
+            union(
+                self.addresses
+                    .iter()
+                    .map(|address| self.index.candidates(address.as_slice())),
+            )
+        });
+        let topics = self
+            .topics
+            .iter()
+            .filter(|wanted| !wanted.is_empty())
+            .map(|wanted| {
+                union(
+                    wanted
+                        .iter()
+                        .map(|topic| self.index.candidates(topic.as_slice())),
+                )
+            });
+        let mut blocks: Option<Vec<u64>> = None;
+        for list in addresses.into_iter().chain(topics) {
+            blocks = Some(match blocks {
+                Some(blocks) => intersect(&blocks, &list),
+                None => list,
+            });
+        }
+        let blocks = blocks.unwrap_or_else(|| union(self.index.bits.iter().cloned()));
+        blocks
+            .into_iter()
+            .filter(|block| range.contains(block))
+            .collect()
+    }
+
+    /// The matching logs of the blocks in `range`, in chain order
+    ///
+    /// Only blocks of the index are searched. Receipts are read lazily, one
+    /// candidate block at a time; an error reading them ends the iteration.
+    pub fn run(&mut self, range: Range<u64>) -> Logs<'_, 'a, F> {
+        Logs {
+            blocks: self.candidates(range).into_iter(),
+            pending: Vec::new().into_iter(),
+            failed: false,
+            filter: self,
+        }
+    }
+
+    fn block_logs(&self, block: BlockNumber, receipts: &[ReceiptEnvelope]) -> Vec<MatchedLog> {
+        let mut matched = Vec::new();
+        let mut log_index = 0;
+        for (transaction, receipt) in receipts.iter().enumerate() {
+            for log in receipt.logs() {
+                if self.matches(log) {
+                    matched.push(MatchedLog {
+                        block,
+                        transaction: TxIndex(transaction as u64),
+                        log_index,
+                        log: log.clone(),
+                    });
+                }
+                log_index += 1;
+            }
+        }
+        matched
+    }
+}
+
+/// Iterator of [`LogFilter::run`]
+pub struct Logs<'f, 'a, F> {
+    filter: &'f mut LogFilter<'a, F>,
+    // Candidate blocks not read yet
+    blocks: std::vec::IntoIter<u64>,
+    // Matches of the last block read
+    pending: std::vec::IntoIter<MatchedLog>,
+    failed: bool,
+}
+
+impl<'a, F> Iterator for Logs<'_, 'a, F>
+where
+    F: FnMut(BlockNumber) -> Result<Vec<ReceiptEnvelope>>,
+{
+    type Item = Result<MatchedLog>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(log) = self.pending.next() {
+                return Some(Ok(log));
+            }
+            if self.failed {
+                return None;
+            }
+            let block = BlockNumber(self.blocks.next()?);
+            match (self.filter.receipts)(block) {
+                Ok(receipts) => {
+                    self.pending = self.filter.block_logs(block, &receipts).into_iter();
+                }
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::CompressionLevel;
+    use crate::snapshots::{HeaderSegmentWriter, SnapshotError, SnapshotRepo};
+    use alloy_consensus::{Eip658Value, Header, Receipt, ReceiptWithBloom, TxReceipt};
+    use alloy_primitives::{address, b256, Bytes};
+    use std::collections::HashMap;
+
+    const TOKEN: Address = address!("00000000000000000000000000000000000000aa");
+    const OTHER: Address = address!("00000000000000000000000000000000000000bb");
+    const TRANSFER: B256 =
+        b256!("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+    const APPROVAL: B256 =
+        b256!("8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925");
+
+    fn log(address: Address, topics: &[B256]) -> Log {
+        Log::new_unchecked(address, topics.to_vec(), Bytes::new())
+    }
+
+    fn receipt(logs: Vec<Log>) -> ReceiptEnvelope {
+        ReceiptEnvelope::Legacy(ReceiptWithBloom::from(Receipt {
+            status: Eip658Value::Eip658(true),
+            cumulative_gas_used: 21_000,
+            logs,
+        }))
+    }
+
+    // Receipts of blocks 0..1000: every 100th block has a transfer of
+    // TOKEN, every 250th an approval of OTHER, the rest none
+    fn chain(dir: &std::path::Path) -> HashMap<u64, Vec<ReceiptEnvelope>> {
+        let mut receipts = HashMap::new();
+        let mut writer = HeaderSegmentWriter::with_compressor(dir, 0, 1000, |b| {
+            b.level(CompressionLevel::Store)
+        })
+        .unwrap();
+        writer.disable_fsync();
+        for number in 0..1000u64 {
+            let mut logs = Vec::new();
+            if number % 100 == 0 {
+                logs.push(log(OTHER, &[APPROVAL]));
+                logs.push(log(TOKEN, &[TRANSFER, B256::with_last_byte(number as u8)]));
+            }
+            if number % 250 == 0 {
+                logs.push(log(OTHER, &[APPROVAL, B256::ZERO]));
+            }
+            let block_receipts = vec![receipt(Vec::new()), receipt(logs)];
+            let mut logs_bloom = Bloom::default();
+            for receipt in &block_receipts {
+                logs_bloom.accrue_bloom(&receipt.bloom());
+            }
+            writer
+                .add_header(&Header {
+                    number,
+                    logs_bloom,
+                    ..Default::default()
+                })
+                .unwrap();
+            receipts.insert(number, block_receipts);
+        }
+        writer.finish().unwrap();
+        receipts
+    }
+
+    #[test]
+    fn test_bloom_index_matches_headers() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        chain(tmp_dir.path());
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        let headers = SegmentSet::<HeadersReader>::open(&repo).unwrap();
+        let index = BloomIndex::build(&headers, 0..2000).unwrap();
+        assert_eq!(index.blocks(), 0..2000);
+
+        let (_, _, reader) = headers.segments().next().unwrap();
+        for input in [TOKEN.as_slice(), OTHER.as_slice(), TRANSFER.as_slice()] {
+            let expected: Vec<u64> = reader
+                .blocks_matching_bloom(BlockNumber(0), BlockNumber(1000), input)
+                .unwrap()
+                .into_iter()
+                .map(BlockNumber::get)
+                .collect();
+            assert_eq!(index.candidates(input), expected);
+        }
+        assert!(index.candidates(&[1, 2, 3]).is_empty());
+    }
+
+    #[test]
+    fn test_log_filter() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let receipts = chain(tmp_dir.path());
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        let headers = SegmentSet::<HeadersReader>::open(&repo).unwrap();
+        let index = BloomIndex::build(&headers, 0..1000).unwrap();
+
+        let mut read = Vec::new();
+        let mut filter = LogFilter::new(&index, |block: BlockNumber| {
+            read.push(block.get());
+            Ok(receipts[&block.get()].clone())
+        })
+        .address(TOKEN)
+        .topic(0, TRANSFER);
+        let logs: Vec<MatchedLog> = filter.run(150..450).map(Result::unwrap).collect();
+        let blocks: Vec<u64> = logs.iter().map(|log| log.block.get()).collect();
+        assert_eq!(blocks, [200, 300, 400]);
+        assert_eq!(logs[0].transaction, TxIndex(1));
+        assert_eq!(logs[0].log_index, 1);
+        assert_eq!(logs[0].log.topics()[1], B256::with_last_byte(200));
+        drop(filter);
+        // Only the candidates' receipts were read
+        assert_eq!(read, [200, 300, 400]);
+
+        // Two topics at position 1, and a topic at position 1 alone
+        let by_topic = |topics: &[(usize, B256)]| {
+            let mut filter = LogFilter::new(&index, |block: BlockNumber| {
+                Ok(receipts[&block.get()].clone())
+            });
+            for &(position, topic) in topics {
+                filter = filter.topic(position, topic);
+            }
+            filter
+                .run(0..1000)
+                .map(|log| log.unwrap().block.get())
+                .collect::<Vec<u64>>()
+        };
+        assert_eq!(
+            by_topic(&[
+                (1, B256::with_last_byte(100)),
+                (1, B256::with_last_byte(44))
+            ]),
+            [100, 300]
+        );
+        assert_eq!(by_topic(&[(1, B256::ZERO)]), [0, 0, 250, 500, 750]);
+
+        // Without criteria, every log in range
+        let mut all = LogFilter::new(&index, |block: BlockNumber| {
+            Ok(receipts[&block.get()].clone())
+        });
+        assert_eq!(all.run(0..101).count(), 5);
+
+        // A failed receipt read is returned once and ends the iteration
+        let mut failing = LogFilter::new(&index, |block: BlockNumber| {
+            Err(SnapshotError::BlockNotFound(block.get()))
+        })
+        .address(OTHER);
+        let results: Vec<_> = failing.run(0..1000).collect();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(SnapshotError::BlockNotFound(0))));
+    }
+}
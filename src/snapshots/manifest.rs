@@ -0,0 +1,160 @@
+//! Lists of the files a snapshot directory holds
+//!
+//! Erigon knows snapshot files by name and torrent infohash: the
+//! preverified `<chain>.toml` files of erigon-snapshot hold
+//! `'<name>' = '<infohash>'` lines, and webseeds serve a `manifest.txt` with
+//! one name per line. [`Manifest`] reads and writes both, for the
+//! `downloader` on one side and
+//! [`SnapshotRepo::write_manifest`](crate::snapshots::SnapshotRepo::write_manifest)
+//! on the other.
+
+use crate::snapshots::{Result, SnapshotError};
+use std::fs;
+use std::path::{Component, Path};
+
+/// A file listed in a snapshot manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path relative to the snapshot directory, e.g.
+    /// `v1-000000-000500-headers.seg` or `domain/v1-accounts.0-64.kv`
+    pub name: String,
+    /// Infohash of the file's torrent as lowercase hex, if the manifest
+    /// pins it
+    pub info_hash: Option<String>,
+}
+
+/// The files a snapshot directory is expected to hold
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Read a manifest file, see [`Manifest::parse`]
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parse a preverified `.toml` (`'<name>' = '<infohash>'` lines) or a
+    /// webseed `manifest.txt` (one name per line)
+    ///
+    /// Blank lines and `#` comments are skipped. Names must stay inside the
+    /// snapshot directory.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad_line = |reason: &str| {
+                SnapshotError::InvalidFormat(format!(
+                    "Invalid manifest line {}: {}",
+                    line_no + 1,
+                    reason
+                ))
+            };
+            let (name, info_hash) = match line.split_once('=') {
+                Some((name, hash)) => {
+                    let hash = unquote(hash.trim()).to_ascii_lowercase();
+                    if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        return Err(bad_line("infohash is not 40 hex digits"));
+                    }
+                    (unquote(name.trim()), Some(hash))
+                }
+                None => (unquote(line), None),
+            };
+            if !is_relative_name(name) {
+                return Err(bad_line("name is not a path inside the snapshot directory"));
+            }
+            entries.push(ManifestEntry {
+                name: name.to_string(),
+                info_hash,
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    /// The preverified `.toml` form; entries without an infohash are left
+    /// out, as Erigon would not accept them
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        for entry in &self.entries {
+            if let Some(info_hash) = &entry.info_hash {
+                toml.push_str(&format!("'{}' = '{}'\n", entry.name, info_hash));
+            }
+        }
+        toml
+    }
+
+    /// The `manifest.txt` form a webseed serves
+    pub fn to_list(&self) -> String {
+        let mut list = String::new();
+        for entry in &self.entries {
+            list.push_str(&entry.name);
+            list.push('\n');
+        }
+        list
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = s
+            .strip_prefix(quote)
+            .and_then(|rest| rest.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    s
+}
+
+// Relative, without `..`, so that it stays inside the snapshot directory
+pub(crate) fn is_relative_name(name: &str) -> bool {
+    !name.is_empty()
+        && Path::new(name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let toml = "\
+# preverified mainnet
+'v1-000000-000500-headers.seg' = 'E9B5C5D1885EE3C6AB6005919E511E1E04C7E34E'
+\"domain/v1-accounts.0-64.kv\" = \"0123456789abcdef0123456789abcdef01234567\"
+";
+        let manifest = Manifest::parse(toml).unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        assert_eq!(manifest.entries[0].name, "v1-000000-000500-headers.seg");
+        assert_eq!(
+            manifest.entries[0].info_hash.as_deref(),
+            Some("e9b5c5d1885ee3c6ab6005919e511e1e04c7e34e")
+        );
+        assert_eq!(manifest.entries[1].name, "domain/v1-accounts.0-64.kv");
+        // Written back in the single-quoted form Erigon uses
+        assert_eq!(Manifest::parse(&manifest.to_toml()).unwrap(), manifest);
+        assert!(manifest
+            .to_toml()
+            .starts_with("'v1-000000-000500-headers.seg' = 'e9b5c5d1"));
+
+        let list = Manifest::parse("v1-000000-000500-bodies.seg\n\nv1-000000-000500-bodies.idx\n")
+            .unwrap();
+        assert_eq!(list.entries.len(), 2);
+        assert_eq!(list.entries[1].info_hash, None);
+        assert_eq!(
+            list.to_list(),
+            "v1-000000-000500-bodies.seg\nv1-000000-000500-bodies.idx\n"
+        );
+        assert_eq!(list.to_toml(), "");
+
+        for bad in ["../etc/passwd", "/abs.seg", "'a.seg' = 'nothex'", "'' = ''"] {
+            assert!(Manifest::parse(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Read-only contents of an index or segment file
+//!
+//! Files are memory mapped where the platform supports it and read into a
+//! buffer otherwise: on wasm targets, which have no mmap, and wherever
+//! mapping a file fails, e.g. empty files on Windows or filesystems that
+//! refuse it.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::{Deref, Range};
+
+/// Access pattern to tell the kernel about, see [`FileData::advise`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// `MADV_SEQUENTIAL`: read ahead further and free pages soon after use
+    Sequential,
+    /// `MADV_WILLNEED`: start reading the pages in now
+    WillNeed,
+}
+
+/// The bytes of a file, mapped or buffered
+pub(crate) enum FileData {
+    #[cfg(not(target_family = "wasm"))]
+    Mapped(memmap2::Mmap),
+    Buffered(Vec<u8>),
+}
+
+impl FileData {
+    /// Map `file`, falling back to reading it whole
+    pub(crate) fn open(file: &File) -> io::Result<Self> {
+        #[cfg(not(target_family = "wasm"))]
+        if file.metadata()?.len() > 0 {
+            // SAFETY: the file is opened read-only and snapshot files are
+            // immutable once renamed into place
+            match unsafe { memmap2::Mmap::map(file) } {
+                Ok(mmap) => return Ok(FileData::Mapped(mmap)),
+                Err(e) => tracing::debug!("mmap failed ({}), reading the file instead", e),
+            }
+        }
+        Self::read(file)
+    }
+
+    /// Read `file` whole into memory
+    pub(crate) fn read(mut file: &File) -> io::Result<Self> {
+        let len = usize::try_from(file.metadata()?.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                "file does not fit in the address space",
+            )
+        })?;
+        let mut data = Vec::with_capacity(len);
+        file.read_to_end(&mut data)?;
+        Ok(FileData::Buffered(data))
+    }
+
+    /// Advise the kernel how the bytes in `range` are about to be read
+    ///
+    /// Only mapped files on unix take advice; buffered data is already in
+    /// memory, so there this does nothing.
+    pub(crate) fn advise(&self, access: Access, range: Range<usize>) -> io::Result<()> {
+        let range = range.start.min(self.len())..range.end.min(self.len());
+        if range.is_empty() {
+            return Ok(());
+        }
+        match self {
+            #[cfg(unix)]
+            FileData::Mapped(mmap) => {
+                let advice = match access {
+                    Access::Sequential => memmap2::Advice::Sequential,
+                    Access::WillNeed => memmap2::Advice::WillNeed,
+                };
+                mmap.advise_range(advice, range.start, range.len())
+            }
+            _ => {
+                tracing::trace!("No {:?} advice for {:?}, not a unix mapping", access, range);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Deref for FileData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(not(target_family = "wasm"))]
+            FileData::Mapped(mmap) => mmap,
+            FileData::Buffered(data) => data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_mapped_and_buffered_agree() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("data");
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let file = File::open(&path).unwrap();
+        let mapped = FileData::open(&file).unwrap();
+        assert!(matches!(mapped, FileData::Mapped(_)));
+        assert_eq!(&*mapped, &data[..]);
+        let buffered = FileData::read(&File::open(&path).unwrap()).unwrap();
+        assert!(matches!(buffered, FileData::Buffered(_)));
+        assert_eq!(&*buffered, &data[..]);
+
+        // Empty files can't be mapped everywhere, so they never are
+        let empty = tmp_dir.path().join("empty");
+        std::fs::write(&empty, b"").unwrap();
+        let data = FileData::open(&File::open(&empty).unwrap()).unwrap();
+        assert!(matches!(data, FileData::Buffered(_)));
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn test_advise_clamps_range() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("data");
+        std::fs::write(&path, vec![7u8; 3 * 4096 + 5]).unwrap();
+
+        for data in [
+            FileData::open(&File::open(&path).unwrap()).unwrap(),
+            FileData::read(&File::open(&path).unwrap()).unwrap(),
+        ] {
+            data.advise(Access::Sequential, 0..data.len()).unwrap();
+            // Unaligned starts and ends past the file are fine
+            data.advise(Access::WillNeed, 4097..1 << 20).unwrap();
+            data.advise(Access::WillNeed, 1 << 20..2 << 20).unwrap();
+            assert_eq!(data[4097], 7);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Erigon's block tables, to and from segments
+//!
+//! Erigon keeps recent blocks in its MDBX database and moves them into
+//! segments once they are final, "retiring" them. [`import_tables`] does the
+//! same for a block range: it reads the canonical headers, bodies,
+//! transactions and senders from the tables and writes the headers, bodies
+//! and transactions segments with their indexes, as
+//! [`import_era1`](super::era1::import_era1) does from era1 files.
+//! [`export_tables`] writes the blocks of segments back into the tables, for
+//! a node to serve them from its database again.
+//!
+//! Both go through [`ChainTables`], which any store laid out like Erigon's
+//! [`tables`] can implement; with the `mdbx` feature libmdbx transactions
+//! do, read-only ones for reading.
+//!
+//! Based on erigon-lib/kv/tables.go and turbo/snapshotsync/freezeblocks.
+
+use crate::compress::Compressor;
+use crate::snapshots::reader::{
+    BodiesReader, HeadersReader, StoredBody, StoredTransaction, TransactionsReader,
+};
+use crate::snapshots::repo::{SnapshotFile, SnapshotRepo, SnapshotType};
+use crate::snapshots::set::SegmentSet;
+use crate::snapshots::types::{BlockNumber, TxIndex, TxNum};
+use crate::snapshots::writer::{build_block_index, build_transactions_index};
+use crate::snapshots::{HeaderSegmentWriter, Result, SnapshotError};
+use alloy_consensus::Header;
+use alloy_primitives::{keccak256, Address, B256};
+use alloy_rlp::Decodable;
+use std::ops::Range;
+use std::path::Path;
+
+/// Names of the Erigon tables the blocks are kept in
+pub mod tables {
+    /// Block number to the hash of its canonical header
+    pub const CANONICAL_HEADER: &str = "CanonicalHeader";
+    /// Block number and hash to the RLP of the header
+    pub const HEADERS: &str = "Header";
+    /// Header hash to the block number
+    pub const HEADER_NUMBER: &str = "HeaderNumber";
+    /// Block number and hash to the body, as in bodies segments
+    pub const BLOCK_BODY: &str = "BlockBody";
+    /// Transaction id to the EIP-2718 encoding of the transaction
+    pub const ETH_TX: &str = "BlockTransaction";
+    /// Block number and hash to the senders of the block's transactions,
+    /// 20 bytes each
+    pub const SENDERS: &str = "TxSender";
+}
+
+/// Reads from tables laid out like Erigon's, see [`tables`]
+pub trait ChainTables {
+    /// The value of `key` in `table`, `None` if it has none
+    fn get(&self, table: &str, key: &[u8]) -> Result<Option<Vec<u8>>>;
+}
+
+/// Writes to tables laid out like Erigon's
+pub trait ChainTablesMut: ChainTables {
+    /// Set the value of `key` in `table`, replacing the one it had
+    fn put(&mut self, table: &str, key: &[u8], value: &[u8]) -> Result<()>;
+}
+
+#[cfg(feature = "mdbx")]
+impl<K, E> ChainTables for libmdbx::Transaction<'_, K, E>
+where
+    K: libmdbx::TransactionKind,
+    E: libmdbx::DatabaseKind,
+{
+    fn get(&self, table: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let table = self.open_table(Some(table))?;
+        let value: Option<Vec<u8>> = libmdbx::Transaction::get(self, &table, key)?;
+        Ok(value)
+    }
+}
+
+#[cfg(feature = "mdbx")]
+impl<E: libmdbx::DatabaseKind> ChainTablesMut for libmdbx::Transaction<'_, libmdbx::RW, E> {
+    fn put(&mut self, table: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        let table = self.create_table(Some(table), libmdbx::TableFlags::empty())?;
+        libmdbx::Transaction::put(self, &table, key, value, libmdbx::WriteFlags::UPSERT)?;
+        Ok(())
+    }
+}
+
+/// Key of a block in the tables keyed by block: its number, big-endian, and
+/// its hash
+pub fn block_key(number: BlockNumber, hash: B256) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..8].copy_from_slice(&number.get().to_be_bytes());
+    key[8..].copy_from_slice(hash.as_slice());
+    key
+}
+
+/// Write the headers, bodies and transactions segments of `blocks`, with
+/// their indexes, into `dir` from the canonical blocks of `db`; returns the
+/// TxNum after the last block's transactions
+///
+/// `blocks` is a block file range, its bounds multiples of 1000, and
+/// `first_tx_num` the TxNum its first block starts at. Bodies are numbered
+/// from it whatever transaction ids the database gave them. A block without
+/// senders in [`tables::SENDERS`] has its transactions stored without one,
+/// to be recovered from their signatures when read.
+pub fn import_tables(
+    db: &impl ChainTables,
+    dir: &Path,
+    blocks: Range<u64>,
+    first_tx_num: TxNum,
+    fsync: bool,
+) -> Result<TxNum> {
+    let mut headers = HeaderSegmentWriter::new(dir, blocks.start, blocks.end)?;
+    if !fsync {
+        headers.disable_fsync();
+    }
+    let segment = |kind| {
+        let stem = SnapshotFile::stem(kind, blocks.start, blocks.end);
+        dir.join(format!("{}.seg", stem))
+    };
+    let compressor = |path: &Path, prefix: &str| {
+        Compressor::builder(path)
+            .log_prefix(prefix)
+            .fsync(fsync)
+            .build()
+    };
+    let bodies_path = segment(SnapshotType::Bodies);
+    let transactions_path = segment(SnapshotType::Transactions);
+    let mut bodies = compressor(&bodies_path, "bodies")?;
+    let mut transactions = compressor(&transactions_path, "transactions")?;
+
+    let mut tx_num = first_tx_num;
+    for number in blocks.clone() {
+        let missing = |table: &str| {
+            SnapshotError::InvalidFormat(format!("Block {} is not in {}", number, table))
+        };
+        let hash = db
+            .get(tables::CANONICAL_HEADER, &number.to_be_bytes())?
+            .filter(|hash| hash.len() == 32)
+            .map(|hash| B256::from_slice(&hash))
+            .ok_or(SnapshotError::BlockNotFound(number))?;
+        let key = block_key(BlockNumber(number), hash);
+        let header = db
+            .get(tables::HEADERS, &key)?
+            .ok_or_else(|| missing(tables::HEADERS))?;
+        headers.add_header(&Header::decode(&mut header.as_slice())?)?;
+
+        let mut body = StoredBody::decode(
+            &db.get(tables::BLOCK_BODY, &key)?
+                .ok_or_else(|| missing(tables::BLOCK_BODY))?,
+        )?;
+        // The block's own transactions follow the system one opening it
+        let tx_ids = body.base_tx_num.get() + 1..;
+        let senders = db.get(tables::SENDERS, &key)?.unwrap_or_default();
+        body.base_tx_num = tx_num;
+        bodies.add_word(&body.encode())?;
+
+        transactions.add_word(&[])?;
+        for (i, id) in tx_ids.take(body.transaction_count() as usize).enumerate() {
+            let tx = db.get(tables::ETH_TX, &id.to_be_bytes())?.ok_or_else(|| {
+                SnapshotError::InvalidFormat(format!(
+                    "Transaction {} of block {} is not in {}",
+                    id,
+                    number,
+                    tables::ETH_TX
+                ))
+            })?;
+            let sender = senders
+                .get(i * Address::len_bytes()..(i + 1) * Address::len_bytes())
+                .unwrap_or(Address::ZERO.as_slice());
+            let mut word = Vec::with_capacity(1 + sender.len() + tx.len());
+            word.push(keccak256(&tx)[0]);
+            word.extend_from_slice(sender);
+            word.extend_from_slice(&tx);
+            transactions.add_word(&word)?;
+        }
+        transactions.add_word(&[])?;
+        tx_num = tx_num.offset(u64::from(body.tx_count));
+    }
+
+    headers.finish()?;
+    bodies.compress()?;
+    transactions.compress()?;
+    build_block_index(&bodies_path, SnapshotType::Bodies, blocks.start, fsync)?;
+    build_transactions_index(&transactions_path, &bodies_path, fsync)?;
+    Ok(tx_num)
+}
+
+/// Write the blocks of `blocks` from the block segments of `repo` into `db`
+/// as canonical
+///
+/// Transactions keep their TxNum as id. Senders the segments do not have
+/// are recovered from the signatures, which needs the `senders` feature.
+pub fn export_tables(
+    repo: &SnapshotRepo,
+    db: &mut impl ChainTablesMut,
+    blocks: Range<u64>,
+) -> Result<()> {
+    let headers = SegmentSet::<HeadersReader>::open(repo)?;
+    let bodies = SegmentSet::<BodiesReader>::open(repo)?;
+    let transactions = SegmentSet::<TransactionsReader>::open(repo)?;
+
+    for number in blocks {
+        let header = headers
+            .header(BlockNumber(number))?
+            .ok_or(SnapshotError::BlockNotFound(number))?;
+        let body = bodies
+            .body(BlockNumber(number))?
+            .ok_or(SnapshotError::BlockNotFound(number))?;
+        let hash = header.hash_slow();
+        let key = block_key(BlockNumber(number), hash);
+        db.put(
+            tables::CANONICAL_HEADER,
+            &number.to_be_bytes(),
+            hash.as_slice(),
+        )?;
+        db.put(
+            tables::HEADER_NUMBER,
+            hash.as_slice(),
+            &number.to_be_bytes(),
+        )?;
+        db.put(tables::HEADERS, &key, &alloy_rlp::encode(&header))?;
+        db.put(tables::BLOCK_BODY, &key, &body.encode())?;
+
+        let Some(first) = body.tx_num(TxIndex(0)) else {
+            continue;
+        };
+        let reader = transactions
+            .locate(first.get())
+            .ok_or(SnapshotError::BlockNotFound(number))?
+            .0;
+        let mut senders = Vec::with_capacity(body.transaction_count() as usize * 20);
+        for tx in reader.transactions(&body)? {
+            db.put(tables::ETH_TX, &tx.tx_num.get().to_be_bytes(), &tx.encoded)?;
+            senders.extend_from_slice(sender(reader, &tx)?.as_slice());
+        }
+        db.put(tables::SENDERS, &key, &senders)?;
+    }
+    Ok(())
+}
+
+// The sender of `tx`, from its word or wherever the reader finds it
+fn sender(reader: &TransactionsReader, tx: &StoredTransaction) -> Result<Address> {
+    if tx.has_sender() {
+        return Ok(tx.sender);
+    }
+    reader
+        .sender_of(tx.tx_num)?
+        .ok_or(SnapshotError::BlockNotFound(tx.tx_num.get()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    // Tables in memory
+    #[derive(Debug, Default, PartialEq)]
+    struct MemTables(BTreeMap<(String, Vec<u8>), Vec<u8>>);
+
+    impl ChainTables for MemTables {
+        fn get(&self, table: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            Ok(self.0.get(&(table.to_string(), key.to_vec())).cloned())
+        }
+    }
+
+    impl ChainTablesMut for MemTables {
+        fn put(&mut self, table: &str, key: &[u8], value: &[u8]) -> Result<()> {
+            self.0
+                .insert((table.to_string(), key.to_vec()), value.to_vec());
+            Ok(())
+        }
+    }
+
+    // A chain of `count` blocks with two transactions every 100 blocks, in
+    // the tables as export_tables writes them
+    fn chain(count: u64) -> MemTables {
+        let mut db = MemTables::default();
+        let mut parent_hash = B256::ZERO;
+        let mut tx_num = 0;
+        for number in 0..count {
+            let header = Header {
+                number,
+                parent_hash,
+                gas_limit: 30_000_000,
+                ..Default::default()
+            };
+            let hash = header.hash_slow();
+            let key = block_key(BlockNumber(number), hash);
+            let tx_count = if number % 100 == 99 { 2 } else { 0 };
+            let body = StoredBody {
+                base_tx_num: TxNum(tx_num),
+                tx_count: tx_count + 2,
+                ommers: Vec::new(),
+                withdrawals: None,
+            };
+            db.put(
+                tables::CANONICAL_HEADER,
+                &number.to_be_bytes(),
+                hash.as_slice(),
+            )
+            .unwrap();
+            db.put(
+                tables::HEADER_NUMBER,
+                hash.as_slice(),
+                &number.to_be_bytes(),
+            )
+            .unwrap();
+            db.put(tables::HEADERS, &key, &alloy_rlp::encode(&header))
+                .unwrap();
+            db.put(tables::BLOCK_BODY, &key, &body.encode()).unwrap();
+            if tx_count > 0 {
+                let mut senders = Vec::new();
+                for i in 0..tx_count as u64 {
+                    let id = tx_num + 1 + i;
+                    let tx = format!("transaction {}", id).into_bytes();
+                    db.put(tables::ETH_TX, &id.to_be_bytes(), &tx).unwrap();
+                    senders.extend_from_slice(Address::with_last_byte(id as u8).as_slice());
+                }
+                db.put(tables::SENDERS, &key, &senders).unwrap();
+            }
+            parent_hash = hash;
+            tx_num += u64::from(tx_count) + 2;
+        }
+        db
+    }
+
+    #[test]
+    fn test_import_then_export() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let db = chain(1000);
+        let next = import_tables(&db, tmp_dir.path(), 0..1000, TxNum(0), false).unwrap();
+        assert_eq!(next, TxNum(1000 * 2 + 10 * 2));
+
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        let transactions = SegmentSet::<TransactionsReader>::open(&repo).unwrap();
+        let (reader, _) = transactions.locate(100).unwrap();
+        let tx = reader.transaction(TxNum(200)).unwrap().unwrap();
+        assert_eq!(tx.encoded, b"transaction 200");
+        assert_eq!(tx.sender, Address::with_last_byte(200));
+        assert_eq!(tx.hash_prefix, keccak256(b"transaction 200")[0]);
+
+        let mut exported = MemTables::default();
+        export_tables(&repo, &mut exported, 0..1000).unwrap();
+        assert_eq!(exported, db);
+
+        // A block the tables do not have
+        assert!(matches!(
+            import_tables(&db, tmp_dir.path(), 1000..2000, next, false),
+            Err(SnapshotError::BlockNotFound(1000))
+        ));
+    }
+}
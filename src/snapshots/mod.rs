@@ -1,17 +1,86 @@
+pub mod bor;
+pub mod btree;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod caplin;
+pub mod chainspec;
+pub mod domain;
+#[cfg(feature = "downloader")]
+pub mod downloader;
+mod elias_fano;
+#[cfg(feature = "era1")]
+pub mod era1;
 pub mod error;
+pub mod existence;
+pub mod export;
+pub mod facade;
+pub mod fsck;
+mod golomb_rice;
+pub mod history;
 pub mod index;
+pub mod json;
+pub mod logs;
+pub mod manifest;
+pub(crate) mod mapped;
+pub mod mdbx;
+pub mod open_files;
+pub mod postings;
 pub mod reader;
 pub mod recsplit;
+pub mod registry;
+pub mod repo;
+pub mod retention;
+pub mod salt;
+pub mod set;
+pub mod state;
+pub mod temporal;
+pub mod torrent;
+pub mod types;
+pub mod writer;
 
-pub use error::{Result, SnapshotError};
+pub use bor::{BorEventsReader, BorSpansReader};
+pub use btree::BtIndex;
+#[cfg(feature = "cache")]
+pub use cache::{CacheSource, CacheStats, CachedReader};
+pub use caplin::{BeaconBlocksReader, BlobSidecarsReader};
+pub use chainspec::{validate_header, ChainSpec};
+pub use domain::{DomainRange, DomainReader};
+#[cfg(feature = "downloader")]
+pub use downloader::{DownloadReport, Downloader, HttpTransport, Transport};
+#[cfg(feature = "era1")]
+pub use era1::{export_era1, import_era1, Era1Block, Era1Reader, Era1Writer};
+pub use error::{BodyViolation, ChainViolation, Result, SnapshotError};
+pub use existence::ExistenceFilter;
+pub use facade::{Capabilities, SegmentInfo, Snapshots, SnapshotsConfig, StateFileInfo};
+pub use fsck::{FsckReport, FsckViolation};
+pub use history::{HistoryReader, InvertedIndexReader, TxNumSequence};
 pub use index::IndexReader;
-pub use reader::HeadersReader;
+pub use logs::{BloomIndex, LogFilter, Logs, MatchedLog};
+pub use manifest::{Manifest, ManifestEntry};
+pub use mdbx::{export_tables, import_tables, ChainTables, ChainTablesMut};
+pub use open_files::{OpenFileStats, OpenFiles};
+pub use postings::{Difference, Intersect, PostingList, Postings, Union};
+pub use reader::{
+    BodiesReader, ChainValidation, HeaderFormat, HeaderRange, HeadersReader, SendersReader,
+    StoredBody, StoredTransaction, TransactionsReader,
+};
+pub use registry::{Accessor, DomainKind, ErigonReader, IndexFlavor, SegmentType, ValueEncoding};
+pub use repo::{SnapshotFile, SnapshotRepo, SnapshotType, StateFile};
+pub use retention::RetentionPlan;
+pub use set::{SegmentSet, SegmentSetMember};
+pub use state::{Account, StateReader, TxNums};
+pub use temporal::{InvertedIdx, Order, TemporalReader, TemporalTx};
+pub use torrent::{verify_file_against_torrent, TorrentInfo};
+pub use types::{BlockNumber, TxIndex, TxNum};
+pub use writer::{
+    build_block_index, build_headers_index, build_transactions_index, HeaderSegmentWriter,
+};
 
 #[cfg(test)]
 mod tests {
     use alloy_consensus::Header;
     use alloy_primitives::{FixedBytes, B256};
-    use alloy_rlp::{Decodable, Encodable};
+    use alloy_rlp::Decodable;
 
     #[test]
     fn test_alloy_header_type() {
@@ -0,0 +1,252 @@
+//! Segments and indexes kept open between lookups
+//!
+//! Opening a segment maps it and parses its dictionaries, opening an index
+//! maps it and reads its header: cheap once, but not on every lookup, and
+//! a slow first lookup per file shows up as latency spikes. [`OpenFiles`]
+//! keeps up to a given number of files open, closing the least recently
+//! used one past that, and counts what it does.
+
+use crate::decompress::Decompressor;
+use crate::metrics;
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::snapshots::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Files kept open by default, see
+/// [`SnapshotRepo::with_open_file_limit`](crate::snapshots::SnapshotRepo::with_open_file_limit)
+pub const DEFAULT_OPEN_FILE_LIMIT: usize = 1024;
+
+/// What an [`OpenFiles`] did since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpenFileStats {
+    /// Lookups of a file that was open
+    pub hits: u64,
+    /// Lookups that opened the file
+    pub misses: u64,
+    /// Files closed to make room for others
+    pub evictions: u64,
+    /// Files open now
+    pub open: usize,
+}
+
+#[derive(Clone)]
+enum OpenFile {
+    Segment(Arc<Decompressor>),
+    Index(Arc<RecSplitIndex>),
+}
+
+// A path may be asked for as a segment and as an index; each is cached on
+// its own, so a lookup always gets the kind it asked for
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum FileKind {
+    Segment,
+    Index,
+}
+
+struct Entry {
+    file: OpenFile,
+    // Value of the use clock when the file was last handed out
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    files: HashMap<(PathBuf, FileKind), Entry>,
+    clock: u64,
+}
+
+/// Open segments and indexes, least recently used closed first
+///
+/// Lookups take `&self` and the set of open files is updated internally,
+/// so one `OpenFiles` serves all the threads or tasks reading a snapshot
+/// directory. Files are handed out as shared references: one evicted while
+/// in use stays open until its last user drops it.
+pub struct OpenFiles {
+    limit: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl OpenFiles {
+    /// Keep up to `limit` files open, at least one
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            entries: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The segment at `path`, opened if it is not open yet
+    pub fn segment(&self, path: &Path) -> Result<Arc<Decompressor>> {
+        let file = self.get_or_open(path, FileKind::Segment, || {
+            Ok(OpenFile::Segment(Arc::new(Decompressor::new(path)?)))
+        })?;
+        match file {
+            OpenFile::Segment(segment) => Ok(segment),
+            OpenFile::Index(_) => unreachable!("segments are cached apart from indexes"),
+        }
+    }
+
+    /// The index at `path`, opened if it is not open yet
+    pub fn index(&self, path: &Path) -> Result<Arc<RecSplitIndex>> {
+        let file = self.get_or_open(path, FileKind::Index, || {
+            Ok(OpenFile::Index(Arc::new(RecSplitIndex::open(path)?)))
+        })?;
+        match file {
+            OpenFile::Index(index) => Ok(index),
+            OpenFile::Segment(_) => unreachable!("indexes are cached apart from segments"),
+        }
+    }
+
+    /// Whether `path` is open, as a segment or as an index
+    pub fn is_open(&self, path: &Path) -> bool {
+        self.lock().files.keys().any(|(open, _)| open == path)
+    }
+
+    pub fn stats(&self) -> OpenFileStats {
+        OpenFileStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            open: self.lock().files.len(),
+        }
+    }
+
+    /// Close every file not in use elsewhere
+    pub fn clear(&self) {
+        self.lock().files.clear();
+    }
+
+    fn get_or_open(
+        &self,
+        path: &Path,
+        kind: FileKind,
+        open: impl FnOnce() -> Result<OpenFile>,
+    ) -> Result<OpenFile> {
+        let key = (path.to_path_buf(), kind);
+        {
+            let mut entries = self.lock();
+            entries.clock += 1;
+            let clock = entries.clock;
+            if let Some(entry) = entries.files.get_mut(&key) {
+                entry.last_used = clock;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                metrics::increment(metrics::OPEN_FILE_HITS, 1);
+                return Ok(entry.file.clone());
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        metrics::increment(metrics::OPEN_FILE_MISSES, 1);
+        // Opened without holding the lock, so that other lookups go on; two
+        // lookups racing for the same file both open it and one is kept
+        let file = open()?;
+
+        let mut entries = self.lock();
+        entries.clock += 1;
+        let clock = entries.clock;
+        if let Some(entry) = entries.files.get_mut(&key) {
+            entry.last_used = clock;
+            return Ok(entry.file.clone());
+        }
+        while entries.files.len() >= self.limit {
+            let Some(oldest) = entries
+                .files
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            tracing::debug!("Closing {} for {}", oldest.0.display(), path.display());
+            entries.files.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        entries.files.insert(
+            key,
+            Entry {
+                file: file.clone(),
+                last_used: clock,
+            },
+        );
+        Ok(file)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        // The entries stay consistent even if a holder panicked
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::write_segment;
+
+    #[test]
+    fn test_least_recently_used_closed() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let paths: Vec<PathBuf> = (0..3)
+            .map(|i| {
+                let path = tmp_dir.path().join(format!("{}.seg", i));
+                write_segment(&path, &[[i as u8; 10]]).unwrap();
+                path
+            })
+            .collect();
+
+        let open_files = OpenFiles::new(2);
+        let first = open_files.segment(&paths[0]).unwrap();
+        open_files.segment(&paths[1]).unwrap();
+        // Used again, so the second one goes first
+        assert!(Arc::ptr_eq(&first, &open_files.segment(&paths[0]).unwrap()));
+        open_files.segment(&paths[2]).unwrap();
+        assert!(open_files.is_open(&paths[0]));
+        assert!(!open_files.is_open(&paths[1]));
+        assert_eq!(
+            open_files.stats(),
+            OpenFileStats {
+                hits: 1,
+                misses: 3,
+                evictions: 1,
+                open: 2,
+            }
+        );
+
+        // Evicted files stay usable by whoever holds them
+        open_files.clear();
+        assert_eq!(first.count(), 1);
+        assert!(open_files
+            .segment(&tmp_dir.path().join("missing.seg"))
+            .is_err());
+        assert_eq!(open_files.stats().open, 0);
+    }
+
+    #[test]
+    fn test_same_path_as_segment_and_index() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("v1-000000-000500-headers.seg");
+        write_segment(&path, &[b"header"]).unwrap();
+
+        let open_files = OpenFiles::new(4);
+        let segment = open_files.segment(&path).unwrap();
+        // Not an index, so it fails to open as one instead of handing out
+        // the cached segment
+        assert!(open_files.index(&path).is_err());
+        assert!(Arc::ptr_eq(&segment, &open_files.segment(&path).unwrap()));
+        assert!(open_files.is_open(&path));
+        assert_eq!(open_files.stats().misses, 2);
+    }
+}
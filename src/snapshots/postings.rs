@@ -0,0 +1,362 @@
+//! Set operations over the txNums of inverted index keys
+//!
+//! An inverted index maps a key to the sorted txNums that touched it.
+//! Queries like "txNums touching address A and storage key B" combine those
+//! posting lists; [`Postings`] are sorted txNum iterators that can also peek
+//! and jump ahead, which is what [`Intersect`], [`Union`] and [`Difference`]
+//! need to combine them lazily. Nothing is collected: intersecting a short
+//! list with a long one seeks through the long one by binary search instead
+//! of decoding it all.
+
+use crate::snapshots::history::TxNumSequence;
+use crate::snapshots::recsplit::EfIterator;
+
+/// A sorted, duplicate-free iterator of txNums that can peek and seek
+pub trait Postings: Iterator<Item = u64> {
+    /// The txNum [`Iterator::next`] would return, without moving past it
+    fn peek(&mut self) -> Option<u64>;
+
+    /// Move past every txNum below `target`
+    fn seek(&mut self, target: u64);
+
+    /// TxNums in both `self` and `other`
+    fn intersect<P: Postings>(self, other: P) -> Intersect<Self, P>
+    where
+        Self: Sized,
+    {
+        Intersect::new(self, other)
+    }
+
+    /// TxNums in `self`, `other` or both, each once
+    fn union<P: Postings>(self, other: P) -> Union<Self, P>
+    where
+        Self: Sized,
+    {
+        Union::new(self, other)
+    }
+
+    /// TxNums in `self` but not in `other`
+    fn difference<P: Postings>(self, other: P) -> Difference<Self, P>
+    where
+        Self: Sized,
+    {
+        Difference::new(self, other)
+    }
+}
+
+/// The txNums of a key over consecutive inverted index files
+///
+/// The sequences must be in txNum order, one per file, as
+/// [`TemporalReader::sequences`](crate::snapshots::TemporalReader::sequences)
+/// returns them. Values are decoded as they are iterated, and
+/// [`Postings::seek`] skips whole files and binary searches within one.
+pub struct PostingList<'a> {
+    sequences: &'a [TxNumSequence],
+    // Sequence `iter` reads
+    current: usize,
+    iter: Option<EfIterator<'a>>,
+    head: Option<u64>,
+}
+
+impl<'a> PostingList<'a> {
+    pub fn new(sequences: &'a [TxNumSequence]) -> Self {
+        Self {
+            sequences,
+            current: 0,
+            iter: sequences.first().and_then(|first| first.iter_from(0)),
+            head: None,
+        }
+    }
+
+    // Load the next txNum into `head`, moving on to the next sequences
+    fn fill(&mut self) {
+        while self.head.is_none() && self.current < self.sequences.len() {
+            self.head = self.iter.as_mut().and_then(Iterator::next);
+            if self.head.is_none() {
+                self.current += 1;
+                self.iter = self
+                    .sequences
+                    .get(self.current)
+                    .and_then(|sequence| sequence.iter_from(0));
+            }
+        }
+    }
+}
+
+impl Iterator for PostingList<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        self.fill();
+        self.head.take()
+    }
+}
+
+impl Postings for PostingList<'_> {
+    fn peek(&mut self) -> Option<u64> {
+        self.fill();
+        self.head
+    }
+
+    fn seek(&mut self, target: u64) {
+        if self.peek().is_none_or(|head| head >= target) {
+            return;
+        }
+        self.head = None;
+        // Files ending before the target are skipped whole
+        while let Some(sequence) = self.sequences.get(self.current) {
+            let last = sequence.len().checked_sub(1).and_then(|i| sequence.get(i));
+            if last.is_some_and(|last| last >= target) {
+                break;
+            }
+            self.current += 1;
+        }
+        self.iter = self.sequences.get(self.current).and_then(|sequence| {
+            let (position, _) = sequence.search(target)?;
+            sequence.iter_from(position)
+        });
+    }
+}
+
+// A combinator's next txNum, `None` until computed
+type Head = Option<Option<u64>>;
+
+// Drop a computed head below `target`, and seek both sides past it
+fn seek_both(head: &mut Head, a: &mut impl Postings, b: &mut impl Postings, target: u64) {
+    if let Some(Some(value)) = *head {
+        if value >= target {
+            return;
+        }
+    }
+    *head = None;
+    a.seek(target);
+    b.seek(target);
+}
+
+/// See [`Postings::intersect`]
+pub struct Intersect<A, B> {
+    a: A,
+    b: B,
+    head: Head,
+}
+
+impl<A: Postings, B: Postings> Intersect<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        Self { a, b, head: None }
+    }
+
+    fn compute(&mut self) -> Option<u64> {
+        loop {
+            let x = self.a.peek()?;
+            self.b.seek(x);
+            let y = self.b.peek()?;
+            if x == y {
+                self.a.next();
+                self.b.next();
+                return Some(x);
+            }
+            self.a.seek(y);
+        }
+    }
+}
+
+impl<A: Postings, B: Postings> Iterator for Intersect<A, B> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        match self.head.take() {
+            Some(head) => head,
+            None => self.compute(),
+        }
+    }
+}
+
+impl<A: Postings, B: Postings> Postings for Intersect<A, B> {
+    fn peek(&mut self) -> Option<u64> {
+        if self.head.is_none() {
+            self.head = Some(self.compute());
+        }
+        self.head.flatten()
+    }
+
+    fn seek(&mut self, target: u64) {
+        seek_both(&mut self.head, &mut self.a, &mut self.b, target);
+    }
+}
+
+/// See [`Postings::union`]
+pub struct Union<A, B> {
+    a: A,
+    b: B,
+    head: Head,
+}
+
+impl<A: Postings, B: Postings> Union<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        Self { a, b, head: None }
+    }
+
+    fn compute(&mut self) -> Option<u64> {
+        match (self.a.peek(), self.b.peek()) {
+            (Some(x), Some(y)) => {
+                if x <= y {
+                    self.a.next();
+                }
+                if y <= x {
+                    self.b.next();
+                }
+                Some(x.min(y))
+            }
+            (Some(_), None) => self.a.next(),
+            (None, _) => self.b.next(),
+        }
+    }
+}
+
+impl<A: Postings, B: Postings> Iterator for Union<A, B> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        match self.head.take() {
+            Some(head) => head,
+            None => self.compute(),
+        }
+    }
+}
+
+impl<A: Postings, B: Postings> Postings for Union<A, B> {
+    fn peek(&mut self) -> Option<u64> {
+        if self.head.is_none() {
+            self.head = Some(self.compute());
+        }
+        self.head.flatten()
+    }
+
+    fn seek(&mut self, target: u64) {
+        seek_both(&mut self.head, &mut self.a, &mut self.b, target);
+    }
+}
+
+/// See [`Postings::difference`]
+pub struct Difference<A, B> {
+    a: A,
+    b: B,
+    head: Head,
+}
+
+impl<A: Postings, B: Postings> Difference<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        Self { a, b, head: None }
+    }
+
+    fn compute(&mut self) -> Option<u64> {
+        loop {
+            let x = self.a.next()?;
+            self.b.seek(x);
+            if self.b.peek() != Some(x) {
+                return Some(x);
+            }
+        }
+    }
+}
+
+impl<A: Postings, B: Postings> Iterator for Difference<A, B> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        match self.head.take() {
+            Some(head) => head,
+            None => self.compute(),
+        }
+    }
+}
+
+impl<A: Postings, B: Postings> Postings for Difference<A, B> {
+    fn peek(&mut self) -> Option<u64> {
+        if self.head.is_none() {
+            self.head = Some(self.compute());
+        }
+        self.head.flatten()
+    }
+
+    fn seek(&mut self, target: u64) {
+        seek_both(&mut self.head, &mut self.a, &mut self.b, target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::history::tests::sequence;
+    use std::collections::BTreeSet;
+
+    fn sequences(parts: &[&[u64]]) -> Vec<TxNumSequence> {
+        parts.iter().map(|part| sequence(part)).collect()
+    }
+
+    #[test]
+    fn test_posting_list_seek() {
+        let parts = sequences(&[&[1, 5, 9], &[20, 21, 40], &[100]]);
+        let all: Vec<u64> = PostingList::new(&parts).collect();
+        assert_eq!(all, [1, 5, 9, 20, 21, 40, 100]);
+
+        let mut list = PostingList::new(&parts);
+        list.seek(6);
+        assert_eq!(list.peek(), Some(9));
+        list.seek(10);
+        assert_eq!(list.next(), Some(20));
+        // Seeking back does nothing
+        list.seek(0);
+        assert_eq!(list.next(), Some(21));
+        list.seek(41);
+        assert_eq!(list.next(), Some(100));
+        assert_eq!(list.next(), None);
+        list.seek(1000);
+        assert_eq!(list.peek(), None);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let a_parts: Vec<Vec<u64>> = vec![(0..200).step_by(3).collect(), vec![1000, 1006]];
+        let b_parts: Vec<Vec<u64>> = vec![(0..100).step_by(2).collect(), (500..1010).collect()];
+        let c_parts: Vec<Vec<u64>> = vec![(0..1100).step_by(5).collect()];
+        let set = |parts: &[Vec<u64>]| parts.iter().flatten().copied().collect::<BTreeSet<u64>>();
+        let (a_set, b_set, c_set) = (set(&a_parts), set(&b_parts), set(&c_parts));
+        let as_slices = |parts: &[Vec<u64>]| {
+            let slices: Vec<&[u64]> = parts.iter().map(Vec::as_slice).collect();
+            sequences(&slices)
+        };
+        let (a, b, c) = (
+            as_slices(&a_parts),
+            as_slices(&b_parts),
+            as_slices(&c_parts),
+        );
+        let list = PostingList::new;
+
+        let got: Vec<u64> = list(&a).intersect(list(&b)).collect();
+        let expected: Vec<u64> = a_set.intersection(&b_set).copied().collect();
+        assert_eq!(got, expected);
+
+        let got: Vec<u64> = list(&a).union(list(&b)).collect();
+        let expected: Vec<u64> = a_set.union(&b_set).copied().collect();
+        assert_eq!(got, expected);
+
+        let got: Vec<u64> = list(&a).difference(list(&b)).collect();
+        let expected: Vec<u64> = a_set.difference(&b_set).copied().collect();
+        assert_eq!(got, expected);
+
+        // (a ∪ b) ∩ c, minus b, from 50 on
+        let mut query = list(&a)
+            .union(list(&b))
+            .intersect(list(&c))
+            .difference(list(&b));
+        query.seek(50);
+        let got: Vec<u64> = query.collect();
+        let expected: Vec<u64> = a_set
+            .union(&b_set)
+            .filter(|n| c_set.contains(n) && !b_set.contains(n) && **n >= 50)
+            .copied()
+            .collect();
+        assert_eq!(got, expected);
+    }
+}
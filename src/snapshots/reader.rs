@@ -1,9 +1,24 @@
 use crate::decompress::{Decompressor, Getter};
-use crate::snapshots::{Result, SnapshotError};
-use alloy_consensus::Header;
-use alloy_primitives::B256;
-use alloy_rlp::Decodable;
+use crate::readahead::ReadAhead;
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::snapshots::types::{BlockNumber, TxIndex, TxNum};
+use crate::snapshots::{
+    BodyViolation, ChainViolation, Result, SnapshotError, SnapshotFile, SnapshotType,
+};
+use alloy_consensus::proofs::{calculate_ommers_root, calculate_withdrawals_root};
+use alloy_consensus::{Header, TxEnvelope};
+use alloy_eips::eip2718::Decodable2718;
+use alloy_eips::eip4895::Withdrawal;
+use alloy_primitives::{keccak256, Address, BloomInput, B256, U256};
+use alloy_rlp::{Decodable, Encodable};
+#[cfg(feature = "senders")]
+use lru::LruCache;
+#[cfg(feature = "senders")]
+use std::num::NonZeroUsize;
+use std::ops::Range;
 use std::path::Path;
+#[cfg(feature = "senders")]
+use std::sync::{Mutex, MutexGuard};
 
 /// Reader for headers snapshot files
 /// Headers use direct Getter access without the Reader wrapper,
@@ -11,37 +26,346 @@ use std::path::Path;
 pub struct HeadersReader {
     decompressor: Decompressor,
     total_words: usize,
+    format: HeaderFormat,
 }
 
 impl HeadersReader {
     /// Open a headers snapshot file
+    ///
+    /// If a `.idx` file with the same stem sits next to the segment it is
+    /// opened as well and used by [`HeadersReader::header_by_hash`] and
+    /// [`HeadersReader::header_by_number`].
     pub fn new(path: &Path) -> Result<Self> {
-        let decompressor =
-            Decompressor::new(path).map_err(|e| SnapshotError::Decompression(e.to_string()))?;
+        let idx_path = path.with_extension("idx");
+        let index = if idx_path.exists() {
+            Some(RecSplitIndex::open(&idx_path)?)
+        } else {
+            None
+        };
+        Self::with_index(path, index)
+    }
+
+    /// Open a headers snapshot file with an explicitly given index (or none)
+    ///
+    /// The index must be an enum index with one key per header, as built by
+    /// [`HeaderSegmentWriter`](crate::snapshots::HeaderSegmentWriter).
+    pub fn with_index(path: &Path, index: Option<RecSplitIndex>) -> Result<Self> {
+        let mut decompressor = Decompressor::new(path)?;
+        if let Some(idx) = index {
+            decompressor.attach_index(idx)?;
+        }
         let total_words = decompressor.count();
         Ok(Self {
             decompressor,
             total_words,
+            format: HeaderFormat::ERIGON,
         })
     }
 
+    /// Decode words laid out and checked as `format` says, instead of
+    /// [`HeaderFormat::ERIGON`]
+    pub fn with_format(mut self, format: HeaderFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn format(&self) -> HeaderFormat {
+        self.format
+    }
+
     /// Get the total number of headers in this snapshot
     pub fn count(&self) -> usize {
         self.total_words
     }
 
+    /// The hash-keyed index, if one is attached
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.decompressor.index()
+    }
+
+    /// Number of the first block of this snapshot, from the attached index
+    pub fn first_block(&self) -> Option<BlockNumber> {
+        self.index().map(|idx| BlockNumber(idx.base_data_id()))
+    }
+
+    /// The `i`-th header of this snapshot, or `None` past the last one
+    pub fn header(&self, i: u64) -> Result<Option<(B256, Header)>> {
+        self.decompressor
+            .get_word(i)
+            .map(|word| self.format.decode(&word))
+            .transpose()
+    }
+
+    /// The `i`-th word of this snapshot as stored, hash prefix included
+    pub fn raw_header(&self, i: u64) -> Option<Vec<u8>> {
+        self.decompressor.get_word(i)
+    }
+
+    /// The `i`-th header of this snapshot with the hash prefix of its word,
+    /// see [`HeaderFormat::parse`]
+    pub fn parsed_header(&self, i: u64) -> Result<Option<(Option<u8>, Header)>> {
+        self.decompressor
+            .get_word(i)
+            .map(|word| self.format.parse(&word))
+            .transpose()
+    }
+
+    /// Find a header by its block number
+    ///
+    /// The first block of the snapshot is taken from the index, so this
+    /// needs one attached.
+    pub fn header_by_number(&self, number: BlockNumber) -> Result<Option<Header>> {
+        let first = self.first_block().ok_or(SnapshotError::IndexNotAvailable)?;
+        let Some(i) = number.ordinal_from(first) else {
+            return Ok(None);
+        };
+        Ok(self.header(i)?.map(|(_, header)| header))
+    }
+
+    /// Iterate over the headers of blocks `from..to` that this snapshot
+    /// holds, decoding them lazily
+    ///
+    /// Like [`HeadersReader::header_by_number`] this needs an index attached.
+    /// Call [`HeaderRange::validate`] on the result to check the chain as it
+    /// is read.
+    pub fn iter_range(&self, from: BlockNumber, to: BlockNumber) -> Result<HeaderRange<'_>> {
+        let first = self
+            .first_block()
+            .ok_or(SnapshotError::IndexNotAvailable)?
+            .get();
+        let start = from.get().max(first);
+        let end = to.get().min(first + self.total_words as u64);
+        let mut getter = self.decompressor.make_getter();
+        if start < end {
+            let offset = self
+                .decompressor
+                .word_offset(start - first)
+                .ok_or(SnapshotError::BlockNotFound(start))?;
+            getter.reset(offset);
+        }
+        // The last header of the snapshot ends with the segment
+        let end_offset = self
+            .decompressor
+            .word_offset(end.saturating_sub(first))
+            .unwrap_or(u64::MAX);
+        Ok(HeaderRange {
+            getter,
+            format: self.format,
+            number: start,
+            end,
+            end_offset,
+            validation: None,
+            parent: None,
+        })
+    }
+
+    /// Total difficulty of block `number`, given `parent_td`, that of the
+    /// block before this snapshot's first one (zero for the snapshot that
+    /// starts at genesis); `None` if the block is not in this snapshot
+    ///
+    /// Snapshots don't store total difficulties, Erigon keeps them in its
+    /// database, so the difficulties of the headers up to `number` are
+    /// summed. Needs an index, like [`HeadersReader::header_by_number`].
+    pub fn total_difficulty(&self, number: BlockNumber, parent_td: U256) -> Result<Option<U256>> {
+        let first = self.first_block().ok_or(SnapshotError::IndexNotAvailable)?;
+        match number.ordinal_from(first) {
+            Some(i) if i < self.total_words as u64 => {}
+            _ => return Ok(None),
+        }
+        let mut td = parent_td;
+        for item in self.iter_range(first, number.offset(1))? {
+            td += item?.1.difficulty;
+        }
+        Ok(Some(td))
+    }
+
+    /// The latest header whose timestamp is at most `timestamp`, or `None`
+    /// if this snapshot starts after it
+    ///
+    /// Timestamps increase along the chain, so this is a binary search over
+    /// the attached index, or a scan without one.
+    pub fn find_by_timestamp(&self, timestamp: u64) -> Result<Option<Header>> {
+        let mut error = None;
+        let found = self.decompressor.binary_search_by(|word| {
+            match self.format.decode(word) {
+                Ok((_, header)) => header.timestamp.cmp(&timestamp),
+                Err(e) => {
+                    // Stop the search here, the error is returned below
+                    error = Some(e);
+                    std::cmp::Ordering::Equal
+                }
+            }
+        })?;
+        if let Some(e) = error {
+            return Err(e);
+        }
+        let i = match found {
+            Ok(i) => i,
+            Err(0) => return Ok(None),
+            Err(i) => i - 1,
+        };
+        Ok(self.header(i)?.map(|(_, header)| header))
+    }
+
+    /// Numbers of the blocks in `from..to` whose logs bloom may contain
+    /// `input`, an address or topic that logs are looked up by
+    ///
+    /// Blooms have false positives, so the receipts of the blocks returned
+    /// still need to be checked. Needs an index, like
+    /// [`HeadersReader::iter_range`].
+    pub fn blocks_matching_bloom(
+        &self,
+        from: BlockNumber,
+        to: BlockNumber,
+        input: &[u8],
+    ) -> Result<Vec<BlockNumber>> {
+        let mut blocks = Vec::new();
+        for item in self.iter_range(from, to)? {
+            let (_, header) = item?;
+            if header.logs_bloom.contains_input(BloomInput::Raw(input)) {
+                blocks.push(BlockNumber(header.number));
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Find a header by its block hash
+    ///
+    /// Uses the RecSplit index when available; otherwise falls back to a
+    /// linear scan of the segment. Returns `None` if the hash is not in this
+    /// snapshot.
+    pub fn header_by_hash(&self, hash: B256) -> Result<Option<Header>> {
+        match self.index() {
+            Some(idx) => self.header_by_hash_indexed(idx, hash),
+            None => self.header_by_hash_scan(hash),
+        }
+    }
+
+    fn header_by_hash_indexed(&self, idx: &RecSplitIndex, hash: B256) -> Result<Option<Header>> {
+        // A perfect hash maps unknown keys somewhere too, so the header we
+        // land on is checked against the requested hash below
+        let Some(ordinal) = idx.lookup(hash.as_slice()) else {
+            return Ok(None);
+        };
+        Ok(self
+            .header(ordinal)?
+            .and_then(|(found_hash, header)| (found_hash == hash).then_some(header)))
+    }
+
+    fn header_by_hash_scan(&self, hash: B256) -> Result<Option<Header>> {
+        tracing::debug!("No headers index, scanning segment for {:?}", hash);
+        let mut getter = self.decompressor.make_getter();
+        while getter.has_next() {
+            let (word, _) = getter.next(Vec::new());
+            // First byte of each word is hash[0]; only decode candidates,
+            // unless words may come without it
+            if !self.format.allow_unprefixed && word.first() != Some(&hash[0]) {
+                continue;
+            }
+            let (found, header) = self.format.decode(&word)?;
+            if found == hash {
+                return Ok(Some(header));
+            }
+        }
+        Ok(None)
+    }
+
     /// Create a getter for iterating through headers
-    pub fn make_getter(&self) -> HeaderGetter {
+    pub fn make_getter(&self) -> HeaderGetter<'_> {
         HeaderGetter {
             getter: self.decompressor.make_getter(),
+            format: self.format,
             block_number: 0, // Will be set based on snapshot range
         }
     }
 }
 
+/// How the words of a headers segment are laid out and checked, see
+/// [`HeadersReader::with_format`]
+///
+/// Erigon stores each header as the first byte of its hash followed by its
+/// RLP, so that scans by hash only decode one header in 256.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderFormat {
+    /// Fail on words whose prefix is not the first byte of the header's
+    /// hash, which costs hashing every header read
+    pub strict: bool,
+    /// Also accept words holding the header's RLP alone, as some older
+    /// tools wrote them
+    pub allow_unprefixed: bool,
+}
+
+impl HeaderFormat {
+    /// Erigon's layout, with prefixes checked
+    pub const ERIGON: Self = Self {
+        strict: true,
+        allow_unprefixed: false,
+    };
+
+    /// The hash prefix of `word`, `None` if it has none, and its header
+    pub fn parse(&self, word: &[u8]) -> Result<(Option<u8>, Header)> {
+        let (prefix, header) = self.split(word)?;
+        if self.strict {
+            check_hash_prefix(prefix, &header.hash_slow())?;
+        }
+        Ok((prefix, header))
+    }
+
+    /// The hash of the header in `word`, and the header
+    pub fn decode(&self, word: &[u8]) -> Result<(B256, Header)> {
+        let (prefix, header) = self.split(word)?;
+        let hash = header.hash_slow();
+        if self.strict {
+            check_hash_prefix(prefix, &hash)?;
+        }
+        Ok((hash, header))
+    }
+
+    // Format: hash[0]_1byte + header_rlp, or header_rlp alone. A prefixed
+    // word does not read as one whole header, as that would take the prefix
+    // to open an RLP list ending exactly with the header's own list.
+    fn split(&self, word: &[u8]) -> Result<(Option<u8>, Header)> {
+        if self.allow_unprefixed {
+            let mut rest = word;
+            if let Ok(header) = Header::decode(&mut rest) {
+                if rest.is_empty() {
+                    return Ok((None, header));
+                }
+            }
+        }
+        let Some((&prefix, mut rlp)) = word.split_first() else {
+            return Err(SnapshotError::InvalidFormat(
+                "Empty word from decompressor".to_string(),
+            ));
+        };
+        Ok((Some(prefix), Header::decode(&mut rlp)?))
+    }
+}
+
+impl Default for HeaderFormat {
+    fn default() -> Self {
+        Self::ERIGON
+    }
+}
+
+fn check_hash_prefix(prefix: Option<u8>, hash: &B256) -> Result<()> {
+    match prefix {
+        Some(prefix) if prefix != hash[0] => Err(SnapshotError::InvalidFormat(format!(
+            "Hash first byte mismatch: expected {:02x}, got {:02x}",
+            prefix, hash[0]
+        ))),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn decode_header_word(word: &[u8]) -> Result<(B256, Header)> {
+    HeaderFormat::ERIGON.decode(word)
+}
+
 /// Iterator for reading headers from a snapshot
 pub struct HeaderGetter<'a> {
     getter: Getter<'a>,
+    format: HeaderFormat,
     block_number: u64,
 }
 
@@ -58,51 +382,898 @@ impl<'a> HeaderGetter<'a> {
     }
 
     /// Read the next header
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<(B256, Header)> {
         let (word, _offset) = self.getter.next(Vec::new());
+        let decoded = self.format.decode(&word)?;
+        self.block_number += 1;
+        Ok(decoded)
+    }
 
-        // Format: hash[0]_1byte + header_rlp
-        if word.is_empty() {
-            return Err(SnapshotError::InvalidFormat(
-                "Empty word from decompressor".to_string(),
-            ));
+    /// Reset to the beginning of the snapshot
+    pub fn reset(&mut self, offset: u64) {
+        self.getter.reset(offset);
+        self.block_number = 0;
+    }
+}
+
+// Smallest gas limit a header may have
+// From go-ethereum: params/protocol_params.go MinGasLimit
+const MIN_GAS_LIMIT: u64 = 5000;
+// From go-ethereum: params/protocol_params.go GasLimitBoundDivisor
+const GAS_LIMIT_BOUND_DIVISOR: u64 = 1024;
+// From go-ethereum: params/protocol_params.go DefaultElasticityMultiplier
+const ELASTICITY_MULTIPLIER: u64 = 2;
+
+/// Chain checks [`HeaderRange::validate`] applies to consecutive headers
+///
+/// Each header must carry the expected number, link to its parent's hash,
+/// have a later timestamp, and keep its gas limit within 1/1024 of its
+/// parent's. At the London block the parent's gas limit is doubled first,
+/// as EIP-1559 raises the limit to twice the old target there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainValidation {
+    /// First EIP-1559 block, `None` if the chain never forks to London
+    pub london_block: Option<u64>,
+}
+
+impl ChainValidation {
+    /// Ethereum mainnet
+    pub const MAINNET: Self = Self {
+        london_block: Some(12_965_000),
+    };
+
+    fn is_london(&self, number: u64) -> bool {
+        self.london_block.is_some_and(|london| number >= london)
+    }
+
+    // From go-ethereum: consensus/misc/eip1559/eip1559.go VerifyEIP1559Header
+    // and consensus/misc/gaslimit.go VerifyGaslimit
+    pub(crate) fn check(
+        &self,
+        parent: &Header,
+        parent_hash: B256,
+        header: &Header,
+    ) -> Option<ChainViolation> {
+        if header.parent_hash != parent_hash {
+            return Some(ChainViolation::ParentHash {
+                expected: parent_hash,
+                actual: header.parent_hash,
+            });
+        }
+        if header.timestamp <= parent.timestamp {
+            return Some(ChainViolation::Timestamp {
+                parent: parent.timestamp,
+                timestamp: header.timestamp,
+            });
+        }
+        let mut parent_gas_limit = parent.gas_limit;
+        if self.is_london(header.number) && !self.is_london(parent.number) {
+            parent_gas_limit = parent_gas_limit.saturating_mul(ELASTICITY_MULTIPLIER);
+        }
+        if header.gas_limit.abs_diff(parent_gas_limit) >= parent_gas_limit / GAS_LIMIT_BOUND_DIVISOR
+        {
+            return Some(ChainViolation::GasLimit {
+                parent: parent_gas_limit,
+                gas_limit: header.gas_limit,
+            });
         }
+        if header.gas_limit < MIN_GAS_LIMIT {
+            return Some(ChainViolation::GasLimitTooLow(header.gas_limit));
+        }
+        None
+    }
+}
 
-        // First byte is hash[0] for indexing
-        let hash_first_byte = word[0];
+impl Default for ChainValidation {
+    fn default() -> Self {
+        Self::MAINNET
+    }
+}
 
-        // Rest is the RLP-encoded header
-        let header_rlp = &word[1..];
+/// Iterator over a block range of a headers snapshot, created by
+/// [`HeadersReader::iter_range`]
+///
+/// Yields each header with its hash. Once an item is an error the iterator
+/// is done.
+pub struct HeaderRange<'a> {
+    getter: Getter<'a>,
+    format: HeaderFormat,
+    // Number of the next header
+    number: u64,
+    end: u64,
+    // Byte offset the range ends at, for readahead
+    end_offset: u64,
+    validation: Option<ChainValidation>,
+    // Previous header and its hash, kept while validating
+    parent: Option<(B256, Header)>,
+}
 
-        // Decode the header
-        let header = Header::decode(&mut &header_rlp[..])?;
+impl HeaderRange<'_> {
+    /// Check the chain as it is read, failing with
+    /// [`SnapshotError::InvalidHeader`] at the first bad header
+    ///
+    /// The first header of the range is only checked for its number, as its
+    /// parent is not read.
+    pub fn validate(mut self, validation: ChainValidation) -> Self {
+        self.validation = Some(validation);
+        self
+    }
 
-        // Calculate the full hash
-        let hash = header.hash_slow();
+    /// Read the segment ahead of the range as it is iterated, see
+    /// [`Getter::read_ahead`]
+    pub fn read_ahead(mut self, read_ahead: ReadAhead) -> Self {
+        self.getter.read_ahead_until(read_ahead, self.end_offset);
+        self
+    }
+
+    fn read(&mut self) -> Result<(B256, Header)> {
+        let (word, _) = self.getter.next(Vec::new());
+        let (hash, header) = self.format.decode(&word)?;
+        let Some(validation) = &self.validation else {
+            return Ok((hash, header));
+        };
+        let violation = if header.number != self.number {
+            Some(ChainViolation::Number {
+                expected: self.number,
+                actual: header.number,
+            })
+        } else {
+            self.parent
+                .as_ref()
+                .and_then(|(parent_hash, parent)| validation.check(parent, *parent_hash, &header))
+        };
+        if let Some(violation) = violation {
+            return Err(SnapshotError::InvalidHeader {
+                number: self.number,
+                hash,
+                violation,
+            });
+        }
+        self.parent = Some((hash, header.clone()));
+        Ok((hash, header))
+    }
+}
+
+impl Iterator for HeaderRange<'_> {
+    type Item = Result<(B256, Header)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.number >= self.end || !self.getter.has_next() {
+            return None;
+        }
+        let item = self.read();
+        self.number = if item.is_ok() {
+            self.number + 1
+        } else {
+            self.end
+        };
+        Some(item)
+    }
+}
+
+/// A block body as stored in a bodies segment
+///
+/// The transactions themselves live in the transactions segment, from
+/// `base_tx_num`: a system transaction, the block's transactions, then
+/// another system transaction.
+// From Erigon: core/types/block.go BodyForStorage
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct StoredBody {
+    /// TxNum of the system transaction opening the block
+    pub base_tx_num: TxNum,
+    /// Number of transactions, counting both system transactions
+    pub tx_count: u32,
+    pub ommers: Vec<Header>,
+    /// Present from Shanghai on
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
 
-        // Verify the first byte matches (sanity check)
-        if hash[0] != hash_first_byte {
+impl StoredBody {
+    pub fn decode(word: &[u8]) -> Result<Self> {
+        let mut buf = word;
+        let header = alloy_rlp::Header::decode(&mut buf)?;
+        if !header.list || header.payload_length != buf.len() {
             return Err(SnapshotError::InvalidFormat(format!(
-                "Hash first byte mismatch: expected {:02x}, got {:02x}",
-                hash_first_byte, hash[0]
+                "Body of {} bytes is not one RLP list",
+                word.len()
             )));
         }
+        let base_tx_num = TxNum(u64::decode(&mut buf)?);
+        let tx_count = u32::decode(&mut buf)?;
+        let ommers = Vec::<Header>::decode(&mut buf)?;
+        let withdrawals = if buf.is_empty() {
+            None
+        } else {
+            Some(Vec::<Withdrawal>::decode(&mut buf)?)
+        };
+        if !buf.is_empty() {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "{} bytes after the body's fields",
+                buf.len()
+            )));
+        }
+        Ok(Self {
+            base_tx_num,
+            tx_count,
+            ommers,
+            withdrawals,
+        })
+    }
 
-        self.block_number += 1;
+    /// Check the body against `header` by Merkle hashing: its ommers
+    /// against the ommers hash and, from Shanghai on, its withdrawals
+    /// against the withdrawals root
+    ///
+    /// Cancun added no body fields: blob transactions are in the
+    /// transactions segment and the blob gas fields in the header.
+    pub fn verify(&self, header: &Header) -> Result<()> {
+        let fail = |violation| {
+            Err(SnapshotError::InvalidBody {
+                number: header.number,
+                violation,
+            })
+        };
+        let ommers_hash = calculate_ommers_root(&self.ommers);
+        if ommers_hash != header.ommers_hash {
+            return fail(BodyViolation::OmmersHash {
+                expected: header.ommers_hash,
+                actual: ommers_hash,
+            });
+        }
+        let withdrawals_root = self.withdrawals.as_deref().map(calculate_withdrawals_root);
+        if withdrawals_root != header.withdrawals_root {
+            return fail(BodyViolation::WithdrawalsRoot {
+                expected: header.withdrawals_root,
+                actual: withdrawals_root,
+            });
+        }
+        Ok(())
+    }
 
-        Ok((hash, header))
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        self.base_tx_num.get().encode(&mut payload);
+        self.tx_count.encode(&mut payload);
+        self.ommers.encode(&mut payload);
+        if let Some(withdrawals) = &self.withdrawals {
+            withdrawals.encode(&mut payload);
+        }
+        let mut word = Vec::with_capacity(payload.len() + 9);
+        alloy_rlp::Header {
+            list: true,
+            payload_length: payload.len(),
+        }
+        .encode(&mut word);
+        word.extend_from_slice(&payload);
+        word
     }
 
-    /// Reset to the beginning of the snapshot
-    pub fn reset(&mut self, offset: u64) {
-        self.getter.reset(offset);
-        self.block_number = 0;
+    /// Number of transactions in the block, without the system ones
+    pub fn transaction_count(&self) -> u64 {
+        u64::from(self.tx_count).saturating_sub(2)
+    }
+
+    /// TxNum of the block's `index`-th transaction, `None` past the last
+    pub fn tx_num(&self, index: TxIndex) -> Option<TxNum> {
+        (index.get() < self.transaction_count()).then(|| self.base_tx_num.offset(1 + index.get()))
+    }
+}
+
+/// Reader for bodies snapshot files
+pub struct BodiesReader {
+    decompressor: Decompressor,
+}
+
+impl BodiesReader {
+    /// Open a bodies snapshot file, with the `.idx` next to it if there is
+    /// one
+    pub fn new(path: &Path) -> Result<Self> {
+        let idx_path = path.with_extension("idx");
+        let index = if idx_path.exists() {
+            Some(RecSplitIndex::open(&idx_path)?)
+        } else {
+            None
+        };
+        Self::with_index(path, index)
+    }
+
+    /// Open a bodies snapshot file with an explicitly given enum index (or
+    /// none)
+    pub fn with_index(path: &Path, index: Option<RecSplitIndex>) -> Result<Self> {
+        let mut decompressor = Decompressor::new(path)?;
+        if let Some(idx) = index {
+            decompressor.attach_index(idx)?;
+        }
+        Ok(Self { decompressor })
+    }
+
+    pub fn count(&self) -> usize {
+        self.decompressor.count()
+    }
+
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.decompressor.index()
+    }
+
+    /// Number of the first block of this snapshot, from the attached index
+    pub fn first_block(&self) -> Option<BlockNumber> {
+        self.index().map(|idx| BlockNumber(idx.base_data_id()))
+    }
+
+    /// The `i`-th body of this snapshot, or `None` past the last one
+    pub fn body(&self, i: u64) -> Result<Option<StoredBody>> {
+        self.decompressor
+            .get_word(i)
+            .map(|word| StoredBody::decode(&word))
+            .transpose()
+    }
+
+    /// The body of block `number`; needs an index attached
+    pub fn body_by_number(&self, number: BlockNumber) -> Result<Option<StoredBody>> {
+        let first = self.first_block().ok_or(SnapshotError::IndexNotAvailable)?;
+        match number.ordinal_from(first) {
+            Some(i) => self.body(i),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A transaction as stored in a transactions segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct StoredTransaction {
+    pub tx_num: TxNum,
+    /// First byte of the transaction hash
+    pub hash_prefix: u8,
+    /// The zero address when the segment does not store the sender, see
+    /// [`StoredTransaction::has_sender`]
+    pub sender: Address,
+    /// EIP-2718 encoding of the signed transaction
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "alloy_primitives::serde_hex::serialize")
+    )]
+    pub encoded: Vec<u8>,
+}
+
+impl StoredTransaction {
+    // Format: hash[0]_1byte + sender_20bytes + eip2718_tx; system
+    // transactions are usually empty words
+    fn decode(tx_num: TxNum, word: &[u8]) -> Result<Option<Self>> {
+        if word.is_empty() {
+            return Ok(None);
+        }
+        if word.len() <= 1 + Address::len_bytes() {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Transaction {} of {} bytes",
+                tx_num,
+                word.len()
+            )));
+        }
+        Ok(Some(Self {
+            tx_num,
+            hash_prefix: word[0],
+            sender: Address::from_slice(&word[1..21]),
+            encoded: word[21..].to_vec(),
+        }))
+    }
+
+    pub fn hash(&self) -> B256 {
+        keccak256(&self.encoded)
+    }
+
+    /// Decode the signed transaction
+    pub fn envelope(&self) -> Result<TxEnvelope> {
+        TxEnvelope::decode_2718(&mut self.encoded.as_slice()).map_err(|e| {
+            SnapshotError::InvalidFormat(format!("Transaction {}: {}", self.tx_num, e))
+        })
+    }
+
+    /// Whether the segment stores the sender. Erigon always writes it, but
+    /// segments written without one hold the zero address, which no
+    /// signature recovers to, so the zero address means "not stored"
+    pub fn has_sender(&self) -> bool {
+        self.sender != Address::ZERO
+    }
+
+    /// Recover the sender from the transaction's signature
+    #[cfg(feature = "senders")]
+    pub fn recover_sender(&self) -> Result<Address> {
+        self.envelope()?.recover_signer().map_err(|e| {
+            SnapshotError::InvalidFormat(format!("Transaction {}: {}", self.tx_num, e))
+        })
     }
 }
 
+/// Reader for transactions snapshot files
+///
+/// Word `i` is the transaction with TxNum `first + i`, where `first` is the
+/// `base_data_id` of the hash-keyed `.idx`.
+///
+/// Senders come from the senders segment of the same range when one is
+/// attached, otherwise from the words themselves. With the `senders`
+/// feature, senders neither stores are recovered from the signatures and
+/// kept in an LRU updated through `&self`, so a reader shared between
+/// threads recovers a recently used sender once.
+pub struct TransactionsReader {
+    decompressor: Decompressor,
+    senders: Option<Box<SendersReader>>,
+    // Recently recovered senders by TxNum
+    #[cfg(feature = "senders")]
+    recovered: Mutex<LruCache<u64, Address>>,
+}
+
+/// Recovered senders a [`TransactionsReader`] keeps unless told otherwise
+#[cfg(feature = "senders")]
+pub const SENDER_CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(1 << 16).unwrap();
+
+impl TransactionsReader {
+    /// Open a transactions snapshot file, with the `.idx` and the senders
+    /// segment next to it if there are
+    pub fn new(path: &Path) -> Result<Self> {
+        let idx_path = path.with_extension("idx");
+        let index = if idx_path.exists() {
+            Some(RecSplitIndex::open(&idx_path)?)
+        } else {
+            None
+        };
+        let mut reader = Self::with_index(path, index)?;
+        let senders_path = SnapshotFile::parse(path)
+            .filter(|file| file.kind == SnapshotType::Transactions)
+            .map(|file| file.sibling(SnapshotType::Senders))
+            .filter(|senders_path| senders_path.exists());
+        if let Some(senders_path) = senders_path {
+            reader.attach_senders(SendersReader::new(&senders_path)?)?;
+        }
+        Ok(reader)
+    }
+
+    /// Open a transactions snapshot file with an explicitly given enum index
+    /// (or none)
+    pub fn with_index(path: &Path, index: Option<RecSplitIndex>) -> Result<Self> {
+        let mut decompressor = Decompressor::new(path)?;
+        if let Some(idx) = index {
+            decompressor.attach_index(idx)?;
+        }
+        Ok(Self {
+            decompressor,
+            senders: None,
+            #[cfg(feature = "senders")]
+            recovered: Mutex::new(LruCache::new(SENDER_CACHE_CAPACITY)),
+        })
+    }
+
+    /// Keep up to `capacity` recovered senders instead of
+    /// [`SENDER_CACHE_CAPACITY`]
+    #[cfg(feature = "senders")]
+    pub fn with_sender_cache_capacity(mut self, capacity: NonZeroUsize) -> Self {
+        self.recovered = Mutex::new(LruCache::new(capacity));
+        self
+    }
+
+    /// Take senders from `senders` rather than from the words, after
+    /// checking it has a word per transaction
+    pub fn attach_senders(&mut self, mut senders: SendersReader) -> Result<()> {
+        senders.check(self)?;
+        self.senders = Some(Box::new(senders));
+        Ok(())
+    }
+
+    /// The attached senders segment
+    pub fn senders(&self) -> Option<&SendersReader> {
+        self.senders.as_deref()
+    }
+
+    /// Number of transactions, system ones included
+    pub fn count(&self) -> usize {
+        self.decompressor.count()
+    }
+
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.decompressor.index()
+    }
+
+    /// TxNum of the first transaction of this snapshot, from the attached
+    /// index
+    pub fn first_tx_num(&self) -> Option<TxNum> {
+        self.index().map(|idx| TxNum(idx.base_data_id()))
+    }
+
+    /// The transaction `tx_num`, `None` if this snapshot does not have it or
+    /// it is a system transaction; needs an index attached
+    pub fn transaction(&self, tx_num: TxNum) -> Result<Option<StoredTransaction>> {
+        let first = self
+            .first_tx_num()
+            .ok_or(SnapshotError::IndexNotAvailable)?;
+        let Some(word) = tx_num
+            .ordinal_from(first)
+            .and_then(|i| self.decompressor.get_word(i))
+        else {
+            return Ok(None);
+        };
+        StoredTransaction::decode(tx_num, &word)
+    }
+
+    /// The transactions of `body`, read in one pass; needs an index attached
+    pub fn transactions(&self, body: &StoredBody) -> Result<Vec<StoredTransaction>> {
+        let count = body.transaction_count();
+        let Some(from) = body.tx_num(TxIndex(0)) else {
+            return Ok(Vec::new());
+        };
+        let first = self
+            .first_tx_num()
+            .ok_or(SnapshotError::IndexNotAvailable)?;
+        let offset = from
+            .ordinal_from(first)
+            .and_then(|i| self.decompressor.word_offset(i))
+            .ok_or(SnapshotError::InvalidFormat(format!(
+                "Transaction {} is not in this snapshot",
+                from
+            )))?;
+        let mut getter = self.decompressor.make_getter();
+        getter.reset(offset);
+        let mut transactions = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let tx_num = from.offset(i);
+            if !getter.has_next() {
+                return Err(SnapshotError::InvalidFormat(format!(
+                    "Transaction {} is not in this snapshot",
+                    tx_num
+                )));
+            }
+            let (word, _) = getter.try_next(Vec::new())?;
+            let tx = StoredTransaction::decode(tx_num, &word)?.ok_or_else(|| {
+                SnapshotError::InvalidFormat(format!("Transaction {} is empty", tx_num))
+            })?;
+            transactions.push(tx);
+        }
+        Ok(transactions)
+    }
+
+    /// The sender of transaction `tx_num`, `None` if this snapshot does not
+    /// have it or it is a system transaction; needs an index attached
+    ///
+    /// Without a senders segment, senders the words do not store are
+    /// recovered from the signature and cached, which needs the `senders`
+    /// feature.
+    pub fn sender_of(&self, tx_num: TxNum) -> Result<Option<Address>> {
+        if let Some(senders) = &self.senders {
+            return senders.sender(tx_num);
+        }
+        let Some(tx) = self.transaction(tx_num)? else {
+            return Ok(None);
+        };
+        if tx.has_sender() {
+            return Ok(Some(tx.sender));
+        }
+        #[cfg(feature = "senders")]
+        {
+            if let Some(sender) = lock(&self.recovered).get(&tx_num.get()) {
+                return Ok(Some(*sender));
+            }
+            let sender = tx.recover_sender()?;
+            lock(&self.recovered).put(tx_num.get(), sender);
+            Ok(Some(sender))
+        }
+        #[cfg(not(feature = "senders"))]
+        Err(SnapshotError::InvalidFormat(format!(
+            "Transaction {} has no stored sender, recovering it needs the `senders` feature",
+            tx_num
+        )))
+    }
+
+    /// The senders of the transactions `tx_nums`, `None` for system
+    /// transactions; needs an index attached
+    ///
+    /// The words are read in one pass, then the senders the segment does not
+    /// store are recovered on the `blocking` thread pool, `parallelism`
+    /// batches at a time, which works under any async runtime. Recovered
+    /// senders are cached for [`TransactionsReader::sender_of`]. With a
+    /// senders segment attached, they are all read from it instead.
+    #[cfg(feature = "senders")]
+    pub async fn recover_senders(
+        &self,
+        tx_nums: Range<TxNum>,
+        parallelism: usize,
+    ) -> Result<Vec<Option<Address>>> {
+        if let Some(senders) = &self.senders {
+            return senders.senders(tx_nums);
+        }
+        let count = tx_nums.end.ordinal_from(tx_nums.start).unwrap_or(0);
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let first = self
+            .first_tx_num()
+            .ok_or(SnapshotError::IndexNotAvailable)?;
+        let not_found = |tx_num| {
+            SnapshotError::InvalidFormat(format!("Transaction {} is not in this snapshot", tx_num))
+        };
+        let offset = tx_nums
+            .start
+            .ordinal_from(first)
+            .and_then(|i| self.decompressor.word_offset(i))
+            .ok_or_else(|| not_found(tx_nums.start))?;
+        let mut getter = self.decompressor.make_getter();
+        getter.reset(offset);
+
+        let mut senders = Vec::with_capacity(count as usize);
+        // Transactions to recover the sender of, by position in `senders`
+        let mut pending = Vec::new();
+        {
+            let mut cache = lock(&self.recovered);
+            for i in 0..count {
+                let tx_num = tx_nums.start.offset(i);
+                if !getter.has_next() {
+                    return Err(not_found(tx_num));
+                }
+                let (word, _) = getter.try_next(Vec::new())?;
+                let sender = match StoredTransaction::decode(tx_num, &word)? {
+                    Some(tx) if tx.has_sender() => Some(tx.sender),
+                    Some(tx) => match cache.get(&tx_num.get()) {
+                        Some(sender) => Some(*sender),
+                        None => {
+                            pending.push((senders.len(), tx));
+                            None
+                        }
+                    },
+                    None => None,
+                };
+                senders.push(sender);
+            }
+        }
+        if pending.is_empty() {
+            return Ok(senders);
+        }
+
+        // Every task recovers every `parallelism`-th pending sender
+        let parallelism = parallelism.clamp(1, pending.len());
+        let mut batches: Vec<Vec<(usize, StoredTransaction)>> =
+            (0..parallelism).map(|_| Vec::new()).collect();
+        for (i, entry) in pending.into_iter().enumerate() {
+            batches[i % parallelism].push(entry);
+        }
+        let tasks: Vec<_> = batches
+            .into_iter()
+            .map(|batch| {
+                blocking::unblock(move || -> Result<Vec<(usize, TxNum, Address)>> {
+                    batch
+                        .into_iter()
+                        .map(|(i, tx)| Ok((i, tx.tx_num, tx.recover_sender()?)))
+                        .collect()
+                })
+            })
+            .collect();
+        for task in tasks {
+            let recovered = task.await?;
+            let mut cache = lock(&self.recovered);
+            for (i, tx_num, sender) in recovered {
+                cache.put(tx_num.get(), sender);
+                senders[i] = Some(sender);
+            }
+        }
+        Ok(senders)
+    }
+
+    /// Forget the recovered senders
+    #[cfg(feature = "senders")]
+    pub fn clear_sender_cache(&self) {
+        lock(&self.recovered).clear();
+    }
+
+    /// Find a transaction by its hash through the index
+    pub fn transaction_by_hash(&self, hash: B256) -> Result<Option<StoredTransaction>> {
+        let idx = self.index().ok_or(SnapshotError::IndexNotAvailable)?;
+        // Unknown hashes land on some transaction too, so check the result
+        let Some(ordinal) = idx.lookup(hash.as_slice()) else {
+            return Ok(None);
+        };
+        let tx_num = TxNum(idx.base_data_id()).offset(ordinal);
+        Ok(self
+            .transaction(tx_num)?
+            .filter(|tx| tx.hash_prefix == hash[0] && tx.hash() == hash))
+    }
+}
+
+/// Reader for senders snapshot files
+///
+/// Word `i` is the 20-byte sender of the transaction with TxNum `first + i`,
+/// empty for system transactions, so that the segment has as many words as
+/// the transactions segment of its range. `first` is the `base_data_id` of
+/// the `.idx`, or without one that of the transactions it is attached to.
+pub struct SendersReader {
+    decompressor: Decompressor,
+    first_tx_num: Option<TxNum>,
+}
+
+impl SendersReader {
+    /// Open a senders snapshot file, with the `.idx` next to it if there is
+    /// one
+    pub fn new(path: &Path) -> Result<Self> {
+        let idx_path = path.with_extension("idx");
+        let index = if idx_path.exists() {
+            Some(RecSplitIndex::open(&idx_path)?)
+        } else {
+            None
+        };
+        Self::with_index(path, index)
+    }
+
+    /// Open a senders snapshot file with an explicitly given enum index (or
+    /// none)
+    pub fn with_index(path: &Path, index: Option<RecSplitIndex>) -> Result<Self> {
+        let mut decompressor = Decompressor::new(path)?;
+        let first_tx_num = index.as_ref().map(|idx| TxNum(idx.base_data_id()));
+        if let Some(idx) = index {
+            decompressor.attach_index(idx)?;
+        }
+        Ok(Self {
+            decompressor,
+            first_tx_num,
+        })
+    }
+
+    /// Number of senders, empty ones of system transactions included
+    pub fn count(&self) -> usize {
+        self.decompressor.count()
+    }
+
+    pub fn index(&self) -> Option<&RecSplitIndex> {
+        self.decompressor.index()
+    }
+
+    /// TxNum of the first sender, from the index or the transactions the
+    /// segment is attached to
+    pub fn first_tx_num(&self) -> Option<TxNum> {
+        self.first_tx_num
+    }
+
+    // Format: sender_20bytes, or an empty word for system transactions
+    fn decode(tx_num: TxNum, word: &[u8]) -> Result<Option<Address>> {
+        match word.len() {
+            0 => Ok(None),
+            20 => Ok(Some(Address::from_slice(word))),
+            len => Err(SnapshotError::InvalidFormat(format!(
+                "Sender of transaction {} of {} bytes",
+                tx_num, len
+            ))),
+        }
+    }
+
+    /// The sender of transaction `tx_num`, `None` if this snapshot does not
+    /// have it or it is a system transaction
+    pub fn sender(&self, tx_num: TxNum) -> Result<Option<Address>> {
+        let first = self
+            .first_tx_num()
+            .ok_or(SnapshotError::IndexNotAvailable)?;
+        let Some(word) = tx_num
+            .ordinal_from(first)
+            .and_then(|i| self.decompressor.get_word(i))
+        else {
+            return Ok(None);
+        };
+        Self::decode(tx_num, &word)
+    }
+
+    /// The senders of the transactions `tx_nums`, read in one pass; `None`
+    /// for system transactions
+    pub fn senders(&self, tx_nums: Range<TxNum>) -> Result<Vec<Option<Address>>> {
+        let count = tx_nums.end.ordinal_from(tx_nums.start).unwrap_or(0);
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let first = self
+            .first_tx_num()
+            .ok_or(SnapshotError::IndexNotAvailable)?;
+        let not_found = |tx_num| {
+            SnapshotError::InvalidFormat(format!("Sender {} is not in this snapshot", tx_num))
+        };
+        let offset = tx_nums
+            .start
+            .ordinal_from(first)
+            .and_then(|i| self.decompressor.word_offset(i))
+            .ok_or_else(|| not_found(tx_nums.start))?;
+        let mut getter = self.decompressor.make_getter();
+        getter.reset(offset);
+        let mut senders = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let tx_num = tx_nums.start.offset(i);
+            if !getter.has_next() {
+                return Err(not_found(tx_num));
+            }
+            let (word, _) = getter.try_next(Vec::new())?;
+            senders.push(Self::decode(tx_num, &word)?);
+        }
+        Ok(senders)
+    }
+
+    // Whether the segment has a sender per transaction of `transactions`,
+    // counted from the same TxNum, which it takes when it has no index
+    fn check(&mut self, transactions: &TransactionsReader) -> Result<()> {
+        if self.count() != transactions.count() {
+            return Err(SnapshotError::SendersMismatch {
+                senders: self.count(),
+                transactions: transactions.count(),
+            });
+        }
+        match (self.first_tx_num, transactions.first_tx_num()) {
+            (Some(first), Some(tx_first)) if first != tx_first => {
+                Err(SnapshotError::InvalidFormat(format!(
+                    "Senders start at transaction {}, transactions at {}",
+                    first, tx_first
+                )))
+            }
+            (None, tx_first) => {
+                self.first_tx_num = tx_first;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check every sender against the word of its transaction in
+    /// `transactions`: system transactions must have no sender, and senders
+    /// the transactions store must match
+    pub fn verify(&self, transactions: &TransactionsReader) -> Result<()> {
+        if self.count() != transactions.count() {
+            return Err(SnapshotError::SendersMismatch {
+                senders: self.count(),
+                transactions: transactions.count(),
+            });
+        }
+        let first = self
+            .first_tx_num()
+            .or(transactions.first_tx_num())
+            .unwrap_or_default();
+        let mut senders = self.decompressor.make_getter();
+        let mut txs = transactions.decompressor.make_getter();
+        for i in 0..self.count() as u64 {
+            let tx_num = first.offset(i);
+            let (word, _) = senders.try_next(Vec::new())?;
+            let sender = Self::decode(tx_num, &word)?;
+            let (word, _) = txs.try_next(Vec::new())?;
+            let tx = StoredTransaction::decode(tx_num, &word)?;
+            let consistent = match (&tx, sender) {
+                (None, None) => true,
+                (Some(tx), Some(sender)) => !tx.has_sender() || tx.sender == sender,
+                _ => false,
+            };
+            if !consistent {
+                return Err(SnapshotError::InvalidFormat(format!(
+                    "Sender {:?} of transaction {} does not match {:?}",
+                    sender,
+                    tx_num,
+                    tx.map(|tx| tx.sender)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// A panic while holding the lock leaves the cache consistent, as it only
+// ever happens outside of the map's own methods
+#[cfg(feature = "senders")]
+fn lock<T>(cache: &Mutex<T>) -> MutexGuard<'_, T> {
+    cache
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::tests::{signed_tx, write_indexed};
     use std::path::PathBuf;
 
     #[test]
@@ -134,4 +1305,797 @@ mod tests {
             count += 1;
         }
     }
+
+    // Write blocks 1000..2000 as a linked chain, letting `tweak` alter each
+    // header before its hash is taken
+    fn write_chain(dir: &Path, tweak: impl Fn(&mut Header)) -> HeadersReader {
+        use crate::compress::CompressionLevel;
+        use crate::snapshots::HeaderSegmentWriter;
+
+        let mut writer = HeaderSegmentWriter::with_compressor(dir, 1000, 2000, |b| {
+            b.level(CompressionLevel::Store)
+        })
+        .unwrap();
+        writer.disable_fsync();
+        let mut parent_hash = B256::ZERO;
+        for number in 1000..2000 {
+            let mut header = Header {
+                number,
+                parent_hash,
+                gas_limit: 15_000_000,
+                timestamp: 1_600_000_000 + number * 12,
+                ..Default::default()
+            };
+            tweak(&mut header);
+            parent_hash = header.hash_slow();
+            writer.add_header(&header).unwrap();
+        }
+        let (seg_path, _) = writer.finish().unwrap();
+        HeadersReader::new(&seg_path).unwrap()
+    }
+
+    fn first_invalid(
+        reader: &HeadersReader,
+        validation: ChainValidation,
+    ) -> Option<(u64, ChainViolation)> {
+        reader
+            .iter_range(BlockNumber(0), BlockNumber(u64::MAX))
+            .unwrap()
+            .validate(validation)
+            .find_map(|item| match item {
+                Err(SnapshotError::InvalidHeader {
+                    number, violation, ..
+                }) => Some((number, violation)),
+                Err(err) => panic!("{}", err),
+                Ok(_) => None,
+            })
+    }
+
+    #[test]
+    fn test_iter_range() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let reader = write_chain(tmp_dir.path(), |_| {});
+
+        let numbers: Vec<u64> = reader
+            .iter_range(BlockNumber(1500), BlockNumber(1505))
+            .unwrap()
+            .map(|item| item.unwrap().1.number)
+            .collect();
+        assert_eq!(numbers, [1500, 1501, 1502, 1503, 1504]);
+        let prefetched: Vec<u64> = reader
+            .iter_range(BlockNumber(1500), BlockNumber(1505))
+            .unwrap()
+            .read_ahead(ReadAhead::Prefetch { window: 256 })
+            .map(|item| item.unwrap().1.number)
+            .collect();
+        assert_eq!(prefetched, numbers);
+        // Clamped to the snapshot's own range
+        assert_eq!(
+            reader
+                .iter_range(BlockNumber(0), BlockNumber(1003))
+                .unwrap()
+                .count(),
+            3
+        );
+        assert_eq!(
+            reader
+                .iter_range(BlockNumber(1998), BlockNumber(5000))
+                .unwrap()
+                .count(),
+            2
+        );
+        assert_eq!(
+            reader
+                .iter_range(BlockNumber(2000), BlockNumber(3000))
+                .unwrap()
+                .count(),
+            0
+        );
+        assert_eq!(first_invalid(&reader, ChainValidation::MAINNET), None);
+
+        let unindexed =
+            HeadersReader::with_index(&tmp_dir.path().join("v1-000001-000002-headers.seg"), None)
+                .unwrap();
+        assert!(matches!(
+            unindexed.iter_range(BlockNumber(1000), BlockNumber(2000)),
+            Err(SnapshotError::IndexNotAvailable)
+        ));
+    }
+
+    #[test]
+    fn test_auxiliary_header_queries() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let address = Address::repeat_byte(0x42);
+        let reader = write_chain(tmp_dir.path(), |h| {
+            h.difficulty = U256::from(h.number);
+            if h.number % 250 == 7 {
+                h.logs_bloom.accrue(BloomInput::Raw(address.as_slice()));
+            }
+        });
+
+        let parent_td = U256::from(5);
+        let td = |number| {
+            reader
+                .total_difficulty(BlockNumber(number), parent_td)
+                .unwrap()
+        };
+        assert_eq!(td(1000), Some(U256::from(1005)));
+        assert_eq!(td(1002), Some(U256::from(5 + 1000 + 1001 + 1002)));
+        assert_eq!(td(1999), Some(U256::from(5 + (1000..2000).sum::<u64>())));
+        assert_eq!(td(999), None);
+        assert_eq!(td(2000), None);
+
+        let at = |timestamp| {
+            reader
+                .find_by_timestamp(timestamp)
+                .unwrap()
+                .map(|h| h.number)
+        };
+        let timestamp = |number: u64| 1_600_000_000 + number * 12;
+        assert_eq!(at(timestamp(1234)), Some(1234));
+        assert_eq!(at(timestamp(1234) + 11), Some(1234));
+        assert_eq!(at(timestamp(1000)), Some(1000));
+        assert_eq!(at(timestamp(1000) - 1), None);
+        assert_eq!(at(u64::MAX), Some(1999));
+
+        let blocks = reader
+            .blocks_matching_bloom(BlockNumber(1000), BlockNumber(1600), address.as_slice())
+            .unwrap();
+        assert_eq!(
+            blocks,
+            [BlockNumber(1007), BlockNumber(1257), BlockNumber(1507)]
+        );
+
+        // The search also works without an index, as a scan
+        let unindexed =
+            HeadersReader::with_index(&tmp_dir.path().join("v1-000001-000002-headers.seg"), None)
+                .unwrap();
+        assert_eq!(
+            unindexed
+                .find_by_timestamp(timestamp(1500))
+                .unwrap()
+                .map(|h| h.number),
+            Some(1500)
+        );
+        assert!(matches!(
+            unindexed.total_difficulty(BlockNumber(1500), parent_td),
+            Err(SnapshotError::IndexNotAvailable)
+        ));
+    }
+
+    #[test]
+    fn test_iter_range_validation() {
+        let london = ChainValidation {
+            london_block: Some(1500),
+        };
+        type Tweak = fn(&mut Header);
+        let cases: [(Tweak, ChainValidation, Option<u64>); 5] = [
+            (
+                |h| {
+                    if h.number == 1200 {
+                        h.parent_hash = B256::repeat_byte(1);
+                    }
+                },
+                ChainValidation::MAINNET,
+                Some(1200),
+            ),
+            (
+                |h| {
+                    if h.number == 1300 {
+                        h.timestamp -= 12;
+                    }
+                },
+                ChainValidation::MAINNET,
+                Some(1300),
+            ),
+            (
+                |h| {
+                    if h.number == 1400 {
+                        h.gas_limit += 15_000_000 / 1024;
+                    }
+                },
+                ChainValidation::MAINNET,
+                Some(1400),
+            ),
+            // The gas limit doubles at London; fine there, not elsewhere
+            (
+                |h| {
+                    if h.number >= 1500 {
+                        h.gas_limit = 30_000_000;
+                    }
+                },
+                ChainValidation::MAINNET,
+                Some(1500),
+            ),
+            (
+                |h| {
+                    if h.number >= 1500 {
+                        h.gas_limit = 30_000_000;
+                    }
+                },
+                london,
+                None,
+            ),
+        ];
+        for (tweak, validation, bad_block) in cases {
+            let tmp_dir = tempfile::TempDir::new().unwrap();
+            let reader = write_chain(tmp_dir.path(), tweak);
+            let found = first_invalid(&reader, validation);
+            assert_eq!(found.map(|(number, _)| number), bad_block);
+            // Without validation the same chain reads fine
+            assert!(reader
+                .iter_range(BlockNumber(1000), BlockNumber(2000))
+                .unwrap()
+                .all(|item| item.is_ok()));
+        }
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let reader = write_chain(tmp_dir.path(), |h| {
+            if h.number == 1300 {
+                h.timestamp -= 12;
+            }
+        });
+        assert_eq!(
+            first_invalid(&reader, ChainValidation::MAINNET),
+            Some((
+                1300,
+                ChainViolation::Timestamp {
+                    parent: 1_600_000_000 + 1299 * 12,
+                    timestamp: 1_600_000_000 + 1300 * 12 - 12,
+                }
+            ))
+        );
+        // The iterator stops at the first error
+        let mut range = reader
+            .iter_range(BlockNumber(1299), BlockNumber(1400))
+            .unwrap()
+            .validate(ChainValidation::MAINNET);
+        assert!(range.next().unwrap().is_ok());
+        assert!(range.next().unwrap().is_err());
+        assert!(range.next().is_none());
+    }
+
+    #[test]
+    fn test_header_by_hash_without_index() {
+        use crate::compress::Compressor;
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let seg_path = tmp_dir.path().join("v1-000000-000500-headers.seg");
+
+        let headers: Vec<Header> = (0..20)
+            .map(|number| Header {
+                number,
+                gas_limit: 30_000_000,
+                timestamp: 1_600_000_000 + number * 12,
+                ..Default::default()
+            })
+            .collect();
+
+        let mut compressor = Compressor::builder(&seg_path)
+            .min_pattern_score(1)
+            .fsync(false)
+            .build()
+            .unwrap();
+        for header in &headers {
+            let mut word = vec![header.hash_slow()[0]];
+            word.extend_from_slice(&alloy_rlp::encode(header));
+            compressor.add_word(&word).unwrap();
+        }
+        compressor.compress().unwrap();
+
+        let reader = HeadersReader::new(&seg_path).unwrap();
+        assert!(reader.index().is_none());
+
+        for header in &headers {
+            let found = reader.header_by_hash(header.hash_slow()).unwrap();
+            assert_eq!(found.as_ref(), Some(header));
+        }
+        assert_eq!(
+            reader.header_by_hash(B256::repeat_byte(0xab)).unwrap(),
+            None
+        );
+
+        // Random access by position works without an index, by number not
+        let (hash, header) = reader.header(7).unwrap().unwrap();
+        assert_eq!(header, headers[7]);
+        assert_eq!(hash, headers[7].hash_slow());
+        assert!(reader.header(20).unwrap().is_none());
+        assert!(matches!(
+            reader.header_by_number(BlockNumber(7)),
+            Err(SnapshotError::IndexNotAvailable)
+        ));
+    }
+
+    #[test]
+    fn test_header_formats() {
+        use crate::compress::Compressor;
+
+        let headers: Vec<Header> = (0..10)
+            .map(|number| Header {
+                number,
+                gas_limit: 30_000_000,
+                ..Default::default()
+            })
+            .collect();
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        // Words 0..5 have the hash prefix, 5..8 a wrong one, 8..10 none
+        let seg_path = tmp_dir.path().join("v1-000000-000500-headers.seg");
+        let mut compressor = Compressor::builder(&seg_path)
+            .min_pattern_score(1)
+            .fsync(false)
+            .build()
+            .unwrap();
+        for (i, header) in headers.iter().enumerate() {
+            let mut word = match i {
+                0..5 => vec![header.hash_slow()[0]],
+                5..8 => vec![!header.hash_slow()[0]],
+                _ => Vec::new(),
+            };
+            word.extend_from_slice(&alloy_rlp::encode(header));
+            compressor.add_word(&word).unwrap();
+        }
+        compressor.compress().unwrap();
+
+        let reader = HeadersReader::new(&seg_path).unwrap();
+        assert_eq!(reader.format(), HeaderFormat::ERIGON);
+        let raw = reader.raw_header(2).unwrap();
+        assert_eq!(raw[0], headers[2].hash_slow()[0]);
+        assert_eq!(
+            reader.parsed_header(2).unwrap(),
+            Some((Some(raw[0]), headers[2].clone()))
+        );
+        assert!(reader.header(5).is_err());
+        assert!(reader.header(8).is_err());
+
+        let lenient = HeadersReader::new(&seg_path)
+            .unwrap()
+            .with_format(HeaderFormat {
+                strict: false,
+                allow_unprefixed: false,
+            });
+        let (prefix, header) = lenient.parsed_header(5).unwrap().unwrap();
+        assert_eq!(prefix, Some(!headers[5].hash_slow()[0]));
+        assert_eq!(header, headers[5]);
+
+        let legacy = HeadersReader::new(&seg_path)
+            .unwrap()
+            .with_format(HeaderFormat {
+                strict: true,
+                allow_unprefixed: true,
+            });
+        assert_eq!(
+            legacy.parsed_header(8).unwrap(),
+            Some((None, headers[8].clone()))
+        );
+        assert_eq!(legacy.header(3).unwrap().unwrap().1, headers[3]);
+        assert!(legacy.header(6).is_err());
+        // Scans by hash decode every word, so strict ones stop at the first
+        // wrong prefix
+        assert!(legacy.header_by_hash(headers[9].hash_slow()).is_err());
+        let legacy = legacy.with_format(HeaderFormat {
+            strict: false,
+            allow_unprefixed: true,
+        });
+        let found = legacy.header_by_hash(headers[9].hash_slow()).unwrap();
+        assert_eq!(found.as_ref(), Some(&headers[9]));
+    }
+
+    #[test]
+    fn test_stored_body_round_trip() {
+        let body = StoredBody {
+            base_tx_num: TxNum(500),
+            tx_count: 5,
+            ommers: vec![Header {
+                number: 7,
+                ..Default::default()
+            }],
+            withdrawals: Some(vec![Withdrawal {
+                index: 1,
+                validator_index: 2,
+                address: Address::with_last_byte(3),
+                amount: 4,
+            }]),
+        };
+        assert_eq!(StoredBody::decode(&body.encode()).unwrap(), body);
+
+        let pre_shanghai = StoredBody {
+            withdrawals: None,
+            ..body.clone()
+        };
+        assert_eq!(
+            StoredBody::decode(&pre_shanghai.encode()).unwrap(),
+            pre_shanghai
+        );
+
+        // The system transactions are not counted nor addressable
+        assert_eq!(body.transaction_count(), 3);
+        assert_eq!(body.tx_num(TxIndex(0)), Some(TxNum(501)));
+        assert_eq!(body.tx_num(TxIndex(2)), Some(TxNum(503)));
+        assert_eq!(body.tx_num(TxIndex(3)), None);
+
+        let mut trailing = body.encode();
+        trailing.push(0x80);
+        assert!(StoredBody::decode(&trailing).is_err());
+    }
+
+    #[test]
+    fn test_stored_body_verify() {
+        let withdrawals = vec![
+            Withdrawal {
+                index: 1,
+                validator_index: 2,
+                address: Address::with_last_byte(3),
+                amount: 4,
+            },
+            Withdrawal {
+                index: 2,
+                validator_index: 5,
+                address: Address::with_last_byte(6),
+                amount: 7,
+            },
+        ];
+        let body = StoredBody {
+            tx_count: 2,
+            withdrawals: Some(withdrawals.clone()),
+            ..Default::default()
+        };
+        let header = Header {
+            number: 17_034_870,
+            withdrawals_root: Some(calculate_withdrawals_root(&withdrawals)),
+            ..Default::default()
+        };
+        body.verify(&header).unwrap();
+
+        let violation = |body: &StoredBody, header: &Header| match body.verify(header) {
+            Err(SnapshotError::InvalidBody { number, violation }) => {
+                assert_eq!(number, header.number);
+                violation
+            }
+            other => panic!("{:?}", other),
+        };
+        let mut tampered = body.clone();
+        tampered.withdrawals.as_mut().unwrap()[1].amount += 1;
+        assert!(matches!(
+            violation(&tampered, &header),
+            BodyViolation::WithdrawalsRoot {
+                expected: Some(_),
+                actual: Some(_)
+            }
+        ));
+        // Withdrawals on one side only
+        let pre_shanghai = Header {
+            withdrawals_root: None,
+            ..header.clone()
+        };
+        assert!(matches!(
+            violation(&body, &pre_shanghai),
+            BodyViolation::WithdrawalsRoot {
+                expected: None,
+                actual: Some(_)
+            }
+        ));
+        let no_withdrawals = StoredBody {
+            withdrawals: None,
+            ..body.clone()
+        };
+        no_withdrawals.verify(&pre_shanghai).unwrap();
+
+        let with_ommer = StoredBody {
+            ommers: vec![Header::default()],
+            ..no_withdrawals
+        };
+        assert!(matches!(
+            violation(&with_ommer, &pre_shanghai),
+            BodyViolation::OmmersHash { .. }
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serialize_stored_transaction() {
+        let tx = StoredTransaction {
+            tx_num: TxNum(7),
+            hash_prefix: 0xab,
+            sender: Address::with_last_byte(1),
+            encoded: vec![0x02, 0xc0],
+        };
+        assert_eq!(
+            serde_json::to_value(&tx).unwrap(),
+            serde_json::json!({
+                "txNum": 7,
+                "hashPrefix": 171,
+                "sender": "0x0000000000000000000000000000000000000001",
+                "encoded": "0x02c0",
+            })
+        );
+    }
+
+    #[cfg(feature = "senders")]
+    #[smol_potat::test]
+    async fn test_recover_senders() {
+        use alloy_consensus::{SignableTransaction, TxLegacy};
+        use alloy_eips::eip2718::Encodable2718;
+        use alloy_primitives::{PrimitiveSignature, TxKind};
+        use k256::ecdsa::SigningKey;
+
+        let key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let signer = Address::from_private_key(&key);
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let first_tx_num = 1000u64;
+
+        // A system transaction, then transactions that alternately store
+        // their sender and leave it zero
+        let mut words = vec![Vec::new()];
+        for nonce in 0..9u64 {
+            let tx = TxLegacy {
+                chain_id: Some(1),
+                nonce,
+                gas_price: 1_000_000_000,
+                gas_limit: 21_000,
+                to: TxKind::Call(Address::with_last_byte(nonce as u8)),
+                ..Default::default()
+            };
+            let signature = key
+                .sign_prehash_recoverable(tx.signature_hash().as_slice())
+                .unwrap();
+            let envelope: TxEnvelope = tx.into_signed(PrimitiveSignature::from(signature)).into();
+            let stored = match nonce % 2 {
+                0 => Address::ZERO,
+                _ => Address::with_last_byte(0xee),
+            };
+            let mut word = vec![envelope.tx_hash()[0]];
+            word.extend_from_slice(stored.as_slice());
+            word.extend_from_slice(&envelope.encoded_2718());
+            words.push(word);
+        }
+        let path = tmp_dir.path().join("v1-000100-000120-transactions.seg");
+        write_indexed(&path, &words, first_tx_num, |i, word| {
+            if word.is_empty() {
+                i.to_be_bytes().to_vec()
+            } else {
+                keccak256(&word[21..]).to_vec()
+            }
+        });
+        let reader = TransactionsReader::new(&path).unwrap();
+
+        assert_eq!(reader.sender_of(TxNum(first_tx_num)).unwrap(), None);
+        assert_eq!(
+            reader.sender_of(TxNum(first_tx_num + 1)).unwrap(),
+            Some(signer)
+        );
+        assert_eq!(
+            reader.sender_of(TxNum(first_tx_num + 2)).unwrap(),
+            Some(Address::with_last_byte(0xee))
+        );
+        assert_eq!(reader.sender_of(TxNum(first_tx_num + 10)).unwrap(), None);
+
+        reader.clear_sender_cache();
+        let range = TxNum(first_tx_num)..TxNum(first_tx_num + 10);
+        for parallelism in [1, 3, 100] {
+            let senders = reader
+                .recover_senders(range.clone(), parallelism)
+                .await
+                .unwrap();
+            assert_eq!(senders.len(), 10);
+            assert_eq!(senders[0], None);
+            for (i, sender) in senders.iter().enumerate().skip(1) {
+                let expected = match i % 2 {
+                    1 => signer,
+                    _ => Address::with_last_byte(0xee),
+                };
+                assert_eq!(*sender, Some(expected));
+            }
+        }
+        assert_eq!(lock(&reader.recovered).len(), 5);
+        assert!(reader
+            .recover_senders(TxNum(first_tx_num + 5)..TxNum(first_tx_num + 5), 1)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(reader
+            .recover_senders(TxNum(first_tx_num + 5)..TxNum(first_tx_num + 11), 1)
+            .await
+            .is_err());
+
+        // The cache keeps the most recently recovered senders only
+        let reader = reader.with_sender_cache_capacity(NonZeroUsize::new(2).unwrap());
+        let senders = reader.recover_senders(range, 2).await.unwrap();
+        assert_eq!(senders[9], Some(signer));
+        assert_eq!(lock(&reader.recovered).len(), 2);
+        assert_eq!(
+            reader.sender_of(TxNum(first_tx_num + 1)).unwrap(),
+            Some(signer)
+        );
+        assert_eq!(lock(&reader.recovered).len(), 2);
+    }
+
+    #[test]
+    fn test_senders_segment() {
+        use crate::testutil::write_segment;
+        use alloy_eips::eip2718::Encodable2718;
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let first_tx_num = 1000u64;
+        let sender = |i: u64| Address::with_last_byte(0x10 + i as u8);
+        // System transactions at 0 and 5; the others store no sender except
+        // transaction 3
+        let mut tx_words = Vec::new();
+        let mut sender_words = Vec::new();
+        for i in 0..6u64 {
+            if i % 5 == 0 {
+                tx_words.push(Vec::new());
+                sender_words.push(Vec::new());
+                continue;
+            }
+            let envelope = signed_tx(i);
+            let stored = if i == 3 { sender(i) } else { Address::ZERO };
+            let mut word = vec![envelope.tx_hash()[0]];
+            word.extend_from_slice(stored.as_slice());
+            word.extend_from_slice(&envelope.encoded_2718());
+            tx_words.push(word);
+            sender_words.push(sender(i).to_vec());
+        }
+        let txs_path = tmp_dir.path().join("v1-000100-000120-transactions.seg");
+        write_indexed(&txs_path, &tx_words, first_tx_num, |i, word| {
+            if word.is_empty() {
+                i.to_be_bytes().to_vec()
+            } else {
+                keccak256(&word[21..]).to_vec()
+            }
+        });
+        let write_senders = |words: &[Vec<u8>]| {
+            let path = tmp_dir.path().join("v1-000100-000120-senders.seg");
+            write_segment(&path, words).unwrap();
+            path
+        };
+
+        // The senders segment is found next to the transactions and takes
+        // their first TxNum, having no index
+        let senders_path = write_senders(&sender_words);
+        let reader = TransactionsReader::new(&txs_path).unwrap();
+        let senders = reader.senders().unwrap();
+        assert_eq!(senders.count(), 6);
+        assert_eq!(senders.first_tx_num(), Some(TxNum(first_tx_num)));
+        senders.verify(&reader).unwrap();
+        assert_eq!(reader.sender_of(TxNum(first_tx_num)).unwrap(), None);
+        for i in 1..5 {
+            let tx_num = TxNum(first_tx_num + i);
+            assert_eq!(reader.sender_of(tx_num).unwrap(), Some(sender(i)));
+        }
+        assert_eq!(reader.sender_of(TxNum(first_tx_num + 6)).unwrap(), None);
+        let range = senders
+            .senders(TxNum(first_tx_num + 4)..TxNum(first_tx_num + 6))
+            .unwrap();
+        assert_eq!(range, [Some(sender(4)), None]);
+        assert!(senders
+            .senders(TxNum(first_tx_num + 4)..TxNum(first_tx_num + 7))
+            .is_err());
+
+        // A sender the transaction contradicts, and one for a system
+        // transaction
+        for (i, word) in [(3, sender(9).to_vec()), (5, sender(5).to_vec())] {
+            let mut words = sender_words.clone();
+            words[i] = word;
+            write_senders(&words);
+            let reader = TransactionsReader::new(&txs_path).unwrap();
+            assert!(matches!(
+                reader.senders().unwrap().verify(&reader),
+                Err(SnapshotError::InvalidFormat(_))
+            ));
+        }
+
+        write_senders(&sender_words[..5]);
+        assert!(matches!(
+            TransactionsReader::new(&txs_path),
+            Err(SnapshotError::SendersMismatch {
+                senders: 5,
+                transactions: 6
+            })
+        ));
+        std::fs::remove_file(&senders_path).unwrap();
+        let reader = TransactionsReader::new(&txs_path).unwrap();
+        assert!(reader.senders().is_none());
+        assert_eq!(
+            reader.sender_of(TxNum(first_tx_num + 3)).unwrap(),
+            Some(sender(3))
+        );
+    }
+
+    #[test]
+    fn test_bodies_and_transactions_readers() {
+        use alloy_eips::eip2718::Encodable2718;
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let first_block = 100u64;
+        let first_tx_num = 1000u64;
+
+        // Block b holds b % 3 transactions between its two system ones
+        let mut bodies = Vec::new();
+        let mut tx_words = Vec::new();
+        let mut envelopes = Vec::new();
+        for b in 0..20u64 {
+            let n = b % 3;
+            bodies.push(StoredBody {
+                base_tx_num: TxNum(first_tx_num + tx_words.len() as u64),
+                tx_count: n as u32 + 2,
+                ..Default::default()
+            });
+            tx_words.push(Vec::new());
+            for _ in 0..n {
+                let envelope = signed_tx(envelopes.len() as u64);
+                let mut word = vec![envelope.tx_hash()[0]];
+                word.extend_from_slice(Address::with_last_byte(0xee).as_slice());
+                word.extend_from_slice(&envelope.encoded_2718());
+                tx_words.push(word);
+                envelopes.push(envelope);
+            }
+            tx_words.push(Vec::new());
+        }
+
+        let bodies_path = tmp_dir.path().join("v1-000100-000120-bodies.seg");
+        let body_words: Vec<Vec<u8>> = bodies.iter().map(StoredBody::encode).collect();
+        write_indexed(&bodies_path, &body_words, first_block, |i, _| {
+            i.to_be_bytes().to_vec()
+        });
+        let txs_path = tmp_dir.path().join("v1-000100-000120-transactions.seg");
+        write_indexed(&txs_path, &tx_words, first_tx_num, |i, word| {
+            if word.is_empty() {
+                i.to_be_bytes().to_vec()
+            } else {
+                keccak256(&word[21..]).to_vec()
+            }
+        });
+
+        let bodies_reader = BodiesReader::new(&bodies_path).unwrap();
+        let txs_reader = TransactionsReader::new(&txs_path).unwrap();
+        assert_eq!(bodies_reader.count(), 20);
+        assert_eq!(bodies_reader.first_block(), Some(BlockNumber(first_block)));
+        assert_eq!(txs_reader.count(), tx_words.len());
+        assert_eq!(txs_reader.first_tx_num(), Some(TxNum(first_tx_num)));
+
+        let body = bodies_reader
+            .body_by_number(BlockNumber(105))
+            .unwrap()
+            .unwrap();
+        assert_eq!(body, bodies[5]);
+        assert!(bodies_reader
+            .body_by_number(BlockNumber(99))
+            .unwrap()
+            .is_none());
+        assert!(bodies_reader
+            .body_by_number(BlockNumber(120))
+            .unwrap()
+            .is_none());
+
+        // Block 105 has two transactions, after those of blocks 100..105
+        let txs = txs_reader.transactions(&body).unwrap();
+        assert_eq!(txs.len(), 2);
+        for (tx, envelope) in txs.iter().zip(&envelopes[4..6]) {
+            assert_eq!(tx.hash(), *envelope.tx_hash());
+            assert_eq!(tx.envelope().unwrap(), *envelope);
+            assert_eq!(tx.sender, Address::with_last_byte(0xee));
+        }
+        assert_eq!(
+            txs_reader
+                .transaction(body.tx_num(TxIndex(1)).unwrap())
+                .unwrap(),
+            Some(txs[1].clone())
+        );
+        // System transactions and numbers outside the snapshot
+        assert!(txs_reader.transaction(body.base_tx_num).unwrap().is_none());
+        assert!(txs_reader.transaction(TxNum(999)).unwrap().is_none());
+        assert!(txs_reader.transactions(&bodies[0]).unwrap().is_empty());
+
+        for envelope in &envelopes {
+            let tx = txs_reader
+                .transaction_by_hash(*envelope.tx_hash())
+                .unwrap()
+                .unwrap();
+            assert_eq!(tx.envelope().unwrap(), *envelope);
+        }
+        assert!(txs_reader
+            .transaction_by_hash(B256::repeat_byte(0x42))
+            .unwrap()
+            .is_none());
+    }
 }
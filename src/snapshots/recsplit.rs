@@ -1,29 +1,350 @@
-/// RecSplit index reader for Erigon snapshot files
+/// RecSplit index reader and builder for Erigon snapshot files
 /// Based on the Go implementation in erigon-lib/recsplit
+use crate::core::{FormatVersion, NameVersion};
+use crate::decompress::SafeReader;
+use crate::error::IndexError;
+use crate::error::ReadError;
+use crate::metrics;
+use crate::snapshots::elias_fano::{
+    build_double_elias_fano, select64, set, set_bits, DoubleEliasFano,
+};
+use crate::snapshots::golomb_rice::{GolombRiceBuilder, GolombRiceReader, BIJ_MEMO};
+use crate::snapshots::mapped::FileData;
 use crate::snapshots::{Result, SnapshotError};
-use memmap2::Mmap;
 use murmur3;
-use std::fs::File;
-use std::io::Cursor;
-use std::path::Path;
+use std::collections::hash_map::RandomState;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh64::xxh64;
 
 /// Features supported in the index file
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Features(u8);
 
 impl Features {
     pub const NONE: Features = Features(0b0);
     pub const ENUMS: Features = Features(0b1);
     pub const LESS_FALSE_POSITIVES: Features = Features(0b10);
+    // Every bit Erigon defines; indexes with others are refused
+    const ALL: Features = Features(0b11);
 
     pub fn contains(&self, feature: Features) -> bool {
         self.0 & feature.0 != 0
     }
 }
 
+// From Go: index.go:145-215 and recsplit.go:667-745
+/// The fields opening an index file, before the records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexHeader {
+    pub base_data_id: u64,
+    pub key_count: u64,
+    /// Width of each record, in bytes; 0 for indexes of a single key
+    pub bytes_per_rec: u8,
+}
+
+impl IndexHeader {
+    pub const LEN: usize = 17;
+
+    pub fn read(reader: &mut SafeReader<'_>) -> std::result::Result<Self, ReadError> {
+        Ok(IndexHeader {
+            base_data_id: reader.u64_be("base data id")?,
+            key_count: reader.u64_be("key count")?,
+            bytes_per_rec: reader.u8("record width")?,
+        })
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[..8].copy_from_slice(&self.base_data_id.to_be_bytes());
+        bytes[8..16].copy_from_slice(&self.key_count.to_be_bytes());
+        bytes[16] = self.bytes_per_rec;
+        bytes
+    }
+}
+
+/// The parameters of an index, between its records and its offsets
+///
+/// Variable-length: a byte counts the start seeds before them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexParams {
+    pub bucket_count: u64,
+    pub bucket_size: u16,
+    pub leaf_size: u16,
+    pub salt: u32,
+    pub start_seed: Vec<u64>,
+    pub features: Features,
+}
+
+impl IndexParams {
+    pub fn read(reader: &mut SafeReader<'_>) -> std::result::Result<Self, ReadError> {
+        let bucket_count = reader.u64_be("bucket count")?;
+        let bucket_size = reader.u16_be("bucket size")?;
+        let leaf_size = reader.u16_be("leaf size")?;
+        let salt = reader.u32_be("salt")?;
+        let start_seed_len = reader.u8("start seed count")?;
+        let start_seed = (0..start_seed_len)
+            .map(|_| reader.u64_be("start seed"))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let features = Features(reader.u8("features")?);
+        Ok(IndexParams {
+            bucket_count,
+            bucket_size,
+            leaf_size,
+            salt,
+            start_seed,
+            features,
+        })
+    }
+
+    /// Append the parameters to `out`; at most 255 start seeds fit
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.bucket_count.to_be_bytes());
+        out.extend_from_slice(&self.bucket_size.to_be_bytes());
+        out.extend_from_slice(&self.leaf_size.to_be_bytes());
+        out.extend_from_slice(&self.salt.to_be_bytes());
+        out.push(self.start_seed.len() as u8);
+        for seed in &self.start_seed {
+            out.extend_from_slice(&seed.to_be_bytes());
+        }
+        out.push(self.features.0);
+    }
+}
+
+// From Go: recsplit.go:57
+// David Stafford's 13th variant of the 64-bit finalizer function in MurmurHash3
+pub(crate) fn remix(z: u64) -> u64 {
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// From Go: recsplit.go:251
+// Maps x, uniformly distributed over [0..2^64), to [0..n)
+fn remap(x: u64, n: u64) -> u64 {
+    ((x as u128 * n as u128) >> 64) as u64
+}
+
+// From Go: recsplit.go:260
+// Like remap, under the assumption that n is less than 2^16
+fn remap16(x: u64, n: u16) -> u16 {
+    const MASK48: u64 = (1 << 48) - 1;
+    (((x & MASK48) * n as u64) >> 48) as u16
+}
+
+// From Go: recsplit.go:286
+fn split_params(
+    m: u16,
+    leaf_size: u16,
+    primary_aggr_bound: u16,
+    secondary_aggr_bound: u16,
+) -> (u16, u16) {
+    if m > secondary_aggr_bound {
+        // High-level aggregation (fanout 2)
+        let unit = secondary_aggr_bound * ((m.div_ceil(2)).div_ceil(secondary_aggr_bound));
+        (2, unit)
+    } else if m > primary_aggr_bound {
+        // Second-level aggregation
+        (m.div_ceil(primary_aggr_bound), primary_aggr_bound)
+    } else {
+        // First-level aggregation
+        (m.div_ceil(leaf_size), leaf_size)
+    }
+}
+
+// From Go: recsplit.go:302 computeGolombRice
+// Each entry packs the Golomb parameter (top 5 bits), the number of nodes in
+// the subtree (11 bits) and the total code length of the subtree (low 16 bits)
+fn compute_golomb_rice(
+    m: u16,
+    table: &mut [u32],
+    leaf_size: u16,
+    primary_aggr_bound: u16,
+    secondary_aggr_bound: u16,
+) -> Result<()> {
+    let (fanout, unit) = split_params(m, leaf_size, primary_aggr_bound, secondary_aggr_bound);
+    let mut k = vec![unit; fanout as usize];
+    k[fanout as usize - 1] = m - unit * (fanout - 1);
+
+    let sqrt_prod: f64 = k.iter().map(|&ki| (ki as f64).sqrt()).product();
+    let p = (m as f64).sqrt()
+        / ((2.0 * std::f64::consts::PI).powf((fanout as f64 - 1.0) / 2.0) * sqrt_prod);
+    let golomb_base_log2 = -((5f64.sqrt() + 1.0) / 2.0).ln();
+    // log2 Golomb modulus
+    let mut golomb_rice_length = (golomb_base_log2 / (-p).ln_1p()).log2().ceil() as u32;
+    if golomb_rice_length > 0x1F {
+        return Err(IndexError::InvalidParameters(format!(
+            "Golomb-Rice length {} too large for m={}",
+            golomb_rice_length, m
+        ))
+        .into());
+    }
+    table[m as usize] = golomb_rice_length << 27;
+    for &ki in &k {
+        golomb_rice_length += table[ki as usize] & 0xFFFF;
+    }
+    if golomb_rice_length > 0xFFFF {
+        return Err(IndexError::InvalidParameters(format!(
+            "Golomb-Rice subtree length {} too large for m={}",
+            golomb_rice_length, m
+        ))
+        .into());
+    }
+    // Sum of Golomb-Rice code lengths in the subtree, stored in the lower 16 bits
+    table[m as usize] |= golomb_rice_length;
+    let mut nodes = 1u32;
+    for &ki in &k {
+        nodes += (table[ki as usize] >> 16) & 0x7FF;
+    }
+    if leaf_size >= 3 && nodes > 0x7FF {
+        return Err(IndexError::InvalidParameters(format!(
+            "Too many nodes ({}) in subtree for m={}",
+            nodes, m
+        ))
+        .into());
+    }
+    table[m as usize] |= nodes << 16;
+    Ok(())
+}
+
+// From Go: index.go:199-207
+// Builds the first `size` entries of the Golomb-Rice parameter table
+pub(crate) fn golomb_rice_table(
+    size: usize,
+    leaf_size: u16,
+    primary_aggr_bound: u16,
+    secondary_aggr_bound: u16,
+) -> Result<Vec<u32>> {
+    let mut table = vec![0u32; size];
+    for i in 0..size {
+        if i == 0 {
+            table[i] = (BIJ_MEMO[0] << 27) | BIJ_MEMO[0];
+        } else if i <= leaf_size as usize {
+            let bij = *BIJ_MEMO.get(i).ok_or_else(|| {
+                SnapshotError::InvalidFormat(format!("Leaf size {} too large", leaf_size))
+            })?;
+            table[i] = (bij << 27) | (1u32 << 16) | bij;
+        } else {
+            compute_golomb_rice(
+                i as u16,
+                &mut table,
+                leaf_size,
+                primary_aggr_bound,
+                secondary_aggr_bound,
+            )?;
+        }
+    }
+    Ok(table)
+}
+
+// From Go: index.go:165-170
+// Lower bounds for primary and secondary key aggregation, derived from leaf size
+pub(crate) fn aggr_bounds(leaf_size: u16) -> (u16, u16) {
+    let primary = leaf_size * (0.35 * leaf_size as f64 + 0.5).ceil().max(2.0) as u16;
+    let secondary = if leaf_size < 7 {
+        primary * 2
+    } else {
+        primary * (0.21 * leaf_size as f64 + 0.9).ceil() as u16
+    };
+    (primary, secondary)
+}
+
+/// Hash a key the way RecSplit does: murmur3 x64_128 with the index salt,
+/// returning (bucket_hash, fingerprint)
+pub fn hash_key(key: &[u8], salt: u32) -> (u64, u64) {
+    Murmur3.hash_key(key, salt)
+}
+
+/// A hash function keys of a RecSplit index are hashed with, picked by
+/// [`RecSplitBuilder::hasher`]
+///
+/// Erigon hashes with [`Murmur3`] and has no field for the hasher, so
+/// indexes hashed otherwise end with a trailer naming it, after the data
+/// Erigon reads.
+///
+/// Hashers are stateless and shared as `&'static` references between
+/// indexes and threads.
+pub trait KeyHasher: Send + Sync + std::fmt::Debug {
+    /// Hash `key` with the index salt into (bucket_hash, fingerprint), two
+    /// independent 64-bit hashes
+    fn hash_key(&self, key: &[u8], salt: u32) -> (u64, u64);
+
+    /// Id in the trailer of indexes built with this hasher; 0 for
+    /// murmur3, whose indexes have no trailer
+    fn id(&self) -> u8;
+}
+
+/// murmur3 x64_128, the hash of every Erigon index
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Murmur3;
+
+impl KeyHasher for Murmur3 {
+    fn hash_key(&self, key: &[u8], salt: u32) -> (u64, u64) {
+        let mut cursor = Cursor::new(key);
+        // Reading from an in-memory cursor cannot fail
+        let hash128 = murmur3::murmur3_x64_128(&mut cursor, salt).unwrap_or_default();
+        // Go's Sum128WithSeed returns (h1, h2); the crate packs h1 in the low half
+        (hash128 as u64, (hash128 >> 64) as u64)
+    }
+
+    fn id(&self) -> u8 {
+        0
+    }
+}
+
+/// xxHash64, several times faster than murmur3 on short keys; the bucket
+/// hash and the fingerprint are two hashes with different seeds
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XxHash64;
+
+// Mixed into the salt for the fingerprint, so that it is independent of the
+// bucket hash
+const XXHASH_FINGERPRINT_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl KeyHasher for XxHash64 {
+    fn hash_key(&self, key: &[u8], salt: u32) -> (u64, u64) {
+        (
+            xxh64(key, salt as u64),
+            xxh64(key, salt as u64 ^ XXHASH_FINGERPRINT_SEED),
+        )
+    }
+
+    fn id(&self) -> u8 {
+        1
+    }
+}
+
+// Ends indexes not hashed with murmur3, followed by the hasher id
+const KEY_HASHER_MAGIC: [u8; 8] = *b"KEYHASHR";
+
+// The hasher an index was built with, from its trailer
+fn recorded_hasher(data: &[u8]) -> Result<&'static dyn KeyHasher> {
+    let Some((&id, rest)) = data.split_last() else {
+        return Ok(&Murmur3);
+    };
+    if !rest.ends_with(&KEY_HASHER_MAGIC) {
+        return Ok(&Murmur3);
+    }
+    match id {
+        id if id == XxHash64.id() => Ok(&XxHash64),
+        id => Err(SnapshotError::InvalidFormat(format!(
+            "Unknown index key hasher {}",
+            id
+        ))),
+    }
+}
+
+// Elias-Fano (eliasfano32) jump table layout used by the enum offsets
+const EF32_Q: u64 = 1 << 8; // 256
+const EF32_Q_MASK: u64 = EF32_Q - 1;
+const EF32_SUPER_Q: u64 = 1 << 14; // 16384
+const EF32_SUPER_Q_SIZE: u64 = 1 + (EF32_SUPER_Q / EF32_Q) / 2; // 1 + 64/2 = 33
+
 /// RecSplit index for perfect hash lookup
 pub struct RecSplitIndex {
-    mmap: Mmap,
+    mmap: FileData,
     base_data_id: u64,
     key_count: u64,
     bytes_per_rec: u8,
@@ -34,11 +355,18 @@ pub struct RecSplitIndex {
     salt: u32,
     start_seed: Vec<u64>,
     features: Features,
+    hasher: &'static dyn KeyHasher,
+    name_version: Option<NameVersion>,
+    primary_aggr_bound: u16,
+    secondary_aggr_bound: u16,
+    golomb_rice: Vec<u32>,
 
     // Offsets into the mmap data
     records_offset: usize,
-    bucket_data_offset: usize,
-    golomb_rice_offset: usize,
+    existence_offset: Option<usize>,
+    gr_data_offset: usize,
+    gr_data_len: usize,
+    double_ef_offset: usize,
 
     // For enum indexes - we store the offset and size of the EF data
     offset_ef_start: Option<usize>,
@@ -49,173 +377,129 @@ impl RecSplitIndex {
     /// Open a RecSplit index file
     pub fn open(path: &Path) -> Result<Self> {
         let file = File::open(path)?;
-        let mmap = unsafe { Mmap::map(&file)? };
+        let mmap = FileData::open(&file)?;
+        let file_name = path.display().to_string();
+        let malformed = |source| IndexError::Read {
+            file: file_name.clone(),
+            source,
+        };
+        let mut reader = SafeReader::new(&mmap);
 
-        if mmap.len() < 17 {
-            return Err(SnapshotError::InvalidFormat(
-                "Index file too small".to_string(),
-            ));
+        let IndexHeader {
+            base_data_id,
+            key_count,
+            bytes_per_rec,
+        } = IndexHeader::read(&mut reader).map_err(malformed)?;
+        if bytes_per_rec > 8 {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Invalid record width {} bytes",
+                bytes_per_rec
+            )));
         }
 
-        let mut offset = 0;
-
-        // Read header: baseDataID (8) + keyCount (8) + bytesPerRec (1)
-        let base_data_id = u64::from_be_bytes(
-            mmap[offset..offset + 8]
-                .try_into()
-                .map_err(|_| SnapshotError::InvalidFormat("Invalid baseDataID".to_string()))?,
-        );
-        offset += 8;
-
-        let key_count = u64::from_be_bytes(
-            mmap[offset..offset + 8]
-                .try_into()
-                .map_err(|_| SnapshotError::InvalidFormat("Invalid keyCount".to_string()))?,
-        );
-        offset += 8;
-
-        let bytes_per_rec = mmap[offset];
-        offset += 1;
-
-        let rec_mask = (1u64 << (8 * bytes_per_rec)) - 1;
-        let records_offset = offset;
+        let rec_mask = match bytes_per_rec {
+            8 => u64::MAX,
+            n => (1u64 << (8 * n)) - 1,
+        };
 
         // Skip records
-        offset += (key_count as usize) * (bytes_per_rec as usize);
+        let records_offset = reader.position();
+        reader
+            .skip(key_count.saturating_mul(bytes_per_rec as u64), "records")
+            .map_err(malformed)?;
 
-        if offset + 8 > mmap.len() {
-            return Err(SnapshotError::InvalidFormat(
-                "Index file truncated after records".to_string(),
-            ));
+        let IndexParams {
+            bucket_count,
+            bucket_size,
+            leaf_size,
+            salt,
+            start_seed,
+            features,
+        } = IndexParams::read(&mut reader).map_err(malformed)?;
+        if leaf_size == 0 || leaf_size > MAX_LEAF_SIZE || bucket_size == 0 {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Invalid leaf size {} or bucket size {}",
+                leaf_size, bucket_size
+            )));
         }
-
-        // Read bucket count, bucket size, leaf size
-        let bucket_count = u64::from_be_bytes(
-            mmap[offset..offset + 8]
-                .try_into()
-                .map_err(|_| SnapshotError::InvalidFormat("Invalid bucketCount".to_string()))?,
-        );
-        offset += 8;
-
-        let bucket_size = u16::from_be_bytes(
-            mmap[offset..offset + 2]
-                .try_into()
-                .map_err(|_| SnapshotError::InvalidFormat("Invalid bucketSize".to_string()))?,
-        );
-        offset += 2;
-
-        let leaf_size = u16::from_be_bytes(
-            mmap[offset..offset + 2]
-                .try_into()
-                .map_err(|_| SnapshotError::InvalidFormat("Invalid leafSize".to_string()))?,
-        );
-        offset += 2;
-
-        // Salt
-        let salt = u32::from_be_bytes(
-            mmap[offset..offset + 4]
-                .try_into()
-                .map_err(|_| SnapshotError::InvalidFormat("Invalid salt".to_string()))?,
-        );
-        offset += 4;
-
-        // Start seeds
-        let start_seed_len = mmap[offset] as usize;
-        offset += 1;
-
-        let mut start_seed = Vec::with_capacity(start_seed_len);
-        for _ in 0..start_seed_len {
-            if offset + 8 > mmap.len() {
-                return Err(SnapshotError::InvalidFormat(
-                    "Index file truncated in start seeds".to_string(),
-                ));
-            }
-            start_seed.push(u64::from_be_bytes(
-                mmap[offset..offset + 8].try_into().unwrap(),
-            ));
-            offset += 8;
+        if features.0 & !Features::ALL.0 != 0 {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Unknown index features bitmap: {:b}",
+                features.0
+            )));
         }
-
-        // Features
-        let features = Features(mmap[offset]);
-        offset += 1;
+        let hasher = recorded_hasher(&mmap)?;
+        let mut existence_offset = None;
 
         // Handle enum indexes with Elias-Fano offsets
-        let (offset_ef_start, offset_ef_size) =
-            if features.contains(Features::ENUMS) && key_count > 0 {
-                // Read Elias-Fano encoded offsets
-                // Format: count (8 bytes) + u (8 bytes) + data (as uint64 array)
-                if offset + 16 > mmap.len() {
-                    return Err(SnapshotError::InvalidFormat(
-                        "Index file truncated in Elias-Fano header".to_string(),
-                    ));
-                }
-
-                let ef_start = offset;
-                let ef_count = u64::from_be_bytes(mmap[offset..offset + 8].try_into().unwrap());
-                let ef_u = u64::from_be_bytes(mmap[offset + 8..offset + 16].try_into().unwrap());
-
-                // The Go code reads the data as: data = unsafe.Slice((*uint64)(unsafe.Pointer(&r[16])), (len(r)-16)/uint64Size)
-                // After count and u, the remaining data is treated as an array of uint64s
-                // We need to calculate how many uint64s are in the data array
-
-                // Calculate l (bits per lower part)
-                let l = if ef_count + 1 == 0 || ef_u == 0 {
-                    0
-                } else {
-                    let ratio = ef_u / (ef_count + 1);
-                    if ratio == 0 {
-                        0
-                    } else {
-                        63 - ratio.leading_zeros() as u64
-                    }
-                };
-
-                // Calculate array sizes (following Go's deriveFields)
-                let words_lower_bits = ((ef_count + 1) * l + 63) / 64 + 1;
-                let words_upper_bits = (ef_count + 1 + (ef_u >> l) + 63) / 64;
-
-                // Jump table calculation from Go's jumpSizeWords()
-                let super_q = 1u64 << 14; // 16384
-                let super_q_size = 1 + 16; // 1 + qPerSuperQ
-                let jump_words = if ef_count == 0 {
-                    0
-                } else {
-                    (1 + (ef_count - 1) / super_q) * super_q_size
-                };
-
-                let total_words = words_lower_bits + words_upper_bits + jump_words;
-                let data_size = 16 + (total_words * 8) as usize; // 16 for count+u, then uint64 array
-
-                if offset + data_size > mmap.len() {
+        let (offset_ef_start, offset_ef_size) = if features.contains(Features::ENUMS)
+            && key_count > 0
+        {
+            // Read Elias-Fano encoded offsets
+            // Format: count (8 bytes) + u (8 bytes) + data (as uint64 array)
+            let ef_start = reader.position();
+            reader
+                .clone()
+                .skip(EliasFanoHeader::LEN as u64, "Elias-Fano header")
+                .map_err(malformed)?;
+            let data_size = ef32_size(reader.rest());
+            reader
+                .skip(data_size as u64, "Elias-Fano offsets")
+                .map_err(malformed)?;
+
+            // Also skip the existence filter if present
+            if features.contains(Features::LESS_FALSE_POSITIVES) {
+                let existence_size = reader.u64_be("existence filter size").map_err(malformed)?;
+                if existence_size != key_count {
                     return Err(SnapshotError::InvalidFormat(format!(
-                        "Index file truncated in Elias-Fano data: need {} bytes, have {}",
-                        data_size,
-                        mmap.len() - offset
+                        "Invalid existence filter size {} for {} keys",
+                        existence_size, key_count
                     )));
                 }
+                existence_offset = Some(reader.position());
+                reader
+                    .skip(existence_size, "existence filter")
+                    .map_err(malformed)?;
+            }
 
-                offset += data_size;
-
-                // Also skip the existence filter if present
-                if features.contains(Features::LESS_FALSE_POSITIVES) {
-                    if offset + 8 > mmap.len() {
-                        return Err(SnapshotError::InvalidFormat(
-                            "Index file truncated in existence filter size".to_string(),
-                        ));
-                    }
-                    let existence_size =
-                        u64::from_be_bytes(mmap[offset..offset + 8].try_into().unwrap());
-                    offset += 8 + existence_size as usize;
-                }
-
-                (Some(ef_start), Some(data_size))
-            } else {
-                (None, None)
-            };
+            (Some(ef_start), Some(data_size))
+        } else {
+            (None, None)
+        };
 
-        let golomb_rice_offset = offset;
-        let bucket_data_offset = offset; // Will be adjusted
+        // Golomb-Rice parameter table: only its size is stored (as u16 in a
+        // 4-byte slot), the table itself is recomputed from the leaf size
+        let golomb_param_size =
+            reader.u16_be("Golomb-Rice table size").map_err(malformed)? as usize;
+        reader
+            .skip(2, "Golomb-Rice table size")
+            .map_err(malformed)?;
+        let (primary_aggr_bound, secondary_aggr_bound) = aggr_bounds(leaf_size);
+        let golomb_rice = golomb_rice_table(
+            golomb_param_size,
+            leaf_size,
+            primary_aggr_bound,
+            secondary_aggr_bound,
+        )?;
+
+        // Golomb-Rice coded seeds, as an array of little-endian u64 words
+        let gr_data_len = reader
+            .u64_be("Golomb-Rice data length")
+            .map_err(malformed)?;
+        let gr_data_offset = reader.position();
+        reader
+            .skip(gr_data_len.saturating_mul(8), "Golomb-Rice data")
+            .map_err(malformed)?;
+        // Fits in a usize, the data was just skipped
+        let gr_data_len = gr_data_len as usize;
+
+        // Double Elias-Fano of cumulative keys and bit positions per bucket
+        let double_ef_offset = reader.position();
+        if key_count > 1 && DoubleEliasFano::read(reader.rest()).is_none() {
+            return Err(SnapshotError::InvalidFormat(
+                "Index file truncated in bucket Elias-Fano data".to_string(),
+            ));
+        }
 
         Ok(RecSplitIndex {
             mmap,
@@ -229,9 +513,19 @@ impl RecSplitIndex {
             salt,
             start_seed,
             features,
+            hasher,
+            name_version: path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(NameVersion::from_file_name),
+            primary_aggr_bound,
+            secondary_aggr_bound,
+            golomb_rice,
             records_offset,
-            bucket_data_offset,
-            golomb_rice_offset,
+            existence_offset,
+            gr_data_offset,
+            gr_data_len,
+            double_ef_offset,
             offset_ef_start,
             offset_ef_size,
         })
@@ -247,14 +541,68 @@ impl RecSplitIndex {
         self.base_data_id
     }
 
+    /// The salt keys were hashed with
+    pub fn salt(&self) -> u32 {
+        self.salt
+    }
+
+    /// The hash function keys were hashed with, from the index trailer
+    pub fn hasher(&self) -> &'static dyn KeyHasher {
+        self.hasher
+    }
+
+    /// Check that the index was built with the datadir's salt (see
+    /// [`crate::snapshots::salt`]), as Erigon does before using it
+    pub fn verify_salt(&self, expected: u32) -> Result<()> {
+        if self.salt != expected {
+            return Err(IndexError::SaltMismatch {
+                expected,
+                actual: self.salt,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Version prefix of the index's file name, if it has one
+    pub fn name_version(&self) -> Option<NameVersion> {
+        self.name_version
+    }
+
+    /// Layout of the index, from its features byte: [`FormatVersion::V1_1`]
+    /// with the `LESS_FALSE_POSITIVES` existence filter
+    pub fn format_version(&self) -> FormatVersion {
+        if self.features.contains(Features::LESS_FALSE_POSITIVES) {
+            FormatVersion::V1_1
+        } else {
+            FormatVersion::V1_0
+        }
+    }
+
     /// Check if this is an enum index
     pub fn is_enum(&self) -> bool {
         self.features.contains(Features::ENUMS)
     }
 
+    /// Get the number of buckets
+    pub fn bucket_count(&self) -> u64 {
+        self.bucket_count
+    }
+
+    /// Get the bucket size used when the index was built
+    pub fn bucket_size(&self) -> u16 {
+        self.bucket_size
+    }
+
+    /// Get the leaf size used when the index was built
+    pub fn leaf_size(&self) -> u16 {
+        self.leaf_size
+    }
+
     /// Ordinal lookup - get offset for the i-th element (0-based)
     /// This is what we need for headers
     pub fn ordinal_lookup(&self, ordinal: u64) -> Option<u64> {
+        metrics::increment(metrics::INDEX_LOOKUPS, 1);
         if ordinal >= self.key_count {
             return None;
         }
@@ -262,256 +610,1110 @@ impl RecSplitIndex {
         // For enum indexes, we need to decode from Elias-Fano data
         if let (Some(ef_start), Some(_ef_size)) = (self.offset_ef_start, self.offset_ef_size) {
             // Get the offset from the Elias-Fano encoded data
-            self.decode_ef_value(ef_start, ordinal)
+            ef32_get(&self.mmap[ef_start..], ordinal)
         } else {
             // For non-enum indexes, read from the records section
-            // Each record is `bytes_per_rec` bytes
-            let record_offset =
-                self.records_offset + (ordinal as usize * self.bytes_per_rec as usize);
+            self.record(ordinal)
+        }
+    }
 
-            if record_offset + self.bytes_per_rec as usize > self.mmap.len() {
-                return None;
-            }
+    /// Offset of the word with data id `id`: the block number, TxNum or slot
+    /// the index counts from [`RecSplitIndex::base_data_id`]
+    pub fn lookup_by_data_id(&self, id: u64) -> Option<u64> {
+        self.ordinal_lookup(id.checked_sub(self.base_data_id)?)
+    }
 
-            // Read the offset value based on bytes_per_rec
-            let offset = match self.bytes_per_rec {
-                1 => self.mmap[record_offset] as u64,
-                2 => u16::from_be_bytes(
-                    self.mmap[record_offset..record_offset + 2]
-                        .try_into()
-                        .ok()?,
-                ) as u64,
-                3 => {
-                    let mut bytes = [0u8; 4];
-                    bytes[1..].copy_from_slice(&self.mmap[record_offset..record_offset + 3]);
-                    u32::from_be_bytes(bytes) as u64
-                }
-                4 => u32::from_be_bytes(
-                    self.mmap[record_offset..record_offset + 4]
-                        .try_into()
-                        .ok()?,
-                ) as u64,
-                5 => {
-                    let mut bytes = [0u8; 8];
-                    bytes[3..].copy_from_slice(&self.mmap[record_offset..record_offset + 5]);
-                    u64::from_be_bytes(bytes)
-                }
-                6 => {
-                    let mut bytes = [0u8; 8];
-                    bytes[2..].copy_from_slice(&self.mmap[record_offset..record_offset + 6]);
-                    u64::from_be_bytes(bytes)
-                }
-                7 => {
-                    let mut bytes = [0u8; 8];
-                    bytes[1..].copy_from_slice(&self.mmap[record_offset..record_offset + 7]);
-                    u64::from_be_bytes(bytes)
-                }
-                8 => u64::from_be_bytes(
-                    self.mmap[record_offset..record_offset + 8]
-                        .try_into()
-                        .ok()?,
-                ),
-                _ => return None,
-            };
+    /// Offsets of the words with data ids `from_id..to_id`, or `None` if the
+    /// index doesn't hold all of them
+    ///
+    /// Enum indexes decode the run with an [`EfIterator`], which is much
+    /// cheaper than an [`RecSplitIndex::ordinal_lookup`] per id.
+    pub fn offsets_range(&self, from_id: u64, to_id: u64) -> Option<Vec<u64>> {
+        if from_id >= to_id {
+            return Some(Vec::new());
+        }
+        let from = from_id.checked_sub(self.base_data_id)?;
+        let to = to_id.checked_sub(self.base_data_id)?;
+        if to > self.key_count {
+            return None;
+        }
+        let offsets: Vec<u64> = match self.offset_ef_start {
+            Some(ef_start) => EfIterator::new(&self.mmap[ef_start..], from)?
+                .take((to - from) as usize)
+                .collect(),
+            None => (from..to).map_while(|rec| self.record(rec)).collect(),
+        };
+        (offsets.len() as u64 == to - from).then_some(offsets)
+    }
+
+    /// Offsets of the words of an enum index from the `from`-th on, in order
+    ///
+    /// Cheaper than an [`RecSplitIndex::ordinal_lookup`] per word for scans;
+    /// `None` for non-enum indexes or if `from` is past the last key.
+    pub fn offsets(&self, from: u64) -> Option<EfIterator<'_>> {
+        EfIterator::new(&self.mmap[self.offset_ef_start?..], from)
+    }
 
-            Some(offset & self.rec_mask)
+    /// Read the `rec`-th fixed-width record (big-endian, `bytes_per_rec` bytes)
+    fn record(&self, rec: u64) -> Option<u64> {
+        let width = self.bytes_per_rec as usize;
+        if width == 0 || width > 8 {
+            return None;
+        }
+        // Records were bounds-checked on open, so only `rec` can be off
+        if rec >= self.key_count {
+            return None;
         }
+        let start = self.records_offset + rec as usize * width;
+        let bytes = self.mmap.get(start..start + width)?;
+        let mut buf = [0u8; 8];
+        buf[8 - width..].copy_from_slice(bytes);
+        Some(u64::from_be_bytes(buf) & self.rec_mask)
     }
 
-    /// Decode a value from the Elias-Fano data
-    fn decode_ef_value(&self, ef_start: usize, index: u64) -> Option<u64> {
-        // Read count and u from the EF header
-        let ef_count = u64::from_be_bytes(self.mmap[ef_start..ef_start + 8].try_into().ok()?);
-        let ef_u = u64::from_be_bytes(self.mmap[ef_start + 8..ef_start + 16].try_into().ok()?);
+    /// Hash-based lookup of `key`
+    ///
+    /// Returns the ordinal of the key for enum indexes (pass it to
+    /// [`RecSplitIndex::ordinal_lookup`] for the offset) and the offset itself
+    /// otherwise. Like any perfect hash, keys that were never added may still
+    /// map to some record; with `LESS_FALSE_POSITIVES` most of those are
+    /// rejected, callers should verify the record they land on.
+    pub fn lookup(&self, key: &[u8]) -> Option<u64> {
+        metrics::increment(metrics::INDEX_LOOKUPS, 1);
+        let (bucket_hash, fingerprint) = self.hasher.hash_key(key, self.salt);
+        self.lookup_hash(bucket_hash, fingerprint)
+    }
+
+    /// `false` if `key` was certainly not added to the index
+    ///
+    /// Only indexes built with `LESS_FALSE_POSITIVES` can tell; for others
+    /// every key may be present. Costs as much as a [`RecSplitIndex::lookup`].
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        match self.existence_offset {
+            Some(_) => self.lookup(key).is_some(),
+            None => self.key_count > 0,
+        }
+    }
 
-        if index > ef_count {
+    // From Go: index.go:289 Lookup
+    fn lookup_hash(&self, bucket_hash: u64, fingerprint: u64) -> Option<u64> {
+        if self.key_count == 0 {
             return None;
         }
+        if self.key_count == 1 {
+            return Some(0);
+        }
 
-        // Calculate l (bits per lower part) - matching Go's deriveFields()
-        let l = if ef_count + 1 == 0 || ef_u == 0 {
-            0
-        } else {
-            let ratio = ef_u / (ef_count + 1);
-            if ratio == 0 {
-                0
+        let gr_data = self
+            .mmap
+            .get(self.gr_data_offset..self.gr_data_offset + self.gr_data_len * 8)?;
+        let mut gr = GolombRiceReader::new(gr_data);
+        let (ef, _) = DoubleEliasFano::read(&self.mmap[self.double_ef_offset..])?;
+
+        let golomb_param = |m: u16| {
+            self.golomb_rice
+                .get(m as usize)
+                .map(|&v| (v >> 27) as usize)
+        };
+        let skip_bits = |m: u16| {
+            self.golomb_rice
+                .get(m as usize)
+                .map(|&v| (v & 0xffff) as usize)
+        };
+        let skip_nodes = |m: u16| {
+            self.golomb_rice
+                .get(m as usize)
+                .map(|&v| ((v >> 16) & 0x7ff) as usize)
+        };
+        let seed = |level: usize| self.start_seed.get(level).copied();
+
+        let bucket = remap(bucket_hash, self.bucket_count);
+        let (mut cum_keys, cum_keys_next, bit_pos) = ef.get3(bucket)?;
+        // Number of keys in this bucket
+        let mut m = cum_keys_next.checked_sub(cum_keys)? as u16;
+        gr.read_reset(bit_pos as usize, skip_bits(m)?)?;
+        let mut level = 0;
+
+        while m > self.secondary_aggr_bound {
+            // fanout = 2
+            let d = gr.read_next(golomb_param(m)?)?;
+            let hmod = remap16(
+                remix(fingerprint.wrapping_add(seed(level)?).wrapping_add(d)),
+                m,
+            );
+            let split =
+                m.div_ceil(2).div_ceil(self.secondary_aggr_bound) * self.secondary_aggr_bound;
+            if hmod < split {
+                m = split;
             } else {
-                63 - ratio.leading_zeros() as u64
+                gr.skip_subtree(skip_nodes(split)?, skip_bits(split)?)?;
+                m -= split;
+                cum_keys = cum_keys.wrapping_add(split as u64);
             }
-        };
+            level += 1;
+        }
+        if m > self.primary_aggr_bound {
+            let d = gr.read_next(golomb_param(m)?)?;
+            let hmod = remap16(
+                remix(fingerprint.wrapping_add(seed(level)?).wrapping_add(d)),
+                m,
+            );
+            let part = hmod / self.primary_aggr_bound;
+            m = (m - part * self.primary_aggr_bound).min(self.primary_aggr_bound);
+            cum_keys = cum_keys.wrapping_add((self.primary_aggr_bound * part) as u64);
+            if part != 0 {
+                gr.skip_subtree(
+                    skip_nodes(self.primary_aggr_bound)? * part as usize,
+                    skip_bits(self.primary_aggr_bound)? * part as usize,
+                )?;
+            }
+            level += 1;
+        }
+        if m > self.leaf_size {
+            let d = gr.read_next(golomb_param(m)?)?;
+            let hmod = remap16(
+                remix(fingerprint.wrapping_add(seed(level)?).wrapping_add(d)),
+                m,
+            );
+            let part = hmod / self.leaf_size;
+            m = (m - part * self.leaf_size).min(self.leaf_size);
+            cum_keys = cum_keys.wrapping_add((self.leaf_size * part) as u64);
+            if part != 0 {
+                gr.skip_subtree(part as usize, skip_bits(self.leaf_size)? * part as usize)?;
+            }
+            level += 1;
+        }
+        let b = gr.read_next(golomb_param(m)?)?;
+        let rec = cum_keys.wrapping_add(remap16(
+            remix(fingerprint.wrapping_add(seed(level)?).wrapping_add(b)),
+            m,
+        ) as u64);
+
+        let found = self.record(rec)?;
+        if let Some(existence_offset) = self.existence_offset {
+            let existence = *self
+                .mmap
+                .get(existence_offset.checked_add(usize::try_from(found).ok()?)?)?;
+            if existence != bucket_hash as u8 {
+                return None;
+            }
+        }
+        Some(found)
+    }
+}
 
-        let lower_bits_mask = if l >= 64 { !0u64 } else { (1u64 << l) - 1 };
+// From Go: eliasfano32 Write
+/// The two numbers opening an eliasfano32 sequence, big-endian; its bit
+/// arrays follow as little-endian `u64` words
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EliasFanoHeader {
+    /// Number of values minus one
+    pub count: u64,
+    /// Largest value plus one
+    pub u: u64,
+}
 
-        // Calculate array boundaries
-        let words_lower_bits = ((ef_count + 1) * l + 63) / 64 + 1;
-        let words_upper_bits = (ef_count + 1 + (ef_u >> l) + 63) / 64;
+impl EliasFanoHeader {
+    pub const LEN: usize = 16;
 
-        // Jump table calculation
-        const SUPER_Q: u64 = 1 << 14; // 16384
-        const SUPER_Q_SIZE: u64 = 1 + 16; // 1 + qPerSuperQ
-        const Q: u64 = 1 << 8; // 256
-        const Q_MASK: u64 = Q - 1;
+    pub fn read(reader: &mut SafeReader<'_>) -> std::result::Result<Self, ReadError> {
+        Ok(EliasFanoHeader {
+            count: reader.u64_be("Elias-Fano count")?,
+            u: reader.u64_be("Elias-Fano universe")?,
+        })
+    }
 
-        let jump_words = if ef_count == 0 {
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[..8].copy_from_slice(&self.count.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.u.to_be_bytes());
+        bytes
+    }
+}
+
+/// Decode the `index`-th value of the eliasfano32 sequence at the start of `ef`
+// From Go: eliasfano32 get
+pub(crate) fn ef32_get(ef: &[u8], index: u64) -> Option<u64> {
+    // Read count and u from the EF header
+    let EliasFanoHeader {
+        count: ef_count,
+        u: ef_u,
+    } = EliasFanoHeader::read(&mut SafeReader::new(ef)).ok()?;
+
+    if index > ef_count {
+        return None;
+    }
+
+    // Calculate l (bits per lower part) - matching Go's deriveFields()
+    let l = if ef_count + 1 == 0 || ef_u == 0 {
+        0
+    } else {
+        let ratio = ef_u / (ef_count + 1);
+        if ratio == 0 {
             0
         } else {
-            (1 + (ef_count - 1) / SUPER_Q) * SUPER_Q_SIZE
-        };
+            63 - ratio.leading_zeros() as u64
+        }
+    };
 
-        // Get the data as u64 array (starting after count and u)
-        // The Go code treats this as little-endian uint64 array
-        let data_start = ef_start + 16;
+    let lower_bits_mask = if l >= 64 { !0u64 } else { (1u64 << l) - 1 };
 
-        // Read lower bits - matching Go's get() function
-        let mut lower = 0u64;
-        if l > 0 {
-            let lower_bit_pos = index * l;
-            let idx64 = (lower_bit_pos / 64) as usize;
-            let shift = lower_bit_pos % 64;
+    // Calculate array boundaries
+    let words_lower_bits = ((ef_count + 1) * l).div_ceil(64) + 1;
+    let words_upper_bits = (ef_count + 1 + (ef_u >> l)).div_ceil(64);
 
-            if data_start + (idx64 + 1) * 8 > self.mmap.len() {
-                return None;
-            }
+    let jump_words = ef32_jump_size_words(ef_count);
 
-            lower = u64::from_le_bytes(
-                self.mmap[data_start + idx64 * 8..data_start + (idx64 + 1) * 8]
-                    .try_into()
-                    .ok()?,
-            ) >> shift;
-
-            if shift > 0 && idx64 + 1 < words_lower_bits as usize {
-                if data_start + (idx64 + 2) * 8 <= self.mmap.len() {
-                    let next_word = u64::from_le_bytes(
-                        self.mmap[data_start + (idx64 + 1) * 8..data_start + (idx64 + 2) * 8]
-                            .try_into()
-                            .ok()?,
-                    );
-                    lower |= next_word << (64 - shift);
-                }
-            }
-        }
+    // Get the data as u64 array (starting after count and u)
+    // The Go code treats this as little-endian uint64 array
+    let data_start = EliasFanoHeader::LEN;
 
-        // Get upper bits array start
-        let upper_start = data_start + (words_lower_bits as usize) * 8;
-        let jump_start = upper_start + (words_upper_bits as usize) * 8;
+    // Read lower bits - matching Go's get() function
+    let mut lower = 0u64;
+    if l > 0 {
+        let lower_bit_pos = index * l;
+        let idx64 = (lower_bit_pos / 64) as usize;
+        let shift = lower_bit_pos % 64;
 
-        // Use jump table to find starting position
-        let jump_super_q = (index / SUPER_Q) * SUPER_Q_SIZE;
-        let jump_inside_super_q = (index % SUPER_Q) / Q;
+        if data_start + (idx64 + 1) * 8 > ef.len() {
+            return None;
+        }
 
-        // Read jump values
-        let mut jump = 0u64;
-        if jump_words > 0 && jump_start + (jump_super_q as usize) * 8 <= self.mmap.len() {
-            jump = u64::from_le_bytes(
-                self.mmap[jump_start + (jump_super_q as usize) * 8
-                    ..jump_start + (jump_super_q as usize + 1) * 8]
+        lower = u64::from_le_bytes(
+            ef[data_start + idx64 * 8..data_start + (idx64 + 1) * 8]
+                .try_into()
+                .ok()?,
+        ) >> shift;
+
+        if shift > 0
+            && idx64 + 1 < words_lower_bits as usize
+            && data_start + (idx64 + 2) * 8 <= ef.len()
+        {
+            let next_word = u64::from_le_bytes(
+                ef[data_start + (idx64 + 1) * 8..data_start + (idx64 + 2) * 8]
                     .try_into()
                     .ok()?,
             );
+            lower |= next_word << (64 - shift);
+        }
+    }
 
-            // Add the inside-super-q offset
-            if jump_inside_super_q > 0 {
-                let idx64 = jump_super_q + 1 + (jump_inside_super_q >> 1);
-                let shift = 32 * (jump_inside_super_q % 2);
-                if jump_start + ((idx64 + 1) as usize) * 8 <= self.mmap.len() {
-                    let offset_word = u64::from_le_bytes(
-                        self.mmap[jump_start + (idx64 as usize) * 8
-                            ..jump_start + ((idx64 + 1) as usize) * 8]
-                            .try_into()
-                            .ok()?,
-                    );
-                    let mask = 0xffffffffu64 << shift;
-                    jump += (offset_word & mask) >> shift;
-                }
+    // Get upper bits array start
+    let upper_start = data_start + (words_lower_bits as usize) * 8;
+    let jump_start = upper_start + (words_upper_bits as usize) * 8;
+
+    // Use jump table to find starting position
+    let jump_super_q = (index / EF32_SUPER_Q) * EF32_SUPER_Q_SIZE;
+    let jump_inside_super_q = (index % EF32_SUPER_Q) / EF32_Q;
+
+    // Read jump values
+    let mut jump = 0u64;
+    if jump_words > 0 && jump_start + (jump_super_q as usize) * 8 <= ef.len() {
+        jump = u64::from_le_bytes(
+            ef[jump_start + (jump_super_q as usize) * 8
+                ..jump_start + (jump_super_q as usize + 1) * 8]
+                .try_into()
+                .ok()?,
+        );
+
+        // Add the inside-super-q offset
+        if jump_inside_super_q > 0 {
+            let idx64 = jump_super_q + 1 + (jump_inside_super_q >> 1);
+            let shift = 32 * (jump_inside_super_q % 2);
+            if jump_start + ((idx64 + 1) as usize) * 8 <= ef.len() {
+                let offset_word = u64::from_le_bytes(
+                    ef[jump_start + (idx64 as usize) * 8..jump_start + ((idx64 + 1) as usize) * 8]
+                        .try_into()
+                        .ok()?,
+                );
+                let mask = 0xffffffffu64 << shift;
+                jump += (offset_word & mask) >> shift;
             }
         }
+    }
 
-        // Find the correct position in upper bits
-        let mut curr_word = jump / 64;
-        let mut window = if upper_start + ((curr_word + 1) as usize) * 8 <= self.mmap.len() {
-            let word = u64::from_le_bytes(
-                self.mmap[upper_start + (curr_word as usize) * 8
-                    ..upper_start + ((curr_word + 1) as usize) * 8]
-                    .try_into()
-                    .ok()?,
-            );
-            word & (!0u64 << (jump % 64))
-        } else {
+    // Find the correct position in upper bits
+    let mut curr_word = jump / 64;
+    let mut window = if upper_start + ((curr_word + 1) as usize) * 8 <= ef.len() {
+        let word = u64::from_le_bytes(
+            ef[upper_start + (curr_word as usize) * 8
+                ..upper_start + ((curr_word + 1) as usize) * 8]
+                .try_into()
+                .ok()?,
+        );
+        word & (!0u64 << (jump % 64))
+    } else {
+        return None;
+    };
+
+    let mut d = (index & EF32_Q_MASK) as u32;
+
+    // Skip words until we have enough 1 bits
+    while window.count_ones() <= d {
+        d -= window.count_ones();
+        curr_word += 1;
+        if upper_start + ((curr_word + 1) as usize) * 8 > ef.len() {
             return None;
+        }
+        window = u64::from_le_bytes(
+            ef[upper_start + (curr_word as usize) * 8
+                ..upper_start + ((curr_word + 1) as usize) * 8]
+                .try_into()
+                .ok()?,
+        );
+    }
+
+    // Select the d-th 1 bit in the current window
+    let sel = select64(window, d as usize);
+
+    // Calculate final value - matching Go's formula
+    let val = ((curr_word * 64 + sel as u64 - index) << l) | (lower & lower_bits_mask);
+
+    Some(val)
+}
+
+/// Sequential reader of an eliasfano32 sequence
+///
+/// [`ef32_get`] finds each value through the jump table and a scan of the
+/// upper bits; this iterator locates its first value that way once and then
+/// follows the upper bits from one value to the next, so walking `n`
+/// consecutive values costs O(n) rather than `n` random decodes.
+// From Go: eliasfano32 EliasFanoIter
+#[derive(Clone)]
+pub struct EfIterator<'a> {
+    ef: &'a [u8],
+    l: u64,
+    lower_bits_mask: u64,
+    words_lower_bits: u64,
+    index: u64,
+    end: u64,
+    // Bit of the upper bits where the next value's search starts
+    bit: u64,
+}
+
+impl<'a> EfIterator<'a> {
+    /// Iterate over the values of the sequence at the start of `ef` from the
+    /// `from`-th on; `None` if `ef` is not a valid sequence or `from` is past
+    /// its end
+    pub fn new(ef: &'a [u8], from: u64) -> Option<Self> {
+        let EliasFanoHeader {
+            count: ef_count,
+            u: ef_u,
+        } = EliasFanoHeader::read(&mut SafeReader::new(ef)).ok()?;
+        let end = ef_count.checked_add(1)?;
+        if from > end {
+            return None;
+        }
+
+        // From Go: deriveFields, as in ef32_get
+        let ratio = ef_u / end;
+        let l = if ratio == 0 {
+            0
+        } else {
+            63 - ratio.leading_zeros() as u64
         };
+        let bit = if from < end {
+            (ef32_get(ef, from)? >> l) + from
+        } else {
+            0
+        };
+        Some(Self {
+            ef,
+            l,
+            lower_bits_mask: if l >= 64 { !0u64 } else { (1u64 << l) - 1 },
+            words_lower_bits: (end * l).div_ceil(64) + 1,
+            index: from,
+            end,
+            bit,
+        })
+    }
 
-        let mut d = (index & Q_MASK) as u32;
+    fn word(&self, i: u64) -> Option<u64> {
+        let start = EliasFanoHeader::LEN + usize::try_from(i).ok()?.checked_mul(8)?;
+        Some(u64::from_le_bytes(
+            self.ef.get(start..start.checked_add(8)?)?.try_into().ok()?,
+        ))
+    }
 
-        // Skip words until we have enough 1 bits
-        while window.count_ones() <= d {
-            d -= window.count_ones();
+    fn decode_next(&mut self) -> Option<u64> {
+        let mut curr_word = self.bit / 64;
+        let mut window = self.word(self.words_lower_bits + curr_word)? & (!0u64 << (self.bit % 64));
+        while window == 0 {
             curr_word += 1;
-            if upper_start + ((curr_word + 1) as usize) * 8 > self.mmap.len() {
-                return None;
+            window = self.word(self.words_lower_bits + curr_word)?;
+        }
+        let one = curr_word * 64 + window.trailing_zeros() as u64;
+
+        let mut lower = 0u64;
+        if self.l > 0 {
+            let lower_bit_pos = self.index * self.l;
+            let shift = lower_bit_pos % 64;
+            lower = self.word(lower_bit_pos / 64)? >> shift;
+            if shift > 0 && shift + self.l > 64 {
+                lower |= self.word(lower_bit_pos / 64 + 1)? << (64 - shift);
             }
-            window = u64::from_le_bytes(
-                self.mmap[upper_start + (curr_word as usize) * 8
-                    ..upper_start + ((curr_word + 1) as usize) * 8]
-                    .try_into()
-                    .ok()?,
-            );
         }
+        let value = ((one - self.index) << self.l) | (lower & self.lower_bits_mask);
+        self.bit = one + 1;
+        self.index += 1;
+        Some(value)
+    }
+}
+
+impl Iterator for EfIterator<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.index >= self.end {
+            return None;
+        }
+        let value = self.decode_next();
+        if value.is_none() {
+            // Truncated data: stop for good rather than skip ahead
+            self.index = self.end;
+        }
+        value
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, usize::try_from(self.end - self.index).ok())
+    }
+}
+
+/// Serialized size in bytes of the eliasfano32 sequence at the start of
+/// `ef`; `usize::MAX` if `ef` is shorter than its header or the header
+/// declares more elements than `ef` could possibly hold
+// From Go: eliasfano32 ReadEliasFano + deriveFields
+pub(crate) fn ef32_size(ef: &[u8]) -> usize {
+    let Ok(EliasFanoHeader {
+        count: ef_count,
+        u: ef_u,
+    }) = EliasFanoHeader::read(&mut SafeReader::new(ef))
+    else {
+        return usize::MAX;
+    };
+    // The upper bits alone take a bit per element, also keeps the sums
+    // below from overflowing on garbage headers
+    if ef_count >= ef.len() as u64 * 8 {
+        return usize::MAX;
+    }
+    let ratio = ef_u / (ef_count + 1);
+    let l = if ratio == 0 {
+        0
+    } else {
+        63 - ratio.leading_zeros() as u64
+    };
+    let words_lower_bits = ((ef_count + 1) * l).div_ceil(64) + 1;
+    let words_upper_bits = (ef_count + 1 + (ef_u >> l)).div_ceil(64);
+    let total_words = words_lower_bits + words_upper_bits + ef32_jump_size_words(ef_count);
+    // Count and u, then the u64 words
+    total_words
+        .checked_mul(8)
+        .and_then(|words| words.checked_add(EliasFanoHeader::LEN as u64))
+        .and_then(|size| usize::try_from(size).ok())
+        .unwrap_or(usize::MAX)
+}
+
+// From Go: eliasfano32 jumpSizeWords, for an EF whose stored count is
+// `ef_count` (number of elements minus one)
+fn ef32_jump_size_words(ef_count: u64) -> u64 {
+    let count = ef_count + 1;
+    let mut size = (count / EF32_SUPER_Q) * EF32_SUPER_Q_SIZE; // Whole blocks
+    if !count.is_multiple_of(EF32_SUPER_Q) {
+        size += 1 + ((count % EF32_SUPER_Q).div_ceil(EF32_Q) + 3) / 2; // Partial block
+    }
+    size
+}
+
+// From Go: eliasfano32 NewEliasFano + AddOffset + Build + Write
+// Serializes a monotone sequence in the layout `ef32_get` reads
+pub(crate) fn build_elias_fano32(offsets: &[u64], max_offset: u64) -> Vec<u8> {
+    let ef_count = offsets.len() as u64 - 1;
+    let ef_u = max_offset + 1;
+
+    // From Go: deriveFields
+    let ratio = ef_u / (ef_count + 1);
+    let l = if ratio == 0 {
+        0
+    } else {
+        63 - ratio.leading_zeros() as u64
+    };
+    let lower_bits_mask = (1u64 << l) - 1;
+    let words_lower_bits = (((ef_count + 1) * l).div_ceil(64) + 1) as usize;
+    let words_upper_bits = (ef_count + 1 + (ef_u >> l)).div_ceil(64) as usize;
+    let jump_words = ef32_jump_size_words(ef_count) as usize;
+
+    let mut lower_bits = vec![0u64; words_lower_bits];
+    let mut upper_bits = vec![0u64; words_upper_bits];
+    let mut jump = vec![0u64; jump_words];
+
+    for (i, &offset) in offsets.iter().enumerate() {
+        if l != 0 {
+            set_bits(&mut lower_bits, i as u64 * l, l, offset & lower_bits_mask);
+        }
+        set(&mut upper_bits, (offset >> l) + i as u64);
+    }
 
-        // Select the d-th 1 bit in the current window
-        let sel = self.select64(window, d as usize);
+    // Jump table: 64-bit position of every SUPER_Q-th one, then 32-bit
+    // offsets from it for every Q-th one
+    let mut c = 0u64;
+    let mut last_super_q = 0u64;
+    for (i, &word) in upper_bits.iter().enumerate() {
+        for b in 0..64u64 {
+            if word & (1u64 << b) == 0 {
+                continue;
+            }
+            let bit = i as u64 * 64 + b;
+            if c & (EF32_SUPER_Q - 1) == 0 {
+                last_super_q = bit;
+                jump[((c / EF32_SUPER_Q) * EF32_SUPER_Q_SIZE) as usize] = last_super_q;
+            }
+            if c & EF32_Q_MASK == 0 {
+                let offset = bit - last_super_q;
+                let jump_super_q = (c / EF32_SUPER_Q) * EF32_SUPER_Q_SIZE;
+                let jump_inside_super_q = (c % EF32_SUPER_Q) / EF32_Q;
+                let idx64 = (jump_super_q + 1 + (jump_inside_super_q >> 1)) as usize;
+                let shift = 32 * (jump_inside_super_q % 2);
+                let mask = 0xffffffffu64 << shift;
+                jump[idx64] = (jump[idx64] & !mask) | (offset << shift);
+            }
+            c += 1;
+        }
+    }
 
-        // Calculate final value - matching Go's formula
-        let val = ((curr_word * 64 + sel as u64 - index) << l) | (lower & lower_bits_mask);
+    let mut out = Vec::with_capacity(
+        EliasFanoHeader::LEN + 8 * (words_lower_bits + words_upper_bits + jump_words),
+    );
+    let header = EliasFanoHeader {
+        count: ef_count,
+        u: ef_u,
+    };
+    out.extend_from_slice(&header.to_bytes());
+    for word in lower_bits.iter().chain(&upper_bits).chain(&jump) {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+/// A random RecSplit salt, for when none is configured
+pub(crate) fn random_salt() -> u32 {
+    // RandomState is seeded from the OS on creation
+    RandomState::new().build_hasher().finish() as u32
+}
 
-        Some(val)
+// From Go: recsplit.go:43 MaxLeafSize
+const MAX_LEAF_SIZE: u16 = 24;
+
+// From Go: recsplit.go:149 DefaultLeafSize / DefaultBucketSize
+pub const DEFAULT_LEAF_SIZE: u16 = 8;
+pub const DEFAULT_BUCKET_SIZE: usize = 100;
+
+// From Go: recsplit.go:159 - one seed per level of recursive split
+const DEFAULT_START_SEED: [u64; 20] = [
+    0x106393c187cae21a,
+    0x6453cec3f7376937,
+    0x643e521ddbd2be98,
+    0x3740c6412f6572cb,
+    0x717d47562f1ce470,
+    0x4cd6eb4c63befb7c,
+    0x9bfd8c5e18c8da73,
+    0x082f20e10092a9a3,
+    0x2ada2ce68d21defc,
+    0xe33cb4f3e7c6466b,
+    0x3980be458c509c59,
+    0xc466fd9584828e8c,
+    0x45f0aabe1a61ede6,
+    0xf6e7b8b33ad9b98d,
+    0x4ef95e25f4b4983d,
+    0x81175195173b92d3,
+    0x4e50927d8dd15978,
+    0x1ea2099d1fafae7f,
+    0x425c8a06fbaaa815,
+    0xcd4216006c74052a,
+];
+
+/// Configuration for building a RecSplit index, see [`RecSplit`]
+///
+/// Mirrors Go's `RecSplitArgs`. When no salt is given a random one is
+/// picked, like Erigon does, so that different nodes end up with different
+/// hash functions.
+pub struct RecSplitBuilder {
+    index_file: PathBuf,
+    key_count: usize,
+    bucket_size: usize,
+    leaf_size: u16,
+    base_data_id: u64,
+    salt: Option<u32>,
+    start_seed: Vec<u64>,
+    hasher: &'static dyn KeyHasher,
+    enums: bool,
+    less_false_positives: bool,
+    fsync: bool,
+}
+
+impl RecSplitBuilder {
+    /// Start configuring an index over exactly `key_count` keys
+    pub fn new(index_file: impl Into<PathBuf>, key_count: usize) -> Self {
+        Self {
+            index_file: index_file.into(),
+            key_count,
+            bucket_size: DEFAULT_BUCKET_SIZE,
+            leaf_size: DEFAULT_LEAF_SIZE,
+            base_data_id: 0,
+            salt: None,
+            start_seed: DEFAULT_START_SEED.to_vec(),
+            hasher: &Murmur3,
+            enums: false,
+            less_false_positives: false,
+            fsync: true,
+        }
+    }
+
+    /// Average number of keys per bucket
+    pub fn bucket_size(mut self, bucket_size: usize) -> Self {
+        self.bucket_size = bucket_size;
+        self
+    }
+
+    /// Maximum number of keys in a leaf of the splitting tree
+    pub fn leaf_size(mut self, leaf_size: u16) -> Self {
+        self.leaf_size = leaf_size;
+        self
     }
 
-    /// Select the n-th set bit in a u64 (0-indexed)
-    fn select64(&self, word: u64, n: usize) -> usize {
-        let mut remaining = n;
-        let mut word = word;
+    /// Application-specific id of the first entry (the first block number
+    /// for block snapshots)
+    pub fn base_data_id(mut self, base_data_id: u64) -> Self {
+        self.base_data_id = base_data_id;
+        self
+    }
+
+    /// Fix the salt instead of picking a random one
+    pub fn salt(mut self, salt: u32) -> Self {
+        self.salt = Some(salt);
+        self
+    }
+
+    /// Hash keys with `hasher` instead of [`Murmur3`]; readers pick the
+    /// same one from the index trailer
+    ///
+    /// Erigon would look such an index up with murmur3, so it must not be
+    /// named like Erigon's files (`v1-…`): [`RecSplitBuilder::build`]
+    /// refuses it.
+    pub fn hasher(mut self, hasher: &'static dyn KeyHasher) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    /// Per-level seeds of the recursive split
+    pub fn start_seed(mut self, start_seed: Vec<u64>) -> Self {
+        self.start_seed = start_seed;
+        self
+    }
 
-        for i in 0..64 {
-            if word & 1 != 0 {
-                if remaining == 0 {
-                    return i;
+    /// Build a two-level index: the hash maps keys to their ordinal and an
+    /// Elias-Fano sequence maps ordinals to offsets. Offsets must then be
+    /// added in non-decreasing order.
+    pub fn enums(mut self, enums: bool) -> Self {
+        self.enums = enums;
+        self
+    }
+
+    /// Store one byte of every key hash to reject most unknown keys; only
+    /// has an effect together with [`RecSplitBuilder::enums`]
+    pub fn less_false_positives(mut self, less_false_positives: bool) -> Self {
+        self.less_false_positives = less_false_positives;
+        self
+    }
+
+    /// Whether to fsync the index before moving it into place
+    pub fn fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Validate the configuration and start accepting keys
+    pub fn build(self) -> Result<RecSplit> {
+        if self.leaf_size == 0 || self.leaf_size > MAX_LEAF_SIZE {
+            return Err(IndexError::InvalidParameters(format!(
+                "Leaf size must be between 1 and {}, got {}",
+                MAX_LEAF_SIZE, self.leaf_size
+            ))
+            .into());
+        }
+        if self.bucket_size == 0 || self.bucket_size > u16::MAX as usize {
+            return Err(IndexError::InvalidParameters(format!(
+                "Bucket size must be between 1 and {}, got {}",
+                u16::MAX,
+                self.bucket_size
+            ))
+            .into());
+        }
+        if self.start_seed.is_empty() || self.start_seed.len() > u8::MAX as usize {
+            return Err(IndexError::InvalidParameters(format!(
+                "Invalid number of start seeds: {}",
+                self.start_seed.len()
+            ))
+            .into());
+        }
+        let erigon_named = self
+            .index_file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(NameVersion::from_file_name)
+            .is_some();
+        if erigon_named && self.hasher.id() != Murmur3.id() {
+            return Err(IndexError::InvalidParameters(format!(
+                "{:?} indexes can't be named like Erigon's, which hashes with murmur3: {}",
+                self.hasher,
+                self.index_file.display()
+            ))
+            .into());
+        }
+        let (primary_aggr_bound, secondary_aggr_bound) = aggr_bounds(self.leaf_size);
+        let salt = self.salt.unwrap_or_else(random_salt);
+        Ok(RecSplit {
+            bucket_count: self.key_count.div_ceil(self.bucket_size) as u64,
+            cfg: self,
+            salt,
+            primary_aggr_bound,
+            secondary_aggr_bound,
+            keys: Vec::new(),
+            offsets: Vec::new(),
+            existence: Vec::new(),
+            max_offset: 0,
+            golomb_rice: Vec::new(),
+            gr: GolombRiceBuilder::default(),
+            records: Vec::new(),
+            bytes_per_rec: 0,
+        })
+    }
+}
+
+/// RecSplit minimal perfect hash builder, the write side of [`RecSplitIndex`]
+///
+/// Unlike Erigon, which spills keys to disk through ETL collectors, keys are
+/// kept in memory (24 bytes each) until [`RecSplit::build`].
+pub struct RecSplit {
+    cfg: RecSplitBuilder,
+    salt: u32,
+    bucket_count: u64,
+    primary_aggr_bound: u16,
+    secondary_aggr_bound: u16,
+    // (bucket, fingerprint, value) per key; the value is the ordinal for enum
+    // indexes and the offset otherwise
+    keys: Vec<(u64, u64, u64)>,
+    offsets: Vec<u64>,
+    existence: Vec<u8>,
+    max_offset: u64,
+    golomb_rice: Vec<u32>,
+    gr: GolombRiceBuilder,
+    records: Vec<u8>,
+    bytes_per_rec: usize,
+}
+
+impl RecSplit {
+    pub fn builder(index_file: impl Into<PathBuf>, key_count: usize) -> RecSplitBuilder {
+        RecSplitBuilder::new(index_file, key_count)
+    }
+
+    /// The salt keys are hashed with
+    pub fn salt(&self) -> u32 {
+        self.salt
+    }
+
+    /// Number of keys added so far
+    pub fn keys_added(&self) -> usize {
+        self.keys.len()
+    }
+
+    // From Go: recsplit.go:358 AddKey
+    pub fn add_key(&mut self, key: &[u8], offset: u64) -> Result<()> {
+        let (hi, lo) = self.cfg.hasher.hash_key(key, self.salt);
+        let bucket = remap(hi, self.bucket_count);
+        if self.cfg.enums {
+            if self.offsets.last().is_some_and(|&prev| offset < prev) {
+                return Err(IndexError::DecreasingOffset {
+                    offset,
+                    previous: self.offsets[self.offsets.len() - 1],
                 }
-                remaining -= 1;
+                .into());
             }
-            word >>= 1;
+            self.offsets.push(offset);
+            self.keys.push((bucket, lo, self.keys.len() as u64));
+            if self.cfg.less_false_positives {
+                // 1 byte from each hashed key
+                self.existence.push(hi as u8);
+            }
+        } else {
+            self.keys.push((bucket, lo, offset));
         }
+        self.max_offset = self.max_offset.max(offset);
+        Ok(())
+    }
 
-        63
+    // From Go: recsplit.go:340 golombParam - grows the table on demand
+    fn golomb_param(&mut self, m: u16) -> Result<usize> {
+        let mut s = self.golomb_rice.len() as u16;
+        while m >= s {
+            self.golomb_rice.push(0);
+            if s == 0 {
+                self.golomb_rice[0] = (BIJ_MEMO[0] << 27) | BIJ_MEMO[0];
+            } else if s <= self.cfg.leaf_size {
+                self.golomb_rice[s as usize] =
+                    (BIJ_MEMO[s as usize] << 27) | (1u32 << 16) | BIJ_MEMO[s as usize];
+            } else {
+                compute_golomb_rice(
+                    s,
+                    &mut self.golomb_rice,
+                    self.cfg.leaf_size,
+                    self.primary_aggr_bound,
+                    self.secondary_aggr_bound,
+                )?;
+            }
+            s += 1;
+        }
+        Ok((self.golomb_rice[m as usize] >> 27) as usize)
     }
 
-    /// Hash-based lookup (more complex, not implemented yet)
-    pub fn lookup(&self, key: &[u8]) -> Option<u64> {
-        if self.key_count == 0 {
-            return None;
+    fn write_record(&mut self, value: u64) {
+        self.records
+            .extend_from_slice(&value.to_be_bytes()[8 - self.bytes_per_rec..]);
+    }
+
+    // From Go: recsplit.go:462 recsplit
+    // Finds the seeds splitting `bucket` (fingerprints) down to bijections
+    // on the leaves, writing records in leaf order and the seeds' fixed
+    // parts to the Golomb-Rice stream; unary parts are collected in `unary`
+    fn recsplit(
+        &mut self,
+        level: usize,
+        bucket: &mut [u64],
+        values: &mut [u64],
+        unary: &mut Vec<u64>,
+    ) -> Result<()> {
+        let level_seed = *self
+            .cfg
+            .start_seed
+            .get(level)
+            .ok_or(IndexError::MissingStartSeed { level })?;
+        let mut salt = level_seed;
+        let m = bucket.len() as u16;
+        if m <= self.cfg.leaf_size {
+            // No need to build aggregation levels - just find bijection
+            loop {
+                let mut mask = 0u32;
+                let fail = bucket.iter().any(|&fp| {
+                    let bit = 1u32 << remap16(remix(fp.wrapping_add(salt)), m);
+                    let taken = mask & bit != 0;
+                    mask |= bit;
+                    taken
+                });
+                if !fail {
+                    break;
+                }
+                salt = salt.wrapping_add(1);
+            }
+            let mut slots = vec![0u64; m as usize];
+            for (&fp, &value) in bucket.iter().zip(values.iter()) {
+                slots[remap16(remix(fp.wrapping_add(salt)), m) as usize] = value;
+            }
+            for value in slots {
+                self.write_record(value);
+            }
+            salt = salt.wrapping_sub(level_seed);
+            let log2golomb = self.golomb_param(m)?;
+            self.gr.append_fixed(salt, log2golomb);
+            unary.push(salt >> log2golomb);
+        } else {
+            let (fanout, unit) = split_params(
+                m,
+                self.cfg.leaf_size,
+                self.primary_aggr_bound,
+                self.secondary_aggr_bound,
+            );
+            let mut count = vec![0u16; fanout as usize];
+            loop {
+                count.fill(0);
+                for &fp in bucket.iter() {
+                    count[(remap16(remix(fp.wrapping_add(salt)), m) / unit) as usize] += 1;
+                }
+                if count[..fanout as usize - 1].iter().all(|&c| c == unit) {
+                    break;
+                }
+                salt = salt.wrapping_add(1);
+            }
+            // Stable partition of the keys into the fanout parts
+            for (i, c) in count.iter_mut().enumerate() {
+                *c = i as u16 * unit;
+            }
+            let mut split_bucket = vec![0u64; m as usize];
+            let mut split_values = vec![0u64; m as usize];
+            for (&fp, &value) in bucket.iter().zip(values.iter()) {
+                let j = (remap16(remix(fp.wrapping_add(salt)), m) / unit) as usize;
+                split_bucket[count[j] as usize] = fp;
+                split_values[count[j] as usize] = value;
+                count[j] += 1;
+            }
+            bucket.copy_from_slice(&split_bucket);
+            values.copy_from_slice(&split_values);
+
+            salt = salt.wrapping_sub(level_seed);
+            let log2golomb = self.golomb_param(m)?;
+            self.gr.append_fixed(salt, log2golomb);
+            unary.push(salt >> log2golomb);
+
+            let mut i = 0u16;
+            while i < m - unit {
+                let range = i as usize..(i + unit) as usize;
+                self.recsplit(
+                    level + 1,
+                    &mut bucket[range.clone()],
+                    &mut values[range],
+                    unary,
+                )?;
+                i += unit;
+            }
+            if m - i > 1 {
+                self.recsplit(
+                    level + 1,
+                    &mut bucket[i as usize..],
+                    &mut values[i as usize..],
+                    unary,
+                )?;
+            } else if m - i == 1 {
+                self.write_record(values[i as usize]);
+            }
         }
+        Ok(())
+    }
 
-        if self.key_count == 1 {
-            return Some(0);
+    /// Build the perfect hash function and write the index file
+    ///
+    /// The index is written to a `.tmp` file first and renamed into place
+    /// once complete. Fails with [`SnapshotError::Collision`] if two keys
+    /// share a 64-bit fingerprint; rebuild with a different salt then.
+    // From Go: recsplit.go:589 Build
+    pub fn build(mut self) -> Result<()> {
+        let keys_added = self.keys.len() as u64;
+        if keys_added != self.cfg.key_count as u64 {
+            return Err(IndexError::KeyCountMismatch {
+                file: self.cfg.index_file.display().to_string(),
+                expected: self.cfg.key_count as u64,
+                actual: keys_added,
+            }
+            .into());
         }
+        let bit_len = |v: u64| (64 - v.leading_zeros()) as usize;
+        self.bytes_per_rec = if self.cfg.enums {
+            bit_len(keys_added + 1).div_ceil(8)
+        } else {
+            bit_len(self.max_offset).div_ceil(8)
+        };
 
-        // Calculate hash using murmur3 with salt
-        let mut cursor = Cursor::new(key);
-        let hash128 = murmur3::murmur3_x64_128(&mut cursor, self.salt).ok()?;
+        let mut keys = std::mem::take(&mut self.keys);
+        keys.sort_unstable();
+        let mut bucket_size_acc = vec![0u64];
+        let mut bucket_pos_acc = vec![0u64];
+        let mut start = 0;
+        while start < keys.len() {
+            let bucket_idx = keys[start].0;
+            let end = start + keys[start..].partition_point(|k| k.0 == bucket_idx);
+            let mut bucket: Vec<u64> = keys[start..end].iter().map(|k| k.1).collect();
+            let mut values: Vec<u64> = keys[start..end].iter().map(|k| k.2).collect();
+            start = end;
+
+            // From Go: recsplit.go:410 recsplitCurrentBucket
+            let idx = bucket_idx as usize;
+            while bucket_size_acc.len() <= idx + 1 {
+                bucket_size_acc.push(bucket_size_acc[bucket_size_acc.len() - 1]);
+            }
+            bucket_size_acc[idx + 1] += bucket.len() as u64;
+            // Sets of size 0 and 1 are not further processed, just write them to index
+            if bucket.len() > 1 {
+                if let Some(w) = bucket.windows(2).find(|w| w[0] == w[1]) {
+                    return Err(SnapshotError::Collision(w[0]));
+                }
+                let mut unary = Vec::new();
+                self.recsplit(0, &mut bucket, &mut values, &mut unary)?;
+                self.gr.append_unary_all(&unary);
+            } else {
+                for value in values {
+                    self.write_record(value);
+                }
+            }
+            while bucket_pos_acc.len() <= idx + 1 {
+                bucket_pos_acc.push(bucket_pos_acc[bucket_pos_acc.len() - 1]);
+            }
+            bucket_pos_acc[idx + 1] = self.gr.bits() as u64;
+        }
+
+        // Sentinel (avoids checking for parts of size 1)
+        self.gr.append_fixed(1, 1);
+        let double_ef = build_double_elias_fano(&bucket_size_acc, &bucket_pos_acc);
 
-        // Split the 128-bit hash into two 64-bit parts
-        let bucket_hash = (hash128 >> 64) as u64;
-        let fingerprint = hash128 as u64;
+        let mut out = Vec::with_capacity(self.records.len() + double_ef.len() + 256);
+        let header = IndexHeader {
+            base_data_id: self.cfg.base_data_id,
+            key_count: keys_added,
+            bytes_per_rec: self.bytes_per_rec as u8,
+        };
+        out.extend_from_slice(&header.to_bytes());
+        out.extend_from_slice(&self.records);
+        let mut features = Features::NONE.0;
+        if self.cfg.enums {
+            features |= Features::ENUMS.0;
+            if self.cfg.less_false_positives {
+                features |= Features::LESS_FALSE_POSITIVES.0;
+            }
+        }
+        IndexParams {
+            bucket_count: self.bucket_count,
+            bucket_size: self.cfg.bucket_size as u16,
+            leaf_size: self.cfg.leaf_size,
+            salt: self.salt,
+            start_seed: self.cfg.start_seed.clone(),
+            features: Features(features),
+        }
+        .write(&mut out);
+        if self.cfg.enums && keys_added > 0 {
+            out.extend_from_slice(&build_elias_fano32(&self.offsets, self.max_offset));
+            if self.cfg.less_false_positives {
+                out.extend_from_slice(&keys_added.to_be_bytes());
+                out.extend_from_slice(&self.existence);
+            }
+        }
+        // Only the size of the Golomb-Rice parameter table is stored, as a
+        // u16 in a 4-byte slot
+        out.extend_from_slice(&(self.golomb_rice.len() as u16).to_be_bytes());
+        out.extend_from_slice(&[0, 0]);
+        self.gr.write_to(&mut out);
+        out.extend_from_slice(&double_ef);
+        if self.cfg.hasher.id() != Murmur3.id() {
+            out.extend_from_slice(&KEY_HASHER_MAGIC);
+            out.push(self.cfg.hasher.id());
+        }
 
-        // This would require implementing the full RecSplit lookup algorithm
-        // with Golomb-Rice decoding, which is quite complex
-        // For now, return None
-        None
+        // Other readers must only ever see complete files: write to .tmp,
+        // fsync, then rename
+        let mut tmp_path = self.cfg.index_file.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&out)?;
+        if self.cfg.fsync {
+            file.sync_all()?;
+        }
+        drop(file);
+        fs::rename(&tmp_path, &self.cfg.index_file)?;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ReadError;
 
     #[test]
     fn test_features() {
@@ -523,4 +1725,373 @@ mod tests {
         assert!(combined.contains(Features::ENUMS));
         assert!(combined.contains(Features::LESS_FALSE_POSITIVES));
     }
+
+    #[test]
+    fn test_index_header_round_trip() {
+        let header = IndexHeader {
+            base_data_id: 1000,
+            key_count: 0x0102,
+            bytes_per_rec: 3,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(bytes[..8], 1000u64.to_be_bytes());
+        assert_eq!(bytes[8..], [0, 0, 0, 0, 0, 0, 1, 2, 3]);
+        assert_eq!(
+            IndexHeader::read(&mut SafeReader::new(&bytes)).unwrap(),
+            header
+        );
+
+        let params = IndexParams {
+            bucket_count: 7,
+            bucket_size: 2000,
+            leaf_size: 8,
+            salt: 0xdead_beef,
+            start_seed: vec![1, u64::MAX],
+            features: Features::ENUMS,
+        };
+        let mut bytes = Vec::new();
+        params.write(&mut bytes);
+        assert_eq!(bytes.len(), 8 + 2 + 2 + 4 + 1 + 2 * 8 + 1);
+        assert_eq!(bytes[12..16], [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(bytes[16], 2);
+        let mut reader = SafeReader::new(&bytes);
+        assert_eq!(IndexParams::read(&mut reader).unwrap(), params);
+        assert!(reader.is_empty());
+        assert!(matches!(
+            IndexParams::read(&mut SafeReader::new(&bytes[..20])),
+            Err(ReadError::OutOfBounds {
+                what: "start seed",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_elias_fano_header_round_trip() {
+        let ef = build_elias_fano32(&[3, 5, 9], 9);
+        let header = EliasFanoHeader::read(&mut SafeReader::new(&ef)).unwrap();
+        assert_eq!(header, EliasFanoHeader { count: 2, u: 10 });
+        assert_eq!(ef[..EliasFanoHeader::LEN], header.to_bytes());
+        assert_eq!(ef32_size(&ef), ef.len());
+        // Too short for the header
+        assert_eq!(ef32_size(&ef[..10]), usize::MAX);
+        assert_eq!(ef32_get(&ef[..10], 0), None);
+    }
+
+    #[test]
+    fn test_hash_key_matches_go_murmur3() {
+        // murmur3.Sum128WithSeed([]byte("hello"), 0) in Go
+        let (h1, h2) = hash_key(b"hello", 0);
+        assert_eq!(h1, 0xcbd8a7b341bd9b02);
+        assert_eq!(h2, 0x5b1e906a48ae1d19);
+    }
+
+    #[test]
+    fn test_xxhash64_index_round_trip() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let keys: Vec<Vec<u8>> = (0..2000u32)
+            .map(|i| format!("key {}", i).into_bytes())
+            .collect();
+        let offsets: Vec<u64> = (0..keys.len() as u64).map(|i| i * 11).collect();
+
+        let path = tmp_dir.path().join("xxhash.idx");
+        let builder = RecSplit::builder(&path, keys.len())
+            .enums(true)
+            .less_false_positives(true)
+            .hasher(&XxHash64)
+            .salt(7);
+        let idx = build_and_open(builder, &keys, &offsets);
+        assert_eq!(idx.features.0 & !Features::ALL.0, 0);
+        assert_eq!(idx.hasher().id(), XxHash64.id());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(idx.lookup(key), Some(i as u64), "key {}", i);
+        }
+
+        // The same keys hash elsewhere with murmur3, which is the default
+        let path = tmp_dir.path().join("murmur3.idx");
+        let builder = RecSplit::builder(&path, keys.len()).enums(true).salt(7);
+        let idx = build_and_open(builder, &keys, &offsets);
+        assert_eq!(idx.hasher().id(), Murmur3.id());
+        assert_ne!(
+            XxHash64.hash_key(&keys[0], 7),
+            Murmur3.hash_key(&keys[0], 7)
+        );
+        let (bucket_hash, fingerprint) = XxHash64.hash_key(&keys[0], 7);
+        assert_ne!(bucket_hash, fingerprint);
+    }
+
+    #[test]
+    fn test_hasher_kept_out_of_erigon_files() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let keys: Vec<Vec<u8>> = (0..100u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let offsets: Vec<u64> = (0..keys.len() as u64).collect();
+
+        // Erigon would look it up with murmur3
+        let erigon_path = tmp_dir.path().join("v1-000000-000500-headers.idx");
+        assert!(matches!(
+            RecSplit::builder(&erigon_path, keys.len())
+                .hasher(&XxHash64)
+                .build(),
+            Err(SnapshotError::Index(IndexError::InvalidParameters(_)))
+        ));
+
+        // The features byte stays within Erigon's bits, the hasher is in
+        // the trailer
+        let path = tmp_dir.path().join("xxhash.idx");
+        let builder = RecSplit::builder(&path, keys.len()).hasher(&XxHash64);
+        let idx = build_and_open(builder, &keys, &offsets);
+        assert_eq!(idx.features, Features::NONE);
+        drop(idx);
+        let mut data = fs::read(&path).unwrap();
+        assert!(data[..data.len() - 1].ends_with(&KEY_HASHER_MAGIC));
+        *data.last_mut().unwrap() = 0xff;
+        fs::write(&path, &data).unwrap();
+        assert!(matches!(
+            RecSplitIndex::open(&path),
+            Err(SnapshotError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_aggr_bounds() {
+        // Values for Erigon's default leaf size of 8
+        assert_eq!(aggr_bounds(8), (32, 96));
+        // Small leaves use a fixed factor of two for the secondary bound
+        assert_eq!(aggr_bounds(4), (8, 16));
+    }
+
+    #[test]
+    fn test_golomb_rice_table_leaves() {
+        let (primary, secondary) = aggr_bounds(8);
+        let table = golomb_rice_table(primary as usize + 1, 8, primary, secondary).unwrap();
+        for m in 1..=8 {
+            // Leaves: Golomb parameter and code length both come from BIJ_MEMO
+            assert_eq!(table[m] >> 27, BIJ_MEMO[m]);
+            assert_eq!(table[m] & 0xffff, BIJ_MEMO[m]);
+            assert_eq!((table[m] >> 16) & 0x7ff, 1);
+        }
+        // A node above the leaves: one node for itself plus one per leaf
+        let nodes = (table[20] >> 16) & 0x7ff;
+        assert_eq!(nodes, 1 + 3);
+    }
+
+    #[test]
+    fn test_ef32_jump_size_words() {
+        // One partial block: 1 word for the super-jump + packed 32-bit jumps
+        assert_eq!(ef32_jump_size_words(0), 1 + 2);
+        // Exactly one full block
+        assert_eq!(ef32_jump_size_words(EF32_SUPER_Q - 1), EF32_SUPER_Q_SIZE);
+        assert_eq!(
+            ef32_jump_size_words(EF32_SUPER_Q),
+            EF32_SUPER_Q_SIZE + 1 + 2
+        );
+    }
+
+    fn build_and_open(
+        builder: RecSplitBuilder,
+        keys: &[Vec<u8>],
+        offsets: &[u64],
+    ) -> RecSplitIndex {
+        let path = builder.index_file.clone();
+        let mut rs = builder.fsync(false).build().unwrap();
+        for (key, &offset) in keys.iter().zip(offsets) {
+            rs.add_key(key, offset).unwrap();
+        }
+        rs.build().unwrap();
+        RecSplitIndex::open(&path).unwrap()
+    }
+
+    #[test]
+    fn test_build_enum_index_round_trip() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("enums.idx");
+        // Enough keys for several buckets, with some large enough to need
+        // both aggregation levels
+        let keys: Vec<Vec<u8>> = (0..3000u32)
+            .map(|i| format!("key {}", i).into_bytes())
+            .collect();
+        let offsets: Vec<u64> = (0..keys.len() as u64).map(|i| i * 37 + i % 5).collect();
+
+        let builder = RecSplit::builder(&path, keys.len())
+            .enums(true)
+            .less_false_positives(true)
+            .base_data_id(500_000)
+            .salt(1)
+            .bucket_size(1000);
+        let idx = build_and_open(builder, &keys, &offsets);
+
+        assert!(idx.is_enum());
+        assert_eq!(idx.key_count(), keys.len() as u64);
+        assert_eq!(idx.base_data_id(), 500_000);
+        assert_eq!(idx.bucket_count(), 3);
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(idx.lookup(key), Some(i as u64), "key {}", i);
+            assert_eq!(idx.ordinal_lookup(i as u64), Some(offsets[i]));
+        }
+        // The existence filter rejects most keys that were never added
+        let false_positives = (0..1000u32)
+            .filter(|i| idx.lookup(format!("missing {}", i).as_bytes()).is_some())
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+
+        // Data ids count from base_data_id
+        assert_eq!(idx.lookup_by_data_id(500_000), Some(offsets[0]));
+        assert_eq!(idx.lookup_by_data_id(502_999), Some(offsets[2999]));
+        assert_eq!(idx.lookup_by_data_id(499_999), None);
+        assert_eq!(idx.lookup_by_data_id(503_000), None);
+        assert_eq!(
+            idx.offsets_range(500_000, 503_000).as_deref(),
+            Some(&offsets[..])
+        );
+        assert_eq!(
+            idx.offsets_range(501_234, 502_345).as_deref(),
+            Some(&offsets[1234..2345])
+        );
+        assert_eq!(idx.offsets_range(500_010, 500_010), Some(Vec::new()));
+        assert_eq!(idx.offsets_range(499_999, 500_010), None);
+        assert_eq!(idx.offsets_range(502_990, 503_001), None);
+        assert!(idx.offsets(0).unwrap().eq(offsets.iter().copied()));
+        assert!(idx
+            .offsets(2500)
+            .unwrap()
+            .eq(offsets[2500..].iter().copied()));
+    }
+
+    #[test]
+    fn test_ef_iterator_matches_get() {
+        // Uneven gaps, across several super-jump blocks
+        let mut values = Vec::new();
+        let mut v = 0u64;
+        for i in 0..40_000u64 {
+            v += (i * 7919) % 1000;
+            values.push(v);
+        }
+        let ef = build_elias_fano32(&values, v);
+        for from in [0, 1, 255, 16_383, 16_384, 39_999, 40_000] {
+            let decoded: Vec<u64> = EfIterator::new(&ef, from).unwrap().collect();
+            assert_eq!(decoded, &values[from as usize..], "from {}", from);
+        }
+        assert!(EfIterator::new(&ef, 40_001).is_none());
+
+        // l = 0: dense values
+        let dense: Vec<u64> = (0..300).collect();
+        let ef = build_elias_fano32(&dense, 299);
+        assert!(EfIterator::new(&ef, 7).unwrap().eq(7..300));
+
+        // A truncated sequence ends the iteration early
+        let ef = build_elias_fano32(&values, v);
+        let truncated = &ef[..ef.len() / 2];
+        assert!(EfIterator::new(truncated, 0).map_or(0, Iterator::count) < values.len());
+    }
+
+    #[test]
+    fn test_build_offset_index_round_trip() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("offsets.idx");
+        let keys: Vec<Vec<u8>> = (0..500u64)
+            .map(|i| (i * 7919).to_be_bytes().to_vec())
+            .collect();
+        // Non-enum indexes accept offsets in any order
+        let offsets: Vec<u64> = (0..keys.len() as u64)
+            .map(|i| (i * 104_729) % 65_537)
+            .collect();
+
+        let builder = RecSplit::builder(&path, keys.len())
+            .leaf_size(4)
+            .bucket_size(50);
+        let idx = build_and_open(builder, &keys, &offsets);
+
+        assert!(!idx.is_enum());
+        assert_eq!(idx.leaf_size(), 4);
+        for (key, &offset) in keys.iter().zip(&offsets) {
+            assert_eq!(idx.lookup(key), Some(offset));
+        }
+    }
+
+    #[test]
+    fn test_name_version() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("enums.idx");
+        let keys: Vec<Vec<u8>> = (0..10u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let offsets: Vec<u64> = (0..10).collect();
+        let builder = RecSplit::builder(&path, keys.len())
+            .enums(true)
+            .less_false_positives(true);
+        let idx = build_and_open(builder, &keys, &offsets);
+        assert_eq!(idx.name_version(), None);
+        assert_eq!(idx.format_version(), FormatVersion::V1_1);
+
+        let named = tmp_dir.path().join("v1.1-000000-000500-headers.idx");
+        fs::copy(&path, &named).unwrap();
+        let idx = RecSplitIndex::open(&named).unwrap();
+        assert_eq!(idx.name_version(), Some(NameVersion::V1_1));
+        assert_eq!(idx.lookup(&keys[3]), Some(3));
+
+        // Opened by path, a file is read whatever its name says
+        let newer = tmp_dir.path().join("v3.0-000000-000500-headers.idx");
+        fs::copy(&path, &newer).unwrap();
+        let idx = RecSplitIndex::open(&newer).unwrap();
+        assert_eq!(idx.name_version(), Some(NameVersion::new(3, 0)));
+        assert_eq!(idx.format_version(), FormatVersion::V1_1);
+        assert_eq!(idx.lookup(&keys[3]), Some(3));
+
+        // Named v1.1, but built without the existence filter
+        let plain = tmp_dir.path().join("v1.1-000000-000500-bodies.idx");
+        let idx = build_and_open(RecSplit::builder(&plain, keys.len()), &keys, &offsets);
+        assert_eq!(idx.name_version(), Some(NameVersion::V1_1));
+        assert_eq!(idx.format_version(), FormatVersion::V1_0);
+    }
+
+    #[test]
+    fn test_build_rejects_wrong_key_count_and_decreasing_enum_offsets() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("bad.idx");
+
+        let mut rs = RecSplit::builder(&path, 2).fsync(false).build().unwrap();
+        rs.add_key(b"a", 0).unwrap();
+        assert!(rs.build().is_err());
+        assert!(!path.exists());
+
+        let mut rs = RecSplit::builder(&path, 2).enums(true).build().unwrap();
+        rs.add_key(b"a", 10).unwrap();
+        assert!(rs.add_key(b"b", 5).is_err());
+
+        assert!(RecSplit::builder(&path, 2).leaf_size(25).build().is_err());
+    }
+
+    #[test]
+    fn test_open_rejects_truncated_index() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("full.idx");
+        let keys: Vec<Vec<u8>> = (0..100u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let offsets: Vec<u64> = (0..100).map(|i| i * 10).collect();
+        let builder = RecSplit::builder(&path, keys.len())
+            .enums(true)
+            .less_false_positives(true);
+        build_and_open(builder, &keys, &offsets);
+
+        // Every prefix cut inside the header and tables fails to open
+        // instead of panicking
+        let data = fs::read(&path).unwrap();
+        let cut_path = tmp_dir.path().join("cut.idx");
+        for len in 0..data.len() - 1 {
+            fs::write(&cut_path, &data[..len]).unwrap();
+            assert!(RecSplitIndex::open(&cut_path).is_err(), "{} bytes", len);
+        }
+
+        // Cut in the records, after the 17-byte header
+        fs::write(&cut_path, &data[..20]).unwrap();
+        match RecSplitIndex::open(&cut_path) {
+            Err(SnapshotError::Index(IndexError::Read { source, .. })) => assert_eq!(
+                source,
+                ReadError::OutOfBounds {
+                    what: "records",
+                    offset: 17,
+                    needed: 100 * data[16] as u64,
+                    available: 3,
+                }
+            ),
+            other => panic!("unexpected result {:?}", other.err()),
+        }
+    }
 }
@@ -0,0 +1,439 @@
+//! Every kind of segment this crate reads, in one place
+//!
+//! A [`SegmentType`] says what a file's words hold ([`ValueEncoding`]) and
+//! which accessors index it ([`Accessor`]), and [`ErigonReader::open`]
+//! picks the reader for a file by its name. Supporting a new kind of segment
+//! means a variant here and an arm in each match below.
+
+use crate::decompress::Decompressor;
+use crate::seg_reader::detect_compress_type;
+use crate::snapshots::repo::{SnapshotFile, SnapshotType, StateFile};
+use crate::snapshots::{
+    BeaconBlocksReader, BlobSidecarsReader, BodiesReader, BorEventsReader, BorSpansReader,
+    DomainReader, HeadersReader, HistoryReader, InvertedIndexReader, Result, SendersReader,
+    SnapshotError, TransactionsReader,
+};
+use std::fmt;
+use std::path::Path;
+
+/// An Erigon 3 state domain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DomainKind {
+    Accounts,
+    Storage,
+    Code,
+    Commitment,
+    Receipt,
+}
+
+impl DomainKind {
+    pub const ALL: [DomainKind; 5] = [
+        DomainKind::Accounts,
+        DomainKind::Storage,
+        DomainKind::Code,
+        DomainKind::Commitment,
+        DomainKind::Receipt,
+    ];
+
+    /// The domain as it appears in file names
+    pub fn name(self) -> &'static str {
+        match self {
+            DomainKind::Accounts => "accounts",
+            DomainKind::Storage => "storage",
+            DomainKind::Code => "code",
+            DomainKind::Commitment => "commitment",
+            DomainKind::Receipt => "receipt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// How the domain's values are encoded
+    pub fn value_encoding(self) -> ValueEncoding {
+        match self {
+            DomainKind::Accounts => ValueEncoding::Account,
+            DomainKind::Storage => ValueEncoding::StorageSlot,
+            DomainKind::Code | DomainKind::Commitment | DomainKind::Receipt => ValueEncoding::Bytes,
+        }
+    }
+}
+
+impl fmt::Display for DomainKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Kind of a segment: a block segment or a state file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SegmentType {
+    Headers,
+    Bodies,
+    Transactions,
+    Senders,
+    BorEvents,
+    BorSpans,
+    BeaconBlocks,
+    BlobSidecars,
+    /// Latest values of a domain (`.kv`)
+    Domain(DomainKind),
+    /// Values of a domain before each change (`.v`)
+    History(DomainKind),
+    /// TxNums at which the keys of a domain changed (`.ef`)
+    InvertedIndex(DomainKind),
+}
+
+/// What the words of a segment hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueEncoding {
+    /// `hash[0]`, then the RLP header
+    Header,
+    /// RLP [`StoredBody`](crate::snapshots::StoredBody)
+    Body,
+    /// `hash[0]`, the 20-byte sender, then the EIP-2718 transaction
+    Transaction,
+    /// 20-byte sender, empty for system transactions
+    Sender,
+    /// Block hash, event id and RLP event record
+    BorEvent,
+    /// JSON Heimdall span
+    BorSpan,
+    /// Snappy-framed SSZ `SignedBeaconBlock`
+    BeaconBlock,
+    /// SSZ `BlobSidecar`s back to back
+    BlobSidecars,
+    /// [`Account`](crate::snapshots::Account) in Erigon's compact encoding
+    Account,
+    /// Storage slot value, big-endian without leading zeros
+    StorageSlot,
+    /// Bytes with no structure known to this crate
+    Bytes,
+    /// Eliasfano32 sequence of txNums
+    TxNums,
+}
+
+/// How an accessor maps to the words of its segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexFlavor {
+    /// Enum RecSplit: word ordinals counted from the base data id (block,
+    /// txNum or slot), and keys (usually hashes) mapped to ordinals
+    Enum,
+    /// RecSplit from a key to the offset of its word
+    Key,
+    /// B-tree over the sorted keys, also serving seeks
+    BTree,
+}
+
+/// An index file next to a segment, with the same stem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Accessor {
+    pub extension: &'static str,
+    pub flavor: IndexFlavor,
+}
+
+impl SegmentType {
+    /// The type of a segment from its file name, `None` if the name is not
+    /// one this crate reads
+    pub fn parse(path: &Path) -> Option<Self> {
+        if let Some(file) = SnapshotFile::parse(path) {
+            return Some(file.kind.into());
+        }
+        let file = StateFile::parse(path)?;
+        let domain = DomainKind::from_name(&file.name)?;
+        match file.extension.as_str() {
+            "kv" => Some(SegmentType::Domain(domain)),
+            "v" => Some(SegmentType::History(domain)),
+            "ef" => Some(SegmentType::InvertedIndex(domain)),
+            _ => None,
+        }
+    }
+
+    /// The block segment type, `None` for state files
+    pub fn snapshot_type(self) -> Option<SnapshotType> {
+        match self {
+            SegmentType::Headers => Some(SnapshotType::Headers),
+            SegmentType::Bodies => Some(SnapshotType::Bodies),
+            SegmentType::Transactions => Some(SnapshotType::Transactions),
+            SegmentType::Senders => Some(SnapshotType::Senders),
+            SegmentType::BorEvents => Some(SnapshotType::BorEvents),
+            SegmentType::BorSpans => Some(SnapshotType::BorSpans),
+            SegmentType::BeaconBlocks => Some(SnapshotType::BeaconBlocks),
+            SegmentType::BlobSidecars => Some(SnapshotType::BlobSidecars),
+            SegmentType::Domain(_) | SegmentType::History(_) | SegmentType::InvertedIndex(_) => {
+                None
+            }
+        }
+    }
+
+    /// What each value word holds; see [`SegmentType::is_key_value`] for
+    /// where the values are
+    pub fn value_encoding(self) -> ValueEncoding {
+        match self {
+            SegmentType::Headers => ValueEncoding::Header,
+            SegmentType::Bodies => ValueEncoding::Body,
+            SegmentType::Transactions => ValueEncoding::Transaction,
+            SegmentType::Senders => ValueEncoding::Sender,
+            SegmentType::BorEvents => ValueEncoding::BorEvent,
+            SegmentType::BorSpans => ValueEncoding::BorSpan,
+            SegmentType::BeaconBlocks => ValueEncoding::BeaconBlock,
+            SegmentType::BlobSidecars => ValueEncoding::BlobSidecars,
+            SegmentType::Domain(domain) | SegmentType::History(domain) => domain.value_encoding(),
+            SegmentType::InvertedIndex(_) => ValueEncoding::TxNums,
+        }
+    }
+
+    /// Whether words alternate between a key and its value, rather than
+    /// every word being a value
+    pub fn is_key_value(self) -> bool {
+        matches!(self, SegmentType::Domain(_) | SegmentType::InvertedIndex(_))
+    }
+
+    /// The accessors Erigon builds for the segment
+    pub fn accessors(self) -> &'static [Accessor] {
+        const IDX: &[Accessor] = &[Accessor {
+            extension: "idx",
+            flavor: IndexFlavor::Enum,
+        }];
+        match self {
+            SegmentType::Headers
+            | SegmentType::Bodies
+            | SegmentType::Transactions
+            | SegmentType::Senders
+            | SegmentType::BorSpans
+            | SegmentType::BeaconBlocks
+            | SegmentType::BlobSidecars => IDX,
+            // Block hash to the offset of the block's first event
+            SegmentType::BorEvents => &[Accessor {
+                extension: "idx",
+                flavor: IndexFlavor::Key,
+            }],
+            SegmentType::Domain(_) => &[
+                Accessor {
+                    extension: "kvi",
+                    flavor: IndexFlavor::Key,
+                },
+                Accessor {
+                    extension: "bt",
+                    flavor: IndexFlavor::BTree,
+                },
+            ],
+            // Keyed by the txNum (8 bytes big-endian) followed by the key
+            SegmentType::History(_) => &[Accessor {
+                extension: "vi",
+                flavor: IndexFlavor::Key,
+            }],
+            SegmentType::InvertedIndex(_) => &[Accessor {
+                extension: "efi",
+                flavor: IndexFlavor::Key,
+            }],
+        }
+    }
+}
+
+impl From<SnapshotType> for SegmentType {
+    fn from(kind: SnapshotType) -> Self {
+        match kind {
+            SnapshotType::Headers => SegmentType::Headers,
+            SnapshotType::Bodies => SegmentType::Bodies,
+            SnapshotType::Transactions => SegmentType::Transactions,
+            SnapshotType::Senders => SegmentType::Senders,
+            SnapshotType::BorEvents => SegmentType::BorEvents,
+            SnapshotType::BorSpans => SegmentType::BorSpans,
+            SnapshotType::BeaconBlocks => SegmentType::BeaconBlocks,
+            SnapshotType::BlobSidecars => SegmentType::BlobSidecars,
+        }
+    }
+}
+
+impl fmt::Display for SegmentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.snapshot_type(), self) {
+            (Some(kind), _) => kind.fmt(f),
+            (None, SegmentType::Domain(domain)) => write!(f, "{}.kv", domain),
+            (None, SegmentType::History(domain)) => write!(f, "{}.v", domain),
+            (None, SegmentType::InvertedIndex(domain)) => write!(f, "{}.ef", domain),
+            (None, _) => unreachable!("block segments have a snapshot type"),
+        }
+    }
+}
+
+/// The reader of a segment, picked by its [`SegmentType`]
+pub enum ErigonReader {
+    Headers(HeadersReader),
+    Bodies(BodiesReader),
+    Transactions(TransactionsReader),
+    Senders(SendersReader),
+    BorEvents(BorEventsReader),
+    BorSpans(BorSpansReader),
+    BeaconBlocks(BeaconBlocksReader),
+    BlobSidecars(BlobSidecarsReader),
+    Domain(DomainKind, DomainReader),
+    History(DomainKind, HistoryReader),
+    InvertedIndex(DomainKind, InvertedIndexReader),
+}
+
+impl ErigonReader {
+    /// Open `path` with the reader for its type, along with the accessors
+    /// next to it
+    ///
+    /// The key/value compression of state files is detected from their
+    /// first words.
+    pub fn open(path: &Path) -> Result<Self> {
+        let kind = SegmentType::parse(path).ok_or_else(|| {
+            SnapshotError::InvalidPath(format!("{}: not a known segment", path.display()))
+        })?;
+        Ok(match kind {
+            SegmentType::Headers => ErigonReader::Headers(HeadersReader::new(path)?),
+            SegmentType::Bodies => ErigonReader::Bodies(BodiesReader::new(path)?),
+            SegmentType::Transactions => ErigonReader::Transactions(TransactionsReader::new(path)?),
+            SegmentType::Senders => ErigonReader::Senders(SendersReader::new(path)?),
+            SegmentType::BorEvents => ErigonReader::BorEvents(BorEventsReader::new(path)?),
+            SegmentType::BorSpans => ErigonReader::BorSpans(BorSpansReader::new(path)?),
+            SegmentType::BeaconBlocks => ErigonReader::BeaconBlocks(BeaconBlocksReader::new(path)?),
+            SegmentType::BlobSidecars => ErigonReader::BlobSidecars(BlobSidecarsReader::new(path)?),
+            SegmentType::Domain(domain) => ErigonReader::Domain(domain, DomainReader::new(path)?),
+            SegmentType::History(domain) => {
+                let compression = detect_compress_type(&Decompressor::new(path)?);
+                ErigonReader::History(domain, HistoryReader::new(path, compression)?)
+            }
+            SegmentType::InvertedIndex(domain) => {
+                ErigonReader::InvertedIndex(domain, InvertedIndexReader::new(path)?)
+            }
+        })
+    }
+
+    pub fn segment_type(&self) -> SegmentType {
+        match self {
+            ErigonReader::Headers(_) => SegmentType::Headers,
+            ErigonReader::Bodies(_) => SegmentType::Bodies,
+            ErigonReader::Transactions(_) => SegmentType::Transactions,
+            ErigonReader::Senders(_) => SegmentType::Senders,
+            ErigonReader::BorEvents(_) => SegmentType::BorEvents,
+            ErigonReader::BorSpans(_) => SegmentType::BorSpans,
+            ErigonReader::BeaconBlocks(_) => SegmentType::BeaconBlocks,
+            ErigonReader::BlobSidecars(_) => SegmentType::BlobSidecars,
+            ErigonReader::Domain(domain, _) => SegmentType::Domain(*domain),
+            ErigonReader::History(domain, _) => SegmentType::History(*domain),
+            ErigonReader::InvertedIndex(domain, _) => SegmentType::InvertedIndex(*domain),
+        }
+    }
+
+    /// Number of items in the segment: headers, events, keys, ...
+    pub fn count(&self) -> usize {
+        match self {
+            ErigonReader::Headers(r) => r.count(),
+            ErigonReader::Bodies(r) => r.count(),
+            ErigonReader::Transactions(r) => r.count(),
+            ErigonReader::Senders(r) => r.count(),
+            ErigonReader::BorEvents(r) => r.count(),
+            ErigonReader::BorSpans(r) => r.count(),
+            ErigonReader::BeaconBlocks(r) => r.count(),
+            ErigonReader::BlobSidecars(r) => r.count(),
+            ErigonReader::Domain(_, r) => r.count(),
+            ErigonReader::History(_, r) => r.count(),
+            ErigonReader::InvertedIndex(_, r) => r.count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::write_segment;
+
+    #[test]
+    fn test_segment_types() {
+        let parse = |name: &str| SegmentType::parse(Path::new(name));
+        assert_eq!(
+            parse("v1-000000-000500-transactions.seg"),
+            Some(SegmentType::Transactions)
+        );
+        assert_eq!(
+            parse("/snap/domain/v1-accounts.0-64.kv"),
+            Some(SegmentType::Domain(DomainKind::Accounts))
+        );
+        assert_eq!(
+            parse("v1.1-storage.64-96.v"),
+            Some(SegmentType::History(DomainKind::Storage))
+        );
+        assert_eq!(
+            parse("v1-code.0-32.ef"),
+            Some(SegmentType::InvertedIndex(DomainKind::Code))
+        );
+        for name in [
+            "v1-accounts.0-64.kvi",
+            "v1-logaddrs.0-64.ef",
+            "v1-000000-000500-headers.idx",
+        ] {
+            assert_eq!(parse(name), None, "{}", name);
+        }
+
+        for kind in SnapshotType::ALL {
+            let segment = SegmentType::from(kind);
+            assert_eq!(segment.snapshot_type(), Some(kind));
+            assert_eq!(segment.to_string(), kind.name());
+            assert!(!segment.is_key_value());
+        }
+        let accounts = SegmentType::Domain(DomainKind::Accounts);
+        assert_eq!(accounts.to_string(), "accounts.kv");
+        assert_eq!(accounts.value_encoding(), ValueEncoding::Account);
+        assert!(accounts.is_key_value());
+        assert_eq!(
+            accounts
+                .accessors()
+                .iter()
+                .map(|a| a.extension)
+                .collect::<Vec<_>>(),
+            ["kvi", "bt"]
+        );
+        assert_eq!(
+            SegmentType::Headers.accessors()[0].flavor,
+            IndexFlavor::Enum
+        );
+        assert_eq!(
+            SegmentType::History(DomainKind::Code).value_encoding(),
+            ValueEncoding::Bytes
+        );
+    }
+
+    #[test]
+    fn test_open_routes_by_name() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let write = |name: &str, words: &[&[u8]]| {
+            let path = tmp_dir.path().join(name);
+            write_segment(&path, words).unwrap();
+            path
+        };
+
+        let kv = write(
+            "v1-accounts.0-1.kv",
+            &[b"key1", b"value1", b"key2", b"value2"],
+        );
+        let reader = ErigonReader::open(&kv).unwrap();
+        assert_eq!(
+            reader.segment_type(),
+            SegmentType::Domain(DomainKind::Accounts)
+        );
+        assert_eq!(reader.count(), 2);
+        let ErigonReader::Domain(_, domain) = reader else {
+            panic!("not a domain reader");
+        };
+        assert_eq!(domain.get(b"key2").unwrap(), Some(b"value2".to_vec()));
+
+        let spans = write(
+            "v1-000000-000500-borspans.seg",
+            &[br#"{"span_id":0,"start_block":0,"end_block":255}"#],
+        );
+        let reader = ErigonReader::open(&spans).unwrap();
+        assert_eq!(reader.segment_type(), SegmentType::BorSpans);
+        assert_eq!(reader.count(), 1);
+
+        let other = write("words.seg", &[b"word"]);
+        assert!(matches!(
+            ErigonReader::open(&other),
+            Err(SnapshotError::InvalidPath(_))
+        ));
+    }
+}
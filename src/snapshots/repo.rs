@@ -0,0 +1,556 @@
+//! Layout of an Erigon snapshot directory
+//!
+//! Block segments are named `v<version>-<from>-<to>-<type>.seg`, with the
+//! block range in thousands of blocks (`v1-000000-000500-headers.seg` holds
+//! blocks 0..500000), and their `.idx` accessors sit next to them with the
+//! same stem. Caplin segments (`beaconblocks`, `blobsidecars`) are named the
+//! same way but count slots instead of blocks.
+//!
+//! Erigon 3 state files are named `v<version>-<name>.<from>-<to>.<ext>`,
+//! with the range in steps (`v1-accounts.0-64.kv`), and live in the
+//! `domain`, `history`, `idx` and `accessor` subdirectories.
+
+use crate::core::NameVersion;
+use crate::decompress::Decompressor;
+use crate::snapshots::manifest::{Manifest, ManifestEntry};
+use crate::snapshots::open_files::{OpenFiles, DEFAULT_OPEN_FILE_LIMIT};
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::snapshots::torrent::{TorrentInfo, DEFAULT_PIECE_LENGTH};
+use crate::snapshots::Result;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// File names count blocks in units of this many blocks
+pub(crate) const BLOCKS_PER_FILE_UNIT: u64 = 1000;
+
+/// Kind of data a block segment holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum SnapshotType {
+    Headers,
+    Bodies,
+    Transactions,
+    /// Senders of the transactions of the same range, one word per
+    /// transaction, read with [`SendersReader`](crate::snapshots::SendersReader)
+    Senders,
+    /// Polygon state sync events, read with
+    /// [`BorEventsReader`](crate::snapshots::BorEventsReader)
+    BorEvents,
+    /// Polygon Heimdall spans, read with
+    /// [`BorSpansReader`](crate::snapshots::BorSpansReader)
+    BorSpans,
+    /// Beacon chain blocks by slot, read with
+    /// [`BeaconBlocksReader`](crate::snapshots::BeaconBlocksReader)
+    BeaconBlocks,
+    /// Beacon chain blob sidecars by slot, read with
+    /// [`BlobSidecarsReader`](crate::snapshots::BlobSidecarsReader)
+    BlobSidecars,
+}
+
+impl SnapshotType {
+    pub const ALL: [SnapshotType; 8] = [
+        SnapshotType::Headers,
+        SnapshotType::Bodies,
+        SnapshotType::Transactions,
+        SnapshotType::Senders,
+        SnapshotType::BorEvents,
+        SnapshotType::BorSpans,
+        SnapshotType::BeaconBlocks,
+        SnapshotType::BlobSidecars,
+    ];
+
+    /// The type as it appears in file names
+    pub fn name(self) -> &'static str {
+        match self {
+            SnapshotType::Headers => "headers",
+            SnapshotType::Bodies => "bodies",
+            SnapshotType::Transactions => "transactions",
+            SnapshotType::Senders => "senders",
+            SnapshotType::BorEvents => "borevents",
+            SnapshotType::BorSpans => "borspans",
+            SnapshotType::BeaconBlocks => "beaconblocks",
+            SnapshotType::BlobSidecars => "blobsidecars",
+        }
+    }
+
+    /// Whether the segment's range counts beacon chain slots, not blocks
+    pub fn is_caplin(self) -> bool {
+        matches!(
+            self,
+            SnapshotType::BeaconBlocks | SnapshotType::BlobSidecars
+        )
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+impl fmt::Display for SnapshotType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A block segment, as described by its file name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotFile {
+    pub path: PathBuf,
+    /// Format version from the name, e.g. "1" or "1.1"
+    pub version: String,
+    /// First block (slot, for caplin types) of the segment
+    pub from_block: u64,
+    /// One past the last block (slot) of the segment
+    pub to_block: u64,
+    pub kind: SnapshotType,
+}
+
+impl SnapshotFile {
+    /// Parse a segment path like `.../v1-000000-000500-headers.seg`,
+    /// returning `None` if its name is not one of a known block segment
+    pub fn parse(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let stem = name.strip_suffix(".seg")?;
+        let mut parts = stem.splitn(4, '-');
+        let version = parts.next()?.strip_prefix('v')?;
+        let from = parts.next()?;
+        let to = parts.next()?;
+        let kind = SnapshotType::from_name(parts.next()?)?;
+        if version.is_empty() || from.len() != 6 || to.len() != 6 {
+            return None;
+        }
+        let from_block = from.parse::<u64>().ok()? * BLOCKS_PER_FILE_UNIT;
+        let to_block = to.parse::<u64>().ok()? * BLOCKS_PER_FILE_UNIT;
+        if from_block >= to_block {
+            return None;
+        }
+        Some(SnapshotFile {
+            path: path.to_path_buf(),
+            version: version.to_string(),
+            from_block,
+            to_block,
+            kind,
+        })
+    }
+
+    /// File stem of a version 1 segment of `kind` over `from_block..to_block`
+    ///
+    /// The bounds must be multiples of 1000 blocks.
+    pub fn stem(kind: SnapshotType, from_block: u64, to_block: u64) -> String {
+        format!(
+            "v1-{:06}-{:06}-{}",
+            from_block / BLOCKS_PER_FILE_UNIT,
+            to_block / BLOCKS_PER_FILE_UNIT,
+            kind
+        )
+    }
+
+    /// Path of the segment's `.idx` file
+    pub fn index_path(&self) -> PathBuf {
+        self.path.with_extension("idx")
+    }
+
+    /// Path of the segment of `kind` with the same version and range
+    pub fn sibling(&self, kind: SnapshotType) -> PathBuf {
+        self.path.with_file_name(format!(
+            "v{}-{:06}-{:06}-{}.seg",
+            self.version,
+            self.from_block / BLOCKS_PER_FILE_UNIT,
+            self.to_block / BLOCKS_PER_FILE_UNIT,
+            kind
+        ))
+    }
+
+    pub fn contains(&self, block: u64) -> bool {
+        (self.from_block..self.to_block).contains(&block)
+    }
+}
+
+/// An Erigon 3 state file, as described by its file name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateFile {
+    pub path: PathBuf,
+    /// Format version from the name, e.g. "1" or "1.1"
+    pub version: String,
+    /// Domain or index name, e.g. "accounts" or "logaddrs"
+    pub name: String,
+    /// First step of the file
+    pub from_step: u64,
+    /// One past the last step of the file
+    pub to_step: u64,
+    /// File type, e.g. "kv", "v" or "ef"
+    pub extension: String,
+}
+
+impl StateFile {
+    /// Parse a state file path like `.../v1-accounts.0-64.kv`, returning
+    /// `None` if its name does not have that shape
+    pub fn parse(path: &Path) -> Option<Self> {
+        let file_name = path.file_name()?.to_str()?;
+        let (version, rest) = file_name.strip_prefix('v')?.split_once('-')?;
+        let (name, rest) = rest.split_once('.')?;
+        let (range, extension) = rest.split_once('.')?;
+        let (from, to) = range.split_once('-')?;
+        if version.is_empty() || name.is_empty() || extension.is_empty() {
+            return None;
+        }
+        let from_step = from.parse::<u64>().ok()?;
+        let to_step = to.parse::<u64>().ok()?;
+        if from_step >= to_step {
+            return None;
+        }
+        Some(StateFile {
+            path: path.to_path_buf(),
+            version: version.to_string(),
+            name: name.to_string(),
+            from_step,
+            to_step,
+            extension: extension.to_string(),
+        })
+    }
+}
+
+/// The block segments found in a snapshot directory
+///
+/// Segments and indexes handed out by [`SnapshotRepo::segment`] and
+/// [`SnapshotRepo::index`] stay open for the next lookups, up to
+/// [`SnapshotRepo::with_open_file_limit`] files. Those lookups take `&self`:
+/// the set of open files is updated internally, and shared with the tasks
+/// of [`SnapshotRepo::open_all`].
+pub struct SnapshotRepo {
+    dir: PathBuf,
+    // Sorted by type, then block range
+    files: Vec<SnapshotFile>,
+    open_files: Arc<OpenFiles>,
+}
+
+impl SnapshotRepo {
+    /// Scan `dir` for block segments; files with other names, or named with
+    /// a version Erigon has not used yet, are skipped
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some(file) = SnapshotFile::parse(&path) else {
+                tracing::debug!("Skipping {}: not a block segment", path.display());
+                continue;
+            };
+            let version = file
+                .path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(NameVersion::from_file_name);
+            match version {
+                Some(version) if !version.is_known() => {
+                    tracing::debug!("Skipping {}: unknown version {}", path.display(), version)
+                }
+                _ => files.push(file),
+            }
+        }
+        files.sort_by(|a, b| {
+            (a.kind, a.from_block, a.to_block).cmp(&(b.kind, b.from_block, b.to_block))
+        });
+        Ok(SnapshotRepo {
+            dir,
+            files,
+            open_files: Arc::new(OpenFiles::new(DEFAULT_OPEN_FILE_LIMIT)),
+        })
+    }
+
+    /// Keep up to `limit` segments and indexes open, instead of
+    /// [`DEFAULT_OPEN_FILE_LIMIT`]; files already open are closed
+    pub fn with_open_file_limit(mut self, limit: usize) -> Self {
+        self.open_files = Arc::new(OpenFiles::new(limit));
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// All segments, sorted by type and block range
+    pub fn files(&self) -> &[SnapshotFile] {
+        &self.files
+    }
+
+    /// The segments of one type, in block order
+    pub fn files_of(&self, kind: SnapshotType) -> impl Iterator<Item = &SnapshotFile> {
+        self.files.iter().filter(move |file| file.kind == kind)
+    }
+
+    /// The segment of `kind` holding `block`
+    pub fn find(&self, kind: SnapshotType, block: u64) -> Option<&SnapshotFile> {
+        self.files_of(kind).find(|file| file.contains(block))
+    }
+
+    /// The segment of `file`, kept open for the next lookups
+    pub fn segment(&self, file: &SnapshotFile) -> Result<Arc<Decompressor>> {
+        self.open_files.segment(&file.path)
+    }
+
+    /// The `.idx` of `file`, kept open for the next lookups; `None` if the
+    /// segment has no index
+    pub fn index(&self, file: &SnapshotFile) -> Result<Option<Arc<RecSplitIndex>>> {
+        let path = file.index_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        self.open_files.index(&path).map(Some)
+    }
+
+    /// The files kept open, and their hit, miss and eviction counts
+    pub fn open_files(&self) -> &OpenFiles {
+        &self.open_files
+    }
+
+    /// Open every segment and index ahead of the first lookups, up to the
+    /// open file limit, `parallelism` files at a time
+    ///
+    /// Files are opened on the `blocking` thread pool, which works under any
+    /// async runtime. Returns the number of files opened.
+    pub async fn open_all(&self, parallelism: usize) -> Result<usize> {
+        let mut paths = Vec::new();
+        for file in &self.files {
+            paths.push((file.path.clone(), false));
+            let index_path = file.index_path();
+            if index_path.exists() {
+                paths.push((index_path, true));
+            }
+        }
+        paths.truncate(self.open_files.limit());
+        let opened = paths.len();
+
+        // Every task opens every `parallelism`-th file
+        let parallelism = parallelism.clamp(1, paths.len().max(1));
+        let tasks: Vec<_> = (0..parallelism)
+            .map(|task| {
+                let open_files = Arc::clone(&self.open_files);
+                let paths: Vec<_> = paths
+                    .iter()
+                    .skip(task)
+                    .step_by(parallelism)
+                    .cloned()
+                    .collect();
+                blocking::unblock(move || -> Result<()> {
+                    for (path, is_index) in paths {
+                        if is_index {
+                            open_files.index(&path)?;
+                        } else {
+                            open_files.segment(&path)?;
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await?;
+        }
+        tracing::debug!("Opened {} files of {}", opened, self.dir.display());
+        Ok(opened)
+    }
+
+    /// Write a `.torrent` next to every segment and its index, and the
+    /// preverified `.toml` listing them by infohash to `path`
+    ///
+    /// This is what Erigon nodes need to fetch segments produced here from a
+    /// webseed, which also serves [`Manifest::to_list`] as `manifest.txt`.
+    /// Torrents use Erigon's piece length, so rewriting the manifest of
+    /// unchanged files gives the same infohashes.
+    pub fn write_manifest(&self, path: &Path) -> Result<Manifest> {
+        let mut entries = Vec::new();
+        for file in &self.files {
+            for data in [file.path.clone(), file.index_path()] {
+                if !data.exists() {
+                    continue;
+                }
+                let torrent = TorrentInfo::create(&data, DEFAULT_PIECE_LENGTH)?;
+                torrent.write_next_to(&data)?;
+                entries.push(ManifestEntry {
+                    name: torrent.name.clone(),
+                    info_hash: Some(torrent.info_hash_hex()),
+                });
+            }
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        let manifest = Manifest { entries };
+
+        // Written aside and renamed, so a webseed never serves half of it
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, manifest.to_toml())?;
+        std::fs::rename(&tmp, path)?;
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_file_names() {
+        let file = SnapshotFile::parse(Path::new("/snap/v1-054600-054700-borevents.seg")).unwrap();
+        assert_eq!(file.kind, SnapshotType::BorEvents);
+        assert_eq!(file.version, "1");
+        assert_eq!((file.from_block, file.to_block), (54_600_000, 54_700_000));
+        assert_eq!(
+            file.index_path(),
+            Path::new("/snap/v1-054600-054700-borevents.idx")
+        );
+
+        let file = SnapshotFile::parse(Path::new("v1.1-000000-000500-borspans.seg")).unwrap();
+        assert_eq!(
+            (file.kind, file.version.as_str()),
+            (SnapshotType::BorSpans, "1.1")
+        );
+
+        let file = SnapshotFile::parse(Path::new("v1-008000-008100-beaconblocks.seg")).unwrap();
+        assert!(file.kind.is_caplin());
+        assert_eq!(file.from_block, 8_000_000);
+
+        for name in [
+            "v1-000000-000500-headers.idx",
+            "v1-000000-000500-accounts.seg",
+            "v1-000500-000000-headers.seg",
+            "v1-0-500-headers.seg",
+            "000000-000500-headers.seg",
+            "salt-blocks.txt",
+        ] {
+            assert_eq!(SnapshotFile::parse(Path::new(name)), None, "{}", name);
+        }
+
+        for kind in SnapshotType::ALL {
+            let name = format!("{}.seg", SnapshotFile::stem(kind, 1000, 2000));
+            let file = SnapshotFile::parse(Path::new(&name)).unwrap();
+            assert_eq!(
+                (file.kind, file.from_block, file.to_block),
+                (kind, 1000, 2000)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_state_file_names() {
+        let file = StateFile::parse(Path::new("/snap/domain/v1-accounts.0-64.kv")).unwrap();
+        assert_eq!(
+            (
+                file.version.as_str(),
+                file.name.as_str(),
+                file.from_step,
+                file.to_step,
+                file.extension.as_str()
+            ),
+            ("1", "accounts", 0, 64, "kv")
+        );
+        let file = StateFile::parse(Path::new("v1.1-storage.96-112.efi")).unwrap();
+        assert_eq!((file.name.as_str(), file.from_step), ("storage", 96));
+
+        for name in [
+            "v1-000000-000500-headers.seg",
+            "v1-accounts.64-0.kv",
+            "v1-accounts.0-64",
+            "accounts.0-64.kv",
+        ] {
+            assert_eq!(StateFile::parse(Path::new(name)), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_repo_lists_segments() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        for name in [
+            "v1-000500-001000-headers.seg",
+            "v1-000000-000500-headers.seg",
+            "v1-000000-000500-headers.idx",
+            "v1-000000-000500-borspans.seg",
+            "v1-000000-000500-borevents.seg",
+            // A version Erigon has not named files with
+            "v3-000000-000500-bodies.seg",
+            "salt-blocks.txt",
+        ] {
+            std::fs::write(tmp_dir.path().join(name), b"").unwrap();
+        }
+
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        assert_eq!(repo.files().len(), 4);
+        let headers: Vec<_> = repo
+            .files_of(SnapshotType::Headers)
+            .map(|file| file.from_block)
+            .collect();
+        assert_eq!(headers, [0, 500_000]);
+        assert_eq!(
+            repo.find(SnapshotType::Headers, 500_000)
+                .map(|f| f.from_block),
+            Some(500_000)
+        );
+        assert!(repo.find(SnapshotType::BorEvents, 499_999).is_some());
+        assert!(repo.find(SnapshotType::BorEvents, 500_000).is_none());
+        assert!(repo.find(SnapshotType::Bodies, 0).is_none());
+    }
+
+    #[smol_potat::test]
+    async fn test_open_all() {
+        use crate::testutil::tests::write_headers;
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        for from in [0, 1000, 2000] {
+            write_headers(tmp_dir.path(), from..from + 1000);
+        }
+
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        assert_eq!(repo.open_all(4).await.unwrap(), 6);
+        let stats = repo.open_files().stats();
+        assert_eq!((stats.misses, stats.open), (6, 6));
+        // Lookups after warming find their files open
+        let file = repo.find(SnapshotType::Headers, 1500).unwrap();
+        assert_eq!(repo.segment(file).unwrap().count(), 1000);
+        assert_eq!(repo.index(file).unwrap().unwrap().key_count(), 1000);
+        assert_eq!(repo.open_files().stats().hits, 2);
+
+        // Only as many as the limit
+        let repo = SnapshotRepo::open(tmp_dir.path())
+            .unwrap()
+            .with_open_file_limit(2);
+        assert_eq!(repo.open_all(1).await.unwrap(), 2);
+        assert_eq!(repo.open_files().stats().evictions, 0);
+    }
+
+    #[test]
+    fn test_write_manifest() {
+        use crate::snapshots::torrent::verify_file_against_torrent;
+        use crate::testutil::tests::write_headers;
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let seg_path = write_headers(tmp_dir.path(), 0..1000);
+        let idx_path = seg_path.with_extension("idx");
+
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        let toml = tmp_dir.path().join("mainnet.toml");
+        let manifest = repo.write_manifest(&toml).unwrap();
+        let names: Vec<_> = manifest.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "v1-000000-000001-headers.idx",
+                "v1-000000-000001-headers.seg"
+            ]
+        );
+        assert_eq!(Manifest::open(&toml).unwrap(), manifest);
+        let torrent = verify_file_against_torrent(&seg_path).unwrap();
+        assert_eq!(manifest.entries[1].info_hash, Some(torrent.info_hash_hex()));
+        verify_file_against_torrent(&idx_path).unwrap();
+
+        // The torrents just written don't count as segments, and writing
+        // again gives the same infohashes
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        assert_eq!(repo.files().len(), 1);
+        assert_eq!(repo.write_manifest(&toml).unwrap(), manifest);
+    }
+}
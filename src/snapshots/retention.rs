@@ -0,0 +1,407 @@
+//! Removing the files of a snapshot directory below a retention horizon
+//!
+//! A pruned node only keeps blocks from some horizon on. [`RetentionPlan`]
+//! works out which files can go: block segments that end at or before the
+//! horizon block, history (`.v`, `.ef`) that ends at or before the horizon
+//! step, and files a bigger merged file covers. Files are removed whole with
+//! everything named after them (accessors, `.torrent`s, the
+//! `transactions-to-block` index); a file straddling the horizon is kept, as
+//! Erigon names files by their range and one cannot be cut without being
+//! rebuilt. Domain `.kv` files hold the latest value of every key and are
+//! never removed.
+//!
+//! A plan is refused if what is kept would leave a gap from the horizon on.
+//! [`RetentionPlan::apply`] moves the files aside before deleting them, and
+//! puts them back if it fails midway, so a directory is not left half
+//! pruned.
+
+use crate::snapshots::manifest::Manifest;
+use crate::snapshots::repo::{SnapshotRepo, SnapshotType};
+use crate::snapshots::state::{state_files, STATE_ACCESSORS};
+use crate::snapshots::{Result, SnapshotError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// Where the files of a plan wait while it is applied
+const STAGING_DIR: &str = ".retention";
+
+/// Files to remove from a snapshot directory, see the module docs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionPlan {
+    dir: PathBuf,
+    // Sorted, each listed once
+    files: Vec<PathBuf>,
+}
+
+impl RetentionPlan {
+    /// Plan the removal of the block segments of `repo` that end at or
+    /// before block `horizon` (slot, for caplin types), and of those a bigger
+    /// indexed file covers
+    pub fn blocks(repo: &SnapshotRepo, horizon: u64) -> Result<Self> {
+        let names = dir_names(repo.dir())?;
+        let mut files = Vec::new();
+        for kind in SnapshotType::ALL {
+            let segments: Vec<Span> = repo
+                .files_of(kind)
+                .map(|file| Span {
+                    range: file.from_block..file.to_block,
+                    path: file.path.clone(),
+                    complete: file.index_path().exists(),
+                })
+                .collect();
+            for path in removable(&kind.to_string(), segments, horizon)? {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                files.extend(
+                    names
+                        .iter()
+                        .filter(|name| {
+                            name.strip_prefix(stem.as_ref())
+                                .is_some_and(|rest| rest.starts_with(['.', '-']))
+                        })
+                        .map(|name| repo.dir().join(name)),
+                );
+            }
+        }
+        Ok(Self::new(repo.dir(), files))
+    }
+
+    /// Plan the removal of the `.v` and `.ef` files of the snapshot
+    /// directory `dir` that end at or before step `horizon`, and of those a
+    /// bigger file with its accessor covers
+    pub fn history(dir: impl AsRef<Path>, horizon: u64) -> Result<Self> {
+        let dir = dir.as_ref();
+        let all = state_files(dir)?;
+        let mut groups: BTreeMap<(String, String), Vec<Span>> = BTreeMap::new();
+        for (extension, accessors) in STATE_ACCESSORS {
+            if extension == "kv" {
+                continue;
+            }
+            for file in all.iter().filter(|f| f.extension == extension) {
+                let stem = file.path.file_stem();
+                let complete = all.iter().any(|other| {
+                    other.path.file_stem() == stem && accessors.contains(&other.extension.as_str())
+                });
+                groups
+                    .entry((file.name.clone(), extension.to_string()))
+                    .or_default()
+                    .push(Span {
+                        range: file.from_step..file.to_step,
+                        path: file.path.clone(),
+                        complete,
+                    });
+            }
+        }
+
+        let mut files = Vec::new();
+        for ((name, extension), spans) in groups {
+            let accessors = STATE_ACCESSORS
+                .iter()
+                .find(|(e, _)| *e == extension)
+                .map_or(&[][..], |(_, accessors)| *accessors);
+            let label = format!("{}.{}", name, extension);
+            for path in removable(&label, spans, horizon)? {
+                let stem = path.file_stem();
+                // The data file, its accessors and their torrents
+                files.extend(
+                    all.iter()
+                        .filter(|other| other.path.file_stem() == stem)
+                        .filter(|other| {
+                            let base = other
+                                .extension
+                                .strip_suffix(".torrent")
+                                .unwrap_or(&other.extension);
+                            base == extension || accessors.contains(&base)
+                        })
+                        .map(|other| other.path.clone()),
+                );
+            }
+        }
+        Ok(Self::new(dir, files))
+    }
+
+    fn new(dir: &Path, mut files: Vec<PathBuf>) -> Self {
+        files.sort();
+        files.dedup();
+        Self {
+            dir: dir.to_path_buf(),
+            files,
+        }
+    }
+
+    /// The files the plan removes
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Remove the files, and their entries from the manifest at `manifest`
+    /// if there is one
+    ///
+    /// The files are first moved aside within the directory; if one cannot
+    /// be, or the manifest cannot be rewritten, those moved are put back and
+    /// nothing is removed. Readers opened before should be reopened.
+    pub fn apply(&self, manifest: Option<&Path>) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let staging = self.dir.join(STAGING_DIR);
+        fs::create_dir_all(&staging)?;
+        let mut moved = Vec::new();
+        let result = self
+            .stage(&staging, &mut moved)
+            .and_then(|()| match manifest {
+                Some(path) => self.update_manifest(path),
+                None => Ok(()),
+            });
+        if let Err(e) = result {
+            for (from, to) in moved.iter().rev() {
+                if let Err(e) = fs::rename(to, from) {
+                    tracing::error!("Could not put back {}: {}", from.display(), e);
+                }
+            }
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+        fs::remove_dir_all(&staging)?;
+        tracing::info!(
+            "Removed {} files from {}",
+            self.files.len(),
+            self.dir.display()
+        );
+        Ok(())
+    }
+
+    // Move every file into `staging`, recording the moves
+    fn stage(&self, staging: &Path, moved: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+        for (i, path) in self.files.iter().enumerate() {
+            let name = path.file_name().ok_or_else(|| {
+                SnapshotError::InvalidPath(format!("{}: no file name", path.display()))
+            })?;
+            // Numbered, as the subdirectories may hold files of the same name
+            let mut staged = std::ffi::OsString::from(format!("{}-", i));
+            staged.push(name);
+            let staged = staging.join(staged);
+            fs::rename(path, &staged)?;
+            moved.push((path.clone(), staged));
+        }
+        Ok(())
+    }
+
+    // Drop the removed files from the manifest, written back in its form
+    fn update_manifest(&self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let mut manifest = Manifest::open(path)?;
+        let removed: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter_map(|file| file.strip_prefix(&self.dir).ok())
+            .map(Path::to_path_buf)
+            .collect();
+        let pinned = manifest.entries.iter().any(|e| e.info_hash.is_some());
+        manifest
+            .entries
+            .retain(|entry| !removed.iter().any(|file| file == Path::new(&entry.name)));
+        let text = match pinned {
+            true => manifest.to_toml(),
+            false => manifest.to_list(),
+        };
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+// A file covering `range`, complete if its accessor is there
+struct Span {
+    range: Range<u64>,
+    path: PathBuf,
+    complete: bool,
+}
+
+// The files of `spans` that end at or before `horizon` or that a bigger
+// complete file covers; an error if removing them leaves a gap from the
+// horizon to the end of the data
+fn removable(label: &str, mut spans: Vec<Span>, horizon: u64) -> Result<Vec<PathBuf>> {
+    // Biggest first among files starting at the same point
+    spans.sort_by_key(|span| (span.range.start, std::cmp::Reverse(span.range.end)));
+    let mut removed = Vec::new();
+    let mut kept: Vec<&Span> = Vec::new();
+    for span in &spans {
+        if span.range.end <= horizon {
+            removed.push(span.path.clone());
+            continue;
+        }
+        let covering = spans.iter().any(|other| {
+            !std::ptr::eq(other, span)
+                && other.complete
+                && other.range.start <= span.range.start
+                && other.range.end >= span.range.end
+                && other.range != span.range
+        });
+        if covering {
+            removed.push(span.path.clone());
+        } else {
+            kept.push(span);
+        }
+    }
+
+    if removed.is_empty() {
+        return Ok(removed);
+    }
+    let mut covered_to = match kept.first() {
+        Some(first) if first.range.start > horizon => {
+            return Err(SnapshotError::RetentionGap {
+                files: label.to_string(),
+                from: horizon,
+                to: first.range.start,
+            });
+        }
+        Some(first) => first.range.end,
+        None => return Ok(removed),
+    };
+    for span in &kept[1..] {
+        if span.range.start > covered_to {
+            return Err(SnapshotError::RetentionGap {
+                files: label.to_string(),
+                from: covered_to,
+                to: span.range.start,
+            });
+        }
+        covered_to = covered_to.max(span.range.end);
+    }
+    Ok(removed)
+}
+
+// Names of the entries of `dir`
+fn dir_names(dir: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        if let Some(name) = entry?.file_name().to_str() {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::history::tests::{write_history, Changes};
+    use crate::snapshots::repo::SnapshotFile;
+    use crate::testutil::tests::write_headers;
+
+    fn headers(from: u64, to: u64) -> String {
+        SnapshotFile::stem(SnapshotType::Headers, from, to)
+    }
+
+    fn names(plan: &RetentionPlan) -> Vec<String> {
+        plan.files()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_block_retention() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        // 0..2000 is a merge of the first two
+        for range in [0..1000, 1000..2000, 0..2000, 2000..3000] {
+            write_headers(dir, range);
+        }
+        let repo = SnapshotRepo::open(dir).unwrap();
+        let manifest_path = dir.join("preverified.toml");
+        repo.write_manifest(&manifest_path).unwrap();
+
+        // 0..2000 straddles the horizon and covers the files it was merged
+        // from
+        let plan = RetentionPlan::blocks(&repo, 1500).unwrap();
+        let stems = [headers(0, 1000), headers(1000, 2000)];
+        let mut expected: Vec<String> = stems
+            .iter()
+            .flat_map(|stem| {
+                ["dictionary.txt", "idx", "idx.torrent", "seg", "seg.torrent"]
+                    .map(|ext| format!("{}.{}", stem, ext))
+            })
+            .collect();
+        expected.sort();
+        assert_eq!(names(&plan), expected);
+
+        plan.apply(Some(&manifest_path)).unwrap();
+        for name in &expected {
+            assert!(!dir.join(name).exists(), "{}", name);
+        }
+        assert!(!dir.join(STAGING_DIR).exists());
+        let manifest = Manifest::open(&manifest_path).unwrap();
+        let listed: Vec<&str> = manifest.entries.iter().map(|e| e.name.as_str()).collect();
+        let mut kept: Vec<String> = [headers(0, 2000), headers(2000, 3000)]
+            .iter()
+            .flat_map(|stem| ["idx", "seg"].map(|ext| format!("{}.{}", stem, ext)))
+            .collect();
+        kept.sort();
+        assert_eq!(listed, kept);
+
+        let repo = SnapshotRepo::open(dir).unwrap();
+        let plan = RetentionPlan::blocks(&repo, 2000).unwrap();
+        assert_eq!(plan.files().len(), 5);
+        plan.apply(None).unwrap();
+        let repo = SnapshotRepo::open(dir).unwrap();
+        assert_eq!(repo.files().len(), 1);
+        assert!(RetentionPlan::blocks(&repo, 2000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_retention_refuses_gaps() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        for range in [0..1000, 2000..3000] {
+            write_headers(dir, range);
+        }
+        let repo = SnapshotRepo::open(dir).unwrap();
+        // Keeping 1000.. needs the missing 1000..2000
+        assert!(matches!(
+            RetentionPlan::blocks(&repo, 1000),
+            Err(SnapshotError::RetentionGap {
+                from: 1000,
+                to: 2000,
+                ..
+            })
+        ));
+        // Nothing goes below 500, so the gap is left as it is
+        assert!(RetentionPlan::blocks(&repo, 500).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_history_retention() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        let changes: Vec<(Vec<u8>, Changes)> = vec![(vec![0xaa; 20], vec![(5, vec![1])])];
+        write_history(dir, "v1-code.0-1", &changes, true);
+        write_history(dir, "v1-code.1-2", &changes, true);
+        // A domain file with the same stem stays
+        fs::write(dir.join("v1-code.0-1.kv"), b"").unwrap();
+
+        let plan = RetentionPlan::history(dir, 1).unwrap();
+        assert_eq!(
+            names(&plan),
+            [
+                "v1-code.0-1.ef",
+                "v1-code.0-1.efi",
+                "v1-code.0-1.v",
+                "v1-code.0-1.vi"
+            ]
+        );
+        plan.apply(None).unwrap();
+        assert!(dir.join("v1-code.0-1.kv").exists());
+        assert!(dir.join("v1-code.1-2.v").exists());
+    }
+}
@@ -0,0 +1,111 @@
+//! Index salts shared by all snapshot files of a datadir
+//!
+//! Erigon seeds every RecSplit index it builds with a salt stored next to the
+//! snapshots: `salt-blocks.txt` for block segments and `salt-state.txt` for
+//! state (domain/history) files. Despite the extension, the file holds the
+//! salt as 4 big-endian bytes. Indices built with a different salt still
+//! work on their own, but Erigon rejects them when validating a datadir.
+
+use crate::snapshots::recsplit::random_salt;
+use crate::snapshots::{Result, SnapshotError};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Which family of snapshot files a salt belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaltKind {
+    Blocks,
+    State,
+}
+
+impl SaltKind {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            SaltKind::Blocks => "salt-blocks.txt",
+            SaltKind::State => "salt-state.txt",
+        }
+    }
+}
+
+/// Path of the salt file of `kind` in snapshot directory `dir`
+pub fn salt_path(dir: &Path, kind: SaltKind) -> PathBuf {
+    dir.join(kind.file_name())
+}
+
+/// Read the salt of `kind` from `dir`; `None` if there is no salt file yet
+pub fn read_salt(dir: &Path, kind: SaltKind) -> Result<Option<u32>> {
+    let path = salt_path(dir, kind);
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let bytes: [u8; 4] = bytes.as_slice().try_into().map_err(|_| {
+        SnapshotError::InvalidFormat(format!(
+            "Salt file {} has {} bytes, expected 4",
+            path.display(),
+            bytes.len()
+        ))
+    })?;
+    Ok(Some(u32::from_be_bytes(bytes)))
+}
+
+/// Read the salt of `kind` from `dir`, generating and storing a random one
+/// if there is none
+///
+/// Like Erigon, an empty salt file (left behind by an interrupted write) is
+/// replaced; any other malformed file is an error.
+// From Go: erigon snaptype ReadAndCreateSaltIfNeeded
+pub fn read_or_create_salt(dir: &Path, kind: SaltKind) -> Result<u32> {
+    let path = salt_path(dir, kind);
+    if fs::metadata(&path).is_ok_and(|m| m.len() > 0) {
+        if let Some(salt) = read_salt(dir, kind)? {
+            return Ok(salt);
+        }
+    }
+
+    fs::create_dir_all(dir)?;
+    let salt = random_salt();
+    let mut file = File::create(&path)?;
+    file.write_all(&salt.to_be_bytes())?;
+    file.sync_all()?;
+    tracing::info!("Created {} with a new salt", path.display());
+    Ok(salt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_or_create_salt() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        assert_eq!(read_salt(dir, SaltKind::Blocks).unwrap(), None);
+
+        let salt = read_or_create_salt(dir, SaltKind::Blocks).unwrap();
+        assert_eq!(
+            fs::read(dir.join("salt-blocks.txt")).unwrap(),
+            salt.to_be_bytes()
+        );
+        // Stable once created, and independent per kind
+        assert_eq!(read_or_create_salt(dir, SaltKind::Blocks).unwrap(), salt);
+        assert_eq!(read_salt(dir, SaltKind::State).unwrap(), None);
+    }
+
+    #[test]
+    fn test_malformed_salt_file() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+
+        fs::write(salt_path(dir, SaltKind::State), [1, 2, 3]).unwrap();
+        assert!(read_salt(dir, SaltKind::State).is_err());
+        assert!(read_or_create_salt(dir, SaltKind::State).is_err());
+
+        // An empty file is treated as missing
+        fs::write(salt_path(dir, SaltKind::State), []).unwrap();
+        let salt = read_or_create_salt(dir, SaltKind::State).unwrap();
+        assert_eq!(read_salt(dir, SaltKind::State).unwrap(), Some(salt));
+    }
+}
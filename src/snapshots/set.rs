@@ -0,0 +1,241 @@
+//! All segments of one type, read as one
+//!
+//! A snapshot directory holds each type in many files of consecutive block
+//! ranges, and after merges also files that cover several smaller ones.
+//! [`SegmentSet`] opens the files of one type once, drops those a bigger
+//! file covers, and finds the segment holding a block number or TxNum with
+//! a binary search, so that callers address the whole chain at once.
+
+use crate::snapshots::reader::{
+    BodiesReader, HeadersReader, StoredBody, StoredTransaction, TransactionsReader,
+};
+use crate::snapshots::repo::{SnapshotFile, SnapshotRepo, SnapshotType};
+use crate::snapshots::types::{BlockNumber, TxNum};
+use crate::snapshots::{Result, SnapshotError};
+use alloy_consensus::Header;
+use std::ops::Range;
+use std::path::Path;
+
+/// A segment reader a [`SegmentSet`] is made of
+pub trait SegmentSetMember: Sized {
+    /// Type of the segments the reader opens
+    const KIND: SnapshotType;
+
+    fn open(path: &Path) -> Result<Self>;
+
+    /// Id of the first word: a block number, or a TxNum for transactions;
+    /// `None` if the segment cannot tell
+    fn first_id(&self, file: &SnapshotFile) -> Option<u64>;
+
+    /// Number of words
+    fn count(&self) -> usize;
+}
+
+impl SegmentSetMember for HeadersReader {
+    const KIND: SnapshotType = SnapshotType::Headers;
+
+    fn open(path: &Path) -> Result<Self> {
+        HeadersReader::new(path)
+    }
+
+    fn first_id(&self, file: &SnapshotFile) -> Option<u64> {
+        Some(self.first_block().map_or(file.from_block, |first| first.0))
+    }
+
+    fn count(&self) -> usize {
+        HeadersReader::count(self)
+    }
+}
+
+impl SegmentSetMember for BodiesReader {
+    const KIND: SnapshotType = SnapshotType::Bodies;
+
+    fn open(path: &Path) -> Result<Self> {
+        BodiesReader::new(path)
+    }
+
+    fn first_id(&self, file: &SnapshotFile) -> Option<u64> {
+        Some(self.first_block().map_or(file.from_block, |first| first.0))
+    }
+
+    fn count(&self) -> usize {
+        BodiesReader::count(self)
+    }
+}
+
+impl SegmentSetMember for TransactionsReader {
+    const KIND: SnapshotType = SnapshotType::Transactions;
+
+    fn open(path: &Path) -> Result<Self> {
+        TransactionsReader::new(path)
+    }
+
+    // TxNums are not in the file name, only the index knows them
+    fn first_id(&self, _file: &SnapshotFile) -> Option<u64> {
+        self.first_tx_num().map(|first| first.0)
+    }
+
+    fn count(&self) -> usize {
+        TransactionsReader::count(self)
+    }
+}
+
+struct Member<T> {
+    // Ids of the segment's words
+    ids: Range<u64>,
+    file: SnapshotFile,
+    reader: T,
+}
+
+/// The segments of one type, addressed by block number or TxNum
+pub struct SegmentSet<T> {
+    // Sorted by id, not overlapping
+    members: Vec<Member<T>>,
+}
+
+impl<T: SegmentSetMember> SegmentSet<T> {
+    /// Open the segments of `T`'s type in `repo`
+    pub fn open(repo: &SnapshotRepo) -> Result<Self> {
+        Self::from_files(repo.files_of(T::KIND))
+    }
+
+    /// Open `files`, skipping those of other types and those another file
+    /// covers
+    pub fn from_files<'a>(files: impl IntoIterator<Item = &'a SnapshotFile>) -> Result<Self> {
+        let mut files: Vec<&SnapshotFile> = files
+            .into_iter()
+            .filter(|file| file.kind == T::KIND)
+            .collect();
+        // Biggest first among files starting at the same block
+        files.sort_by_key(|file| (file.from_block, std::cmp::Reverse(file.to_block)));
+
+        let mut members: Vec<Member<T>> = Vec::new();
+        let mut covered_to = 0;
+        for file in files {
+            if file.to_block <= covered_to {
+                tracing::debug!("Skipping {}: covered by a bigger file", file.path.display());
+                continue;
+            }
+            let reader = T::open(&file.path)?;
+            let first = reader
+                .first_id(file)
+                .ok_or(SnapshotError::IndexNotAvailable)?;
+            let ids = first..first + reader.count() as u64;
+            if let Some(last) = members.last() {
+                if ids.start < last.ids.end {
+                    return Err(SnapshotError::InvalidFormat(format!(
+                        "{} starts at {}, inside {} ending at {}",
+                        file.path.display(),
+                        ids.start,
+                        last.file.path.display(),
+                        last.ids.end
+                    )));
+                }
+            }
+            covered_to = file.to_block;
+            members.push(Member {
+                ids,
+                file: file.clone(),
+                reader,
+            });
+        }
+        Ok(Self { members })
+    }
+
+    /// The segment holding `id`, with the ordinal of `id` in it
+    pub fn locate(&self, id: u64) -> Option<(&T, u64)> {
+        let i = self.members.partition_point(|member| member.ids.end <= id);
+        let member = self.members.get(i)?;
+        member
+            .ids
+            .contains(&id)
+            .then(|| (&member.reader, id - member.ids.start))
+    }
+
+    /// Ids from the first segment's first to the last one's end; there may
+    /// be gaps between segments
+    pub fn ids(&self) -> Option<Range<u64>> {
+        Some(self.members.first()?.ids.start..self.members.last()?.ids.end)
+    }
+
+    /// The segments in id order, with the ids of their words
+    pub fn segments(&self) -> impl Iterator<Item = (&SnapshotFile, Range<u64>, &T)> {
+        self.members
+            .iter()
+            .map(|member| (&member.file, member.ids.clone(), &member.reader))
+    }
+
+    /// Number of segments
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+impl SegmentSet<HeadersReader> {
+    /// The header of block `number`, from whichever segment holds it
+    pub fn header(&self, number: BlockNumber) -> Result<Option<Header>> {
+        match self.locate(number.0) {
+            Some((reader, i)) => Ok(reader.header(i)?.map(|(_, header)| header)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl SegmentSet<BodiesReader> {
+    /// The body of block `number`, from whichever segment holds it
+    pub fn body(&self, number: BlockNumber) -> Result<Option<StoredBody>> {
+        match self.locate(number.0) {
+            Some((reader, i)) => reader.body(i),
+            None => Ok(None),
+        }
+    }
+}
+
+impl SegmentSet<TransactionsReader> {
+    /// The transaction `tx_num`, `None` for system transactions and TxNums
+    /// no segment holds
+    pub fn transaction(&self, tx_num: TxNum) -> Result<Option<StoredTransaction>> {
+        match self.locate(tx_num.0) {
+            Some((reader, _)) => reader.transaction(tx_num),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::tests::write_headers;
+
+    #[test]
+    fn test_headers_across_segments() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        // 0..2000 covers the first two, and 4000..5000 leaves a gap
+        for range in [0..1000, 1000..2000, 0..2000, 2000..3000, 4000..5000] {
+            write_headers(tmp_dir.path(), range);
+        }
+        let repo = SnapshotRepo::open(tmp_dir.path()).unwrap();
+        let set = SegmentSet::<HeadersReader>::open(&repo).unwrap();
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.ids(), Some(0..5000));
+        let ranges: Vec<_> = set.segments().map(|(_, ids, _)| ids).collect();
+        assert_eq!(ranges, [0..2000, 2000..3000, 4000..5000]);
+
+        for number in [0, 999, 1000, 1999, 2000, 2999, 4000, 4999] {
+            let header = set.header(BlockNumber(number)).unwrap().unwrap();
+            assert_eq!(header.number, number);
+        }
+        for number in [3000, 3999, 5000] {
+            assert!(set.header(BlockNumber(number)).unwrap().is_none());
+        }
+        let (_, i) = set.locate(2500).unwrap();
+        assert_eq!(i, 500);
+
+        // No bodies in the directory
+        assert!(SegmentSet::<BodiesReader>::open(&repo).unwrap().is_empty());
+    }
+}
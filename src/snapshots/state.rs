@@ -0,0 +1,536 @@
+//! Historical account and storage lookups over Erigon 3 state files
+//!
+//! The value a key had as of a txNum is the value recorded before its first
+//! change at or after that txNum, found through the history files
+//! ([`InvertedIndexReader`] and [`HistoryReader`]). A key that never changed
+//! again has its latest value, found in the domain files
+//! ([`DomainReader`]). Changes made after the last frozen step only live in
+//! Erigon's database, so lookups past the files answer with the latest
+//! frozen value.
+
+use crate::decompress::Decompressor;
+use crate::seg_reader::{detect_compress_type, FileCompression};
+use crate::snapshots::recsplit::RecSplitIndex;
+use crate::snapshots::registry::DomainKind;
+use crate::snapshots::repo::StateFile;
+use crate::snapshots::types::{BlockNumber, TxNum};
+use crate::snapshots::{DomainReader, HistoryReader, InvertedIndexReader, Result, SnapshotError};
+use alloy_consensus::constants::KECCAK_EMPTY;
+use alloy_primitives::{Address, B256, U256};
+use std::path::Path;
+
+// Where Erigon 3 keeps each kind of state file in a snapshot directory
+const STATE_SUBDIRS: [&str; 4] = ["domain", "history", "idx", "accessor"];
+
+// Extensions of the state files holding data, with those of their
+// accessors
+pub(super) const STATE_ACCESSORS: [(&str, &[&str]); 3] = [
+    ("kv", &["bt", "kvei", "kvi"]),
+    ("v", &["vi"]),
+    ("ef", &["efi"]),
+];
+
+/// Maps blocks to the txNums of their transactions
+///
+/// Erigon numbers every transaction of the chain, with a system transaction
+/// before and after the user transactions of each block. The mapping lives
+/// in Erigon's database rather than in the snapshot files, so it is given
+/// as the last txNum of each block from block 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxNums {
+    max_tx_nums: Vec<u64>,
+}
+
+impl TxNums {
+    /// `max_tx_nums[n]` is the last txNum of block `n`
+    pub fn new(max_tx_nums: Vec<u64>) -> Self {
+        Self { max_tx_nums }
+    }
+
+    /// Last txNum of `block`
+    pub fn max(&self, block: BlockNumber) -> Option<TxNum> {
+        let max = self.max_tx_nums.get(usize::try_from(block.get()).ok()?)?;
+        Some(TxNum(*max))
+    }
+
+    /// First txNum of `block`
+    pub fn min(&self, block: BlockNumber) -> Option<TxNum> {
+        self.max(block)?;
+        match block.get() {
+            0 => Some(TxNum(0)),
+            n => self.max(BlockNumber(n - 1)).map(|max| max.offset(1)),
+        }
+    }
+}
+
+/// An account as stored in the accounts domain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct Account {
+    pub nonce: u64,
+    pub balance: U256,
+    pub code_hash: B256,
+    pub incarnation: u64,
+}
+
+impl Account {
+    /// Decode an accounts domain value: nonce, balance, code hash and
+    /// incarnation, each as a length byte followed by that many big-endian
+    /// bytes (an empty code hash meaning no code)
+    // From Go: erigon-lib/types/accounts/account.go DeserialiseV3
+    pub fn decode(value: &[u8]) -> Result<Self> {
+        let mut rest = value;
+        let mut field = |name: &str| -> Result<&[u8]> {
+            let (&len, tail) = rest.split_first().ok_or_else(|| truncated(name))?;
+            let (bytes, tail) = tail
+                .split_at_checked(len as usize)
+                .ok_or_else(|| truncated(name))?;
+            rest = tail;
+            Ok(bytes)
+        };
+        let nonce = field("nonce")?;
+        let balance = field("balance")?;
+        let code_hash = field("code hash")?;
+        let incarnation = field("incarnation")?;
+        if nonce.len() > 8 || balance.len() > 32 || incarnation.len() > 8 {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Oversized account field in {}",
+                hex::encode(value)
+            )));
+        }
+        let code_hash = match code_hash.len() {
+            0 => KECCAK_EMPTY,
+            32 => B256::from_slice(code_hash),
+            len => {
+                return Err(SnapshotError::InvalidFormat(format!(
+                    "Account code hash of {} bytes",
+                    len
+                )))
+            }
+        };
+        if !rest.is_empty() {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "{} trailing bytes after account",
+                rest.len()
+            )));
+        }
+        Ok(Account {
+            nonce: be_u64(nonce),
+            balance: U256::from_be_slice(balance),
+            code_hash,
+            incarnation: be_u64(incarnation),
+        })
+    }
+
+    /// Encode as an accounts domain value
+    // From Go: erigon-lib/types/accounts/account.go SerialiseV3
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = Vec::new();
+        let mut field = |bytes: &[u8]| {
+            value.push(bytes.len() as u8);
+            value.extend_from_slice(bytes);
+        };
+        field(trim_leading_zeros(&self.nonce.to_be_bytes()));
+        field(trim_leading_zeros(&self.balance.to_be_bytes::<32>()));
+        if self.code_hash == KECCAK_EMPTY {
+            field(&[]);
+        } else {
+            field(self.code_hash.as_slice());
+        }
+        field(trim_leading_zeros(&self.incarnation.to_be_bytes()));
+        value
+    }
+}
+
+fn truncated(field: &str) -> SnapshotError {
+    SnapshotError::InvalidFormat(format!("Account value truncated in {}", field))
+}
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &b| (n << 8) | b as u64)
+}
+
+fn trim_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+// The files of one domain, with merged-away files left out
+#[derive(Default)]
+pub(super) struct StateDomain {
+    // Newest first
+    latest: Vec<DomainReader>,
+    // Oldest first, inverted index and values of the same step range
+    history: Vec<(InvertedIndexReader, HistoryReader)>,
+}
+
+impl StateDomain {
+    pub(super) fn open(files: &[StateFile], domain: DomainKind) -> Result<Self> {
+        let of = |extension: &str| {
+            visible(
+                files
+                    .iter()
+                    .filter(|f| f.name == domain.name() && f.extension == extension)
+                    .collect(),
+            )
+        };
+        let mut domain = StateDomain::default();
+        for file in of("kv").into_iter().rev() {
+            domain.latest.push(DomainReader::new(&file.path)?);
+        }
+        let values = of("v");
+        for ef in of("ef") {
+            let Some(v) = values
+                .iter()
+                .find(|v| (v.from_step, v.to_step) == (ef.from_step, ef.to_step))
+            else {
+                tracing::warn!("No history values for {}, skipping it", ef.path.display());
+                continue;
+            };
+            domain.history.push((
+                open_with_accessor(&ef.path, files, "efi", |path, index| {
+                    InvertedIndexReader::with_index(path, detect_compression(path)?, index)
+                })?,
+                open_with_accessor(&v.path, files, "vi", |path, index| {
+                    HistoryReader::with_index(path, detect_compression(path)?, index)
+                })?,
+            ));
+        }
+        Ok(domain)
+    }
+
+    // History, then the domain's latest
+    fn get_as_of(&self, key: &[u8], tx_num: u64) -> Result<Option<Vec<u8>>> {
+        match self.history_seek(key, tx_num)? {
+            Some(value) => Ok(Some(value)),
+            None => self.latest(key),
+        }
+    }
+
+    // The value before the first change at or after `tx_num`, `None` if the
+    // key did not change again
+    // From Go: history.go HistoryRoTx.HistorySeek
+    pub(super) fn history_seek(&self, key: &[u8], tx_num: u64) -> Result<Option<Vec<u8>>> {
+        for (ii, values) in &self.history {
+            if let Some(found) = ii.seek(key, tx_num)? {
+                return values.get(ii, key, found)?.map(Some).ok_or_else(|| {
+                    SnapshotError::InvalidFormat(format!(
+                        "No history value for change {} of {}",
+                        found,
+                        hex::encode(key)
+                    ))
+                });
+            }
+        }
+        Ok(None)
+    }
+
+    // The value in the newest domain file that has the key
+    pub(super) fn latest(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        for domain in &self.latest {
+            if let Some(value) = domain.get(key)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    // The inverted indexes of the history, oldest first
+    pub(super) fn inverted_indexes(&self) -> impl DoubleEndedIterator<Item = &InvertedIndexReader> {
+        self.history.iter().map(|(ii, _)| ii)
+    }
+}
+
+// Files sorted by range, leaving out those inside a larger file's range
+pub(super) fn visible(mut files: Vec<&StateFile>) -> Vec<&StateFile> {
+    files.sort_by_key(|f| (f.from_step, std::cmp::Reverse(f.to_step)));
+    let mut kept: Vec<&StateFile> = Vec::new();
+    for file in files {
+        match kept.last() {
+            Some(last) if file.to_step <= last.to_step => {}
+            _ => kept.push(file),
+        }
+    }
+    kept
+}
+
+pub(super) fn detect_compression(path: &Path) -> Result<FileCompression> {
+    Ok(detect_compress_type(&Decompressor::new(path)?))
+}
+
+// Open `path` with the accessor of the same stem and `extension`, wherever
+// in the directory it is
+pub(super) fn open_with_accessor<T>(
+    path: &Path,
+    files: &[StateFile],
+    extension: &str,
+    open: impl FnOnce(&Path, Option<RecSplitIndex>) -> Result<T>,
+) -> Result<T> {
+    let stem = path.file_stem();
+    let accessor = files
+        .iter()
+        .find(|f| f.extension == extension && f.path.file_stem() == stem);
+    let index = accessor.map(|f| RecSplitIndex::open(&f.path)).transpose()?;
+    open(path, index)
+}
+
+// The state files in `dir` and its `domain`, `history`, `idx` and
+// `accessor` subdirectories
+pub(super) fn state_files(dir: &Path) -> Result<Vec<StateFile>> {
+    let mut files = Vec::new();
+    let dirs = std::iter::once(dir.to_path_buf()).chain(STATE_SUBDIRS.map(|sub| dir.join(sub)));
+    for dir in dirs.filter(|dir| dir.is_dir()) {
+        for entry in std::fs::read_dir(&dir)? {
+            if let Some(file) = StateFile::parse(&entry?.path()) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Historical account and storage lookups over a snapshot directory
+///
+/// Reads the `accounts` and `storage` domains. Storage keys are the address
+/// followed by the slot.
+pub struct StateReader {
+    tx_nums: TxNums,
+    accounts: StateDomain,
+    storage: StateDomain,
+}
+
+impl StateReader {
+    /// Open the state files of the snapshot directory `dir`
+    ///
+    /// Files are looked for in `dir` and its `domain`, `history`, `idx` and
+    /// `accessor` subdirectories.
+    pub fn open(dir: impl AsRef<Path>, tx_nums: TxNums) -> Result<Self> {
+        let files = state_files(dir.as_ref())?;
+        Ok(Self {
+            tx_nums,
+            accounts: StateDomain::open(&files, DomainKind::Accounts)?,
+            storage: StateDomain::open(&files, DomainKind::Storage)?,
+        })
+    }
+
+    /// The account `address` as of after block `block`, `None` if it did
+    /// not exist then
+    pub fn account_at(&self, address: Address, block: BlockNumber) -> Result<Option<Account>> {
+        self.account_as_of(address, self.tx_num_after(block)?)
+    }
+
+    /// Storage slot `slot` of `address` as of after block `block`
+    pub fn storage_at(&self, address: Address, slot: B256, block: BlockNumber) -> Result<U256> {
+        self.storage_as_of(address, slot, self.tx_num_after(block)?)
+    }
+
+    /// The account `address` as of before txNum `tx_num`
+    pub fn account_as_of(&self, address: Address, tx_num: TxNum) -> Result<Option<Account>> {
+        match self.accounts.get_as_of(address.as_slice(), tx_num.get())? {
+            // Erased or not yet created
+            Some(value) if value.is_empty() => Ok(None),
+            Some(value) => Account::decode(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Storage slot `slot` of `address` as of before txNum `tx_num`
+    pub fn storage_as_of(&self, address: Address, slot: B256, tx_num: TxNum) -> Result<U256> {
+        let mut key = address.to_vec();
+        key.extend_from_slice(slot.as_slice());
+        match self.storage.get_as_of(&key, tx_num.get())? {
+            Some(value) if value.len() > 32 => Err(SnapshotError::InvalidFormat(format!(
+                "Storage value of {} bytes",
+                value.len()
+            ))),
+            Some(value) => Ok(U256::from_be_slice(&value)),
+            None => Ok(U256::ZERO),
+        }
+    }
+
+    // State after `block` is the state before the first txNum of the next
+    fn tx_num_after(&self, block: BlockNumber) -> Result<TxNum> {
+        self.tx_nums
+            .max(block)
+            .map(|max| max.offset(1))
+            .ok_or(SnapshotError::BlockNotFound(block.get()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::history::tests::{write_history, Changes};
+    use crate::testutil::write_segment;
+
+    fn account(nonce: u64, balance: u64) -> Account {
+        Account {
+            nonce,
+            balance: U256::from(balance),
+            code_hash: KECCAK_EMPTY,
+            incarnation: 0,
+        }
+    }
+
+    fn write_domain(path: &Path, pairs: &[(Vec<u8>, Vec<u8>)]) {
+        let words: Vec<&Vec<u8>> = pairs.iter().flat_map(|(key, value)| [key, value]).collect();
+        write_segment(path, &words).unwrap();
+    }
+
+    #[test]
+    fn test_account_encoding() {
+        for account in [
+            account(0, 0),
+            account(1, 1_000_000_000_000_000_000),
+            Account {
+                nonce: u64::MAX,
+                balance: U256::MAX,
+                code_hash: B256::repeat_byte(0xcc),
+                incarnation: 2,
+            },
+        ] {
+            assert_eq!(Account::decode(&account.encode()).unwrap(), account);
+        }
+        assert_eq!(account(0, 0).encode(), [0, 0, 0, 0]);
+        assert_eq!(account(5, 256).encode(), [1, 5, 2, 1, 0, 0, 0]);
+        for bad in [&[1u8][..], &[0, 0, 3, 1, 2, 3, 0], &[0, 0, 0, 0, 0]] {
+            assert!(Account::decode(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_tx_nums() {
+        // Block 0 has no user transactions, block 1 three
+        let tx_nums = TxNums::new(vec![1, 6, 8]);
+        let block = |n| (tx_nums.min(BlockNumber(n)), tx_nums.max(BlockNumber(n)));
+        assert_eq!(block(0), (Some(TxNum(0)), Some(TxNum(1))));
+        assert_eq!(block(1), (Some(TxNum(2)), Some(TxNum(6))));
+        assert_eq!(block(3), (None, None));
+        assert_eq!(TxNums::default().min(BlockNumber(0)), None);
+    }
+
+    #[test]
+    fn test_state_at_block() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        for sub in STATE_SUBDIRS {
+            std::fs::create_dir(dir.join(sub)).unwrap();
+        }
+        // Blocks of 10 txNums: block n spans 10n..10n+9
+        let tx_nums = TxNums::new((0..100).map(|n| n * 10 + 9).collect());
+
+        let alice = Address::repeat_byte(0xa1);
+        let bob = Address::repeat_byte(0xb0);
+        let carol = Address::repeat_byte(0xc0);
+        let slot = B256::with_last_byte(1);
+        let mut bob_slot = bob.to_vec();
+        bob_slot.extend_from_slice(slot.as_slice());
+
+        // Alice is created in block 2 and changes in blocks 5 and 12; Bob
+        // is created in block 12; Carol only appears in the latest state
+        let accounts: Vec<(Vec<u8>, Changes)> = vec![
+            (
+                alice.to_vec(),
+                vec![
+                    (23, Vec::new()),
+                    (55, account(1, 100).encode()),
+                    (124, account(2, 50).encode()),
+                ],
+            ),
+            (bob.to_vec(), vec![(121, Vec::new())]),
+        ];
+        let storage: Vec<(Vec<u8>, Changes)> =
+            vec![(bob_slot.clone(), vec![(125, Vec::new()), (300, vec![7])])];
+        // Two history files, the first split at txNum 100 (step 1)
+        write_history(
+            &dir.join("idx"),
+            "v1-accounts.0-1",
+            &accounts[..1]
+                .iter()
+                .map(|(k, c)| (k.clone(), c[..2].to_vec()))
+                .collect::<Vec<_>>(),
+            false,
+        );
+        write_history(
+            &dir.join("idx"),
+            "v1-accounts.1-4",
+            &[
+                (alice.to_vec(), accounts[0].1[2..].to_vec()),
+                accounts[1].clone(),
+            ],
+            true,
+        );
+        write_history(&dir.join("idx"), "v1-storage.0-4", &storage, false);
+        // The values sit with the inverted indices; Erigon keeps them apart
+        for name in ["v1-accounts.1-4.v", "v1-accounts.1-4.vi"] {
+            std::fs::rename(dir.join("idx").join(name), dir.join("history").join(name)).unwrap();
+        }
+        write_domain(
+            &dir.join("domain").join("v1-accounts.0-4.kv"),
+            &[
+                (alice.to_vec(), account(3, 10).encode()),
+                (bob.to_vec(), account(1, 0).encode()),
+                (carol.to_vec(), account(7, 7).encode()),
+            ],
+        );
+        write_domain(
+            &dir.join("domain").join("v1-storage.0-4.kv"),
+            &[(bob_slot.clone(), vec![0x01, 0x00])],
+        );
+        // A file merged into 0-4 and not yet removed is ignored
+        write_domain(
+            &dir.join("domain").join("v1-accounts.0-1.kv"),
+            &[(alice.to_vec(), account(9, 9).encode())],
+        );
+
+        let state = StateReader::open(dir, tx_nums).unwrap();
+        let alice_at = |block| state.account_at(alice, BlockNumber(block)).unwrap();
+        assert_eq!(alice_at(1), None);
+        assert_eq!(alice_at(2), Some(account(1, 100)));
+        assert_eq!(alice_at(4), Some(account(1, 100)));
+        assert_eq!(alice_at(5), Some(account(2, 50)));
+        assert_eq!(alice_at(11), Some(account(2, 50)));
+        assert_eq!(alice_at(12), Some(account(3, 10)));
+        assert_eq!(alice_at(99), Some(account(3, 10)));
+
+        assert_eq!(state.account_at(bob, BlockNumber(11)).unwrap(), None);
+        assert_eq!(
+            state.account_at(bob, BlockNumber(12)).unwrap(),
+            Some(account(1, 0))
+        );
+        assert_eq!(
+            state.account_at(carol, BlockNumber(0)).unwrap(),
+            Some(account(7, 7))
+        );
+        assert_eq!(
+            state.account_at(Address::ZERO, BlockNumber(50)).unwrap(),
+            None
+        );
+
+        assert_eq!(
+            state.storage_at(bob, slot, BlockNumber(11)).unwrap(),
+            U256::ZERO
+        );
+        assert_eq!(
+            state.storage_at(bob, slot, BlockNumber(12)).unwrap(),
+            U256::from(7)
+        );
+        assert_eq!(
+            state.storage_at(bob, slot, BlockNumber(30)).unwrap(),
+            U256::from(256)
+        );
+        assert_eq!(
+            state.storage_at(bob, B256::ZERO, BlockNumber(30)).unwrap(),
+            U256::ZERO
+        );
+
+        assert!(matches!(
+            state.account_at(alice, BlockNumber(100)),
+            Err(SnapshotError::BlockNotFound(100))
+        ));
+    }
+}
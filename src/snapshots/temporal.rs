@@ -0,0 +1,330 @@
+//! Erigon's temporal key-value interface over snapshot files
+//!
+//! Erigon code reads state through `kv.TemporalTx`: the latest value of a
+//! key in a domain (`DomainGet`), the value it had before a txNum from the
+//! domain's history (`HistorySeek`), and the txNums at which a key changed
+//! from an inverted index (`IndexRange`). [`TemporalTx`] mirrors those
+//! calls, and [`TemporalReader`] answers them from the state files of a
+//! snapshot directory, so logic ported from Erigon can take the trait and
+//! run on the files. Changes made after the last frozen step only live in
+//! Erigon's database and are not seen.
+
+use crate::snapshots::history::{InvertedIndexReader, TxNumSequence};
+use crate::snapshots::registry::DomainKind;
+use crate::snapshots::repo::StateFile;
+use crate::snapshots::state::{
+    detect_compression, open_with_accessor, state_files, visible, StateDomain,
+};
+use crate::snapshots::types::TxNum;
+use crate::snapshots::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+
+/// An inverted index: a domain's history index, or one standing alone
+// From Go: erigon-lib/kv/tables.go InvertedIdx
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InvertedIdx {
+    Domain(DomainKind),
+    LogAddrs,
+    LogTopics,
+    TracesFrom,
+    TracesTo,
+}
+
+impl InvertedIdx {
+    /// The standalone indexes
+    pub const STANDALONE: [InvertedIdx; 4] = [
+        InvertedIdx::LogAddrs,
+        InvertedIdx::LogTopics,
+        InvertedIdx::TracesFrom,
+        InvertedIdx::TracesTo,
+    ];
+
+    /// The index as it appears in file names
+    pub fn name(self) -> &'static str {
+        match self {
+            InvertedIdx::Domain(domain) => domain.name(),
+            InvertedIdx::LogAddrs => "logaddrs",
+            InvertedIdx::LogTopics => "logtopics",
+            InvertedIdx::TracesFrom => "tracesfrom",
+            InvertedIdx::TracesTo => "tracesto",
+        }
+    }
+}
+
+impl fmt::Display for InvertedIdx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Order of the txNums from [`TemporalTx::index_range`]
+// From Go: erigon-lib/kv/order By
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Reads of Erigon's `kv.TemporalTx`, keyed by txNum
+pub trait TemporalTx {
+    /// The latest value of `key` in `domain`, `None` if it has none
+    // From Go: kv.TemporalTx DomainGet
+    fn domain_get(&self, domain: DomainKind, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// The value `key` had before its first change at or after `tx_num`,
+    /// `None` if it did not change again; an empty value means the key did
+    /// not exist
+    // From Go: kv.TemporalTx HistorySeek
+    fn history_seek(
+        &self,
+        domain: DomainKind,
+        key: &[u8],
+        tx_num: TxNum,
+    ) -> Result<Option<Vec<u8>>>;
+
+    /// The txNums in `tx_nums` at which `key` changed, in `order`, at most
+    /// `limit` of them
+    // From Go: kv.TemporalTx IndexRange
+    fn index_range(
+        &self,
+        index: InvertedIdx,
+        key: &[u8],
+        tx_nums: Range<TxNum>,
+        order: Order,
+        limit: Option<usize>,
+    ) -> Result<Vec<TxNum>>;
+
+    /// The value of `key` as of before `tx_num`: from the history if the key
+    /// changed since, the latest value otherwise
+    // From Go: kv.TemporalTx DomainGetAsOf
+    fn domain_get_as_of(
+        &self,
+        domain: DomainKind,
+        key: &[u8],
+        tx_num: TxNum,
+    ) -> Result<Option<Vec<u8>>> {
+        match self.history_seek(domain, key, tx_num)? {
+            Some(value) => Ok(Some(value)),
+            None => self.domain_get(domain, key),
+        }
+    }
+}
+
+/// [`TemporalTx`] over the state files of a snapshot directory
+///
+/// All files are opened up front; lookups only read them, so one reader
+/// serves any number of threads.
+pub struct TemporalReader {
+    domains: HashMap<DomainKind, StateDomain>,
+    // Standalone inverted indexes, oldest file first
+    indexes: HashMap<InvertedIdx, Vec<InvertedIndexReader>>,
+}
+
+impl TemporalReader {
+    /// Open the state files of the snapshot directory `dir`, looked for as
+    /// by [`StateReader::open`](crate::snapshots::StateReader::open)
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let files = state_files(dir.as_ref())?;
+        let mut domains = HashMap::new();
+        for domain in DomainKind::ALL {
+            domains.insert(domain, StateDomain::open(&files, domain)?);
+        }
+        let mut indexes = HashMap::new();
+        for index in InvertedIdx::STANDALONE {
+            indexes.insert(index, open_index(&files, index)?);
+        }
+        Ok(Self { domains, indexes })
+    }
+
+    /// The txNums of `key` in `index`, one sequence per file holding the
+    /// key, oldest first; combine them with
+    /// [`PostingList`](crate::snapshots::PostingList)
+    pub fn sequences(&self, index: InvertedIdx, key: &[u8]) -> Result<Vec<TxNumSequence>> {
+        let mut sequences = Vec::new();
+        for file in self.inverted_indexes(index) {
+            sequences.extend(file.tx_nums(key)?);
+        }
+        Ok(sequences)
+    }
+
+    fn inverted_indexes(&self, index: InvertedIdx) -> Vec<&InvertedIndexReader> {
+        match index {
+            InvertedIdx::Domain(domain) => self
+                .domains
+                .get(&domain)
+                .map(|domain| domain.inverted_indexes().collect())
+                .unwrap_or_default(),
+            _ => self
+                .indexes
+                .get(&index)
+                .map(|files| files.iter().collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl TemporalTx for TemporalReader {
+    fn domain_get(&self, domain: DomainKind, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.domains.get(&domain) {
+            Some(domain) => domain.latest(key),
+            None => Ok(None),
+        }
+    }
+
+    fn history_seek(
+        &self,
+        domain: DomainKind,
+        key: &[u8],
+        tx_num: TxNum,
+    ) -> Result<Option<Vec<u8>>> {
+        match self.domains.get(&domain) {
+            Some(domain) => domain.history_seek(key, tx_num.get()),
+            None => Ok(None),
+        }
+    }
+
+    fn index_range(
+        &self,
+        index: InvertedIdx,
+        key: &[u8],
+        tx_nums: Range<TxNum>,
+        order: Order,
+        limit: Option<usize>,
+    ) -> Result<Vec<TxNum>> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let range = tx_nums.start.get()..tx_nums.end.get();
+        let mut files = self.inverted_indexes(index);
+        if order == Order::Desc {
+            files.reverse();
+        }
+        let mut found = Vec::new();
+        for file in files {
+            if found.len() >= limit {
+                break;
+            }
+            let Some(sequence) = file.tx_nums(key)? else {
+                continue;
+            };
+            let in_range = |tx_num: &u64| range.contains(tx_num);
+            let left = limit - found.len();
+            match order {
+                Order::Asc => found.extend(
+                    sequence
+                        .iter()
+                        .skip_while(|tx_num| *tx_num < range.start)
+                        .take_while(in_range)
+                        .take(left)
+                        .map(TxNum),
+                ),
+                Order::Desc => {
+                    let mut tx_nums: Vec<u64> = sequence.iter().filter(in_range).collect();
+                    tx_nums.reverse();
+                    found.extend(tx_nums.into_iter().take(left).map(TxNum));
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+// The `.ef` files of a standalone index, with their `.efi` accessors
+fn open_index(files: &[StateFile], index: InvertedIdx) -> Result<Vec<InvertedIndexReader>> {
+    visible(
+        files
+            .iter()
+            .filter(|f| f.name == index.name() && f.extension == "ef")
+            .collect(),
+    )
+    .into_iter()
+    .map(|ef| {
+        open_with_accessor(&ef.path, files, "efi", |path, accessor| {
+            InvertedIndexReader::with_index(path, detect_compression(path)?, accessor)
+        })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::history::tests::{write_history, Changes};
+    use crate::testutil::write_segment;
+
+    #[test]
+    fn test_temporal_reads() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        let key = vec![0xaa; 20];
+        let other = vec![0xbb; 20];
+
+        // The key changed at txNums 5, 20 and 30, then 150 and 160
+        let early: Vec<(Vec<u8>, Changes)> = vec![
+            (
+                key.clone(),
+                vec![(5, Vec::new()), (20, vec![1]), (30, vec![2])],
+            ),
+            (other.clone(), vec![(7, Vec::new())]),
+        ];
+        let late: Vec<(Vec<u8>, Changes)> =
+            vec![(key.clone(), vec![(150, vec![3]), (160, vec![4])])];
+        write_history(dir, "v1-code.0-1", &early, true);
+        write_history(dir, "v1-code.1-2", &late, false);
+        write_segment(
+            &dir.join("v1-code.0-2.kv"),
+            &[&key, &vec![5], &other, &vec![9]],
+        )
+        .unwrap();
+        // A standalone index has no values
+        write_history(dir, "v1-logaddrs.0-2", &early, true);
+        std::fs::remove_file(dir.join("v1-logaddrs.0-2.v")).unwrap();
+
+        let tx: &dyn TemporalTx = &TemporalReader::open(dir).unwrap();
+        let code = DomainKind::Code;
+        assert_eq!(tx.domain_get(code, &key).unwrap(), Some(vec![5]));
+        assert_eq!(tx.domain_get(DomainKind::Accounts, &key).unwrap(), None);
+
+        assert_eq!(tx.history_seek(code, &key, TxNum(0)).unwrap(), Some(vec![]));
+        assert_eq!(
+            tx.history_seek(code, &key, TxNum(21)).unwrap(),
+            Some(vec![2])
+        );
+        assert_eq!(
+            tx.history_seek(code, &key, TxNum(31)).unwrap(),
+            Some(vec![3])
+        );
+        assert_eq!(tx.history_seek(code, &key, TxNum(161)).unwrap(), None);
+        assert_eq!(
+            tx.domain_get_as_of(code, &key, TxNum(155)).unwrap(),
+            Some(vec![4])
+        );
+        assert_eq!(
+            tx.domain_get_as_of(code, &key, TxNum(200)).unwrap(),
+            Some(vec![5])
+        );
+
+        let range = |index, from, to, order, limit| {
+            tx.index_range(index, &key, TxNum(from)..TxNum(to), order, limit)
+                .unwrap()
+                .into_iter()
+                .map(|tx_num| tx_num.get())
+                .collect::<Vec<_>>()
+        };
+        let code = InvertedIdx::Domain(code);
+        assert_eq!(
+            range(code, 0, u64::MAX, Order::Asc, None),
+            [5, 20, 30, 150, 160]
+        );
+        assert_eq!(range(code, 20, 160, Order::Asc, None), [20, 30, 150]);
+        assert_eq!(range(code, 6, 200, Order::Desc, Some(3)), [160, 150, 30]);
+        assert_eq!(range(code, 21, 151, Order::Asc, Some(1)), [30]);
+        assert_eq!(
+            range(InvertedIdx::LogAddrs, 0, 25, Order::Desc, None),
+            [20, 5]
+        );
+        assert!(range(InvertedIdx::TracesTo, 0, 100, Order::Asc, None).is_empty());
+    }
+}
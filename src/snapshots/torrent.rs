@@ -0,0 +1,526 @@
+//! `.torrent` metadata of snapshot files
+//!
+//! Erigon publishes every snapshot file with a single-file torrent next to
+//! it (`v1-000000-000500-headers.seg.torrent`). Its info dictionary carries
+//! the file length and the SHA-1 of every piece, which is enough to check a
+//! downloaded file without a torrent client.
+
+use crate::snapshots::mapped::FileData;
+use crate::snapshots::{Result, SnapshotError};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+const PIECE_HASH_LEN: usize = 20;
+
+/// Piece length of the torrents Erigon creates for its snapshot files
+pub const DEFAULT_PIECE_LENGTH: u64 = 2 << 20;
+
+/// The parts of a single-file torrent needed to validate its file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentInfo {
+    /// File name, as stored in the info dictionary
+    pub name: String,
+    /// Expected file length in bytes
+    pub length: u64,
+    /// Bytes per piece; the last piece may be shorter
+    pub piece_length: u64,
+    pieces: Vec<[u8; PIECE_HASH_LEN]>,
+    info_hash: [u8; PIECE_HASH_LEN],
+}
+
+impl TorrentInfo {
+    /// Parse a `.torrent` file
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// Parse the bencoded contents of a `.torrent` file
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut parser = Parser { data, pos: 0 };
+        let Value::Dict(root) = parser.value()? else {
+            return Err(invalid("top level is not a dictionary"));
+        };
+        if parser.pos != data.len() {
+            return Err(invalid("trailing data after top-level dictionary"));
+        }
+        let info_raw = root
+            .iter()
+            .find(|(key, _)| *key == b"info")
+            .map(|(_, value)| value)
+            .ok_or_else(|| invalid("missing info dictionary"))?;
+        let Value::Dict(info) = &info_raw.value else {
+            return Err(invalid("info is not a dictionary"));
+        };
+        let field = |name: &[u8]| {
+            info.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| &value.value)
+        };
+
+        if field(b"files").is_some() {
+            return Err(invalid("multi-file torrents are not snapshot torrents"));
+        }
+        let name = match field(b"name") {
+            Some(Value::Bytes(name)) => {
+                String::from_utf8(name.to_vec()).map_err(|_| invalid("name is not valid UTF-8"))?
+            }
+            _ => return Err(invalid("missing name")),
+        };
+        let length = match field(b"length") {
+            Some(&Value::Int(length)) if length >= 0 => length as u64,
+            _ => return Err(invalid("missing or negative length")),
+        };
+        let piece_length = match field(b"piece length") {
+            Some(&Value::Int(piece_length)) if piece_length > 0 => piece_length as u64,
+            _ => return Err(invalid("missing or non-positive piece length")),
+        };
+        let pieces = match field(b"pieces") {
+            Some(Value::Bytes(pieces)) if pieces.len().is_multiple_of(PIECE_HASH_LEN) => pieces
+                .chunks_exact(PIECE_HASH_LEN)
+                .map(|hash| hash.try_into().unwrap())
+                .collect::<Vec<_>>(),
+            _ => {
+                return Err(invalid(
+                    "missing pieces or pieces length not a multiple of 20",
+                ))
+            }
+        };
+        if pieces.len() as u64 != length.div_ceil(piece_length) {
+            return Err(invalid(&format!(
+                "{} piece hashes for {} bytes in pieces of {}",
+                pieces.len(),
+                length,
+                piece_length
+            )));
+        }
+
+        // The infohash covers the info dictionary exactly as encoded
+        let info_hash = sha1(&data[info_raw.start..info_raw.end]);
+        Ok(Self {
+            name,
+            length,
+            piece_length,
+            pieces,
+            info_hash,
+        })
+    }
+
+    /// Hash the file at `path` in pieces of `piece_length` bytes
+    ///
+    /// The torrent is named after the file. With [`DEFAULT_PIECE_LENGTH`],
+    /// a file gets the same infohash as from Erigon's own torrent creation.
+    pub fn create(path: &Path, piece_length: u64) -> Result<Self> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| SnapshotError::InvalidPath(path.display().to_string()))?
+            .to_string();
+        if piece_length == 0 {
+            return Err(invalid("piece length must be positive"));
+        }
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let pieces = if length == 0 {
+            Vec::new()
+        } else {
+            let mmap = FileData::open(&file)?;
+            let piece_length = usize::try_from(piece_length).unwrap_or(usize::MAX);
+            mmap.chunks(piece_length).map(sha1).collect()
+        };
+        let mut torrent = Self {
+            name,
+            length,
+            piece_length,
+            pieces,
+            info_hash: [0; PIECE_HASH_LEN],
+        };
+        torrent.info_hash = sha1(&torrent.encode_info());
+        Ok(torrent)
+    }
+
+    /// The bencoded `.torrent`, holding only the info dictionary
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = b"d4:info".to_vec();
+        data.extend_from_slice(&self.encode_info());
+        data.push(b'e');
+        data
+    }
+
+    /// Write the `.torrent` of this file next to `path`
+    pub fn write_next_to(&self, path: &Path) -> Result<PathBuf> {
+        let torrent = torrent_path(path);
+        fs::write(&torrent, self.to_bytes())?;
+        Ok(torrent)
+    }
+
+    // Keys in the sorted order bencode requires
+    fn encode_info(&self) -> Vec<u8> {
+        let mut info = format!(
+            "d6:lengthi{}e4:name{}:{}12:piece lengthi{}e6:pieces{}:",
+            self.length,
+            self.name.len(),
+            self.name,
+            self.piece_length,
+            self.pieces.len() * PIECE_HASH_LEN
+        )
+        .into_bytes();
+        for piece in &self.pieces {
+            info.extend_from_slice(piece);
+        }
+        info.push(b'e');
+        info
+    }
+
+    /// SHA-1 of the bencoded info dictionary, which identifies the torrent
+    pub fn info_hash(&self) -> [u8; PIECE_HASH_LEN] {
+        self.info_hash
+    }
+
+    /// [`TorrentInfo::info_hash`] as lowercase hex, as magnet links show it
+    pub fn info_hash_hex(&self) -> String {
+        hex::encode(self.info_hash)
+    }
+
+    /// Number of pieces the file is split into
+    pub fn piece_count(&self) -> usize {
+        self.pieces.len()
+    }
+
+    /// Check the file at `path` against the length and piece hashes
+    ///
+    /// The file name must match the torrent's name too, so a segment is not
+    /// accidentally checked against the torrent of another one.
+    pub fn verify_file(&self, path: &Path) -> Result<()> {
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        if file_name != Some(self.name.as_str()) {
+            return Err(SnapshotError::TorrentMismatch(format!(
+                "{} is not {}",
+                path.display(),
+                self.name
+            )));
+        }
+        self.verify_data(path)
+    }
+
+    /// [`TorrentInfo::verify_file`] without the name check, for a file
+    /// still under a temporary name
+    pub(crate) fn verify_data(&self, path: &Path) -> Result<()> {
+        let file = File::open(path)?;
+        let actual_length = file.metadata()?.len();
+        if actual_length != self.length {
+            return Err(SnapshotError::TorrentMismatch(format!(
+                "{} has {} bytes, torrent expects {}",
+                path.display(),
+                actual_length,
+                self.length
+            )));
+        }
+        if self.length == 0 {
+            // Nothing to hash, and empty files can't be mapped
+            return Ok(());
+        }
+
+        let mmap = FileData::open(&file)?;
+        // A piece larger than the address space holds the whole file
+        let piece_length = usize::try_from(self.piece_length).unwrap_or(usize::MAX);
+        for (piece, (data, expected)) in mmap.chunks(piece_length).zip(&self.pieces).enumerate() {
+            if sha1(data) != *expected {
+                return Err(SnapshotError::TorrentMismatch(format!(
+                    "piece {} of {} (bytes {}..{}) does not match its hash",
+                    piece,
+                    path.display(),
+                    piece as u64 * self.piece_length,
+                    piece as u64 * self.piece_length + data.len() as u64
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Path of the torrent Erigon keeps next to snapshot file `path`
+pub fn torrent_path(path: &Path) -> PathBuf {
+    let mut torrent = path.as_os_str().to_owned();
+    torrent.push(".torrent");
+    PathBuf::from(torrent)
+}
+
+/// Check the snapshot file at `path` against the `.torrent` next to it
+///
+/// Returns the parsed torrent on success, e.g. for its infohash.
+pub fn verify_file_against_torrent(path: &Path) -> Result<TorrentInfo> {
+    let torrent = TorrentInfo::open(&torrent_path(path))?;
+    torrent.verify_file(path)?;
+    Ok(torrent)
+}
+
+fn sha1(data: &[u8]) -> [u8; PIECE_HASH_LEN] {
+    digest(&SHA1_FOR_LEGACY_USE_ONLY, data)
+        .as_ref()
+        .try_into()
+        .unwrap()
+}
+
+fn invalid(reason: &str) -> SnapshotError {
+    SnapshotError::InvalidFormat(format!("Invalid torrent: {}", reason))
+}
+
+// Bencode, just enough of it for torrent metadata
+
+enum Value<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    // Lists (announce-list, url-list) are validated but not needed
+    List,
+    Dict(Vec<(&'a [u8], Spanned<'a>)>),
+}
+
+// A value with the byte range it was decoded from
+struct Spanned<'a> {
+    value: Value<'a>,
+    start: usize,
+    end: usize,
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> Result<Value<'a>> {
+        Ok(self.spanned()?.value)
+    }
+
+    fn spanned(&mut self) -> Result<Spanned<'a>> {
+        let start = self.pos;
+        let value = match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                Value::Int(self.int_until(b'e')?)
+            }
+            b'l' => {
+                self.pos += 1;
+                while self.peek()? != b'e' {
+                    self.spanned()?;
+                }
+                self.pos += 1;
+                Value::List
+            }
+            b'd' => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                while self.peek()? != b'e' {
+                    let key = self.bytes()?;
+                    entries.push((key, self.spanned()?));
+                }
+                self.pos += 1;
+                Value::Dict(entries)
+            }
+            b'0'..=b'9' => Value::Bytes(self.bytes()?),
+            other => {
+                return Err(invalid(&format!(
+                    "unexpected byte {:#04x} at offset {}",
+                    other, self.pos
+                )))
+            }
+        };
+        Ok(Spanned {
+            value,
+            start,
+            end: self.pos,
+        })
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| invalid("unexpected end of data"))
+    }
+
+    // `<len>:<bytes>`
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.int_until(b':')?;
+        let len = usize::try_from(len).map_err(|_| invalid("negative string length"))?;
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or_else(|| invalid("string runs past end of data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    // Decimal integer terminated by `end`, which is consumed
+    fn int_until(&mut self, end: u8) -> Result<i64> {
+        let rest = &self.data[self.pos..];
+        let len = rest
+            .iter()
+            .position(|&b| b == end)
+            .ok_or_else(|| invalid("unterminated integer"))?;
+        let int = std::str::from_utf8(&rest[..len])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| invalid(&format!("bad integer at offset {}", self.pos)))?;
+        self.pos += len + 1;
+        Ok(int)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bencoded single-file torrent for `name`, hashing `content` in pieces
+    fn make_torrent(name: &str, content: &[u8], piece_length: usize) -> Vec<u8> {
+        let mut pieces = Vec::new();
+        for piece in content.chunks(piece_length) {
+            pieces.extend_from_slice(&sha1(piece));
+        }
+        let mut info = format!(
+            "d6:lengthi{}e4:name{}:{}12:piece lengthi{}e6:pieces{}:",
+            content.len(),
+            name.len(),
+            name,
+            piece_length,
+            pieces.len()
+        )
+        .into_bytes();
+        info.extend_from_slice(&pieces);
+        info.push(b'e');
+
+        let mut torrent = b"d8:announce11:udp://t.org13:creation datei1700000000e4:info".to_vec();
+        torrent.extend_from_slice(&info);
+        torrent.push(b'e');
+        torrent
+    }
+
+    // Just the info dictionary, as TorrentInfo::to_bytes writes it
+    fn make_torrent_info_only(name: &str, content: &[u8], piece_length: usize) -> Vec<u8> {
+        let torrent = make_torrent(name, content, piece_length);
+        let start = torrent.windows(6).position(|w| w == b"4:info").unwrap();
+        let mut data = b"d".to_vec();
+        data.extend_from_slice(&torrent[start..]);
+        data
+    }
+
+    #[test]
+    fn test_parse_torrent() {
+        let content: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let data = make_torrent("v1-000000-000500-headers.seg", &content, 4096);
+
+        let torrent = TorrentInfo::parse(&data).unwrap();
+        assert_eq!(torrent.name, "v1-000000-000500-headers.seg");
+        assert_eq!(torrent.length, 10_000);
+        assert_eq!(torrent.piece_length, 4096);
+        assert_eq!(torrent.piece_count(), 3);
+
+        // The infohash is the SHA-1 of the info dictionary as it appears in
+        // the file
+        let start = data.windows(6).position(|w| w == b"4:info").unwrap() + 6;
+        assert_eq!(torrent.info_hash(), sha1(&data[start..data.len() - 1]));
+        assert_eq!(torrent.info_hash_hex().len(), 40);
+    }
+
+    #[test]
+    fn test_create_torrent() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("v1-000000-000500-headers.seg");
+        let content: Vec<u8> = (0..10_000u32).map(|i| (i % 13) as u8).collect();
+        fs::write(&path, &content).unwrap();
+
+        let torrent = TorrentInfo::create(&path, 4096).unwrap();
+        assert_eq!(torrent.piece_count(), 3);
+        // Same bytes as a torrent made elsewhere, so the same infohash
+        let data = torrent.to_bytes();
+        assert_eq!(
+            data,
+            make_torrent_info_only("v1-000000-000500-headers.seg", &content, 4096)
+        );
+        assert_eq!(TorrentInfo::parse(&data).unwrap(), torrent);
+
+        torrent.write_next_to(&path).unwrap();
+        assert_eq!(verify_file_against_torrent(&path).unwrap(), torrent);
+
+        let empty = tmp_dir.path().join("empty.seg");
+        fs::write(&empty, b"").unwrap();
+        let torrent = TorrentInfo::create(&empty, DEFAULT_PIECE_LENGTH).unwrap();
+        assert_eq!(torrent.piece_count(), 0);
+        torrent.write_next_to(&empty).unwrap();
+        verify_file_against_torrent(&empty).unwrap();
+    }
+
+    #[test]
+    fn test_sha1_known_answer() {
+        assert_eq!(
+            hex::encode(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
+
+    #[test]
+    fn test_verify_file_against_torrent() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("v1-000000-000500-bodies.seg");
+        let mut content: Vec<u8> = (0..9000u32).map(|i| (i * 7 % 256) as u8).collect();
+        fs::write(&path, &content).unwrap();
+        fs::write(
+            torrent_path(&path),
+            make_torrent("v1-000000-000500-bodies.seg", &content, 2048),
+        )
+        .unwrap();
+        assert!(torrent_path(&path).ends_with("v1-000000-000500-bodies.seg.torrent"));
+
+        let torrent = verify_file_against_torrent(&path).unwrap();
+        assert_eq!(torrent.piece_count(), 5);
+
+        // A flipped byte in the third piece
+        content[4100] ^= 1;
+        fs::write(&path, &content).unwrap();
+        let err = verify_file_against_torrent(&path).unwrap_err().to_string();
+        assert!(err.contains("piece 2"), "{}", err);
+
+        // Truncated download
+        fs::write(&path, &content[..8000]).unwrap();
+        let err = verify_file_against_torrent(&path).unwrap_err().to_string();
+        assert!(err.contains("8000 bytes"), "{}", err);
+
+        // Torrent of another file
+        let other = tmp_dir.path().join("v1-000500-001000-bodies.seg");
+        fs::write(&other, &content).unwrap();
+        assert!(torrent.verify_file(&other).is_err());
+    }
+
+    #[test]
+    fn test_rejects_malformed_torrents() {
+        let good = make_torrent("a.seg", b"hello", 4);
+        assert!(TorrentInfo::parse(&good).is_ok());
+
+        for data in [
+            &b""[..],
+            b"le",
+            b"d4:infoi1ee",
+            b"d4:infod4:name5:a.segee",
+            b"d4:infod6:lengthi5e4:name5:a.seg12:piece lengthi4e6:pieces3:abcee",
+            b"d4:infod5:filesle4:name1:aee",
+            b"d4:info",
+            b"i12",
+            b"99:short",
+        ] {
+            assert!(TorrentInfo::parse(data).is_err(), "{:?}", data);
+        }
+        // Trailing data
+        let mut trailing = good.clone();
+        trailing.push(b'x');
+        assert!(TorrentInfo::parse(&trailing).is_err());
+        // Piece count must match the length
+        let mut wrong_count = good;
+        let pos = wrong_count
+            .windows(9)
+            .position(|w| w == b"lengthi5e")
+            .unwrap();
+        wrong_count[pos + 7] = b'9';
+        assert!(TorrentInfo::parse(&wrong_count).is_err());
+    }
+}
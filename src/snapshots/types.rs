@@ -0,0 +1,87 @@
+//! Numbers that address chain data in snapshots
+//!
+//! Segments are read by word ordinal, but callers think in block numbers and
+//! transaction numbers, and an enum `.idx` only bridges the two through its
+//! `base_data_id`. These wrappers keep the three apart: readers take a
+//! [`BlockNumber`] or [`TxNum`] and do the subtraction themselves, so an
+//! ordinal can't be passed where a block number belongs.
+
+use std::fmt;
+
+macro_rules! chain_number {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize), serde(transparent))]
+        pub struct $name(pub u64);
+
+        impl $name {
+            pub const fn get(self) -> u64 {
+                self.0
+            }
+
+            /// Ordinal of this number among those counted from `first`, or
+            /// `None` if it comes before `first`
+            pub fn ordinal_from(self, first: Self) -> Option<u64> {
+                self.0.checked_sub(first.0)
+            }
+
+            /// The number `n` after this one
+            pub fn offset(self, n: u64) -> Self {
+                Self(self.0 + n)
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(n: u64) -> Self {
+                Self(n)
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(n: $name) -> u64 {
+                n.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+chain_number! {
+    /// Number of a block in the chain; the `base_data_id` of headers and
+    /// bodies indexes
+    BlockNumber
+}
+
+chain_number! {
+    /// Erigon's global transaction number, counting every transaction of the
+    /// chain plus a system transaction before and after each block's; the
+    /// `base_data_id` of transactions indexes
+    TxNum
+}
+
+chain_number! {
+    /// Position of a transaction among the transactions of its block,
+    /// not counting the system transactions
+    TxIndex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ordinals() {
+        let first = BlockNumber(1000);
+        assert_eq!(BlockNumber(1005).ordinal_from(first), Some(5));
+        assert_eq!(BlockNumber(999).ordinal_from(first), None);
+        assert_eq!(first.offset(5), BlockNumber(1005));
+        assert_eq!(u64::from(TxNum::from(7)), 7);
+        assert_eq!(TxIndex(3).to_string(), "3");
+    }
+}
@@ -0,0 +1,701 @@
+use crate::compress::{encode_varint, Compressor, CompressorBuilder};
+use crate::decompress::Decompressor;
+use crate::readahead::ReadAhead;
+use crate::snapshots::reader::{decode_header_word, ChainValidation, StoredBody};
+use crate::snapshots::recsplit::RecSplit;
+use crate::snapshots::registry::{IndexFlavor, SegmentType};
+use crate::snapshots::repo::{SnapshotFile, SnapshotType, BLOCKS_PER_FILE_UNIT};
+use crate::snapshots::salt::{read_or_create_salt, SaltKind};
+use crate::snapshots::{ChainViolation, Result, SnapshotError};
+use alloy_consensus::Header;
+use alloy_primitives::{keccak256, Address, B256};
+use std::path::{Path, PathBuf};
+
+// Attempts at building the index after a fingerprint collision, each with
+// the next salt, as Erigon does
+const MAX_INDEX_ATTEMPTS: usize = 3;
+
+/// Writer for headers snapshot files, the write side of
+/// [`HeadersReader`](crate::snapshots::HeadersReader)
+///
+/// Headers must be added in block order and cover the whole range. Each
+/// header is stored as Erigon does it: the first byte of its hash followed
+/// by its RLP encoding. [`HeaderSegmentWriter::finish`] compresses the
+/// segment and builds the hash-keyed `.idx` next to it, salted with the
+/// directory's `salt-blocks.txt` (created if missing) so Erigon accepts it.
+pub struct HeaderSegmentWriter {
+    seg_path: PathBuf,
+    idx_path: PathBuf,
+    from_block: u64,
+    to_block: u64,
+    compressor: Compressor,
+    hashes: Vec<B256>,
+    salt: u32,
+    fsync: bool,
+}
+
+impl HeaderSegmentWriter {
+    /// Create a writer for blocks `from_block..to_block` in `dir`, with the
+    /// default compressor settings
+    pub fn new(dir: impl AsRef<Path>, from_block: u64, to_block: u64) -> Result<Self> {
+        Self::with_compressor(dir, from_block, to_block, |builder| builder)
+    }
+
+    /// Like [`HeaderSegmentWriter::new`], with a hook to tune the compressor
+    pub fn with_compressor(
+        dir: impl AsRef<Path>,
+        from_block: u64,
+        to_block: u64,
+        configure: impl FnOnce(CompressorBuilder) -> CompressorBuilder,
+    ) -> Result<Self> {
+        if from_block >= to_block
+            || !from_block.is_multiple_of(BLOCKS_PER_FILE_UNIT)
+            || !to_block.is_multiple_of(BLOCKS_PER_FILE_UNIT)
+        {
+            return Err(SnapshotError::InvalidPath(format!(
+                "Invalid block range {}-{}, bounds must be multiples of {}",
+                from_block, to_block, BLOCKS_PER_FILE_UNIT
+            )));
+        }
+        let stem = SnapshotFile::stem(SnapshotType::Headers, from_block, to_block);
+        let salt = read_or_create_salt(dir.as_ref(), SaltKind::Blocks)?;
+        let seg_path = dir.as_ref().join(format!("{}.seg", stem));
+        let idx_path = dir.as_ref().join(format!("{}.idx", stem));
+
+        let compressor = configure(Compressor::builder(&seg_path).log_prefix("headers")).build()?;
+        Ok(Self {
+            seg_path,
+            idx_path,
+            from_block,
+            to_block,
+            compressor,
+            hashes: Vec::with_capacity((to_block - from_block) as usize),
+            salt,
+            fsync: true,
+        })
+    }
+
+    /// Skip fsync of the segment and the index (for tests)
+    pub fn disable_fsync(&mut self) {
+        self.compressor.disable_fsync();
+        self.fsync = false;
+    }
+
+    /// Number of headers added so far
+    pub fn count(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Add the next header; its number must follow the previous one
+    pub fn add_header(&mut self, header: &Header) -> Result<()> {
+        let expected = self.from_block + self.hashes.len() as u64;
+        if header.number != expected || expected >= self.to_block {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Expected header {} in range {}-{}, got {}",
+                expected, self.from_block, self.to_block, header.number
+            )));
+        }
+        let hash = header.hash_slow();
+        let mut word = Vec::with_capacity(1 + alloy_rlp::Encodable::length(header));
+        word.push(hash[0]);
+        alloy_rlp::Encodable::encode(header, &mut word);
+        self.compressor.add_word(&word)?;
+        self.hashes.push(hash);
+        Ok(())
+    }
+
+    /// Compress the segment and build its index; returns the paths of the
+    /// `.seg` and `.idx` files
+    pub fn finish(mut self) -> Result<(PathBuf, PathBuf)> {
+        let expected = self.to_block - self.from_block;
+        if self.hashes.len() as u64 != expected {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Range {}-{} needs {} headers, got {}",
+                self.from_block,
+                self.to_block,
+                expected,
+                self.hashes.len()
+            )));
+        }
+        self.compressor.compress()?;
+
+        // Word offsets are only known once the segment is compressed
+        let decompressor = Decompressor::new(&self.seg_path)?;
+        let mut offsets = Vec::with_capacity(self.hashes.len());
+        let mut getter = decompressor.make_getter();
+        let mut offset = 0;
+        while getter.has_next() {
+            offsets.push(offset);
+            offset = getter.skip().0;
+        }
+        if offsets.len() != self.hashes.len() {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Segment has {} words, expected {}",
+                offsets.len(),
+                self.hashes.len()
+            )));
+        }
+
+        build_salted(&self.idx_path, self.salt, |salt| {
+            self.build_index(&offsets, salt)
+        })?;
+        Ok((self.seg_path, self.idx_path))
+    }
+
+    fn build_index(&self, offsets: &[u64], salt: u32) -> Result<()> {
+        let mut rs = RecSplit::builder(&self.idx_path, self.hashes.len())
+            .enums(true)
+            .less_false_positives(true)
+            .base_data_id(self.from_block)
+            .salt(salt)
+            .fsync(self.fsync)
+            .build()?;
+        for (hash, &offset) in self.hashes.iter().zip(offsets) {
+            rs.add_key(hash.as_slice(), offset)?;
+        }
+        rs.build()
+    }
+}
+
+// Run `build` with `salt`, moving on to the next salt after a fingerprint
+// collision
+fn build_salted(idx_path: &Path, mut salt: u32, build: impl Fn(u32) -> Result<()>) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        match build(salt) {
+            Err(SnapshotError::Collision(fingerprint)) if attempt < MAX_INDEX_ATTEMPTS => {
+                tracing::warn!(
+                    "Collision {:#x} building {}, retrying with the next salt",
+                    fingerprint,
+                    idx_path.display()
+                );
+                // From Go: recsplit.go:266 ResetNextSalt
+                salt = salt.wrapping_add(1);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Build the `.idx` Erigon keeps next to the block segment `seg_path` of
+/// `kind`, returning its path
+///
+/// `base_data_id` is the first block (slot, for caplin types; txNum, for
+/// transactions) of the segment. Headers are keyed by their hash and
+/// transactions by theirs, system transactions by their TxNum as in
+/// [`build_transactions_index`]; bor events map the hash of each block to the
+/// offset of its first event, and other segments are keyed by ordinal. The index is salted with the directory's
+/// `salt-blocks.txt`, created if missing.
+pub fn build_block_index(
+    seg_path: &Path,
+    kind: SnapshotType,
+    base_data_id: u64,
+    fsync: bool,
+) -> Result<PathBuf> {
+    let decompressor = Decompressor::new(seg_path)?;
+    let mut keys: Vec<(Vec<u8>, u64)> = Vec::with_capacity(decompressor.count());
+    let mut getter = decompressor.make_getter();
+    let mut offset = 0;
+    let mut ordinal = 0u64;
+    let mut word = Vec::new();
+    let mut varint = [0u8; 10];
+    while getter.has_next() {
+        word.clear();
+        let next;
+        (word, next) = getter.try_next(word)?;
+        let invalid = || {
+            SnapshotError::InvalidFormat(format!(
+                "Word {} of {} is not a {} word",
+                ordinal,
+                seg_path.display(),
+                kind
+            ))
+        };
+        let key = match kind {
+            SnapshotType::Headers => {
+                let rlp = word.get(1..).ok_or_else(invalid)?;
+                Some(keccak256(rlp).to_vec())
+            }
+            SnapshotType::Transactions => Some(
+                tx_key(&word, base_data_id + ordinal)
+                    .ok_or_else(invalid)?
+                    .to_vec(),
+            ),
+            SnapshotType::BorEvents => {
+                let hash = word.get(..B256::len_bytes()).ok_or_else(invalid)?;
+                // Events of a block are consecutive, only the first is keyed
+                let first_of_block = keys.last().is_none_or(|(last, _)| last != hash);
+                first_of_block.then(|| hash.to_vec())
+            }
+            _ => {
+                let n = encode_varint(&mut varint, ordinal);
+                Some(varint[..n].to_vec())
+            }
+        };
+        if let Some(key) = key {
+            keys.push((key, offset));
+        }
+        offset = next;
+        ordinal += 1;
+    }
+
+    let idx_path = seg_path.with_extension("idx");
+    let dir = seg_path.parent().unwrap_or(Path::new("."));
+    let salt = read_or_create_salt(dir, SaltKind::Blocks)?;
+    let enums = SegmentType::from(kind).accessors()[0].flavor == IndexFlavor::Enum;
+    build_salted(&idx_path, salt, |salt| {
+        let mut rs = RecSplit::builder(&idx_path, keys.len())
+            .enums(enums)
+            .less_false_positives(matches!(
+                kind,
+                SnapshotType::Headers | SnapshotType::Transactions
+            ))
+            .base_data_id(base_data_id)
+            .salt(salt)
+            .fsync(fsync)
+            .build()?;
+        for (key, offset) in &keys {
+            rs.add_key(key, *offset)?;
+        }
+        rs.build()
+    })?;
+    Ok(idx_path)
+}
+
+/// Build the hash-keyed `.idx` of the headers segment at `seg_path` from
+/// its headers alone, returning its path
+///
+/// The first block comes from the segment's name, and every header must
+/// carry the number its position gives it, so that the index maps each hash
+/// to the number of its block. With `validation` the headers must also form
+/// a chain, see [`HeaderRange::validate`]. Headers are streamed from the
+/// segment and hashed as they come, again after a salt collision, rather
+/// than held in memory.
+///
+/// [`HeaderRange::validate`]: crate::snapshots::reader::HeaderRange::validate
+pub fn build_headers_index(
+    seg_path: &Path,
+    validation: Option<ChainValidation>,
+    fsync: bool,
+) -> Result<PathBuf> {
+    let file = SnapshotFile::parse(seg_path)
+        .filter(|file| file.kind == SnapshotType::Headers)
+        .ok_or_else(|| {
+            SnapshotError::InvalidPath(format!("{} is not a headers segment", seg_path.display()))
+        })?;
+    let decompressor = Decompressor::new(seg_path)?;
+    let expected = file.to_block - file.from_block;
+    if decompressor.count() as u64 != expected {
+        return Err(SnapshotError::InvalidFormat(format!(
+            "{} has {} headers, its range needs {}",
+            seg_path.display(),
+            decompressor.count(),
+            expected
+        )));
+    }
+
+    let idx_path = seg_path.with_extension("idx");
+    let dir = seg_path.parent().unwrap_or(Path::new("."));
+    let salt = read_or_create_salt(dir, SaltKind::Blocks)?;
+    build_salted(&idx_path, salt, |salt| {
+        let mut rs = RecSplit::builder(&idx_path, decompressor.count())
+            .enums(true)
+            .less_false_positives(true)
+            .base_data_id(file.from_block)
+            .salt(salt)
+            .fsync(fsync)
+            .build()?;
+        let mut getter = decompressor.make_getter();
+        getter.read_ahead(ReadAhead::Sequential);
+        let mut offset = 0;
+        let mut number = file.from_block;
+        let mut parent: Option<(B256, Header)> = None;
+        let mut word = Vec::new();
+        while getter.has_next() {
+            word.clear();
+            let next;
+            (word, next) = getter.try_next(word)?;
+            let (hash, header) = decode_header_word(&word)?;
+            let violation = if header.number != number {
+                Some(ChainViolation::Number {
+                    expected: number,
+                    actual: header.number,
+                })
+            } else {
+                validation.and_then(|validation| {
+                    let (parent_hash, parent) = parent.as_ref()?;
+                    validation.check(parent, *parent_hash, &header)
+                })
+            };
+            if let Some(violation) = violation {
+                return Err(SnapshotError::InvalidHeader {
+                    number,
+                    hash,
+                    violation,
+                });
+            }
+            rs.add_key(hash.as_slice(), offset)?;
+            if validation.is_some() {
+                parent = Some((hash, header));
+            }
+            offset = next;
+            number += 1;
+        }
+        rs.build()
+    })?;
+    Ok(idx_path)
+}
+
+/// Build the two indexes Erigon keeps next to the transactions segment at
+/// `seg_path`, returning their paths: the `.idx` from transaction hash to
+/// TxNum, and the `-to-block.idx` from transaction hash to block number
+///
+/// `bodies_path` is the bodies segment of the same block range, which gives
+/// the TxNum of the first transaction and the block of each one. System
+/// transactions are keyed by their TxNum, big-endian and padded to 32 bytes,
+/// as Erigon does. Transactions are streamed and hashed in a single pass
+/// that feeds both indexes, again after a salt collision.
+pub fn build_transactions_index(
+    seg_path: &Path,
+    bodies_path: &Path,
+    fsync: bool,
+) -> Result<(PathBuf, PathBuf)> {
+    let file = SnapshotFile::parse(seg_path)
+        .filter(|file| file.kind == SnapshotType::Transactions)
+        .ok_or_else(|| {
+            SnapshotError::InvalidPath(format!(
+                "{} is not a transactions segment",
+                seg_path.display()
+            ))
+        })?;
+    SnapshotFile::parse(bodies_path)
+        .filter(|bodies| {
+            bodies.kind == SnapshotType::Bodies
+                && (bodies.from_block, bodies.to_block) == (file.from_block, file.to_block)
+        })
+        .ok_or_else(|| {
+            SnapshotError::InvalidPath(format!(
+                "{} is not the bodies segment of {}",
+                bodies_path.display(),
+                seg_path.display()
+            ))
+        })?;
+    let transactions = Decompressor::new(seg_path)?;
+    let bodies = Decompressor::new(bodies_path)?;
+    let next_body = |getter: &mut crate::decompress::Getter<'_>, tx_num: u64| {
+        if !getter.has_next() {
+            return Err(SnapshotError::InvalidFormat(format!(
+                "Transaction {} is past the last body of {}",
+                tx_num,
+                bodies_path.display()
+            )));
+        }
+        StoredBody::decode(&getter.try_next(Vec::new())?.0)
+    };
+    let first_tx_num = next_body(&mut bodies.make_getter(), 0)?.base_tx_num.get();
+
+    let idx_path = seg_path.with_extension("idx");
+    let stem = seg_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let to_block_path = seg_path.with_file_name(format!("{}-to-block.idx", stem));
+    let dir = seg_path.parent().unwrap_or(Path::new("."));
+    let salt = read_or_create_salt(dir, SaltKind::Blocks)?;
+    build_salted(&idx_path, salt, |salt| {
+        let mut by_hash = RecSplit::builder(&idx_path, transactions.count())
+            .enums(true)
+            .less_false_positives(true)
+            .base_data_id(first_tx_num)
+            .salt(salt)
+            .fsync(fsync)
+            .build()?;
+        let mut to_block = RecSplit::builder(&to_block_path, transactions.count())
+            .base_data_id(file.from_block)
+            .salt(salt)
+            .fsync(fsync)
+            .build()?;
+        let mut body_getter = bodies.make_getter();
+        let mut body = next_body(&mut body_getter, first_tx_num)?;
+        let mut block = file.from_block;
+        let mut getter = transactions.make_getter();
+        getter.read_ahead(ReadAhead::Sequential);
+        let mut offset = 0;
+        let mut tx_num = first_tx_num;
+        let mut word = Vec::new();
+        while getter.has_next() {
+            word.clear();
+            let next;
+            (word, next) = getter.try_next(word)?;
+            // Past the bodies the transaction is not in, empty blocks included
+            while body.base_tx_num.get() + u64::from(body.tx_count) <= tx_num {
+                body = next_body(&mut body_getter, tx_num)?;
+                block += 1;
+            }
+            if body.base_tx_num.get() > tx_num {
+                return Err(SnapshotError::InvalidFormat(format!(
+                    "Transaction {} is in no body, block {} starts at {}",
+                    tx_num, block, body.base_tx_num
+                )));
+            }
+            let key = tx_key(&word, tx_num).ok_or_else(|| {
+                SnapshotError::InvalidFormat(format!(
+                    "Transaction {} of {} bytes",
+                    tx_num,
+                    word.len()
+                ))
+            })?;
+            by_hash.add_key(key.as_slice(), offset)?;
+            to_block.add_key(key.as_slice(), block)?;
+            offset = next;
+            tx_num += 1;
+        }
+        by_hash.build()?;
+        to_block.build()
+    })?;
+    Ok((idx_path, to_block_path))
+}
+
+// Index key of the transaction word with TxNum `tx_num`: the hash of its
+// EIP-2718 encoding, after the hash byte and sender; `None` if the word is
+// too short to hold one
+// From Erigon: turbo/snapshotsync/freezeblocks/block_snapshots.go TransactionsIdx
+fn tx_key(word: &[u8], tx_num: u64) -> Option<B256> {
+    if word.is_empty() {
+        // System transactions: the TxNum, padded
+        let mut key = B256::ZERO;
+        key[..8].copy_from_slice(&tx_num.to_be_bytes());
+        return Some(key);
+    }
+    let tx = word.get(1 + Address::len_bytes()..)?;
+    (!tx.is_empty()).then(|| keccak256(tx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::CompressionLevel;
+    use crate::error::IndexError;
+    use crate::snapshots::salt::read_salt;
+    use crate::snapshots::{BlockNumber, HeadersReader};
+
+    fn headers(range: std::ops::Range<u64>) -> Vec<Header> {
+        range
+            .map(|number| Header {
+                number,
+                gas_limit: 30_000_000,
+                gas_used: number * 21_000 % 30_000_000,
+                timestamp: 1_600_000_000 + number * 12,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_headers_segment_and_index() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let headers = headers(1000..2000);
+
+        let mut writer = HeaderSegmentWriter::with_compressor(tmp_dir.path(), 1000, 2000, |b| {
+            // Pattern search is slow in debug builds and irrelevant here
+            b.level(CompressionLevel::Store)
+        })
+        .unwrap();
+        writer.disable_fsync();
+        for header in &headers {
+            writer.add_header(header).unwrap();
+        }
+        let (seg_path, idx_path) = writer.finish().unwrap();
+        assert!(seg_path.ends_with("v1-000001-000002-headers.seg"));
+        assert!(idx_path.ends_with("v1-000001-000002-headers.idx"));
+
+        let reader = HeadersReader::new(&seg_path).unwrap();
+        let idx = reader
+            .index()
+            .expect("index is picked up next to the segment");
+        assert_eq!(idx.base_data_id(), 1000);
+        let salt = read_salt(tmp_dir.path(), SaltKind::Blocks)
+            .unwrap()
+            .expect("salt file is created by the writer");
+        idx.verify_salt(salt).unwrap();
+        assert!(matches!(
+            idx.verify_salt(salt.wrapping_add(1)),
+            Err(SnapshotError::Index(IndexError::SaltMismatch { .. }))
+        ));
+        assert_eq!(reader.count(), headers.len());
+
+        for header in &headers {
+            let found = reader.header_by_hash(header.hash_slow()).unwrap();
+            assert_eq!(found.as_ref(), Some(header));
+        }
+        assert_eq!(
+            reader.header_by_hash(B256::repeat_byte(0xab)).unwrap(),
+            None
+        );
+        for number in [1000, 1001, 1500, 1999] {
+            let found = reader.header_by_number(BlockNumber(number)).unwrap();
+            assert_eq!(found.as_ref(), Some(&headers[(number - 1000) as usize]));
+        }
+        assert_eq!(reader.header_by_number(BlockNumber(999)).unwrap(), None);
+        assert_eq!(reader.header_by_number(BlockNumber(2000)).unwrap(), None);
+
+        let decompressor = Decompressor::new(&seg_path).unwrap();
+        decompressor.verify_with_index(idx).unwrap();
+    }
+
+    #[test]
+    fn test_build_headers_index_from_segment() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        // Linked into a chain, so that validation passes
+        let mut headers = headers(0..1000);
+        for i in 1..headers.len() {
+            headers[i].parent_hash = headers[i - 1].hash_slow();
+        }
+        let mut writer = HeaderSegmentWriter::with_compressor(tmp_dir.path(), 0, 1000, |b| {
+            b.level(CompressionLevel::Store)
+        })
+        .unwrap();
+        writer.disable_fsync();
+        for header in &headers {
+            writer.add_header(header).unwrap();
+        }
+        let (seg_path, idx_path) = writer.finish().unwrap();
+        let written = std::fs::read(&idx_path).unwrap();
+        std::fs::remove_file(&idx_path).unwrap();
+
+        // The same index as the writer's, from the segment alone
+        let built =
+            build_headers_index(&seg_path, Some(ChainValidation::default()), false).unwrap();
+        assert_eq!(built, idx_path);
+        assert_eq!(std::fs::read(&idx_path).unwrap(), written);
+        let reader = HeadersReader::new(&seg_path).unwrap();
+        let found = reader.header_by_hash(headers[777].hash_slow()).unwrap();
+        assert_eq!(found.map(|h| h.number), Some(777));
+
+        // The unlinked headers of another segment fail validation only
+        let mut writer = HeaderSegmentWriter::new(tmp_dir.path(), 1000, 2000).unwrap();
+        writer.disable_fsync();
+        for header in &self::headers(1000..2000) {
+            writer.add_header(header).unwrap();
+        }
+        let (seg_path, _) = writer.finish().unwrap();
+        assert!(matches!(
+            build_headers_index(&seg_path, Some(ChainValidation::default()), false),
+            Err(SnapshotError::InvalidHeader {
+                number: 1001,
+                violation: ChainViolation::ParentHash { .. },
+                ..
+            })
+        ));
+        build_headers_index(&seg_path, None, false).unwrap();
+
+        // Only headers segments whose name matches their contents
+        let moved = tmp_dir.path().join("v1-000002-000003-headers.seg");
+        std::fs::rename(&seg_path, &moved).unwrap();
+        assert!(matches!(
+            build_headers_index(&moved, None, false),
+            Err(SnapshotError::InvalidHeader {
+                number: 2000,
+                violation: ChainViolation::Number { .. },
+                ..
+            })
+        ));
+        assert!(build_headers_index(
+            &tmp_dir.path().join("v1-000002-000003-bodies.seg"),
+            None,
+            false
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_build_transactions_index() {
+        use crate::snapshots::recsplit::RecSplitIndex;
+        use crate::snapshots::{TransactionsReader, TxNum};
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let compressor = |kind| {
+            let stem = SnapshotFile::stem(kind, 0, 1000);
+            let path = tmp_dir.path().join(format!("{}.seg", stem));
+            let mut compressor = Compressor::builder(&path)
+                .level(CompressionLevel::Store)
+                .build()
+                .unwrap();
+            compressor.disable_fsync();
+            (path, compressor)
+        };
+        let (bodies_path, mut bodies) = compressor(SnapshotType::Bodies);
+        let (seg_path, mut transactions) = compressor(SnapshotType::Transactions);
+
+        // Blocks of 0 to 2 transactions between their system transactions,
+        // from TxNum 100 on
+        let mut tx_num = 100;
+        let mut expected = Vec::new();
+        for block in 0..1000u64 {
+            let count = block % 3;
+            bodies
+                .add_word(
+                    &StoredBody {
+                        base_tx_num: TxNum(tx_num),
+                        tx_count: count as u32 + 2,
+                        ..Default::default()
+                    }
+                    .encode(),
+                )
+                .unwrap();
+            transactions.add_word(&[]).unwrap();
+            for i in 0..count {
+                let encoded = format!("tx {} of block {}", i, block).into_bytes();
+                let hash = keccak256(&encoded);
+                let mut word = vec![hash[0]];
+                word.extend_from_slice(&[0xaa; 20]);
+                word.extend_from_slice(&encoded);
+                transactions.add_word(&word).unwrap();
+                expected.push((hash, tx_num + 1 + i, block));
+            }
+            transactions.add_word(&[]).unwrap();
+            tx_num += count + 2;
+        }
+        bodies.compress().unwrap();
+        transactions.compress().unwrap();
+
+        let (idx_path, to_block_path) =
+            build_transactions_index(&seg_path, &bodies_path, false).unwrap();
+        assert!(to_block_path.ends_with("v1-000000-000001-transactions-to-block.idx"));
+        let reader = TransactionsReader::new(&seg_path).unwrap();
+        assert_eq!(reader.first_tx_num(), Some(TxNum(100)));
+        let to_block = RecSplitIndex::open(&to_block_path).unwrap();
+        assert_eq!(to_block.base_data_id(), 0);
+        assert!(!to_block.is_enum());
+        for &(hash, tx_num, block) in &expected {
+            let tx = reader.transaction_by_hash(hash).unwrap().unwrap();
+            assert_eq!(tx.tx_num, TxNum(tx_num));
+            assert_eq!(to_block.lookup(hash.as_slice()), Some(block));
+        }
+        // System transactions are keyed by their TxNum
+        let idx = RecSplitIndex::open(&idx_path).unwrap();
+        assert_eq!(idx.lookup(tx_key(&[], 102).unwrap().as_slice()), Some(2));
+
+        // Bodies of another range, or a bodies segment given as transactions
+        let other = tmp_dir.path().join("v1-000001-000002-bodies.seg");
+        std::fs::copy(&bodies_path, &other).unwrap();
+        assert!(build_transactions_index(&seg_path, &other, false).is_err());
+        assert!(build_transactions_index(&bodies_path, &bodies_path, false).is_err());
+    }
+
+    #[test]
+    fn test_rejects_out_of_order_and_incomplete_ranges() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        assert!(HeaderSegmentWriter::new(tmp_dir.path(), 0, 1500).is_err());
+
+        let mut writer = HeaderSegmentWriter::new(tmp_dir.path(), 0, 1000).unwrap();
+        writer.disable_fsync();
+        assert!(writer.add_header(&headers(1..2)[0]).is_err());
+        for header in &headers(0..10) {
+            writer.add_header(header).unwrap();
+        }
+        assert_eq!(writer.count(), 10);
+        assert!(writer.finish().is_err());
+    }
+}
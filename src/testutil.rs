@@ -0,0 +1,648 @@
+//! Synthetic chains for tests and benches
+//!
+//! [`SyntheticChain`] makes up blocks that look like a real chain's to the
+//! compressor and the readers: headers linked by hash with the fields of
+//! their forks, builders and client strings from small pools, EIP-1559 and
+//! legacy transactions between a few hot accounts and contracts, calldata
+//! of ABI words that are mostly zero padding, and withdrawals with their
+//! root. Everything is a function of the seed and the block number, so a
+//! corpus is the same on every run and every machine.
+//!
+//! [`SyntheticChain::write_segments`] writes the headers, bodies and
+//! transactions segments of a block range with their indexes, the way
+//! [`import_era1`](crate::snapshots::era1::import_era1) does, as fixtures
+//! the [`Snapshots`](crate::snapshots::Snapshots) facade opens;
+//! [`SyntheticChain::write_headers`] the headers alone, and
+//! [`write_segment`] any words.
+//!
+//! The crate's own unit tests build on this module with or without the
+//! `testutil` feature.
+
+use crate::compress::{CompressionLevel, Compressor};
+use crate::snapshots::reader::StoredBody;
+use crate::snapshots::repo::{SnapshotFile, SnapshotType};
+use crate::snapshots::types::TxNum;
+use crate::snapshots::writer::{build_block_index, build_transactions_index};
+use crate::snapshots::{ChainSpec, HeaderSegmentWriter, Result};
+use alloy_consensus::constants::EMPTY_OMMER_ROOT_HASH;
+use alloy_consensus::proofs::{calculate_transaction_root, calculate_withdrawals_root};
+use alloy_consensus::{
+    BlockBody, Header, SignableTransaction, Transaction, TxEip1559, TxEnvelope, TxLegacy,
+};
+use alloy_eips::eip2718::Encodable2718;
+use alloy_eips::eip4895::{Withdrawal, Withdrawals};
+use alloy_primitives::{Address, Bloom, Bytes, PrimitiveSignature, TxKind, B256, B64, U256};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// Sizes of the pools blocks draw from; popular entries are drawn far more
+// often than the rest
+const BUILDERS: u64 = 16;
+const ACCOUNTS: u64 = 256;
+const CONTRACTS: u64 = 64;
+const SELECTORS: u64 = 16;
+
+const CLIENTS: [&[u8]; 6] = [
+    b"",
+    b"geth",
+    b"erigon",
+    b"beaverbuild.org",
+    b"Titan (titanbuilder.xyz)",
+    b"rsync-builder.xyz",
+];
+
+const GAS_LIMIT: u64 = 30_000_000;
+const BLOCK_TIME: u64 = 12;
+const WITHDRAWALS_PER_BLOCK: u64 = 16;
+const GWEI: u64 = 1_000_000_000;
+
+/// A chain with every fork up to Cancun from genesis, so that headers
+/// carry all the optional fields but the requests hash
+pub const SYNTHETIC: ChainSpec = ChainSpec {
+    name: "synthetic",
+    chain_id: 1337,
+    london_block: Some(0),
+    shanghai_time: Some(0),
+    cancun_time: Some(0),
+    prague_time: None,
+};
+
+/// A block [`SyntheticChain`] made up, with the sender of each transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticBlock {
+    pub header: Header,
+    pub body: BlockBody<TxEnvelope>,
+    pub senders: Vec<Address>,
+}
+
+/// Generator of deterministic blocks, configured builder-style
+///
+/// Block `n` has timestamp `genesis_time + 12n` plus up to 5s of jitter,
+/// and forks as `spec` says at that number and time. The blocks of one
+/// call link by parent hash; the first one's parent hash is made up, so
+/// ranges generated separately do not link to each other.
+#[derive(Debug, Clone)]
+pub struct SyntheticChain {
+    seed: u64,
+    spec: ChainSpec,
+    genesis_time: u64,
+    max_transactions: u32,
+    level: CompressionLevel,
+}
+
+impl SyntheticChain {
+    /// A chain of [`SYNTHETIC`] blocks from `seed`, of up to 16
+    /// transactions each, written with pattern search
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            spec: SYNTHETIC,
+            genesis_time: 1_700_000_000,
+            max_transactions: 16,
+            level: CompressionLevel::Default,
+        }
+    }
+
+    /// Fork schedule of the headers (default: [`SYNTHETIC`])
+    pub fn spec(mut self, spec: ChainSpec) -> Self {
+        self.spec = spec;
+        self
+    }
+
+    /// Timestamp of block zero (default: 1_700_000_000)
+    pub fn genesis_time(mut self, time: u64) -> Self {
+        self.genesis_time = time;
+        self
+    }
+
+    /// Most transactions a block has (default: 16)
+    pub fn max_transactions(mut self, count: u32) -> Self {
+        self.max_transactions = count;
+        self
+    }
+
+    /// Compression level of [`SyntheticChain::write_segments`]; `Store` is
+    /// much faster in debug builds (default: `Default`)
+    pub fn level(mut self, level: CompressionLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn blocks(&self, blocks: Range<u64>) -> Vec<SyntheticBlock> {
+        let mut parent_hash = None;
+        blocks
+            .map(|number| {
+                let block = self.block(number, parent_hash);
+                parent_hash = Some(block.header.hash_slow());
+                block
+            })
+            .collect()
+    }
+
+    pub fn headers(&self, blocks: Range<u64>) -> Vec<Header> {
+        self.blocks(blocks)
+            .into_iter()
+            .map(|block| block.header)
+            .collect()
+    }
+
+    /// The words of a headers segment: the first byte of each hash, then
+    /// the header's RLP
+    pub fn header_words(&self, blocks: Range<u64>) -> Vec<Vec<u8>> {
+        self.headers(blocks)
+            .iter()
+            .map(|header| {
+                let mut word = vec![header.hash_slow()[0]];
+                word.extend_from_slice(&alloy_rlp::encode(header));
+                word
+            })
+            .collect()
+    }
+
+    /// The words of a transactions segment: for each transaction the first
+    /// byte of its hash, its sender and its EIP-2718 encoding, and an empty
+    /// word for each system transaction around a block's own
+    pub fn transaction_words(&self, blocks: Range<u64>) -> Vec<Vec<u8>> {
+        let mut words = Vec::new();
+        for block in self.blocks(blocks) {
+            words.push(Vec::new());
+            for (tx, sender) in block.body.transactions.iter().zip(&block.senders) {
+                words.push(transaction_word(tx, *sender));
+            }
+            words.push(Vec::new());
+        }
+        words
+    }
+
+    /// Write the headers segment of `blocks` and its index in `dir`,
+    /// without fsync; returns the paths of the `.seg` and `.idx` files
+    ///
+    /// `blocks` is a block file range, its bounds multiples of 1000.
+    pub fn write_headers(&self, dir: &Path, blocks: Range<u64>) -> Result<(PathBuf, PathBuf)> {
+        let mut headers =
+            HeaderSegmentWriter::with_compressor(dir, blocks.start, blocks.end, |builder| {
+                builder.level(self.level)
+            })?;
+        headers.disable_fsync();
+        for header in self.headers(blocks) {
+            headers.add_header(&header)?;
+        }
+        headers.finish()
+    }
+
+    /// Write the headers, bodies and transactions segments of `blocks`
+    /// with their indexes in `dir`, without fsync
+    ///
+    /// `blocks` is a block file range, its bounds multiples of 1000, and
+    /// `first_tx_num` the TxNum its first block starts at. Returns the
+    /// TxNum after the last block's transactions, to chain ranges.
+    pub fn write_segments(
+        &self,
+        dir: &Path,
+        blocks: Range<u64>,
+        first_tx_num: TxNum,
+    ) -> Result<TxNum> {
+        let mut headers =
+            HeaderSegmentWriter::with_compressor(dir, blocks.start, blocks.end, |builder| {
+                builder.level(self.level)
+            })?;
+        headers.disable_fsync();
+        let segment = |kind| {
+            let stem = SnapshotFile::stem(kind, blocks.start, blocks.end);
+            dir.join(format!("{}.seg", stem))
+        };
+        let compressor = |path: &Path, prefix: &str| {
+            Compressor::builder(path)
+                .log_prefix(prefix)
+                .level(self.level)
+                .fsync(false)
+                .build()
+        };
+        let bodies_path = segment(SnapshotType::Bodies);
+        let transactions_path = segment(SnapshotType::Transactions);
+        let mut bodies = compressor(&bodies_path, "bodies")?;
+        let mut transactions = compressor(&transactions_path, "transactions")?;
+
+        let mut tx_num = first_tx_num;
+        for block in self.blocks(blocks.clone()) {
+            headers.add_header(&block.header)?;
+            let txs = &block.body.transactions;
+            let body = StoredBody {
+                base_tx_num: tx_num,
+                tx_count: txs.len() as u32 + 2,
+                ommers: block.body.ommers,
+                withdrawals: block.body.withdrawals.map(|w| w.into_inner()),
+            };
+            bodies.add_word(&body.encode())?;
+            transactions.add_word(&[])?;
+            for (tx, sender) in txs.iter().zip(&block.senders) {
+                transactions.add_word(&transaction_word(tx, *sender))?;
+            }
+            transactions.add_word(&[])?;
+            tx_num = tx_num.offset(txs.len() as u64 + 2);
+        }
+
+        headers.finish()?;
+        bodies.compress()?;
+        transactions.compress()?;
+        build_block_index(&bodies_path, SnapshotType::Bodies, blocks.start, false)?;
+        build_transactions_index(&transactions_path, &bodies_path, false)?;
+        Ok(tx_num)
+    }
+
+    fn block(&self, number: u64, parent_hash: Option<B256>) -> SyntheticBlock {
+        let mut rng = SplitMix64::new(self.seed, number);
+        // Drawn either way, so the rest of the block does not depend on it
+        let made_up = rng.b256();
+        let parent_hash = parent_hash.unwrap_or(made_up);
+        let timestamp = self.genesis_time + number * BLOCK_TIME + rng.below(BLOCK_TIME / 2);
+        let london = self.spec.is_london(number);
+        let shanghai = self.spec.is_shanghai(timestamp);
+        let cancun = self.spec.is_cancun(timestamp);
+        // Proof of stake from the first fork after the merge
+        let merged = shanghai;
+
+        let base_fee = london.then(|| GWEI + rng.below(50 * GWEI));
+        let count = rng.below(u64::from(self.max_transactions) + 1);
+        let mut transactions = Vec::with_capacity(count as usize);
+        let mut senders = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (tx, sender) = self.transaction(&mut rng, base_fee);
+            transactions.push(tx);
+            senders.push(sender);
+        }
+        let withdrawals = shanghai.then(|| {
+            (0..WITHDRAWALS_PER_BLOCK)
+                .map(|i| Withdrawal {
+                    index: number * WITHDRAWALS_PER_BLOCK + i,
+                    validator_index: rng.below(1_000_000),
+                    address: pooled(&mut rng, 0xa0, ACCOUNTS),
+                    amount: 10_000_000 + rng.below(10_000_000),
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let gas_limit = if london { GAS_LIMIT } else { GAS_LIMIT / 2 };
+        let gas_used = transactions
+            .iter()
+            .map(|tx| tx.gas_limit() * 7 / 10)
+            .sum::<u64>()
+            .min(gas_limit);
+        let mut logs_bloom = Bloom::ZERO;
+        for _ in 0..count * 3 {
+            let bit = rng.below(2048) as usize;
+            logs_bloom.0[bit / 8] |= 1 << (bit % 8);
+        }
+        let header = Header {
+            parent_hash,
+            ommers_hash: EMPTY_OMMER_ROOT_HASH,
+            beneficiary: pooled(&mut rng, 0xb0, BUILDERS),
+            state_root: rng.b256(),
+            transactions_root: calculate_transaction_root(&transactions),
+            receipts_root: rng.b256(),
+            logs_bloom,
+            difficulty: if merged {
+                U256::ZERO
+            } else {
+                U256::from(1u64 << 50) + U256::from(rng.below(1 << 40))
+            },
+            number,
+            // Varies within the bound ChainValidation allows, and doubles at
+            // London as the target becomes half the limit
+            gas_limit: gas_limit + rng.below(gas_limit / 4096),
+            gas_used,
+            timestamp,
+            extra_data: Bytes::from_static(CLIENTS[rng.below(CLIENTS.len() as u64) as usize]),
+            mix_hash: rng.b256(),
+            nonce: if merged {
+                B64::ZERO
+            } else {
+                B64::from(rng.next())
+            },
+            base_fee_per_gas: base_fee,
+            withdrawals_root: withdrawals.as_deref().map(calculate_withdrawals_root),
+            blob_gas_used: cancun.then_some(0),
+            excess_blob_gas: cancun.then_some(0),
+            parent_beacon_block_root: cancun.then(|| rng.b256()),
+            requests_hash: self.spec.is_prague(timestamp).then(|| rng.b256()),
+            target_blobs_per_block: None,
+        };
+        SyntheticBlock {
+            header,
+            body: BlockBody {
+                transactions,
+                ommers: Vec::new(),
+                withdrawals: withdrawals.map(Withdrawals::new),
+            },
+            senders,
+        }
+    }
+
+    // A transfer between accounts or a contract call, EIP-1559 for most
+    // once the base fee is there; signatures are made up, so the sender
+    // must be stored
+    fn transaction(&self, rng: &mut SplitMix64, base_fee: Option<u64>) -> (TxEnvelope, Address) {
+        let sender = pooled(rng, 0xa0, ACCOUNTS);
+        let nonce = rng.below(10_000);
+        let (to, input, gas_limit) = if rng.below(10) < 3 {
+            (pooled(rng, 0xa0, ACCOUNTS), Bytes::new(), 21_000)
+        } else {
+            let mut input = vec![0xa9, 0x05, 0x9c, rng.below(SELECTORS) as u8];
+            for _ in 0..1 + rng.below(6) {
+                let mut abi_word = [0u8; 32];
+                match rng.below(3) {
+                    // An address
+                    0 => abi_word[12..].copy_from_slice(pooled(rng, 0xa0, ACCOUNTS).as_slice()),
+                    // An amount
+                    1 => abi_word[24..].copy_from_slice(&rng.below(1 << 40).to_be_bytes()),
+                    _ => abi_word = rng.b256().0,
+                }
+                input.extend_from_slice(&abi_word);
+            }
+            let gas_limit = 50_000 + rng.below(450) * 1000;
+            (pooled(rng, 0xc0, CONTRACTS), Bytes::from(input), gas_limit)
+        };
+        let value = if rng.below(10) < 6 {
+            U256::ZERO
+        } else {
+            U256::from(rng.below(1000)) * U256::from(1_000_000_000_000_000u64)
+        };
+        let signature = PrimitiveSignature::new(
+            U256::from_be_bytes(rng.b256().0),
+            U256::from_be_bytes(rng.b256().0),
+            rng.below(2) == 1,
+        );
+        let tx: TxEnvelope = match base_fee {
+            Some(base_fee) if rng.below(10) < 8 => {
+                let tip = u128::from(rng.below(3 * GWEI));
+                TxEip1559 {
+                    chain_id: self.spec.chain_id,
+                    nonce,
+                    gas_limit,
+                    max_fee_per_gas: u128::from(base_fee) * 2 + tip,
+                    max_priority_fee_per_gas: tip,
+                    to: TxKind::Call(to),
+                    value,
+                    access_list: Default::default(),
+                    input,
+                }
+                .into_signed(signature)
+                .into()
+            }
+            base_fee => TxLegacy {
+                chain_id: Some(self.spec.chain_id),
+                nonce,
+                gas_price: u128::from(base_fee.unwrap_or(20 * GWEI) + rng.below(2 * GWEI)),
+                gas_limit,
+                to: TxKind::Call(to),
+                value,
+                input,
+            }
+            .into_signed(signature)
+            .into(),
+        };
+        (tx, sender)
+    }
+}
+
+/// Compress `words` into a segment at `path` with the default settings,
+/// without fsync
+pub fn write_segment<W: AsRef<[u8]>>(path: &Path, words: &[W]) -> Result<()> {
+    let mut compressor = Compressor::builder(path).fsync(false).build()?;
+    for word in words {
+        compressor.add_word(word.as_ref())?;
+    }
+    compressor.compress()?;
+    Ok(())
+}
+
+fn transaction_word(tx: &TxEnvelope, sender: Address) -> Vec<u8> {
+    let mut word = vec![tx.tx_hash()[0]];
+    word.extend_from_slice(sender.as_slice());
+    word.extend_from_slice(&tx.encoded_2718());
+    word
+}
+
+// One of `size` addresses tagged `tag`, the first ones the most likely
+fn pooled(rng: &mut SplitMix64, tag: u8, size: u64) -> Address {
+    let bound = 1 + rng.below(size);
+    let mut address = Address::repeat_byte(tag);
+    address.0[12..].copy_from_slice(&rng.below(bound).to_be_bytes());
+    address
+}
+
+// From Vigna: https://prng.di.unimi.it/splitmix64.c
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    // Streams of consecutive blocks step through the same sequence, so
+    // they start at scrambled, far apart points of it
+    fn new(seed: u64, number: u64) -> Self {
+        let mut scramble = Self(number);
+        let mut rng = Self(scramble.next() ^ seed);
+        Self(rng.next())
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn b256(&mut self) -> B256 {
+        let mut bytes = [0u8; 32];
+        for chunk in bytes.chunks_mut(8) {
+            chunk.copy_from_slice(&self.next().to_le_bytes());
+        }
+        B256::from(bytes)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::snapshots::recsplit::RecSplit;
+    use crate::snapshots::types::{BlockNumber, TxIndex};
+    use crate::snapshots::{validate_header, HeadersReader, Snapshots, SnapshotsConfig};
+    use crate::Decompressor;
+
+    // The chain of the snapshots tests' fixtures: pre-London blocks
+    // without transactions, stored uncompressed
+    pub(crate) fn fixture_chain() -> SyntheticChain {
+        SyntheticChain::new(0)
+            .spec(ChainSpec::MAINNET)
+            .genesis_time(0)
+            .max_transactions(0)
+            .level(CompressionLevel::Store)
+    }
+
+    // The headers of `range` and their index in `dir`; returns the segment
+    // path
+    pub(crate) fn write_headers(dir: &Path, range: Range<u64>) -> PathBuf {
+        fixture_chain().write_headers(dir, range).unwrap().0
+    }
+
+    // Compress `words` to `path` with an enum index starting at `base`,
+    // keyed by `key(i, word)`
+    pub(crate) fn write_indexed(
+        path: &Path,
+        words: &[Vec<u8>],
+        base: u64,
+        key: impl Fn(u64, &[u8]) -> Vec<u8>,
+    ) {
+        write_segment(path, words).unwrap();
+        let decompressor = Decompressor::new(path).unwrap();
+        let mut getter = decompressor.make_getter();
+        let mut rs = RecSplit::builder(path.with_extension("idx"), words.len())
+            .enums(true)
+            .base_data_id(base)
+            .fsync(false)
+            .build()
+            .unwrap();
+        let mut offset = 0;
+        for (i, word) in words.iter().enumerate() {
+            rs.add_key(&key(i as u64, word), offset).unwrap();
+            offset = getter.skip().0;
+        }
+        rs.build().unwrap();
+    }
+
+    // Secret key signing the transactions of `signed_tx`
+    const SENDER_KEY: [u8; 32] = [0x42; 32];
+
+    // A transfer of `nonce` wei to an address ending in `nonce`, legacy for
+    // even nonces and EIP-1559 for odd ones, signed with `SENDER_KEY` so
+    // that its sender can be recovered
+    pub(crate) fn signed_tx(nonce: u64) -> TxEnvelope {
+        let to = TxKind::Call(Address::with_last_byte(nonce as u8));
+        let sign = |hash: B256| {
+            let key = k256::ecdsa::SigningKey::from_slice(&SENDER_KEY).unwrap();
+            let (signature, recovery_id) = key.sign_prehash_recoverable(hash.as_slice()).unwrap();
+            PrimitiveSignature::new(
+                U256::from_be_slice(&signature.r().to_bytes()),
+                U256::from_be_slice(&signature.s().to_bytes()),
+                recovery_id.is_y_odd(),
+            )
+        };
+        if nonce.is_multiple_of(2) {
+            let tx = TxLegacy {
+                chain_id: Some(1),
+                nonce,
+                gas_price: 30,
+                gas_limit: 21_000,
+                to,
+                value: U256::from(nonce),
+                ..Default::default()
+            };
+            let signature = sign(tx.signature_hash());
+            tx.into_signed(signature).into()
+        } else {
+            let tx = TxEip1559 {
+                chain_id: 1,
+                nonce,
+                gas_limit: 21_000,
+                max_fee_per_gas: 50,
+                max_priority_fee_per_gas: 2,
+                to,
+                value: U256::from(nonce),
+                ..Default::default()
+            };
+            let signature = sign(tx.signature_hash());
+            tx.into_signed(signature).into()
+        }
+    }
+
+    #[test]
+    fn test_synthetic_chain_is_deterministic() {
+        let chain = SyntheticChain::new(7);
+        assert_eq!(chain.blocks(100..200), chain.blocks(100..200));
+        assert_eq!(
+            chain.transaction_words(0..10),
+            SyntheticChain::new(7).transaction_words(0..10)
+        );
+        assert_ne!(
+            chain.header_words(0..10),
+            SyntheticChain::new(8).header_words(0..10)
+        );
+        // A block is the same whatever range it is generated in, but for
+        // its parent hash
+        let mut block = chain.blocks(150..151).remove(0);
+        let mut linked = chain.blocks(100..200).remove(50);
+        block.header.parent_hash = B256::ZERO;
+        linked.header.parent_hash = B256::ZERO;
+        assert_eq!(block, linked);
+    }
+
+    #[test]
+    fn test_write_segments() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let chain = SyntheticChain::new(42).level(CompressionLevel::Store);
+        let blocks = chain.blocks(0..1000);
+        let next = chain
+            .write_segments(tmp_dir.path(), 0..1000, TxNum(0))
+            .unwrap();
+        let tx_count: usize = blocks.iter().map(|b| b.body.transactions.len()).sum();
+        assert_eq!(next, TxNum(tx_count as u64 + 2 * 1000));
+
+        let headers = HeadersReader::new(
+            tmp_dir
+                .path()
+                .join("v1-000000-000001-headers.seg")
+                .as_path(),
+        )
+        .unwrap();
+        let mut count = 0;
+        for (item, block) in headers
+            .iter_range(BlockNumber(0), BlockNumber(1000))
+            .unwrap()
+            .validate(SYNTHETIC.validation())
+            .zip(&blocks)
+        {
+            let (hash, header) = item.unwrap();
+            assert_eq!(hash, block.header.hash_slow());
+            validate_header(&header, &SYNTHETIC).unwrap();
+            count += 1;
+        }
+        assert_eq!(count, 1000);
+
+        let snapshots =
+            Snapshots::open(SnapshotsConfig::new(tmp_dir.path()).verify_bodies(true)).unwrap();
+        for number in [0, 1, 500, 999] {
+            let block = &blocks[number as usize];
+            let body = snapshots.body(BlockNumber(number)).unwrap().unwrap();
+            assert_eq!(
+                body.transaction_count(),
+                block.body.transactions.len() as u64
+            );
+            for (i, (tx, sender)) in block
+                .body
+                .transactions
+                .iter()
+                .zip(&block.senders)
+                .enumerate()
+            {
+                let stored = snapshots
+                    .transaction(body.tx_num(TxIndex(i as u64)).unwrap())
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(stored.sender, *sender);
+                assert_eq!(&stored.envelope().unwrap(), tx);
+            }
+        }
+
+        let transactions = Decompressor::new(
+            tmp_dir
+                .path()
+                .join("v1-000000-000001-transactions.seg")
+                .as_path(),
+        )
+        .unwrap();
+        assert_eq!(transactions.count(), tx_count + 2 * 1000);
+    }
+}
@@ -0,0 +1,18 @@
+//! Tracing of the compression and decompression paths
+//!
+//! Phases are spans and end with a summary event at `info` or `debug`.
+//! Events about single words, patterns and positions are emitted with
+//! [`word_trace!`] only: they are compiled out unless the `detailed-trace`
+//! feature is on, as even a disabled event costs a check per word.
+
+/// A `trace` event about a single word, pattern or position, compiled out
+/// without the `detailed-trace` feature
+macro_rules! word_trace {
+    ($($arg:tt)+) => {
+        if cfg!(feature = "detailed-trace") {
+            tracing::trace!($($arg)+);
+        }
+    };
+}
+
+pub(crate) use word_trace;
@@ -0,0 +1,162 @@
+//! Sources of words for a [`Compressor`](crate::Compressor)
+//!
+//! [`Compressor::add_word`](crate::Compressor::add_word) takes one borrowed
+//! word at a time from the caller. A [`WordSource`] hands words over itself,
+//! so that [`Compressor::compress_from`](crate::Compressor::compress_from)
+//! can drain a raw words file, another segment, a channel or an iterator:
+//!
+//! - [`RawWordsFile::words`](crate::compress::RawWordsFile::words) reads the
+//!   words of a raw words file back, with their compression flags
+//! - [`SegmentSource`] decodes the words of a segment, to compress them again
+//! - any `Iterator<Item = Vec<u8>>`, which includes the receiving end of an
+//!   `mpsc` channel through [`Receiver::into_iter`](std::sync::mpsc::Receiver::into_iter)
+//!   or [`Receiver::iter`](std::sync::mpsc::Receiver::iter)
+
+use crate::decompress::{Decompressor, Getter};
+use crate::error::CompressionError;
+use crate::readahead::ReadAhead;
+use std::borrow::Cow;
+
+/// Words to compress, pulled one at a time
+pub trait WordSource {
+    /// The next word and whether it is to be compressed (`false` stores it
+    /// as it is, as [`Compressor::add_uncompressed_word`] does), or `None`
+    /// once the source is exhausted
+    ///
+    /// [`Compressor::add_uncompressed_word`]: crate::Compressor::add_uncompressed_word
+    fn next_word(&mut self) -> Option<(Cow<'_, [u8]>, bool)>;
+
+    /// The error that ended the source early, if any
+    ///
+    /// Called once `next_word` returned `None`, so that a failed read is not
+    /// taken for the end of the words.
+    fn take_error(&mut self) -> Option<CompressionError> {
+        None
+    }
+}
+
+/// Words of an iterator, all of them compressed
+impl<I: Iterator<Item = Vec<u8>>> WordSource for I {
+    fn next_word(&mut self) -> Option<(Cow<'_, [u8]>, bool)> {
+        self.next().map(|word| (Cow::Owned(word), true))
+    }
+}
+
+/// The words of a segment, decoded one by one into a reused buffer
+///
+/// Every word is handed over to be compressed: a segment does not record
+/// which of its words were added uncompressed. Words are decoded with
+/// [`Getter::try_next`], so a corrupt word stops the source with an error
+/// rather than a panic.
+pub struct SegmentSource<'a> {
+    getter: Getter<'a>,
+    word: Vec<u8>,
+    error: Option<CompressionError>,
+}
+
+impl<'a> SegmentSource<'a> {
+    /// Read `segment` from its first word, with sequential readahead
+    pub fn new(segment: &'a Decompressor) -> Self {
+        let mut getter = segment.make_getter();
+        getter.read_ahead(ReadAhead::Sequential);
+        Self::from_getter(getter)
+    }
+
+    /// Read from where `getter` is to the end of its segment
+    pub fn from_getter(getter: Getter<'a>) -> Self {
+        Self {
+            getter,
+            word: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+impl WordSource for SegmentSource<'_> {
+    fn next_word(&mut self) -> Option<(Cow<'_, [u8]>, bool)> {
+        if self.error.is_some() || !self.getter.has_next() {
+            return None;
+        }
+        let mut word = std::mem::take(&mut self.word);
+        word.clear();
+        match self.getter.try_next(word) {
+            Ok((word, _)) => {
+                self.word = word;
+                Some((Cow::Borrowed(&self.word), true))
+            }
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    fn take_error(&mut self) -> Option<CompressionError> {
+        self.error.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::{Cfg, Compressor};
+    use std::sync::mpsc;
+
+    fn words() -> Vec<Vec<u8>> {
+        (0..500u32)
+            .map(|i| format!("word {} of the source {}", i % 37, i).into_bytes())
+            .collect()
+    }
+
+    fn read_all(path: &std::path::Path) -> Vec<Vec<u8>> {
+        let decompressor = Decompressor::new(path).unwrap();
+        let mut getter = decompressor.make_getter();
+        let mut out = Vec::new();
+        while getter.has_next() {
+            out.push(getter.next(Vec::new()).0);
+        }
+        out
+    }
+
+    fn compressor(path: &std::path::Path) -> Compressor {
+        Compressor::builder(path)
+            .cfg(Cfg {
+                min_pattern_score: 1,
+                ..Cfg::default()
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_compress_from_iterator_and_segment() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let first = tmp_dir.path().join("first.seg");
+        let mut c = compressor(&first);
+        c.compress_from(words().into_iter()).unwrap();
+        assert_eq!(c.count(), 500);
+        assert_eq!(read_all(&first), words());
+
+        // Compressing a segment again gives back the same words
+        let second = tmp_dir.path().join("second.seg");
+        let segment = Decompressor::new(&first).unwrap();
+        compressor(&second)
+            .compress_from(SegmentSource::new(&segment))
+            .unwrap();
+        assert_eq!(read_all(&second), words());
+    }
+
+    #[test]
+    fn test_compress_from_channel() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("channel.seg");
+        let (tx, rx) = mpsc::channel();
+        for word in words() {
+            tx.send(word).unwrap();
+        }
+        // The source ends once every sender is gone
+        drop(tx);
+        compressor(&path).compress_from(rx.into_iter()).unwrap();
+        assert_eq!(read_all(&path), words());
+    }
+}
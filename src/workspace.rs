@@ -0,0 +1,306 @@
+//! Scratch space for a compression run
+//!
+//! A [`Compressor`](crate::Compressor) writes its raw words, spilled
+//! superstrings and intermediate file into a directory of its own, created
+//! with a unique name under the temporary directory and removed when the
+//! compressor is dropped, whether compression succeeded, failed or panicked.
+//! The `.seg.tmp` output, which has to sit next to the final file to be
+//! renamed into place, is registered with the workspace so it goes too.
+//!
+//! A process that is killed leaves these behind; [`recover_or_clean`] clears
+//! them before the next run, except for workspaces holding a checkpoint
+//! (see [`Compressor::checkpoint`](crate::Compressor::checkpoint)), which a
+//! run can resume from.
+
+use crate::compress::CHECKPOINT_FILE;
+use crate::decompress::Decompressor;
+use crate::error::CompressionError;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Suffix of workspace directory names
+const WORKSPACE_SUFFIX: &str = ".work";
+
+static NEXT_WORKSPACE: AtomicU64 = AtomicU64::new(0);
+
+/// A uniquely named directory for the intermediate files of one run, removed
+/// on drop along with any registered file outside it
+#[derive(Debug)]
+pub struct TempWorkspace {
+    dir: PathBuf,
+    registered: Vec<PathBuf>,
+    // Leave the directory on drop, see TempWorkspace::keep
+    keep: bool,
+}
+
+impl TempWorkspace {
+    /// Create `{name}.{pid}.{n}.work` under `parent`
+    pub fn new(parent: impl AsRef<Path>, name: &str) -> Result<Self, CompressionError> {
+        let parent = parent.as_ref();
+        loop {
+            let n = NEXT_WORKSPACE.fetch_add(1, Ordering::Relaxed);
+            let dir = parent.join(format!(
+                "{}.{}.{}{}",
+                name,
+                std::process::id(),
+                n,
+                WORKSPACE_SUFFIX
+            ));
+            match fs::create_dir(&dir) {
+                Ok(()) => {
+                    return Ok(TempWorkspace {
+                        dir,
+                        registered: Vec::new(),
+                        keep: false,
+                    })
+                }
+                // Left by an earlier process with the same pid
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(CompressionError::FileCreate {
+                        path: dir.display().to_string(),
+                        source: e,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Take over the workspace `dir` left by an earlier run
+    pub fn reopen(dir: impl Into<PathBuf>) -> Result<Self, CompressionError> {
+        let dir = dir.into();
+        if !dir.is_dir() {
+            return Err(CompressionError::FileOpen {
+                path: dir.display().to_string(),
+                source: std::io::ErrorKind::NotFound.into(),
+            });
+        }
+        Ok(TempWorkspace {
+            dir,
+            registered: Vec::new(),
+            keep: false,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the file `name` inside the workspace
+    pub fn path(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Remove `path`, which lives outside the workspace, on drop
+    pub fn register(&mut self, path: impl Into<PathBuf>) {
+        self.registered.push(path.into());
+    }
+
+    /// Leave the directory in place on drop, returning it; registered files
+    /// are still removed
+    pub fn keep(&mut self) -> PathBuf {
+        self.keep = true;
+        self.dir.clone()
+    }
+
+    /// Stop tracking `path`, e.g. once it has been renamed into place
+    pub fn forget(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        self.registered.retain(|p| p != path);
+    }
+}
+
+impl Drop for TempWorkspace {
+    fn drop(&mut self) {
+        for path in &self.registered {
+            match fs::remove_file(path) {
+                Ok(()) => tracing::debug!("Removed {}", path.display()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::warn!("Failed to remove {}: {}", path.display(), e),
+            }
+        }
+        if self.keep {
+            return;
+        }
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            tracing::warn!("Failed to remove workspace {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+/// What [`recover_or_clean`] did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    /// Segments renamed into place from a complete `.seg.tmp`
+    pub recovered: Vec<PathBuf>,
+    /// Workspaces and partial files removed
+    pub removed: Vec<PathBuf>,
+    /// Workspaces left in place as they hold a checkpoint to resume from
+    pub resumable: Vec<PathBuf>,
+}
+
+/// Clear what interrupted compressions left in `dir`
+///
+/// Workspaces and `.idt` files are removed, but for workspaces with a
+/// checkpoint, which are reported instead. A `.seg.tmp` is renamed to its
+/// `.seg` when that does not exist yet and the file carries a checksum
+/// footer that verifies (see [`crate::CompressorBuilder::checksum`]), since
+/// only then is it known to be complete; otherwise it is removed as well.
+/// Must not run while a compression is writing to `dir`.
+pub fn recover_or_clean(dir: impl AsRef<Path>) -> Result<CleanupReport, CompressionError> {
+    let dir = dir.as_ref();
+    let entries = fs::read_dir(dir).map_err(|e| CompressionError::FileOpen {
+        path: dir.display().to_string(),
+        source: e,
+    })?;
+
+    let mut report = CleanupReport::default();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            if name.ends_with(WORKSPACE_SUFFIX) {
+                if path.join(CHECKPOINT_FILE).is_file() {
+                    report.resumable.push(path);
+                    continue;
+                }
+                fs::remove_dir_all(&path)?;
+                report.removed.push(path);
+            }
+            continue;
+        }
+
+        if let Some(seg_name) = name.strip_suffix(".tmp").filter(|n| n.ends_with(".seg")) {
+            let target = dir.join(seg_name);
+            if !target.exists() && is_complete_segment(&path) {
+                fs::rename(&path, &target).map_err(|e| CompressionError::FileRename {
+                    from: path.display().to_string(),
+                    to: target.display().to_string(),
+                    source: e,
+                })?;
+                tracing::info!("Recovered {}", target.display());
+                report.recovered.push(target);
+                continue;
+            }
+        } else if !name.ends_with(".idt") {
+            continue;
+        }
+        fs::remove_file(&path)?;
+        tracing::info!("Removed stale {}", path.display());
+        report.removed.push(path);
+    }
+    report.recovered.sort();
+    report.removed.sort();
+    report.resumable.sort();
+    Ok(report)
+}
+
+fn is_complete_segment(path: &Path) -> bool {
+    Decompressor::new(path).is_ok_and(|d| d.checksum().is_some() && d.verify_checksum().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compressor;
+    use tempfile::TempDir;
+
+    fn compress(path: &Path, checksum: bool) {
+        let mut compressor = Compressor::builder(path)
+            .fsync(false)
+            .checksum(checksum)
+            .build()
+            .unwrap();
+        for i in 0..100u32 {
+            compressor
+                .add_word(format!("word {}", i).as_bytes())
+                .unwrap();
+        }
+        compressor.compress().unwrap();
+    }
+
+    #[test]
+    fn test_workspace_removed_on_drop_and_panic() {
+        let tmp_dir = TempDir::new().unwrap();
+        let outside = tmp_dir.path().join("out.seg.tmp");
+
+        let mut ws = TempWorkspace::new(tmp_dir.path(), "a.seg").unwrap();
+        let other = TempWorkspace::new(tmp_dir.path(), "a.seg").unwrap();
+        assert_ne!(ws.dir(), other.dir());
+        fs::write(ws.path("a.idt"), b"words").unwrap();
+        fs::write(&outside, b"partial").unwrap();
+        ws.register(&outside);
+        let dir = ws.dir().to_path_buf();
+        drop(ws);
+        drop(other);
+        assert!(!dir.exists());
+        assert!(!outside.exists());
+
+        let result = std::panic::catch_unwind(|| {
+            let ws = TempWorkspace::new(tmp_dir.path(), "b.seg").unwrap();
+            fs::write(ws.path("b.idt"), b"words").unwrap();
+            panic!("compression failed");
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_compressor_leaves_only_the_segment() {
+        let tmp_dir = TempDir::new().unwrap();
+        compress(&tmp_dir.path().join("a.seg"), false);
+        // The dictionary dump of trace logging is meant to stay
+        let mut names: Vec<_> = fs::read_dir(tmp_dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .filter(|name| !name.ends_with(".dictionary.txt"))
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a.seg"]);
+    }
+
+    #[test]
+    fn test_recover_or_clean() {
+        let tmp_dir = TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+
+        // A finished segment with a footer, as if the rename never happened
+        compress(&dir.join("done.seg"), true);
+        fs::rename(dir.join("done.seg"), dir.join("done.seg.tmp")).unwrap();
+        // The same without a footer can't be told from a partial file
+        compress(&dir.join("plain.seg"), false);
+        fs::rename(dir.join("plain.seg"), dir.join("plain.seg.tmp")).unwrap();
+        // A truncated one
+        compress(&dir.join("cut.seg"), true);
+        let data = fs::read(dir.join("cut.seg")).unwrap();
+        fs::write(dir.join("cut.seg.tmp"), &data[..data.len() / 2]).unwrap();
+        fs::remove_file(dir.join("cut.seg")).unwrap();
+        // Leftovers of a killed run
+        let stale = dir.join("x.seg.123.0.work");
+        fs::create_dir(&stale).unwrap();
+        fs::write(stale.join("x.idt"), b"words").unwrap();
+        fs::write(dir.join("y.idt"), b"words").unwrap();
+        fs::write(dir.join("notes.txt"), b"keep").unwrap();
+
+        let report = recover_or_clean(dir).unwrap();
+        assert_eq!(report.recovered, [dir.join("done.seg")]);
+        assert_eq!(
+            report.removed,
+            [
+                dir.join("cut.seg.tmp"),
+                dir.join("plain.seg.tmp"),
+                stale,
+                dir.join("y.idt")
+            ]
+        );
+        Decompressor::new(dir.join("done.seg"))
+            .unwrap()
+            .verify_checksum()
+            .unwrap();
+        assert!(dir.join("notes.txt").exists());
+        assert_eq!(recover_or_clean(dir).unwrap(), CleanupReport::default());
+    }
+}
@@ -15,11 +15,13 @@ mod tests {
         let tmp_dir = TempDir::new().unwrap();
         let output_file = tmp_dir.path().join("test.seg");
 
-        let mut cfg = Cfg::default();
         // Lower thresholds to ensure patterns are found
-        cfg.min_pattern_score = 2;
-        cfg.min_pattern_len = 3;
-        cfg.sampling_factor = 1; // Sample all superstrings
+        let cfg = Cfg {
+            min_pattern_score: 2,
+            min_pattern_len: 3,
+            sampling_factor: 1, // Sample all superstrings
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -119,4 +121,245 @@ mod tests {
             result.err()
         );
     }
+
+    #[test]
+    fn test_compressor_builder() {
+        use erigon_dumper::decompress::Decompressor;
+        use std::sync::atomic::{AtomicU64, Ordering};
+        use std::sync::Arc;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let output_file = tmp_dir.path().join("builder.seg");
+
+        let last_progress = Arc::new(AtomicU64::new(0));
+        let progress = last_progress.clone();
+
+        // No tmp dir given: intermediate files go next to the output
+        let mut compressor = Compressor::builder(&output_file)
+            .min_pattern_score(2)
+            .pattern_len_range(3, 64)
+            .sampling_factor(1)
+            .fsync(false)
+            .progress(move |done, total| {
+                assert!(done <= total);
+                progress.store(done, Ordering::SeqCst);
+            })
+            .build()
+            .unwrap();
+
+        let words: Vec<String> = (0..50).map(|i| format!("builder word {}", i)).collect();
+        for word in &words {
+            compressor.add_word(word.as_bytes()).unwrap();
+        }
+        compressor.compress().unwrap();
+        assert_eq!(last_progress.load(Ordering::SeqCst), words.len() as u64);
+
+        let decompressor = Decompressor::new(&output_file).unwrap();
+        let mut getter = decompressor.make_getter();
+        for word in &words {
+            let (decoded, _) = getter.next(Vec::new());
+            assert_eq!(decoded, word.as_bytes());
+        }
+        assert!(!getter.has_next());
+    }
+
+    #[test]
+    fn test_compressor_builder_rejects_invalid_config() {
+        use erigon_dumper::CompressionError;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let output_file = tmp_dir.path().join("invalid.seg");
+
+        assert!(matches!(
+            Compressor::builder(&output_file)
+                .pattern_len_range(10, 5)
+                .build(),
+            Err(CompressionError::InvalidPatternLengthRange { min: 10, max: 5 })
+        ));
+        assert!(matches!(
+            Compressor::builder(&output_file).workers(0).build(),
+            Err(CompressionError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            Compressor::builder(&output_file).sampling_factor(0).build(),
+            Err(CompressionError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_store_level_skips_dictionary() {
+        use erigon_dumper::compress::CompressionLevel;
+        use erigon_dumper::decompress::Decompressor;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let output_file = tmp_dir.path().join("store.seg");
+
+        // Thresholds low enough that the default level would find patterns
+        let mut compressor = Compressor::builder(&output_file)
+            .min_pattern_score(2)
+            .pattern_len_range(3, 64)
+            .sampling_factor(1)
+            .level(CompressionLevel::Store)
+            .fsync(false)
+            .build()
+            .unwrap();
+
+        let words: Vec<Vec<u8>> = (0..200)
+            .map(|i| format!("repeated payload {}", i % 7).into_bytes())
+            .chain(std::iter::once(Vec::new()))
+            .collect();
+        for (i, word) in words.iter().enumerate() {
+            if i % 2 == 0 {
+                compressor.add_word(word).unwrap();
+            } else {
+                compressor.add_uncompressed_word(word).unwrap();
+            }
+        }
+        compressor.compress().unwrap();
+
+        let decompressor = Decompressor::new(&output_file).unwrap();
+        assert_eq!(decompressor.dict_words(), 0);
+        assert_eq!(decompressor.count(), words.len());
+        assert_eq!(decompressor.empty_words_count(), 1);
+
+        let mut getter = decompressor.make_getter();
+        for word in &words {
+            let (decoded, _) = getter.next(Vec::new());
+            assert_eq!(&decoded, word);
+        }
+        assert!(!getter.has_next());
+
+        decompressor.verify().unwrap();
+    }
+
+    #[test]
+    fn test_compression_stats() {
+        use erigon_dumper::decompress::Decompressor;
+
+        let tmp_dir = TempDir::new().unwrap();
+        let output_file = tmp_dir.path().join("stats.seg");
+
+        let mut compressor = Compressor::builder(&output_file)
+            .min_pattern_score(2)
+            .pattern_len_range(3, 64)
+            .sampling_factor(1)
+            .fsync(false)
+            .build()
+            .unwrap();
+
+        let words: Vec<Vec<u8>> = (0..100)
+            .map(|i| format!("stats payload {}", i % 5).into_bytes())
+            .collect();
+        for word in &words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.add_uncompressed_word(b"raw").unwrap();
+        compressor.add_word(b"").unwrap();
+        compressor.compress().unwrap();
+
+        let stats = compressor.stats().clone();
+        let input_bytes = words.iter().map(Vec::len).sum::<usize>() as u64 + 3;
+        assert_eq!(stats.words, 102);
+        assert_eq!(stats.empty_words, 1);
+        assert!(stats.patterns > 0);
+        assert_eq!(stats.input_bytes, Some(input_bytes));
+        // The pass-through word is never covered
+        let covered = stats.covered_bytes.unwrap();
+        assert!(covered > 0 && covered <= input_bytes - 3);
+        let coverage = stats.coverage().unwrap();
+        assert!(coverage > 0.0 && coverage < 100.0, "{}", coverage);
+        assert_eq!(
+            stats.output_bytes,
+            std::fs::metadata(&output_file).unwrap().len()
+        );
+        assert!(stats.phases.is_some());
+        assert_eq!(
+            stats.pattern_depths.iter().sum::<u64>(),
+            stats.patterns as u64
+        );
+        assert_eq!(
+            stats.position_depths.iter().sum::<u64>(),
+            stats.positions as u64
+        );
+
+        // The header carries everything but the compression-time figures
+        let decompressor = Decompressor::new(&output_file).unwrap();
+        let header_stats = decompressor.stats();
+        assert_eq!(header_stats.coverage(), None);
+        assert_eq!(
+            header_stats,
+            erigon_dumper::CompressionStats {
+                input_bytes: None,
+                covered_bytes: None,
+                phases: None,
+                ..stats
+            }
+        );
+    }
+
+    #[test]
+    fn test_reuse_dictionary_across_segments() {
+        use erigon_dumper::compress::DictionaryBuilder;
+        use erigon_dumper::decompress::Decompressor;
+        use erigon_dumper::error::{CompressError, CompressionError};
+
+        let tmp_dir = TempDir::new().unwrap();
+        let range = |from: u32| -> Vec<Vec<u8>> {
+            (from..from + 100)
+                .map(|i| format!("header range payload {}", i % 10).into_bytes())
+                .collect()
+        };
+
+        // Train on the first segment
+        let first = tmp_dir.path().join("v1-000000-000100-headers.seg");
+        let mut compressor = Compressor::builder(&first)
+            .min_pattern_score(2)
+            .pattern_len_range(3, 64)
+            .sampling_factor(1)
+            .fsync(false)
+            .build()
+            .unwrap();
+        for word in range(0) {
+            compressor.add_word(&word).unwrap();
+        }
+        compressor.compress().unwrap();
+        let trained = compressor.dictionary().unwrap();
+        assert!(!trained.is_empty());
+
+        let data = trained.serialize();
+        let dict = DictionaryBuilder::deserialize(&data).unwrap();
+        assert_eq!(dict.serialize(), data);
+
+        // The default score threshold finds no patterns in so few words, so
+        // any patterns the second segment uses come from the dictionary
+        let second = tmp_dir.path().join("v1-000100-000200-headers.seg");
+        let mut compressor = Compressor::builder(&second)
+            .fsync(false)
+            .build()
+            .unwrap()
+            .with_dictionary(dict);
+        let words = range(100);
+        for word in &words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+        assert!(compressor.stats().patterns > 0);
+        assert_eq!(compressor.dictionary().unwrap().serialize(), data);
+
+        let decompressor = Decompressor::new(&second).unwrap();
+        let mut getter = decompressor.make_getter();
+        for word in &words {
+            assert_eq!(&getter.next(Vec::new()).0, word);
+        }
+        assert!(!getter.has_next());
+
+        for bad in [&data[..data.len() - 1], &[data.as_slice(), &[0]].concat()] {
+            assert!(matches!(
+                DictionaryBuilder::deserialize(bad),
+                Err(CompressionError::Compress(
+                    CompressError::MalformedDictionary(_)
+                ))
+            ));
+        }
+    }
 }
@@ -3,8 +3,13 @@
 
 #[cfg(test)]
 mod tests {
-    use erigon_dumper::compress::{Cfg, Compressor};
+    use erigon_dumper::compress::{Cfg, CompressionLevel, Compressor};
     use erigon_dumper::decompress::Decompressor;
+    use erigon_dumper::error::{DecompressError, IndexError, ReadError};
+    use erigon_dumper::{
+        CancellationToken, CompressionError, DecompressorOptions, FormatVersion, NameVersion,
+        OffsetTable, ReadAhead,
+    };
     use tempfile::TempDir;
 
     // Lorem ipsum test data
@@ -29,9 +34,11 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("compressed");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 1;
-        cfg.workers = 2;
+        let cfg = Cfg {
+            min_pattern_score: 1,
+            workers: 2,
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -172,9 +179,11 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("compressed2");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 1;
-        cfg.workers = 2;
+        let cfg = Cfg {
+            min_pattern_score: 1,
+            workers: 2,
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -197,6 +206,55 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
         (tmp_dir, decompressor)
     }
 
+    // Go test: TestDecompressMatchOKCondensed
+    #[test]
+    fn test_decompress_match_ok_condensed() {
+        let (_tmp_dir, decompressor) = prepare_stupid_dict(10000);
+        // Reopen with Go's condensePatternTableBitThreshold = 4
+        let decompressor =
+            Decompressor::with_condense_threshold(decompressor.file_path(), 4).unwrap();
+        let mut getter = decompressor.make_getter();
+
+        let mut i = 0;
+        while getter.has_next() {
+            let expected = format!("word-{}", i);
+            if i % 2 != 0 {
+                assert!(
+                    getter.match_prefix(expected.as_bytes()),
+                    "expected match with {}",
+                    expected
+                );
+                getter.skip();
+            } else {
+                let (word, _) = getter.next(Vec::new());
+                assert_eq!(String::from_utf8_lossy(&word), expected);
+            }
+            i += 1;
+        }
+        assert_eq!(i, 10000);
+    }
+
+    #[test]
+    fn test_condense_threshold_trades_memory() {
+        let (_tmp_dir, dense) = prepare_stupid_dict(10000);
+        let condensed = Decompressor::with_condense_threshold(dense.file_path(), 3).unwrap();
+        assert!(condensed.pattern_table_entries() < dense.pattern_table_entries());
+
+        let mut a = dense.make_getter();
+        let mut b = condensed.make_getter();
+        while a.has_next() {
+            assert_eq!(a.next(Vec::new()), b.next(Vec::new()));
+        }
+        assert!(!b.has_next());
+
+        for threshold in [0, 2, 10] {
+            assert!(matches!(
+                Decompressor::with_condense_threshold(dense.file_path(), threshold),
+                Err(CompressionError::InvalidConfig(_))
+            ));
+        }
+    }
+
     // Go test: TestDecompressMatchNotOK
     #[test]
     fn test_decompress_match_not_ok() {
@@ -230,8 +288,10 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("empty");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 1;
+        let cfg = Cfg {
+            min_pattern_score: 1,
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -248,7 +308,7 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
 
         // Should be able to open empty compressed file
         let decompressor = Decompressor::new(&file_path).unwrap();
-        let mut getter = decompressor.make_getter();
+        let getter = decompressor.make_getter();
 
         // Should have no words
         assert!(!getter.has_next());
@@ -262,8 +322,10 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("single");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 100;
+        let cfg = Cfg {
+            min_pattern_score: 100,
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -299,9 +361,11 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("patterns");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 1;
-        cfg.min_pattern_len = 4;
+        let cfg = Cfg {
+            min_pattern_score: 1,
+            min_pattern_len: 4,
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -348,8 +412,10 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("empty_words");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 100;
+        let cfg = Cfg {
+            min_pattern_score: 100,
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -401,9 +467,11 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
 
         // Read first few words
         let mut first_words = Vec::new();
-        for i in 0..5 {
+        for (i, w) in lorem_strings.iter().take(5).enumerate() {
             assert!(getter.has_next());
             let (word, _) = getter.next(Vec::new());
+            let expected = format!("{} {}", String::from_utf8_lossy(w), i);
+            assert_eq!(word, expected.as_bytes());
             first_words.push(word);
         }
 
@@ -430,4 +498,850 @@ excepteur sint occaecat cupidatat non proident sunt in culpa qui officia deserun
             getter.skip();
         }
     }
+
+    // Match functions on words added with add_uncompressed_word, directly and
+    // through seg_reader::Reader
+    #[test]
+    fn test_match_uncompressed() {
+        use erigon_dumper::seg_reader::{FileCompression, SegmentReader};
+        use std::cmp::Ordering::{Equal, Greater, Less};
+
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("uncompressed.kv");
+        let mut compressor = Compressor::builder(&file_path)
+            .fsync(false)
+            .build()
+            .unwrap();
+        for word in [&b"key1"[..], b"value1", b"", b"value2"] {
+            compressor.add_uncompressed_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+
+        let decompressor = Decompressor::new(&file_path).unwrap();
+        let mut getter = decompressor.make_getter();
+        assert!(getter.match_prefix_uncompressed(b"key"));
+        assert!(getter.match_prefix_uncompressed(b"key1"));
+        assert!(getter.match_prefix_uncompressed(b""));
+        assert!(!getter.match_prefix_uncompressed(b"key12"));
+        assert!(!getter.match_prefix_uncompressed(b"kez"));
+        assert_eq!(getter.match_cmp_uncompressed(b"key0"), Less);
+        assert_eq!(getter.match_cmp_uncompressed(b"key10"), Greater);
+        assert_eq!(getter.offset(), 0);
+        assert_eq!(getter.match_cmp_uncompressed(b"key1"), Equal); // moves offset
+        assert_eq!(getter.next_uncompressed().0, b"value1");
+        assert!(getter.match_prefix_uncompressed(b""));
+        assert!(!getter.match_prefix_uncompressed(b"v"));
+        assert_eq!(getter.match_cmp_uncompressed(b"a"), Greater);
+        assert_eq!(getter.match_cmp_uncompressed(b""), Equal);
+        assert_eq!(getter.next_uncompressed().0, b"value2");
+        assert!(!getter.has_next());
+
+        // A key match leaves the reader on the value
+        let segment = SegmentReader::new(&file_path, FileCompression::None).unwrap();
+        let mut reader = segment.make_reader();
+        assert!(reader.match_prefix(b"key"));
+        assert_eq!(reader.match_cmp(b"key1"), Equal);
+        assert_eq!(reader.next(Vec::new()).0, b"value1");
+        assert_eq!(reader.match_cmp(b""), Equal);
+        assert_eq!(reader.next(Vec::new()).0, b"value2");
+    }
+
+    // Random access by ordinal, with and without an enum index
+    #[test]
+    fn test_get_word() {
+        use erigon_dumper::snapshots::recsplit::{RecSplit, RecSplitIndex};
+
+        let (tmp_dir, mut decompressor) = prepare_lorem_dict();
+        let expected: Vec<Vec<u8>> = get_lorem_strings()
+            .iter()
+            .enumerate()
+            .map(|(k, w)| format!("{} {}", String::from_utf8_lossy(w), k).into_bytes())
+            .collect();
+        let count = expected.len() as u64;
+
+        // Without an index the words are skipped one by one
+        assert_eq!(decompressor.word_offset(0), Some(0));
+        assert_eq!(decompressor.get_word(3).as_ref(), Some(&expected[3]));
+        assert_eq!(decompressor.get_word(count), None);
+
+        let mut offsets = Vec::new();
+        let mut getter = decompressor.make_getter();
+        while getter.has_next() {
+            offsets.push(getter.offset());
+            getter.skip();
+        }
+        let build_index = |name: &str, enums: bool| {
+            let path = tmp_dir.path().join(name);
+            let mut rs = RecSplit::builder(&path, expected.len())
+                .bucket_size(10)
+                .enums(enums)
+                .salt(1)
+                .fsync(false)
+                .build()
+                .unwrap();
+            for (word, &offset) in expected.iter().zip(&offsets) {
+                rs.add_key(word, offset).unwrap();
+            }
+            rs.build().unwrap();
+            RecSplitIndex::open(&path).unwrap()
+        };
+
+        let err = decompressor
+            .attach_index(build_index("plain.idx", false))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CompressionError::Index(IndexError::WrongKind { is_enum: false, .. })
+        ));
+        assert!(decompressor.index().is_none());
+
+        decompressor
+            .attach_index(build_index("enum.idx", true))
+            .unwrap();
+        for (i, word) in expected.iter().enumerate() {
+            assert_eq!(decompressor.word_offset(i as u64), Some(offsets[i]));
+            assert_eq!(decompressor.get_word(i as u64).as_ref(), Some(word));
+        }
+        assert_eq!(decompressor.word_offset(count), None);
+        assert_eq!(decompressor.get_word(count), None);
+    }
+
+    // The dictionaries as read back from the file
+    #[test]
+    fn test_dictionary_introspection() {
+        let (_tmp_dir, decompressor) = prepare_stupid_dict(1000);
+        let stats = decompressor.stats();
+
+        let patterns: Vec<(u64, &[u8])> = decompressor.patterns().collect();
+        assert!(!patterns.is_empty());
+        assert_eq!(patterns.len(), decompressor.dict_words());
+        let mut depths = vec![0u64; stats.pattern_depths.len()];
+        for &(depth, _) in &patterns {
+            depths[depth as usize] += 1;
+        }
+        assert_eq!(depths, stats.pattern_depths);
+
+        let positions: Vec<(u64, u64)> = decompressor.positions().collect();
+        assert_eq!(positions.len(), stats.positions);
+        // The terminator of every word's pattern list
+        assert!(positions.iter().any(|&(_, pos)| pos == 0));
+
+        let mut out = Vec::new();
+        decompressor.dump_dictionary(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert_eq!(lines.next().unwrap(), "compressed2: 1000 words (0 empty)");
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "patterns: {} ({} bytes)",
+                patterns.len(),
+                stats.pattern_dict_size
+            )
+        );
+        let (depth, pattern) = patterns[0];
+        assert_eq!(
+            lines.next().unwrap().split_whitespace().collect::<Vec<_>>(),
+            [
+                "0".to_string(),
+                "depth".to_string(),
+                depth.to_string(),
+                "len".to_string(),
+                pattern.len().to_string(),
+                hex::encode(pattern)
+            ]
+        );
+        assert_eq!(out.lines().count(), 3 + patterns.len() + positions.len());
+    }
+
+    // Bookmarks and key lookup in a sorted segment
+    #[test]
+    fn test_getter_state_and_binary_search() {
+        use erigon_dumper::snapshots::recsplit::{RecSplit, RecSplitIndex};
+
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("sorted.seg");
+        // Every other key, so that absent keys fall between present ones
+        let keys: Vec<Vec<u8>> = (0..500u32)
+            .map(|i| format!("account-{:06}", i * 2).into_bytes())
+            .collect();
+        let mut compressor = Compressor::builder(&file_path)
+            .fsync(false)
+            .build()
+            .unwrap();
+        for key in &keys {
+            compressor.add_word(key).unwrap();
+        }
+        compressor.compress().unwrap();
+        let mut decompressor = Decompressor::new(&file_path).unwrap();
+
+        let mut getter = decompressor.make_getter();
+        let mut offsets = Vec::new();
+        while getter.has_next() {
+            offsets.push(getter.offset());
+            getter.skip();
+        }
+        getter.reset(offsets[10]);
+        let saved = getter.save_state();
+        assert_eq!(saved.offset(), offsets[10]);
+        for _ in 0..5 {
+            getter.next(Vec::new());
+        }
+        getter.restore_state(saved);
+        assert_eq!(getter.next(Vec::new()).0, keys[10]);
+        getter.restore_state(saved);
+        assert_eq!(getter.match_cmp(&keys[11]), std::cmp::Ordering::Greater);
+        assert_eq!(getter.save_state(), saved);
+
+        let search = |decompressor: &Decompressor, key: &[u8]| {
+            decompressor.binary_search_by(|word| word.cmp(key)).unwrap()
+        };
+        let check = |decompressor: &Decompressor| {
+            assert_eq!(search(decompressor, &keys[0]), Ok(0));
+            assert_eq!(search(decompressor, &keys[123]), Ok(123));
+            assert_eq!(search(decompressor, &keys[499]), Ok(499));
+            assert_eq!(search(decompressor, b"account-000247"), Err(124));
+            assert_eq!(search(decompressor, b"a"), Err(0));
+            assert_eq!(search(decompressor, b"b"), Err(500));
+        };
+        // Word by word without an index
+        check(&decompressor);
+
+        let idx_path = tmp_dir.path().join("sorted.idx");
+        let mut rs = RecSplit::builder(&idx_path, keys.len())
+            .bucket_size(10)
+            .enums(true)
+            .salt(1)
+            .fsync(false)
+            .build()
+            .unwrap();
+        for (key, &offset) in keys.iter().zip(&offsets) {
+            rs.add_key(key, offset).unwrap();
+        }
+        rs.build().unwrap();
+        decompressor
+            .attach_index(RecSplitIndex::open(&idx_path).unwrap())
+            .unwrap();
+        check(&decompressor);
+    }
+
+    // Malformed headers and dictionaries are reported with their location
+    #[test]
+    fn test_open_reports_structured_errors() {
+        let tmp_dir = TempDir::new().unwrap();
+        let open = |name: &str, data: &[u8]| {
+            let path = tmp_dir.path().join(name);
+            std::fs::write(&path, data).unwrap();
+            match Decompressor::new(&path) {
+                Err(CompressionError::Decompress(err)) => err,
+                Err(err) => panic!("{}: unexpected error {}", name, err),
+                Ok(_) => panic!("{}: opened", name),
+            }
+        };
+        // words, empty words, pattern dict size, then the pattern dict
+        let header = |dict_size: u64| {
+            let mut data = [
+                1u64.to_be_bytes(),
+                0u64.to_be_bytes(),
+                dict_size.to_be_bytes(),
+            ]
+            .concat();
+            data.resize(32, 0);
+            data
+        };
+
+        let err = open("small", &[0; 10]);
+        assert!(matches!(
+            err,
+            DecompressError::FileTooSmall {
+                size: 10,
+                min: 32,
+                ..
+            }
+        ));
+
+        let err = open("dict_size", &header(100));
+        assert!(matches!(
+            err,
+            DecompressError::DictionarySize {
+                dict: "pattern",
+                size: 100,
+                available: 8,
+                ..
+            }
+        ));
+
+        // Depth 51 at offset 0, then a pattern length
+        let mut data = header(8);
+        data[24..26].copy_from_slice(&[51, 1]);
+        let err = open("depth", &data);
+        assert!(matches!(
+            err,
+            DecompressError::DepthOverflow {
+                dict: "pattern",
+                offset: 0,
+                depth: 51,
+                max: 50,
+                ..
+            }
+        ));
+
+        // Pattern of 9 bytes in an 8-byte dictionary
+        data[24..26].copy_from_slice(&[1, 9]);
+        let err = open("pattern", &data);
+        assert!(matches!(
+            err,
+            DecompressError::PatternOutOfBounds {
+                offset: 2,
+                size: 9,
+                dict_size: 8,
+                ..
+            }
+        ));
+
+        // Varint running past the end of the dictionary
+        data[24..32].copy_from_slice(&[1, 1, b'a', 0x80, 0x80, 0x80, 0x80, 0x80]);
+        let err = open("varint", &data);
+        match err {
+            DecompressError::Read { file, source } => {
+                assert_eq!(file, "varint");
+                assert_eq!(
+                    source,
+                    ReadError::OutOfBounds {
+                        what: "pattern depth",
+                        offset: 27,
+                        needed: 6,
+                        available: 5,
+                    }
+                );
+            }
+            err => panic!("unexpected error {}", err),
+        }
+    }
+
+    // Segments for the edge cases: no words, only empty words and no
+    // patterns. The bytes are assembled by hand after the layout of
+    // go/src/compress.go, not written by it. Go's decompressor rejects files
+    // under 32 bytes, and ones with no words but more than the header
+    #[test]
+    fn test_go_edge_case_segments() {
+        let tmp_dir = TempDir::new().unwrap();
+        let write = |name: &str, data: &[u8]| {
+            let path = tmp_dir.path().join(name);
+            std::fs::write(&path, data).unwrap();
+            path
+        };
+        let segment = |words: u64, empty: u64, patterns: &[u8], positions: &[u8], data: &[u8]| {
+            [
+                &words.to_be_bytes()[..],
+                &empty.to_be_bytes(),
+                &(patterns.len() as u64).to_be_bytes(),
+                patterns,
+                &(positions.len() as u64).to_be_bytes(),
+                positions,
+                data,
+            ]
+            .concat()
+        };
+        let words = |d: &Decompressor| {
+            let mut getter = d.make_getter();
+            let mut words = Vec::new();
+            while getter.has_next() {
+                words.push(getter.next(Vec::new()).0);
+            }
+            words
+        };
+
+        // No words: the header and two empty dictionaries
+        let empty = segment(0, 0, &[], &[], &[]);
+        assert_eq!(empty, [0; 32]);
+        let d = Decompressor::new(write("empty", &empty)).unwrap();
+        assert_eq!(d.count(), 0);
+        assert!(words(&d).is_empty());
+        assert!(d.verify().is_ok());
+
+        // Only empty words: the length and terminator positions take a bit
+        // each, and every word is flushed to a byte
+        let data = segment(3, 3, &[], &[1, 0, 1, 1], &[1, 1, 1]);
+        let d = Decompressor::new(write("empty_words", &data)).unwrap();
+        assert_eq!((d.count(), d.empty_words_count()), (3, 3));
+        assert_eq!(words(&d), vec![Vec::<u8>::new(); 3]);
+        assert_eq!(d.get_word(2), Some(Vec::new()));
+
+        // No patterns: words are their length code and uncovered bytes
+        let data = segment(
+            3,
+            1,
+            &[],
+            &[1, 0, 2, 1, 2, 3],
+            &[3, b'a', b'b', 1, 3, b'c', b'd'],
+        );
+        let d = Decompressor::new(write("no_patterns", &data)).unwrap();
+        assert_eq!(d.dict_words(), 0);
+        assert_eq!(words(&d), vec![b"ab".to_vec(), Vec::new(), b"cd".to_vec()]);
+        let mut getter = d.make_getter();
+        assert!(getter.match_prefix(b"a"));
+        getter.skip();
+        getter.skip();
+        assert!(getter.match_cmp(b"cd").is_eq());
+
+        let open = |name: &str, data: &[u8]| match Decompressor::new(write(name, data)) {
+            Err(CompressionError::Decompress(err)) => err,
+            Err(err) => panic!("{}: unexpected error {}", name, err),
+            Ok(_) => panic!("{}: opened", name),
+        };
+        assert!(matches!(
+            open("short", &empty[..31]),
+            DecompressError::FileTooSmall {
+                size: 31,
+                min: 32,
+                ..
+            }
+        ));
+        // No words, yet data after the header or patterns without positions
+        assert!(matches!(
+            open("trailing", &segment(0, 0, &[], &[], &[0])),
+            DecompressError::NoWords { size: 33, .. }
+        ));
+        assert!(matches!(
+            open("patterns_only", &segment(0, 0, &[1, 1, b'a'], &[], &[])),
+            DecompressError::NoWords { size: 35, .. }
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        let (_tmp_dir, decompressor) = prepare_lorem_dict();
+        let report = decompressor.verify().unwrap();
+
+        let lorem_strings = get_lorem_strings();
+        let expected_bytes: usize = lorem_strings
+            .iter()
+            .enumerate()
+            .map(|(k, w)| format!("{} {}", String::from_utf8_lossy(w), k).len())
+            .sum();
+        assert_eq!(report.words, lorem_strings.len() as u64);
+        assert_eq!(report.empty_words, 0);
+        assert_eq!(report.total_word_bytes, expected_bytes as u64);
+    }
+
+    #[test]
+    fn test_verify_cancellable() {
+        let (_tmp_dir, decompressor) = prepare_lorem_dict();
+        let cancel = CancellationToken::new();
+        assert_eq!(
+            decompressor.verify_cancellable(None, &cancel).unwrap(),
+            decompressor.verify().unwrap()
+        );
+        cancel.cancel();
+        assert!(matches!(
+            decompressor.verify_cancellable(None, &cancel),
+            Err(CompressionError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_verify_detects_corruption() {
+        let (tmp_dir, decompressor) = prepare_lorem_dict();
+        let original = std::fs::read(tmp_dir.path().join("compressed")).unwrap();
+        drop(decompressor);
+
+        // Truncated word data
+        let truncated_path = tmp_dir.path().join("truncated");
+        std::fs::write(&truncated_path, &original[..original.len() - 3]).unwrap();
+        let truncated = Decompressor::new(&truncated_path).unwrap();
+        assert!(matches!(
+            truncated.verify(),
+            Err(CompressionError::VerificationFailed { .. })
+        ));
+
+        // Header claims more words than the segment holds
+        let mut bumped = original.clone();
+        let words_count = u64::from_be_bytes(bumped[0..8].try_into().unwrap());
+        bumped[0..8].copy_from_slice(&(words_count + 1).to_be_bytes());
+        let bumped_path = tmp_dir.path().join("bumped");
+        std::fs::write(&bumped_path, &bumped).unwrap();
+        let bumped = Decompressor::new(&bumped_path).unwrap();
+        assert!(matches!(
+            bumped.verify(),
+            Err(CompressionError::VerificationFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_name_version() {
+        let (tmp_dir, decompressor) = prepare_lorem_dict();
+        assert_eq!(decompressor.name_version(), None);
+        let words = decompressor.count();
+        drop(decompressor);
+        let path = tmp_dir.path().join("compressed");
+
+        let named = tmp_dir.path().join("v1.1-000000-000500-headers.seg");
+        std::fs::copy(&path, &named).unwrap();
+        let d = Decompressor::new(&named).unwrap();
+        assert_eq!(d.name_version(), Some(NameVersion::V1_1));
+        assert_eq!(d.count(), words);
+
+        // Opened by path, a file is read whatever its name says
+        let newer = tmp_dir.path().join("v3-000000-000500-headers.seg");
+        std::fs::copy(&path, &newer).unwrap();
+        let d = Decompressor::new(&newer).unwrap();
+        assert_eq!(d.name_version(), Some(NameVersion::new(3, 0)));
+        assert_eq!(d.format_version(), FormatVersion::V1_0);
+        assert_eq!(d.count(), words);
+    }
+
+    #[test]
+    fn test_decompressor_options() {
+        let (tmp_dir, decompressor) = prepare_lorem_dict();
+        let words = decompressor.count();
+        drop(decompressor);
+        let path = tmp_dir.path().join("compressed");
+        let mut garbage = std::fs::read(&path).unwrap();
+        garbage.extend_from_slice(&[0xff; 7]);
+        let garbage_path = tmp_dir.path().join("garbage");
+        std::fs::write(&garbage_path, &garbage).unwrap();
+
+        // Strict by default: trailing bytes fail verification, here on open
+        let err = DecompressorOptions::new()
+            .verify(true)
+            .open(&garbage_path)
+            .err()
+            .unwrap();
+        assert!(matches!(err, CompressionError::VerificationFailed { .. }));
+
+        // Lenient: the segment ends after the last declared word
+        let lenient = DecompressorOptions::new()
+            .strict(false)
+            .verify(true)
+            .open(&garbage_path)
+            .unwrap();
+        assert_eq!(lenient.count(), words);
+        let mut getter = lenient.make_getter();
+        let mut read = 0;
+        while getter.has_next() {
+            getter.skip();
+            read += 1;
+        }
+        assert_eq!(read, words);
+
+        // Dictionaries deeper than the limit do not open
+        let err = DecompressorOptions::new()
+            .max_depth(1)
+            .open(&path)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            CompressionError::Decompress(DecompressError::DepthOverflow { max: 1, .. })
+        ));
+
+        for options in [
+            DecompressorOptions::new().max_depth(0),
+            DecompressorOptions::new().max_depth(51),
+            DecompressorOptions::new().condense_threshold(2),
+        ] {
+            assert!(matches!(
+                options.open(&path),
+                Err(CompressionError::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_table_memory_limit() {
+        let (_tmp_dir, dense) = prepare_stupid_dict(10000);
+        let usage = dense.memory_usage();
+        assert!(usage.pattern_tables > 0);
+        assert!(usage.position_tables > 0);
+        assert_eq!(usage.total(), usage.pattern_tables + usage.position_tables);
+
+        let condensed = Decompressor::with_condense_threshold(dense.file_path(), 3).unwrap();
+        let condensed = condensed.memory_usage();
+        assert!(condensed.pattern_tables < usage.pattern_tables);
+
+        // A budget the dense tables fit in opens the segment, a smaller one
+        // fails before building them
+        let fits = DecompressorOptions::new()
+            .max_table_bytes(usage.total() * 2)
+            .open(dense.file_path())
+            .unwrap();
+        assert_eq!(fits.memory_usage(), usage);
+        // Enough for the condensed tables, and the table structs themselves
+        let limit = condensed.total() + 1024;
+        assert!(limit < usage.pattern_tables);
+        let err = DecompressorOptions::new()
+            .max_table_bytes(limit)
+            .open(dense.file_path())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            CompressionError::Decompress(DecompressError::TableTooLarge {
+                table: "pattern",
+                ..
+            })
+        ));
+        DecompressorOptions::new()
+            .condense_threshold(3)
+            .max_table_bytes(limit)
+            .open(dense.file_path())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_offset_table() {
+        let (tmp_dir, decompressor) = prepare_stupid_dict(1000);
+        let table = decompressor.build_offset_table(64).unwrap();
+        assert_eq!(table.len(), 16);
+        assert_eq!(table.floor(130), Some((128, table.floor(128).unwrap().1)));
+        assert_eq!(table.floor(1000), None);
+        assert!(decompressor.build_offset_table(0).is_err());
+
+        // Through a sidecar file and back
+        let sidecar = tmp_dir.path().join("compressed2.offsets");
+        table.save(&sidecar).unwrap();
+        let loaded = OffsetTable::open(&sidecar).unwrap();
+        assert_eq!(loaded, table);
+
+        let mut decompressor = Decompressor::new(decompressor.file_path()).unwrap();
+        let unsampled: Vec<_> = [0, 63, 64, 500, 999]
+            .iter()
+            .map(|&i| decompressor.word_offset(i))
+            .collect();
+        decompressor.attach_offset_table(loaded).unwrap();
+        for (&i, offset) in [0, 63, 64, 500, 999].iter().zip(unsampled) {
+            assert_eq!(decompressor.word_offset(i), offset);
+            assert_eq!(
+                decompressor.get_word(i).unwrap(),
+                format!("word-{}", i).into_bytes()
+            );
+        }
+        assert_eq!(decompressor.word_offset(1000), None);
+
+        // A table of another segment is refused
+        let (_other_dir, other) = prepare_stupid_dict(999);
+        let err = decompressor
+            .attach_offset_table(other.build_offset_table(64).unwrap())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            CompressionError::Decompress(DecompressError::OffsetTable { .. })
+        ));
+
+        let mut data = std::fs::read(&sidecar).unwrap();
+        data.truncate(data.len() - 1);
+        std::fs::write(&sidecar, &data).unwrap();
+        assert!(OffsetTable::open(&sidecar).is_err());
+    }
+
+    #[test]
+    fn test_checksum_footer() {
+        let tmp_dir = TempDir::new().unwrap();
+        let compress = |name: &str, checksum: bool, level: CompressionLevel| {
+            let path = tmp_dir.path().join(name);
+            let mut compressor = Compressor::builder(&path)
+                .min_pattern_score(1)
+                .level(level)
+                .checksum(checksum)
+                .fsync(false)
+                .build()
+                .unwrap();
+            for (k, w) in get_lorem_strings().iter().enumerate() {
+                let word = format!("{} {}", String::from_utf8_lossy(w), k);
+                compressor.add_word(word.as_bytes()).unwrap();
+            }
+            compressor.compress().unwrap();
+            path
+        };
+
+        let plain = compress("plain", false, CompressionLevel::Default);
+        let summed = compress("summed", true, CompressionLevel::Default);
+        let plain_bytes = std::fs::read(&plain).unwrap();
+        let summed_bytes = std::fs::read(&summed).unwrap();
+        assert!(summed_bytes.starts_with(&plain_bytes));
+        assert!(summed_bytes.ends_with(b"SEGCKSUM"));
+
+        // The footer is not part of the words
+        let plain = Decompressor::new(&plain).unwrap();
+        let decompressor = Decompressor::new(&summed).unwrap();
+        assert!(plain.checksum().is_none());
+        assert!(decompressor.checksum().is_some());
+        assert_eq!(decompressor.verify().unwrap(), plain.verify().unwrap());
+        decompressor.verify_checksum().unwrap();
+        assert!(matches!(
+            plain.verify_checksum(),
+            Err(CompressionError::VerificationFailed { .. })
+        ));
+
+        let stored = compress("stored", true, CompressionLevel::Store);
+        let stored = Decompressor::new(&stored).unwrap();
+        stored.verify_checksum().unwrap();
+        assert_eq!(
+            stored.checksum().unwrap().words,
+            decompressor.checksum().unwrap().words
+        );
+
+        // A flipped bit in the last word fails the payload checksum, a wrong
+        // word checksum in the footer fails the word checksum
+        let footer_start = plain_bytes.len();
+        for corrupt_at in [footer_start - 1, footer_start + 1] {
+            let mut corrupted = summed_bytes.clone();
+            corrupted[corrupt_at] ^= 0x01;
+            let path = tmp_dir.path().join("corrupted");
+            std::fs::write(&path, &corrupted).unwrap();
+            let err = Decompressor::new(&path)
+                .unwrap()
+                .verify_checksum()
+                .unwrap_err();
+            assert!(
+                matches!(err, CompressionError::VerificationFailed { .. }),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_next() {
+        let (tmp_dir, decompressor) = prepare_lorem_dict();
+        let mut getter = decompressor.make_getter();
+        let mut checked = decompressor.make_getter();
+        while getter.has_next() {
+            assert_eq!(
+                checked.try_next(Vec::new()).unwrap(),
+                getter.next(Vec::new())
+            );
+        }
+        assert!(!checked.has_next());
+
+        // The last word runs past the end of a truncated file
+        let original = std::fs::read(tmp_dir.path().join("compressed")).unwrap();
+        let truncated_path = tmp_dir.path().join("truncated");
+        std::fs::write(&truncated_path, &original[..original.len() - 3]).unwrap();
+        let truncated = Decompressor::new(&truncated_path).unwrap();
+        let mut checked = truncated.make_getter();
+        let mut words = 0;
+        let err = loop {
+            match checked.try_next(Vec::new()) {
+                Ok(_) => words += 1,
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(words, truncated.count() - 1);
+        assert!(matches!(err, CompressionError::UnexpectedEof));
+        // A failed read leaves the getter on the bad word
+        assert!(checked.has_next());
+        assert!(checked.try_next(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_next_raw_and_copy_words() {
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("compressed");
+        let mut compressor = Compressor::builder(&file_path)
+            .min_pattern_score(1)
+            .fsync(false)
+            .build()
+            .unwrap();
+        // Some empty words, and some stored as they are
+        let mut words = Vec::new();
+        for (i, word) in get_lorem_strings().into_iter().enumerate() {
+            if i % 7 == 0 {
+                compressor.add_uncompressed_word(&word).unwrap();
+            } else {
+                compressor.add_word(&word).unwrap();
+            }
+            words.push(word);
+            if i % 5 == 0 {
+                compressor.add_word(&[]).unwrap();
+                words.push(Vec::new());
+            }
+        }
+        compressor.compress().unwrap();
+        let decompressor = Decompressor::new(&file_path).unwrap();
+
+        // Raw spans tile the data and skip like decoded words do
+        let mut raw = decompressor.make_getter();
+        let mut getter = decompressor.make_getter();
+        let mut total = 0;
+        for word in &words {
+            let (bytes, word_len) = raw.next_raw().unwrap();
+            assert_eq!(word_len, word.len());
+            total += bytes.len();
+            assert_eq!(getter.skip().0, raw.offset());
+        }
+        assert!(!raw.has_next());
+        assert_eq!(total, raw.size());
+
+        for range in [0..words.len() as u64, 3..40, 10..11] {
+            let copy_path = tmp_dir.path().join("copy");
+            let mut file = std::fs::File::create(&copy_path).unwrap();
+            let written = decompressor.copy_words(range.clone(), &mut file).unwrap();
+            drop(file);
+            assert_eq!(written, std::fs::metadata(&copy_path).unwrap().len());
+
+            let copy = Decompressor::new(&copy_path).unwrap();
+            let expected = &words[range.start as usize..range.end as usize];
+            assert_eq!(copy.count(), expected.len());
+            assert_eq!(
+                copy.empty_words_count(),
+                expected.iter().filter(|word| word.is_empty()).count()
+            );
+            assert_eq!(copy.dict_words(), decompressor.dict_words());
+            let mut getter = copy.make_getter();
+            for word in expected {
+                assert_eq!(&getter.next(Vec::new()).0, word);
+            }
+            assert!(!getter.has_next());
+            copy.verify().unwrap();
+        }
+
+        let err = decompressor
+            .copy_words(5..words.len() as u64 + 1, Vec::new())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CompressionError::Decompress(DecompressError::WordRange { start: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let (_tmp_dir, decompressor) = prepare_lorem_dict();
+        let mut plain = decompressor.make_getter();
+        let mut expected = Vec::new();
+        while plain.has_next() {
+            expected.push(plain.next(Vec::new()).0);
+        }
+
+        for read_ahead in [
+            ReadAhead::Default,
+            ReadAhead::Sequential,
+            ReadAhead::WillNeed,
+            ReadAhead::Prefetch { window: 16 },
+            ReadAhead::PREFETCH,
+        ] {
+            let mut getter = decompressor.make_getter();
+            getter.read_ahead(read_ahead);
+            let mut words = Vec::new();
+            while getter.has_next() {
+                words.push(getter.next(Vec::new()).0);
+            }
+            assert_eq!(words, expected, "{:?}", read_ahead);
+
+            // Seeking back with prefetch reads running
+            getter.reset(0);
+            getter.skip();
+            assert_eq!(getter.next(Vec::new()).0, expected[1]);
+        }
+
+        // A setting can be replaced midway
+        let mut getter = decompressor.make_getter();
+        getter.read_ahead_until(ReadAhead::Prefetch { window: 8 }, 10);
+        getter.skip();
+        getter.read_ahead(ReadAhead::Default);
+        assert_eq!(getter.next(Vec::new()).0, expected[1]);
+    }
 }
@@ -1,7 +1,6 @@
 use erigon_dumper::compress::{Cfg, Compressor};
 use erigon_dumper::decompress::Decompressor;
 use proptest::prelude::*;
-use std::path::Path;
 use tempfile::TempDir;
 
 // Strategy for generating test words
@@ -27,9 +26,11 @@ proptest! {
         let file_path = tmp_dir.path().join("compressed.seg");
 
         // Configure compressor
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 2;
-        cfg.workers = 1;
+        let cfg = Cfg {
+            min_pattern_score: 2,
+            workers: 1,
+            ..Default::default()
+        };
 
         // Compress the words
         let mut compressor = Compressor::new(
@@ -91,9 +92,11 @@ proptest! {
         let file_path = tmp_dir.path().join("compressed.seg");
 
         // Configure compressor
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 2;
-        cfg.workers = 1;
+        let cfg = Cfg {
+            min_pattern_score: 2,
+            workers: 1,
+            ..Default::default()
+        };
 
         // Compress the words
         let mut compressor = Compressor::new(
@@ -146,9 +149,11 @@ proptest! {
         let file_path = tmp_dir.path().join("compressed.seg");
 
         // Configure compressor with variable workers
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 2;
-        cfg.workers = workers;
+        let cfg = Cfg {
+            min_pattern_score: 2,
+            workers,
+            ..Default::default()
+        };
 
         // Compress the words
         let mut compressor = Compressor::new(
@@ -204,8 +209,10 @@ mod edge_cases {
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("compressed.seg");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 2;
+        let cfg = Cfg {
+            min_pattern_score: 2,
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -265,8 +272,10 @@ mod edge_cases {
         let tmp_dir = TempDir::new().unwrap();
         let file_path = tmp_dir.path().join("compressed.seg");
 
-        let mut cfg = Cfg::default();
-        cfg.min_pattern_score = 2;
+        let cfg = Cfg {
+            min_pattern_score: 2,
+            ..Default::default()
+        };
 
         let mut compressor = Compressor::new(
             cfg,
@@ -312,3 +321,518 @@ mod edge_cases {
         }
     }
 }
+
+// Malformed files must be reported as errors, never panic
+#[cfg(test)]
+mod malformed_input {
+    use super::*;
+    use erigon_dumper::snapshots::recsplit::{RecSplit, RecSplitIndex};
+    use std::sync::OnceLock;
+
+    // A small valid segment with both dictionaries in use
+    fn segment_bytes() -> &'static [u8] {
+        static SEGMENT: OnceLock<Vec<u8>> = OnceLock::new();
+        SEGMENT.get_or_init(|| {
+            let tmp_dir = TempDir::new().unwrap();
+            let file_path = tmp_dir.path().join("valid.seg");
+            let mut compressor = Compressor::builder(&file_path)
+                .min_pattern_score(1)
+                .fsync(false)
+                .build()
+                .unwrap();
+            for i in 0..30 {
+                let word = format!("{} longlongword {}", i % 4, i);
+                compressor.add_word(word.as_bytes()).unwrap();
+                compressor.add_word(b"").unwrap();
+            }
+            compressor.compress().unwrap();
+            std::fs::read(&file_path).unwrap()
+        })
+    }
+
+    // A small valid enum index with the existence filter
+    fn index_bytes() -> &'static [u8] {
+        static INDEX: OnceLock<Vec<u8>> = OnceLock::new();
+        INDEX.get_or_init(|| {
+            let tmp_dir = TempDir::new().unwrap();
+            let path = tmp_dir.path().join("valid.idx");
+            let mut rs = RecSplit::builder(&path, 40)
+                .bucket_size(10)
+                .enums(true)
+                .less_false_positives(true)
+                .salt(1)
+                .fsync(false)
+                .build()
+                .unwrap();
+            for i in 0..40u64 {
+                rs.add_key(&i.to_be_bytes(), i * 17).unwrap();
+            }
+            rs.build().unwrap();
+            std::fs::read(&path).unwrap()
+        })
+    }
+
+    fn mutate(data: &[u8], edits: &[(usize, u8)]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        for &(i, byte) in edits {
+            let len = data.len();
+            data[i % len] = byte;
+        }
+        data
+    }
+
+    fn decode_segment(data: &[u8]) {
+        let tmp_dir = TempDir::new().unwrap();
+        let file_path = tmp_dir.path().join("fuzz.seg");
+        std::fs::write(&file_path, data).unwrap();
+        let Ok(decompressor) = Decompressor::new(&file_path) else {
+            return;
+        };
+        let _ = decompressor.verify();
+        // Unchecked decoding may return garbage but must stop
+        let mut getter = decompressor.make_getter();
+        while getter.has_next() {
+            getter.match_prefix(b"1 long");
+            getter.next(Vec::new());
+        }
+        let mut getter = decompressor.make_getter();
+        while getter.has_next() {
+            getter.skip();
+        }
+        let mut getter = decompressor.make_getter();
+        while getter.has_next() {
+            if getter.try_next(Vec::new()).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn read_index(data: &[u8]) {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("fuzz.idx");
+        std::fs::write(&path, data).unwrap();
+        let Ok(idx) = RecSplitIndex::open(&path) else {
+            return;
+        };
+        for i in 0..idx.key_count().min(64) {
+            idx.lookup(&i.to_be_bytes());
+            idx.ordinal_lookup(i);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(200))]
+
+        #[test]
+        fn test_decompressor_arbitrary_bytes(data in prop::collection::vec(any::<u8>(), 0..256)) {
+            decode_segment(&data);
+        }
+
+        #[test]
+        fn test_decompressor_mutated_segment(
+            edits in prop::collection::vec((any::<usize>(), any::<u8>()), 1..8),
+            truncate in any::<prop::sample::Index>(),
+        ) {
+            let data = mutate(segment_bytes(), &edits);
+            decode_segment(&data);
+            decode_segment(&data[..truncate.index(data.len())]);
+        }
+
+        #[test]
+        fn test_index_arbitrary_bytes(data in prop::collection::vec(any::<u8>(), 0..256)) {
+            read_index(&data);
+        }
+
+        #[test]
+        fn test_index_mutated(
+            edits in prop::collection::vec((any::<usize>(), any::<u8>()), 1..8),
+            truncate in any::<prop::sample::Index>(),
+        ) {
+            let data = mutate(index_bytes(), &edits);
+            read_index(&data);
+            read_index(&data[..truncate.index(data.len())]);
+        }
+    }
+}
+
+// Round-trips of word shapes that stress the dictionary and the encoding,
+// under every combination of the options that change how words are written
+#[cfg(test)]
+mod word_shapes {
+    use super::*;
+    use erigon_dumper::compress::{CompressionLevel, OptimizerMode, SamplingStrategy};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const MAX_PATTERN_LEN: usize = 32;
+
+    fn cfg_matrix() -> Vec<Cfg> {
+        let base = Cfg {
+            min_pattern_score: 1,
+            max_pattern_len: MAX_PATTERN_LEN,
+            sampling_factor: 1,
+            verify_codes: true,
+            ..Default::default()
+        };
+        let mut matrix = Vec::new();
+        for optimizer in [
+            OptimizerMode::Exact,
+            OptimizerMode::Greedy { min_word_len: 16 },
+        ] {
+            for sampling in [
+                SamplingStrategy::EveryNth,
+                SamplingStrategy::Reservoir { words: 50, seed: 7 },
+            ] {
+                for prune_dictionary in [false, true] {
+                    for workers in [1, 3] {
+                        // Spilled superstrings with the footer, to keep the
+                        // matrix small
+                        for (checksum, superstring_memory_limit) in
+                            [(false, None), (true, Some(256))]
+                        {
+                            matrix.push(Cfg {
+                                optimizer,
+                                sampling,
+                                prune_dictionary,
+                                workers,
+                                checksum,
+                                superstring_memory_limit,
+                                ..base.clone()
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        for checksum in [false, true] {
+            matrix.push(Cfg {
+                level: CompressionLevel::Store,
+                checksum,
+                ..base.clone()
+            });
+        }
+        matrix
+    }
+
+    fn round_trip_all(words: &[Vec<u8>]) -> Result<(), TestCaseError> {
+        for cfg in cfg_matrix() {
+            let tmp_dir = TempDir::new().unwrap();
+            let file_path = tmp_dir.path().join("shapes.seg");
+            let mut compressor = Compressor::new(
+                cfg.clone(),
+                file_path.to_str().unwrap().to_string(),
+                tmp_dir.path().to_str().unwrap().to_string(),
+                "proptest".to_string(),
+                log::Level::Debug,
+            )
+            .unwrap();
+            compressor.disable_fsync();
+            for word in words {
+                compressor.add_word(word).unwrap();
+            }
+            if let Err(err) = compressor.compress() {
+                return Err(TestCaseError::fail(format!("{} with {:?}", err, cfg)));
+            }
+            drop(compressor);
+
+            let decompressor = Decompressor::new(&file_path).unwrap();
+            prop_assert_eq!(decompressor.count(), words.len(), "{:?}", cfg);
+            let mut getter = decompressor.make_getter();
+            for (i, word) in words.iter().enumerate() {
+                prop_assert!(getter.has_next(), "word {} missing with {:?}", i, cfg);
+                let (decompressed, _) = getter.next(Vec::new());
+                prop_assert!(
+                    &decompressed == word,
+                    "word {} of {} bytes mismatch with {:?}",
+                    i,
+                    word.len(),
+                    cfg
+                );
+            }
+            prop_assert!(!getter.has_next());
+        }
+        Ok(())
+    }
+
+    // Mostly empty words, between short random ones
+    fn empty_heavy() -> impl Strategy<Value = Vec<Vec<u8>>> {
+        prop::collection::vec(
+            prop_oneof![
+                4 => Just(Vec::new()),
+                1 => prop::collection::vec(any::<u8>(), 1..32),
+            ],
+            1..200,
+        )
+    }
+
+    fn all_identical() -> impl Strategy<Value = Vec<Vec<u8>>> {
+        (prop::collection::vec(any::<u8>(), 0..64), 1usize..200)
+            .prop_map(|(word, count)| vec![word; count])
+    }
+
+    // Lists of a few recurring addresses and small integers, RLP-encoded
+    // the way transactions and receipts are
+    fn rlp_like() -> impl Strategy<Value = Vec<Vec<u8>>> {
+        let addresses = prop::collection::vec(prop::array::uniform20(any::<u8>()), 1..4);
+        (
+            addresses,
+            prop::collection::vec(
+                prop::collection::vec((any::<bool>(), any::<u16>()), 1..12),
+                1..60,
+            ),
+        )
+            .prop_map(|(addresses, words)| {
+                words
+                    .into_iter()
+                    .map(|fields| {
+                        let mut payload = Vec::new();
+                        for (is_address, value) in fields {
+                            if is_address {
+                                payload.push(0x94);
+                                payload.extend_from_slice(
+                                    &addresses[value as usize % addresses.len()],
+                                );
+                            } else if value < 0x80 {
+                                payload.push(value as u8);
+                            } else {
+                                payload.push(0x82);
+                                payload.extend_from_slice(&value.to_be_bytes());
+                            }
+                        }
+                        let mut word = if payload.len() < 56 {
+                            vec![0xc0 + payload.len() as u8]
+                        } else {
+                            vec![0xf8, payload.len() as u8]
+                        };
+                        word.extend_from_slice(&payload);
+                        word
+                    })
+                    .collect()
+            })
+    }
+
+    // A random chunk repeated with noise, `len` bytes long
+    fn repetitive(len: std::ops::Range<usize>) -> impl Strategy<Value = Vec<u8>> {
+        (
+            prop::collection::vec(any::<u8>(), 1..48),
+            len,
+            prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 0..16),
+        )
+            .prop_map(|(chunk, len, noise)| {
+                let mut word: Vec<u8> = chunk.iter().copied().cycle().take(len).collect();
+                for (i, byte) in noise {
+                    *i.get_mut(&mut word) = byte;
+                }
+                word
+            })
+    }
+
+    // Random bytes with a chunk repeated here and there; a repetitive word
+    // this long takes seconds per configuration to cover
+    fn sparse_repeats(len: std::ops::Range<usize>) -> impl Strategy<Value = Vec<u8>> {
+        (
+            any::<u64>(),
+            len,
+            prop::collection::vec(any::<u8>(), 8..48),
+            prop::collection::vec(any::<prop::sample::Index>(), 2..16),
+        )
+            .prop_map(|(seed, len, chunk, offsets)| {
+                let mut word = vec![0u8; len];
+                StdRng::seed_from_u64(seed).fill(word.as_mut_slice());
+                for offset in offsets {
+                    let start = offset.index(len - chunk.len());
+                    word[start..start + chunk.len()].copy_from_slice(&chunk);
+                }
+                word
+            })
+    }
+
+    // Each case compresses its words once per configuration, hence few cases
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(4))]
+
+        #[test]
+        fn test_empty_heavy_words(words in empty_heavy()) {
+            round_trip_all(&words)?;
+        }
+
+        #[test]
+        fn test_identical_words(words in all_identical()) {
+            round_trip_all(&words)?;
+        }
+
+        #[test]
+        fn test_rlp_like_words(words in rlp_like()) {
+            round_trip_all(&words)?;
+        }
+
+        #[test]
+        fn test_words_longer_than_patterns(
+            words in prop::collection::vec(
+                repetitive(MAX_PATTERN_LEN + 1..MAX_PATTERN_LEN * 6),
+                1..12,
+            )
+        ) {
+            round_trip_all(&words)?;
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(1))]
+
+        // Lengths and pattern positions that don't fit in 16 bits
+        #[test]
+        fn test_words_over_64k(
+            word in sparse_repeats(65_537..66_000),
+            short in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..16), 0..8),
+        ) {
+            let mut words = short;
+            words.insert(words.len() / 2, word);
+            round_trip_all(&words)?;
+        }
+    }
+}
+
+// The ways of producing a segment that must agree byte for byte. Erigon
+// expects a given set of words and settings to always give the same file, so
+// any of these drifting apart is a bug in one of them.
+#[cfg(test)]
+mod differential {
+    use super::*;
+    use erigon_dumper::compress::CompressorBuilder;
+    use erigon_dumper::SegmentSource;
+
+    fn builder(path: &std::path::Path) -> CompressorBuilder {
+        Compressor::builder(path).fsync(false).cfg(Cfg {
+            min_pattern_score: 2,
+            max_pattern_len: 32,
+            sampling_factor: 1,
+            ..Default::default()
+        })
+    }
+
+    fn compress_words(
+        path: &std::path::Path,
+        configure: impl FnOnce(CompressorBuilder) -> CompressorBuilder,
+        words: &[Vec<u8>],
+    ) -> (Vec<u8>, erigon_dumper::DictionaryBuilder) {
+        let mut compressor = configure(builder(path)).build().unwrap();
+        for word in words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+        let dictionary = compressor.dictionary().cloned().unwrap();
+        drop(compressor);
+        (std::fs::read(path).unwrap(), dictionary)
+    }
+
+    fn compare_all(words: &[Vec<u8>]) -> Result<(), TestCaseError> {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = |name: &str| tmp_dir.path().join(name);
+        let (reference, dictionary) = compress_words(&path("reference.seg"), |b| b, words);
+
+        let mut variants = vec![
+            (
+                "repeat",
+                compress_words(&path("repeat.seg"), |b| b, words).0,
+            ),
+            (
+                "workers",
+                compress_words(&path("workers.seg"), |b| b.workers(4), words).0,
+            ),
+            (
+                "spilled superstrings",
+                compress_words(
+                    &path("spilled.seg"),
+                    |b| b.superstring_memory_limit(0),
+                    words,
+                )
+                .0,
+            ),
+        ];
+
+        let mut compressor = builder(&path("dictionary.seg")).build().unwrap();
+        compressor = compressor.with_dictionary(dictionary);
+        for word in words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.compress().unwrap();
+        drop(compressor);
+        variants.push((
+            "given dictionary",
+            std::fs::read(path("dictionary.seg")).unwrap(),
+        ));
+
+        let mut compressor = builder(&path("iterator.seg")).build().unwrap();
+        compressor.add_from(words.iter().cloned()).unwrap();
+        compressor.compress().unwrap();
+        drop(compressor);
+        variants.push(("iterator", std::fs::read(path("iterator.seg")).unwrap()));
+
+        let segment = Decompressor::new(path("reference.seg")).unwrap();
+        let mut compressor = builder(&path("recompressed.seg")).build().unwrap();
+        compressor.add_from(SegmentSource::new(&segment)).unwrap();
+        compressor.compress().unwrap();
+        drop(compressor);
+        variants.push((
+            "recompressed",
+            std::fs::read(path("recompressed.seg")).unwrap(),
+        ));
+
+        for (name, bytes) in variants {
+            let first_difference = reference
+                .iter()
+                .zip(&bytes)
+                .position(|(a, b)| a != b)
+                .unwrap_or(reference.len().min(bytes.len()));
+            prop_assert!(
+                bytes == reference,
+                "{} differs from the reference at byte {} ({} vs {} bytes)",
+                name,
+                first_difference,
+                bytes.len(),
+                reference.len()
+            );
+        }
+        Ok(())
+    }
+
+    // Words built from a few shared chunks, so that the dictionary is not
+    // empty and the pattern order matters
+    fn chunked_words() -> impl Strategy<Value = Vec<Vec<u8>>> {
+        prop::collection::vec(prop::collection::vec(any::<u8>(), 4..24), 1..6).prop_flat_map(
+            |chunks| {
+                let count = chunks.len();
+                prop::collection::vec(
+                    prop::collection::vec(
+                        (0..count, prop::collection::vec(any::<u8>(), 0..4)),
+                        0..6,
+                    )
+                    .prop_map(move |parts| {
+                        let mut word = Vec::new();
+                        for (chunk, noise) in parts {
+                            word.extend_from_slice(&chunks[chunk]);
+                            word.extend_from_slice(&noise);
+                        }
+                        word
+                    }),
+                    1..80,
+                )
+            },
+        )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn test_random_words_agree(words in words_strategy()) {
+            compare_all(&words)?;
+        }
+
+        #[test]
+        fn test_chunked_words_agree(words in chunked_words()) {
+            compare_all(&words)?;
+        }
+    }
+}